@@ -0,0 +1,5 @@
+//! Code shared by the ctld-agent and the CSI driver
+
+pub mod limit;
+pub mod logging;
+pub mod parameters;
@@ -0,0 +1,251 @@
+//! Concurrency limiter
+//!
+//! The agent bounds its `zfs send | zfs recv` copies with it and the driver
+//! its node stage/publish/expand operations. A limiter is a semaphore with a
+//! fixed number of slots: [`Limiter::acquire`] queues for a slot,
+//! [`Limiter::try_acquire`] lets the caller fail instead.
+//!
+//! On shutdown a limiter can be drained: running holders get until the drain
+//! timeout to finish, after which work wrapped in [`Permit::unless_aborted`]
+//! is dropped. Anything it spawned must use `kill_on_drop` so dropping it
+//! stops the processes.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, watch};
+use tracing::{debug, info, warn};
+
+/// Semaphore limiting concurrent operations of one kind
+#[derive(Debug, Clone)]
+pub struct Limiter {
+    /// What is being limited, for logs ("copy", "node operation")
+    name: &'static str,
+    semaphore: Arc<Semaphore>,
+    limit: usize,
+    /// Set once the limiter is drained; running holders abort
+    abort: Arc<watch::Sender<bool>>,
+    /// Told the number of slots in use whenever it changes
+    observer: Option<fn(usize)>,
+}
+
+impl Limiter {
+    /// Create a limiter allowing `limit` concurrent holders (at least one)
+    pub fn new(name: &'static str, limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self {
+            name,
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit,
+            abort: Arc::new(watch::Sender::new(false)),
+            observer: None,
+        }
+    }
+
+    /// Report the number of slots in use to `observer`, e.g. a gauge setter
+    pub fn with_observer(mut self, observer: fn(usize)) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Maximum number of concurrent holders
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Wait for a slot
+    pub async fn acquire(&self) -> Permit {
+        if let Some(permit) = self.try_acquire() {
+            return permit;
+        }
+        debug!(
+            limiter = self.name,
+            limit = self.limit,
+            "Concurrency limit reached, waiting for a slot"
+        );
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("limiter semaphore is never closed");
+        self.permit(permit)
+    }
+
+    /// Take a slot if one is free
+    pub fn try_acquire(&self) -> Option<Permit> {
+        let permit = self.semaphore.clone().try_acquire_owned().ok()?;
+        Some(self.permit(permit))
+    }
+
+    /// Holders currently owning a slot
+    pub fn in_flight(&self) -> usize {
+        self.limit - self.semaphore.available_permits()
+    }
+
+    /// Drain the limiter for shutdown.
+    ///
+    /// Waits up to `timeout` for running holders to finish, then aborts the
+    /// rest and waits for them to release their slots. Work starting
+    /// afterwards is aborted immediately. Returns whether every holder
+    /// finished on its own.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let all = self.limit as u32;
+        let in_flight = self.in_flight();
+        if in_flight > 0 {
+            info!(
+                limiter = self.name,
+                in_flight,
+                ?timeout,
+                "Waiting for running operations to finish"
+            );
+        }
+
+        let finished = match tokio::time::timeout(timeout, self.semaphore.acquire_many(all)).await {
+            Ok(_all_slots) => true,
+            Err(_) => {
+                warn!(
+                    limiter = self.name,
+                    in_flight = self.in_flight(),
+                    "Operations still running after drain timeout, aborting them"
+                );
+                self.abort.send_replace(true);
+                // Aborted holders release their slot once they have cleaned up
+                let _all_slots = self.semaphore.acquire_many(all).await;
+                info!(limiter = self.name, "Aborted operations cleaned up");
+                false
+            }
+        };
+        self.abort.send_replace(true);
+        finished
+    }
+
+    fn permit(&self, permit: OwnedSemaphorePermit) -> Permit {
+        let permit = Permit {
+            _permit: permit,
+            limiter: self.clone(),
+        };
+        self.report(self.in_flight());
+        permit
+    }
+
+    fn report(&self, in_flight: usize) {
+        if let Some(observer) = self.observer {
+            observer(in_flight);
+        }
+    }
+}
+
+/// Held for the duration of one operation; releases the slot on drop
+#[derive(Debug)]
+pub struct Permit {
+    _permit: OwnedSemaphorePermit,
+    limiter: Limiter,
+}
+
+impl Permit {
+    /// Run `work` unless the limiter is drained first, in which case the
+    /// future is dropped and `None` returned.
+    pub async fn unless_aborted<T>(&self, work: impl Future<Output = T>) -> Option<T> {
+        let mut abort = self.limiter.abort.subscribe();
+        tokio::select! {
+            result = work => Some(result),
+            _ = abort.wait_for(|aborted| *aborted) => None,
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        // The semaphore permit is released after this body runs
+        self.limiter
+            .report(self.limiter.in_flight().saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_holders_beyond_limit_queue() {
+        let limiter = Limiter::new("test", 2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let limiter = limiter.clone();
+                let running = running.clone();
+                let peak = peak.clone();
+                tokio::spawn(async move {
+                    let _permit = limiter.acquire().await;
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        // All holders complete: excess ones waited rather than failing
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(limiter.in_flight(), 0);
+
+        // A saturated limiter refuses a try_acquire
+        let held = limiter.try_acquire().unwrap();
+        let _other = limiter.try_acquire().unwrap();
+        assert!(limiter.try_acquire().is_none());
+        drop(held);
+        assert!(limiter.try_acquire().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_running_holders() {
+        let limiter = Limiter::new("test", 2);
+        let permit = limiter.acquire().await;
+        let work = tokio::spawn(async move {
+            permit
+                .unless_aborted(tokio::time::sleep(Duration::from_millis(20)))
+                .await
+        });
+
+        assert!(limiter.drain(Duration::from_secs(5)).await);
+        assert!(work.await.unwrap().is_some());
+        assert_eq!(limiter.in_flight(), 0);
+
+        // Work starting after the drain is refused
+        let permit = limiter.acquire().await;
+        assert!(
+            permit
+                .unless_aborted(std::future::pending::<()>())
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drain_timeout_aborts_holders() {
+        let limiter = Limiter::new("test", 2);
+        let permit = limiter.acquire().await;
+        let work =
+            tokio::spawn(async move { permit.unless_aborted(std::future::pending::<()>()).await });
+
+        assert!(!limiter.drain(Duration::from_millis(20)).await);
+        assert!(work.await.unwrap().is_none());
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_zero_limit_still_allows_one_holder() {
+        let limiter = Limiter::new("test", 0);
+        let _permit = limiter.acquire().await;
+        assert_eq!(limiter.in_flight(), 1);
+        assert_eq!(limiter.limit(), 1);
+    }
+}
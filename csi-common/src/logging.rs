@@ -0,0 +1,39 @@
+//! Log output setup
+
+use std::str::FromStr;
+
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+/// Format of log lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line, for log collectors such as Loki or ELK
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("invalid log format '{}': expected text or json", s)),
+        }
+    }
+}
+
+/// Install the global subscriber. The JSON formatter is a different
+/// subscriber type, so each format is built and installed separately.
+pub fn init_tracing(level: Level, format: LogFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let builder = FmtSubscriber::builder().with_max_level(level);
+    match format {
+        LogFormat::Text => tracing::subscriber::set_global_default(builder.finish())?,
+        // Each line carries the fields of its enclosing #[instrument] spans
+        LogFormat::Json => tracing::subscriber::set_global_default(builder.json().finish())?,
+    }
+    Ok(())
+}
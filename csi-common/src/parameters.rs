@@ -0,0 +1,25 @@
+//! StorageClass parameter keys
+
+/// Key with case and `_`/`-` separators removed.
+///
+/// The agent matches StorageClass keys by this spelling, and the driver
+/// rewrites them the same way, so `provisioning_mode` and `provisioningMode`
+/// name the same parameter on both sides.
+pub fn normalize_key(key: &str) -> String {
+    key.chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_key() {
+        assert_eq!(normalize_key("provisioningMode"), "provisioningmode");
+        assert_eq!(normalize_key("provisioning_mode"), "provisioningmode");
+        assert_eq!(normalize_key("Enable-Unmap"), "enableunmap");
+    }
+}
@@ -5,9 +5,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build_client(false)
         .compile_protos(&["../proto/csi.proto"], &["../proto"])?;
 
-    // Compile agent proto for client
+    // Compile agent proto for client. The server stubs only back the mock
+    // agent of the unit tests, so they are left out unless requested.
+    let mock_agent = std::env::var_os("CARGO_FEATURE_MOCK_AGENT").is_some();
     tonic_prost_build::configure()
-        .build_server(false)
+        .build_server(mock_agent)
+        .generate_default_stubs(mock_agent)
         .build_client(true)
         .compile_protos(&["../proto/ctld_agent.proto"], &["../proto"])?;
 
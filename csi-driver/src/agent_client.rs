@@ -1,32 +1,39 @@
 //! Agent Client Wrapper
 //!
 //! Provides a wrapper around the ctld-agent gRPC client for volume and snapshot operations.
-//! Includes automatic retry with exponential backoff for transient failures.
+//! Includes automatic retry with exponential backoff for transient failures,
+//! tuned through [`RetryPolicy`].
 
 use std::collections::HashMap;
 use std::future::Future;
+use std::hash::{BuildHasher, RandomState};
 use std::path::PathBuf;
 use std::time::Duration;
 
 use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
 use tracing::{debug, warn};
 
+use crate::agent_info::AgentInfo;
 use crate::metrics;
 
-/// Maximum number of retry attempts for transient failures
-const MAX_RETRIES: u32 = 3;
-/// Initial backoff delay in milliseconds
-const INITIAL_BACKOFF_MS: u64 = 100;
-/// Maximum backoff delay in milliseconds
-const MAX_BACKOFF_MS: u64 = 5000;
+/// Default number of attempts per call, the first one included
+pub const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+/// Default delay before the first retry
+pub const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+/// Maximum backoff delay
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
 /// Backoff multiplier (exponential factor)
-const BACKOFF_MULTIPLIER: u64 = 2;
+const BACKOFF_MULTIPLIER: u32 = 2;
+/// Default fraction of each delay that is randomized
+const DEFAULT_RETRY_JITTER: f64 = 0.2;
 
 use crate::agent::{
     AuthCredentials, CreateSnapshotRequest, CreateVolumeRequest, DeleteSnapshotRequest,
-    DeleteVolumeRequest, ExpandVolumeRequest, ExportType, GetCapacityRequest, GetVolumeRequest,
-    ListSnapshotsRequest, ListVolumesRequest, Snapshot, Volume, VolumeContentSource,
-    storage_agent_client::StorageAgentClient,
+    DeleteVolumeRequest, ExpandVolumeRequest, ExportType, GetCapacityRequest,
+    GetRecentErrorsRequest, GetSystemInfoRequest, GetVolumeRequest, IsVolumeExportReadyRequest,
+    ListSnapshotsRequest, ListVolumeAttachmentsRequest, ListVolumesRequest, RecentError,
+    SetVolumeAttachmentRequest, Snapshot, Volume, VolumeAttachment, VolumeContentSource,
+    VolumeExistsRequest, storage_agent_client::StorageAgentClient,
 };
 
 /// TLS configuration for connecting to ctld-agent
@@ -38,16 +45,67 @@ pub struct TlsConfig {
     pub domain: String,
 }
 
+/// How agent calls are retried after a transient failure.
+///
+/// The delay before retry `n` is `base_delay * 2^(n-1)`, capped at 5 seconds,
+/// with up to `jitter` of it randomized (0.2 = +/-20%) so controllers that
+/// lost the agent together do not reconnect in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts per call, the first one included (1 disables retries)
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    /// Fraction of each delay that is randomized, between 0 and 1
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_RETRY_ATTEMPTS,
+            base_delay: DEFAULT_RETRY_BASE_DELAY,
+            jitter: DEFAULT_RETRY_JITTER,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (1-based), without jitter
+    fn backoff(&self, retry: u32) -> Duration {
+        let factor = BACKOFF_MULTIPLIER.saturating_pow(retry.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(MAX_BACKOFF)
+    }
+
+    /// Spread `delay` by up to `jitter` in either direction. `sample` is a
+    /// uniform value in [0, 1).
+    fn apply_jitter(&self, delay: Duration, sample: f64) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        delay.mul_f64(1.0 - jitter + 2.0 * jitter * sample)
+    }
+}
+
+/// Uniform sample in [0, 1) for backoff jitter.
+///
+/// Each `RandomState` is freshly keyed, which is random enough to spread
+/// retries without pulling in an RNG.
+fn jitter_sample() -> f64 {
+    (RandomState::new().hash_one(()) >> 11) as f64 / (1u64 << 53) as f64
+}
+
 /// Client wrapper for the ctld-agent storage service.
 #[derive(Debug, Clone)]
 pub struct AgentClient {
     client: StorageAgentClient<Channel>,
+    retry_policy: RetryPolicy,
+    /// Filled in by [`AgentClient::handshake`]
+    info: AgentInfo,
 }
 
 /// Check if a gRPC status code indicates a retryable error.
 ///
 /// Retryable errors are transient failures that may succeed on retry:
 /// - Unavailable: Server temporarily unavailable
+/// - DeadlineExceeded: Request timed out (agent RPCs are idempotent)
 /// - ResourceExhausted: Rate limited, may succeed after backoff
 /// - Aborted: Operation aborted, can be retried
 /// - Unknown: Unknown error, might be transient
@@ -55,6 +113,7 @@ fn is_retryable(status: &tonic::Status) -> bool {
     matches!(
         status.code(),
         tonic::Code::Unavailable
+            | tonic::Code::DeadlineExceeded
             | tonic::Code::ResourceExhausted
             | tonic::Code::Aborted
             | tonic::Code::Unknown
@@ -63,15 +122,18 @@ fn is_retryable(status: &tonic::Status) -> bool {
 
 /// Execute an async operation with exponential backoff retry.
 ///
-/// Retries the operation up to MAX_RETRIES times for retryable errors,
+/// Makes up to `policy.max_attempts` attempts while errors are retryable,
 /// with exponential backoff between attempts.
-async fn with_retry<T, F, Fut>(operation_name: &str, mut operation: F) -> Result<T, tonic::Status>
+async fn with_retry<T, F, Fut>(
+    policy: &RetryPolicy,
+    operation_name: &str,
+    mut operation: F,
+) -> Result<T, tonic::Status>
 where
     F: FnMut() -> Fut,
     Fut: Future<Output = Result<T, tonic::Status>>,
 {
     let mut attempt = 0;
-    let mut backoff_ms = INITIAL_BACKOFF_MS;
 
     loop {
         match operation().await {
@@ -79,7 +141,7 @@ where
             Err(status) => {
                 attempt += 1;
 
-                if !is_retryable(&status) || attempt > MAX_RETRIES {
+                if !is_retryable(&status) || attempt >= policy.max_attempts {
                     if attempt > 1 {
                         warn!(
                             operation = operation_name,
@@ -91,22 +153,20 @@ where
                     return Err(status);
                 }
 
+                let backoff = policy.apply_jitter(policy.backoff(attempt), jitter_sample());
                 warn!(
                     operation = operation_name,
                     attempt = attempt,
-                    max_retries = MAX_RETRIES,
+                    max_attempts = policy.max_attempts,
                     code = ?status.code(),
-                    backoff_ms = backoff_ms,
+                    backoff_ms = backoff.as_millis() as u64,
                     "Retryable error, backing off"
                 );
 
                 // Record retry metric
                 metrics::record_retry(operation_name);
 
-                tokio::time::sleep(Duration::from_millis(backoff_ms)).await;
-
-                // Exponential backoff with cap
-                backoff_ms = (backoff_ms * BACKOFF_MULTIPLIER).min(MAX_BACKOFF_MS);
+                tokio::time::sleep(backoff).await;
             }
         }
     }
@@ -116,7 +176,47 @@ impl AgentClient {
     /// Connect to the ctld-agent at the specified endpoint (plaintext).
     pub async fn connect(endpoint: &str) -> Result<Self, tonic::transport::Error> {
         let client = StorageAgentClient::connect(endpoint.to_string()).await?;
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            retry_policy: RetryPolicy::default(),
+            info: AgentInfo::default(),
+        })
+    }
+
+    /// Retry transient failures according to `policy`
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Ask the agent for its version and API revision (see
+    /// [`crate::agent_info`]). An agent without GetSystemInfo is recorded as
+    /// [`AgentInfo::legacy`].
+    pub async fn handshake(mut self) -> Result<Self, tonic::Status> {
+        let client = self.client.clone();
+        let info = with_retry(&self.retry_policy, "get_system_info", || {
+            let mut c = client.clone();
+            async move {
+                let response = c.get_system_info(GetSystemInfoRequest {}).await?;
+                let inner = response.into_inner();
+                Ok(AgentInfo {
+                    agent_version: inner.agent_version,
+                    api_version: inner.api_version,
+                })
+            }
+        })
+        .await;
+        self.info = match info {
+            Ok(info) => info,
+            Err(e) if e.code() == tonic::Code::Unimplemented => AgentInfo::legacy(),
+            Err(e) => return Err(e),
+        };
+        Ok(self)
+    }
+
+    /// What the agent reported during [`AgentClient::handshake`]
+    pub fn info(&self) -> &AgentInfo {
+        &self.info
     }
 
     /// Connect to ctld-agent with optional mTLS and robust connection settings.
@@ -162,7 +262,11 @@ impl AgentClient {
 
         let channel = endpoint_builder.connect().await?;
         let client = StorageAgentClient::new(channel);
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            retry_policy: RetryPolicy::default(),
+            info: AgentInfo::default(),
+        })
     }
 
     /// Create a new volume with the specified parameters.
@@ -195,7 +299,7 @@ impl AgentClient {
         debug!(name = name, "Creating volume with retry");
 
         let client = self.client.clone();
-        with_retry("create_volume", || {
+        with_retry(&self.retry_policy, "create_volume", || {
             let req = request.clone();
             let mut c = client.clone();
             async move {
@@ -220,7 +324,7 @@ impl AgentClient {
         debug!(volume_id = volume_id, "Deleting volume with retry");
 
         let client = self.client.clone();
-        with_retry("delete_volume", || {
+        with_retry(&self.retry_policy, "delete_volume", || {
             let req = request.clone();
             let mut c = client.clone();
             async move {
@@ -251,7 +355,7 @@ impl AgentClient {
         );
 
         let client = self.client.clone();
-        with_retry("expand_volume", || {
+        with_retry(&self.retry_policy, "expand_volume", || {
             let req = request.clone();
             let mut c = client.clone();
             async move {
@@ -273,7 +377,7 @@ impl AgentClient {
         debug!(volume_id = volume_id, "Getting volume with retry");
 
         let client = self.client.clone();
-        with_retry("get_volume", || {
+        with_retry(&self.retry_policy, "get_volume", || {
             let req = request.clone();
             let mut c = client.clone();
             async move {
@@ -287,6 +391,82 @@ impl AgentClient {
         .await
     }
 
+    /// Whether the volume's target is live in CTL on the agent.
+    ///
+    /// Makes a single attempt: callers poll it, and retrying inside each
+    /// poll would stretch the poll interval by the retry backoff.
+    pub async fn is_volume_export_ready(&mut self, volume_id: &str) -> Result<bool, tonic::Status> {
+        let request = IsVolumeExportReadyRequest {
+            volume_id: volume_id.to_string(),
+        };
+
+        let response = self.client.is_volume_export_ready(request).await?;
+        Ok(response.into_inner().ready)
+    }
+
+    /// Whether the volume's dataset exists on the agent.
+    ///
+    /// Much cheaper than [`Self::get_volume`] when only existence matters.
+    /// Automatically retries on transient failures with exponential backoff.
+    pub async fn volume_exists(&mut self, volume_id: &str) -> Result<bool, tonic::Status> {
+        let request = VolumeExistsRequest {
+            volume_id: volume_id.to_string(),
+        };
+
+        let client = self.client.clone();
+        with_retry(&self.retry_policy, "volume_exists", || {
+            let req = request.clone();
+            let mut c = client.clone();
+            async move {
+                let response = c.volume_exists(req).await?;
+                Ok(response.into_inner().exists)
+            }
+        })
+        .await
+    }
+
+    /// Record the nodes a volume is published to (none clears it).
+    ///
+    /// Automatically retries on transient failures with exponential backoff.
+    pub async fn set_volume_attachment(
+        &mut self,
+        attachment: VolumeAttachment,
+    ) -> Result<(), tonic::Status> {
+        let request = SetVolumeAttachmentRequest {
+            attachment: Some(attachment),
+        };
+
+        let client = self.client.clone();
+        with_retry(&self.retry_policy, "set_volume_attachment", || {
+            let req = request.clone();
+            let mut c = client.clone();
+            async move {
+                c.set_volume_attachment(req).await?;
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    /// List the volume attachments recorded with [`Self::set_volume_attachment`].
+    ///
+    /// Automatically retries on transient failures with exponential backoff.
+    pub async fn list_volume_attachments(
+        &mut self,
+    ) -> Result<Vec<VolumeAttachment>, tonic::Status> {
+        let client = self.client.clone();
+        with_retry(&self.retry_policy, "list_volume_attachments", || {
+            let mut c = client.clone();
+            async move {
+                let response = c
+                    .list_volume_attachments(ListVolumeAttachmentsRequest {})
+                    .await?;
+                Ok(response.into_inner().attachments)
+            }
+        })
+        .await
+    }
+
     /// Create a snapshot of a volume.
     ///
     /// Automatically retries on transient failures with exponential backoff.
@@ -307,7 +487,7 @@ impl AgentClient {
         );
 
         let client = self.client.clone();
-        with_retry("create_snapshot", || {
+        with_retry(&self.retry_policy, "create_snapshot", || {
             let req = request.clone();
             let mut c = client.clone();
             async move {
@@ -332,7 +512,7 @@ impl AgentClient {
         debug!(snapshot_id = snapshot_id, "Deleting snapshot with retry");
 
         let client = self.client.clone();
-        with_retry("delete_snapshot", || {
+        with_retry(&self.retry_policy, "delete_snapshot", || {
             let req = request.clone();
             let mut c = client.clone();
             async move {
@@ -360,7 +540,7 @@ impl AgentClient {
         debug!(max_entries, starting_token = ?starting_token, "Listing volumes with retry");
 
         let client = self.client.clone();
-        with_retry("list_volumes", || {
+        with_retry(&self.retry_policy, "list_volumes", || {
             let req = request.clone();
             let mut c = client.clone();
             async move {
@@ -389,7 +569,7 @@ impl AgentClient {
         debug!("Getting capacity with retry");
 
         let client = self.client.clone();
-        with_retry("get_capacity", || {
+        with_retry(&self.retry_policy, "get_capacity", || {
             let req = request.clone();
             let mut c = client.clone();
             async move {
@@ -415,6 +595,8 @@ impl AgentClient {
             source_volume_id: source_volume_id.unwrap_or("").to_string(),
             max_entries,
             starting_token: starting_token.unwrap_or("").to_string(),
+            // System snapshots are not Kubernetes VolumeSnapshots
+            include_system: false,
         };
 
         debug!(
@@ -425,7 +607,7 @@ impl AgentClient {
         );
 
         let client = self.client.clone();
-        with_retry("list_snapshots", || {
+        with_retry(&self.retry_policy, "list_snapshots", || {
             let req = request.clone();
             let mut c = client.clone();
             async move {
@@ -441,6 +623,26 @@ impl AgentClient {
         })
         .await
     }
+
+    /// Fetch the agent's most recent operation errors, newest first.
+    ///
+    /// `max_entries` of 0 returns everything the agent has kept.
+    pub async fn get_recent_errors(
+        &mut self,
+        max_entries: u32,
+    ) -> Result<Vec<RecentError>, tonic::Status> {
+        let request = GetRecentErrorsRequest { max_entries };
+
+        let client = self.client.clone();
+        with_retry(&self.retry_policy, "get_recent_errors", || {
+            let mut c = client.clone();
+            async move {
+                let response = c.get_recent_errors(request).await?;
+                Ok(response.into_inner().errors)
+            }
+        })
+        .await
+    }
 }
 
 #[cfg(test)]
@@ -449,6 +651,121 @@ mod tests {
     use std::sync::Arc;
     use std::sync::atomic::{AtomicU32, Ordering};
 
+    use tonic::transport::Server;
+    use tonic::transport::server::TcpIncoming;
+
+    use crate::agent::GetCapacityResponse;
+    use crate::agent::storage_agent_server::{StorageAgent, StorageAgentServer};
+
+    /// Default attempts without the wait between them
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Agent that is unavailable for the first `failures` calls
+    struct FlakyAgent {
+        calls: Arc<AtomicU32>,
+        failures: u32,
+    }
+
+    #[tonic::async_trait]
+    impl StorageAgent for FlakyAgent {
+        async fn get_capacity(
+            &self,
+            _request: tonic::Request<GetCapacityRequest>,
+        ) -> Result<tonic::Response<GetCapacityResponse>, tonic::Status> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(tonic::Status::unavailable("agent restarting"));
+            }
+            Ok(tonic::Response::new(GetCapacityResponse {
+                available_capacity: 10,
+                total_capacity: 100,
+                used_capacity: 90,
+            }))
+        }
+    }
+
+    /// Serve a `FlakyAgent` on a local port and connect a client to it
+    async fn flaky_agent_client(failures: u32) -> (AgentClient, Arc<AtomicU32>) {
+        let calls = Arc::new(AtomicU32::new(0));
+        let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = incoming.local_addr().unwrap();
+        let agent = FlakyAgent {
+            calls: calls.clone(),
+            failures,
+        };
+        tokio::spawn(
+            Server::builder()
+                .add_service(StorageAgentServer::new(agent))
+                .serve_with_incoming(incoming),
+        );
+        let client = AgentClient::connect(&format!("http://{}", addr))
+            .await
+            .unwrap()
+            .with_retry_policy(fast_policy());
+        (client, calls)
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            jitter: 0.0,
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(4), Duration::from_millis(800));
+        assert_eq!(policy.backoff(10), MAX_BACKOFF);
+        assert_eq!(policy.backoff(u32::MAX), MAX_BACKOFF);
+
+        let delay = Duration::from_millis(1000);
+        assert_eq!(policy.apply_jitter(delay, 0.7), delay);
+        let policy = RetryPolicy {
+            jitter: 0.2,
+            ..policy
+        };
+        assert_eq!(policy.apply_jitter(delay, 0.0), Duration::from_millis(800));
+        assert_eq!(policy.apply_jitter(delay, 0.5), delay);
+        assert!(policy.apply_jitter(delay, 0.999) < Duration::from_millis(1200));
+
+        for _ in 0..100 {
+            let sample = jitter_sample();
+            assert!((0.0..1.0).contains(&sample), "{sample}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_agent_client_retries_unavailable_agent() {
+        let (mut client, calls) = flaky_agent_client(2).await;
+        assert_eq!(client.get_capacity().await.unwrap(), (10, 100));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // A single attempt surfaces the first failure
+        let (client, calls) = flaky_agent_client(2).await;
+        let mut client = client.with_retry_policy(RetryPolicy {
+            max_attempts: 1,
+            ..fast_policy()
+        });
+        let err = client.get_capacity().await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_with_agent_without_system_info() {
+        // The mock only implements GetCapacity, like an agent that predates
+        // GetSystemInfo
+        let (client, _) = flaky_agent_client(0).await;
+        assert_eq!(client.info(), &AgentInfo::legacy());
+        let client = client.handshake().await.unwrap();
+        assert_eq!(client.info(), &AgentInfo::legacy());
+        assert!(client.info().is_outdated());
+    }
+
     #[test]
     fn test_export_type_conversion() {
         // Verify export type enum values
@@ -461,6 +778,7 @@ mod tests {
     fn test_is_retryable() {
         // Retryable errors
         assert!(is_retryable(&tonic::Status::unavailable("server down")));
+        assert!(is_retryable(&tonic::Status::deadline_exceeded("timed out")));
         assert!(is_retryable(&tonic::Status::resource_exhausted(
             "rate limited"
         )));
@@ -480,7 +798,7 @@ mod tests {
         let counter = Arc::new(AtomicU32::new(0));
         let counter_clone = counter.clone();
 
-        let result: Result<i32, tonic::Status> = with_retry("test", || {
+        let result: Result<i32, tonic::Status> = with_retry(&fast_policy(), "test", || {
             let c = counter_clone.clone();
             async move {
                 c.fetch_add(1, Ordering::SeqCst);
@@ -498,7 +816,7 @@ mod tests {
         let counter = Arc::new(AtomicU32::new(0));
         let counter_clone = counter.clone();
 
-        let result: Result<i32, tonic::Status> = with_retry("test", || {
+        let result: Result<i32, tonic::Status> = with_retry(&fast_policy(), "test", || {
             let c = counter_clone.clone();
             async move {
                 let attempt = c.fetch_add(1, Ordering::SeqCst) + 1;
@@ -520,7 +838,7 @@ mod tests {
         let counter = Arc::new(AtomicU32::new(0));
         let counter_clone = counter.clone();
 
-        let result: Result<i32, tonic::Status> = with_retry("test", || {
+        let result: Result<i32, tonic::Status> = with_retry(&fast_policy(), "test", || {
             let c = counter_clone.clone();
             async move {
                 c.fetch_add(1, Ordering::SeqCst);
@@ -540,7 +858,7 @@ mod tests {
         let counter = Arc::new(AtomicU32::new(0));
         let counter_clone = counter.clone();
 
-        let result: Result<i32, tonic::Status> = with_retry("test", || {
+        let result: Result<i32, tonic::Status> = with_retry(&fast_policy(), "test", || {
             let c = counter_clone.clone();
             async move {
                 c.fetch_add(1, Ordering::SeqCst);
@@ -551,7 +869,6 @@ mod tests {
 
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().code(), tonic::Code::Unavailable);
-        // Should retry MAX_RETRIES + 1 times (initial + retries)
-        assert_eq!(counter.load(Ordering::SeqCst), MAX_RETRIES + 1);
+        assert_eq!(counter.load(Ordering::SeqCst), DEFAULT_RETRY_ATTEMPTS);
     }
 }
@@ -0,0 +1,156 @@
+//! ctld-agent version handshake.
+//!
+//! During a rolling upgrade the controller can be newer than the agent it
+//! talks to. On connect the controller asks the agent for its API revision
+//! (GetSystemInfo) and only uses RPCs the agent has, failing with a clear
+//! message instead of an opaque `UNIMPLEMENTED` when a configured feature
+//! needs a newer agent. Agents that predate GetSystemInfo report revision 0.
+
+use std::fmt;
+
+use tonic::Status;
+
+/// Agent API revision this driver was built against
+pub const AGENT_API_VERSION: u32 = 6;
+
+/// Agent capability the controller uses only when the agent has it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgentFeature {
+    /// IsVolumeExportReady, behind `--wait-for-export-ready`
+    ExportReady,
+    /// VolumeExists, a cheaper existence check than GetVolume
+    VolumeExists,
+    /// SetVolumeAttachment/ListVolumeAttachments, persisting attach tracking
+    VolumeAttachments,
+}
+
+impl AgentFeature {
+    /// First agent API revision providing the feature
+    pub fn since_api_version(self) -> u32 {
+        match self {
+            AgentFeature::ExportReady | AgentFeature::VolumeExists => 1,
+            AgentFeature::VolumeAttachments => 6,
+        }
+    }
+}
+
+impl fmt::Display for AgentFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgentFeature::ExportReady => write!(f, "IsVolumeExportReady"),
+            AgentFeature::VolumeExists => write!(f, "VolumeExists"),
+            AgentFeature::VolumeAttachments => write!(f, "SetVolumeAttachment"),
+        }
+    }
+}
+
+/// What the connected agent reported about itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentInfo {
+    /// Agent release, empty for agents that predate GetSystemInfo
+    pub agent_version: String,
+    pub api_version: u32,
+}
+
+impl AgentInfo {
+    /// An agent without GetSystemInfo
+    pub fn legacy() -> Self {
+        Self {
+            agent_version: String::new(),
+            api_version: 0,
+        }
+    }
+
+    pub fn supports(&self, feature: AgentFeature) -> bool {
+        self.api_version >= feature.since_api_version()
+    }
+
+    /// Fail with `FailedPrecondition` unless the agent supports `feature`
+    pub fn require(&self, feature: AgentFeature, reason: &str) -> Result<(), Status> {
+        if self.supports(feature) {
+            return Ok(());
+        }
+        Err(Status::failed_precondition(format!(
+            "{} needs ctld-agent support for {} (API version {}), but the connected agent {} \
+             only provides API version {}; upgrade the agent",
+            reason,
+            feature,
+            feature.since_api_version(),
+            self.describe_version(),
+            self.api_version
+        )))
+    }
+
+    /// Whether the agent is older than this driver expects
+    pub fn is_outdated(&self) -> bool {
+        self.api_version < AGENT_API_VERSION
+    }
+
+    fn describe_version(&self) -> String {
+        if self.agent_version.is_empty() {
+            "(version unknown)".to_string()
+        } else {
+            self.agent_version.clone()
+        }
+    }
+}
+
+impl Default for AgentInfo {
+    fn default() -> Self {
+        Self::legacy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(api_version: u32) -> AgentInfo {
+        AgentInfo {
+            agent_version: "0.4.0".to_string(),
+            api_version,
+        }
+    }
+
+    #[test]
+    fn test_feature_gating_by_api_version() {
+        // Agent older than the controller
+        let legacy = AgentInfo::legacy();
+        assert!(legacy.is_outdated());
+        assert!(!legacy.supports(AgentFeature::ExportReady));
+        assert!(!legacy.supports(AgentFeature::VolumeExists));
+
+        // Matching versions
+        let current = agent(AGENT_API_VERSION);
+        assert!(!current.is_outdated());
+        assert!(current.supports(AgentFeature::ExportReady));
+        assert!(current.supports(AgentFeature::VolumeExists));
+        assert!(current.supports(AgentFeature::VolumeAttachments));
+        assert!(!agent(5).supports(AgentFeature::VolumeAttachments));
+
+        // Agent newer than the controller still serves what it knows
+        let newer = agent(AGENT_API_VERSION + 1);
+        assert!(!newer.is_outdated());
+        assert!(newer.supports(AgentFeature::ExportReady));
+    }
+
+    #[test]
+    fn test_require_reports_missing_feature() {
+        assert!(
+            agent(AGENT_API_VERSION)
+                .require(AgentFeature::ExportReady, "--wait-for-export-ready")
+                .is_ok()
+        );
+
+        let err = AgentInfo::legacy()
+            .require(AgentFeature::ExportReady, "--wait-for-export-ready")
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert_eq!(
+            err.message(),
+            "--wait-for-export-ready needs ctld-agent support for IsVolumeExportReady \
+             (API version 1), but the connected agent (version unknown) only provides \
+             API version 0; upgrade the agent"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Controller-side attach tracking.
+//!
+//! ControllerPublishVolume records which node a volume is attached to so a
+//! second node cannot attach a single-node volume while the first still has
+//! it: two initiators writing one ext4/xfs device corrupt it. Volumes
+//! published with a multi-node access mode may be attached to several nodes
+//! at once, as long as every attachment uses a multi-node mode.
+//!
+//! The external-attacher does not publish VolumeAttachments again once they
+//! are marked attached, so the state cannot be rebuilt from its calls after
+//! a controller restart. Every change is instead saved in the volume's
+//! metadata on the agent (SetVolumeAttachment), and the tracker is loaded
+//! from there (ListVolumeAttachments) before its first use.
+
+use std::collections::{BTreeSet, HashMap};
+
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Nodes a volume is published to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub nodes: BTreeSet<String>,
+    /// Published with a multi-node access mode
+    pub multi_node: bool,
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    /// Whether the saved attachments have been loaded
+    loaded: bool,
+    attachments: HashMap<String, Attachment>,
+}
+
+/// Volume attachments, keyed by volume ID
+#[derive(Debug, Default)]
+pub struct AttachmentTracker {
+    state: Mutex<TrackerState>,
+}
+
+impl AttachmentTracker {
+    /// Take the tracker for one change. Holding it while the change is saved
+    /// keeps a concurrent publish from deciding on unsaved state.
+    pub async fn lock(&self) -> Attachments<'_> {
+        Attachments {
+            state: self.state.lock().await,
+        }
+    }
+}
+
+/// Exclusive access to the tracked attachments
+pub struct Attachments<'a> {
+    state: MutexGuard<'a, TrackerState>,
+}
+
+impl Attachments<'_> {
+    /// Whether [`Self::load`] has run
+    pub fn is_loaded(&self) -> bool {
+        self.state.loaded
+    }
+
+    /// Replace the tracked attachments with saved ones
+    pub fn load(&mut self, attachments: impl IntoIterator<Item = (String, Attachment)>) {
+        self.state.attachments = attachments
+            .into_iter()
+            .filter(|(_, attachment)| !attachment.nodes.is_empty())
+            .collect();
+        self.state.loaded = true;
+    }
+
+    /// Record that `volume_id` is published to `node_id`.
+    ///
+    /// Returns whether the attachment changed; publishing again to the same
+    /// node succeeds without a change. Fails with the node already holding
+    /// the volume when it cannot be shared.
+    pub fn publish(
+        &mut self,
+        volume_id: &str,
+        node_id: &str,
+        multi_node: bool,
+    ) -> Result<bool, String> {
+        let attachments = &mut self.state.attachments;
+        let Some(attachment) = attachments.get_mut(volume_id) else {
+            attachments.insert(
+                volume_id.to_string(),
+                Attachment {
+                    nodes: BTreeSet::from([node_id.to_string()]),
+                    multi_node,
+                },
+            );
+            return Ok(true);
+        };
+
+        if attachment.nodes.contains(node_id) {
+            return Ok(false);
+        }
+        if attachment.multi_node && multi_node {
+            attachment.nodes.insert(node_id.to_string());
+            return Ok(true);
+        }
+        Err(attachment.nodes.iter().next().cloned().unwrap_or_default())
+    }
+
+    /// Forget the attachment of `volume_id` to `node_id` (to every node when
+    /// `node_id` is empty). Returns whether the attachment changed;
+    /// unpublishing a volume that is not attached is a no-op.
+    pub fn unpublish(&mut self, volume_id: &str, node_id: &str) -> bool {
+        let attachments = &mut self.state.attachments;
+        if node_id.is_empty() {
+            return attachments.remove(volume_id).is_some();
+        }
+        let Some(attachment) = attachments.get_mut(volume_id) else {
+            return false;
+        };
+        let removed = attachment.nodes.remove(node_id);
+        if attachment.nodes.is_empty() {
+            attachments.remove(volume_id);
+        }
+        removed
+    }
+
+    /// Current attachment of `volume_id`, if published anywhere
+    pub fn get(&self, volume_id: &str) -> Option<Attachment> {
+        self.state.attachments.get(volume_id).cloned()
+    }
+
+    /// Restore an attachment taken with [`Self::get`], e.g. after saving a
+    /// change failed
+    pub fn set(&mut self, volume_id: &str, attachment: Option<Attachment>) {
+        match attachment {
+            Some(attachment) => {
+                self.state
+                    .attachments
+                    .insert(volume_id.to_string(), attachment);
+            }
+            None => {
+                self.state.attachments.remove(volume_id);
+            }
+        }
+    }
+
+    /// Nodes `volume_id` is currently published to
+    pub fn nodes(&self, volume_id: &str) -> Vec<String> {
+        self.state
+            .attachments
+            .get(volume_id)
+            .map(|a| a.nodes.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_single_node_volume_rejects_second_node() {
+        let tracker = AttachmentTracker::default();
+        let mut attachments = tracker.lock().await;
+        assert_eq!(attachments.publish("pvc-1", "node-a", false), Ok(true));
+        // Retried publish to the same node is idempotent
+        assert_eq!(attachments.publish("pvc-1", "node-a", false), Ok(false));
+
+        assert_eq!(
+            attachments.publish("pvc-1", "node-b", false).unwrap_err(),
+            "node-a"
+        );
+        // A multi-node request cannot join a single-node attachment either
+        assert!(attachments.publish("pvc-1", "node-b", true).is_err());
+
+        assert!(attachments.unpublish("pvc-1", "node-a"));
+        attachments.publish("pvc-1", "node-b", false).unwrap();
+        assert_eq!(attachments.nodes("pvc-1"), ["node-b"]);
+    }
+
+    #[tokio::test]
+    async fn test_multi_node_volume_shared() {
+        let tracker = AttachmentTracker::default();
+        let mut attachments = tracker.lock().await;
+        attachments.publish("pvc-1", "node-a", true).unwrap();
+        attachments.publish("pvc-1", "node-b", true).unwrap();
+        assert!(attachments.publish("pvc-1", "node-c", false).is_err());
+        assert_eq!(attachments.nodes("pvc-1"), ["node-a", "node-b"]);
+
+        attachments.unpublish("pvc-1", "node-a");
+        assert_eq!(attachments.nodes("pvc-1"), ["node-b"]);
+    }
+
+    #[tokio::test]
+    async fn test_unpublish_is_idempotent() {
+        let tracker = AttachmentTracker::default();
+        let mut attachments = tracker.lock().await;
+        assert!(!attachments.unpublish("pvc-1", "node-a"));
+
+        attachments.publish("pvc-1", "node-a", true).unwrap();
+        attachments.publish("pvc-1", "node-b", true).unwrap();
+        assert!(!attachments.unpublish("pvc-1", "node-c"));
+        assert_eq!(attachments.nodes("pvc-1").len(), 2);
+
+        // An empty node ID detaches the volume everywhere
+        assert!(attachments.unpublish("pvc-1", ""));
+        assert!(attachments.nodes("pvc-1").is_empty());
+        assert!(!attachments.unpublish("pvc-1", ""));
+    }
+
+    #[tokio::test]
+    async fn test_loaded_attachments_survive_restart() {
+        let saved = Attachment {
+            nodes: BTreeSet::from(["node-a".to_string()]),
+            multi_node: false,
+        };
+
+        // A new controller loads what the previous one saved
+        let tracker = AttachmentTracker::default();
+        let mut attachments = tracker.lock().await;
+        assert!(!attachments.is_loaded());
+        attachments.load([("pvc-1".to_string(), saved.clone())]);
+        assert!(attachments.is_loaded());
+        assert!(attachments.publish("pvc-1", "node-b", false).is_err());
+
+        // A change that could not be saved is rolled back
+        let previous = attachments.get("pvc-1");
+        assert!(attachments.unpublish("pvc-1", "node-a"));
+        attachments.set("pvc-1", previous);
+        assert_eq!(attachments.get("pvc-1"), Some(saved));
+    }
+}
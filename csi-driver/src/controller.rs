@@ -3,6 +3,7 @@
 //! Handles volume and snapshot lifecycle operations by calling the ctld-agent daemon.
 
 use std::collections::HashMap;
+use std::time::Duration;
 
 use prost_types::Timestamp;
 use tokio::sync::RwLock;
@@ -13,10 +14,17 @@ use crate::agent::{
     AuthCredentials, IscsiChapCredentials, NvmeAuthCredentials, VolumeContentSource,
     auth_credentials,
 };
-use crate::agent_client::{AgentClient, TlsConfig};
+use crate::agent_client::{AgentClient, RetryPolicy, TlsConfig};
+use crate::agent_info::{AGENT_API_VERSION, AgentFeature};
+use crate::attachments::{Attachment, AttachmentTracker, Attachments};
 use crate::csi;
 use crate::metrics::{self, OperationTimer};
-use crate::types::{CloneMode, ExportType, NvmeofConnectOptions, ProvisioningMode};
+use crate::platform;
+use crate::types::{
+    CloneMode, DEFAULT_PORT_CONTEXT_KEY, DefaultPorts, DirectIo, Endpoints, ExportType,
+    IscsiDiscoveryOptions, MKFS_OPTIONS_PARAM, NodeInitiators, NvmeofConnectOptions,
+    NvmeofDiscovery, NvmeofMultipath, ProvisioningMode, canonical_parameters, unknown_parameters,
+};
 
 // Standard CSI secret keys for iSCSI CHAP authentication
 // These follow the Linux open-iscsi naming conventions used by the CSI spec
@@ -38,12 +46,275 @@ const CLONE_MODE_PARAM: &str = "cloneMode";
 /// Default volume size: 1GB
 const DEFAULT_VOLUME_SIZE: i64 = 1024 * 1024 * 1024;
 
+/// Interval between export readiness checks after CreateVolume
+const EXPORT_READY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Poll `is_ready` until it reports true or `timeout` elapses.
+///
+/// Errors from a single check are logged and polled through, except
+/// `Unimplemented` (an agent without the readiness RPC), which skips the wait.
+async fn wait_for_export_ready<F, Fut>(
+    volume_id: &str,
+    mut is_ready: F,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<(), Status>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<bool, Status>>,
+{
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        match is_ready().await {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(e) if e.code() == tonic::Code::Unimplemented => {
+                warn!(
+                    volume_id = %volume_id,
+                    "Agent does not support export readiness checks, not waiting"
+                );
+                return Ok(());
+            }
+            Err(e) => {
+                debug!(volume_id = %volume_id, error = %e, "Export readiness check failed");
+            }
+        }
+
+        if tokio::time::Instant::now() + interval > deadline {
+            return Err(Status::deadline_exceeded(format!(
+                "export of volume {} not ready after {:?}",
+                volume_id, timeout
+            )));
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// The StorageClass `endpoints` normalized for the volume context.
+///
+/// Validated here so a malformed portal list fails CreateVolume instead of
+/// every later NodeStageVolume. `default_port` is filled in for host-only
+/// entries and IPv6 hosts bracketed, so the node parses exactly what was
+/// checked.
+fn context_endpoints(
+    parameters: &HashMap<String, String>,
+    default_port: u16,
+) -> Result<Option<String>, Status> {
+    parameters
+        .get("endpoints")
+        .map(|value| {
+            Endpoints::parse_strict(value, default_port)
+                .map(|endpoints| endpoints.to_context_string())
+                .map_err(|e| {
+                    Status::invalid_argument(format!(
+                        "endpoints parameter: {} (IPv6 addresses must be bracketed, e.g. [fd00::1]:{})",
+                        e, default_port
+                    ))
+                })
+        })
+        .transpose()
+}
+
+/// Fail with NotFound unless the volume exists.
+///
+/// Uses the agent's lightweight `exists` check when `has_exists` says the
+/// agent supports it, and the full `get` lookup otherwise or when the agent
+/// turns out to predate the VolumeExists RPC.
+async fn ensure_volume_exists<E, EFut, G, GFut>(
+    volume_id: &str,
+    has_exists: bool,
+    exists: E,
+    get: G,
+) -> Result<(), Status>
+where
+    E: FnOnce() -> EFut,
+    EFut: std::future::Future<Output = Result<bool, Status>>,
+    G: FnOnce() -> GFut,
+    GFut: std::future::Future<Output = Result<(), Status>>,
+{
+    if !has_exists {
+        return get().await;
+    }
+    match exists().await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Status::not_found(format!("volume {} not found", volume_id))),
+        Err(e) if e.code() == tonic::Code::Unimplemented => {
+            debug!(
+                volume_id = %volume_id,
+                "Agent does not support VolumeExists, falling back to GetVolume"
+            );
+            get().await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether the agent reports the volume as existing, or `None` when that is
+/// unknown: the agent predates VolumeExists (by `has_exists` or by answering
+/// Unimplemented) or the check failed. Callers keep their previous checks
+/// for `None`.
+async fn agent_volume_exists<E, EFut>(volume_id: &str, has_exists: bool, exists: E) -> Option<bool>
+where
+    E: FnOnce() -> EFut,
+    EFut: std::future::Future<Output = Result<bool, Status>>,
+{
+    if !has_exists {
+        return None;
+    }
+    match exists().await {
+        Ok(exists) => Some(exists),
+        Err(e) => {
+            debug!(volume_id = %volume_id, error = %e, "VolumeExists unavailable");
+            None
+        }
+    }
+}
+
+/// Check an existing volume against a repeated CreateVolume request.
+///
+/// The volume matches when its export type and the requested parameters are
+/// the same and its size fits the capacity range; otherwise the name is
+/// taken by a different volume and the request fails with `AlreadyExists`.
+/// The provisioning mode is not compared: the agent reports the mode in
+/// effect, which can differ from the request.
+fn check_existing_volume(
+    existing: &crate::agent::Volume,
+    export_type: ExportType,
+    capacity_range: Option<&csi::CapacityRange>,
+    parameters: &HashMap<String, String>,
+) -> Result<(), Status> {
+    let mismatch = |what: String| {
+        Status::already_exists(format!(
+            "volume {} already exists with {}",
+            existing.name, what
+        ))
+    };
+
+    if existing.export_type() != export_type.into() {
+        return Err(mismatch("a different export type".to_string()));
+    }
+
+    let required = ControllerService::get_volume_size(capacity_range);
+    let limit = capacity_range.map_or(0, |range| range.limit_bytes);
+    if existing.size_bytes < required || (limit > 0 && existing.size_bytes > limit) {
+        return Err(mismatch(format!("size {} bytes", existing.size_bytes)));
+    }
+
+    if let Some((key, value)) = parameters.iter().find(|(key, value)| {
+        key.as_str() != ProvisioningMode::PARAM_NAME
+            && existing.parameters.get(key.as_str()) != Some(*value)
+    }) {
+        return Err(mismatch(format!(
+            "a different {} (requested '{}')",
+            key, value
+        )));
+    }
+    Ok(())
+}
+
+/// Reasons the requested capabilities cannot be satisfied (empty when all
+/// are supported).
+///
+/// Mount volumes carry ext4/xfs on a single device, which cannot be shared
+/// by writers on several nodes; raw block volumes leave that coordination to
+/// the application.
+fn unsupported_capability_reasons(capabilities: &[csi::VolumeCapability]) -> Vec<String> {
+    let mut unsupported_reasons: Vec<String> = Vec::new();
+
+    for cap in capabilities {
+        // Determine if this is a block volume request
+        let is_block = matches!(
+            &cap.access_type,
+            Some(csi::volume_capability::AccessType::Block(_))
+        );
+
+        // Check access type (mount vs block)
+        match &cap.access_type {
+            Some(csi::volume_capability::AccessType::Mount(_)) => {
+                // Mount volumes are fully supported
+            }
+            Some(csi::volume_capability::AccessType::Block(_)) => {
+                // Block volumes are supported (raw device access)
+            }
+            None => {
+                unsupported_reasons.push("Volume capability must specify access type".to_string());
+            }
+        }
+
+        // Check access mode
+        if let Some(access_mode) = &cap.access_mode {
+            use csi::volume_capability::access_mode::Mode;
+            match Mode::try_from(access_mode.mode) {
+                Ok(Mode::SingleNodeWriter) => {
+                    // ReadWriteOnce (RWO) - fully supported
+                }
+                Ok(Mode::SingleNodeReaderOnly) => {
+                    // ReadOnlyOnce - supported
+                }
+                Ok(Mode::MultiNodeReaderOnly) => {
+                    // ReadOnlyMany (ROX) - supported (iSCSI/NVMeoF allows multiple readers)
+                }
+                Ok(Mode::MultiNodeSingleWriter) => {
+                    // Multiple nodes attached, single writer - useful for active-passive failover.
+                    // Supported for block volumes (application/SCSI PR handles coordination).
+                    if !is_block {
+                        unsupported_reasons.push(
+                            "MULTI_NODE_SINGLE_WRITER not supported for mount volumes".to_string(),
+                        );
+                    }
+                }
+                Ok(Mode::MultiNodeMultiWriter) => {
+                    // ReadWriteMany (RWX) - supported for block volumes (application handles coordination),
+                    // but not for mount volumes (standard filesystems can't handle concurrent writers)
+                    if !is_block {
+                        unsupported_reasons.push(
+                            "MULTI_NODE_MULTI_WRITER not supported for mount volumes (requires cluster filesystem)"
+                                .to_string(),
+                        );
+                    }
+                }
+                Ok(Mode::SingleNodeSingleWriter) => {
+                    // ReadWriteOncePod (RWOP) - GA in Kubernetes 1.29+
+                    // Kubernetes enforces single-pod constraint, driver just allows it
+                }
+                Ok(Mode::SingleNodeMultiWriter) => {
+                    // Single node, multiple writers - supported (same as RWO semantically)
+                }
+                Ok(Mode::Unknown) | Err(_) => {
+                    unsupported_reasons.push(format!("Unknown access mode: {}", access_mode.mode));
+                }
+            }
+        }
+    }
+
+    unsupported_reasons
+}
+
+/// Whether a capability lets the volume be attached to several nodes
+fn is_multi_node(capability: &csi::VolumeCapability) -> bool {
+    use csi::volume_capability::access_mode::Mode;
+
+    capability
+        .access_mode
+        .as_ref()
+        .and_then(|m| Mode::try_from(m.mode).ok())
+        .is_some_and(|mode| {
+            matches!(
+                mode,
+                Mode::MultiNodeReaderOnly
+                    | Mode::MultiNodeSingleWriter
+                    | Mode::MultiNodeMultiWriter
+            )
+        })
+}
+
 /// CSI Controller Service
 ///
 /// Implements the CSI Controller service which handles:
 /// - Volume creation and deletion
 /// - Volume expansion
 /// - Snapshot creation and deletion
+/// - Attach tracking (ControllerPublish/UnpublishVolume)
 /// - Capability reporting
 ///
 /// Uses RwLock for the client cache to allow concurrent read access
@@ -56,6 +327,16 @@ pub struct ControllerService {
     tls_config: Option<TlsConfig>,
     /// Lazily initialized agent client connection (RwLock for better concurrency)
     client: RwLock<Option<AgentClient>>,
+    /// Reject CreateVolume requests carrying unrecognized StorageClass parameters
+    strict_parameters: bool,
+    /// How long CreateVolume waits for the export to go live (None = don't wait)
+    export_ready_timeout: Option<Duration>,
+    /// Nodes each volume is published to
+    attachments: AttachmentTracker,
+    /// Ports for endpoints listed without one
+    default_ports: DefaultPorts,
+    /// Retry policy for agent calls
+    retry_policy: RetryPolicy,
 }
 
 impl ControllerService {
@@ -65,6 +346,11 @@ impl ControllerService {
             agent_endpoint,
             tls_config: None,
             client: RwLock::new(None),
+            strict_parameters: false,
+            export_ready_timeout: None,
+            attachments: AttachmentTracker::default(),
+            default_ports: DefaultPorts::default(),
+            retry_policy: RetryPolicy::default(),
         }
     }
 
@@ -74,7 +360,58 @@ impl ControllerService {
             agent_endpoint,
             tls_config,
             client: RwLock::new(None),
+            strict_parameters: false,
+            export_ready_timeout: None,
+            attachments: AttachmentTracker::default(),
+            default_ports: DefaultPorts::default(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Reject (rather than ignore) unrecognized StorageClass parameters.
+    pub fn with_strict_parameters(mut self, strict: bool) -> Self {
+        self.strict_parameters = strict;
+        self
+    }
+
+    /// Make CreateVolume wait up to `timeout` for the target to go live on
+    /// the agent, failing with `DeadlineExceeded` otherwise.
+    pub fn with_export_ready_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.export_ready_timeout = timeout;
+        self
+    }
+
+    /// Use `ports` for StorageClass endpoints that omit the port.
+    pub fn with_default_ports(mut self, ports: DefaultPorts) -> Self {
+        self.default_ports = ports;
+        self
+    }
+
+    /// Retry transient agent failures according to `policy`.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Check StorageClass parameters against the known-key registry.
+    ///
+    /// In strict mode unknown keys fail the request with `InvalidArgument`;
+    /// otherwise they are logged at debug and ignored.
+    fn check_parameters(parameters: &HashMap<String, String>, strict: bool) -> Result<(), Status> {
+        let unknown = unknown_parameters(parameters);
+        if unknown.is_empty() {
+            return Ok(());
+        }
+
+        if strict {
+            return Err(Status::invalid_argument(format!(
+                "unrecognized StorageClass parameter(s): {}",
+                unknown.join(", ")
+            )));
         }
+
+        debug!(unknown = ?unknown, "Ignoring unrecognized StorageClass parameters");
+        Ok(())
     }
 
     /// Get or create the agent client connection.
@@ -105,14 +442,140 @@ impl ControllerService {
                 metrics::record_connection_attempt(false);
                 metrics::set_agent_connected(false);
                 Status::unavailable("Agent connection failed")
+            })?
+            .with_retry_policy(self.retry_policy)
+            .handshake()
+            .await
+            .map_err(|e| {
+                error!(error = %e, "ctld-agent version handshake failed");
+                metrics::record_connection_attempt(false);
+                metrics::set_agent_connected(false);
+                Status::unavailable(format!("Agent version handshake failed: {}", e.message()))
             })?;
 
+        let agent = client.info();
+        if agent.is_outdated() {
+            warn!(
+                agent_version = %agent.agent_version,
+                api_version = agent.api_version,
+                expected_api_version = AGENT_API_VERSION,
+                "ctld-agent is older than this controller; features it lacks are disabled"
+            );
+        } else {
+            info!(
+                agent_version = %agent.agent_version,
+                api_version = agent.api_version,
+                "Connected to ctld-agent"
+            );
+        }
+
         metrics::record_connection_attempt(true);
         metrics::set_agent_connected(true);
         *guard = Some(client.clone());
         Ok(client)
     }
 
+    /// Load the attachments saved on the agent on first use.
+    ///
+    /// Agents without SetVolumeAttachment keep none; tracking then starts
+    /// empty and is lost again when the controller restarts.
+    async fn load_attachments(
+        attachments: &mut Attachments<'_>,
+        client: &AgentClient,
+    ) -> Result<(), Status> {
+        if attachments.is_loaded() {
+            return Ok(());
+        }
+        if !client.info().supports(AgentFeature::VolumeAttachments) {
+            warn!(
+                "ctld-agent cannot save volume attachments; attach tracking is lost when the \
+                 controller restarts"
+            );
+            attachments.load([]);
+            return Ok(());
+        }
+
+        let saved = client.clone().list_volume_attachments().await?;
+        info!(
+            count = saved.len(),
+            "Loaded volume attachments from ctld-agent"
+        );
+        attachments.load(saved.into_iter().map(|a| {
+            (
+                a.volume_id,
+                Attachment {
+                    nodes: a.node_ids.into_iter().collect(),
+                    multi_node: a.multi_node,
+                },
+            )
+        }));
+        Ok(())
+    }
+
+    /// Save a volume's attachment (`None` once it is published nowhere) on
+    /// agents that can store it.
+    ///
+    /// The initiators of the nodes (reported in their node IDs) are passed
+    /// along, so the agent admits only them to the volume.
+    async fn save_attachment(
+        client: &AgentClient,
+        volume_id: &str,
+        attachment: Option<&Attachment>,
+    ) -> Result<(), Status> {
+        if !client.info().supports(AgentFeature::VolumeAttachments) {
+            return Ok(());
+        }
+        client
+            .clone()
+            .set_volume_attachment(agent_attachment(volume_id, attachment))
+            .await
+    }
+
+    /// The volume named `name` if it is already fully exported.
+    ///
+    /// Lets a retried CreateVolume return without another round of agent
+    /// work. A volume the agent knows but has not exported yet (an earlier
+    /// create was interrupted) is left to the agent's CreateVolume to finish,
+    /// as are agents that cannot report export readiness. Lookup errors only
+    /// skip the shortcut.
+    async fn find_exported_volume(
+        client: &mut AgentClient,
+        name: &str,
+    ) -> Option<crate::agent::Volume> {
+        if !client.info().supports(AgentFeature::ExportReady) {
+            return None;
+        }
+        // New volumes, the common case, skip the full lookup
+        let exists = agent_volume_exists(
+            name,
+            client.info().supports(AgentFeature::VolumeExists),
+            || {
+                let mut client = client.clone();
+                async move { client.volume_exists(name).await }
+            },
+        )
+        .await;
+        if exists == Some(false) {
+            return None;
+        }
+        let volume = match client.get_volume(name).await {
+            Ok(volume) => volume,
+            Err(e) if e.code() == tonic::Code::NotFound => return None,
+            Err(e) => {
+                debug!(name = %name, error = %e, "GetVolume failed, creating through the agent");
+                return None;
+            }
+        };
+        match client.is_volume_export_ready(name).await {
+            Ok(true) => Some(volume),
+            Ok(false) => None,
+            Err(e) => {
+                debug!(name = %name, error = %e, "Export readiness unknown, creating through the agent");
+                None
+            }
+        }
+    }
+
     /// Clear the cached connection (call on transport errors).
     async fn clear_client(&self) {
         let mut guard = self.client.write().await;
@@ -255,11 +718,37 @@ impl ControllerService {
         };
 
         // Parse clone mode from StorageClass parameters
-        let clone_mode: crate::agent::CloneMode = parameters
+        let requested_mode = parameters
             .get(CLONE_MODE_PARAM)
             .and_then(|s| s.parse::<CloneMode>().ok())
-            .unwrap_or_default()
-            .into();
+            .unwrap_or_default();
+
+        // strongIsolation overrides LINKED so tenants never share blocks
+        let strong_isolation = match parameters
+            .get(CloneMode::STRONG_ISOLATION_PARAM)
+            .map(|v| v.to_lowercase())
+            .as_deref()
+        {
+            None | Some("") | Some("false") | Some("0") | Some("no") => false,
+            Some("true") | Some("1") | Some("yes") => true,
+            Some(other) => {
+                return Err(Status::invalid_argument(format!(
+                    "invalid {} value '{}': expected true or false",
+                    CloneMode::STRONG_ISOLATION_PARAM,
+                    other
+                )));
+            }
+        };
+        let (effective_mode, override_reason) = requested_mode.enforce_isolation(strong_isolation);
+        if let Some(reason) = override_reason {
+            warn!(
+                requested = %requested_mode,
+                effective = %effective_mode,
+                reason = reason,
+                "Overriding clone mode"
+            );
+        }
+        let clone_mode: crate::agent::CloneMode = effective_mode.into();
 
         match source_type {
             Type::Snapshot(snapshot_source) => {
@@ -321,13 +810,16 @@ impl ControllerService {
     /// `parameters` contains the original StorageClass parameters which may include
     /// portal addresses and filesystem type needed by the node service.
     ///
-    /// `content_source` is the original request's volume_content_source, which must
-    /// be echoed back in the response when creating a volume from a snapshot or clone.
-    /// The CSI external-provisioner requires this field to properly bind the PV.
+    /// The content source reported by the agent is preferred; `requested` (the
+    /// original request's volume_content_source) is the fallback for agents that
+    /// don't report one. It must be echoed back when creating a volume from a
+    /// snapshot or clone: the CSI external-provisioner requires this field to
+    /// properly bind the PV.
     fn agent_volume_to_csi(
         volume: &crate::agent::Volume,
         parameters: &HashMap<String, String>,
-        content_source: Option<csi::VolumeContentSource>,
+        requested: Option<csi::VolumeContentSource>,
+        default_ports: DefaultPorts,
     ) -> csi::Volume {
         let mut volume_context = HashMap::new();
         // Volume context keys use camelCase (Kubernetes convention)
@@ -344,24 +836,61 @@ impl ControllerService {
         volume_context.insert("exportType".to_string(), export_type.to_string());
 
         // Pass through endpoints for node service (required for iSCSI/NVMeoF connection)
-        // Format: "host:port,host:port,..." - supports multipath when multiple endpoints provided
-        if let Some(endpoints) = parameters.get("endpoints") {
-            volume_context.insert("endpoints".to_string(), endpoints.clone());
+        // Format: "host:port,host:port,..." - supports multipath when multiple endpoints provided.
+        // Validated in create_volume; the raw value is kept if normalizing fails.
+        let default_port = default_ports.port(export_type);
+        let endpoints = context_endpoints(parameters, default_port)
+            .ok()
+            .flatten()
+            .or_else(|| parameters.get("endpoints").cloned());
+        if let Some(endpoints) = endpoints {
+            volume_context.insert("endpoints".to_string(), endpoints);
+            // Normalized endpoints already carry their ports; the default is
+            // for the raw fallback above and for copies of the context
+            if default_port != export_type.default_port() {
+                volume_context.insert(
+                    DEFAULT_PORT_CONTEXT_KEY.to_string(),
+                    default_port.to_string(),
+                );
+            }
         }
 
         // Pass through filesystem type for node service
         if let Some(fs_type) = parameters.get("fsType") {
             volume_context.insert("fsType".to_string(), fs_type.clone());
         }
+        if let Some(direct_io) = parameters.get(DirectIo::PARAM_NAME) {
+            volume_context.insert(DirectIo::PARAM_NAME.to_string(), direct_io.clone());
+        }
+        if let Some(mkfs_options) = parameters.get(MKFS_OPTIONS_PARAM) {
+            volume_context.insert(MKFS_OPTIONS_PARAM.to_string(), mkfs_options.clone());
+        }
 
-        if export_type == ExportType::Nvmeof {
-            for key in NvmeofConnectOptions::PARAM_NAMES {
-                if let Some(value) = parameters.get(*key) {
-                    volume_context.insert((*key).to_string(), value.clone());
-                }
+        let connect_params = match export_type {
+            ExportType::Iscsi => IscsiDiscoveryOptions::PARAM_NAMES,
+            ExportType::Nvmeof => NvmeofConnectOptions::PARAM_NAMES,
+        };
+        let multipath_param =
+            (export_type == ExportType::Nvmeof).then_some(&NvmeofMultipath::PARAM_NAME);
+        for key in connect_params.iter().chain(multipath_param) {
+            if let Some(value) = parameters.get(*key) {
+                volume_context.insert((*key).to_string(), value.clone());
             }
         }
 
+        // Validated in create_volume
+        if export_type == ExportType::Nvmeof
+            && let Ok(Some(discovery)) = NvmeofDiscovery::from_parameters(parameters)
+        {
+            discovery.to_volume_context(&mut volume_context);
+        }
+
+        let content_source = volume
+            .content_source
+            .as_ref()
+            .and_then(Self::agent_content_source_to_csi)
+            .or(requested);
+
         csi::Volume {
             capacity_bytes: volume.size_bytes,
             volume_id: volume.id.clone(),
@@ -371,6 +900,29 @@ impl ControllerService {
         }
     }
 
+    /// Convert the agent's content source to its CSI form.
+    ///
+    /// Image URL sources have no CSI counterpart and map to None.
+    fn agent_content_source_to_csi(
+        source: &VolumeContentSource,
+    ) -> Option<csi::VolumeContentSource> {
+        use crate::agent::volume_content_source::Source;
+        use csi::volume_content_source::{SnapshotSource, Type, VolumeSource};
+
+        let source_type = match source.source.as_ref()? {
+            Source::SnapshotId(snapshot_id) => Type::Snapshot(SnapshotSource {
+                snapshot_id: snapshot_id.clone(),
+            }),
+            Source::SourceVolumeId(volume_id) => Type::Volume(VolumeSource {
+                volume_id: volume_id.clone(),
+            }),
+            Source::ImageUrl(_) => return None,
+        };
+        Some(csi::VolumeContentSource {
+            r#type: Some(source_type),
+        })
+    }
+
     /// Convert agent Snapshot to CSI Snapshot.
     fn agent_snapshot_to_csi(snapshot: &crate::agent::Snapshot) -> csi::Snapshot {
         csi::Snapshot {
@@ -381,12 +933,39 @@ impl ControllerService {
                 seconds: snapshot.creation_time,
                 nanos: 0,
             }),
-            ready_to_use: true,
+            ready_to_use: snapshot.ready_to_use,
             group_snapshot_id: String::new(),
         }
     }
 }
 
+/// The agent's record of a volume's attachment.
+///
+/// Initiator names and host NQNs are listed only when every node reported
+/// one; a single node without leaves the volume open to any initiator.
+fn agent_attachment(
+    volume_id: &str,
+    attachment: Option<&Attachment>,
+) -> crate::agent::VolumeAttachment {
+    let nodes: Vec<String> = attachment
+        .map(|a| a.nodes.iter().cloned().collect())
+        .unwrap_or_default();
+    let initiators: Vec<NodeInitiators> = nodes
+        .iter()
+        .map(|node_id| NodeInitiators::decode_node_id(node_id).1)
+        .collect();
+    let initiator_names: Option<Vec<String>> = initiators.iter().map(|i| i.iqn.clone()).collect();
+    let host_nqns: Option<Vec<String>> = initiators.iter().map(|i| i.host_nqn.clone()).collect();
+
+    crate::agent::VolumeAttachment {
+        volume_id: volume_id.to_string(),
+        initiator_names: initiator_names.unwrap_or_default(),
+        host_nqns: host_nqns.unwrap_or_default(),
+        node_ids: nodes,
+        multi_node: attachment.is_some_and(|a| a.multi_node),
+    }
+}
+
 #[tonic::async_trait]
 impl csi::controller_server::Controller for ControllerService {
     /// Create a new volume.
@@ -395,7 +974,8 @@ impl csi::controller_server::Controller for ControllerService {
         request: Request<csi::CreateVolumeRequest>,
     ) -> Result<Response<csi::CreateVolumeResponse>, Status> {
         let timer = OperationTimer::new("create_volume");
-        let req = request.into_inner();
+        let mut req = request.into_inner();
+        req.parameters = canonical_parameters(std::mem::take(&mut req.parameters));
         let name = &req.name;
 
         if name.is_empty() {
@@ -405,9 +985,19 @@ impl csi::controller_server::Controller for ControllerService {
 
         info!(name = %name, "CreateVolume request");
 
+        if let Err(e) = Self::check_parameters(&req.parameters, self.strict_parameters) {
+            timer.failure("invalid_argument");
+            return Err(e);
+        }
+
         let size_bytes = Self::get_volume_size(req.capacity_range.as_ref());
         let export_type = Self::parse_export_type(&req.parameters);
 
+        if let Err(e) = context_endpoints(&req.parameters, self.default_ports.port(export_type)) {
+            timer.failure("invalid_argument");
+            return Err(e);
+        }
+
         if export_type == ExportType::Nvmeof
             && let Err(e) = NvmeofConnectOptions::parse(&req.parameters)
         {
@@ -415,6 +1005,56 @@ impl csi::controller_server::Controller for ControllerService {
             return Err(Status::invalid_argument(e.to_string()));
         }
 
+        if export_type == ExportType::Nvmeof
+            && let Err(e) = NvmeofDiscovery::from_parameters(&req.parameters)
+        {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument(e.to_string()));
+        }
+
+        if export_type == ExportType::Nvmeof
+            && let Err(e) = NvmeofMultipath::parse(&req.parameters)
+        {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument(e.to_string()));
+        }
+
+        if export_type == ExportType::Iscsi
+            && let Err(e) = IscsiDiscoveryOptions::parse(&req.parameters)
+        {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument(e.to_string()));
+        }
+
+        if let Err(e) = DirectIo::from_parameters(&req.parameters) {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument(e));
+        }
+
+        // Checked again at stage time. Stage formats with the capability's
+        // fsType when it has one, so validate against the same type.
+        if let Some(mkfs_options) = req.parameters.get(MKFS_OPTIONS_PARAM) {
+            let default_fs_type = req.parameters.get("fsType").map_or("", String::as_str);
+            for capability in &req.volume_capabilities {
+                let Some(csi::volume_capability::AccessType::Mount(mount)) =
+                    &capability.access_type
+                else {
+                    continue;
+                };
+                let fs_type = if mount.fs_type.is_empty() {
+                    default_fs_type
+                } else {
+                    &mount.fs_type
+                };
+                if let Err(e) = platform::validate_fs_type(fs_type)
+                    .and_then(|fs_type| platform::format_options(fs_type, mkfs_options))
+                {
+                    timer.failure("invalid_argument");
+                    return Err(e);
+                }
+            }
+        }
+
         // Extract authentication credentials from CSI secrets
         let auth = Self::extract_auth_credentials(&req.secrets, export_type);
 
@@ -440,6 +1080,46 @@ impl csi::controller_server::Controller for ControllerService {
         );
 
         let mut client = self.get_client().await?;
+        // Refuse before creating anything rather than leave a volume behind
+        // that can never report ready
+        if self.export_ready_timeout.is_some()
+            && let Err(e) = client
+                .info()
+                .require(AgentFeature::ExportReady, "--wait-for-export-ready")
+        {
+            error!(error = %e, "Connected agent cannot report export readiness");
+            timer.failure("failed_precondition");
+            return Err(e);
+        }
+
+        // A retried request for a volume that is already in place. Requests
+        // with a content source always go to the agent, since GetVolume does
+        // not report the source to compare against.
+        if content_source.is_none()
+            && let Some(existing) = Self::find_exported_volume(&mut client, name).await
+        {
+            if let Err(e) = check_existing_volume(
+                &existing,
+                export_type,
+                req.capacity_range.as_ref(),
+                &req.parameters,
+            ) {
+                warn!(name = %name, error = %e, "CreateVolume conflicts with existing volume");
+                timer.failure("already_exists");
+                return Err(e);
+            }
+            info!(volume_id = %existing.id, "Volume already exists (idempotent success)");
+            timer.success();
+            return Ok(Response::new(csi::CreateVolumeResponse {
+                volume: Some(Self::agent_volume_to_csi(
+                    &existing,
+                    &req.parameters,
+                    None,
+                    self.default_ports,
+                )),
+            }));
+        }
+
         let volume = match client
             .create_volume(
                 name,
@@ -469,12 +1149,32 @@ impl csi::controller_server::Controller for ControllerService {
             "Volume created successfully"
         );
 
+        if let Some(timeout) = self.export_ready_timeout {
+            let ready = wait_for_export_ready(
+                &volume.id,
+                || {
+                    let mut client = client.clone();
+                    let volume_id = volume.id.clone();
+                    async move { client.is_volume_export_ready(&volume_id).await }
+                },
+                timeout,
+                EXPORT_READY_POLL_INTERVAL,
+            )
+            .await;
+            if let Err(e) = ready {
+                error!(volume_id = %volume.id, error = %e, "Volume export did not become ready");
+                timer.failure(&e.code().to_string());
+                return Err(e);
+            }
+        }
+
         timer.success();
         Ok(Response::new(csi::CreateVolumeResponse {
             volume: Some(Self::agent_volume_to_csi(
                 &volume,
                 &req.parameters,
                 req.volume_content_source,
+                self.default_ports,
             )),
         }))
     }
@@ -499,6 +1199,27 @@ impl csi::controller_server::Controller for ControllerService {
         if let Err(e) = client.delete_volume(volume_id).await {
             // NOT_FOUND is acceptable - volume may have already been deleted
             if e.code() == tonic::Code::NotFound {
+                // ...unless the agent still has its dataset, in which case
+                // the NotFound came from something else and the delete must
+                // be retried
+                let exists = agent_volume_exists(
+                    volume_id,
+                    client.info().supports(AgentFeature::VolumeExists),
+                    || {
+                        let mut client = client.clone();
+                        async move { client.volume_exists(volume_id).await }
+                    },
+                )
+                .await;
+                if exists == Some(true) {
+                    error!(volume_id = %volume_id, error = %e, "Delete reported NotFound but the volume still exists");
+                    timer.failure("internal");
+                    return Err(Status::internal(format!(
+                        "volume {} still exists after delete: {}",
+                        volume_id,
+                        e.message()
+                    )));
+                }
                 warn!(volume_id = %volume_id, "Volume not found, treating as already deleted");
             } else {
                 error!(error = %e, "Failed to delete volume via agent");
@@ -604,6 +1325,13 @@ impl csi::controller_server::Controller for ControllerService {
                     },
                 )),
             },
+            csi::ControllerServiceCapability {
+                r#type: Some(csi::controller_service_capability::Type::Rpc(
+                    csi::controller_service_capability::Rpc {
+                        r#type: Type::PublishUnpublishVolume as i32,
+                    },
+                )),
+            },
             csi::ControllerServiceCapability {
                 r#type: Some(csi::controller_service_capability::Type::Rpc(
                     csi::controller_service_capability::Rpc {
@@ -760,83 +1488,29 @@ impl csi::controller_server::Controller for ControllerService {
             return Err(Status::invalid_argument("Volume ID is required"));
         }
 
+        if req.volume_capabilities.is_empty() {
+            return Err(Status::invalid_argument("Volume capabilities are required"));
+        }
+
         info!(volume_id = %volume_id, "ValidateVolumeCapabilities request");
 
         // Verify the volume exists
-        let mut client = self.get_client().await?;
-        client.get_volume(volume_id).await?;
-
-        // Validate each requested capability
-        let mut unsupported_reasons: Vec<String> = Vec::new();
-
-        for cap in &req.volume_capabilities {
-            // Determine if this is a block volume request
-            let is_block = matches!(
-                &cap.access_type,
-                Some(csi::volume_capability::AccessType::Block(_))
-            );
-
-            // Check access type (mount vs block)
-            match &cap.access_type {
-                Some(csi::volume_capability::AccessType::Mount(_)) => {
-                    // Mount volumes are fully supported
-                }
-                Some(csi::volume_capability::AccessType::Block(_)) => {
-                    // Block volumes are supported (raw device access)
-                }
-                None => {
-                    unsupported_reasons
-                        .push("Volume capability must specify access type".to_string());
-                }
-            }
+        let client = self.get_client().await?;
+        ensure_volume_exists(
+            volume_id,
+            client.info().supports(AgentFeature::VolumeExists),
+            || {
+                let mut client = client.clone();
+                async move { client.volume_exists(volume_id).await }
+            },
+            || {
+                let mut client = client.clone();
+                async move { client.get_volume(volume_id).await.map(|_| ()) }
+            },
+        )
+        .await?;
 
-            // Check access mode
-            if let Some(access_mode) = &cap.access_mode {
-                use csi::volume_capability::access_mode::Mode;
-                match Mode::try_from(access_mode.mode) {
-                    Ok(Mode::SingleNodeWriter) => {
-                        // ReadWriteOnce (RWO) - fully supported
-                    }
-                    Ok(Mode::SingleNodeReaderOnly) => {
-                        // ReadOnlyOnce - supported
-                    }
-                    Ok(Mode::MultiNodeReaderOnly) => {
-                        // ReadOnlyMany (ROX) - supported (iSCSI/NVMeoF allows multiple readers)
-                    }
-                    Ok(Mode::MultiNodeSingleWriter) => {
-                        // Multiple nodes attached, single writer - useful for active-passive failover.
-                        // Supported for block volumes (application/SCSI PR handles coordination).
-                        if !is_block {
-                            unsupported_reasons.push(
-                                "MULTI_NODE_SINGLE_WRITER not supported for mount volumes"
-                                    .to_string(),
-                            );
-                        }
-                    }
-                    Ok(Mode::MultiNodeMultiWriter) => {
-                        // ReadWriteMany (RWX) - supported for block volumes (application handles coordination),
-                        // but not for mount volumes (standard filesystems can't handle concurrent writers)
-                        if !is_block {
-                            unsupported_reasons.push(
-                                "MULTI_NODE_MULTI_WRITER not supported for mount volumes (requires cluster filesystem)"
-                                    .to_string(),
-                            );
-                        }
-                    }
-                    Ok(Mode::SingleNodeSingleWriter) => {
-                        // ReadWriteOncePod (RWOP) - GA in Kubernetes 1.29+
-                        // Kubernetes enforces single-pod constraint, driver just allows it
-                    }
-                    Ok(Mode::SingleNodeMultiWriter) => {
-                        // Single node, multiple writers - supported (same as RWO semantically)
-                    }
-                    Ok(Mode::Unknown) | Err(_) => {
-                        unsupported_reasons
-                            .push(format!("Unknown access mode: {}", access_mode.mode));
-                    }
-                }
-            }
-        }
+        let unsupported_reasons = unsupported_capability_reasons(&req.volume_capabilities);
 
         // If any capability is unsupported, return without confirmed
         if !unsupported_reasons.is_empty() {
@@ -860,31 +1534,161 @@ impl csi::controller_server::Controller for ControllerService {
         }))
     }
 
-    /// Publish a volume to a node (not implemented for external attacher).
+    /// Publish a volume to a node.
+    ///
+    /// Records the node as the volume's owner; a single-node volume already
+    /// published elsewhere fails with `FailedPrecondition`. The target
+    /// endpoints are returned in `publish_context` for NodeStageVolume.
     async fn controller_publish_volume(
         &self,
-        _request: Request<csi::ControllerPublishVolumeRequest>,
+        request: Request<csi::ControllerPublishVolumeRequest>,
     ) -> Result<Response<csi::ControllerPublishVolumeResponse>, Status> {
-        Err(Status::unimplemented(
-            "ControllerPublishVolume is not supported",
-        ))
-    }
-
-    /// Unpublish a volume from a node (not implemented for external attacher).
-    async fn controller_unpublish_volume(
-        &self,
-        _request: Request<csi::ControllerUnpublishVolumeRequest>,
-    ) -> Result<Response<csi::ControllerUnpublishVolumeResponse>, Status> {
-        Err(Status::unimplemented(
-            "ControllerUnpublishVolume is not supported",
-        ))
-    }
+        let timer = OperationTimer::new("controller_publish_volume");
+        let req = request.into_inner();
+        let volume_id = &req.volume_id;
+        let node_id = &req.node_id;
 
-    /// List all volumes.
-    ///
-    /// Returns volumes with optional pagination support.
-    async fn list_volumes(
-        &self,
+        if volume_id.is_empty() {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument("Volume ID is required"));
+        }
+        if node_id.is_empty() {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument("Node ID is required"));
+        }
+        let Some(capability) = req.volume_capability.as_ref() else {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument("Volume capability is required"));
+        };
+        let unsupported = unsupported_capability_reasons(std::slice::from_ref(capability));
+        if !unsupported.is_empty() {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument(unsupported.join("; ")));
+        }
+
+        info!(volume_id = %volume_id, node_id = %node_id, "ControllerPublishVolume request");
+
+        let client = self.get_client().await?;
+        if let Err(e) = ensure_volume_exists(
+            volume_id,
+            client.info().supports(AgentFeature::VolumeExists),
+            || {
+                let mut client = client.clone();
+                async move { client.volume_exists(volume_id).await }
+            },
+            || {
+                let mut client = client.clone();
+                async move { client.get_volume(volume_id).await.map(|_| ()) }
+            },
+        )
+        .await
+        {
+            timer.failure(&e.code().to_string());
+            return Err(e);
+        }
+
+        let mut attachments = self.attachments.lock().await;
+        if let Err(e) = Self::load_attachments(&mut attachments, &client).await {
+            timer.failure(&e.code().to_string());
+            return Err(e);
+        }
+        let previous = attachments.get(volume_id);
+        match attachments.publish(volume_id, node_id, is_multi_node(capability)) {
+            Ok(false) => {}
+            Ok(true) => {
+                let attachment = attachments.get(volume_id);
+                if let Err(e) = Self::save_attachment(&client, volume_id, attachment.as_ref()).await
+                {
+                    attachments.set(volume_id, previous);
+                    timer.failure(&e.code().to_string());
+                    return Err(e);
+                }
+            }
+            Err(owner) => {
+                warn!(
+                    volume_id = %volume_id,
+                    node_id = %node_id,
+                    owner = %owner,
+                    "Refusing to publish volume attached to another node"
+                );
+                timer.failure("failed_precondition");
+                return Err(Status::failed_precondition(format!(
+                    "volume {} is already published to node {}",
+                    volume_id, owner
+                )));
+            }
+        }
+        drop(attachments);
+
+        let mut publish_context = HashMap::new();
+        for key in ["endpoints", DEFAULT_PORT_CONTEXT_KEY] {
+            if let Some(value) = req.volume_context.get(key) {
+                publish_context.insert(key.to_string(), value.clone());
+            }
+        }
+
+        info!(volume_id = %volume_id, node_id = %node_id, "Volume published");
+        timer.success();
+        Ok(Response::new(csi::ControllerPublishVolumeResponse {
+            publish_context,
+        }))
+    }
+
+    /// Unpublish a volume from a node (from every node when `node_id` is
+    /// empty). Succeeds when the volume is not published.
+    async fn controller_unpublish_volume(
+        &self,
+        request: Request<csi::ControllerUnpublishVolumeRequest>,
+    ) -> Result<Response<csi::ControllerUnpublishVolumeResponse>, Status> {
+        let timer = OperationTimer::new("controller_unpublish_volume");
+        let req = request.into_inner();
+
+        if req.volume_id.is_empty() {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument("Volume ID is required"));
+        }
+
+        info!(
+            volume_id = %req.volume_id,
+            node_id = %req.node_id,
+            "ControllerUnpublishVolume request"
+        );
+        let client = match self.get_client().await {
+            Ok(client) => client,
+            Err(e) => {
+                timer.failure(&e.code().to_string());
+                return Err(e);
+            }
+        };
+        let mut attachments = self.attachments.lock().await;
+        if let Err(e) = Self::load_attachments(&mut attachments, &client).await {
+            timer.failure(&e.code().to_string());
+            return Err(e);
+        }
+        let previous = attachments.get(&req.volume_id);
+        if attachments.unpublish(&req.volume_id, &req.node_id) {
+            let attachment = attachments.get(&req.volume_id);
+            match Self::save_attachment(&client, &req.volume_id, attachment.as_ref()).await {
+                // A deleted volume has nothing left to record
+                Ok(()) => {}
+                Err(e) if e.code() == tonic::Code::NotFound => {}
+                Err(e) => {
+                    attachments.set(&req.volume_id, previous);
+                    timer.failure(&e.code().to_string());
+                    return Err(e);
+                }
+            }
+        }
+
+        timer.success();
+        Ok(Response::new(csi::ControllerUnpublishVolumeResponse {}))
+    }
+
+    /// List all volumes.
+    ///
+    /// Returns volumes with optional pagination support.
+    async fn list_volumes(
+        &self,
         request: Request<csi::ListVolumesRequest>,
     ) -> Result<Response<csi::ListVolumesResponse>, Status> {
         let req = request.into_inner();
@@ -911,7 +1715,8 @@ impl csi::controller_server::Controller for ControllerService {
             .iter()
             .map(|v| {
                 // ListVolumes doesn't have content_source info - pass None
-                let volume = Self::agent_volume_to_csi(v, &HashMap::new(), None);
+                let volume =
+                    Self::agent_volume_to_csi(v, &HashMap::new(), None, self.default_ports);
                 csi::list_volumes_response::Entry {
                     volume: Some(volume),
                     status: None, // We don't track published nodes currently
@@ -1055,6 +1860,313 @@ impl csi::controller_server::Controller for ControllerService {
 mod tests {
     use super::*;
 
+    fn snapshot_source() -> csi::VolumeContentSource {
+        csi::VolumeContentSource {
+            r#type: Some(csi::volume_content_source::Type::Snapshot(
+                csi::volume_content_source::SnapshotSource {
+                    snapshot_id: "pvc-src@snap".to_string(),
+                },
+            )),
+        }
+    }
+
+    #[test]
+    fn test_strong_isolation_forces_copy_over_linked() {
+        let mut params = HashMap::new();
+        params.insert("cloneMode".to_string(), "linked".to_string());
+        params.insert("strongIsolation".to_string(), "true".to_string());
+
+        let source = ControllerService::extract_content_source(Some(&snapshot_source()), &params)
+            .unwrap()
+            .unwrap();
+        assert_eq!(source.clone_mode, crate::agent::CloneMode::Copy as i32);
+
+        params.insert("strongIsolation".to_string(), "false".to_string());
+        let source = ControllerService::extract_content_source(Some(&snapshot_source()), &params)
+            .unwrap()
+            .unwrap();
+        assert_eq!(source.clone_mode, crate::agent::CloneMode::Linked as i32);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_export_ready_polls_until_live() {
+        let checks = std::sync::atomic::AtomicU32::new(0);
+        let result = wait_for_export_ready(
+            "pvc-1",
+            || {
+                let n = checks.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                // Fake agent: one failed check, then not ready, then live
+                async move {
+                    match n {
+                        0 => Err(Status::unavailable("agent restarting")),
+                        1 => Ok(false),
+                        _ => Ok(true),
+                    }
+                }
+            },
+            Duration::from_secs(5),
+            Duration::from_millis(1),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(checks.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_export_ready_times_out() {
+        let err = wait_for_export_ready(
+            "pvc-1",
+            || async { Ok(false) },
+            Duration::from_millis(20),
+            Duration::from_millis(5),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::DeadlineExceeded);
+        assert!(err.message().contains("pvc-1"));
+    }
+
+    #[test]
+    fn test_context_endpoints() {
+        let params = |value: &str| HashMap::from([("endpoints".to_string(), value.to_string())]);
+
+        assert_eq!(context_endpoints(&HashMap::new(), 3260).unwrap(), None);
+        assert_eq!(
+            context_endpoints(&params("10.0.0.1, 10.0.0.2:3261"), 3260).unwrap(),
+            Some("10.0.0.1:3260,10.0.0.2:3261".to_string())
+        );
+        assert_eq!(
+            context_endpoints(&params("[fd00::10],san.example.com"), 4420).unwrap(),
+            Some("[fd00::10]:4420,san.example.com:4420".to_string())
+        );
+
+        let err = context_endpoints(&params("fd00::10:4420"), 4420).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert!(err.message().contains("fd00::10:4420"));
+        assert!(context_endpoints(&params(","), 3260).is_err());
+    }
+
+    #[test]
+    fn test_capability_access_modes() {
+        use csi::volume_capability::access_mode::Mode;
+        use csi::volume_capability::{AccessType, BlockVolume, MountVolume};
+
+        let capability = |mode: Mode, block: bool| csi::VolumeCapability {
+            access_mode: Some(csi::volume_capability::AccessMode { mode: mode as i32 }),
+            access_type: Some(if block {
+                AccessType::Block(BlockVolume {})
+            } else {
+                AccessType::Mount(MountVolume::default())
+            }),
+        };
+
+        assert!(
+            unsupported_capability_reasons(&[
+                capability(Mode::SingleNodeWriter, false),
+                capability(Mode::SingleNodeReaderOnly, false),
+                capability(Mode::SingleNodeWriter, true),
+            ])
+            .is_empty()
+        );
+
+        // A filesystem on one zvol cannot take writers on several nodes
+        let reasons = unsupported_capability_reasons(&[
+            capability(Mode::SingleNodeWriter, false),
+            capability(Mode::MultiNodeMultiWriter, false),
+        ]);
+        assert_eq!(reasons.len(), 1);
+        assert!(reasons[0].contains("MULTI_NODE_MULTI_WRITER"));
+        // Raw block leaves coordination to the application
+        assert!(
+            unsupported_capability_reasons(&[capability(Mode::MultiNodeMultiWriter, true)])
+                .is_empty()
+        );
+
+        let missing_type = csi::VolumeCapability {
+            access_mode: Some(csi::volume_capability::AccessMode {
+                mode: Mode::SingleNodeWriter as i32,
+            }),
+            access_type: None,
+        };
+        assert_eq!(unsupported_capability_reasons(&[missing_type]).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_ensure_volume_exists() {
+        let get_unused = || async { panic!("GetVolume should not be called") };
+        assert!(
+            ensure_volume_exists("pvc-1", true, || async { Ok(true) }, get_unused)
+                .await
+                .is_ok()
+        );
+
+        let err = ensure_volume_exists("pvc-1", true, || async { Ok(false) }, get_unused)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+
+        // Agent errors other than Unimplemented are passed through
+        let err = ensure_volume_exists(
+            "pvc-1",
+            true,
+            || async { Err(Status::unavailable("agent restarting")) },
+            get_unused,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn test_agent_volume_exists() {
+        assert_eq!(
+            agent_volume_exists("pvc-1", true, || async { Ok(true) }).await,
+            Some(true)
+        );
+        assert_eq!(
+            agent_volume_exists("pvc-1", true, || async { Ok(false) }).await,
+            Some(false)
+        );
+
+        // Unknown for legacy agents and failed checks, so callers fall back
+        let exists_unused = || async { panic!("VolumeExists should not be called") };
+        assert_eq!(
+            agent_volume_exists("pvc-1", false, exists_unused).await,
+            None
+        );
+        assert_eq!(
+            agent_volume_exists("pvc-1", true, || async {
+                Err(Status::unimplemented("unknown method"))
+            })
+            .await,
+            None
+        );
+        assert_eq!(
+            agent_volume_exists("pvc-1", true, || async {
+                Err(Status::unavailable("agent restarting"))
+            })
+            .await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ensure_volume_exists_falls_back_for_old_agent() {
+        let old_agent = || async { Err(Status::unimplemented("unknown method")) };
+        assert!(
+            ensure_volume_exists("pvc-1", true, old_agent, || async { Ok(()) })
+                .await
+                .is_ok()
+        );
+        let err = ensure_volume_exists("pvc-1", true, old_agent, || async {
+            Err(Status::not_found("Volume not found"))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+
+        // An agent whose API version lacks VolumeExists is not asked
+        let exists_unused = || async { panic!("VolumeExists should not be called") };
+        assert!(
+            ensure_volume_exists("pvc-1", false, exists_unused, || async { Ok(()) })
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_export_ready_skips_old_agent() {
+        let result = wait_for_export_ready(
+            "pvc-1",
+            || async { Err(Status::unimplemented("unknown method")) },
+            Duration::from_millis(20),
+            Duration::from_millis(5),
+        )
+        .await;
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_strong_isolation_rejects_invalid_value() {
+        let mut params = HashMap::new();
+        params.insert("strongIsolation".to_string(), "maybe".to_string());
+
+        let err = ControllerService::extract_content_source(Some(&snapshot_source()), &params)
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_check_parameters_strict_rejects_unknown() {
+        let mut params = HashMap::new();
+        params.insert("exportType".to_string(), "iscsi".to_string());
+        params.insert("compresion".to_string(), "lz4".to_string());
+
+        let err = ControllerService::check_parameters(&params, true).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert!(err.message().contains("compresion"));
+    }
+
+    #[test]
+    fn test_check_parameters_lenient_ignores_unknown() {
+        let mut params = HashMap::new();
+        params.insert("compresion".to_string(), "lz4".to_string());
+
+        assert!(ControllerService::check_parameters(&params, false).is_ok());
+
+        // Known keys pass in strict mode too
+        params.remove("compresion");
+        params.insert("provisioningMode".to_string(), "thick".to_string());
+        assert!(ControllerService::check_parameters(&params, true).is_ok());
+    }
+
+    #[test]
+    fn test_agent_attachment_lists_initiators_of_all_nodes() {
+        let attachment = |nodes: &[&str]| Attachment {
+            nodes: nodes.iter().map(|n| n.to_string()).collect(),
+            multi_node: true,
+        };
+
+        let saved = agent_attachment(
+            "pvc-1",
+            Some(&attachment(&[
+                "worker-1;iqn=iqn.x:w1;nqn=nqn.x:w1",
+                "worker-2;iqn=iqn.x:w2",
+            ])),
+        );
+        assert_eq!(saved.node_ids.len(), 2);
+        assert!(saved.multi_node);
+        assert_eq!(saved.initiator_names, ["iqn.x:w1", "iqn.x:w2"]);
+        // worker-2 has no host NQN, so NVMeoF access stays open
+        assert!(saved.host_nqns.is_empty());
+
+        let saved = agent_attachment("pvc-1", Some(&attachment(&["worker-1"])));
+        assert!(saved.initiator_names.is_empty());
+
+        let cleared = agent_attachment("pvc-1", None);
+        assert!(cleared.node_ids.is_empty() && cleared.initiator_names.is_empty());
+    }
+
+    #[test]
+    fn test_agent_snapshot_ready_to_use_passthrough() {
+        let mut snapshot = crate::agent::Snapshot {
+            id: "pvc-1@snap".to_string(),
+            source_volume_id: "pvc-1".to_string(),
+            name: "snap".to_string(),
+            creation_time: 1700000000,
+            size_bytes: 0,
+            ready_to_use: true,
+            written_bytes: 0,
+            used_bytes: 0,
+            referenced_bytes: 0,
+            system: false,
+        };
+        assert!(ControllerService::agent_snapshot_to_csi(&snapshot).ready_to_use);
+
+        snapshot.ready_to_use = false;
+        assert!(!ControllerService::agent_snapshot_to_csi(&snapshot).ready_to_use);
+    }
+
     #[test]
     fn test_parse_export_type() {
         let mut params = HashMap::new();
@@ -1088,6 +2200,88 @@ mod tests {
         );
     }
 
+    fn sourced_volume(source: crate::agent::volume_content_source::Source) -> crate::agent::Volume {
+        crate::agent::Volume {
+            id: "pvc-new".to_string(),
+            name: "pvc-new".to_string(),
+            size_bytes: 1024,
+            zfs_dataset: "tank/csi/pvc-new".to_string(),
+            export_type: crate::agent::ExportType::Iscsi as i32,
+            target_name: "iqn.2024-01.org.freebsd.csi:pvc-new".to_string(),
+            lun_id: 0,
+            parameters: HashMap::new(),
+            content_source: Some(VolumeContentSource {
+                source: Some(source),
+                clone_mode: crate::agent::CloneMode::Linked as i32,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_agent_volume_to_csi_reports_snapshot_source() {
+        use crate::agent::volume_content_source::Source;
+
+        let volume = sourced_volume(Source::SnapshotId("pvc-src@snap".to_string()));
+        let csi_volume = ControllerService::agent_volume_to_csi(
+            &volume,
+            &HashMap::new(),
+            None,
+            DefaultPorts::default(),
+        );
+
+        assert_eq!(csi_volume.content_source, Some(snapshot_source()));
+    }
+
+    #[test]
+    fn test_agent_volume_to_csi_reports_volume_source() {
+        use crate::agent::volume_content_source::Source;
+        use csi::volume_content_source::{Type, VolumeSource};
+
+        let volume = sourced_volume(Source::SourceVolumeId("pvc-src".to_string()));
+        let csi_volume = ControllerService::agent_volume_to_csi(
+            &volume,
+            &HashMap::new(),
+            None,
+            DefaultPorts::default(),
+        );
+
+        assert_eq!(
+            csi_volume.content_source,
+            Some(csi::VolumeContentSource {
+                r#type: Some(Type::Volume(VolumeSource {
+                    volume_id: "pvc-src".to_string(),
+                })),
+            })
+        );
+    }
+
+    #[test]
+    fn test_agent_volume_to_csi_falls_back_to_requested_source() {
+        use crate::agent::volume_content_source::Source;
+
+        // Older agents don't report a source; the request's is echoed
+        let mut volume = sourced_volume(Source::SnapshotId("pvc-src@snap".to_string()));
+        volume.content_source = None;
+        let csi_volume = ControllerService::agent_volume_to_csi(
+            &volume,
+            &HashMap::new(),
+            Some(snapshot_source()),
+            DefaultPorts::default(),
+        );
+        assert_eq!(csi_volume.content_source, Some(snapshot_source()));
+
+        // Image URL sources have no CSI form
+        let volume = sourced_volume(Source::ImageUrl("https://images/base.zfs".to_string()));
+        let csi_volume = ControllerService::agent_volume_to_csi(
+            &volume,
+            &HashMap::new(),
+            None,
+            DefaultPorts::default(),
+        );
+        assert_eq!(csi_volume.content_source, None);
+    }
+
     #[test]
     fn test_agent_volume_to_csi_preserves_nvmeof_connect_options() {
         let volume = crate::agent::Volume {
@@ -1099,6 +2293,8 @@ mod tests {
             target_name: "nqn.2024-01.org.freebsd.csi:vol-1".to_string(),
             lun_id: 0,
             parameters: HashMap::new(),
+            content_source: None,
+            ..Default::default()
         };
         let mut params = HashMap::new();
         params.insert("endpoints".to_string(), "10.0.0.10:4420".to_string());
@@ -1108,9 +2304,15 @@ mod tests {
         params.insert("nvmeof.keepAliveTmo".to_string(), "5".to_string());
         params.insert("nvmeof.reconnectDelay".to_string(), "2".to_string());
         params.insert("nvmeof.ctrlLossTmo".to_string(), "60".to_string());
+        params.insert("nvmeof.multipath".to_string(), "native".to_string());
 
-        let csi_volume = ControllerService::agent_volume_to_csi(&volume, &params, None);
+        let csi_volume =
+            ControllerService::agent_volume_to_csi(&volume, &params, None, DefaultPorts::default());
 
+        assert_eq!(
+            csi_volume.volume_context.get("nvmeof.multipath"),
+            Some(&"native".to_string())
+        );
         assert_eq!(
             csi_volume.volume_context.get("nvmeof.nrIoQueues"),
             Some(&"2".to_string())
@@ -1148,15 +2350,326 @@ mod tests {
             target_name: "iqn.2024-01.org.freebsd.csi:vol-1".to_string(),
             lun_id: 0,
             parameters: HashMap::new(),
+            content_source: None,
+            ..Default::default()
         };
         let mut params = HashMap::new();
         params.insert("nvmeof.nrIoQueues".to_string(), "2".to_string());
 
-        let csi_volume = ControllerService::agent_volume_to_csi(&volume, &params, None);
+        let csi_volume =
+            ControllerService::agent_volume_to_csi(&volume, &params, None, DefaultPorts::default());
 
         assert!(!csi_volume.volume_context.contains_key("nvmeof.nrIoQueues"));
     }
 
+    #[test]
+    fn test_agent_volume_to_csi_sets_nvme_discovery_controller() {
+        let volume = crate::agent::Volume {
+            id: "vol-1".to_string(),
+            name: "test".to_string(),
+            size_bytes: 1024,
+            zfs_dataset: "tank/vol-1".to_string(),
+            export_type: crate::agent::ExportType::Nvmeof as i32,
+            target_name: "nqn.2024-01.org.freebsd.csi:vol-1".to_string(),
+            lun_id: 1,
+            parameters: HashMap::new(),
+            content_source: None,
+            ..Default::default()
+        };
+        let mut params = HashMap::new();
+        params.insert("endpoints".to_string(), "10.0.0.1:4420".to_string());
+
+        let csi_volume =
+            ControllerService::agent_volume_to_csi(&volume, &params, None, DefaultPorts::default());
+        assert!(!csi_volume.volume_context.contains_key("discoveryNqn"));
+
+        params.insert("nvmeof.discovery".to_string(), "discovery".to_string());
+        let csi_volume =
+            ControllerService::agent_volume_to_csi(&volume, &params, None, DefaultPorts::default());
+        assert_eq!(
+            csi_volume
+                .volume_context
+                .get("discoveryNqn")
+                .map(String::as_str),
+            Some("nqn.2014-08.org.nvmexpress.discovery")
+        );
+        assert_eq!(
+            csi_volume
+                .volume_context
+                .get("discoveryEndpoints")
+                .map(String::as_str),
+            Some("10.0.0.1:8009")
+        );
+        // The I/O endpoints stay available as the fallback
+        assert_eq!(
+            csi_volume
+                .volume_context
+                .get("endpoints")
+                .map(String::as_str),
+            Some("10.0.0.1:4420")
+        );
+    }
+
+    #[test]
+    fn test_agent_volume_to_csi_preserves_iscsi_discovery_options() {
+        let volume = crate::agent::Volume {
+            id: "vol-1".to_string(),
+            name: "test".to_string(),
+            size_bytes: 1024,
+            zfs_dataset: "tank/vol-1".to_string(),
+            export_type: crate::agent::ExportType::Iscsi as i32,
+            target_name: "iqn.2024-01.org.freebsd.csi:vol-1".to_string(),
+            lun_id: 0,
+            parameters: HashMap::new(),
+            content_source: None,
+            ..Default::default()
+        };
+        let mut params = HashMap::new();
+        params.insert("discovery".to_string(), "sendtargets".to_string());
+        params.insert("discoveryRetries".to_string(), "5".to_string());
+
+        let csi_volume =
+            ControllerService::agent_volume_to_csi(&volume, &params, None, DefaultPorts::default());
+
+        assert_eq!(
+            csi_volume.volume_context.get("discovery"),
+            Some(&"sendtargets".to_string())
+        );
+        assert_eq!(
+            csi_volume.volume_context.get("discoveryRetries"),
+            Some(&"5".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check_existing_volume() {
+        let existing = crate::agent::Volume {
+            id: "pvc-1".to_string(),
+            name: "pvc-1".to_string(),
+            size_bytes: 1 << 30,
+            export_type: crate::agent::ExportType::Iscsi as i32,
+            parameters: HashMap::from([
+                ("fsType".to_string(), "xfs".to_string()),
+                (ProvisioningMode::PARAM_NAME.to_string(), "thin".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let range = |required_bytes, limit_bytes| csi::CapacityRange {
+            required_bytes,
+            limit_bytes,
+        };
+        let params = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let check = |export_type, range: csi::CapacityRange, parameters| {
+            check_existing_volume(&existing, export_type, Some(&range), &parameters)
+        };
+
+        assert!(
+            check(
+                ExportType::Iscsi,
+                range(1 << 30, 0),
+                params(&[("fsType", "xfs")])
+            )
+            .is_ok()
+        );
+        // A smaller request is satisfied by the existing volume
+        assert!(check(ExportType::Iscsi, range(1 << 20, 2 << 30), HashMap::new()).is_ok());
+        // The reported mode is not compared against the requested one
+        assert!(
+            check(
+                ExportType::Iscsi,
+                range(1 << 30, 0),
+                params(&[(ProvisioningMode::PARAM_NAME, "thick")])
+            )
+            .is_ok()
+        );
+
+        for (export_type, range, parameters) in [
+            (ExportType::Iscsi, range(2 << 30, 0), HashMap::new()),
+            (ExportType::Iscsi, range(0, 1 << 20), HashMap::new()),
+            (ExportType::Nvmeof, range(1 << 30, 0), HashMap::new()),
+            (
+                ExportType::Iscsi,
+                range(1 << 30, 0),
+                params(&[("fsType", "ext4")]),
+            ),
+            (
+                ExportType::Iscsi,
+                range(1 << 30, 0),
+                params(&[("compression", "lz4")]),
+            ),
+        ] {
+            assert_eq!(
+                check(export_type, range, parameters).unwrap_err().code(),
+                tonic::Code::AlreadyExists
+            );
+        }
+    }
+
+    /// Agent keeping volumes in memory that, like the real one without its
+    /// recovery path, fails to create a volume that already exists
+    #[derive(Default)]
+    struct MemoryAgent {
+        volumes: std::sync::Mutex<HashMap<String, crate::agent::Volume>>,
+    }
+
+    #[tonic::async_trait]
+    impl crate::agent::storage_agent_server::StorageAgent for MemoryAgent {
+        async fn create_volume(
+            &self,
+            request: Request<crate::agent::CreateVolumeRequest>,
+        ) -> Result<Response<crate::agent::CreateVolumeResponse>, Status> {
+            let req = request.into_inner();
+            let mut volumes = self.volumes.lock().unwrap();
+            if volumes.contains_key(&req.name) {
+                return Err(Status::internal("dataset already exists"));
+            }
+            let volume = crate::agent::Volume {
+                id: req.name.clone(),
+                name: req.name.clone(),
+                size_bytes: req.size_bytes,
+                export_type: req.export_type,
+                parameters: req.parameters,
+                ..Default::default()
+            };
+            volumes.insert(req.name, volume.clone());
+            Ok(Response::new(crate::agent::CreateVolumeResponse {
+                volume: Some(volume),
+            }))
+        }
+
+        async fn get_volume(
+            &self,
+            request: Request<crate::agent::GetVolumeRequest>,
+        ) -> Result<Response<crate::agent::GetVolumeResponse>, Status> {
+            let volume_id = request.into_inner().volume_id;
+            let volume = self.volumes.lock().unwrap().get(&volume_id).cloned();
+            match volume {
+                Some(volume) => Ok(Response::new(crate::agent::GetVolumeResponse {
+                    volume: Some(volume),
+                })),
+                None => Err(Status::not_found("volume not found")),
+            }
+        }
+
+        async fn is_volume_export_ready(
+            &self,
+            request: Request<crate::agent::IsVolumeExportReadyRequest>,
+        ) -> Result<Response<crate::agent::IsVolumeExportReadyResponse>, Status> {
+            let volume_id = request.into_inner().volume_id;
+            let ready = self.volumes.lock().unwrap().contains_key(&volume_id);
+            Ok(Response::new(crate::agent::IsVolumeExportReadyResponse {
+                ready,
+            }))
+        }
+
+        async fn get_system_info(
+            &self,
+            _request: Request<crate::agent::GetSystemInfoRequest>,
+        ) -> Result<Response<crate::agent::GetSystemInfoResponse>, Status> {
+            Ok(Response::new(crate::agent::GetSystemInfoResponse {
+                agent_version: "test".to_string(),
+                api_version: crate::agent_info::AGENT_API_VERSION,
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_volume_twice_returns_existing_volume() {
+        use crate::agent::storage_agent_server::StorageAgentServer;
+        use csi::controller_server::Controller;
+        use tonic::transport::Server;
+        use tonic::transport::server::TcpIncoming;
+
+        let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = incoming.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(StorageAgentServer::new(MemoryAgent::default()))
+                .serve_with_incoming(incoming),
+        );
+        let controller = ControllerService::new(format!("http://{}", addr));
+
+        let request = |required_bytes| csi::CreateVolumeRequest {
+            name: "pvc-1".to_string(),
+            capacity_range: Some(csi::CapacityRange {
+                required_bytes,
+                limit_bytes: 0,
+            }),
+            parameters: HashMap::from([("fsType".to_string(), "xfs".to_string())]),
+            ..Default::default()
+        };
+
+        let first = controller
+            .create_volume(Request::new(request(1 << 30)))
+            .await
+            .unwrap()
+            .into_inner()
+            .volume
+            .unwrap();
+        let second = controller
+            .create_volume(Request::new(request(1 << 30)))
+            .await
+            .unwrap()
+            .into_inner()
+            .volume
+            .unwrap();
+        assert_eq!(second.volume_id, first.volume_id);
+        assert_eq!(second.capacity_bytes, 1 << 30);
+        assert_eq!(second.volume_context, first.volume_context);
+
+        let err = controller
+            .create_volume(Request::new(request(2 << 30)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::AlreadyExists);
+    }
+
+    #[test]
+    fn test_agent_volume_to_csi_uses_configured_default_port() {
+        let volume = crate::agent::Volume {
+            id: "vol-1".to_string(),
+            export_type: crate::agent::ExportType::Nvmeof as i32,
+            ..Default::default()
+        };
+        let ports = DefaultPorts {
+            iscsi: 13260,
+            nvmeof: 14420,
+        };
+        let mut params = HashMap::new();
+        params.insert(
+            "endpoints".to_string(),
+            "10.0.0.1,10.0.0.2:4420".to_string(),
+        );
+
+        let csi_volume = ControllerService::agent_volume_to_csi(&volume, &params, None, ports);
+        assert_eq!(
+            csi_volume.volume_context.get("endpoints"),
+            Some(&"10.0.0.1:14420,10.0.0.2:4420".to_string())
+        );
+        assert_eq!(
+            csi_volume.volume_context.get(DEFAULT_PORT_CONTEXT_KEY),
+            Some(&"14420".to_string())
+        );
+
+        // The standard ports need no extra context key
+        let csi_volume =
+            ControllerService::agent_volume_to_csi(&volume, &params, None, DefaultPorts::default());
+        assert_eq!(
+            csi_volume.volume_context.get("endpoints"),
+            Some(&"10.0.0.1:4420,10.0.0.2:4420".to_string())
+        );
+        assert!(
+            !csi_volume
+                .volume_context
+                .contains_key(DEFAULT_PORT_CONTEXT_KEY)
+        );
+    }
+
     #[test]
     fn test_get_volume_size() {
         // No capacity range
@@ -0,0 +1,124 @@
+//! TLS for a TCP CSI endpoint.
+//!
+//! Kubelet and the sidecars normally reach the driver over a Unix socket,
+//! which stays plaintext: it is local and protected by file permissions.
+//! When the CSI gRPC server listens on TCP (e.g. for remote sidecars) it can
+//! serve TLS instead, reusing `--tls-cert`/`--tls-key` as its identity and
+//! `--tls-ca` to require client certificates, mirroring the agent's setup.
+
+use std::path::{Path, PathBuf};
+
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+/// How the CSI endpoint's listener is secured
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndpointSecurity {
+    /// No TLS (Unix sockets, or TCP without `--endpoint-tls`)
+    Plaintext,
+    /// TLS with the given identity; clients must present a certificate
+    /// signed by `client_ca` when it is set
+    Tls {
+        cert: PathBuf,
+        key: PathBuf,
+        client_ca: Option<PathBuf>,
+    },
+}
+
+/// Decide how to secure `endpoint`.
+///
+/// TLS is only applied to TCP endpoints and only when `enabled`; it then
+/// needs both a certificate and a key.
+pub fn endpoint_security(
+    endpoint: &str,
+    enabled: bool,
+    cert: Option<&Path>,
+    key: Option<&Path>,
+    client_ca: Option<&Path>,
+) -> Result<EndpointSecurity, String> {
+    if !enabled || endpoint.starts_with("unix://") {
+        return Ok(EndpointSecurity::Plaintext);
+    }
+    match (cert, key) {
+        (Some(cert), Some(key)) => Ok(EndpointSecurity::Tls {
+            cert: cert.to_path_buf(),
+            key: key.to_path_buf(),
+            client_ca: client_ca.map(Path::to_path_buf),
+        }),
+        _ => Err(
+            "endpoint TLS configuration incomplete: --endpoint-tls requires both --tls-cert and --tls-key"
+                .to_string(),
+        ),
+    }
+}
+
+/// Load the server TLS configuration for a TLS endpoint.
+pub async fn server_tls_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+) -> std::io::Result<ServerTlsConfig> {
+    let cert = tokio::fs::read(cert).await?;
+    let key = tokio::fs::read(key).await?;
+    let mut tls = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+    if let Some(ca) = client_ca {
+        tls = tls.client_ca_root(Certificate::from_pem(tokio::fs::read(ca).await?));
+    }
+    Ok(tls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tcp_endpoint_uses_tls_when_enabled() {
+        let cert = Path::new("/etc/csi/server.crt");
+        let key = Path::new("/etc/csi/server.key");
+        let ca = Path::new("/etc/csi/ca.crt");
+
+        assert_eq!(
+            endpoint_security("0.0.0.0:10000", true, Some(cert), Some(key), Some(ca)).unwrap(),
+            EndpointSecurity::Tls {
+                cert: cert.to_path_buf(),
+                key: key.to_path_buf(),
+                client_ca: Some(ca.to_path_buf()),
+            }
+        );
+        assert_eq!(
+            endpoint_security("0.0.0.0:10000", true, Some(cert), Some(key), None).unwrap(),
+            EndpointSecurity::Tls {
+                cert: cert.to_path_buf(),
+                key: key.to_path_buf(),
+                client_ca: None,
+            }
+        );
+
+        // Not requested: the certificates stay reserved for the agent client
+        assert_eq!(
+            endpoint_security("0.0.0.0:10000", false, Some(cert), Some(key), Some(ca)).unwrap(),
+            EndpointSecurity::Plaintext
+        );
+        // Unix sockets are always plaintext
+        assert_eq!(
+            endpoint_security("unix:///csi/csi.sock", true, Some(cert), Some(key), None).unwrap(),
+            EndpointSecurity::Plaintext
+        );
+    }
+
+    #[test]
+    fn test_incomplete_endpoint_tls_rejected() {
+        let cert = Path::new("/etc/csi/server.crt");
+        assert!(endpoint_security("0.0.0.0:10000", true, Some(cert), None, None).is_err());
+        assert!(endpoint_security("0.0.0.0:10000", true, None, None, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_server_tls_config_missing_files() {
+        let dir = std::env::temp_dir().join(format!("csi-endpoint-tls-{}", std::process::id()));
+        assert!(
+            server_tls_config(&dir.join("server.crt"), &dir.join("server.key"), None)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! Node-side records of volumes staged from a shared NVMeoF controller
+//!
+//! Volumes in a `controllerGroup` are namespaces of one controller whose NQN
+//! cannot be derived from the volume ID, and the controller exposes every
+//! member's namespace. Staging such a volume records the controller NQN and
+//! the volume's namespace ID in a small file per volume, so publish,
+//! NodeGetVolumeStats and NodeUnstageVolume (which get no volume context)
+//! select the right namespace, also after a driver restart. Unstage
+//! disconnects the shared controller only once no other member is staged.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, MutexGuard};
+use tracing::warn;
+
+/// Default directory for the records, inside the plugin's kubelet directory
+pub const DEFAULT_STATE_DIR: &str = "/var/lib/kubelet/plugins/csi.freebsd.org/state";
+
+/// A staged volume's namespace on a shared controller
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupedNamespace {
+    /// NQN of the shared controller
+    pub target_name: String,
+    /// The volume's namespace ID on that controller
+    pub namespace_id: u32,
+}
+
+/// Records of staged grouped volumes, one JSON file per volume ID
+#[derive(Debug, Clone)]
+pub struct GroupedNamespaces {
+    dir: PathBuf,
+    /// Serializes membership changes against the disconnect decision
+    lock: Arc<Mutex<()>>,
+}
+
+impl Default for GroupedNamespaces {
+    fn default() -> Self {
+        Self::new(DEFAULT_STATE_DIR)
+    }
+}
+
+impl GroupedNamespaces {
+    /// Keep the records in `dir` (created on first use)
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Hold off other stages and unstages of grouped volumes.
+    ///
+    /// Take it around recording a member before it connects and around the
+    /// "last member?" check plus disconnect, so a volume never connects
+    /// through a controller that is being torn down.
+    pub async fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().await
+    }
+
+    fn path(&self, volume_id: &str) -> io::Result<PathBuf> {
+        if volume_id.is_empty()
+            || volume_id.contains('/')
+            || volume_id.contains("..")
+            || volume_id.contains('\0')
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("volume ID '{}' cannot be used as a file name", volume_id),
+            ));
+        }
+        Ok(self.dir.join(format!("{}.json", volume_id)))
+    }
+
+    /// Record (or replace) the namespace a volume is staged from
+    pub async fn record(&self, volume_id: &str, namespace: &GroupedNamespace) -> io::Result<()> {
+        let path = self.path(volume_id)?;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let json = serde_json::to_vec(namespace).map_err(io::Error::other)?;
+        let temp = path.with_extension("json.tmp");
+        tokio::fs::write(&temp, json).await?;
+        tokio::fs::rename(&temp, &path).await
+    }
+
+    /// The recorded namespace of a volume, if it is a staged grouped volume
+    pub async fn get(&self, volume_id: &str) -> Option<GroupedNamespace> {
+        let path = self.path(volume_id).ok()?;
+        read_record(&path).await
+    }
+
+    /// Forget a volume; a missing record is not an error
+    pub async fn remove(&self, volume_id: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(volume_id)?).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Volumes recorded as staged from `target_name`
+    pub async fn members(&self, target_name: &str) -> Vec<String> {
+        self.all()
+            .await
+            .into_iter()
+            .filter(|(_, ns)| ns.target_name == target_name)
+            .map(|(volume_id, _)| volume_id)
+            .collect()
+    }
+
+    /// Every recorded volume with its namespace, sorted by volume ID
+    pub async fn all(&self) -> Vec<(String, GroupedNamespace)> {
+        let mut records = Vec::new();
+        let Ok(mut entries) = tokio::fs::read_dir(&self.dir).await else {
+            return records;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let Some(volume_id) = path
+                .extension()
+                .filter(|ext| *ext == "json")
+                .and(path.file_stem())
+                .and_then(|stem| stem.to_str())
+            else {
+                continue;
+            };
+            if let Some(namespace) = read_record(&path).await {
+                records.push((volume_id.to_string(), namespace));
+            }
+        }
+        records.sort_by(|a, b| a.0.cmp(&b.0));
+        records
+    }
+}
+
+async fn read_record(path: &Path) -> Option<GroupedNamespace> {
+    let data = tokio::fs::read(path).await.ok()?;
+    match serde_json::from_slice(&data) {
+        Ok(namespace) => Some(namespace),
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "Ignoring unreadable namespace record");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_records_track_members_per_controller() {
+        let dir = std::env::temp_dir().join(format!("grouped-ns-{}", uuid::Uuid::new_v4()));
+        let records = GroupedNamespaces::new(&dir);
+        let ns = |target: &str, id| GroupedNamespace {
+            target_name: target.to_string(),
+            namespace_id: id,
+        };
+
+        assert_eq!(records.get("pvc-a").await, None);
+        assert!(records.members("nqn.test:group:db").await.is_empty());
+
+        records
+            .record("pvc-a", &ns("nqn.test:group:db", 1))
+            .await
+            .unwrap();
+        records
+            .record("pvc-b", &ns("nqn.test:group:db", 2))
+            .await
+            .unwrap();
+        records
+            .record("pvc-c", &ns("nqn.test:group:web", 1))
+            .await
+            .unwrap();
+
+        assert_eq!(records.get("pvc-b").await, Some(ns("nqn.test:group:db", 2)));
+        assert_eq!(
+            records.members("nqn.test:group:db").await,
+            ["pvc-a", "pvc-b"]
+        );
+
+        records.remove("pvc-a").await.unwrap();
+        records.remove("pvc-a").await.unwrap();
+        assert_eq!(records.members("nqn.test:group:db").await, ["pvc-b"]);
+        assert_eq!(
+            records.all().await,
+            [
+                ("pvc-b".to_string(), ns("nqn.test:group:db", 2)),
+                ("pvc-c".to_string(), ns("nqn.test:group:web", 1))
+            ]
+        );
+        assert!(
+            records
+                .record("../x", &ns("nqn.test:group:db", 3))
+                .await
+                .is_err()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -5,8 +5,15 @@
 //!
 //! This library provides:
 //! - CSI Identity, Controller, and Node service implementations
-//! - Agent client for communication with ctld-agent
+//! - Agent client for communication with ctld-agent, gated on the agent's
+//!   API version
+//! - Controller-side attach tracking against dual attachment
 //! - Platform-specific mount/unmount operations
+//! - Reconnection of failed multipath paths on staged volumes
+//! - Namespace selection for volumes sharing an NVMeoF controller
+//! - Concurrency limiting of node stage/publish/expand operations
+//! - Optional TLS on a TCP CSI endpoint
+//! - Volume usage and per-volume I/O statistics
 
 /// CSI proto generated types
 pub mod csi {
@@ -19,12 +26,20 @@ pub mod agent {
 }
 
 pub mod agent_client;
+pub mod agent_info;
+pub mod attachments;
 pub mod controller;
+pub mod endpoint_tls;
+pub mod grouped_namespaces;
 pub mod identity;
 pub mod metrics;
 pub mod node;
+pub mod node_limit;
+pub mod path_maintenance;
 pub mod platform;
+pub mod socket;
 pub mod types;
+pub mod volume_stats;
 
 pub use agent_client::AgentClient;
 pub use controller::ControllerService;
@@ -5,24 +5,38 @@
 
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
+use csi_common::logging::{LogFormat, init_tracing};
 use tokio::signal;
-use tracing::{Level, debug, info};
-use tracing_subscriber::FmtSubscriber;
+use tracing::{Level, debug, info, warn};
 
-use csi_driver::agent_client::TlsConfig;
+use csi_driver::agent_client::{
+    DEFAULT_RETRY_ATTEMPTS, DEFAULT_RETRY_BASE_DELAY, RetryPolicy, TlsConfig,
+};
 use csi_driver::controller::ControllerService;
 use csi_driver::csi;
+use csi_driver::endpoint_tls::{self, EndpointSecurity};
+use csi_driver::grouped_namespaces::{DEFAULT_STATE_DIR, GroupedNamespaces};
 use csi_driver::identity::{IdentityService, ReadinessState};
 use csi_driver::metrics;
-use csi_driver::node::NodeService;
+use csi_driver::node::{MissingTargetNamePolicy, NodeService, StageRetry, StaleMountPolicy};
+use csi_driver::node_limit::{DEFAULT_NODE_MAX_CONCURRENT_OPS, SaturationPolicy};
+use csi_driver::path_maintenance::{self, StagedTargets};
+use csi_driver::platform;
+use csi_driver::socket;
+use csi_driver::types::{DefaultPorts, MAX_NODE_ID_LEN, NodeInitiators};
 
 /// CLI arguments for the CSI driver
 #[derive(Parser, Debug)]
 #[command(name = "csi-driver")]
 #[command(about = "FreeBSD CSI Driver for Kubernetes")]
 struct Args {
+    /// Print the recognized StorageClass parameters as JSON and exit
+    #[arg(long)]
+    list_parameters: bool,
+
     /// CSI endpoint (unix socket path)
     #[arg(long, default_value = "unix:///var/run/csi/csi.sock")]
     endpoint: String,
@@ -51,6 +65,10 @@ struct Args {
     #[arg(long, default_value = "info")]
     log_level: String,
 
+    /// Log line format: "text" or "json"
+    #[arg(long, env = "LOG_FORMAT", default_value = "text")]
+    log_format: LogFormat,
+
     /// TLS certificate file (PEM format)
     #[arg(long, env = "TLS_CERT_PATH")]
     tls_cert: Option<PathBuf>,
@@ -63,6 +81,12 @@ struct Args {
     #[arg(long, env = "TLS_CA_PATH")]
     tls_ca: Option<PathBuf>,
 
+    /// Serve TLS on a TCP --endpoint, using --tls-cert/--tls-key as the
+    /// server identity and requiring client certificates signed by --tls-ca
+    /// when set. Unix socket endpoints stay plaintext
+    #[arg(long, env = "ENDPOINT_TLS", default_value = "false")]
+    endpoint_tls: bool,
+
     /// TLS domain name (for server certificate verification)
     #[arg(long, env = "TLS_DOMAIN", default_value = "ctld-agent")]
     tls_domain: String,
@@ -71,12 +95,120 @@ struct Args {
     /// If not set, metrics endpoint is disabled
     #[arg(long, env = "METRICS_ADDR")]
     metrics_addr: Option<String>,
+
+    /// Reject CreateVolume requests with unrecognized StorageClass parameters
+    /// instead of ignoring them
+    #[arg(long, env = "STRICT_PARAMETERS", default_value = "false")]
+    strict_parameters: bool,
+
+    /// Make CreateVolume return only once the volume's target is live on the
+    /// agent, failing with DeadlineExceeded after --export-ready-timeout
+    #[arg(long, env = "WAIT_FOR_EXPORT_READY", default_value = "false")]
+    wait_for_export_ready: bool,
+
+    /// Seconds CreateVolume waits for the export to go live
+    #[arg(long, env = "EXPORT_READY_TIMEOUT", default_value = "30", value_parser = clap::value_parser!(u64).range(1..))]
+    export_ready_timeout: u64,
+
+    /// Attempts per agent call (first one included) while the agent is
+    /// unavailable or times out
+    #[arg(long, env = "AGENT_RETRY_ATTEMPTS", default_value_t = DEFAULT_RETRY_ATTEMPTS, value_parser = clap::value_parser!(u32).range(1..))]
+    agent_retry_attempts: u32,
+
+    /// Milliseconds before the first agent call retry, doubling per retry
+    #[arg(long, env = "AGENT_RETRY_BASE_DELAY_MS", default_value_t = DEFAULT_RETRY_BASE_DELAY.as_millis() as u64)]
+    agent_retry_base_delay_ms: u64,
+
+    /// Port assumed for iSCSI endpoints listed without one
+    #[arg(long, env = "DEFAULT_ISCSI_PORT", default_value = "3260", value_parser = clap::value_parser!(u16).range(1..))]
+    default_iscsi_port: u16,
+
+    /// Port assumed for NVMeoF endpoints listed without one
+    #[arg(long, env = "DEFAULT_NVME_PORT", default_value = "4420", value_parser = clap::value_parser!(u16).range(1..))]
+    default_nvme_port: u16,
+
+    /// Remount a lost staging mount during NodePublishVolume when the target
+    /// session is still active (e.g. after a node reboot), instead of failing
+    #[arg(long, env = "AUTO_RESTAGE", default_value = "false")]
+    auto_restage: bool,
+
+    /// NodeStageVolume handling of a staging path already mounted from a
+    /// device that is not the volume's: "remount" it or "fail"
+    #[arg(long, env = "STALE_STAGING_MOUNT", default_value = "remount")]
+    stale_staging_mount: StaleMountPolicy,
+
+    /// NodePublishVolume of a block volume replaces an empty directory at
+    /// the target path with the device symlink instead of failing
+    #[arg(long, env = "REMOVE_EMPTY_BLOCK_TARGET_DIR", default_value = "false")]
+    remove_empty_block_target_dir: bool,
+
+    /// NodeStageVolume handling of a volume context without targetName:
+    /// "derive" it from exportType and the volume ID, or "fail"
+    #[arg(long, env = "MISSING_TARGET_NAME", default_value = "derive")]
+    missing_target_name: MissingTargetNamePolicy,
+
+    /// Export per-volume read/write operation, byte and time counters, read
+    /// from the kernel's block device stats when kubelet polls
+    /// NodeGetVolumeStats
+    #[arg(long, env = "VOLUME_IO_STATS", default_value = "false")]
+    volume_io_stats: bool,
+
+    /// Append this node's iSCSI initiator name and NVMe host NQN to the
+    /// node ID reported by NodeGetInfo, generating them if missing
+    #[arg(long, env = "REPORT_INITIATOR_NAMES", default_value = "false")]
+    report_initiator_names: bool,
+
+    /// Periodically log in / connect again to failed paths of staged
+    /// multipath volumes, leaving live paths untouched
+    #[arg(long, env = "PATH_MAINTENANCE", default_value = "false")]
+    path_maintenance: bool,
+
+    /// Seconds between path maintenance passes
+    #[arg(long, env = "PATH_MAINTENANCE_INTERVAL", default_value = "60", value_parser = clap::value_parser!(u64).range(1..))]
+    path_maintenance_interval: u64,
+
+    /// Seconds allowed for connecting to each iSCSI portal / NVMeoF endpoint
+    /// during NodeStageVolume before the attempt is abandoned and cleaned up
+    #[arg(long, env = "CONNECT_TIMEOUT", default_value = "60", value_parser = clap::value_parser!(u64).range(1..))]
+    connect_timeout: u64,
+
+    /// Attempts per NodeStageVolume; a transient failure tears down the
+    /// partial session and mount and starts over (1 disables retrying)
+    #[arg(long, env = "STAGE_ATTEMPTS", default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    stage_attempts: u32,
+
+    /// Seconds before the second staging attempt, doubling for each further one
+    #[arg(long, env = "STAGE_RETRY_BACKOFF", default_value = "2")]
+    stage_retry_backoff: u64,
+
+    /// Maximum concurrent NodeStageVolume/NodePublishVolume/NodeExpandVolume
+    /// operations on this node
+    #[arg(long, env = "NODE_MAX_CONCURRENT_OPS", default_value_t = DEFAULT_NODE_MAX_CONCURRENT_OPS)]
+    node_max_concurrent_ops: usize,
+
+    /// What node operations do when the limit is reached: "queue" (wait for
+    /// a slot) or "reject" (fail with RESOURCE_EXHAUSTED so kubelet retries)
+    #[arg(long, env = "NODE_SATURATION_POLICY", default_value = "queue")]
+    node_saturation_policy: SaturationPolicy,
+
+    /// Directory for node state that must survive a driver restart (records
+    /// of volumes staged from a shared NVMeoF controller)
+    #[arg(long, env = "NODE_STATE_DIR", default_value = DEFAULT_STATE_DIR)]
+    state_dir: PathBuf,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
+    if args.list_parameters {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(csi_driver::types::PARAMETERS)?
+        );
+        return Ok(());
+    }
+
     // Initialize tracing
     let level = match args.log_level.as_str() {
         "trace" => Level::TRACE,
@@ -87,8 +219,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         _ => Level::INFO,
     };
 
-    let subscriber = FmtSubscriber::builder().with_max_level(level).finish();
-    tracing::subscriber::set_global_default(subscriber)?;
+    init_tracing(level, args.log_format)?;
 
     // Initialize Prometheus metrics endpoint if configured
     if let Some(ref addr_str) = args.metrics_addr {
@@ -128,8 +259,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     use csi::node_server::NodeServer;
     use tonic::transport::Server;
 
+    let security = endpoint_tls::endpoint_security(
+        &endpoint,
+        args.endpoint_tls,
+        args.tls_cert.as_deref(),
+        args.tls_key.as_deref(),
+        args.tls_ca.as_deref(),
+    )?;
+    if args.endpoint_tls && security == EndpointSecurity::Plaintext {
+        warn!("--endpoint-tls has no effect on a Unix socket endpoint");
+    }
+
     let identity = IdentityService::with_readiness(readiness.clone());
     let mut server = Server::builder();
+    if let EndpointSecurity::Tls {
+        cert,
+        key,
+        client_ca,
+    } = &security
+    {
+        let tls = endpoint_tls::server_tls_config(cert, key, client_ca.as_deref()).await?;
+        server = server.tls_config(tls)?;
+        if client_ca.is_some() {
+            info!("CSI endpoint TLS enabled - client certificates required");
+        } else {
+            info!("CSI endpoint TLS enabled (server-only, no client verification)");
+        }
+    }
     let mut router = server.add_service(IdentityServer::new(identity));
 
     if args.controller {
@@ -161,13 +317,54 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         };
 
-        let controller = ControllerService::with_tls(args.agent_endpoint.clone(), tls_config);
+        let controller = ControllerService::with_tls(args.agent_endpoint.clone(), tls_config)
+            .with_strict_parameters(args.strict_parameters)
+            .with_export_ready_timeout(
+                args.wait_for_export_ready
+                    .then(|| Duration::from_secs(args.export_ready_timeout)),
+            )
+            .with_default_ports(DefaultPorts {
+                iscsi: args.default_iscsi_port,
+                nvmeof: args.default_nvme_port,
+            })
+            .with_retry_policy(RetryPolicy {
+                max_attempts: args.agent_retry_attempts,
+                base_delay: Duration::from_millis(args.agent_retry_base_delay_ms),
+                ..RetryPolicy::default()
+            });
         router = router.add_service(ControllerServer::new(controller));
     }
 
     if args.node {
         info!("Enabling Node service");
-        let node_svc = NodeService::new(node_id.clone());
+        let reported_node_id = if args.report_initiator_names {
+            node_id_with_initiators(&node_id).await?
+        } else {
+            node_id.clone()
+        };
+        let mut node_svc = NodeService::new(reported_node_id)
+            .with_auto_restage(args.auto_restage)
+            .with_stale_mount_policy(args.stale_staging_mount)
+            .with_remove_empty_block_target_dir(args.remove_empty_block_target_dir)
+            .with_missing_target_name(args.missing_target_name)
+            .with_volume_io_stats(args.volume_io_stats)
+            .with_grouped_namespaces(GroupedNamespaces::new(&args.state_dir))
+            .with_connect_timeout(Duration::from_secs(args.connect_timeout))
+            .with_stage_retry(StageRetry {
+                attempts: args.stage_attempts,
+                backoff: Duration::from_secs(args.stage_retry_backoff),
+            })
+            .with_op_limit(args.node_max_concurrent_ops, args.node_saturation_policy);
+        if args.path_maintenance {
+            let targets = StagedTargets::default();
+            path_maintenance::spawn(
+                targets.clone(),
+                Duration::from_secs(args.path_maintenance_interval),
+            );
+            node_svc = node_svc.with_path_maintenance(targets);
+        }
+        let staged = node_svc.restore_staged_volumes().await;
+        info!(count = staged, "Found volumes already staged on this node");
         router = router.add_service(NodeServer::new(node_svc));
     }
 
@@ -178,9 +375,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     if endpoint.starts_with("unix://") {
         let path = endpoint.strip_prefix("unix://").unwrap();
 
-        // Create parent directory if needed
+        // Create or validate the parent directory before binding
         if let Some(parent) = std::path::Path::new(path).parent() {
-            tokio::fs::create_dir_all(parent).await?;
+            socket::prepare_socket_parent(parent)?;
         }
 
         // Remove existing socket file
@@ -224,6 +421,31 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// `node_id` with this node's initiator names appended.
+///
+/// Falls back to the plain node ID when the result would exceed what
+/// Kubernetes accepts.
+async fn node_id_with_initiators(node_id: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let initiators = NodeInitiators {
+        iqn: Some(
+            platform::ensure_initiator_name(platform::ISCSI_INITIATOR_NAME_FILE.as_ref()).await?,
+        ),
+        host_nqn: Some(platform::ensure_host_nqn(platform::NVME_HOST_NQN_FILE.as_ref()).await?),
+    };
+    let reported = initiators.encode_node_id(node_id);
+    if reported.len() > MAX_NODE_ID_LEN {
+        warn!(
+            node_id = %node_id,
+            length = reported.len(),
+            "Node ID with initiator names exceeds {} characters, not reporting them",
+            MAX_NODE_ID_LEN
+        );
+        return Ok(node_id.to_string());
+    }
+    info!(node_id = %reported, "Reporting initiator names in node ID");
+    Ok(reported)
+}
+
 /// Wait for shutdown signal (SIGTERM, SIGINT, or SIGHUP)
 ///
 /// This function only supports Unix systems (FreeBSD/Linux) since the CSI driver
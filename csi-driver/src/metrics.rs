@@ -10,6 +10,8 @@ use metrics::{counter, gauge, histogram};
 use metrics_exporter_prometheus::PrometheusBuilder;
 use tracing::info;
 
+use crate::volume_stats::DiskStats;
+
 /// Metric names
 pub mod names {
     /// Counter: Total number of CSI operations by type and status
@@ -22,6 +24,24 @@ pub mod names {
     pub const CSI_AGENT_CONNECTION_ATTEMPTS: &str = "csi_agent_connection_attempts";
     /// Counter: Number of retried operations
     pub const CSI_RETRIES_TOTAL: &str = "csi_retries_total";
+    /// Gauge: Volume staged on this node (1), labeled by volume_id
+    pub const CSI_VOLUME_STAGED: &str = "csi_volume_staged";
+    /// Counter: Node operations rejected by the concurrency limit
+    pub const CSI_RATE_LIMITED_TOTAL: &str = "csi_rate_limited_total";
+    /// Counter: Staged targets whose IQN/NQN prefix differs from the node's
+    pub const CSI_TARGET_PREFIX_MISMATCH_TOTAL: &str = "csi_target_prefix_mismatch_total";
+    /// Counter: Completed reads on a volume's device, labeled by volume_id
+    pub const CSI_VOLUME_READ_OPS_TOTAL: &str = "csi_volume_read_ops_total";
+    /// Counter: Completed writes on a volume's device, labeled by volume_id
+    pub const CSI_VOLUME_WRITE_OPS_TOTAL: &str = "csi_volume_write_ops_total";
+    /// Counter: Bytes read from a volume's device, labeled by volume_id
+    pub const CSI_VOLUME_READ_BYTES_TOTAL: &str = "csi_volume_read_bytes_total";
+    /// Counter: Bytes written to a volume's device, labeled by volume_id
+    pub const CSI_VOLUME_WRITE_BYTES_TOTAL: &str = "csi_volume_write_bytes_total";
+    /// Counter: Milliseconds spent on reads, labeled by volume_id
+    pub const CSI_VOLUME_READ_TIME_MS_TOTAL: &str = "csi_volume_read_time_milliseconds_total";
+    /// Counter: Milliseconds spent on writes, labeled by volume_id
+    pub const CSI_VOLUME_WRITE_TIME_MS_TOTAL: &str = "csi_volume_write_time_milliseconds_total";
 }
 
 /// Initialize the Prometheus metrics exporter
@@ -50,6 +70,15 @@ pub fn set_agent_connected(connected: bool) {
     gauge!(names::CSI_AGENT_CONNECTED).set(if connected { 1.0 } else { 0.0 });
 }
 
+/// Record that a volume was staged on (1) or unstaged from (0) this node
+pub fn set_volume_staged(volume_id: &str, staged: bool) {
+    gauge!(names::CSI_VOLUME_STAGED, "volume_id" => volume_id.to_string()).set(if staged {
+        1.0
+    } else {
+        0.0
+    });
+}
+
 /// Record an agent connection attempt
 pub fn record_connection_attempt(success: bool) {
     counter!(names::CSI_AGENT_CONNECTION_ATTEMPTS, "success" => success.to_string()).increment(1);
@@ -60,6 +89,35 @@ pub fn record_retry(operation: &str) {
     counter!(names::CSI_RETRIES_TOTAL, "operation" => operation.to_string()).increment(1);
 }
 
+/// Record a node operation rejected by the concurrency limit
+pub fn record_rate_limited(operation: &str) {
+    counter!(names::CSI_RATE_LIMITED_TOTAL, "operation" => operation.to_string()).increment(1);
+}
+
+/// Record a staged target named outside the node's IQN/NQN prefix
+pub fn record_target_prefix_mismatch(export_type: &str) {
+    counter!(names::CSI_TARGET_PREFIX_MISMATCH_TOTAL, "export_type" => export_type.to_string())
+        .increment(1);
+}
+
+/// Count I/O a volume's device did, as the `increase` of the kernel's
+/// counters since they were last counted
+pub fn record_volume_io(volume_id: &str, increase: &DiskStats) {
+    let id = volume_id.to_string();
+    counter!(names::CSI_VOLUME_READ_OPS_TOTAL, "volume_id" => id.clone())
+        .increment(increase.read_ios);
+    counter!(names::CSI_VOLUME_WRITE_OPS_TOTAL, "volume_id" => id.clone())
+        .increment(increase.write_ios);
+    counter!(names::CSI_VOLUME_READ_BYTES_TOTAL, "volume_id" => id.clone())
+        .increment(increase.read_bytes);
+    counter!(names::CSI_VOLUME_WRITE_BYTES_TOTAL, "volume_id" => id.clone())
+        .increment(increase.write_bytes);
+    counter!(names::CSI_VOLUME_READ_TIME_MS_TOTAL, "volume_id" => id.clone())
+        .increment(increase.read_time_ms);
+    counter!(names::CSI_VOLUME_WRITE_TIME_MS_TOTAL, "volume_id" => id)
+        .increment(increase.write_time_ms);
+}
+
 /// Helper for timing operations
 pub struct OperationTimer {
     operation: String,
@@ -99,4 +157,23 @@ mod tests {
         // Just verify it doesn't panic - actual metrics recording requires init
         drop(timer);
     }
+
+    #[test]
+    fn test_stage_and_unstage_toggle_volume_gauge() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+
+        metrics::with_local_recorder(&recorder, || {
+            set_volume_staged("pvc-gauge-1", true);
+            set_volume_staged("pvc-gauge-2", true);
+        });
+        let rendered = handle.render();
+        assert!(rendered.contains(r#"csi_volume_staged{volume_id="pvc-gauge-1"} 1"#));
+        assert!(rendered.contains(r#"csi_volume_staged{volume_id="pvc-gauge-2"} 1"#));
+
+        metrics::with_local_recorder(&recorder, || set_volume_staged("pvc-gauge-1", false));
+        let rendered = handle.render();
+        assert!(rendered.contains(r#"csi_volume_staged{volume_id="pvc-gauge-1"} 0"#));
+        assert!(rendered.contains(r#"csi_volume_staged{volume_id="pvc-gauge-2"} 1"#));
+    }
 }
@@ -13,9 +13,15 @@
 //! - NVMeoF: `nqn.2024-01.org.freebsd.csi:<volume_id>`
 //!
 //! This allows NodeUnstageVolume to determine the target to disconnect
-//! without requiring local metadata storage.
+//! without requiring local metadata storage. The exception are volumes in a
+//! `controllerGroup`, which share one NVMeoF controller; their controller NQN
+//! and namespace ID are recorded on disk at stage time (see
+//! [`crate::grouped_namespaces`]).
 
+use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
 
 // Note: fs operations use tokio::fs for async file I/O,
 // Command uses tokio::process::Command for async process execution.
@@ -26,12 +32,21 @@ use tokio::process::Command;
 use tonic::{Request, Response, Status};
 use tracing::{debug, error, info, warn};
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use crate::csi;
+use crate::grouped_namespaces::{GroupedNamespace, GroupedNamespaces};
+use crate::metrics;
+use crate::node_limit::{self, DEFAULT_NODE_MAX_CONCURRENT_OPS, SaturationPolicy};
+use crate::path_maintenance::{StagedTarget, StagedTargets};
 use crate::platform;
 use crate::platform::{IscsiChapCredentials, NvmeAuthCredentials};
-use crate::types::{Endpoints, ExportType, NvmeofConnectOptions};
+use crate::types::{
+    DEFAULT_PORT_CONTEXT_KEY, DirectIo, Endpoints, ExportType, IscsiDiscoveryOptions,
+    MKFS_OPTIONS_PARAM, NvmeofConnectOptions, NvmeofDiscovery, NvmeofMultipath,
+};
+use crate::volume_stats;
+use csi_common::limit::Limiter;
 
 /// Base IQN prefix for iSCSI targets (must match ctld-agent configuration)
 const BASE_IQN: &str = "iqn.2024-01.org.freebsd.csi";
@@ -51,6 +66,132 @@ const CHAP_MUTUAL_PASSWORD_KEY: &str = "node.session.auth.password_in";
 const NVME_SECRET_KEY: &str = "nvme.auth.secret";
 const NVME_CTRL_SECRET_KEY: &str = "nvme.auth.ctrl_secret";
 
+/// Connectivity checks after a disconnect before the session counts as stuck.
+/// Some initiators tear sessions down asynchronously after logout returns.
+const DISCONNECT_VERIFY_ATTEMPTS: u32 = 5;
+/// Delay between disconnect verification checks
+const DISCONNECT_VERIFY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Poll `is_connected` until it reports false, checking at most `attempts`
+/// times. Returns whether the session cleared.
+async fn wait_for_disconnect<F, Fut>(mut is_connected: F, attempts: u32, interval: Duration) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    for attempt in 1..=attempts {
+        if !is_connected().await {
+            return true;
+        }
+        if attempt < attempts {
+            tokio::time::sleep(interval).await;
+        }
+    }
+    false
+}
+
+/// Whole-pipeline retry budget for NodeStageVolume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageRetry {
+    /// Attempts per NodeStageVolume call (1 disables retrying)
+    pub attempts: u32,
+    /// Delay before the second attempt, doubling for each further one
+    pub backoff: Duration,
+}
+
+impl Default for StageRetry {
+    fn default() -> Self {
+        Self {
+            attempts: 1,
+            backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl StageRetry {
+    /// Delay before attempt `attempt + 1`
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(1u32 << (attempt - 1).min(MAX_STAGE_BACKOFF_DOUBLINGS))
+    }
+}
+
+/// Cap on backoff doublings between staging attempts
+const MAX_STAGE_BACKOFF_DOUBLINGS: u32 = 5;
+
+/// Whether a failed staging attempt may succeed when run again.
+///
+/// Bad requests and conditions needing an operator (e.g. a filesystem that
+/// mounts read-only) are returned immediately.
+fn is_transient_stage_error(status: &Status) -> bool {
+    matches!(
+        status.code(),
+        tonic::Code::Internal
+            | tonic::Code::Unavailable
+            | tonic::Code::DeadlineExceeded
+            | tonic::Code::Aborted
+            | tonic::Code::Unknown
+    )
+}
+
+/// Run `attempt` up to `retry.attempts` times, calling `cleanup` between
+/// attempts so each one starts from a clean state. Returns the last error
+/// when the budget is exhausted, the error is not transient, or cleanup
+/// fails (retrying on top of leftovers could double-connect or mount).
+async fn retry_stage<F, Fut, C, CFut>(
+    volume_id: &str,
+    retry: StageRetry,
+    mut attempt: F,
+    mut cleanup: C,
+) -> Result<(), Status>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: std::future::Future<Output = Result<(), Status>>,
+    C: FnMut() -> CFut,
+    CFut: std::future::Future<Output = Result<(), Status>>,
+{
+    let attempts = retry.attempts.max(1);
+    let mut current = 1;
+    loop {
+        let err = match attempt(current).await {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+        if current >= attempts || !is_transient_stage_error(&err) {
+            return Err(err);
+        }
+
+        let delay = retry.delay(current);
+        warn!(
+            volume_id = %volume_id,
+            attempt = current,
+            max_attempts = attempts,
+            retry_in = ?delay,
+            error = %err.message(),
+            "Staging attempt failed, cleaning up before retrying"
+        );
+        if let Err(cleanup_err) = cleanup().await {
+            error!(
+                volume_id = %volume_id,
+                error = %cleanup_err.message(),
+                "Failed to clean up partial staging; not retrying"
+            );
+            return Err(err);
+        }
+        tokio::time::sleep(delay).await;
+        current += 1;
+    }
+}
+
+/// Volumes staged on this node and the last kernel I/O counters counted for
+/// each, behind the per-volume metrics
+#[derive(Debug, Default)]
+struct VolumeSeries {
+    staged: BTreeSet<String>,
+    /// Kept across unstage, so a restaged volume is not counted twice
+    io: HashMap<String, volume_stats::DiskStats>,
+}
+
 /// CSI Node Service
 ///
 /// Implements the CSI Node service which handles:
@@ -62,12 +203,171 @@ const NVME_CTRL_SECRET_KEY: &str = "nvme.auth.ctrl_secret";
 pub struct NodeService {
     /// The node identifier for this CSI node
     node_id: String,
+    /// Remount a lost staging mount at publish time instead of failing
+    auto_restage: bool,
+    /// Remove an empty directory found at a block volume's target path
+    remove_empty_block_target_dir: bool,
+    /// Handling of a staging mount backed by another device
+    stale_mount_policy: StaleMountPolicy,
+    /// Handling of a volume context without `targetName`
+    missing_target_name: MissingTargetNamePolicy,
+    /// Staged multipath volumes, recorded when path maintenance is enabled
+    staged_targets: Option<StagedTargets>,
+    /// Time allowed for connecting to each endpoint during staging
+    connect_timeout: Duration,
+    /// Bounds concurrent stage/publish/expand operations
+    op_limiter: Limiter,
+    /// What an operation does when the limiter is saturated
+    saturation_policy: SaturationPolicy,
+    /// Retry budget for the whole staging pipeline
+    stage_retry: StageRetry,
+    /// Export per-volume device I/O counters from NodeGetVolumeStats
+    volume_io_stats: bool,
+    /// Staged volumes that share an NVMeoF controller with others
+    grouped_namespaces: GroupedNamespaces,
+    /// Staged volumes reported in the per-volume metrics
+    volume_series: std::sync::Mutex<VolumeSeries>,
 }
 
 impl NodeService {
     /// Create a new NodeService with the specified node ID.
     pub fn new(node_id: String) -> Self {
-        Self { node_id }
+        Self {
+            node_id,
+            auto_restage: false,
+            remove_empty_block_target_dir: false,
+            stale_mount_policy: StaleMountPolicy::default(),
+            missing_target_name: MissingTargetNamePolicy::default(),
+            staged_targets: None,
+            connect_timeout: platform::DEFAULT_CONNECT_TIMEOUT,
+            op_limiter: node_limit::node_op_limiter(DEFAULT_NODE_MAX_CONCURRENT_OPS),
+            saturation_policy: SaturationPolicy::default(),
+            stage_retry: StageRetry::default(),
+            volume_io_stats: false,
+            grouped_namespaces: GroupedNamespaces::default(),
+            volume_series: std::sync::Mutex::default(),
+        }
+    }
+
+    /// Re-stage filesystem volumes whose staging mount disappeared (e.g. after
+    /// a node reboot) when the target session is still active.
+    pub fn with_auto_restage(mut self, auto_restage: bool) -> Self {
+        self.auto_restage = auto_restage;
+        self
+    }
+
+    /// Replace an empty directory at a block volume's target path with the
+    /// device symlink instead of failing.
+    pub fn with_remove_empty_block_target_dir(mut self, enabled: bool) -> Self {
+        self.remove_empty_block_target_dir = enabled;
+        self
+    }
+
+    /// Choose what NodeStageVolume does when the staging path is already
+    /// mounted from a device other than the volume's session device.
+    pub fn with_stale_mount_policy(mut self, policy: StaleMountPolicy) -> Self {
+        self.stale_mount_policy = policy;
+        self
+    }
+
+    /// Choose what NodeStageVolume does when the volume context has no
+    /// `targetName`.
+    pub fn with_missing_target_name(mut self, policy: MissingTargetNamePolicy) -> Self {
+        self.missing_target_name = policy;
+        self
+    }
+
+    /// Record staged multipath volumes in `targets` so the path maintenance
+    /// task can reconnect their failed paths.
+    pub fn with_path_maintenance(mut self, targets: StagedTargets) -> Self {
+        self.staged_targets = Some(targets);
+        self
+    }
+
+    /// Bound each endpoint's connect/login during staging; a portal that
+    /// never completes login is abandoned after `timeout`.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Limit how many stage/publish/expand operations run at once, and
+    /// whether operations beyond the limit wait or fail.
+    pub fn with_op_limit(mut self, limit: usize, policy: SaturationPolicy) -> Self {
+        self.op_limiter = node_limit::node_op_limiter(limit);
+        self.saturation_policy = policy;
+        self
+    }
+
+    /// Retry a failed NodeStageVolume from scratch, tearing down partial
+    /// sessions and mounts between attempts.
+    pub fn with_stage_retry(mut self, retry: StageRetry) -> Self {
+        self.stage_retry = retry;
+        self
+    }
+
+    /// Export the I/O counters of each volume's device as metrics whenever
+    /// kubelet polls NodeGetVolumeStats.
+    pub fn with_volume_io_stats(mut self, enabled: bool) -> Self {
+        self.volume_io_stats = enabled;
+        self
+    }
+
+    /// Keep the records of volumes staged from a shared NVMeoF controller
+    /// in `records`.
+    pub fn with_grouped_namespaces(mut self, records: GroupedNamespaces) -> Self {
+        self.grouped_namespaces = records;
+        self
+    }
+
+    /// Report the volumes already staged on this node in the staged-volume
+    /// metrics, so they survive a driver restart. Returns how many were found.
+    ///
+    /// Every staged volume keeps its session (block volumes have no staging
+    /// mount), so the volumes are read back from the sessions named after
+    /// the node's IQN/NQN prefix, plus the grouped namespace records whose
+    /// shared controller is still connected.
+    pub async fn restore_staged_volumes(&self) -> usize {
+        let iscsi = platform::iscsi_session_targets().await;
+        let nvmeof = platform::nvmeof_subsystem_nqns().await;
+        let grouped = self.grouped_namespaces.all().await;
+
+        let volumes = staged_volume_ids(&iscsi, &nvmeof, &grouped);
+        for volume_id in &volumes {
+            self.set_volume_staged(volume_id, true);
+        }
+        volumes.len()
+    }
+
+    /// Record that a volume was staged on or unstaged from this node.
+    ///
+    /// An unstaged volume is reported as 0 and its I/O is no longer counted.
+    fn set_volume_staged(&self, volume_id: &str, staged: bool) {
+        let mut series = self.volume_series.lock().unwrap();
+        if staged {
+            series.staged.insert(volume_id.to_string());
+        } else {
+            series.staged.remove(volume_id);
+        }
+        metrics::set_volume_staged(volume_id, staged);
+    }
+
+    /// Count the I/O a staged volume's device did since its last report.
+    ///
+    /// The kernel's counters are cumulative since the device appeared, so the
+    /// exported counters grow by their increase over the previous report; one
+    /// that went backwards restarted with the device and counts from zero. The
+    /// first report of a volume counts everything the device did so far.
+    fn count_volume_io(&self, volume_id: &str, stats: &volume_stats::DiskStats) {
+        let mut series = self.volume_series.lock().unwrap();
+        if !series.staged.contains(volume_id) {
+            return;
+        }
+        let previous = series
+            .io
+            .insert(volume_id.to_string(), *stats)
+            .unwrap_or_default();
+        metrics::record_volume_io(volume_id, &stats.increase_since(&previous));
     }
 
     /// Validate that a path is safe to use in shell commands.
@@ -298,37 +598,66 @@ impl NodeService {
         }
     }
 
-    /// Get the device backing a mount point.
+    /// Whether the filesystem on `path` already spans its whole device.
     ///
-    /// Uses `findmnt -n -o SOURCE` for reliable device lookup.
-    async fn get_mount_device(path: &str) -> Result<String, Status> {
-        Self::validate_path(path)?;
+    /// Compares the size recorded in the filesystem superblock with the
+    /// block device size. Returns `None` if either cannot be determined, in
+    /// which case the caller should just attempt the resize.
+    async fn is_already_expanded(path: &str, fs_type: &str) -> Option<bool> {
+        let device = Self::get_mount_device(path).await.ok()?;
+        let device_bytes = Self::command_stdout("blockdev", &["--getsize64", &device])
+            .await?
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+
+        let (fs_bytes, block_size) = match fs_type {
+            "ext4" | "ext3" | "ext2" => {
+                parse_dumpe2fs_size(&Self::command_stdout("dumpe2fs", &["-h", &device]).await?)?
+            }
+            "xfs" => parse_xfs_info_size(&Self::command_stdout("xfs_info", &[path]).await?)?,
+            _ => return None,
+        };
 
-        let output = Command::new("findmnt")
-            .args(["-n", "-o", "SOURCE", path])
-            .output()
-            .await
-            .map_err(|e| {
-                error!(error = %e, "Failed to execute findmnt");
-                Status::internal(format!("Failed to get mount device: {}", e))
-            })?;
+        debug!(
+            path = %path,
+            device = %device,
+            fs_bytes = fs_bytes,
+            device_bytes = device_bytes,
+            "Compared filesystem and device size"
+        );
+        Some(!needs_expansion(fs_bytes, device_bytes, block_size))
+    }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            error!(path = %path, stderr = %stderr, "findmnt failed");
-            return Err(Status::internal(format!(
-                "Path {} is not a mount point",
-                path
-            )));
+    /// Run a command and return its stdout, or `None` if it fails
+    async fn command_stdout(program: &str, args: &[&str]) -> Option<String> {
+        match Command::new(program).args(args).output().await {
+            Ok(output) if output.status.success() => {
+                Some(String::from_utf8_lossy(&output.stdout).into_owned())
+            }
+            Ok(output) => {
+                debug!(
+                    program = %program,
+                    stderr = %String::from_utf8_lossy(&output.stderr),
+                    "Size query failed"
+                );
+                None
+            }
+            Err(e) => {
+                debug!(program = %program, error = %e, "Size query could not run");
+                None
+            }
         }
+    }
 
-        let device = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        if device.is_empty() {
-            return Err(Status::internal(format!(
-                "Could not determine device for mount point {}",
-                path
-            )));
-        }
+    /// Get the device backing a mount point.
+    ///
+    /// The lookup method (findmnt, mountinfo or df) is chosen by the platform
+    /// module based on what the host provides.
+    async fn get_mount_device(path: &str) -> Result<String, Status> {
+        Self::validate_path(path)?;
+
+        let device = platform::find_mount_source(path).await?;
 
         // Validate device path looks reasonable (starts with /dev/)
         if !device.starts_with("/dev/") {
@@ -356,7 +685,18 @@ impl NodeService {
             .get("endpoints")
             .ok_or_else(|| Status::invalid_argument("Missing 'endpoints' in volume_context"))?;
 
-        Endpoints::parse(endpoints_str, export_type.default_port())
+        // Host-only endpoints use the controller's configured default port
+        let default_port = match volume_context.get(DEFAULT_PORT_CONTEXT_KEY) {
+            Some(port) => port.parse::<u16>().ok().filter(|&p| p > 0).ok_or_else(|| {
+                Status::invalid_argument(format!(
+                    "Invalid '{}' in volume_context: {}",
+                    DEFAULT_PORT_CONTEXT_KEY, port
+                ))
+            })?,
+            None => export_type.default_port(),
+        };
+
+        Endpoints::parse(endpoints_str, default_port)
             .map_err(|e| Status::invalid_argument(e.to_string()))
     }
 
@@ -370,13 +710,65 @@ impl NodeService {
         format!("{}:{}", BASE_NQN, volume_id)
     }
 
+    /// Target name to stage, from the volume context.
+    ///
+    /// An explicit `targetName` is authoritative. Without one, and with the
+    /// `derive` policy, the name is derived from the export type and volume
+    /// ID the same way unstaging derives it, provided `exportType` is set.
+    fn staging_target_name(
+        volume_id: &str,
+        volume_context: &HashMap<String, String>,
+        policy: MissingTargetNamePolicy,
+    ) -> Result<String, Status> {
+        if let Some(target_name) = volume_context.get("targetName") {
+            return Ok(target_name.clone());
+        }
+        let missing = || Status::invalid_argument("targetName is required in volume context");
+        if policy == MissingTargetNamePolicy::Fail {
+            return Err(missing());
+        }
+        let export_type: ExportType = volume_context
+            .get("exportType")
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(missing)?;
+
+        let target_name = match export_type {
+            ExportType::Iscsi => Self::derive_iqn(volume_id),
+            ExportType::Nvmeof => Self::derive_nqn(volume_id),
+        };
+        warn!(
+            volume_id = %volume_id,
+            target = %target_name,
+            "targetName missing from volume context, using the derived {} target name",
+            export_type
+        );
+        Ok(target_name)
+    }
+
+    /// Check a staged target against the prefix unstaging derives names from.
+    ///
+    /// NodeUnstageVolume gets no volume context, so it rebuilds the target
+    /// name from `BASE_IQN`/`BASE_NQN`. If the agent runs with a different
+    /// `--base-iqn`/`--base-nqn` that lookup finds no session and the volume
+    /// is left connected. Returns the expected prefix on mismatch.
+    fn target_prefix_mismatch(export_type: ExportType, target_name: &str) -> Option<&'static str> {
+        let base = match export_type {
+            ExportType::Iscsi => BASE_IQN,
+            ExportType::Nvmeof => BASE_NQN,
+        };
+        let matches = target_name
+            .strip_prefix(base)
+            .is_some_and(|rest| rest.starts_with(':'));
+        (!matches).then_some(base)
+    }
+
     /// Find and disconnect any iSCSI/NVMeoF targets for this volume.
     /// Uses session queries to find connected targets matching the volume ID.
     ///
     /// Returns error if disconnect fails - this is critical for correctness.
     /// Returning success when still connected would lie to Kubernetes and
     /// could cause data corruption (zombie LUNs, dual-attach scenarios).
-    async fn disconnect_volume_targets(volume_id: &str) -> Result<(), Status> {
+    async fn disconnect_volume_targets(&self, volume_id: &str) -> Result<(), Status> {
         debug!(volume_id = %volume_id, "Attempting to disconnect volume targets");
 
         // Try iSCSI first
@@ -394,8 +786,14 @@ impl NodeService {
                 ))
             })?;
 
-            // Verify disconnect succeeded
-            if platform::is_iscsi_connected(&iqn).await {
+            // Verify disconnect succeeded, allowing for asynchronous teardown
+            if !wait_for_disconnect(
+                || platform::is_iscsi_connected(&iqn),
+                DISCONNECT_VERIFY_ATTEMPTS,
+                DISCONNECT_VERIFY_INTERVAL,
+            )
+            .await
+            {
                 error!(target = %iqn, "iSCSI target still connected after disconnect");
                 return Err(Status::internal(format!(
                     "iSCSI target {} still connected after disconnect attempt",
@@ -404,14 +802,21 @@ impl NodeService {
             }
         }
 
-        // Try NVMeoF
-        let nqn = Self::derive_nqn(volume_id);
-        let nvme_connected = platform::is_nvmeof_connected(&nqn).await;
+        // Try NVMeoF; a grouped volume shares its controller with other volumes
+        if let Some(namespace) = self.grouped_namespaces.get(volume_id).await {
+            return self.release_grouped_namespace(volume_id, &namespace).await;
+        }
+        Self::disconnect_nvmeof_target(&Self::derive_nqn(volume_id)).await
+    }
+
+    /// Disconnect an NVMeoF controller and wait until its session is gone.
+    async fn disconnect_nvmeof_target(nqn: &str) -> Result<(), Status> {
+        let nvme_connected = platform::is_nvmeof_connected(nqn).await;
         debug!(target = %nqn, connected = %nvme_connected, "Checking NVMeoF target");
 
         if nvme_connected {
             info!(target = %nqn, "Disconnecting NVMeoF target");
-            platform::disconnect_nvmeof(&nqn).await.map_err(|e| {
+            platform::disconnect_nvmeof(nqn).await.map_err(|e| {
                 error!(error = %e, target = %nqn, "Failed to disconnect NVMeoF target");
                 Status::internal(format!(
                     "Failed to disconnect NVMeoF target {}: {}. Volume may still be connected.",
@@ -419,8 +824,14 @@ impl NodeService {
                 ))
             })?;
 
-            // Verify disconnect succeeded
-            if platform::is_nvmeof_connected(&nqn).await {
+            // Verify disconnect succeeded, allowing for asynchronous teardown
+            if !wait_for_disconnect(
+                || platform::is_nvmeof_connected(nqn),
+                DISCONNECT_VERIFY_ATTEMPTS,
+                DISCONNECT_VERIFY_INTERVAL,
+            )
+            .await
+            {
                 error!(target = %nqn, "NVMeoF target still connected after disconnect");
                 return Err(Status::internal(format!(
                     "NVMeoF target {} still connected after disconnect attempt",
@@ -435,6 +846,81 @@ impl NodeService {
         Ok(())
     }
 
+    /// Release a grouped volume's namespace on unstage.
+    ///
+    /// The shared controller is disconnected only when no other volume of
+    /// the group is staged on this node; its namespaces are still in use
+    /// otherwise. The record is dropped last so a failed disconnect is
+    /// retried by the next unstage.
+    async fn release_grouped_namespace(
+        &self,
+        volume_id: &str,
+        namespace: &GroupedNamespace,
+    ) -> Result<(), Status> {
+        let _guard = self.grouped_namespaces.lock().await;
+        let others: Vec<String> = self
+            .grouped_namespaces
+            .members(&namespace.target_name)
+            .await
+            .into_iter()
+            .filter(|id| id != volume_id)
+            .collect();
+        if others.is_empty() {
+            Self::disconnect_nvmeof_target(&namespace.target_name).await?;
+        } else {
+            info!(
+                volume_id = %volume_id,
+                target = %namespace.target_name,
+                staged = ?others,
+                "Leaving shared NVMeoF controller connected for other staged volumes"
+            );
+        }
+        self.grouped_namespaces
+            .remove(volume_id)
+            .await
+            .map_err(|e| {
+                Status::internal(format!(
+                    "Failed to remove namespace record of volume {}: {}",
+                    volume_id, e
+                ))
+            })
+    }
+
+    /// NVMeoF controller and namespace of a staged volume: the recorded
+    /// shared controller of a grouped volume, otherwise its own controller.
+    async fn nvmeof_target(&self, volume_id: &str) -> (String, Option<u32>) {
+        match self.grouped_namespaces.get(volume_id).await {
+            Some(namespace) => (namespace.target_name, Some(namespace.namespace_id)),
+            None => (Self::derive_nqn(volume_id), None),
+        }
+    }
+
+    /// Shared-controller namespace of a volume being staged, if any.
+    ///
+    /// A volume in a controller group connects to a controller whose NQN is
+    /// not derived from its ID; its namespace ID is the `lunId` from the
+    /// volume context.
+    fn grouped_namespace(
+        volume_id: &str,
+        target_name: &str,
+        volume_context: &HashMap<String, String>,
+    ) -> Result<Option<GroupedNamespace>, Status> {
+        if target_name == Self::derive_nqn(volume_id) {
+            return Ok(None);
+        }
+        let namespace_id = match volume_context.get("lunId") {
+            Some(id) => id.parse::<u32>().ok().filter(|id| *id > 0).ok_or_else(|| {
+                Status::invalid_argument(format!("Invalid 'lunId' in volume_context: {}", id))
+            })?,
+            // Controllers of ungrouped volumes only have namespace 1
+            None => 1,
+        };
+        Ok(Some(GroupedNamespace {
+            target_name: target_name.to_string(),
+            namespace_id,
+        }))
+    }
+
     /// Check if a volume capability is for block (raw device) access.
     fn is_block_volume(volume_capability: &Option<csi::VolumeCapability>) -> bool {
         matches!(
@@ -445,6 +931,26 @@ impl NodeService {
         )
     }
 
+    /// Whether a read-only staging mount is expected: the capability only
+    /// grants read access, or its mount flags ask for `ro`.
+    fn expects_read_only_mount(
+        volume_capability: &Option<csi::VolumeCapability>,
+        mount_options: &[String],
+    ) -> bool {
+        use csi::volume_capability::access_mode::Mode;
+
+        let reader_only = volume_capability
+            .as_ref()
+            .and_then(|cap| cap.access_mode.as_ref())
+            .is_some_and(|mode| {
+                matches!(
+                    Mode::try_from(mode.mode),
+                    Ok(Mode::SingleNodeReaderOnly | Mode::MultiNodeReaderOnly)
+                )
+            });
+        reader_only || mount_options.iter().any(|option| option == "ro")
+    }
+
     /// Get filesystem type from volume capability, with platform default fallback.
     fn get_fs_type_from_capability(
         volume_capability: &Option<csi::VolumeCapability>,
@@ -467,122 +973,89 @@ impl NodeService {
         platform::validate_fs_type(fs_type_raw)
     }
 
-    /// Check if a block volume is staged by checking for an active target session.
-    ///
-    /// For block volumes, "staged" means the target session is connected.
-    /// We check both iSCSI and NVMeoF based on the derived target names.
-    async fn is_block_volume_staged(volume_id: &str) -> bool {
-        let iqn = Self::derive_iqn(volume_id);
-        if platform::is_iscsi_connected(&iqn).await {
-            return true;
-        }
+    /// Options for the staging mount: the capability's mount flags plus any
+    /// `directIo` tuning. Block volumes get no mount, only a hint to open the
+    /// device with `O_DIRECT`.
+    fn staging_mount_options(
+        volume_id: &str,
+        volume_capability: &Option<csi::VolumeCapability>,
+        volume_context: &HashMap<String, String>,
+    ) -> Result<Vec<String>, Status> {
+        let direct_io = DirectIo::from_parameters(volume_context).map_err(|e| {
+            Status::invalid_argument(format!("Invalid option in volume context: {}", e))
+        })?;
+
+        let Some(csi::volume_capability::AccessType::Mount(mount)) = volume_capability
+            .as_ref()
+            .and_then(|cap| cap.access_type.as_ref())
+        else {
+            if direct_io {
+                info!(
+                    volume_id = %volume_id,
+                    "directIo has no effect on raw block volumes; the workload must open the \
+                     device with O_DIRECT itself"
+                );
+            }
+            return Ok(Vec::new());
+        };
 
-        let nqn = Self::derive_nqn(volume_id);
-        platform::is_nvmeof_connected(&nqn).await
+        let fs_type = Self::get_fs_type_from_capability(volume_capability, volume_context)?;
+        platform::mount_options(fs_type, &mount.mount_flags, direct_io)
     }
 
-    /// Find the block device for a volume by querying active sessions.
-    ///
-    /// Tries iSCSI first, then NVMeoF. Returns the device path if found.
-    async fn find_block_device(volume_id: &str) -> Result<String, Status> {
-        // Try iSCSI first
-        let iqn = Self::derive_iqn(volume_id);
-        if platform::is_iscsi_connected(&iqn).await {
-            return platform::find_iscsi_device(&iqn).await;
-        }
-
-        // Try NVMeoF
-        let nqn = Self::derive_nqn(volume_id);
-        if platform::is_nvmeof_connected(&nqn).await {
-            return platform::find_nvmeof_device(&nqn).await;
+    /// Extra mkfs arguments from `mkfsOptions`, used only if the volume has
+    /// no filesystem yet. Block volumes are never formatted.
+    fn staging_format_options(
+        volume_capability: &Option<csi::VolumeCapability>,
+        volume_context: &HashMap<String, String>,
+    ) -> Result<Vec<String>, Status> {
+        let Some(options) = volume_context.get(MKFS_OPTIONS_PARAM) else {
+            return Ok(Vec::new());
+        };
+        if Self::is_block_volume(volume_capability) {
+            return Ok(Vec::new());
         }
-
-        Err(Status::failed_precondition(format!(
-            "No active session found for volume {}",
-            volume_id
-        )))
+        let fs_type = Self::get_fs_type_from_capability(volume_capability, volume_context)?;
+        platform::format_options(fs_type, options)
     }
-}
 
-#[tonic::async_trait]
-impl csi::node_server::Node for NodeService {
-    /// Stage a volume to a staging path.
-    ///
-    /// For filesystem volumes: connects to iSCSI/NVMeoF target, formats if needed, and mounts.
-    /// For block volumes: connects to target and stores device path (no mount).
-    async fn node_stage_volume(
+    /// One attempt at the connect → format → mount part of NodeStageVolume.
+    async fn stage_once(
         &self,
-        request: Request<csi::NodeStageVolumeRequest>,
-    ) -> Result<Response<csi::NodeStageVolumeResponse>, Status> {
-        let req = request.into_inner();
+        req: &csi::NodeStageVolumeRequest,
+        target_name: &str,
+        export_type: ExportType,
+        mut endpoints: Endpoints,
+        mount_options: &[String],
+        is_block: bool,
+    ) -> Result<(), Status> {
         let volume_id = &req.volume_id;
         let staging_target_path = &req.staging_target_path;
         let volume_context = &req.volume_context;
-        let is_block = Self::is_block_volume(&req.volume_capability);
-
-        if volume_id.is_empty() {
-            return Err(Status::invalid_argument("Volume ID is required"));
-        }
-
-        if staging_target_path.is_empty() {
-            return Err(Status::invalid_argument("Staging target path is required"));
-        }
-
-        Self::validate_path(staging_target_path)?;
-
-        info!(
-            volume_id = %volume_id,
-            staging_target_path = %staging_target_path,
-            is_block = %is_block,
-            "NodeStageVolume request"
-        );
-
-        // Get volume context parameters
-        let target_name = volume_context
-            .get("targetName")
-            .ok_or_else(|| Status::invalid_argument("targetName is required in volume context"))?;
-
-        Self::validate_target_name(target_name)?;
-
-        let export_type: ExportType = volume_context
-            .get("exportType")
-            .and_then(|s| s.parse().ok())
-            .unwrap_or_default();
-
-        // Parse all endpoints from volume_context for multipath support
-        let endpoints = Self::parse_endpoints(volume_context, export_type)?;
-
-        debug!(
-            volume_id = %volume_id,
-            endpoints = %endpoints.to_portal_string(),
-            multipath = endpoints.is_multipath(),
-            "Parsed endpoints for staging"
-        );
-
-        // Check if already staged
-        if is_block {
-            // Block volume: check if target session is active
-            if Self::is_block_volume_staged(volume_id).await {
-                info!(volume_id = %volume_id, "Block volume already staged (session active)");
-                return Ok(Response::new(csi::NodeStageVolumeResponse {}));
-            }
-        } else {
-            // Mount volume: check if mounted
-            if platform::is_mounted(staging_target_path).await? {
-                info!(staging_target_path = %staging_target_path, "Volume already staged");
-                return Ok(Response::new(csi::NodeStageVolumeResponse {}));
-            }
-        }
 
         // Extract authentication credentials from secrets based on export type
         let secrets = &req.secrets;
 
         // Connect to target and get device (multipath: connects to all endpoints)
+        let mut nvme_session = None;
         let device = match export_type {
             ExportType::Iscsi => {
                 let chap_creds = Self::extract_iscsi_chap(secrets);
-                platform::connect_iscsi(target_name, endpoints.as_slice(), chap_creds.as_ref())
-                    .await?
+                let discovery = IscsiDiscoveryOptions::parse(volume_context).map_err(|e| {
+                    Status::invalid_argument(format!(
+                        "Invalid iSCSI discovery option in volume context: {}",
+                        e
+                    ))
+                })?;
+
+                platform::connect_iscsi(
+                    target_name,
+                    endpoints.as_slice(),
+                    chap_creds.as_ref(),
+                    Some(&discovery),
+                    self.connect_timeout,
+                )
+                .await?
             }
             ExportType::Nvmeof => {
                 let nvme_creds = Self::extract_nvme_auth(secrets);
@@ -592,16 +1065,103 @@ impl csi::node_server::Node for NodeService {
                         e
                     ))
                 })?;
+                let discovery =
+                    NvmeofDiscovery::from_volume_context(volume_context).map_err(|e| {
+                        Status::invalid_argument(format!(
+                            "Invalid NVMe discovery endpoints in volume context: {}",
+                            e
+                        ))
+                    })?;
+                let multipath = NvmeofMultipath::parse(volume_context)
+                    .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+                // Native multipath connects every controller the subsystem
+                // advertises, asking the first endpoint's discovery service
+                // when no discovery controllers are configured
+                let discovery = match multipath {
+                    NvmeofMultipath::Native => {
+                        if !platform::is_nvme_native_multipath_enabled().await {
+                            return Err(Status::failed_precondition(format!(
+                                "{}=native requires native NVMe multipath on the node \
+                                 (nvme_core.multipath=Y)",
+                                NvmeofMultipath::PARAM_NAME
+                            )));
+                        }
+                        discovery.or_else(|| NvmeofDiscovery::from_first_endpoint(&endpoints))
+                    }
+                    NvmeofMultipath::Auto => discovery,
+                };
+
+                if let Some(discovery) = discovery {
+                    let discovered = platform::discover_nvmeof_endpoints(
+                        target_name,
+                        discovery.endpoints.as_slice(),
+                        self.connect_timeout,
+                    )
+                    .await;
+                    if discovered.is_empty() {
+                        warn!(
+                            volume_id = %volume_id,
+                            target = %target_name,
+                            "NVMe discovery did not list the target, connecting to configured endpoints"
+                        );
+                    } else {
+                        info!(
+                            volume_id = %volume_id,
+                            endpoints = ?discovered.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+                            "Using endpoints from NVMe discovery"
+                        );
+                        endpoints = discovered.into_iter().collect();
+                    }
+                }
+
+                // Record a grouped volume before connecting, so an unstage
+                // of another member keeps the shared controller connected
+                let namespace = Self::grouped_namespace(volume_id, target_name, volume_context)?;
+                if let Some(namespace) = &namespace {
+                    let _guard = self.grouped_namespaces.lock().await;
+                    self.grouped_namespaces
+                        .record(volume_id, namespace)
+                        .await
+                        .map_err(|e| {
+                            Status::internal(format!(
+                                "Failed to record namespace of volume {}: {}",
+                                volume_id, e
+                            ))
+                        })?;
+                }
 
-                platform::connect_nvmeof(
+                let device = platform::connect_nvmeof(
                     target_name,
+                    namespace.map(|ns| ns.namespace_id),
                     endpoints.as_slice(),
                     nvme_creds.as_ref(),
                     Some(&connect_options),
+                    self.connect_timeout,
                 )
-                .await?
+                .await?;
+                nvme_session = Some((nvme_creds, connect_options));
+                device
             }
         };
+        // Mount by a name that survives reboots reordering /dev/sdX
+        let device = platform::stable_device_path(&device).await;
+
+        if let Some(targets) = &self.staged_targets
+            && endpoints.is_multipath()
+        {
+            let (nvme_auth, nvme_options) = nvme_session.unzip();
+            targets.insert(
+                volume_id,
+                StagedTarget {
+                    export_type,
+                    target_name: target_name.to_string(),
+                    endpoints: endpoints.as_slice().to_vec(),
+                    nvme_auth: nvme_auth.flatten(),
+                    nvme_options,
+                },
+            );
+        }
 
         if is_block {
             // Block volume: connection is complete, device will be queried at publish time
@@ -616,22 +1176,672 @@ impl csi::node_server::Node for NodeService {
             let fs_type =
                 Self::get_fs_type_from_capability(&req.volume_capability, volume_context)?;
 
+            // Never reformat: mkfsOptions only shape a brand-new filesystem
             if platform::needs_formatting(&device).await? {
-                platform::format_device(&device, fs_type).await?;
+                let format_options =
+                    Self::staging_format_options(&req.volume_capability, volume_context)?;
+                platform::format_device(&device, fs_type, &format_options).await?;
             }
 
             // Mount the device to staging path
-            platform::mount_device(&device, staging_target_path, fs_type).await?;
+            platform::mount_device(&device, staging_target_path, fs_type, mount_options).await?;
 
-            info!(
-                volume_id = %volume_id,
-                staging_target_path = %staging_target_path,
+            // A filesystem with errors may come up read-only; catch it here
+            // rather than letting the workload's writes fail silently
+            if !Self::expects_read_only_mount(&req.volume_capability, mount_options)
+                && platform::is_read_only_mount(staging_target_path).await?
+            {
+                error!(
+                    volume_id = %volume_id,
+                    device = %device,
+                    fs_type = %fs_type,
+                    "Filesystem mounted read-only unexpectedly; it likely needs repair"
+                );
+                // Unmount so a retry doesn't mistake the read-only mount for a staged volume
+                if let Err(e) = platform::unmount(staging_target_path).await {
+                    warn!(error = %e, "Failed to unmount read-only staging mount");
+                }
+                return Err(Status::failed_precondition(format!(
+                    "{} filesystem on {} mounted read-only, indicating filesystem errors; \
+                     run fsck/xfs_repair on the volume before retrying",
+                    fs_type, device
+                )));
+            }
+
+            info!(
+                volume_id = %volume_id,
+                staging_target_path = %staging_target_path,
                 device = %device,
                 fs_type = %fs_type,
                 "Mount volume staged successfully"
             );
         }
 
+        Ok(())
+    }
+
+    /// Undo whatever a failed staging attempt left behind (staging mount,
+    /// path maintenance entry, sessions) so the next attempt starts clean.
+    async fn reset_partial_stage(
+        &self,
+        volume_id: &str,
+        staging_target_path: &str,
+    ) -> Result<(), Status> {
+        if platform::is_mounted(staging_target_path).await? {
+            platform::unmount(staging_target_path).await?;
+        }
+        if let Some(targets) = &self.staged_targets {
+            targets.remove(volume_id).await;
+        }
+        self.disconnect_volume_targets(volume_id).await
+    }
+
+    /// Check if a block volume is staged by checking for an active target session.
+    ///
+    /// For block volumes, "staged" means the target session is connected.
+    /// We check both iSCSI and NVMeoF based on the derived target names.
+    async fn is_block_volume_staged(&self, volume_id: &str) -> bool {
+        let iqn = Self::derive_iqn(volume_id);
+        if platform::is_iscsi_connected(&iqn).await {
+            return true;
+        }
+
+        let (nqn, _) = self.nvmeof_target(volume_id).await;
+        platform::is_nvmeof_connected(&nqn).await
+    }
+
+    /// Find the block device for a volume by querying active sessions.
+    ///
+    /// Tries iSCSI first, then NVMeoF. Returns the device's persistent path
+    /// (see [`platform::stable_device_path`]) if found.
+    async fn find_block_device(&self, volume_id: &str) -> Result<String, Status> {
+        // Try iSCSI first
+        let iqn = Self::derive_iqn(volume_id);
+        if platform::is_iscsi_connected(&iqn).await {
+            let device = platform::find_iscsi_device(&iqn).await?;
+            return Ok(platform::stable_device_path(&device).await);
+        }
+
+        // Try NVMeoF
+        let (nqn, namespace_id) = self.nvmeof_target(volume_id).await;
+        if platform::is_nvmeof_connected(&nqn).await {
+            let device = platform::find_nvmeof_device(&nqn, namespace_id).await?;
+            return Ok(platform::stable_device_path(&device).await);
+        }
+
+        Err(Status::failed_precondition(format!(
+            "No active session found for volume {}",
+            volume_id
+        )))
+    }
+
+    /// Check that a volume's paths are mounted and its session is active.
+    ///
+    /// `staging_target_path` is optional in NodeGetVolumeStats and only
+    /// checked when the CO passes it. A block volume is published as a
+    /// symlink rather than mounted, so it is instead checked to resolve to
+    /// the device of the volume's session.
+    async fn check_volume_condition(
+        &self,
+        volume_id: &str,
+        staging_target_path: &str,
+        volume_path: &str,
+        is_block: bool,
+    ) -> Result<csi::VolumeCondition, Status> {
+        if is_block {
+            let device_resolved = match self.find_block_device(volume_id).await {
+                Ok(device) => tokio::fs::canonicalize(&device).await.ok(),
+                Err(_) => None,
+            };
+            let link_resolved = tokio::fs::canonicalize(volume_path).await.ok();
+            return Ok(block_volume_condition(
+                volume_path,
+                link_resolved.as_deref(),
+                device_resolved.as_deref(),
+            ));
+        }
+
+        let mut unmounted = None;
+        for path in [staging_target_path, volume_path] {
+            if !path.is_empty() && !platform::is_mounted(path).await? {
+                unmounted = Some(path);
+                break;
+            }
+        }
+
+        let session_active = platform::is_iscsi_connected(&Self::derive_iqn(volume_id)).await
+            || platform::is_nvmeof_connected(&self.nvmeof_target(volume_id).await.0).await;
+
+        Ok(volume_condition(unmounted, session_active))
+    }
+
+    /// Export the I/O counters of a volume's device.
+    ///
+    /// The device comes from the volume's session, falling back to the
+    /// source of a filesystem volume's mount. Failures are only logged: the
+    /// counters are a best-effort extra on top of the usage report.
+    async fn record_volume_io(&self, volume_id: &str, volume_path: &str, is_block: bool) {
+        let device = match self.find_block_device(volume_id).await {
+            Ok(device) => device,
+            Err(_) if !is_block => match Self::get_mount_device(volume_path).await {
+                Ok(device) => device,
+                Err(e) => {
+                    debug!(volume_id = %volume_id, error = %e, "No device for volume I/O stats");
+                    return;
+                }
+            },
+            Err(e) => {
+                debug!(volume_id = %volume_id, error = %e, "No device for volume I/O stats");
+                return;
+            }
+        };
+
+        match volume_stats::read_disk_stats(&device).await {
+            Some(stats) => self.count_volume_io(volume_id, &stats),
+            None => debug!(
+                volume_id = %volume_id,
+                device = %device,
+                "Kernel reports no I/O stats for device"
+            ),
+        }
+    }
+
+    /// Decide how to handle the staging mount when publishing a filesystem volume.
+    ///
+    /// A missing staging mount with an active session typically means the node
+    /// rebooted and reconnected while kubelet still believes the volume is
+    /// staged. With auto-restage enabled it is remounted; otherwise publish
+    /// fails as before.
+    fn staging_check(staged: bool, session_active: bool, auto_restage: bool) -> StagingCheck {
+        match (staged, session_active, auto_restage) {
+            (true, _, _) => StagingCheck::Staged,
+            (false, true, true) => StagingCheck::Restage,
+            _ => StagingCheck::NotStaged,
+        }
+    }
+
+    /// Remount a filesystem volume's staging path from its active session.
+    ///
+    /// Unlike NodeStageVolume this never formats: a device without a
+    /// filesystem at this point means something else is wrong.
+    async fn restage_filesystem(
+        &self,
+        volume_id: &str,
+        staging_target_path: &str,
+        volume_capability: &Option<csi::VolumeCapability>,
+        volume_context: &HashMap<String, String>,
+    ) -> Result<(), Status> {
+        let device = self.find_block_device(volume_id).await?;
+        let fs_type = Self::get_fs_type_from_capability(volume_capability, volume_context)?;
+        let mount_options =
+            Self::staging_mount_options(volume_id, volume_capability, volume_context)?;
+
+        if platform::needs_formatting(&device).await? {
+            return Err(Status::failed_precondition(format!(
+                "Device {} for volume {} has no filesystem; refusing to format during re-stage",
+                device, volume_id
+            )));
+        }
+
+        platform::mount_device(&device, staging_target_path, fs_type, &mount_options).await?;
+
+        info!(
+            volume_id = %volume_id,
+            staging_target_path = %staging_target_path,
+            device = %device,
+            "Re-staged volume after lost staging mount"
+        );
+        Ok(())
+    }
+
+    /// Decide what to do with a mount already present at a filesystem
+    /// volume's staging path.
+    ///
+    /// `mount_device` and `session_device` are the canonicalized mount source
+    /// and the volume's current session device (`None` if they do not
+    /// resolve). Only a mount of the session device counts as staged: after
+    /// a node crash the path may still hold another volume's device. Without
+    /// any session for the volume (e.g. after a reboot) the mount cannot be
+    /// the volume's own, so the stale mount policy applies; it does too when
+    /// both devices resolve and differ. While the session exists but either
+    /// device is unknown (session still logging in, device node not created
+    /// yet) the mount may well be the volume's own, so it is left alone and
+    /// the stage retried.
+    fn existing_mount_action(
+        mount_device: Option<&Path>,
+        session_device: Option<&Path>,
+        session_connected: bool,
+        policy: StaleMountPolicy,
+    ) -> ExistingMountAction {
+        let stale = match policy {
+            StaleMountPolicy::Remount => ExistingMountAction::Remount,
+            StaleMountPolicy::Fail => ExistingMountAction::Conflict,
+        };
+        if !session_connected {
+            return stale;
+        }
+        match (mount_device, session_device) {
+            (Some(mounted), Some(current)) if mounted == current => {
+                ExistingMountAction::AlreadyStaged
+            }
+            (Some(_), Some(_)) => stale,
+            _ => ExistingMountAction::Unresolved,
+        }
+    }
+
+    /// Check an existing staging mount against the volume's session device.
+    async fn check_existing_staging_mount(
+        &self,
+        volume_id: &str,
+        staging_target_path: &str,
+    ) -> Result<ExistingMountAction, Status> {
+        let mount_source = Self::get_mount_device(staging_target_path).await?;
+        let session_connected = self.is_block_volume_staged(volume_id).await;
+        let session_device = if session_connected {
+            self.find_block_device(volume_id).await.ok()
+        } else {
+            None
+        };
+
+        let mount_device = tokio::fs::canonicalize(&mount_source).await.ok();
+        let current_device = match &session_device {
+            Some(device) => tokio::fs::canonicalize(device).await.ok(),
+            None => None,
+        };
+        let action = Self::existing_mount_action(
+            mount_device.as_deref(),
+            current_device.as_deref(),
+            session_connected,
+            self.stale_mount_policy,
+        );
+        if action == ExistingMountAction::Unresolved {
+            warn!(
+                volume_id = %volume_id,
+                staging_target_path = %staging_target_path,
+                mounted_device = %mount_source,
+                session_device = %session_device.as_deref().unwrap_or("none"),
+                "Cannot tell whether the staging mount belongs to this volume"
+            );
+        } else if action != ExistingMountAction::AlreadyStaged {
+            warn!(
+                volume_id = %volume_id,
+                staging_target_path = %staging_target_path,
+                mounted_device = %mount_source,
+                session_device = %session_device.as_deref().unwrap_or("none"),
+                policy = %self.stale_mount_policy,
+                "Staging path is mounted from a device that does not belong to this volume"
+            );
+        }
+        Ok(action)
+    }
+
+    /// Decide what to do with an existing symlink at a block volume's target path.
+    ///
+    /// `link_resolved` and `device_resolved` are the canonicalized link target and
+    /// current session device (`None` if they do not resolve). A link is current
+    /// only if it resolves to the same device node as the active session; a
+    /// dangling link or one resolving elsewhere (device renamed on reconnect) is
+    /// replaced.
+    fn block_publish_action(
+        link: &Path,
+        link_resolved: Option<&Path>,
+        device: &Path,
+        device_resolved: Option<&Path>,
+    ) -> BlockPublishAction {
+        match (link_resolved, device_resolved) {
+            (None, _) => BlockPublishAction::Replace,
+            (Some(link_dev), Some(current_dev)) if link_dev == current_dev => {
+                BlockPublishAction::AlreadyPublished
+            }
+            (Some(_), None) if link == device => BlockPublishAction::AlreadyPublished,
+            _ => BlockPublishAction::Replace,
+        }
+    }
+
+    /// Make a block volume's target path ready for its device symlink.
+    ///
+    /// Creates the parent directory if needed. A file in the way of the
+    /// parent, or a directory or file at the target path itself, fails with
+    /// `FAILED_PRECONDITION` naming the path to fix, rather than the bare
+    /// `create_dir_all`/`symlink` error. With `remove_empty_dir` an empty
+    /// directory at the target path is removed instead.
+    async fn prepare_block_target(
+        target_path: &Path,
+        remove_empty_dir: bool,
+    ) -> Result<(), Status> {
+        if let Some(parent) = target_path.parent() {
+            // The first ancestor that exists must be a directory
+            for ancestor in parent.ancestors() {
+                match tokio::fs::metadata(ancestor).await {
+                    Ok(meta) if meta.is_dir() => break,
+                    Ok(_) => {
+                        return Err(Status::failed_precondition(format!(
+                            "cannot create the parent directory of target path {}: {} exists \
+                             but is not a directory; remove it and retry",
+                            target_path.display(),
+                            ancestor.display()
+                        )));
+                    }
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory
+                        ) => {}
+                    Err(e) => {
+                        return Err(Status::internal(format!(
+                            "Failed to inspect {}: {}",
+                            ancestor.display(),
+                            e
+                        )));
+                    }
+                }
+            }
+            tokio::fs::create_dir_all(parent).await.map_err(|e| {
+                error!(error = %e, path = ?parent, "Failed to create parent directory");
+                Status::internal(format!("Failed to create parent directory: {}", e))
+            })?;
+        }
+
+        let meta = match tokio::fs::symlink_metadata(target_path).await {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => {
+                return Err(Status::internal(format!(
+                    "Failed to inspect target path {}: {}",
+                    target_path.display(),
+                    e
+                )));
+            }
+        };
+        if !meta.is_dir() {
+            return Err(Status::failed_precondition(format!(
+                "target path {} exists and is not a symlink; block volumes are published as \
+                 a symlink to the device, remove it and retry",
+                target_path.display()
+            )));
+        }
+
+        let empty = match tokio::fs::read_dir(target_path).await {
+            Ok(mut entries) => matches!(entries.next_entry().await, Ok(None)),
+            Err(_) => false,
+        };
+        if empty && remove_empty_dir {
+            warn!(target_path = %target_path.display(), "Removing empty directory at block target path");
+            return tokio::fs::remove_dir(target_path).await.map_err(|e| {
+                Status::internal(format!(
+                    "Failed to remove directory {}: {}",
+                    target_path.display(),
+                    e
+                ))
+            });
+        }
+        Err(Status::failed_precondition(format!(
+            "target path {} is a {}directory, but block volumes are published as a symlink \
+             to the device; remove it{}",
+            target_path.display(),
+            if empty { "" } else { "non-empty " },
+            if empty {
+                " or enable --remove-empty-block-target-dir"
+            } else {
+                ""
+            }
+        )))
+    }
+}
+
+/// State of the staging mount when publishing a filesystem volume
+#[derive(Debug, PartialEq, Eq)]
+enum StagingCheck {
+    /// Staging path is mounted
+    Staged,
+    /// Staging mount is gone but the session is up; remount it
+    Restage,
+    /// Staging mount is gone and cannot be recovered here
+    NotStaged,
+}
+
+/// What NodeStageVolume does when the staging path is already mounted from
+/// a device other than the volume's session device
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StaleMountPolicy {
+    /// Unmount the stale mount and stage the volume
+    #[default]
+    Remount,
+    /// Fail with FailedPrecondition, leaving the mount for an operator
+    Fail,
+}
+
+impl fmt::Display for StaleMountPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StaleMountPolicy::Remount => write!(f, "remount"),
+            StaleMountPolicy::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+impl FromStr for StaleMountPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "remount" => Ok(StaleMountPolicy::Remount),
+            "fail" => Ok(StaleMountPolicy::Fail),
+            _ => Err(format!(
+                "invalid stale mount policy '{}': expected remount or fail",
+                s
+            )),
+        }
+    }
+}
+
+/// What NodeStageVolume does when the volume context has no `targetName`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingTargetNamePolicy {
+    /// Derive the name from `exportType` and the volume ID
+    #[default]
+    Derive,
+    /// Fail with InvalidArgument
+    Fail,
+}
+
+impl fmt::Display for MissingTargetNamePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MissingTargetNamePolicy::Derive => write!(f, "derive"),
+            MissingTargetNamePolicy::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+impl FromStr for MissingTargetNamePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "derive" => Ok(MissingTargetNamePolicy::Derive),
+            "fail" => Ok(MissingTargetNamePolicy::Fail),
+            _ => Err(format!(
+                "invalid missing target name policy '{}': expected derive or fail",
+                s
+            )),
+        }
+    }
+}
+
+/// Action for a mount already present at a filesystem volume's staging path
+#[derive(Debug, PartialEq, Eq)]
+enum ExistingMountAction {
+    /// The volume's own device is mounted; nothing to do
+    AlreadyStaged,
+    /// Another device is mounted; unmount it and stage
+    Remount,
+    /// Another device is mounted; refuse to stage
+    Conflict,
+    /// The session is up but the mount source or its device does not
+    /// resolve; retry later
+    Unresolved,
+}
+
+/// Action for an existing target path when publishing a block volume
+#[derive(Debug, PartialEq, Eq)]
+enum BlockPublishAction {
+    /// No symlink at the target path yet
+    Create,
+    /// Symlink already points at the session's current device
+    AlreadyPublished,
+    /// Symlink is stale and must be replaced
+    Replace,
+}
+
+#[tonic::async_trait]
+impl csi::node_server::Node for NodeService {
+    /// Stage a volume to a staging path.
+    ///
+    /// For filesystem volumes: connects to iSCSI/NVMeoF target, formats if needed, and mounts.
+    /// For block volumes: connects to target and stores device path (no mount).
+    async fn node_stage_volume(
+        &self,
+        request: Request<csi::NodeStageVolumeRequest>,
+    ) -> Result<Response<csi::NodeStageVolumeResponse>, Status> {
+        let _permit = node_limit::acquire(
+            &self.op_limiter,
+            self.saturation_policy,
+            "node_stage_volume",
+        )
+        .await?;
+        let req = request.into_inner();
+        let volume_id = &req.volume_id;
+        let staging_target_path = &req.staging_target_path;
+        let volume_context = &req.volume_context;
+        let is_block = Self::is_block_volume(&req.volume_capability);
+
+        if volume_id.is_empty() {
+            return Err(Status::invalid_argument("Volume ID is required"));
+        }
+
+        if staging_target_path.is_empty() {
+            return Err(Status::invalid_argument("Staging target path is required"));
+        }
+
+        Self::validate_path(staging_target_path)?;
+
+        info!(
+            volume_id = %volume_id,
+            staging_target_path = %staging_target_path,
+            is_block = %is_block,
+            "NodeStageVolume request"
+        );
+
+        // Resolve mount and format options before connecting so a conflict
+        // fails fast
+        let mount_options =
+            Self::staging_mount_options(volume_id, &req.volume_capability, volume_context)?;
+        Self::staging_format_options(&req.volume_capability, volume_context)?;
+
+        // Get volume context parameters
+        let target_name =
+            Self::staging_target_name(volume_id, volume_context, self.missing_target_name)?;
+
+        Self::validate_target_name(&target_name)?;
+
+        let export_type: ExportType = volume_context
+            .get("exportType")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default();
+
+        // Staging works with any target name, but unstaging can only find the
+        // session under our own prefix; flag the misconfiguration loudly now
+        if let Some(expected) = Self::target_prefix_mismatch(export_type, &target_name) {
+            error!(
+                volume_id = %volume_id,
+                target = %target_name,
+                expected_prefix = %expected,
+                "Target name does not use the node's {} prefix; NodeUnstageVolume will not \
+                 find this session. The agent's --base-iqn/--base-nqn must match the node.",
+                export_type
+            );
+            metrics::record_target_prefix_mismatch(&export_type.to_string());
+        }
+
+        // Parse all endpoints for multipath support. ControllerPublishVolume
+        // hands them over in publish_context; volume_context is the fallback
+        // for volumes attached without a controller publish.
+        let endpoints_source = if req.publish_context.contains_key("endpoints") {
+            &req.publish_context
+        } else {
+            volume_context
+        };
+        let endpoints = Self::parse_endpoints(endpoints_source, export_type)?;
+
+        debug!(
+            volume_id = %volume_id,
+            endpoints = %endpoints.to_portal_string(),
+            multipath = endpoints.is_multipath(),
+            "Parsed endpoints for staging"
+        );
+
+        // Check if already staged
+        if is_block {
+            // Block volume: check if target session is active
+            if self.is_block_volume_staged(volume_id).await {
+                info!(volume_id = %volume_id, "Block volume already staged (session active)");
+                self.set_volume_staged(volume_id, true);
+                return Ok(Response::new(csi::NodeStageVolumeResponse {}));
+            }
+        } else {
+            // Mount volume: check if mounted, and from the right device
+            if platform::is_mounted(staging_target_path).await? {
+                match self
+                    .check_existing_staging_mount(volume_id, staging_target_path)
+                    .await?
+                {
+                    ExistingMountAction::AlreadyStaged => {
+                        info!(staging_target_path = %staging_target_path, "Volume already staged");
+                        self.set_volume_staged(volume_id, true);
+                        return Ok(Response::new(csi::NodeStageVolumeResponse {}));
+                    }
+                    ExistingMountAction::Remount => {
+                        platform::unmount(staging_target_path).await?;
+                    }
+                    ExistingMountAction::Conflict => {
+                        return Err(Status::failed_precondition(format!(
+                            "staging path {} is mounted from a device that does not belong \
+                             to volume {}; unmount it before staging",
+                            staging_target_path, volume_id
+                        )));
+                    }
+                    ExistingMountAction::Unresolved => {
+                        return Err(Status::unavailable(format!(
+                            "staging path {} is mounted, but its device or the session device \
+                             of volume {} does not resolve yet; retry",
+                            staging_target_path, volume_id
+                        )));
+                    }
+                }
+            }
+        }
+
+        // Connect, format and mount, starting over from a clean slate when
+        // a transient failure leaves a partial session behind
+        retry_stage(
+            volume_id,
+            self.stage_retry,
+            |_attempt| {
+                self.stage_once(
+                    &req,
+                    &target_name,
+                    export_type,
+                    endpoints.clone(),
+                    &mount_options,
+                    is_block,
+                )
+            },
+            || self.reset_partial_stage(volume_id, staging_target_path),
+        )
+        .await?;
+
+        self.set_volume_staged(volume_id, true);
         Ok(Response::new(csi::NodeStageVolumeResponse {}))
     }
 
@@ -674,11 +1884,17 @@ impl csi::node_server::Node for NodeService {
         }
         // Block volumes have no mount to clean up
 
+        // Stop path maintenance before disconnecting so it can't log back in
+        if let Some(targets) = &self.staged_targets {
+            targets.remove(volume_id).await;
+        }
+
         // Disconnect any iSCSI/NVMeoF targets for this volume.
         // Target names are derived from volume_id using our naming convention.
         // IMPORTANT: We must return error if disconnect fails - lying to Kubernetes
         // about the disconnect state can cause data corruption (zombie LUNs).
-        Self::disconnect_volume_targets(volume_id).await?;
+        self.disconnect_volume_targets(volume_id).await?;
+        self.set_volume_staged(volume_id, false);
 
         info!(
             volume_id = %volume_id,
@@ -697,6 +1913,12 @@ impl csi::node_server::Node for NodeService {
         &self,
         request: Request<csi::NodePublishVolumeRequest>,
     ) -> Result<Response<csi::NodePublishVolumeResponse>, Status> {
+        let _permit = node_limit::acquire(
+            &self.op_limiter,
+            self.saturation_policy,
+            "node_publish_volume",
+        )
+        .await?;
         let req = request.into_inner();
         let volume_id = &req.volume_id;
         let target_path = &req.target_path;
@@ -732,30 +1954,48 @@ impl csi::node_server::Node for NodeService {
 
         if is_block {
             // Block volume: query device from active session and create symlink
-            let device = Self::find_block_device(volume_id).await?;
+            let device = self.find_block_device(volume_id).await?;
+
+            // Check if already published. After a pod restart the session can
+            // survive while a reconnect renamed the device (e.g. sdb -> sdc),
+            // leaving a symlink that dangles or points at another device.
+            let existing_link = tokio::fs::read_link(target_path).await.ok();
+            let action = match existing_link.as_deref() {
+                None => BlockPublishAction::Create,
+                Some(link) => {
+                    let link_resolved = tokio::fs::canonicalize(target_path).await.ok();
+                    let device_resolved = tokio::fs::canonicalize(&device).await.ok();
+                    Self::block_publish_action(
+                        link,
+                        link_resolved.as_deref(),
+                        Path::new(&device),
+                        device_resolved.as_deref(),
+                    )
+                }
+            };
 
-            // Check if already published (symlink exists and points to same device)
-            if let Ok(existing) = tokio::fs::read_link(target_path).await {
-                if existing.to_string_lossy() == device {
+            match action {
+                BlockPublishAction::AlreadyPublished => {
                     info!(target_path = %target_path, "Block volume already published");
                     return Ok(Response::new(csi::NodePublishVolumeResponse {}));
                 }
-                // Remove stale symlink
-                tokio::fs::remove_file(target_path).await.map_err(|e| {
-                    error!(error = %e, path = %target_path, "Failed to remove stale symlink");
-                    Status::internal(format!("Failed to remove stale symlink: {}", e))
-                })?;
+                BlockPublishAction::Replace => {
+                    warn!(
+                        target_path = %target_path,
+                        stale_link = ?existing_link,
+                        device = %device,
+                        "Replacing stale block device symlink"
+                    );
+                    tokio::fs::remove_file(target_path).await.map_err(|e| {
+                        error!(error = %e, path = %target_path, "Failed to remove stale symlink");
+                        Status::internal(format!("Failed to remove stale symlink: {}", e))
+                    })?;
+                }
+                BlockPublishAction::Create => {}
             }
 
-            // Create parent directory if needed
-            if let Some(parent) = Path::new(target_path).parent()
-                && !tokio::fs::try_exists(parent).await.unwrap_or(false)
-            {
-                tokio::fs::create_dir_all(parent).await.map_err(|e| {
-                    error!(error = %e, path = ?parent, "Failed to create parent directory");
-                    Status::internal(format!("Failed to create parent directory: {}", e))
-                })?;
-            }
+            Self::prepare_block_target(Path::new(target_path), self.remove_empty_block_target_dir)
+                .await?;
 
             // Create symlink to device
             tokio::fs::symlink(&device, target_path).await.map_err(|e| {
@@ -771,12 +2011,34 @@ impl csi::node_server::Node for NodeService {
             );
         } else {
             // Mount volume: bind mount from staging
-            // Check if staging path is mounted
-            if !platform::is_mounted(staging_target_path).await? {
-                return Err(Status::failed_precondition(format!(
-                    "Volume not staged at {}",
-                    staging_target_path
-                )));
+            // Check if staging path is mounted. Only probe the session when
+            // the mount is missing and auto-restage could act on it.
+            let staged = platform::is_mounted(staging_target_path).await?;
+            let session_active =
+                !staged && self.auto_restage && self.is_block_volume_staged(volume_id).await;
+
+            match Self::staging_check(staged, session_active, self.auto_restage) {
+                StagingCheck::Staged => {}
+                StagingCheck::Restage => {
+                    warn!(
+                        volume_id = %volume_id,
+                        staging_target_path = %staging_target_path,
+                        "Staging mount lost but session is active, re-staging"
+                    );
+                    self.restage_filesystem(
+                        volume_id,
+                        staging_target_path,
+                        &req.volume_capability,
+                        &req.volume_context,
+                    )
+                    .await?;
+                }
+                StagingCheck::NotStaged => {
+                    return Err(Status::failed_precondition(format!(
+                        "Volume not staged at {}",
+                        staging_target_path
+                    )));
+                }
             }
 
             // Check if already published
@@ -921,6 +2183,20 @@ impl csi::node_server::Node for NodeService {
                     },
                 )),
             },
+            csi::NodeServiceCapability {
+                r#type: Some(csi::node_service_capability::Type::Rpc(
+                    csi::node_service_capability::Rpc {
+                        r#type: csi::node_service_capability::rpc::Type::GetVolumeStats as i32,
+                    },
+                )),
+            },
+            csi::NodeServiceCapability {
+                r#type: Some(csi::node_service_capability::Type::Rpc(
+                    csi::node_service_capability::Rpc {
+                        r#type: csi::node_service_capability::rpc::Type::VolumeCondition as i32,
+                    },
+                )),
+            },
         ];
 
         Ok(Response::new(csi::NodeGetCapabilitiesResponse {
@@ -941,64 +2217,564 @@ impl csi::node_server::Node for NodeService {
         &self,
         request: Request<csi::NodeExpandVolumeRequest>,
     ) -> Result<Response<csi::NodeExpandVolumeResponse>, Status> {
+        let _permit = node_limit::acquire(
+            &self.op_limiter,
+            self.saturation_policy,
+            "node_expand_volume",
+        )
+        .await?;
+        let req = request.into_inner();
+        let volume_id = &req.volume_id;
+        let volume_path = &req.volume_path;
+
+        if volume_id.is_empty() {
+            return Err(Status::invalid_argument("Volume ID is required"));
+        }
+
+        if volume_path.is_empty() {
+            return Err(Status::invalid_argument("Volume path is required"));
+        }
+
+        Self::validate_path(volume_path)?;
+
+        info!(
+            volume_id = %volume_id,
+            volume_path = %volume_path,
+            "NodeExpandVolume request"
+        );
+
+        // Detect filesystem type and resize if needed
+        let fs_type = Self::detect_filesystem_type(volume_path).await?;
+        debug!(volume_id = %volume_id, fs_type = %fs_type, "Detected filesystem type");
+
+        // CSI retries NodeExpandVolume; skip the resize once the filesystem
+        // already covers the whole device
+        if Self::is_already_expanded(volume_path, &fs_type).await == Some(true) {
+            let capacity_bytes = Self::get_volume_capacity(volume_path).await?;
+            info!(
+                volume_id = %volume_id,
+                capacity_bytes = capacity_bytes,
+                "Filesystem already spans the device, nothing to expand"
+            );
+            return Ok(Response::new(csi::NodeExpandVolumeResponse {
+                capacity_bytes,
+            }));
+        }
+
+        // Perform filesystem-specific resize
+        let resized = Self::resize_filesystem(volume_path, &fs_type).await?;
+        if resized {
+            info!(volume_id = %volume_id, fs_type = %fs_type, "Filesystem resized successfully");
+        } else {
+            debug!(volume_id = %volume_id, fs_type = %fs_type, "Filesystem resize not needed or automatic");
+        }
+
+        // Get final capacity after resize
+        let capacity_bytes = Self::get_volume_capacity(volume_path).await?;
+
+        info!(
+            volume_id = %volume_id,
+            capacity_bytes = capacity_bytes,
+            "Volume expansion completed"
+        );
+
+        Ok(Response::new(csi::NodeExpandVolumeResponse {
+            capacity_bytes,
+        }))
+    }
+
+    /// Report volume capacity usage and condition.
+    ///
+    /// Filesystem volumes report bytes and inodes from `df`; block volumes
+    /// report the device size. With `--volume-io-stats` the device's I/O
+    /// counters are also exported as per-volume metrics, since the CSI
+    /// response has no field for them.
+    ///
+    /// The volume is abnormal when its staging or target path is no longer
+    /// mounted, or when the mount lingers after the iSCSI/NVMeoF session
+    /// dropped; a block volume is abnormal when its symlink does not resolve
+    /// to its session's device. Usage is left out for an abnormal volume: `df` on a mount
+    /// whose device is gone can block until the kernel gives up on it.
+    async fn node_get_volume_stats(
+        &self,
+        request: Request<csi::NodeGetVolumeStatsRequest>,
+    ) -> Result<Response<csi::NodeGetVolumeStatsResponse>, Status> {
         let req = request.into_inner();
         let volume_id = &req.volume_id;
         let volume_path = &req.volume_path;
 
-        if volume_id.is_empty() {
-            return Err(Status::invalid_argument("Volume ID is required"));
-        }
+        if volume_id.is_empty() {
+            return Err(Status::invalid_argument("Volume ID is required"));
+        }
+
+        if volume_path.is_empty() {
+            return Err(Status::invalid_argument("Volume path is required"));
+        }
+
+        Self::validate_path(volume_path)?;
+
+        let metadata = tokio::fs::metadata(volume_path).await.map_err(|e| {
+            Status::not_found(format!("Volume path {} not found: {}", volume_path, e))
+        })?;
+
+        let is_block = std::os::unix::fs::FileTypeExt::is_block_device(&metadata.file_type());
+        let condition = self
+            .check_volume_condition(volume_id, &req.staging_target_path, volume_path, is_block)
+            .await?;
+        if condition.abnormal {
+            warn!(
+                volume_id = %volume_id,
+                message = %condition.message,
+                "Volume condition is abnormal"
+            );
+            return Ok(Response::new(csi::NodeGetVolumeStatsResponse {
+                usage: Vec::new(),
+                volume_condition: Some(condition),
+            }));
+        }
+
+        let usage = if is_block {
+            let total = volume_stats::block_device_size(volume_path).await?;
+            vec![csi::VolumeUsage {
+                total: total as i64,
+                unit: csi::volume_usage::Unit::Bytes as i32,
+                ..Default::default()
+            }]
+        } else {
+            let fs = volume_stats::filesystem_usage(volume_path).await?;
+            vec![
+                csi::VolumeUsage {
+                    available: fs.available_bytes as i64,
+                    total: fs.total_bytes as i64,
+                    used: fs.used_bytes as i64,
+                    unit: csi::volume_usage::Unit::Bytes as i32,
+                },
+                csi::VolumeUsage {
+                    available: fs.available_inodes as i64,
+                    total: fs.total_inodes as i64,
+                    used: fs.used_inodes as i64,
+                    unit: csi::volume_usage::Unit::Inodes as i32,
+                },
+            ]
+        };
+
+        if self.volume_io_stats {
+            self.record_volume_io(volume_id, volume_path, is_block)
+                .await;
+        }
+
+        Ok(Response::new(csi::NodeGetVolumeStatsResponse {
+            usage,
+            volume_condition: Some(condition),
+        }))
+    }
+}
+
+/// Volume condition from the mount and session checks.
+///
+/// `unmounted` is the first of the volume's paths found not mounted.
+fn volume_condition(unmounted: Option<&str>, session_active: bool) -> csi::VolumeCondition {
+    let message = match (unmounted, session_active) {
+        (Some(path), _) => format!("{} is no longer mounted", path),
+        (None, false) => {
+            "Volume is mounted but its iSCSI/NVMeoF session is gone; I/O to it will fail"
+                .to_string()
+        }
+        (None, true) => {
+            return csi::VolumeCondition {
+                abnormal: false,
+                message: "Volume is mounted and its session is active".to_string(),
+            };
+        }
+    };
+    csi::VolumeCondition {
+        abnormal: true,
+        message,
+    }
+}
+
+/// Condition of a block volume published at `path`.
+///
+/// `link_resolved` is what the published symlink resolves to and
+/// `device_resolved` the canonical device of the volume's active session
+/// (`None` without a session).
+fn block_volume_condition(
+    path: &str,
+    link_resolved: Option<&Path>,
+    device_resolved: Option<&Path>,
+) -> csi::VolumeCondition {
+    let message = match (link_resolved, device_resolved) {
+        (_, None) => "Block volume's iSCSI/NVMeoF session is gone; I/O to it will fail".to_string(),
+        (Some(link), Some(device)) if link == device => {
+            return csi::VolumeCondition {
+                abnormal: false,
+                message: "Block volume resolves to its session device".to_string(),
+            };
+        }
+        (Some(link), Some(device)) => format!(
+            "{} resolves to {}, not to the session device {}",
+            path,
+            link.display(),
+            device.display()
+        ),
+        (None, Some(device)) => format!(
+            "{} no longer resolves; the session device is {}",
+            path,
+            device.display()
+        ),
+    };
+    csi::VolumeCondition {
+        abnormal: true,
+        message,
+    }
+}
+
+/// Whether a filesystem of `fs_bytes` can grow into a `device_bytes` device.
+///
+/// Filesystems only grow in whole blocks, so a tail smaller than one block
+/// does not count as room to expand.
+fn needs_expansion(fs_bytes: u64, device_bytes: u64, block_size: u64) -> bool {
+    device_bytes.saturating_sub(fs_bytes) >= block_size.max(1)
+}
+
+/// Filesystem size and block size from `dumpe2fs -h` output
+fn parse_dumpe2fs_size(output: &str) -> Option<(u64, u64)> {
+    let field = |name: &str| {
+        output.lines().find_map(|line| {
+            line.strip_prefix(name)?
+                .strip_prefix(':')?
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+    };
+    let block_count = field("Block count")?;
+    let block_size = field("Block size")?;
+    Some((block_count * block_size, block_size))
+}
+
+/// Data section size and block size from `xfs_info` output
+///
+/// The relevant line looks like:
+/// `data     =                       bsize=4096   blocks=262144, imaxpct=25`
+fn parse_xfs_info_size(output: &str) -> Option<(u64, u64)> {
+    let line = output
+        .lines()
+        .find(|line| line.trim_start().starts_with("data"))?;
+    let field = |name: &str| {
+        line.split(|c: char| c.is_whitespace() || c == ',')
+            .find_map(|token| token.strip_prefix(name)?.parse::<u64>().ok())
+    };
+    let block_size = field("bsize=")?;
+    let blocks = field("blocks=")?;
+    Some((blocks * block_size, block_size))
+}
+
+/// IDs of the volumes staged on this node, given the connected iSCSI
+/// targets and NVMeoF subsystems and the grouped namespace records.
+fn staged_volume_ids(
+    iscsi_targets: &[String],
+    nvmeof_subsystems: &[String],
+    grouped: &[(String, GroupedNamespace)],
+) -> BTreeSet<String> {
+    let own_volume = |name: &str, base: &str| {
+        name.strip_prefix(base)
+            .and_then(|rest| rest.strip_prefix(':'))
+            // Shared controllers (`<base>:group:<name>`) are no volume
+            .filter(|volume_id| !volume_id.is_empty() && !volume_id.contains(':'))
+            .map(str::to_string)
+    };
+
+    let mut volumes: BTreeSet<String> = iscsi_targets
+        .iter()
+        .filter_map(|iqn| own_volume(iqn, BASE_IQN))
+        .chain(
+            nvmeof_subsystems
+                .iter()
+                .filter_map(|nqn| own_volume(nqn, BASE_NQN)),
+        )
+        .collect();
+    volumes.extend(
+        grouped
+            .iter()
+            .filter(|(_, ns)| nvmeof_subsystems.contains(&ns.target_name))
+            .map(|(volume_id, _)| volume_id.clone()),
+    );
+    volumes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_io_counts_growth_of_kernel_counters() {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let node = NodeService::new("node-1".to_string());
+        let stats = |read_ios| volume_stats::DiskStats {
+            read_ios,
+            ..Default::default()
+        };
+
+        ::metrics::with_local_recorder(&recorder, || {
+            // Not staged here: nothing to publish
+            node.count_volume_io("pvc-io-1", &stats(7));
+            node.set_volume_staged("pvc-io-2", true);
+            node.count_volume_io("pvc-io-2", &stats(7));
+            node.count_volume_io("pvc-io-2", &stats(10));
+        });
+        let rendered = handle.render();
+        assert!(!rendered.contains(r#"volume_id="pvc-io-1""#));
+        assert!(rendered.contains("# TYPE csi_volume_read_ops_total counter"));
+        assert!(rendered.contains(r#"csi_volume_read_ops_total{volume_id="pvc-io-2"} 10"#));
+
+        // The device was reconnected and its counters restarted
+        ::metrics::with_local_recorder(&recorder, || node.count_volume_io("pvc-io-2", &stats(4)));
+        let rendered = handle.render();
+        assert!(rendered.contains(r#"csi_volume_read_ops_total{volume_id="pvc-io-2"} 14"#));
+
+        // Unstaged volumes are no longer counted
+        ::metrics::with_local_recorder(&recorder, || {
+            node.set_volume_staged("pvc-io-2", false);
+            node.count_volume_io("pvc-io-2", &stats(20));
+        });
+        let rendered = handle.render();
+        assert!(rendered.contains(r#"csi_volume_staged{volume_id="pvc-io-2"} 0"#));
+        assert!(rendered.contains(r#"csi_volume_read_ops_total{volume_id="pvc-io-2"} 14"#));
+
+        // Restaged on the same device: only new I/O counts
+        ::metrics::with_local_recorder(&recorder, || {
+            node.set_volume_staged("pvc-io-2", true);
+            node.count_volume_io("pvc-io-2", &stats(6));
+        });
+        let rendered = handle.render();
+        assert!(rendered.contains(r#"csi_volume_read_ops_total{volume_id="pvc-io-2"} 16"#));
+    }
+
+    #[test]
+    fn test_staged_volume_ids() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let grouped = [
+            (
+                "pvc-db-1".to_string(),
+                GroupedNamespace {
+                    target_name: format!("{}:group:db", BASE_NQN),
+                    namespace_id: 1,
+                },
+            ),
+            (
+                "pvc-web-1".to_string(),
+                GroupedNamespace {
+                    target_name: format!("{}:group:web", BASE_NQN),
+                    namespace_id: 1,
+                },
+            ),
+        ];
+
+        let volumes = staged_volume_ids(
+            &names(&[
+                "iqn.2024-01.org.freebsd.csi:pvc-1",
+                "iqn.2024-01.org.freebsd.csi:pvc-1",
+                "iqn.2010-10.org.example:disk",
+            ]),
+            &names(&[
+                "nqn.2024-01.org.freebsd.csi:pvc-2",
+                "nqn.2024-01.org.freebsd.csi:group:db",
+                "nqn.2014-08.org.nvmexpress.discovery",
+            ]),
+            &grouped,
+        );
+        assert_eq!(
+            volumes.into_iter().collect::<Vec<_>>(),
+            ["pvc-1", "pvc-2", "pvc-db-1"]
+        );
+    }
 
-        if volume_path.is_empty() {
-            return Err(Status::invalid_argument("Volume path is required"));
-        }
+    #[test]
+    fn test_expects_read_only_mount() {
+        use csi::volume_capability::access_mode::Mode;
+
+        let capability = |mode: Mode| {
+            Some(csi::VolumeCapability {
+                access_mode: Some(csi::volume_capability::AccessMode { mode: mode as i32 }),
+                access_type: None,
+            })
+        };
 
-        Self::validate_path(volume_path)?;
+        let ro = ["noatime".to_string(), "ro".to_string()];
+
+        assert!(NodeService::expects_read_only_mount(
+            &capability(Mode::MultiNodeReaderOnly),
+            &[]
+        ));
+        assert!(NodeService::expects_read_only_mount(
+            &capability(Mode::SingleNodeReaderOnly),
+            &[]
+        ));
+        assert!(!NodeService::expects_read_only_mount(
+            &capability(Mode::SingleNodeWriter),
+            &[]
+        ));
+        assert!(!NodeService::expects_read_only_mount(&None, &[]));
+        // A writer asking for an ro mount gets one
+        assert!(NodeService::expects_read_only_mount(
+            &capability(Mode::SingleNodeWriter),
+            &ro
+        ));
+        assert!(!NodeService::expects_read_only_mount(
+            &capability(Mode::SingleNodeWriter),
+            &["rw".to_string()]
+        ));
+    }
 
-        info!(
-            volume_id = %volume_id,
-            volume_path = %volume_path,
-            "NodeExpandVolume request"
+    #[test]
+    fn test_staging_mount_options_direct_io() {
+        use csi::volume_capability::{AccessType, BlockVolume, MountVolume};
+
+        let capability = |access_type: AccessType| {
+            Some(csi::VolumeCapability {
+                access_mode: None,
+                access_type: Some(access_type),
+            })
+        };
+        let mount = |fs_type: &str, flags: &[&str]| {
+            capability(AccessType::Mount(MountVolume {
+                fs_type: fs_type.to_string(),
+                mount_flags: flags.iter().map(|f| f.to_string()).collect(),
+                ..Default::default()
+            }))
+        };
+        let mut context = HashMap::new();
+
+        // Mount flags pass through unchanged without directIo
+        assert_eq!(
+            NodeService::staging_mount_options("vol", &mount("ext4", &["noatime"]), &context)
+                .unwrap(),
+            ["noatime"]
         );
 
-        // Detect filesystem type and resize if needed
-        let fs_type = Self::detect_filesystem_type(volume_path).await?;
-        debug!(volume_id = %volume_id, fs_type = %fs_type, "Detected filesystem type");
+        context.insert(DirectIo::PARAM_NAME.to_string(), "true".to_string());
+        assert_eq!(
+            NodeService::staging_mount_options("vol", &mount("ext4", &["noatime"]), &context)
+                .unwrap(),
+            ["noatime", "dioread_nolock"]
+        );
+        assert!(
+            NodeService::staging_mount_options("vol", &mount("xfs", &[]), &context)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            NodeService::staging_mount_options("vol", &mount("ext4", &["data=journal"]), &context)
+                .unwrap_err()
+                .code(),
+            tonic::Code::InvalidArgument
+        );
 
-        // Perform filesystem-specific resize
-        let resized = Self::resize_filesystem(volume_path, &fs_type).await?;
-        if resized {
-            info!(volume_id = %volume_id, fs_type = %fs_type, "Filesystem resized successfully");
-        } else {
-            debug!(volume_id = %volume_id, fs_type = %fs_type, "Filesystem resize not needed or automatic");
-        }
+        // Unsafe flags are refused before anything is mounted
+        assert_eq!(
+            NodeService::staging_mount_options("vol", &mount("ext4", &["noatime;id"]), &context)
+                .unwrap_err()
+                .code(),
+            tonic::Code::InvalidArgument
+        );
 
-        // Get final capacity after resize
-        let capacity_bytes = Self::get_volume_capacity(volume_path).await?;
+        // Block volumes are not mounted
+        assert!(
+            NodeService::staging_mount_options(
+                "vol",
+                &capability(AccessType::Block(BlockVolume {})),
+                &context
+            )
+            .unwrap()
+            .is_empty()
+        );
 
-        info!(
-            volume_id = %volume_id,
-            capacity_bytes = capacity_bytes,
-            "Volume expansion completed"
+        context.insert(DirectIo::PARAM_NAME.to_string(), "maybe".to_string());
+        assert!(NodeService::staging_mount_options("vol", &mount("ext4", &[]), &context).is_err());
+    }
+
+    #[test]
+    fn test_staging_format_options() {
+        use csi::volume_capability::{AccessType, BlockVolume, MountVolume};
+
+        let mount = |fs_type: &str| {
+            Some(csi::VolumeCapability {
+                access_mode: None,
+                access_type: Some(AccessType::Mount(MountVolume {
+                    fs_type: fs_type.to_string(),
+                    ..Default::default()
+                })),
+            })
+        };
+        let mut context = HashMap::new();
+        assert!(
+            NodeService::staging_format_options(&mount("ext4"), &context)
+                .unwrap()
+                .is_empty()
         );
 
-        Ok(Response::new(csi::NodeExpandVolumeResponse {
-            capacity_bytes,
-        }))
+        context.insert(
+            MKFS_OPTIONS_PARAM.to_string(),
+            "-E stride=16,stripe-width=64".to_string(),
+        );
+        assert_eq!(
+            NodeService::staging_format_options(&mount("ext4"), &context).unwrap(),
+            ["-E", "stride=16,stripe-width=64"]
+        );
+        // Checked against the capability's fsType: -E is an ext4 flag
+        assert!(NodeService::staging_format_options(&mount("xfs"), &context).is_err());
+
+        // Block volumes are never formatted
+        let block = Some(csi::VolumeCapability {
+            access_mode: None,
+            access_type: Some(AccessType::Block(BlockVolume {})),
+        });
+        assert!(
+            NodeService::staging_format_options(&block, &context)
+                .unwrap()
+                .is_empty()
+        );
     }
 
-    /// Get volume statistics (not implemented).
-    async fn node_get_volume_stats(
-        &self,
-        _request: Request<csi::NodeGetVolumeStatsRequest>,
-    ) -> Result<Response<csi::NodeGetVolumeStatsResponse>, Status> {
-        Err(Status::unimplemented("NodeGetVolumeStats is not supported"))
+    #[test]
+    fn test_needs_expansion_skips_already_expanded() {
+        let gib = 1024 * 1024 * 1024;
+        // Filesystem already covers the device (up to a partial block)
+        assert!(!needs_expansion(gib, gib, 4096));
+        assert!(!needs_expansion(gib, gib + 1024, 4096));
+        // Device grew by at least one block
+        assert!(needs_expansion(gib, 2 * gib, 4096));
+        assert!(needs_expansion(gib, gib + 4096, 4096));
+        // A filesystem larger than the device never needs growing
+        assert!(!needs_expansion(2 * gib, gib, 4096));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_parse_dumpe2fs_size() {
+        let output = "Filesystem volume name:   <none>\n\
+                      Block count:              262144\n\
+                      Reserved block count:     13107\n\
+                      Block size:               4096\n";
+        assert_eq!(parse_dumpe2fs_size(output), Some((262144 * 4096, 4096)));
+        assert_eq!(parse_dumpe2fs_size("Block size: 4096\n"), None);
+    }
+
+    #[test]
+    fn test_parse_xfs_info_size() {
+        let output = "meta-data=/dev/sdb               isize=512    agcount=4, agsize=65536 blks\n\
+                      \x20        =                       sectsz=512   attr=2, projid32bit=1\n\
+                      data     =                       bsize=4096   blocks=262144, imaxpct=25\n\
+                      \x20        =                       sunit=0      swidth=0 blks\n\
+                      log      =internal log           bsize=4096   blocks=16384, version=2\n";
+        let (bytes, block_size) = parse_xfs_info_size(output).unwrap();
+        assert_eq!((bytes, block_size), (262144 * 4096, 4096));
+
+        // Device grown to 2 GiB needs expansion; same size does not
+        assert!(needs_expansion(bytes, 2 * 1024 * 1024 * 1024, block_size));
+        assert!(!needs_expansion(bytes, bytes, block_size));
+    }
 
     #[test]
     fn test_validate_path_valid() {
@@ -1033,6 +2809,195 @@ mod tests {
         assert!(NodeService::validate_target_name("nqn.2023-01.com.example:nvme.target1").is_ok());
     }
 
+    fn fast_retry(attempts: u32) -> StageRetry {
+        StageRetry {
+            attempts,
+            backoff: Duration::from_millis(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_stage_succeeds_after_transient_failure() {
+        let attempts = std::cell::Cell::new(0);
+        let cleanups = std::cell::Cell::new(0);
+        let result = retry_stage(
+            "vol",
+            fast_retry(3),
+            |attempt| {
+                attempts.set(attempt);
+                let result = if attempt < 3 {
+                    Err(Status::deadline_exceeded("login timed out"))
+                } else {
+                    Ok(())
+                };
+                async move { result }
+            },
+            || {
+                cleanups.set(cleanups.get() + 1);
+                async { Ok(()) }
+            },
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(attempts.get(), 3);
+        // Every failed attempt was cleaned up before the next one
+        assert_eq!(cleanups.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_stage_returns_last_error_when_exhausted() {
+        let attempts = std::cell::Cell::new(0);
+        let err = retry_stage(
+            "vol",
+            fast_retry(2),
+            |attempt| {
+                attempts.set(attempt);
+                async move { Err(Status::internal(format!("attempt {}", attempt))) }
+            },
+            || async { Ok(()) },
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(attempts.get(), 2);
+        assert_eq!(err.message(), "attempt 2");
+    }
+
+    #[tokio::test]
+    async fn test_retry_stage_stops_on_permanent_error_or_failed_cleanup() {
+        // Not transient: no second attempt
+        let attempts = std::cell::Cell::new(0);
+        let err = retry_stage(
+            "vol",
+            fast_retry(3),
+            |attempt| {
+                attempts.set(attempt);
+                async { Err(Status::failed_precondition("mounted read-only")) }
+            },
+            || async { Ok(()) },
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert_eq!(attempts.get(), 1);
+
+        // Leftovers that can't be removed must not be retried on top of
+        let err = retry_stage(
+            "vol",
+            fast_retry(3),
+            |attempt| {
+                attempts.set(attempt);
+                async { Err(Status::internal("mount failed")) }
+            },
+            || async { Err(Status::internal("still connected")) },
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.message(), "mount failed");
+        assert_eq!(attempts.get(), 1);
+
+        // A single attempt never retries
+        assert!(
+            retry_stage(
+                "vol",
+                StageRetry::default(),
+                |attempt| {
+                    attempts.set(attempt);
+                    async { Err(Status::internal("mount failed")) }
+                },
+                || async { unreachable!("no cleanup without a retry") },
+            )
+            .await
+            .is_err()
+        );
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[test]
+    fn test_stage_retry_backoff_doubles() {
+        let retry = StageRetry {
+            attempts: 10,
+            backoff: Duration::from_secs(1),
+        };
+        assert_eq!(retry.delay(1), Duration::from_secs(1));
+        assert_eq!(retry.delay(2), Duration::from_secs(2));
+        assert_eq!(retry.delay(3), Duration::from_secs(4));
+        assert_eq!(retry.delay(9), Duration::from_secs(32));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_disconnect_clears_after_lingering() {
+        let checks = std::cell::Cell::new(0);
+        // Session lingers for two checks, then goes away
+        let cleared = wait_for_disconnect(
+            || {
+                checks.set(checks.get() + 1);
+                let connected = checks.get() <= 2;
+                async move { connected }
+            },
+            DISCONNECT_VERIFY_ATTEMPTS,
+            Duration::from_millis(1),
+        )
+        .await;
+        assert!(cleared);
+        assert_eq!(checks.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_disconnect_gives_up_when_stuck() {
+        let checks = std::cell::Cell::new(0);
+        let cleared = wait_for_disconnect(
+            || {
+                checks.set(checks.get() + 1);
+                async { true }
+            },
+            DISCONNECT_VERIFY_ATTEMPTS,
+            Duration::from_millis(1),
+        )
+        .await;
+        assert!(!cleared);
+        assert_eq!(checks.get(), DISCONNECT_VERIFY_ATTEMPTS);
+    }
+
+    #[test]
+    fn test_target_prefix_mismatch() {
+        let iqn = format!("{}:pvc-1", BASE_IQN);
+        let nqn = format!("{}:pvc-1", BASE_NQN);
+        assert_eq!(
+            NodeService::target_prefix_mismatch(ExportType::Iscsi, &iqn),
+            None
+        );
+        assert_eq!(
+            NodeService::target_prefix_mismatch(ExportType::Nvmeof, &nqn),
+            None
+        );
+
+        // Agent started with a different --base-iqn/--base-nqn
+        assert_eq!(
+            NodeService::target_prefix_mismatch(
+                ExportType::Iscsi,
+                "iqn.2024-01.com.example.storage:pvc-1"
+            ),
+            Some(BASE_IQN)
+        );
+        assert_eq!(
+            NodeService::target_prefix_mismatch(
+                ExportType::Nvmeof,
+                "nqn.2024-01.com.example.storage:pvc-1"
+            ),
+            Some(BASE_NQN)
+        );
+
+        // Prefix must end at the separator, and match the export type
+        assert_eq!(
+            NodeService::target_prefix_mismatch(ExportType::Iscsi, &format!("{}x:pvc-1", BASE_IQN)),
+            Some(BASE_IQN)
+        );
+        assert_eq!(
+            NodeService::target_prefix_mismatch(ExportType::Iscsi, &nqn),
+            Some(BASE_IQN)
+        );
+    }
+
     #[test]
     fn test_validate_target_name_invalid() {
         // Empty
@@ -1084,6 +3049,28 @@ mod tests {
         assert_eq!(endpoints.first().unwrap().port, 4420);
     }
 
+    #[test]
+    fn test_parse_endpoints_context_default_port() {
+        use crate::types::ExportType;
+        let mut ctx = std::collections::HashMap::new();
+        ctx.insert(
+            "endpoints".to_string(),
+            "192.168.1.1,192.168.1.2:3260".to_string(),
+        );
+        ctx.insert(DEFAULT_PORT_CONTEXT_KEY.to_string(), "13260".to_string());
+
+        let endpoints = NodeService::parse_endpoints(&ctx, ExportType::Iscsi).unwrap();
+        assert_eq!(
+            endpoints.to_portal_string(),
+            "192.168.1.1:13260,192.168.1.2:3260"
+        );
+
+        for bad in ["0", "70000", "iscsi"] {
+            ctx.insert(DEFAULT_PORT_CONTEXT_KEY.to_string(), bad.to_string());
+            assert!(NodeService::parse_endpoints(&ctx, ExportType::Iscsi).is_err());
+        }
+    }
+
     #[test]
     fn test_parse_endpoints_multipath() {
         use crate::types::ExportType;
@@ -1301,4 +3288,360 @@ mod tests {
         assert_eq!(creds.secret, "DHHC-1:00:host-secret");
         assert!(creds.ctrl_secret.is_none());
     }
+
+    #[test]
+    fn test_staging_check_mounted_is_staged() {
+        assert_eq!(
+            NodeService::staging_check(true, false, false),
+            StagingCheck::Staged
+        );
+        assert_eq!(
+            NodeService::staging_check(true, true, true),
+            StagingCheck::Staged
+        );
+    }
+
+    #[test]
+    fn test_staging_check_lost_mount_restages_only_when_enabled() {
+        // Node rebooted: session reconnected but staging mount is gone
+        assert_eq!(
+            NodeService::staging_check(false, true, true),
+            StagingCheck::Restage
+        );
+        assert_eq!(
+            NodeService::staging_check(false, true, false),
+            StagingCheck::NotStaged
+        );
+        // No session to remount from
+        assert_eq!(
+            NodeService::staging_check(false, false, true),
+            StagingCheck::NotStaged
+        );
+    }
+
+    #[test]
+    fn test_existing_mount_of_session_device_is_staged() {
+        let device = Path::new("/dev/sdc");
+        for policy in [StaleMountPolicy::Remount, StaleMountPolicy::Fail] {
+            assert_eq!(
+                NodeService::existing_mount_action(Some(device), Some(device), true, policy),
+                ExistingMountAction::AlreadyStaged
+            );
+        }
+    }
+
+    #[test]
+    fn test_existing_mount_of_other_device_is_remediated() {
+        let mounted = Path::new("/dev/sdb");
+        let current = Path::new("/dev/sdc");
+        // Left behind by another volume before a node crash
+        assert_eq!(
+            NodeService::existing_mount_action(
+                Some(mounted),
+                Some(current),
+                true,
+                StaleMountPolicy::Remount
+            ),
+            ExistingMountAction::Remount
+        );
+        assert_eq!(
+            NodeService::existing_mount_action(
+                Some(mounted),
+                Some(current),
+                true,
+                StaleMountPolicy::Fail
+            ),
+            ExistingMountAction::Conflict
+        );
+        // Either device unknown while the session is up: never unmount on a
+        // guess, whatever the policy
+        for policy in [StaleMountPolicy::Remount, StaleMountPolicy::Fail] {
+            for (mount_device, session_device) in
+                [(Some(mounted), None), (None, Some(current)), (None, None)]
+            {
+                assert_eq!(
+                    NodeService::existing_mount_action(mount_device, session_device, true, policy),
+                    ExistingMountAction::Unresolved
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_existing_mount_without_session_is_remediated() {
+        // Node rebooted or crashed: no session, so the mount is not the volume's
+        let mounted = Path::new("/dev/sdb");
+        for mount_device in [Some(mounted), None] {
+            assert_eq!(
+                NodeService::existing_mount_action(
+                    mount_device,
+                    None,
+                    false,
+                    StaleMountPolicy::Remount
+                ),
+                ExistingMountAction::Remount
+            );
+            assert_eq!(
+                NodeService::existing_mount_action(
+                    mount_device,
+                    None,
+                    false,
+                    StaleMountPolicy::Fail
+                ),
+                ExistingMountAction::Conflict
+            );
+        }
+    }
+
+    #[test]
+    fn test_volume_condition() {
+        let healthy = volume_condition(None, true);
+        assert!(!healthy.abnormal);
+
+        let session_lost = volume_condition(None, false);
+        assert!(session_lost.abnormal);
+        assert!(session_lost.message.contains("session is gone"));
+
+        // A missing mount is reported whatever the session state
+        for session_active in [true, false] {
+            let unmounted = volume_condition(Some("/staging/pvc-1"), session_active);
+            assert!(unmounted.abnormal);
+            assert_eq!(unmounted.message, "/staging/pvc-1 is no longer mounted");
+        }
+    }
+
+    #[test]
+    fn test_block_volume_condition() {
+        let sdb = Path::new("/dev/sdb");
+        let healthy = block_volume_condition("/pods/vol", Some(sdb), Some(sdb));
+        assert!(!healthy.abnormal);
+
+        let renamed = block_volume_condition("/pods/vol", Some(sdb), Some(Path::new("/dev/sdc")));
+        assert!(renamed.abnormal);
+        assert!(
+            renamed
+                .message
+                .contains("not to the session device /dev/sdc")
+        );
+
+        assert!(block_volume_condition("/pods/vol", None, Some(sdb)).abnormal);
+        for link in [Some(sdb), None] {
+            let session_lost = block_volume_condition("/pods/vol", link, None);
+            assert!(session_lost.abnormal);
+            assert!(session_lost.message.contains("session is gone"));
+        }
+    }
+
+    #[test]
+    fn test_stale_mount_policy_parse() {
+        assert_eq!(StaleMountPolicy::default(), StaleMountPolicy::Remount);
+        assert_eq!(
+            "Fail".parse::<StaleMountPolicy>(),
+            Ok(StaleMountPolicy::Fail)
+        );
+        assert_eq!(
+            "remount".parse::<StaleMountPolicy>(),
+            Ok(StaleMountPolicy::Remount)
+        );
+        assert!("ignore".parse::<StaleMountPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_staging_target_name() {
+        let context = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let derive = MissingTargetNamePolicy::Derive;
+
+        // An explicit name wins, even one under another prefix
+        let explicit = context(&[
+            ("targetName", "iqn.2024-01.com.example:pvc-1"),
+            ("exportType", "nvmeof"),
+        ]);
+        assert_eq!(
+            NodeService::staging_target_name("pvc-1", &explicit, derive).unwrap(),
+            "iqn.2024-01.com.example:pvc-1"
+        );
+
+        assert_eq!(
+            NodeService::staging_target_name("pvc-1", &context(&[("exportType", "iscsi")]), derive)
+                .unwrap(),
+            format!("{}:pvc-1", BASE_IQN)
+        );
+        assert_eq!(
+            NodeService::staging_target_name(
+                "pvc-1",
+                &context(&[("exportType", "nvmeof")]),
+                derive
+            )
+            .unwrap(),
+            format!("{}:pvc-1", BASE_NQN)
+        );
+
+        // Nothing to derive from, or deriving disabled
+        for (ctx, policy) in [
+            (context(&[]), derive),
+            (context(&[("exportType", "fc")]), derive),
+            (
+                context(&[("exportType", "iscsi")]),
+                MissingTargetNamePolicy::Fail,
+            ),
+        ] {
+            let err = NodeService::staging_target_name("pvc-1", &ctx, policy).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[test]
+    fn test_missing_target_name_policy_parse() {
+        assert_eq!(
+            MissingTargetNamePolicy::default(),
+            MissingTargetNamePolicy::Derive
+        );
+        assert_eq!(
+            "FAIL".parse::<MissingTargetNamePolicy>(),
+            Ok(MissingTargetNamePolicy::Fail)
+        );
+        assert!("guess".parse::<MissingTargetNamePolicy>().is_err());
+    }
+
+    /// Fresh scratch directory for block target tests
+    fn block_target_root(name: &str) -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!(
+            "csi-block-target-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[tokio::test]
+    async fn test_prepare_block_target_creates_parent() {
+        let root = block_target_root("parent");
+        let target = root.join("pods/pvc-1/dev");
+        NodeService::prepare_block_target(&target, false)
+            .await
+            .unwrap();
+        assert!(root.join("pods/pvc-1").is_dir());
+        assert!(!target.exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_prepare_block_target_parent_is_a_file() {
+        let root = block_target_root("parent-file");
+        std::fs::write(root.join("pods"), b"").unwrap();
+        let target = root.join("pods/pvc-1/dev");
+
+        let err = NodeService::prepare_block_target(&target, true)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(
+            err.message().contains(&format!(
+                "{} exists but is not a directory",
+                root.join("pods").display()
+            )),
+            "{}",
+            err.message()
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_prepare_block_target_is_a_directory() {
+        let root = block_target_root("target-dir");
+        let target = root.join("dev");
+        std::fs::create_dir(&target).unwrap();
+
+        // An empty directory is only removed when enabled
+        let err = NodeService::prepare_block_target(&target, false)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(err.message().contains("--remove-empty-block-target-dir"));
+        assert!(target.is_dir());
+        NodeService::prepare_block_target(&target, true)
+            .await
+            .unwrap();
+        assert!(!target.exists());
+
+        // A non-empty directory is never removed
+        std::fs::create_dir(&target).unwrap();
+        std::fs::write(target.join("data"), b"x").unwrap();
+        let err = NodeService::prepare_block_target(&target, true)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(err.message().contains("non-empty directory"));
+        assert!(target.join("data").exists());
+
+        // Neither is a regular file
+        let file = root.join("file");
+        std::fs::write(&file, b"").unwrap();
+        let err = NodeService::prepare_block_target(&file, true)
+            .await
+            .unwrap_err();
+        assert!(err.message().contains("is not a symlink"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_auto_restage_defaults_off() {
+        assert!(!NodeService::new("node-1".to_string()).auto_restage);
+        assert!(
+            NodeService::new("node-1".to_string())
+                .with_auto_restage(true)
+                .auto_restage
+        );
+    }
+
+    #[test]
+    fn test_block_publish_action_device_renamed_replaces_link() {
+        // Reconnect renamed the device: the old name now belongs to another disk
+        let action = NodeService::block_publish_action(
+            Path::new("/dev/sdb"),
+            Some(Path::new("/dev/sdb")),
+            Path::new("/dev/sdc"),
+            Some(Path::new("/dev/sdc")),
+        );
+        assert_eq!(action, BlockPublishAction::Replace);
+    }
+
+    #[test]
+    fn test_block_publish_action_dangling_link_replaced() {
+        // Old device node vanished after reconnect
+        let action = NodeService::block_publish_action(
+            Path::new("/dev/nvme0n1"),
+            None,
+            Path::new("/dev/nvme1n1"),
+            Some(Path::new("/dev/nvme1n1")),
+        );
+        assert_eq!(action, BlockPublishAction::Replace);
+    }
+
+    #[test]
+    fn test_block_publish_action_same_device_is_published() {
+        let action = NodeService::block_publish_action(
+            Path::new("/dev/sdc"),
+            Some(Path::new("/dev/sdc")),
+            Path::new("/dev/sdc"),
+            Some(Path::new("/dev/sdc")),
+        );
+        assert_eq!(action, BlockPublishAction::AlreadyPublished);
+
+        // Multipath alias resolving to the same dm node is also current
+        let action = NodeService::block_publish_action(
+            Path::new("/dev/mapper/mpatha"),
+            Some(Path::new("/dev/dm-0")),
+            Path::new("/dev/dm-0"),
+            Some(Path::new("/dev/dm-0")),
+        );
+        assert_eq!(action, BlockPublishAction::AlreadyPublished);
+    }
 }
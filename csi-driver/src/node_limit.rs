@@ -0,0 +1,122 @@
+//! Concurrency limit for node operations.
+//!
+//! A burst of pod starts makes kubelet call NodeStageVolume/NodePublishVolume
+//! for many volumes at once, each spawning `iscsiadm`, `nvme` and `mkfs`
+//! processes. A [`Limiter`], the type the agent bounds its copies with, caps
+//! how many of these run at the same time. When all slots are busy an
+//! operation either waits for one or fails with `ResourceExhausted` so
+//! kubelet retries it later.
+
+use std::fmt;
+use std::str::FromStr;
+
+use csi_common::limit::{Limiter, Permit};
+use tonic::Status;
+use tracing::warn;
+
+use crate::metrics;
+
+/// Default number of concurrent node operations
+pub const DEFAULT_NODE_MAX_CONCURRENT_OPS: usize = 10;
+
+/// What an operation does when every slot is taken
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SaturationPolicy {
+    /// Wait for a slot to free up
+    #[default]
+    Queue,
+    /// Fail immediately with ResourceExhausted
+    Reject,
+}
+
+impl fmt::Display for SaturationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaturationPolicy::Queue => write!(f, "queue"),
+            SaturationPolicy::Reject => write!(f, "reject"),
+        }
+    }
+}
+
+impl FromStr for SaturationPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "queue" => Ok(SaturationPolicy::Queue),
+            "reject" => Ok(SaturationPolicy::Reject),
+            _ => Err(format!(
+                "invalid saturation policy '{}': expected queue or reject",
+                s
+            )),
+        }
+    }
+}
+
+/// Limiter for stage/publish/expand operations allowing `limit` at once (at
+/// least one)
+pub fn node_op_limiter(limit: usize) -> Limiter {
+    Limiter::new("node operation", limit)
+}
+
+/// Take a slot for `operation`, waiting or failing per `policy`
+pub async fn acquire(
+    limiter: &Limiter,
+    policy: SaturationPolicy,
+    operation: &str,
+) -> Result<Permit, Status> {
+    match policy {
+        SaturationPolicy::Queue => Ok(limiter.acquire().await),
+        SaturationPolicy::Reject => limiter.try_acquire().ok_or_else(|| {
+            warn!(
+                operation = %operation,
+                limit = limiter.limit(),
+                "Node operation limit reached, rejecting"
+            );
+            metrics::record_rate_limited(operation);
+            Status::resource_exhausted(format!(
+                "Too many concurrent node operations (max: {}). Please retry later.",
+                limiter.limit()
+            ))
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reject_policy_fails_when_saturated() {
+        let limiter = node_op_limiter(1);
+        let reject = SaturationPolicy::Reject;
+        let held = acquire(&limiter, reject, "node_stage_volume")
+            .await
+            .unwrap();
+
+        let err = acquire(&limiter, reject, "node_publish_volume")
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+
+        drop(held);
+        assert!(
+            acquire(&limiter, reject, "node_publish_volume")
+                .await
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_saturation_policy_parse() {
+        assert_eq!(
+            "queue".parse::<SaturationPolicy>().unwrap(),
+            SaturationPolicy::Queue
+        );
+        assert_eq!(
+            "REJECT".parse::<SaturationPolicy>().unwrap(),
+            SaturationPolicy::Reject
+        );
+        assert!("drop".parse::<SaturationPolicy>().is_err());
+    }
+}
@@ -0,0 +1,271 @@
+//! Periodic reconnection of failed multipath paths
+//!
+//! When a multipath volume is staged, a portal that is unreachable at that
+//! moment is skipped as long as another one succeeds. Paths whose controller
+//! was removed after `ctrl_loss_tmo` (NVMeoF) or whose session was logged out
+//! (iSCSI) are not re-established by the kernel either. The maintenance pass
+//! compares the endpoints each staged multipath volume was connected with
+//! against the live path state and logs in / connects only the missing or
+//! failed ones. Live paths and paths the kernel is still retrying are never
+//! touched.
+//!
+//! Staged targets are tracked in memory, so volumes staged before a driver
+//! restart are not covered until they are staged again.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::platform::{self, NvmeAuthCredentials, PathState};
+use crate::types::{Endpoint, ExportType, NvmeofConnectOptions};
+
+/// Connection details of a staged multipath volume
+#[derive(Debug, Clone)]
+pub struct StagedTarget {
+    /// Transport used for the volume
+    pub export_type: ExportType,
+    /// Target IQN or NQN
+    pub target_name: String,
+    /// All endpoints the volume was staged with
+    pub endpoints: Vec<Endpoint>,
+    /// DH-HMAC-CHAP credentials (NVMeoF only; iSCSI keeps CHAP in the node record)
+    pub nvme_auth: Option<NvmeAuthCredentials>,
+    /// `nvme connect` options (NVMeoF only)
+    pub nvme_options: Option<NvmeofConnectOptions>,
+}
+
+/// Registry of staged multipath volumes, keyed by volume ID.
+///
+/// Each entry has its own lock, held by the maintenance pass while it
+/// reconnects the volume and by [`StagedTargets::remove`], so a pass never
+/// logs back into a volume that unstage is tearing down.
+#[derive(Debug, Clone, Default)]
+pub struct StagedTargets {
+    targets: Arc<Mutex<HashMap<String, Arc<AsyncMutex<StagedTarget>>>>>,
+}
+
+impl StagedTargets {
+    /// Record (or replace) the connection details of a staged volume
+    pub fn insert(&self, volume_id: &str, target: StagedTarget) {
+        self.targets
+            .lock()
+            .unwrap()
+            .insert(volume_id.to_string(), Arc::new(AsyncMutex::new(target)));
+    }
+
+    /// Forget a volume (on unstage), waiting for a reconnect in progress
+    pub async fn remove(&self, volume_id: &str) {
+        let entry = self.targets.lock().unwrap().get(volume_id).cloned();
+        let Some(entry) = entry else {
+            return;
+        };
+        let _reconnecting = entry.lock().await;
+        let mut targets = self.targets.lock().unwrap();
+        if targets
+            .get(volume_id)
+            .is_some_and(|current| Arc::ptr_eq(current, &entry))
+        {
+            targets.remove(volume_id);
+        }
+    }
+
+    /// Whether `entry` is still the recorded entry of `volume_id`
+    fn is_current(&self, volume_id: &str, entry: &Arc<AsyncMutex<StagedTarget>>) -> bool {
+        self.targets
+            .lock()
+            .unwrap()
+            .get(volume_id)
+            .is_some_and(|current| Arc::ptr_eq(current, entry))
+    }
+
+    fn snapshot(&self) -> Vec<(String, Arc<AsyncMutex<StagedTarget>>)> {
+        self.targets
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, entry)| (id.clone(), entry.clone()))
+            .collect()
+    }
+}
+
+/// Decide which endpoints of a staged volume need a new login/connect.
+///
+/// Endpoints with a live path, or one the kernel is still recovering, are
+/// left alone; missing and failed paths are reconnected, including all of
+/// them when the volume lost every path.
+pub fn endpoints_to_reconnect<'a>(
+    endpoints: &'a [Endpoint],
+    paths: &HashMap<Endpoint, PathState>,
+) -> Vec<&'a Endpoint> {
+    endpoints
+        .iter()
+        .filter(|endpoint| matches!(paths.get(endpoint), None | Some(PathState::Failed)))
+        .collect()
+}
+
+/// Run one maintenance pass over all staged multipath volumes
+pub async fn run_once(targets: &StagedTargets) {
+    for (volume_id, entry) in targets.snapshot() {
+        // Unstage removes the entry under this lock, so a volume found here
+        // is still staged until the reconnects below are done
+        let target = entry.lock().await;
+        if !targets.is_current(&volume_id, &entry) {
+            debug!(volume_id = %volume_id, "Volume was unstaged, skipping");
+            continue;
+        }
+
+        let paths = match target.export_type {
+            ExportType::Iscsi => platform::iscsi_path_states(&target.target_name).await,
+            ExportType::Nvmeof => platform::nvmeof_path_states(&target.target_name).await,
+        };
+
+        let missing = endpoints_to_reconnect(&target.endpoints, &paths);
+        if missing.is_empty() {
+            debug!(volume_id = %volume_id, "All multipath paths healthy");
+            continue;
+        }
+
+        for endpoint in missing {
+            info!(
+                volume_id = %volume_id,
+                target = %target.target_name,
+                endpoint = %endpoint,
+                "Reconnecting failed multipath path"
+            );
+
+            let result = match target.export_type {
+                ExportType::Iscsi => {
+                    platform::login_iscsi_portal(&target.target_name, endpoint).await
+                }
+                ExportType::Nvmeof => {
+                    platform::connect_nvmeof_path(
+                        &target.target_name,
+                        endpoint,
+                        target.nvme_auth.as_ref(),
+                        target.nvme_options.as_ref(),
+                    )
+                    .await
+                }
+            };
+
+            if let Err(e) = result {
+                warn!(
+                    volume_id = %volume_id,
+                    endpoint = %endpoint,
+                    error = %e.message(),
+                    "Path reconnect failed, will retry on next pass"
+                );
+            }
+        }
+    }
+}
+
+/// Spawn the periodic maintenance task
+pub fn spawn(targets: StagedTargets, interval: Duration) -> JoinHandle<()> {
+    info!(
+        interval_secs = interval.as_secs(),
+        "Multipath path maintenance enabled"
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // The first tick completes immediately; skip it so freshly started
+        // drivers don't race in-flight NodeStageVolume calls
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            run_once(&targets).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoints() -> Vec<Endpoint> {
+        vec![
+            Endpoint::new("10.0.0.1", 4420),
+            Endpoint::new("10.0.0.2", 4420),
+            Endpoint::new("10.0.0.3", 4420),
+        ]
+    }
+
+    #[test]
+    fn test_reconnects_only_missing_and_failed_paths() {
+        let endpoints = endpoints();
+        let paths = HashMap::from([
+            (endpoints[0].clone(), PathState::Live),
+            (endpoints[1].clone(), PathState::Failed),
+            // endpoints[2] has no controller/session at all
+        ]);
+
+        let reconnect = endpoints_to_reconnect(&endpoints, &paths);
+        assert_eq!(reconnect, vec![&endpoints[1], &endpoints[2]]);
+    }
+
+    #[test]
+    fn test_recovering_paths_are_left_to_the_kernel() {
+        let endpoints = endpoints();
+        let paths = HashMap::from([
+            (endpoints[0].clone(), PathState::Live),
+            (endpoints[1].clone(), PathState::Recovering),
+            (endpoints[2].clone(), PathState::Live),
+        ]);
+
+        assert!(endpoints_to_reconnect(&endpoints, &paths).is_empty());
+
+        // A recovering path alone still counts as staged
+        let paths = HashMap::from([(endpoints[0].clone(), PathState::Recovering)]);
+        assert_eq!(
+            endpoints_to_reconnect(&endpoints, &paths),
+            vec![&endpoints[1], &endpoints[2]]
+        );
+    }
+
+    #[test]
+    fn test_no_usable_path_reconnects_every_endpoint() {
+        let endpoints = endpoints();
+        let all: Vec<_> = endpoints.iter().collect();
+        assert_eq!(endpoints_to_reconnect(&endpoints, &HashMap::new()), all);
+
+        let paths = HashMap::from([(endpoints[0].clone(), PathState::Failed)]);
+        assert_eq!(endpoints_to_reconnect(&endpoints, &paths), all);
+    }
+
+    #[tokio::test]
+    async fn test_remove_waits_for_reconnect_in_progress() {
+        let targets = StagedTargets::default();
+        targets.insert(
+            "pvc-1",
+            StagedTarget {
+                export_type: ExportType::Iscsi,
+                target_name: "iqn.2024-01.org.freebsd.csi:pvc-1".to_string(),
+                endpoints: endpoints(),
+                nvme_auth: None,
+                nvme_options: None,
+            },
+        );
+        let (volume_id, entry) = targets.snapshot().pop().unwrap();
+
+        // A pass holding the entry keeps unstage from removing it
+        let reconnecting = entry.lock().await;
+        let remove = tokio::spawn({
+            let targets = targets.clone();
+            async move { targets.remove("pvc-1").await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!remove.is_finished());
+        assert!(targets.is_current(&volume_id, &entry));
+
+        drop(reconnecting);
+        remove.await.unwrap();
+        assert!(!targets.is_current(&volume_id, &entry));
+        assert!(targets.snapshot().is_empty());
+    }
+}
@@ -6,18 +6,30 @@
 //! - mkfs.ext4/mkfs.xfs for filesystem formatting
 //! - mount --bind for bind mounts
 
+use std::collections::HashMap;
+use std::future::Future;
 use std::path::Path;
+use std::time::Duration;
 
 use tokio::process::Command;
 use tonic::Status;
 use tracing::{debug, error, info, warn};
 
 use super::PlatformResult;
-use crate::types::{Endpoint, NvmeofConnectOptions};
+use crate::types::{
+    Endpoint, Endpoints, ExportType, IscsiDiscoveryMode, IscsiDiscoveryOptions,
+    NvmeofConnectOptions,
+};
 
 /// Default filesystem type for Linux
 pub const DEFAULT_FS_TYPE: &str = "ext4";
 
+/// Delay between SendTargets discovery attempts in milliseconds
+const DISCOVERY_RETRY_DELAY_MS: u64 = 1000;
+
+/// Default time allowed for connecting to a single endpoint
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
 /// iSCSI CHAP credentials for initiator authentication
 #[derive(Debug, Clone)]
 pub struct IscsiChapCredentials {
@@ -87,6 +99,436 @@ pub async fn is_nvmeof_connected(target_nqn: &str) -> bool {
     false
 }
 
+/// Health of a single transport path (one portal/controller) to a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathState {
+    /// Logged in / controller live and carrying I/O
+    Live,
+    /// The kernel or iscsid is already retrying this path
+    Recovering,
+    /// The path is gone or permanently failed and needs a new login/connect
+    Failed,
+}
+
+/// Query the per-portal session state of an iSCSI target.
+///
+/// Portals without a session are absent from the returned map.
+pub async fn iscsi_path_states(target_iqn: &str) -> HashMap<Endpoint, PathState> {
+    let output = Command::new("iscsiadm")
+        .args(["-m", "session", "-P", "1"])
+        .output()
+        .await;
+
+    match output {
+        Ok(out) if out.status.success() => {
+            parse_iscsi_session_states(&String::from_utf8_lossy(&out.stdout), target_iqn)
+        }
+        _ => HashMap::new(),
+    }
+}
+
+/// Parse `iscsiadm -m session -P 1` output into per-portal states for one target.
+fn parse_iscsi_session_states(output: &str, target_iqn: &str) -> HashMap<Endpoint, PathState> {
+    let mut states = HashMap::new();
+    let mut in_target = false;
+    let mut portal: Option<Endpoint> = None;
+
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Target:") {
+            in_target = rest.split_whitespace().next() == Some(target_iqn);
+            portal = None;
+        } else if !in_target {
+            continue;
+        } else if let Some(rest) = line.strip_prefix("Persistent Portal:") {
+            // "10.0.0.1:3260,1" - drop the target portal group tag
+            let addr = rest.trim().split(',').next().unwrap_or("");
+            portal = Endpoints::parse(addr, ExportType::Iscsi.default_port())
+                .ok()
+                .and_then(|e| e.first().cloned());
+        } else if let Some(rest) = line.strip_prefix("iSCSI Session State:")
+            && let Some(endpoint) = portal.take()
+        {
+            let state = match rest.trim() {
+                "LOGGED_IN" => PathState::Live,
+                "FAILED" => PathState::Recovering,
+                _ => PathState::Failed,
+            };
+            states.insert(endpoint, state);
+        }
+    }
+
+    states
+}
+
+/// Target IQNs of all iSCSI sessions on this node.
+pub async fn iscsi_session_targets() -> Vec<String> {
+    // iscsiadm exits non-zero when there are no sessions
+    match Command::new("iscsiadm")
+        .args(["-m", "session"])
+        .output()
+        .await
+    {
+        Ok(out) if out.status.success() => {
+            parse_iscsi_session_targets(&String::from_utf8_lossy(&out.stdout))
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Parse `iscsiadm -m session` lines ("tcp: [1] 10.0.0.1:3260,1 <iqn> (non-flash)").
+fn parse_iscsi_session_targets(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(3))
+        .map(str::to_string)
+        .collect()
+}
+
+/// NQNs of all NVMeoF subsystems connected on this node.
+pub async fn nvmeof_subsystem_nqns() -> Vec<String> {
+    let mut nqns = Vec::new();
+    let Ok(mut subsystems) = tokio::fs::read_dir("/sys/class/nvme-subsystem").await else {
+        return nqns;
+    };
+    while let Ok(Some(subsys)) = subsystems.next_entry().await {
+        if let Ok(nqn) = tokio::fs::read_to_string(subsys.path().join("subsysnqn")).await {
+            nqns.push(nqn.trim().to_string());
+        }
+    }
+    nqns
+}
+
+/// Query the per-controller state of an NVMeoF subsystem from sysfs.
+///
+/// Endpoints without a controller are absent from the returned map.
+pub async fn nvmeof_path_states(target_nqn: &str) -> HashMap<Endpoint, PathState> {
+    nvme_controllers(target_nqn)
+        .await
+        .into_iter()
+        .map(|(_, endpoint, state)| (endpoint, state))
+        .collect()
+}
+
+/// List the controllers of an NVMeoF subsystem as (name, endpoint, state).
+async fn nvme_controllers(target_nqn: &str) -> Vec<(String, Endpoint, PathState)> {
+    let mut controllers = Vec::new();
+
+    let Ok(mut subsystems) = tokio::fs::read_dir("/sys/class/nvme-subsystem").await else {
+        return controllers;
+    };
+
+    while let Ok(Some(subsys)) = subsystems.next_entry().await {
+        let nqn = tokio::fs::read_to_string(subsys.path().join("subsysnqn")).await;
+        if nqn.map(|n| n.trim() != target_nqn).unwrap_or(true) {
+            continue;
+        }
+
+        let Ok(mut entries) = tokio::fs::read_dir(subsys.path()).await else {
+            continue;
+        };
+        while let Ok(Some(ctrl)) = entries.next_entry().await {
+            let address = tokio::fs::read_to_string(ctrl.path().join("address")).await;
+            let state = tokio::fs::read_to_string(ctrl.path().join("state")).await;
+            if let (Ok(address), Ok(state)) = (address, state)
+                && let Some(endpoint) = parse_nvme_ctrl_address(&address)
+            {
+                let name = ctrl.file_name().to_string_lossy().into_owned();
+                controllers.push((name, endpoint, nvme_ctrl_path_state(&state)));
+            }
+        }
+    }
+
+    controllers
+}
+
+/// Parse a controller `address` attribute ("traddr=10.0.0.1,trsvcid=4420,...").
+fn parse_nvme_ctrl_address(address: &str) -> Option<Endpoint> {
+    let mut traddr = None;
+    let mut trsvcid = None;
+    for field in address.trim().split(',') {
+        match field.split_once('=') {
+            Some(("traddr", value)) => traddr = Some(value),
+            Some(("trsvcid", value)) => trsvcid = value.parse::<u16>().ok(),
+            _ => {}
+        }
+    }
+    Some(Endpoint::new(traddr?, trsvcid?))
+}
+
+/// Map a controller `state` attribute to a path state.
+fn nvme_ctrl_path_state(state: &str) -> PathState {
+    match state.trim() {
+        "live" => PathState::Live,
+        // The kernel keeps reconnecting until ctrl_loss_tmo expires
+        "connecting" | "resetting" | "new" => PathState::Recovering,
+        _ => PathState::Failed,
+    }
+}
+
+/// Log in to a single portal of an iSCSI target using its existing node record.
+///
+/// The node record (including CHAP settings) was written when the volume was
+/// staged, so no credentials are needed here.
+pub async fn login_iscsi_portal(target_iqn: &str, endpoint: &Endpoint) -> PlatformResult<()> {
+    let portal = endpoint.to_portal_string();
+    let output = Command::new("iscsiadm")
+        .args(["-m", "node", "-T", target_iqn, "-p", &portal, "--login"])
+        .output()
+        .await
+        .map_err(|e| Status::internal(format!("Failed to execute iscsiadm login: {}", e)))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.contains("already present") {
+            return Err(Status::internal(format!(
+                "iSCSI login to {} failed: {}",
+                portal, stderr
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Connect a single NVMeoF path without touching the target's other controllers.
+pub async fn connect_nvmeof_path(
+    target_nqn: &str,
+    endpoint: &Endpoint,
+    auth_credentials: Option<&NvmeAuthCredentials>,
+    connect_options: Option<&NvmeofConnectOptions>,
+) -> PlatformResult<()> {
+    let args = build_nvme_connect_args(target_nqn, endpoint, connect_options, auth_credentials);
+    let output = Command::new("nvme")
+        .args(&args)
+        .output()
+        .await
+        .map_err(|e| Status::internal(format!("Failed to execute nvme connect: {}", e)))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.contains("already connected") {
+            return Err(Status::internal(format!(
+                "nvme connect to {} failed: {}",
+                endpoint, stderr
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Ask NVMe discovery controllers for the endpoints of `target_nqn`.
+///
+/// Each discovery endpoint is queried with `nvme discover`; endpoints that
+/// fail or time out are skipped. Returns the distinct TCP endpoints listed
+/// for the target, empty if none were found (the caller then connects to the
+/// configured endpoints directly).
+pub async fn discover_nvmeof_endpoints(
+    target_nqn: &str,
+    discovery_endpoints: &[Endpoint],
+    timeout: Duration,
+) -> Vec<Endpoint> {
+    let mut found: Vec<Endpoint> = Vec::new();
+
+    for endpoint in discovery_endpoints {
+        let discover = Command::new("nvme")
+            .args([
+                "discover",
+                "-t",
+                "tcp",
+                "-a",
+                &endpoint.host,
+                "-s",
+                &endpoint.port.to_string(),
+                "-o",
+                "json",
+            ])
+            .kill_on_drop(true)
+            .output();
+
+        match tokio::time::timeout(timeout, discover).await {
+            Ok(Ok(output)) if output.status.success() => {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let listed = find_discovery_log_endpoints(&stdout, target_nqn);
+                if listed.is_empty() {
+                    warn!(
+                        discovery = %endpoint,
+                        target = %target_nqn,
+                        "Target not listed in NVMe discovery log"
+                    );
+                }
+                for e in listed {
+                    if !found.contains(&e) {
+                        found.push(e);
+                    }
+                }
+            }
+            Ok(Ok(output)) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                warn!(discovery = %endpoint, stderr = %stderr, "nvme discover failed");
+            }
+            Ok(Err(e)) => {
+                // The binary is missing or not executable - other endpoints won't help
+                error!(error = %e, "Failed to execute nvme discover");
+                break;
+            }
+            Err(_) => {
+                warn!(discovery = %endpoint, timeout = ?timeout, "nvme discover timed out");
+            }
+        }
+    }
+
+    found
+}
+
+/// TCP endpoints listed for `target_nqn` in `nvme discover -o json` output.
+///
+/// The NQN must match exactly; discovery subsystem and referral entries
+/// never do.
+fn find_discovery_log_endpoints(output: &str, target_nqn: &str) -> Vec<Endpoint> {
+    let Ok(log) = serde_json::from_str::<serde_json::Value>(output) else {
+        return Vec::new();
+    };
+    let Some(records) = log.get("records").and_then(|r| r.as_array()) else {
+        return Vec::new();
+    };
+
+    records
+        .iter()
+        .filter(|r| r.get("subnqn").and_then(|v| v.as_str()) == Some(target_nqn))
+        .filter(|r| r.get("trtype").and_then(|v| v.as_str()) == Some("tcp"))
+        .filter_map(|r| {
+            let host = r.get("traddr")?.as_str()?.trim();
+            let port = r.get("trsvcid")?.as_str()?.trim().parse::<u16>().ok()?;
+            (!host.is_empty()).then(|| Endpoint::new(host, port))
+        })
+        .collect()
+}
+
+/// Directories under /dev/disk holding persistent device links, most
+/// preferred first
+const STABLE_LINK_DIRS: &[&str] = &["by-id", "by-uuid"];
+
+/// Persistent path for a block device.
+///
+/// Kernel names such as /dev/sdb or /dev/dm-3 are handed out in discovery
+/// order and can change across reboots and reconnects. Returns the udev link
+/// in /dev/disk/by-id (or by-uuid) resolving to the same device node, or
+/// `device` itself when there is none.
+///
+/// A by-uuid link names a filesystem UUID, which a cloned volume shares with
+/// its origin; udev then points the link at whichever device appeared last.
+/// Such a link is only used when no other device carries the UUID.
+pub async fn stable_device_path(device: &str) -> String {
+    match find_stable_link(Path::new("/dev/disk"), device).await {
+        Some(link) => {
+            if let Some(uuid) = by_uuid_link_name(&link) {
+                let devices = devices_with_uuid(uuid).await;
+                if devices.len() > 1 {
+                    warn!(
+                        device = %device,
+                        uuid = %uuid,
+                        devices = ?devices,
+                        "Filesystem UUID is shared by several devices, not using its by-uuid link"
+                    );
+                    return device.to_string();
+                }
+            }
+            debug!(device = %device, stable = %link, "Using persistent device link");
+            link
+        }
+        None => device.to_string(),
+    }
+}
+
+/// The UUID named by a /dev/disk/by-uuid link, if `link` is one
+fn by_uuid_link_name(link: &str) -> Option<&str> {
+    let (dir, name) = link.rsplit_once('/')?;
+    dir.ends_with("/by-uuid").then_some(name)
+}
+
+/// Device nodes carrying filesystem UUID `uuid`.
+///
+/// Devices claimed by a holder, such as the paths of a multipath map, share
+/// the map's UUID and are left out.
+async fn devices_with_uuid(uuid: &str) -> Vec<String> {
+    let output = Command::new("blkid")
+        .args(["-t", &format!("UUID={}", uuid), "-o", "device"])
+        .output()
+        .await;
+    let Ok(output) = output else {
+        return Vec::new();
+    };
+
+    let mut devices = Vec::new();
+    for device in parse_blkid_devices(&String::from_utf8_lossy(&output.stdout)) {
+        let name = device.rsplit('/').next().unwrap_or(&device);
+        let holders = Path::new("/sys/class/block").join(name).join("holders");
+        let claimed = match tokio::fs::read_dir(&holders).await {
+            Ok(mut entries) => matches!(entries.next_entry().await, Ok(Some(_))),
+            Err(_) => false,
+        };
+        if !claimed {
+            devices.push(device);
+        }
+    }
+    devices
+}
+
+/// Device paths listed by `blkid -o device`, one per line
+fn parse_blkid_devices(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Find the preferred link under `disk_dir`'s by-id/by-uuid directories
+/// that resolves to the same node as `device`
+async fn find_stable_link(disk_dir: &Path, device: &str) -> Option<String> {
+    let device = tokio::fs::canonicalize(device).await.ok()?;
+
+    for dir in STABLE_LINK_DIRS {
+        let dir = disk_dir.join(dir);
+        let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+            continue;
+        };
+
+        let mut names = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if tokio::fs::canonicalize(entry.path()).await.ok().as_ref() == Some(&device) {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        if let Some(name) = names
+            .into_iter()
+            .min_by(|a, b| (stable_link_rank(a), a).cmp(&(stable_link_rank(b), b)))
+        {
+            return Some(dir.join(name).to_string_lossy().into_owned());
+        }
+    }
+    None
+}
+
+/// Preference among by-id names of one device, lower first.
+///
+/// Identifiers of the LUN/namespace itself (WWN, EUI/UUID, multipath WWID)
+/// come before names built from vendor/model/serial strings, and
+/// administrator-chosen multipath aliases (`dm-name-`) come last.
+fn stable_link_rank(name: &str) -> u8 {
+    const IDENTITY_PREFIXES: &[&str] = &["wwn-", "dm-uuid-mpath-", "nvme-eui.", "nvme-uuid."];
+    if IDENTITY_PREFIXES.iter().any(|p| name.starts_with(p)) {
+        0
+    } else if name.starts_with("dm-name-") {
+        2
+    } else {
+        1
+    }
+}
+
 /// Check if a device is claimed by multipath and return the multipath device path.
 ///
 /// This checks if the raw device (e.g., /dev/sda, /dev/nvme0n1) is a slave
@@ -147,7 +589,7 @@ async fn resolve_multipath_device(device: &str) -> String {
 ///
 /// Returns true if the kernel's nvme_core module has multipath enabled,
 /// which means NVMe devices are handled by kernel multipath instead of dm-multipath.
-async fn is_nvme_native_multipath_enabled() -> bool {
+pub async fn is_nvme_native_multipath_enabled() -> bool {
     let multipath_path = "/sys/module/nvme_core/parameters/multipath";
     if let Ok(value) = tokio::fs::read_to_string(multipath_path).await {
         let v = value.trim();
@@ -160,7 +602,7 @@ async fn is_nvme_native_multipath_enabled() -> bool {
 /// Connect to an iSCSI target using iscsiadm with support for multiple portals.
 ///
 /// When multiple endpoints are provided, this function will:
-/// 1. Create node entries directly (without sendtargets discovery) for each portal
+/// 1. Create node entries for each portal, either directly or via sendtargets discovery
 /// 2. Configure CHAP authentication if credentials are provided
 /// 3. Login to the target via each portal
 /// 4. Wait for dm-multipath to combine the paths
@@ -170,10 +612,14 @@ async fn is_nvme_native_multipath_enabled() -> bool {
 /// * `target_iqn` - The iSCSI Qualified Name of the target
 /// * `endpoints` - One or more endpoints (host:port pairs) for multipath support
 /// * `chap_credentials` - Optional CHAP credentials for authentication
+/// * `discovery` - Optional discovery settings; direct node creation when absent
+/// * `connect_timeout` - Time allowed for each portal's node setup and login
 pub async fn connect_iscsi(
     target_iqn: &str,
     endpoints: &[Endpoint],
     chap_credentials: Option<&IscsiChapCredentials>,
+    discovery: Option<&IscsiDiscoveryOptions>,
+    connect_timeout: Duration,
 ) -> PlatformResult<String> {
     if endpoints.is_empty() {
         return Err(Status::invalid_argument(
@@ -183,20 +629,112 @@ pub async fn connect_iscsi(
 
     let multipath_mode = endpoints.len() > 1;
 
+    let discovery = discovery.copied().unwrap_or_default();
+
     info!(
         target_iqn = %target_iqn,
         endpoints = ?endpoints.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
         multipath = multipath_mode,
+        discovery = %discovery.mode,
         "Connecting to iSCSI target"
     );
 
-    // Track successful logins for multipath
+    // Track successful logins and timed-out portals for multipath
     let mut successful_logins = 0;
+    let mut timed_out = 0;
 
-    // Step 1 & 2: Create node entries and login to each portal
+    // Step 1 & 2: Create node entries and login to each portal, each
+    // within its own timeout so one dead portal can't stall the others
     for endpoint in endpoints {
-        let portal = endpoint.to_portal_string();
+        let login = connect_endpoint_with_timeout(
+            endpoint,
+            connect_timeout,
+            setup_iscsi_portal(
+                target_iqn,
+                endpoint,
+                chap_credentials,
+                &discovery,
+                multipath_mode,
+            ),
+            || cleanup_iscsi_portal(target_iqn, endpoint),
+        )
+        .await;
+
+        match login {
+            Ok(true) => successful_logins += 1,
+            Ok(false) => {}
+            Err(e) if multipath_mode && e.code() == tonic::Code::DeadlineExceeded => {
+                warn!(portal = %endpoint, "{} (continuing with other portals)", e.message());
+                timed_out += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    // Ensure at least one login succeeded
+    if successful_logins == 0 {
+        if timed_out > 0 {
+            return Err(Status::deadline_exceeded(
+                "Timed out logging in to every iSCSI portal",
+            ));
+        }
+        return Err(Status::internal(
+            "Failed to login to any iSCSI portal".to_string(),
+        ));
+    }
+
+    // Step 3: Wait for devices to appear and multipath to settle
+    // Longer wait for multipath to allow dm-multipath to combine paths
+    let settle_time = if multipath_mode { 3000 } else { 1000 };
+    info!(
+        settle_time_ms = settle_time,
+        successful_logins = successful_logins,
+        "Waiting for device(s) to settle"
+    );
+    tokio::time::sleep(std::time::Duration::from_millis(settle_time)).await;
+
+    // Step 4: Find the device (with multipath awareness)
+    let device = find_iscsi_device(target_iqn).await?;
+    info!(
+        device = %device,
+        multipath = multipath_mode,
+        paths = successful_logins,
+        "iSCSI target connected"
+    );
+
+    Ok(device)
+}
+
+/// Create the node record for one portal, configure CHAP on it and log in.
+///
+/// Returns whether a session is now established. A failed login is an error
+/// for a single portal but only a warning in multipath mode.
+async fn setup_iscsi_portal(
+    target_iqn: &str,
+    endpoint: &Endpoint,
+    chap_credentials: Option<&IscsiChapCredentials>,
+    discovery: &IscsiDiscoveryOptions,
+    multipath_mode: bool,
+) -> PlatformResult<bool> {
+    let mut portal = endpoint.to_portal_string();
+
+    // SendTargets discovery creates the node records itself; use the portal
+    // the target reported so the login matches the discovered record
+    let discovered_portal = match discovery.mode {
+        IscsiDiscoveryMode::SendTargets => {
+            discover_iscsi_target(target_iqn, &portal, discovery.retries).await
+        }
+        IscsiDiscoveryMode::Direct => None,
+    };
 
+    if let Some(discovered) = discovered_portal {
+        info!(
+            portal = %discovered,
+            target = %target_iqn,
+            "iSCSI node entry created via sendtargets discovery"
+        );
+        portal = discovered;
+    } else {
         // Create node entry directly without discovery
         // This is more robust as it doesn't depend on discovery auth settings
         let node_create_output = Command::new("iscsiadm")
@@ -222,19 +760,120 @@ pub async fn connect_iscsi(
         }
 
         info!(portal = %portal, target = %target_iqn, "iSCSI node entry created");
+    }
 
-        // Configure CHAP authentication if credentials are provided
-        if let Some(chap) = chap_credentials {
-            debug!(
-                target_iqn = %target_iqn,
-                portal = %portal,
-                username = %chap.username,
-                has_mutual = chap.mutual_username.is_some(),
-                "Configuring CHAP authentication"
-            );
+    // Configure CHAP authentication if credentials are provided
+    if let Some(chap) = chap_credentials {
+        debug!(
+            target_iqn = %target_iqn,
+            portal = %portal,
+            username = %chap.username,
+            has_mutual = chap.mutual_username.is_some(),
+            "Configuring CHAP authentication"
+        );
+
+        // Set authentication method to CHAP
+        let auth_method_output = Command::new("iscsiadm")
+            .args([
+                "-m",
+                "node",
+                "-T",
+                target_iqn,
+                "-p",
+                &portal,
+                "-o",
+                "update",
+                "-n",
+                "node.session.auth.authmethod",
+                "-v",
+                "CHAP",
+            ])
+            .output()
+            .await
+            .map_err(|e| {
+                error!(error = %e, "Failed to set CHAP auth method");
+                Status::internal(format!("Failed to set CHAP auth method: {}", e))
+            })?;
+
+        if !auth_method_output.status.success() {
+            let stderr = String::from_utf8_lossy(&auth_method_output.stderr);
+            error!(stderr = %stderr, "Failed to set CHAP auth method");
+            return Err(Status::internal(format!(
+                "Failed to set CHAP auth method: {}",
+                stderr
+            )));
+        }
+
+        // Set CHAP username
+        let username_output = Command::new("iscsiadm")
+            .args([
+                "-m",
+                "node",
+                "-T",
+                target_iqn,
+                "-p",
+                &portal,
+                "-o",
+                "update",
+                "-n",
+                "node.session.auth.username",
+                "-v",
+                &chap.username,
+            ])
+            .output()
+            .await
+            .map_err(|e| {
+                error!(error = %e, "Failed to set CHAP username");
+                Status::internal(format!("Failed to set CHAP username: {}", e))
+            })?;
+
+        if !username_output.status.success() {
+            let stderr = String::from_utf8_lossy(&username_output.stderr);
+            error!(stderr = %stderr, "Failed to set CHAP username");
+            return Err(Status::internal(format!(
+                "Failed to set CHAP username: {}",
+                stderr
+            )));
+        }
+
+        // Set CHAP password
+        let password_output = Command::new("iscsiadm")
+            .args([
+                "-m",
+                "node",
+                "-T",
+                target_iqn,
+                "-p",
+                &portal,
+                "-o",
+                "update",
+                "-n",
+                "node.session.auth.password",
+                "-v",
+                &chap.password,
+            ])
+            .output()
+            .await
+            .map_err(|e| {
+                error!(error = %e, "Failed to set CHAP password");
+                Status::internal(format!("Failed to set CHAP password: {}", e))
+            })?;
 
-            // Set authentication method to CHAP
-            let auth_method_output = Command::new("iscsiadm")
+        if !password_output.status.success() {
+            let stderr = String::from_utf8_lossy(&password_output.stderr);
+            error!(stderr = %stderr, "Failed to set CHAP password");
+            return Err(Status::internal(format!(
+                "Failed to set CHAP password: {}",
+                stderr
+            )));
+        }
+
+        // Configure mutual CHAP if provided
+        if let (Some(mutual_user), Some(mutual_pass)) =
+            (&chap.mutual_username, &chap.mutual_password)
+        {
+            // Set mutual CHAP username (target authenticates to initiator)
+            let mutual_user_output = Command::new("iscsiadm")
                 .args([
                     "-m",
                     "node",
@@ -245,28 +884,28 @@ pub async fn connect_iscsi(
                     "-o",
                     "update",
                     "-n",
-                    "node.session.auth.authmethod",
+                    "node.session.auth.username_in",
                     "-v",
-                    "CHAP",
+                    mutual_user,
                 ])
                 .output()
                 .await
                 .map_err(|e| {
-                    error!(error = %e, "Failed to set CHAP auth method");
-                    Status::internal(format!("Failed to set CHAP auth method: {}", e))
+                    error!(error = %e, "Failed to set mutual CHAP username");
+                    Status::internal(format!("Failed to set mutual CHAP username: {}", e))
                 })?;
 
-            if !auth_method_output.status.success() {
-                let stderr = String::from_utf8_lossy(&auth_method_output.stderr);
-                error!(stderr = %stderr, "Failed to set CHAP auth method");
+            if !mutual_user_output.status.success() {
+                let stderr = String::from_utf8_lossy(&mutual_user_output.stderr);
+                error!(stderr = %stderr, "Failed to set mutual CHAP username");
                 return Err(Status::internal(format!(
-                    "Failed to set CHAP auth method: {}",
+                    "Failed to set mutual CHAP username: {}",
                     stderr
                 )));
             }
 
-            // Set CHAP username
-            let username_output = Command::new("iscsiadm")
+            // Set mutual CHAP password
+            let mutual_pass_output = Command::new("iscsiadm")
                 .args([
                     "-m",
                     "node",
@@ -277,197 +916,233 @@ pub async fn connect_iscsi(
                     "-o",
                     "update",
                     "-n",
-                    "node.session.auth.username",
+                    "node.session.auth.password_in",
                     "-v",
-                    &chap.username,
+                    mutual_pass,
                 ])
                 .output()
                 .await
                 .map_err(|e| {
-                    error!(error = %e, "Failed to set CHAP username");
-                    Status::internal(format!("Failed to set CHAP username: {}", e))
+                    error!(error = %e, "Failed to set mutual CHAP password");
+                    Status::internal(format!("Failed to set mutual CHAP password: {}", e))
                 })?;
 
-            if !username_output.status.success() {
-                let stderr = String::from_utf8_lossy(&username_output.stderr);
-                error!(stderr = %stderr, "Failed to set CHAP username");
+            if !mutual_pass_output.status.success() {
+                let stderr = String::from_utf8_lossy(&mutual_pass_output.stderr);
+                error!(stderr = %stderr, "Failed to set mutual CHAP password");
                 return Err(Status::internal(format!(
-                    "Failed to set CHAP username: {}",
+                    "Failed to set mutual CHAP password: {}",
                     stderr
                 )));
             }
 
-            // Set CHAP password
-            let password_output = Command::new("iscsiadm")
-                .args([
-                    "-m",
-                    "node",
-                    "-T",
-                    target_iqn,
-                    "-p",
-                    &portal,
-                    "-o",
-                    "update",
-                    "-n",
-                    "node.session.auth.password",
-                    "-v",
-                    &chap.password,
-                ])
-                .output()
-                .await
-                .map_err(|e| {
-                    error!(error = %e, "Failed to set CHAP password");
-                    Status::internal(format!("Failed to set CHAP password: {}", e))
-                })?;
+            debug!(portal = %portal, "Mutual CHAP configured");
+        }
 
-            if !password_output.status.success() {
-                let stderr = String::from_utf8_lossy(&password_output.stderr);
-                error!(stderr = %stderr, "Failed to set CHAP password");
-                return Err(Status::internal(format!(
-                    "Failed to set CHAP password: {}",
-                    stderr
-                )));
-            }
+        info!(portal = %portal, "CHAP authentication configured");
+    }
 
-            // Configure mutual CHAP if provided
-            if let (Some(mutual_user), Some(mutual_pass)) =
-                (&chap.mutual_username, &chap.mutual_password)
-            {
-                // Set mutual CHAP username (target authenticates to initiator)
-                let mutual_user_output = Command::new("iscsiadm")
-                    .args([
-                        "-m",
-                        "node",
-                        "-T",
-                        target_iqn,
-                        "-p",
-                        &portal,
-                        "-o",
-                        "update",
-                        "-n",
-                        "node.session.auth.username_in",
-                        "-v",
-                        mutual_user,
-                    ])
-                    .output()
-                    .await
-                    .map_err(|e| {
-                        error!(error = %e, "Failed to set mutual CHAP username");
-                        Status::internal(format!("Failed to set mutual CHAP username: {}", e))
-                    })?;
-
-                if !mutual_user_output.status.success() {
-                    let stderr = String::from_utf8_lossy(&mutual_user_output.stderr);
-                    error!(stderr = %stderr, "Failed to set mutual CHAP username");
-                    return Err(Status::internal(format!(
-                        "Failed to set mutual CHAP username: {}",
-                        stderr
-                    )));
-                }
+    // Login to the target via this portal
+    let login_output = Command::new("iscsiadm")
+        .args(["-m", "node", "-T", target_iqn, "-p", &portal, "--login"])
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| {
+            error!(error = %e, portal = %portal, "Failed to execute iscsiadm login");
+            Status::internal(format!("Failed to execute iscsiadm login: {}", e))
+        })?;
 
-                // Set mutual CHAP password
-                let mutual_pass_output = Command::new("iscsiadm")
-                    .args([
-                        "-m",
-                        "node",
-                        "-T",
-                        target_iqn,
-                        "-p",
-                        &portal,
-                        "-o",
-                        "update",
-                        "-n",
-                        "node.session.auth.password_in",
-                        "-v",
-                        mutual_pass,
-                    ])
-                    .output()
-                    .await
-                    .map_err(|e| {
-                        error!(error = %e, "Failed to set mutual CHAP password");
-                        Status::internal(format!("Failed to set mutual CHAP password: {}", e))
-                    })?;
-
-                if !mutual_pass_output.status.success() {
-                    let stderr = String::from_utf8_lossy(&mutual_pass_output.stderr);
-                    error!(stderr = %stderr, "Failed to set mutual CHAP password");
-                    return Err(Status::internal(format!(
-                        "Failed to set mutual CHAP password: {}",
-                        stderr
-                    )));
-                }
+    if !login_output.status.success() {
+        let stderr = String::from_utf8_lossy(&login_output.stderr);
+        // Check if already logged in
+        if stderr.contains("already present") || stderr.contains("session already exists") {
+            info!(target_iqn = %target_iqn, portal = %portal, "iSCSI session already exists");
+            return Ok(true);
+        }
+        // In multipath mode, warn but continue; in single mode, fail
+        if multipath_mode {
+            warn!(
+                stderr = %stderr,
+                portal = %portal,
+                "iscsiadm login failed for portal (continuing with other portals)"
+            );
+            return Ok(false);
+        }
+        error!(stderr = %stderr, "iscsiadm login failed");
+        return Err(Status::internal(format!(
+            "iscsiadm login failed: {}",
+            stderr
+        )));
+    }
 
-                debug!(portal = %portal, "Mutual CHAP configured");
-            }
+    info!(target_iqn = %target_iqn, portal = %portal, "iSCSI login successful");
+    Ok(true)
+}
+
+/// Remove the session and node record a timed-out login left for one portal.
+///
+/// Both steps are best-effort: either may legitimately have nothing to remove.
+async fn cleanup_iscsi_portal(target_iqn: &str, endpoint: &Endpoint) {
+    let portal = endpoint.to_portal_string();
+    for op in [&["--logout"][..], &["-o", "delete"][..]] {
+        let output = Command::new("iscsiadm")
+            .args(["-m", "node", "-T", target_iqn, "-p", &portal])
+            .args(op)
+            .output()
+            .await;
+        match output {
+            Ok(out) if !out.status.success() => debug!(
+                portal = %portal,
+                stderr = %String::from_utf8_lossy(&out.stderr),
+                "iSCSI portal cleanup step had nothing to do"
+            ),
+            Ok(_) => {}
+            Err(e) => warn!(error = %e, portal = %portal, "Failed to execute iscsiadm cleanup"),
+        }
+    }
+}
 
-            info!(portal = %portal, "CHAP authentication configured");
+/// Run the connect of a single endpoint under `timeout`.
+///
+/// A portal that accepts TCP but never completes login would otherwise hang
+/// staging indefinitely. On expiry the connect future is dropped (killing its
+/// child process), `cleanup` removes whatever partial session it left behind,
+/// and `DeadlineExceeded` is returned.
+async fn connect_endpoint_with_timeout<T, C>(
+    endpoint: &Endpoint,
+    timeout: Duration,
+    connect: impl Future<Output = PlatformResult<T>>,
+    cleanup: impl FnOnce() -> C,
+) -> PlatformResult<T>
+where
+    C: Future<Output = ()>,
+{
+    match tokio::time::timeout(timeout, connect).await {
+        Ok(result) => result,
+        Err(_) => {
+            warn!(
+                endpoint = %endpoint,
+                timeout = ?timeout,
+                "Connect timed out, cleaning up partial session"
+            );
+            cleanup().await;
+            Err(Status::deadline_exceeded(format!(
+                "Connect to {} timed out after {:?}",
+                endpoint, timeout
+            )))
         }
+    }
+}
 
-        // Login to the target via this portal
-        let login_output = Command::new("iscsiadm")
-            .args(["-m", "node", "-T", target_iqn, "-p", &portal, "--login"])
+/// Run SendTargets discovery against a portal until the expected target shows up.
+///
+/// Retries up to `attempts` times because a freshly exported target may not be
+/// advertised until ctld finishes reloading. Returns the portal reported for
+/// the target, or None if discovery never listed it (the caller then falls
+/// back to creating the node record directly).
+async fn discover_iscsi_target(target_iqn: &str, portal: &str, attempts: u32) -> Option<String> {
+    let args = build_iscsi_discovery_args(portal);
+
+    for attempt in 1..=attempts {
+        match Command::new("iscsiadm")
+            .args(&args)
+            .kill_on_drop(true)
             .output()
             .await
-            .map_err(|e| {
-                error!(error = %e, portal = %portal, "Failed to execute iscsiadm login");
-                Status::internal(format!("Failed to execute iscsiadm login: {}", e))
-            })?;
-
-        if !login_output.status.success() {
-            let stderr = String::from_utf8_lossy(&login_output.stderr);
-            // Check if already logged in
-            if stderr.contains("already present") || stderr.contains("session already exists") {
-                info!(target_iqn = %target_iqn, portal = %portal, "iSCSI session already exists");
-                successful_logins += 1;
-            } else {
-                // In multipath mode, warn but continue; in single mode, fail
-                if multipath_mode {
-                    warn!(
-                        stderr = %stderr,
+        {
+            Ok(output) if output.status.success() => {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                if let Some(found) = find_sendtargets_portal(&stdout, target_iqn, portal) {
+                    debug!(
                         portal = %portal,
-                        "iscsiadm login failed for portal (continuing with other portals)"
+                        discovered_portal = %found,
+                        attempt = attempt,
+                        "Target found via sendtargets discovery"
                     );
-                } else {
-                    error!(stderr = %stderr, "iscsiadm login failed");
-                    return Err(Status::internal(format!(
-                        "iscsiadm login failed: {}",
-                        stderr
-                    )));
+                    return Some(found);
                 }
+                warn!(
+                    portal = %portal,
+                    target = %target_iqn,
+                    attempt = attempt,
+                    max_attempts = attempts,
+                    "Target not listed in sendtargets discovery output"
+                );
+            }
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                warn!(
+                    stderr = %stderr,
+                    portal = %portal,
+                    attempt = attempt,
+                    max_attempts = attempts,
+                    "iscsiadm sendtargets discovery failed"
+                );
+            }
+            Err(e) => {
+                // The binary is missing or not executable - retrying won't help
+                error!(error = %e, portal = %portal, "Failed to execute iscsiadm discovery");
+                return None;
             }
-        } else {
-            info!(target_iqn = %target_iqn, portal = %portal, "iSCSI login successful");
-            successful_logins += 1;
         }
-    }
 
-    // Ensure at least one login succeeded
-    if successful_logins == 0 {
-        return Err(Status::internal(
-            "Failed to login to any iSCSI portal".to_string(),
-        ));
+        if attempt < attempts {
+            tokio::time::sleep(std::time::Duration::from_millis(DISCOVERY_RETRY_DELAY_MS)).await;
+        }
     }
 
-    // Step 3: Wait for devices to appear and multipath to settle
-    // Longer wait for multipath to allow dm-multipath to combine paths
-    let settle_time = if multipath_mode { 3000 } else { 1000 };
-    info!(
-        settle_time_ms = settle_time,
-        successful_logins = successful_logins,
-        "Waiting for device(s) to settle"
+    warn!(
+        portal = %portal,
+        target = %target_iqn,
+        "Sendtargets discovery did not find target, falling back to direct login"
     );
-    tokio::time::sleep(std::time::Duration::from_millis(settle_time)).await;
+    None
+}
 
-    // Step 4: Find the device (with multipath awareness)
-    let device = find_iscsi_device(target_iqn).await?;
-    info!(
-        device = %device,
-        multipath = multipath_mode,
-        paths = successful_logins,
-        "iSCSI target connected"
-    );
+/// Build the `iscsiadm` arguments for SendTargets discovery against a portal.
+fn build_iscsi_discovery_args(portal: &str) -> Vec<String> {
+    vec![
+        "-m".to_string(),
+        "discovery".to_string(),
+        "-t".to_string(),
+        "sendtargets".to_string(),
+        "-p".to_string(),
+        portal.to_string(),
+    ]
+}
 
-    Ok(device)
+/// Find the portal advertising `target_iqn` in `iscsiadm -m discovery` output.
+///
+/// Each line has the form `<portal>,<tpgt> <iqn>`, e.g.
+/// `10.0.0.1:3260,1 iqn.2024-01.org.freebsd.csi:pvc-123`. The IQN must match
+/// exactly - a prefix match could log in to another volume's target.
+///
+/// A target is advertised once per portal of its portal group. The entry for
+/// `queried_portal` wins, so each multipath endpoint logs in through its own
+/// portal; otherwise the first listed portal is used.
+fn find_sendtargets_portal(output: &str, target_iqn: &str, queried_portal: &str) -> Option<String> {
+    let mut portals = output.lines().filter_map(|line| {
+        let mut fields = line.split_whitespace();
+        let portal_tpgt = fields.next()?;
+        let iqn = fields.next()?;
+        if iqn != target_iqn {
+            return None;
+        }
+        // Strip the ",<tpgt>" suffix; IPv6 portals are bracketed so the
+        // last comma always separates the portal group tag
+        let portal = match portal_tpgt.rsplit_once(',') {
+            Some((portal, _tpgt)) => portal,
+            None => portal_tpgt,
+        };
+        (!portal.is_empty()).then_some(portal)
+    });
+    let first = portals.next()?;
+    let portal = std::iter::once(first)
+        .chain(portals)
+        .find(|portal| portal.eq_ignore_ascii_case(queried_portal))
+        .unwrap_or(first);
+    Some(portal.to_string())
 }
 
 /// Find the device associated with an iSCSI target.
@@ -639,13 +1314,17 @@ pub async fn disconnect_iscsi(target_iqn: &str) -> PlatformResult<()> {
 ///
 /// # Arguments
 /// * `target_nqn` - The NVMe Qualified Name of the target
+/// * `namespace_id` - Namespace of the volume when the controller is shared
 /// * `endpoints` - One or more endpoints (host:port pairs) for multipath support
 /// * `auth_credentials` - Optional DH-HMAC-CHAP credentials for authentication
+/// * `connect_timeout` - Time allowed for each endpoint's connect
 pub async fn connect_nvmeof(
     target_nqn: &str,
+    namespace_id: Option<u32>,
     endpoints: &[Endpoint],
     auth_credentials: Option<&NvmeAuthCredentials>,
     connect_options: Option<&NvmeofConnectOptions>,
+    connect_timeout: Duration,
 ) -> PlatformResult<String> {
     if endpoints.is_empty() {
         return Err(Status::invalid_argument(
@@ -663,56 +1342,44 @@ pub async fn connect_nvmeof(
         "Connecting to NVMeoF target"
     );
 
-    // Track successful connections
+    // Track successful connections and timed-out endpoints
     let mut successful_connects = 0;
+    let mut timed_out = 0;
 
-    // Connect to each endpoint (each with its own host:port)
+    // Connect to each endpoint within its own timeout
     for endpoint in endpoints {
-        let mut cmd = Command::new("nvme");
-        let args = build_nvme_connect_args(target_nqn, endpoint, connect_options, auth_credentials);
-        cmd.args(&args);
-
-        if let Some(auth) = auth_credentials {
-            debug!(
-                target_nqn = %target_nqn,
-                endpoint = %endpoint,
-                has_ctrl_secret = auth.ctrl_secret.is_some(),
-                "Configuring NVMeoF DH-HMAC-CHAP authentication"
-            );
-        }
-
-        let output = cmd.output().await.map_err(|e| {
-            error!(error = %e, endpoint = %endpoint, "Failed to execute nvme connect");
-            Status::internal(format!("Failed to execute nvme connect: {}", e))
-        })?;
+        let connect = connect_endpoint_with_timeout(
+            endpoint,
+            connect_timeout,
+            connect_nvmeof_endpoint(
+                target_nqn,
+                endpoint,
+                auth_credentials,
+                connect_options,
+                multipath_mode,
+            ),
+            || cleanup_nvmeof_path(target_nqn, endpoint),
+        )
+        .await;
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            // Check if already connected
-            if stderr.contains("already connected") {
-                info!(target_nqn = %target_nqn, endpoint = %endpoint, "NVMeoF target already connected");
-                successful_connects += 1;
-            } else {
-                // In multipath mode, warn but continue; in single mode, fail
-                if multipath_mode {
-                    warn!(
-                        stderr = %stderr,
-                        endpoint = %endpoint,
-                        "nvme connect failed for endpoint (continuing with other endpoints)"
-                    );
-                } else {
-                    error!(stderr = %stderr, "nvme connect failed");
-                    return Err(Status::internal(format!("nvme connect failed: {}", stderr)));
-                }
+        match connect {
+            Ok(true) => successful_connects += 1,
+            Ok(false) => {}
+            Err(e) if multipath_mode && e.code() == tonic::Code::DeadlineExceeded => {
+                warn!(endpoint = %endpoint, "{} (continuing with other endpoints)", e.message());
+                timed_out += 1;
             }
-        } else {
-            info!(target_nqn = %target_nqn, endpoint = %endpoint, "NVMeoF connect successful");
-            successful_connects += 1;
+            Err(e) => return Err(e),
         }
     }
 
     // Ensure at least one connection succeeded
     if successful_connects == 0 {
+        if timed_out > 0 {
+            return Err(Status::deadline_exceeded(
+                "Timed out connecting to every NVMeoF endpoint",
+            ));
+        }
         return Err(Status::internal(
             "Failed to connect to any NVMeoF endpoint".to_string(),
         ));
@@ -729,7 +1396,7 @@ pub async fn connect_nvmeof(
     tokio::time::sleep(std::time::Duration::from_millis(settle_time)).await;
 
     // Find the device (with multipath awareness)
-    let device = find_nvmeof_device(target_nqn).await?;
+    let device = find_nvmeof_device(target_nqn, namespace_id).await?;
     info!(
         device = %device,
         multipath = multipath_mode,
@@ -740,6 +1407,86 @@ pub async fn connect_nvmeof(
     Ok(device)
 }
 
+/// Connect one endpoint of an NVMeoF target.
+///
+/// Returns whether the controller is now connected. A failed connect is an
+/// error for a single endpoint but only a warning in multipath mode.
+async fn connect_nvmeof_endpoint(
+    target_nqn: &str,
+    endpoint: &Endpoint,
+    auth_credentials: Option<&NvmeAuthCredentials>,
+    connect_options: Option<&NvmeofConnectOptions>,
+    multipath_mode: bool,
+) -> PlatformResult<bool> {
+    let mut cmd = Command::new("nvme");
+    let args = build_nvme_connect_args(target_nqn, endpoint, connect_options, auth_credentials);
+    cmd.args(&args).kill_on_drop(true);
+
+    if let Some(auth) = auth_credentials {
+        debug!(
+            target_nqn = %target_nqn,
+            endpoint = %endpoint,
+            has_ctrl_secret = auth.ctrl_secret.is_some(),
+            "Configuring NVMeoF DH-HMAC-CHAP authentication"
+        );
+    }
+
+    let output = cmd.output().await.map_err(|e| {
+        error!(error = %e, endpoint = %endpoint, "Failed to execute nvme connect");
+        Status::internal(format!("Failed to execute nvme connect: {}", e))
+    })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // Check if already connected
+        if stderr.contains("already connected") {
+            info!(target_nqn = %target_nqn, endpoint = %endpoint, "NVMeoF target already connected");
+            return Ok(true);
+        }
+        // In multipath mode, warn but continue; in single mode, fail
+        if multipath_mode {
+            warn!(
+                stderr = %stderr,
+                endpoint = %endpoint,
+                "nvme connect failed for endpoint (continuing with other endpoints)"
+            );
+            return Ok(false);
+        }
+        error!(stderr = %stderr, "nvme connect failed");
+        return Err(Status::internal(format!("nvme connect failed: {}", stderr)));
+    }
+
+    info!(target_nqn = %target_nqn, endpoint = %endpoint, "NVMeoF connect successful");
+    Ok(true)
+}
+
+/// Disconnect any controller a timed-out connect left for one endpoint.
+///
+/// Only that endpoint's controllers are removed; other paths of the
+/// subsystem stay connected.
+async fn cleanup_nvmeof_path(target_nqn: &str, endpoint: &Endpoint) {
+    for (ctrl, ctrl_endpoint, _) in nvme_controllers(target_nqn).await {
+        if ctrl_endpoint != *endpoint {
+            continue;
+        }
+        let output = Command::new("nvme")
+            .args(["disconnect", "-d", &ctrl])
+            .output()
+            .await;
+        match output {
+            Ok(out) if out.status.success() => {
+                info!(controller = %ctrl, endpoint = %endpoint, "Disconnected partial NVMeoF controller");
+            }
+            Ok(out) => warn!(
+                controller = %ctrl,
+                stderr = %String::from_utf8_lossy(&out.stderr),
+                "Failed to disconnect partial NVMeoF controller"
+            ),
+            Err(e) => warn!(error = %e, controller = %ctrl, "Failed to execute nvme disconnect"),
+        }
+    }
+}
+
 fn build_nvme_connect_args(
     target_nqn: &str,
     endpoint: &Endpoint,
@@ -832,9 +1579,29 @@ fn is_nvme_namespace_device(path: &str) -> bool {
     chars.peek().is_some_and(|c| c.is_ascii_digit())
 }
 
+/// Whether an NVMe namespace device has namespace ID `namespace_id` (any
+/// namespace when `None`).
+///
+/// A controller shared by a controller group exposes every member's
+/// namespace, so the volume's own one is picked by its ID.
+async fn nvme_namespace_matches(device: &str, namespace_id: Option<u32>) -> bool {
+    let Some(namespace_id) = namespace_id else {
+        return true;
+    };
+    let name = device.rsplit('/').next().unwrap_or(device);
+    tokio::fs::read_to_string(format!("/sys/block/{}/nsid", name))
+        .await
+        .ok()
+        .and_then(|nsid| nsid.trim().parse::<u32>().ok())
+        == Some(namespace_id)
+}
+
 /// Helper to find NVMe device via `nvme list-subsys` command.
 /// Returns the device path (e.g., "/dev/nvme0n1") if found, None otherwise.
-async fn find_device_via_list_subsys(target_nqn: &str) -> Option<String> {
+async fn find_device_via_list_subsys(
+    target_nqn: &str,
+    namespace_id: Option<u32>,
+) -> Option<String> {
     let output = Command::new("nvme")
         .args(["list-subsys", "-o", "json"])
         .output()
@@ -857,7 +1624,9 @@ async fn find_device_via_list_subsys(target_nqn: &str) -> Option<String> {
         if let Some(paths) = subsys.get("Paths").and_then(|p| p.as_array()) {
             for path in paths {
                 let name = path.get("Name").and_then(|n| n.as_str())?;
-                if is_nvme_namespace_device(name) {
+                if is_nvme_namespace_device(name)
+                    && nvme_namespace_matches(name, namespace_id).await
+                {
                     return Some(format!("/dev/{}", name));
                 }
             }
@@ -867,7 +1636,9 @@ async fn find_device_via_list_subsys(target_nqn: &str) -> Option<String> {
         if let Some(namespaces) = subsys.get("Namespaces").and_then(|n| n.as_array()) {
             for ns in namespaces {
                 let name = ns.get("NameSpace").and_then(|n| n.as_str())?;
-                if is_nvme_namespace_device(name) {
+                if is_nvme_namespace_device(name)
+                    && nvme_namespace_matches(name, namespace_id).await
+                {
                     return Some(format!("/dev/{}", name));
                 }
             }
@@ -887,7 +1658,13 @@ async fn find_device_via_list_subsys(target_nqn: &str) -> Option<String> {
 /// Note: Even with native NVMe multipath enabled (nvme_core.multipath=Y),
 /// dm-multipath may still be configured to claim NVMe devices. We must
 /// always check for dm devices to avoid "device in use" errors.
-pub async fn find_nvmeof_device(target_nqn: &str) -> PlatformResult<String> {
+///
+/// With `namespace_id` only that namespace of the controller matches;
+/// without it the controller's first namespace is used.
+pub async fn find_nvmeof_device(
+    target_nqn: &str,
+    namespace_id: Option<u32>,
+) -> PlatformResult<String> {
     let native_multipath = is_nvme_native_multipath_enabled().await;
     debug!(
         native_multipath = native_multipath,
@@ -903,7 +1680,7 @@ pub async fn find_nvmeof_device(target_nqn: &str) -> PlatformResult<String> {
 
     // Method 1: Use nvme list-subsys which directly maps NQN to devices
     // This is the most reliable method as it's specifically designed for this purpose
-    if let Some(device) = find_device_via_list_subsys(target_nqn).await {
+    if let Some(device) = find_device_via_list_subsys(target_nqn, namespace_id).await {
         info!(
             device = %device,
             target_nqn = %target_nqn,
@@ -935,7 +1712,10 @@ pub async fn find_nvmeof_device(target_nqn: &str) -> PlatformResult<String> {
                     .and_then(|n| n.as_str())
                     .unwrap_or("");
                 // CRITICAL: Require exact NQN match, not substring match
-                if subsys_nqn == target_nqn && is_nvme_namespace_device(dev_path) {
+                if subsys_nqn == target_nqn
+                    && is_nvme_namespace_device(dev_path)
+                    && nvme_namespace_matches(dev_path, namespace_id).await
+                {
                     info!(
                         device = %dev_path,
                         target_nqn = %target_nqn,
@@ -963,7 +1743,9 @@ pub async fn find_nvmeof_device(target_nqn: &str) -> PlatformResult<String> {
                         let name = ns_entry.file_name();
                         let name_str = name.to_string_lossy();
                         // Only match namespace devices like nvme0n1, not controller devices like nvme0
-                        if is_nvme_namespace_device(&name_str) {
+                        if is_nvme_namespace_device(&name_str)
+                            && nvme_namespace_matches(&name_str, namespace_id).await
+                        {
                             let raw_device = format!("/dev/{}", name_str);
                             info!(
                                 device = %raw_device,
@@ -1003,7 +1785,9 @@ pub async fn find_nvmeof_device(target_nqn: &str) -> PlatformResult<String> {
                     target_nqn = %target_nqn,
                     "Checking NVMe device NQN"
                 );
-                if nqn_trimmed == target_nqn {
+                if nqn_trimmed == target_nqn
+                    && nvme_namespace_matches(&name_str, namespace_id).await
+                {
                     let raw_device = format!("/dev/{}", name_str);
                     info!(
                         device = %raw_device,
@@ -1021,11 +1805,15 @@ pub async fn find_nvmeof_device(target_nqn: &str) -> PlatformResult<String> {
     // CRITICAL: Do NOT return an arbitrary device - this causes data corruption!
     error!(
         target_nqn = %target_nqn,
+        namespace_id = ?namespace_id,
         "No NVMe device found matching target NQN. Device may not be connected."
     );
     Err(Status::internal(format!(
-        "No NVMe device found for NQN '{}'. Ensure the target is connected and the NQN is correct.",
-        target_nqn
+        "No NVMe device found for NQN '{}'{}. Ensure the target is connected and the NQN is correct.",
+        target_nqn,
+        namespace_id
+            .map(|id| format!(" namespace {}", id))
+            .unwrap_or_default()
     )))
 }
 
@@ -1060,13 +1848,17 @@ pub async fn disconnect_nvmeof(target_nqn: &str) -> PlatformResult<()> {
 }
 
 /// Format a device with the specified filesystem type.
-pub async fn format_device(device: &str, fs_type: &str) -> PlatformResult<()> {
-    info!(device = %device, fs_type = %fs_type, "Formatting device");
+///
+/// `options` are extra mkfs arguments, already checked by [`format_options`].
+pub async fn format_device(device: &str, fs_type: &str, options: &[String]) -> PlatformResult<()> {
+    info!(device = %device, fs_type = %fs_type, options = ?options, "Formatting device");
 
     match fs_type.to_lowercase().as_str() {
         "ext4" => {
             let output = Command::new("mkfs.ext4")
-                .args(["-F", device]) // -F to force (don't prompt)
+                .arg("-F") // force (don't prompt)
+                .args(options)
+                .arg(device)
                 .output()
                 .await
                 .map_err(|e| {
@@ -1082,7 +1874,9 @@ pub async fn format_device(device: &str, fs_type: &str) -> PlatformResult<()> {
         }
         "xfs" => {
             let output = Command::new("mkfs.xfs")
-                .args(["-f", device]) // -f to force
+                .arg("-f") // force
+                .args(options)
+                .arg(device)
                 .output()
                 .await
                 .map_err(|e| {
@@ -1139,8 +1933,178 @@ pub async fn needs_formatting(device: &str) -> PlatformResult<bool> {
     Ok(!stdout.contains("TYPE="))
 }
 
+/// mkfs.ext4 flags accepted in `mkfsOptions`; each takes a value
+const EXT4_FORMAT_FLAGS: &[&str] = &[
+    "-b", "-E", "-G", "-g", "-I", "-i", "-L", "-m", "-N", "-O", "-T",
+];
+
+/// mkfs.xfs flags accepted in `mkfsOptions`; each takes a value
+const XFS_FORMAT_FLAGS: &[&str] = &["-b", "-d", "-i", "-L", "-l", "-m", "-n", "-s"];
+
+/// Suboptions that point mkfs at a file or device other than the volume
+const FORMAT_TARGET_SUBOPTIONS: &[&str] = &["device", "file", "logdev", "name", "rtdev"];
+
+/// Suboptions (by filesystem and flag) that place or size the filesystem
+/// differently from the whole volume
+const FORMAT_LAYOUT_SUBOPTIONS: &[(&str, &str, &str)] =
+    &[("ext4", "-E", "offset"), ("xfs", "-d", "size")];
+
+/// Extra mkfs arguments for a `fs_type` filesystem from the `mkfsOptions`
+/// volume context value, e.g. `-E stride=16,stripe-width=64`.
+///
+/// Only flags that tune the new filesystem are allowed, each followed by its
+/// value. Flags that change what gets formatted or how (dry runs, external
+/// journal or log devices, a filesystem size) are rejected, as are values
+/// containing `/`, shell metacharacters or control characters.
+pub fn format_options(fs_type: &str, options: &str) -> PlatformResult<Vec<String>> {
+    let fs_type = fs_type.to_lowercase();
+    let allowed = match fs_type.as_str() {
+        "ext4" => EXT4_FORMAT_FLAGS,
+        "xfs" => XFS_FORMAT_FLAGS,
+        _ => {
+            return Err(Status::invalid_argument(format!(
+                "mkfsOptions are not supported for fsType {}",
+                fs_type
+            )));
+        }
+    };
+
+    let mut args = Vec::new();
+    let mut tokens = options.split_whitespace();
+    while let Some(flag) = tokens.next() {
+        if !allowed.contains(&flag) {
+            return Err(Status::invalid_argument(format!(
+                "mkfsOptions flag '{}' is not allowed for {}; allowed: {}",
+                flag,
+                fs_type,
+                allowed.join(" ")
+            )));
+        }
+        let value = tokens
+            .next()
+            .filter(|v| !v.starts_with('-'))
+            .ok_or_else(|| {
+                Status::invalid_argument(format!("mkfsOptions flag '{}' needs a value", flag))
+            })?;
+        if value.contains('/')
+            || value.contains(MOUNT_OPTION_FORBIDDEN)
+            || value.contains(char::is_control)
+        {
+            return Err(Status::invalid_argument(format!(
+                "mkfsOptions value for '{}' contains a forbidden character",
+                flag
+            )));
+        }
+        let mut suboptions = value
+            .split(',')
+            .map(|sub| sub.split('=').next().unwrap_or_default());
+        if let Some(target) = suboptions
+            .clone()
+            .find(|key| FORMAT_TARGET_SUBOPTIONS.contains(key))
+        {
+            return Err(Status::invalid_argument(format!(
+                "mkfsOptions suboption '{}' is not allowed; the volume is the only format target",
+                target
+            )));
+        }
+        if let Some(layout) = suboptions
+            .find(|key| FORMAT_LAYOUT_SUBOPTIONS.contains(&(fs_type.as_str(), flag, *key)))
+        {
+            return Err(Status::invalid_argument(format!(
+                "mkfsOptions suboption '{} {}' is not allowed; the filesystem always spans the whole volume",
+                flag, layout
+            )));
+        }
+        args.push(flag.to_string());
+        args.push(value.to_string());
+    }
+    Ok(args)
+}
+
+/// Characters never found in a legitimate mount option
+const MOUNT_OPTION_FORBIDDEN: &[char] = &[';', '|', '&', '$', '`', '<', '>', '(', ')', '\\', '\''];
+
+/// Split a comma-separated mount option string on the commas outside double
+/// quotes, so an SELinux MCS context such as
+/// `context="system_u:object_r:foo:s0:c1,c2"` stays one option.
+fn split_mount_options(flags: &str) -> Vec<&str> {
+    let mut options = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in flags.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                options.push(&flags[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    options.push(&flags[start..]);
+    options
+}
+
+/// Options for mounting a `fs_type` filesystem.
+///
+/// `mount_flags` (from the volume capability, i.e. the StorageClass
+/// `mountOptions`) are kept as given, except that flags containing shell
+/// metacharacters, whitespace or control characters are rejected. With
+/// `direct_io`, options that keep `O_DIRECT` fast are appended: ext4 gets
+/// `dioread_nolock` so direct reads don't serialize on the inode lock, XFS
+/// needs nothing. Flags that defeat direct I/O are rejected.
+pub fn mount_options(
+    fs_type: &str,
+    mount_flags: &[String],
+    direct_io: bool,
+) -> PlatformResult<Vec<String>> {
+    let mut options: Vec<String> = mount_flags
+        .iter()
+        .flat_map(|flags| split_mount_options(flags))
+        .map(str::trim)
+        .filter(|flag| !flag.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    if let Some(index) = options.iter().position(|flag| {
+        flag.contains(MOUNT_OPTION_FORBIDDEN)
+            || flag.contains(|c: char| c.is_whitespace() || c.is_control())
+    }) {
+        // The flag itself is not echoed: mount flags may carry secrets
+        return Err(Status::invalid_argument(format!(
+            "mount option {} contains a forbidden character",
+            index + 1
+        )));
+    }
+
+    if direct_io && fs_type.eq_ignore_ascii_case("ext4") {
+        // data=journal routes every write through the journal; ext4 falls
+        // back to buffered I/O for O_DIRECT opens
+        if let Some(conflict) = options
+            .iter()
+            .find(|flag| *flag == "dioread_lock" || *flag == "data=journal")
+        {
+            return Err(Status::invalid_argument(format!(
+                "mount option '{}' conflicts with directIo on ext4",
+                conflict
+            )));
+        }
+        if !options.iter().any(|flag| flag == "dioread_nolock") {
+            options.push("dioread_nolock".to_string());
+        }
+    }
+
+    Ok(options)
+}
+
 /// Mount a device to a target path.
-pub async fn mount_device(device: &str, target: &str, fs_type: &str) -> PlatformResult<()> {
+pub async fn mount_device(
+    device: &str,
+    target: &str,
+    fs_type: &str,
+    options: &[String],
+) -> PlatformResult<()> {
+    // Options are not logged: CSI mount flags may carry secrets
     info!(device = %device, target = %target, fs_type = %fs_type, "Mounting device");
 
     // Ensure target directory exists
@@ -1151,8 +2115,16 @@ pub async fn mount_device(device: &str, target: &str, fs_type: &str) -> Platform
 
     let fs_type_lower = fs_type.to_lowercase();
 
+    let mut args = vec!["-t".to_string(), fs_type_lower];
+    if !options.is_empty() {
+        args.push("-o".to_string());
+        args.push(options.join(","));
+    }
+    args.push(device.to_string());
+    args.push(target.to_string());
+
     let output = Command::new("mount")
-        .args(["-t", &fs_type_lower, device, target])
+        .args(&args)
         .output()
         .await
         .map_err(|e| {
@@ -1237,9 +2209,7 @@ pub async fn unmount(target: &str) -> PlatformResult<()> {
 pub async fn is_mounted(target: &str) -> PlatformResult<bool> {
     // On Linux, check /proc/mounts for efficiency
     if let Ok(mounts) = tokio::fs::read_to_string("/proc/mounts").await {
-        return Ok(mounts
-            .lines()
-            .any(|line| line.split_whitespace().nth(1) == Some(target)));
+        return Ok(mounts_contain(&mounts, target));
     }
 
     // Fallback to mount command
@@ -1252,6 +2222,213 @@ pub async fn is_mounted(target: &str) -> PlatformResult<bool> {
     Ok(stdout.lines().any(|line| line.contains(target)))
 }
 
+/// Whether `/proc/mounts` content has an entry mounted exactly at `target`.
+fn mounts_contain(mounts: &str, target: &str) -> bool {
+    mounts
+        .lines()
+        .any(|line| line.split_whitespace().nth(1) == Some(target))
+}
+
+/// How the device backing a mount point is resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MountSourceMethod {
+    /// `findmnt -n -o SOURCE` (util-linux)
+    Findmnt,
+    /// Parse `/proc/self/mountinfo` (Linux without util-linux)
+    Mountinfo,
+    /// Parse `df -P` output (FreeBSD and other systems without procfs)
+    Df,
+}
+
+/// Pick the lookup method from what is available on this host.
+///
+/// `findmnt` is preferred when installed; otherwise mountinfo is parsed
+/// directly, and `df` is the last resort where neither exists.
+pub fn select_mount_source_method(
+    findmnt_available: bool,
+    mountinfo_available: bool,
+) -> MountSourceMethod {
+    if findmnt_available {
+        MountSourceMethod::Findmnt
+    } else if mountinfo_available {
+        MountSourceMethod::Mountinfo
+    } else {
+        MountSourceMethod::Df
+    }
+}
+
+/// Path of the per-process mount table on Linux
+const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+
+/// Get the device backing a mount point.
+///
+/// Works without `findmnt`: falls back to `/proc/self/mountinfo`, then to
+/// `df -P`, so filesystem expansion also works on minimal images and FreeBSD.
+pub async fn find_mount_source(path: &str) -> PlatformResult<String> {
+    let findmnt_available = command_exists("findmnt").await;
+    let mountinfo_available = Path::new(MOUNTINFO_PATH).exists();
+    let method = select_mount_source_method(findmnt_available, mountinfo_available);
+    debug!(path = %path, method = ?method, "Resolving mount source");
+
+    let device = match method {
+        MountSourceMethod::Findmnt => {
+            let stdout =
+                run_mount_query(Command::new("findmnt").args(["-n", "-o", "SOURCE", path])).await?;
+            Some(stdout.trim().to_string()).filter(|d| !d.is_empty())
+        }
+        MountSourceMethod::Mountinfo => {
+            let mountinfo = tokio::fs::read_to_string(MOUNTINFO_PATH)
+                .await
+                .map_err(|e| Status::internal(format!("Failed to read mountinfo: {}", e)))?;
+            mountinfo_source(&mountinfo, path)
+        }
+        MountSourceMethod::Df => {
+            let stdout = run_mount_query(Command::new("df").args(["-P", path])).await?;
+            df_source(&stdout, path)
+        }
+    };
+
+    device.ok_or_else(|| Status::internal(format!("Path {} is not a mount point", path)))
+}
+
+/// Whether `program` can be executed (i.e. is on PATH)
+async fn command_exists(program: &str) -> bool {
+    !matches!(
+        Command::new(program).arg("--version").output().await,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound
+    )
+}
+
+/// Run a mount lookup command and return its stdout
+async fn run_mount_query(command: &mut Command) -> PlatformResult<String> {
+    let output = command.output().await.map_err(|e| {
+        error!(error = %e, "Failed to execute mount lookup");
+        Status::internal(format!("Failed to get mount device: {}", e))
+    })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        error!(stderr = %stderr, "Mount lookup failed");
+        return Err(Status::internal(format!(
+            "Failed to get mount device: {}",
+            stderr.trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Find the mount source for `target` in `/proc/self/mountinfo` content.
+///
+/// Line format: `id parent major:minor root mountpoint options [optional...] - fstype source superopts`.
+/// When a path is mounted over, the last (topmost) entry wins.
+/// Report whether the filesystem mounted at `path` is read-only.
+///
+/// Both the per-mount and the superblock options are checked: ext4 with
+/// `errors=remount-ro` (and xfs after a shutdown) flips only the superblock
+/// to read-only, leaving the mount itself flagged `rw`. Returns false when
+/// the options can't be determined (`df` fallback), so staging isn't
+/// blocked on platforms without findmnt or procfs.
+pub async fn is_read_only_mount(path: &str) -> PlatformResult<bool> {
+    let findmnt_available = command_exists("findmnt").await;
+    let mountinfo_available = Path::new(MOUNTINFO_PATH).exists();
+    let options = match select_mount_source_method(findmnt_available, mountinfo_available) {
+        MountSourceMethod::Findmnt => Some(
+            run_mount_query(Command::new("findmnt").args([
+                "-n",
+                "-o",
+                "VFS-OPTIONS,FS-OPTIONS",
+                path,
+            ]))
+            .await?
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(","),
+        ),
+        MountSourceMethod::Mountinfo => {
+            let mountinfo = tokio::fs::read_to_string(MOUNTINFO_PATH)
+                .await
+                .map_err(|e| Status::internal(format!("Failed to read mountinfo: {}", e)))?;
+            mountinfo_options(&mountinfo, path)
+        }
+        MountSourceMethod::Df => None,
+    };
+
+    Ok(options.is_some_and(|o| options_read_only(&o)))
+}
+
+/// Per-mount and superblock options of the topmost mount at `target`,
+/// joined into one comma-separated list.
+fn mountinfo_options(mountinfo: &str, target: &str) -> Option<String> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let (fields, rest) = line.split_once(" - ")?;
+            let mut fields = fields.split_whitespace();
+            let mount_point = fields.nth(4)?;
+            if unescape_mount_path(mount_point) != target {
+                return None;
+            }
+            let mount_options = fields.next()?;
+            let super_options = rest.split_whitespace().nth(2).unwrap_or("");
+            Some(format!("{},{}", mount_options, super_options))
+        })
+        .next_back()
+}
+
+/// Whether a comma-separated mount option list marks the mount read-only
+fn options_read_only(options: &str) -> bool {
+    options.split(',').any(|o| o.trim() == "ro")
+}
+
+fn mountinfo_source(mountinfo: &str, target: &str) -> Option<String> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let (fields, rest) = line.split_once(" - ")?;
+            let mount_point = fields.split_whitespace().nth(4)?;
+            if unescape_mount_path(mount_point) != target {
+                return None;
+            }
+            rest.split_whitespace().nth(1).map(unescape_mount_path)
+        })
+        .next_back()
+}
+
+/// Undo the octal escaping (`\040` for space etc.) used in mount tables
+fn unescape_mount_path(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4);
+        if bytes[i] == b'\\'
+            && let Some(digits) = escape
+            && digits.iter().all(|d| (b'0'..=b'7').contains(d))
+        {
+            out.push((digits[0] - b'0') * 64 + (digits[1] - b'0') * 8 + (digits[2] - b'0'));
+            i += 4;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Extract the filesystem source from `df -P <target>` output.
+///
+/// `df` reports the filesystem containing a path, so the entry only counts
+/// when its mount point (last column) is `target` itself.
+fn df_source(output: &str, target: &str) -> Option<String> {
+    let line = output.lines().nth(1)?;
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 6 || fields[5..].join(" ") != target {
+        return None;
+    }
+    Some(fields[0].to_string())
+}
+
 /// Validate filesystem type for Linux.
 pub fn validate_fs_type(fs_type: &str) -> PlatformResult<&'static str> {
     match fs_type.to_lowercase().as_str() {
@@ -1275,9 +2452,476 @@ pub fn default_fs_type() -> &'static str {
     DEFAULT_FS_TYPE
 }
 
+/// open-iscsi's initiator name file
+pub const ISCSI_INITIATOR_NAME_FILE: &str = "/etc/iscsi/initiatorname.iscsi";
+
+/// nvme-cli's host NQN file
+pub const NVME_HOST_NQN_FILE: &str = "/etc/nvme/hostnqn";
+
+/// Initiator IQN from the contents of an initiatorname.iscsi file
+fn parse_initiator_name(content: &str) -> Option<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| line.strip_prefix("InitiatorName="))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+}
+
+/// Host NQN from the contents of a hostnqn file
+fn parse_host_nqn(content: &str) -> Option<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("nqn."))
+        .map(String::from)
+}
+
+/// This node's iSCSI initiator name, read from `path`.
+///
+/// When the file is missing or has no name, a new one is generated the way
+/// `iscsi-iname` does and written to `path` so iscsid and later calls use
+/// the same name.
+pub async fn ensure_initiator_name(path: &Path) -> PlatformResult<String> {
+    ensure_identity(path, parse_initiator_name, || {
+        let iqn = format!(
+            "iqn.2016-04.com.open-iscsi:{}",
+            &uuid::Uuid::new_v4().simple().to_string()[..12]
+        );
+        let content = format!("InitiatorName={}\n", iqn);
+        (iqn, content)
+    })
+    .await
+}
+
+/// This node's NVMe host NQN, read from `path`.
+///
+/// When missing, a UUID-based NQN is generated (as `nvme gen-hostnqn` does)
+/// and written to `path`.
+pub async fn ensure_host_nqn(path: &Path) -> PlatformResult<String> {
+    ensure_identity(path, parse_host_nqn, || {
+        let nqn = format!("nqn.2014-08.org.nvmexpress:uuid:{}", uuid::Uuid::new_v4());
+        let content = format!("{}\n", nqn);
+        (nqn, content)
+    })
+    .await
+}
+
+/// Read an identity from `path`, generating and persisting one if absent
+async fn ensure_identity(
+    path: &Path,
+    parse: fn(&str) -> Option<String>,
+    generate: impl FnOnce() -> (String, String),
+) -> PlatformResult<String> {
+    if let Ok(content) = tokio::fs::read_to_string(path).await
+        && let Some(identity) = parse(&content)
+    {
+        return Ok(identity);
+    }
+
+    let (identity, content) = generate();
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(|e| {
+            Status::internal(format!("Failed to create {}: {}", parent.display(), e))
+        })?;
+    }
+    tokio::fs::write(path, content)
+        .await
+        .map_err(|e| Status::internal(format!("Failed to write {}: {}", path.display(), e)))?;
+    info!(path = %path.display(), identity = %identity, "Generated initiator identity");
+    Ok(identity)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Fake /dev with kernel device nodes and /dev/disk link directories
+    struct DevFixture(std::path::PathBuf);
+
+    impl DevFixture {
+        fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("csi-dev-test-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            for dir in ["disk/by-id", "disk/by-uuid"] {
+                std::fs::create_dir_all(root.join(dir)).unwrap();
+            }
+            Self(root)
+        }
+
+        fn device(&self, name: &str) -> String {
+            let path = self.0.join(name);
+            std::fs::write(&path, b"").unwrap();
+            path.to_string_lossy().into_owned()
+        }
+
+        fn link(&self, dir: &str, name: &str, device: &str) {
+            let target = format!("../../{}", device);
+            std::os::unix::fs::symlink(target, self.0.join("disk").join(dir).join(name)).unwrap();
+        }
+
+        fn disk_dir(&self) -> std::path::PathBuf {
+            self.0.join("disk")
+        }
+    }
+
+    impl Drop for DevFixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_find_stable_link_prefers_identity_by_id() {
+        let dev = DevFixture::new("by-id");
+        let sdb = dev.device("sdb");
+        dev.device("sdc");
+        dev.link("by-id", "scsi-SFreeBSD_CTLDISK_pvc-a", "sdb");
+        dev.link("by-id", "wwn-0x6589cfc000000a1b", "sdb");
+        dev.link("by-id", "wwn-0x6589cfc000000c2d", "sdc");
+        dev.link("by-uuid", "0f3c5e2a-7d41-4c1e-9b0e-2f6d8a1c4e55", "sdb");
+
+        let link = find_stable_link(&dev.disk_dir(), &sdb).await.unwrap();
+        assert!(link.ends_with("by-id/wwn-0x6589cfc000000a1b"), "{}", link);
+        // The link resolves back to the device it stands for
+        assert_eq!(
+            std::fs::canonicalize(&link).unwrap(),
+            std::fs::canonicalize(&sdb).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_stable_link_falls_back_to_by_uuid() {
+        let dev = DevFixture::new("by-uuid");
+        let dm = dev.device("dm-3");
+        let sdd = dev.device("sdd");
+        dev.link("by-uuid", "0f3c5e2a-7d41-4c1e-9b0e-2f6d8a1c4e55", "dm-3");
+
+        let link = find_stable_link(&dev.disk_dir(), &dm).await.unwrap();
+        assert!(link.ends_with("by-uuid/0f3c5e2a-7d41-4c1e-9b0e-2f6d8a1c4e55"));
+
+        // No persistent link at all: keep the kernel name
+        assert_eq!(find_stable_link(&dev.disk_dir(), &sdd).await, None);
+        assert_eq!(
+            find_stable_link(&dev.disk_dir(), "/nonexistent/sdz").await,
+            None
+        );
+    }
+
+    #[test]
+    fn test_by_uuid_link_name_and_blkid_devices() {
+        assert_eq!(
+            by_uuid_link_name("/dev/disk/by-uuid/0f3c5e2a-7d41-4c1e-9b0e-2f6d8a1c4e55"),
+            Some("0f3c5e2a-7d41-4c1e-9b0e-2f6d8a1c4e55")
+        );
+        assert_eq!(
+            by_uuid_link_name("/dev/disk/by-id/wwn-0x6589cfc000000a1b"),
+            None
+        );
+        assert_eq!(by_uuid_link_name("/dev/sdb"), None);
+
+        assert_eq!(
+            parse_blkid_devices("/dev/sdb\n/dev/sdc\n\n"),
+            ["/dev/sdb", "/dev/sdc"]
+        );
+        assert!(parse_blkid_devices("").is_empty());
+    }
+
+    #[test]
+    fn test_stable_link_rank() {
+        assert_eq!(stable_link_rank("wwn-0x6589cfc000000a1b"), 0);
+        assert_eq!(stable_link_rank("dm-uuid-mpath-36589cfc000000a1b"), 0);
+        assert_eq!(stable_link_rank("nvme-eui.6589cfc000000a1b"), 0);
+        assert_eq!(stable_link_rank("nvme-FreeBSD_CTL_pvc-a_1"), 1);
+        assert_eq!(stable_link_rank("dm-name-mpatha"), 2);
+    }
+
+    #[test]
+    fn test_parse_initiator_name() {
+        let content = "## DO NOT EDIT OR REMOVE THIS FILE!\n\
+                       # InitiatorName=iqn.commented.out\n\
+                       InitiatorName=iqn.2004-10.com.ubuntu:01:5f8e2b1c9a7d\n";
+        assert_eq!(
+            parse_initiator_name(content).as_deref(),
+            Some("iqn.2004-10.com.ubuntu:01:5f8e2b1c9a7d")
+        );
+        assert_eq!(parse_initiator_name("InitiatorName=\n"), None);
+        assert_eq!(parse_initiator_name("# nothing here\n"), None);
+    }
+
+    #[test]
+    fn test_parse_host_nqn() {
+        assert_eq!(
+            parse_host_nqn(
+                "nqn.2014-08.org.nvmexpress:uuid:9b1f2c3d-4e5f-4a6b-8c7d-0e1f2a3b4c5d\n"
+            )
+            .as_deref(),
+            Some("nqn.2014-08.org.nvmexpress:uuid:9b1f2c3d-4e5f-4a6b-8c7d-0e1f2a3b4c5d")
+        );
+        assert_eq!(parse_host_nqn("\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_ensure_initiator_identities_generate_if_missing() {
+        let dir = std::env::temp_dir().join(format!("csi-initiator-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let iscsi_file = dir.join("iscsi/initiatorname.iscsi");
+        let nvme_file = dir.join("nvme/hostnqn");
+
+        let iqn = ensure_initiator_name(&iscsi_file).await.unwrap();
+        assert!(iqn.starts_with("iqn.2016-04.com.open-iscsi:"), "{}", iqn);
+        let written = std::fs::read_to_string(&iscsi_file).unwrap();
+        assert_eq!(written, format!("InitiatorName={}\n", iqn));
+        // Stable: the persisted name is reused
+        assert_eq!(ensure_initiator_name(&iscsi_file).await.unwrap(), iqn);
+
+        let nqn = ensure_host_nqn(&nvme_file).await.unwrap();
+        assert!(
+            nqn.starts_with("nqn.2014-08.org.nvmexpress:uuid:"),
+            "{}",
+            nqn
+        );
+        assert_eq!(ensure_host_nqn(&nvme_file).await.unwrap(), nqn);
+
+        // An existing name is never replaced
+        std::fs::write(&iscsi_file, "InitiatorName=iqn.2004-10.com.ubuntu:01:abc\n").unwrap();
+        assert_eq!(
+            ensure_initiator_name(&iscsi_file).await.unwrap(),
+            "iqn.2004-10.com.ubuntu:01:abc"
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Sets its flag when dropped, i.e. when the owning future is aborted
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hanging_connect_is_aborted_and_cleaned_up() {
+        let endpoint = Endpoint::new("10.0.0.1", 3260);
+        let aborted = Arc::new(AtomicBool::new(false));
+        let cleaned = Arc::new(AtomicBool::new(false));
+
+        let guard = DropFlag(aborted.clone());
+        let hanging = async move {
+            let _guard = guard;
+            std::future::pending::<PlatformResult<bool>>().await
+        };
+        let err = connect_endpoint_with_timeout(
+            &endpoint,
+            Duration::from_millis(20),
+            hanging,
+            || async { cleaned.store(true, Ordering::SeqCst) },
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(err.code(), tonic::Code::DeadlineExceeded);
+        assert!(err.message().contains("10.0.0.1:3260"));
+        assert!(aborted.load(Ordering::SeqCst));
+        assert!(cleaned.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_connect_within_timeout_skips_cleanup() {
+        let endpoint = Endpoint::new("10.0.0.1", 4420);
+        let cleaned = AtomicBool::new(false);
+
+        let connected = connect_endpoint_with_timeout(
+            &endpoint,
+            Duration::from_secs(5),
+            async { Ok(true) },
+            || async { cleaned.store(true, Ordering::SeqCst) },
+        )
+        .await
+        .unwrap();
+
+        assert!(connected);
+        assert!(!cleaned.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_mountinfo_options_detects_read_only() {
+        let mountinfo = "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+                         98 22 259:3 / /stage/a rw,relatime - ext4 /dev/nvme1n1 rw,errors=remount-ro\n\
+                         99 22 8:32 / /stage/b rw,relatime - ext4 /dev/sdc ro,errors=remount-ro\n\
+                         100 22 8:48 / /stage/c ro,relatime - xfs /dev/sdd ro,attr2\n";
+
+        let healthy = mountinfo_options(mountinfo, "/stage/a").unwrap();
+        assert!(!options_read_only(&healthy));
+
+        // errors=remount-ro only flips the superblock options
+        let remounted = mountinfo_options(mountinfo, "/stage/b").unwrap();
+        assert!(options_read_only(&remounted));
+
+        let read_only = mountinfo_options(mountinfo, "/stage/c").unwrap();
+        assert!(options_read_only(&read_only));
+
+        assert_eq!(mountinfo_options(mountinfo, "/stage/missing"), None);
+    }
+
+    #[test]
+    fn test_options_read_only_matches_whole_option() {
+        assert!(options_read_only("ro,relatime"));
+        assert!(options_read_only("rw,relatime,ro"));
+        assert!(!options_read_only("rw,errors=remount-ro"));
+        assert!(!options_read_only("rw,norecovery"));
+        assert!(!options_read_only(""));
+    }
+
+    #[test]
+    fn test_mountinfo_source_resolves_device() {
+        let staging = "/var/lib/kubelet/plugins/kubernetes.io/csi/csi.freebsd.org/abc/globalmount";
+        let mountinfo = format!(
+            "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+             98 22 259:3 / {} rw,relatime shared:40 - ext4 /dev/nvme1n1 rw\n\
+             99 22 8:32 / /mnt/with\\040space rw - xfs /dev/sdc rw\n",
+            staging
+        );
+
+        assert_eq!(
+            mountinfo_source(&mountinfo, staging).as_deref(),
+            Some("/dev/nvme1n1")
+        );
+        assert_eq!(
+            mountinfo_source(&mountinfo, "/mnt/with space").as_deref(),
+            Some("/dev/sdc")
+        );
+        assert_eq!(mountinfo_source(&mountinfo, "/var/lib"), None);
+
+        // The topmost of stacked mounts wins
+        let stacked = format!(
+            "{}100 98 8:48 / {} rw - ext4 /dev/sdd rw\n",
+            mountinfo, staging
+        );
+        assert_eq!(
+            mountinfo_source(&stacked, staging).as_deref(),
+            Some("/dev/sdd")
+        );
+    }
+
+    #[test]
+    fn test_df_source_requires_exact_mount_point() {
+        let output = "Filesystem 1024-blocks Used Available Capacity Mounted on\n\
+                      /dev/da1p1 10321208 123 9668796 1% /mnt/vol\n";
+        assert_eq!(df_source(output, "/mnt/vol").as_deref(), Some("/dev/da1p1"));
+        // A path inside the filesystem is not its mount point
+        assert_eq!(df_source(output, "/mnt/vol/sub"), None);
+        assert_eq!(df_source("", "/mnt/vol"), None);
+    }
+
+    #[test]
+    fn test_mount_source_method_selection() {
+        assert_eq!(
+            select_mount_source_method(true, true),
+            MountSourceMethod::Findmnt
+        );
+        assert_eq!(
+            select_mount_source_method(false, true),
+            MountSourceMethod::Mountinfo
+        );
+        assert_eq!(
+            select_mount_source_method(false, false),
+            MountSourceMethod::Df
+        );
+    }
+
+    #[test]
+    fn test_mounts_contain_detects_lost_staging_mount() {
+        let staging = "/var/lib/kubelet/plugins/kubernetes.io/csi/csi.freebsd.org/abc/globalmount";
+        let before_reboot = format!(
+            "/dev/sda1 / ext4 rw 0 0\n/dev/sdb {} ext4 rw,relatime 0 0\n",
+            staging
+        );
+        let after_reboot = "/dev/sda1 / ext4 rw 0 0\n";
+
+        assert!(mounts_contain(&before_reboot, staging));
+        assert!(!mounts_contain(after_reboot, staging));
+        // A mount below the staging path does not count
+        assert!(!mounts_contain(
+            &format!("/dev/sdc {}/sub ext4 rw 0 0\n", staging),
+            staging
+        ));
+    }
+
+    #[test]
+    fn test_parse_iscsi_session_targets() {
+        let output = "\
+tcp: [1] 10.0.0.1:3260,1 iqn.2024-01.org.freebsd.csi:pvc-1 (non-flash)
+tcp: [2] 10.0.0.2:3260,1 iqn.2024-01.org.freebsd.csi:pvc-1 (non-flash)
+tcp: [3] [fd00::1]:3260,1 iqn.2024-01.org.freebsd.csi:pvc-2 (non-flash)
+";
+        assert_eq!(
+            parse_iscsi_session_targets(output),
+            [
+                "iqn.2024-01.org.freebsd.csi:pvc-1",
+                "iqn.2024-01.org.freebsd.csi:pvc-1",
+                "iqn.2024-01.org.freebsd.csi:pvc-2"
+            ]
+        );
+        assert!(parse_iscsi_session_targets("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_iscsi_session_states() {
+        let output = "\
+Target: iqn.2024-01.org.freebsd.csi:pvc-1 (non-flash)
+\tCurrent Portal: 10.0.0.1:3260,1
+\tPersistent Portal: 10.0.0.1:3260,1
+\t\tiSCSI Connection State: LOGGED IN
+\t\tiSCSI Session State: LOGGED_IN
+\tCurrent Portal: 10.0.0.2:3260,1
+\tPersistent Portal: 10.0.0.2:3260,1
+\t\tiSCSI Connection State: TRANSPORT WAIT
+\t\tiSCSI Session State: FAILED
+Target: iqn.2024-01.org.freebsd.csi:pvc-2 (non-flash)
+\tCurrent Portal: 10.0.0.3:3260,1
+\tPersistent Portal: 10.0.0.3:3260,1
+\t\tiSCSI Session State: LOGGED_IN
+";
+        let states = parse_iscsi_session_states(output, "iqn.2024-01.org.freebsd.csi:pvc-1");
+
+        assert_eq!(states.len(), 2);
+        assert_eq!(
+            states.get(&Endpoint::new("10.0.0.1", 3260)),
+            Some(&PathState::Live)
+        );
+        assert_eq!(
+            states.get(&Endpoint::new("10.0.0.2", 3260)),
+            Some(&PathState::Recovering)
+        );
+        // Sessions of other targets are ignored
+        assert!(!states.contains_key(&Endpoint::new("10.0.0.3", 3260)));
+    }
+
+    #[test]
+    fn test_parse_nvme_ctrl_address_and_state() {
+        assert_eq!(
+            parse_nvme_ctrl_address("traddr=10.0.0.1,trsvcid=4420,src_addr=10.0.0.9\n"),
+            Some(Endpoint::new("10.0.0.1", 4420))
+        );
+        assert_eq!(
+            parse_nvme_ctrl_address("traddr=fd00::1,trsvcid=4420"),
+            Some(Endpoint::new("fd00::1", 4420))
+        );
+        assert_eq!(parse_nvme_ctrl_address("traddr=10.0.0.1"), None);
+
+        assert_eq!(nvme_ctrl_path_state("live\n"), PathState::Live);
+        assert_eq!(nvme_ctrl_path_state("connecting"), PathState::Recovering);
+        assert_eq!(nvme_ctrl_path_state("deleting (no IO)"), PathState::Failed);
+        assert_eq!(nvme_ctrl_path_state("dead"), PathState::Failed);
+    }
 
     #[test]
     fn test_validate_fs_type_valid() {
@@ -1287,6 +2931,138 @@ mod tests {
         assert_eq!(validate_fs_type("EXT4").unwrap(), "ext4");
     }
 
+    #[test]
+    fn test_mount_options_direct_io() {
+        let flags = |f: &[&str]| f.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert!(mount_options("ext4", &[], false).unwrap().is_empty());
+        assert_eq!(
+            mount_options("ext4", &flags(&["noatime,discard"]), false).unwrap(),
+            ["noatime", "discard"]
+        );
+
+        assert_eq!(
+            mount_options("ext4", &flags(&["noatime"]), true).unwrap(),
+            ["noatime", "dioread_nolock"]
+        );
+        // Already requested: not duplicated
+        assert_eq!(
+            mount_options("ext4", &flags(&["dioread_nolock"]), true).unwrap(),
+            ["dioread_nolock"]
+        );
+        // XFS handles O_DIRECT without extra options
+        assert_eq!(
+            mount_options("xfs", &flags(&["noatime"]), true).unwrap(),
+            ["noatime"]
+        );
+
+        for conflict in ["dioread_lock", "data=journal"] {
+            let err = mount_options("ext4", &flags(&[conflict]), true).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument);
+            assert!(err.message().contains(conflict));
+            // Without directIo the flag is passed through
+            assert!(mount_options("ext4", &flags(&[conflict]), false).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_format_options() {
+        assert!(format_options("ext4", "").unwrap().is_empty());
+        assert_eq!(
+            format_options("ext4", " -E stride=16,stripe-width=64  -i 65536 ").unwrap(),
+            ["-E", "stride=16,stripe-width=64", "-i", "65536"]
+        );
+        assert_eq!(
+            format_options("XFS", "-d su=64k,sw=4 -L data").unwrap(),
+            ["-d", "su=64k,sw=4", "-L", "data"]
+        );
+        // Only the data section's size is rejected, not the log's
+        assert!(format_options("xfs", "-l size=64m").is_ok());
+
+        for (fs_type, bad) in [
+            // Not allowlisted: dry run, fs size, external journal, force
+            ("ext4", "-n"),
+            ("ext4", "-J device=sdb"),
+            ("xfs", "-f"),
+            ("xfs", "-E stride=16"),
+            // Flag without a value, or a stray positional argument
+            ("ext4", "-E"),
+            ("ext4", "-b -i 4096"),
+            ("ext4", "1000000"),
+            // Another format target
+            ("xfs", "-d name=sdb"),
+            ("xfs", "-l logdev=sdc,size=64m"),
+            ("ext4", "-E root_owner=0:0,offset=0 -L /dev/sdb"),
+            ("ext4", "-L $(id)"),
+            ("zfs", "-b 4096"),
+            // Filesystem smaller than or offset into the volume
+            ("xfs", "-d su=64k,size=1g"),
+            ("ext4", "-E offset=4096"),
+        ] {
+            let err = format_options(fs_type, bad).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument, "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_mount_options_rejects_metacharacters() {
+        let flags = |f: &[&str]| f.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            mount_options(
+                "xfs",
+                &flags(&["nodev", "noatime, discard", "uid=1000,gid=1000"]),
+                false
+            )
+            .unwrap(),
+            ["nodev", "noatime", "discard", "uid=1000", "gid=1000"]
+        );
+        // MCS category lists are not split on their commas
+        assert_eq!(
+            mount_options(
+                "ext4",
+                &flags(&["noatime,context=\"system_u:object_r:foo:s0:c1,c2\",nodev"]),
+                false
+            )
+            .unwrap(),
+            [
+                "noatime",
+                "context=\"system_u:object_r:foo:s0:c1,c2\"",
+                "nodev"
+            ]
+        );
+        // SELinux contexts keep their quotes and colons
+        assert!(
+            mount_options(
+                "ext4",
+                &flags(&["context=\"system_u:object_r:container_file_t:s0\""]),
+                false
+            )
+            .is_ok()
+        );
+
+        for bad in [
+            "noatime;reboot",
+            "ro|rw",
+            "x=$(id)",
+            "x=`id`",
+            "a&b",
+            "a>b",
+            "a\\b",
+            "it's",
+            "no atime",
+            "noa\ntime",
+        ] {
+            let err = mount_options("ext4", &flags(&["nodev", bad]), false).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument, "{bad:?}");
+            // Names the offending flag by position, not content
+            assert_eq!(
+                err.message(),
+                "mount option 2 contains a forbidden character"
+            );
+        }
+    }
+
     #[test]
     fn test_validate_fs_type_invalid() {
         assert!(validate_fs_type("ufs").is_err());
@@ -1320,6 +3096,140 @@ mod tests {
         assert!(!is_nvme_namespace_device("/dev/nvme0n")); // Missing namespace number
     }
 
+    #[test]
+    fn test_build_iscsi_discovery_args() {
+        let args = build_iscsi_discovery_args("10.0.0.10:3260");
+
+        assert_eq!(
+            args,
+            vec![
+                "-m",
+                "discovery",
+                "-t",
+                "sendtargets",
+                "-p",
+                "10.0.0.10:3260"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_discovery_log_endpoints() {
+        let output = r#"{
+  "device": "nvme0",
+  "genctr": 3,
+  "records": [
+    {"trtype": "tcp", "adrfam": "ipv4", "subtype": "current discovery subsystem",
+     "trsvcid": "8009", "subnqn": "nqn.2014-08.org.nvmexpress.discovery", "traddr": "10.0.0.1"},
+    {"trtype": "tcp", "adrfam": "ipv4", "subtype": "nvme subsystem",
+     "trsvcid": "4420", "subnqn": "nqn.2024-01.org.freebsd.csi:pvc-1", "traddr": "10.0.0.1"},
+    {"trtype": "tcp", "adrfam": "ipv6", "subtype": "nvme subsystem",
+     "trsvcid": "4420", "subnqn": "nqn.2024-01.org.freebsd.csi:pvc-1", "traddr": "2001:db8::1"},
+    {"trtype": "tcp", "adrfam": "ipv4", "subtype": "nvme subsystem",
+     "trsvcid": "4420", "subnqn": "nqn.2024-01.org.freebsd.csi:pvc-12", "traddr": "10.0.0.2"}
+  ]
+}"#;
+
+        assert_eq!(
+            find_discovery_log_endpoints(output, "nqn.2024-01.org.freebsd.csi:pvc-1"),
+            vec![
+                Endpoint::new("10.0.0.1", 4420),
+                Endpoint::new("2001:db8::1", 4420)
+            ]
+        );
+        assert!(find_discovery_log_endpoints(output, "nqn.2024-01.org.freebsd.csi:pvc").is_empty());
+        assert!(find_discovery_log_endpoints("not json", "nqn.x:y").is_empty());
+        assert!(find_discovery_log_endpoints("{}", "nqn.x:y").is_empty());
+    }
+
+    #[test]
+    fn test_find_sendtargets_portal_matches_exact_iqn() {
+        let output = "\
+10.0.0.10:3260,1 iqn.2024-01.org.freebsd.csi:pvc-1
+10.0.0.10:3260,1 iqn.2024-01.org.freebsd.csi:pvc-12
+[2001:db8::1]:3260,2 iqn.2024-01.org.freebsd.csi:pvc-12
+";
+
+        assert_eq!(
+            find_sendtargets_portal(
+                output,
+                "iqn.2024-01.org.freebsd.csi:pvc-1",
+                "10.0.0.10:3260"
+            ),
+            Some("10.0.0.10:3260".to_string())
+        );
+        assert_eq!(
+            find_sendtargets_portal(
+                output,
+                "iqn.2024-01.org.freebsd.csi:pvc-12",
+                "10.0.0.10:3260"
+            ),
+            Some("10.0.0.10:3260".to_string())
+        );
+    }
+
+    #[test]
+    fn test_find_sendtargets_portal_prefers_queried_portal() {
+        let output = "\
+10.0.0.10:3260,1 iqn.2024-01.org.freebsd.csi:pvc-1
+10.0.1.10:3260,1 iqn.2024-01.org.freebsd.csi:pvc-1
+";
+
+        assert_eq!(
+            find_sendtargets_portal(
+                output,
+                "iqn.2024-01.org.freebsd.csi:pvc-1",
+                "10.0.1.10:3260"
+            ),
+            Some("10.0.1.10:3260".to_string())
+        );
+        // A portal not in the listing (e.g. a DNS name) gets the first entry
+        assert_eq!(
+            find_sendtargets_portal(
+                output,
+                "iqn.2024-01.org.freebsd.csi:pvc-1",
+                "storage.example.com:3260"
+            ),
+            Some("10.0.0.10:3260".to_string())
+        );
+    }
+
+    #[test]
+    fn test_find_sendtargets_portal_ipv6() {
+        let output = "[2001:db8::1]:3260,2 iqn.2024-01.org.freebsd.csi:pvc-6\n";
+
+        assert_eq!(
+            find_sendtargets_portal(
+                output,
+                "iqn.2024-01.org.freebsd.csi:pvc-6",
+                "[2001:db8::1]:3260"
+            ),
+            Some("[2001:db8::1]:3260".to_string())
+        );
+    }
+
+    #[test]
+    fn test_find_sendtargets_portal_no_match() {
+        let output = "10.0.0.10:3260,1 iqn.2024-01.org.freebsd.csi:pvc-10\n";
+
+        assert_eq!(
+            find_sendtargets_portal(
+                output,
+                "iqn.2024-01.org.freebsd.csi:pvc-1",
+                "10.0.0.10:3260"
+            ),
+            None
+        );
+        assert_eq!(
+            find_sendtargets_portal("", "iqn.x:y", "10.0.0.10:3260"),
+            None
+        );
+        assert_eq!(
+            find_sendtargets_portal("iscsiadm: No portals found\n", "iqn.x:y", "10.0.0.10:3260"),
+            None
+        );
+    }
+
     #[test]
     fn test_build_nvme_connect_args_includes_connect_options() {
         let endpoint = Endpoint::new("10.0.0.10", 4420);
@@ -10,8 +10,14 @@
 //! use crate::types::{Endpoint, Endpoints};
 //!
 //! let endpoints = Endpoints::parse("10.0.0.1:3260,10.0.0.2:3260", 3260)?;
-//! let device = platform::connect_iscsi(target_iqn, endpoints.as_slice())?;
-//! platform::format_device(&device, "ext4")?;
+//! let device = platform::connect_iscsi(
+//!     target_iqn,
+//!     endpoints.as_slice(),
+//!     None,
+//!     None,
+//!     platform::DEFAULT_CONNECT_TIMEOUT,
+//! )?;
+//! platform::format_device(&device, "ext4", &[])?;
 //! ```
 
 mod linux;
@@ -23,8 +29,12 @@ pub type PlatformResult<T> = Result<T, Status>;
 
 // Re-export all platform functions and types
 pub use linux::{
-    IscsiChapCredentials, NvmeAuthCredentials, bind_mount, connect_iscsi, connect_nvmeof,
-    default_fs_type, disconnect_iscsi, disconnect_nvmeof, find_iscsi_device, find_nvmeof_device,
-    format_device, is_iscsi_connected, is_mounted, is_nvmeof_connected, mount_device,
-    needs_formatting, unmount, validate_fs_type,
+    DEFAULT_CONNECT_TIMEOUT, ISCSI_INITIATOR_NAME_FILE, IscsiChapCredentials, NVME_HOST_NQN_FILE,
+    NvmeAuthCredentials, PathState, bind_mount, connect_iscsi, connect_nvmeof, connect_nvmeof_path,
+    default_fs_type, disconnect_iscsi, disconnect_nvmeof, discover_nvmeof_endpoints,
+    ensure_host_nqn, ensure_initiator_name, find_iscsi_device, find_mount_source,
+    find_nvmeof_device, format_device, format_options, is_iscsi_connected, is_mounted,
+    is_nvme_native_multipath_enabled, is_nvmeof_connected, is_read_only_mount, iscsi_path_states,
+    iscsi_session_targets, login_iscsi_portal, mount_device, mount_options, needs_formatting,
+    nvmeof_path_states, nvmeof_subsystem_nqns, stable_device_path, unmount, validate_fs_type,
 };
@@ -0,0 +1,173 @@
+//! CSI socket directory preparation.
+//!
+//! The CSI endpoint is a Unix socket that kubelet and the sidecars connect to.
+//! Anyone who can write to the socket's directory can swap the socket for one
+//! of their own, so the directory is validated before binding: it must be a
+//! real directory (not a symlink), owned by root or the driver's user, and not
+//! writable by other users. A missing directory is created with a restrictive
+//! mode.
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::Path;
+
+/// Mode used when creating a missing socket directory
+pub const SOCKET_DIR_MODE: u32 = 0o750;
+
+/// Validate (or create) the directory that will hold the CSI socket.
+///
+/// Refuses a parent that is a symlink, is not a directory, is owned by an
+/// unexpected user, or is world-writable.
+pub fn prepare_socket_parent(parent: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(parent) {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(SOCKET_DIR_MODE)
+                .create(parent)?;
+        }
+        Err(e) => return Err(e),
+    }
+
+    validate_socket_parent(parent, effective_uid())
+}
+
+fn validate_socket_parent(parent: &Path, uid: u32) -> io::Result<()> {
+    let meta = fs::symlink_metadata(parent)?;
+
+    if meta.file_type().is_symlink() {
+        let target = fs::read_link(parent)
+            .map(|t| t.display().to_string())
+            .unwrap_or_else(|_| "<unreadable>".to_string());
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "socket directory {} is a symlink to {}; refusing to bind",
+                parent.display(),
+                target
+            ),
+        ));
+    }
+
+    if !meta.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("socket parent {} is not a directory", parent.display()),
+        ));
+    }
+
+    let owner = meta.uid();
+    if owner != 0 && owner != uid {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "socket directory {} is owned by uid {}, expected root or the driver user",
+                parent.display(),
+                owner
+            ),
+        ));
+    }
+
+    // A sticky world-writable directory (e.g. /tmp) still lets other users
+    // create entries, so reject any world-writable bit.
+    if meta.mode() & 0o002 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "socket directory {} is world-writable (mode {:o})",
+                parent.display(),
+                meta.mode() & 0o7777
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Effective uid of this process
+fn effective_uid() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{PermissionsExt, symlink};
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("csi-socket-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_rejects_symlink_parent() {
+        let base = scratch_dir("symlink");
+        let real = base.join("real");
+        fs::create_dir(&real).unwrap();
+        let link = base.join("link");
+        symlink(&real, &link).unwrap();
+
+        let err = prepare_socket_parent(&link).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(err.to_string().contains("symlink"));
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_creates_missing_dir_with_restrictive_mode() {
+        let base = scratch_dir("create");
+        let parent = base.join("csi");
+
+        prepare_socket_parent(&parent).unwrap();
+
+        let meta = fs::symlink_metadata(&parent).unwrap();
+        assert!(meta.is_dir());
+        // umask can only remove bits, never add group-write or other access
+        assert_eq!(meta.permissions().mode() & 0o027, 0);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_world_writable_dir() {
+        let base = scratch_dir("writable");
+        let parent = base.join("csi");
+        fs::create_dir(&parent).unwrap();
+        fs::set_permissions(&parent, fs::Permissions::from_mode(0o777)).unwrap();
+
+        let err = prepare_socket_parent(&parent).unwrap_err();
+        assert!(err.to_string().contains("world-writable"));
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_unexpected_owner_and_non_directory() {
+        let base = scratch_dir("owner");
+        let owner = fs::metadata(&base).unwrap().uid();
+
+        // Root-owned directories are always accepted; others must match our uid
+        assert!(validate_socket_parent(&base, owner).is_ok());
+        assert_eq!(effective_uid(), owner);
+        if owner != 0 {
+            assert!(validate_socket_parent(&base, owner.wrapping_add(1)).is_err());
+        } else {
+            assert!(validate_socket_parent(&base, 1000).is_ok());
+        }
+
+        let file = base.join("file");
+        fs::write(&file, b"").unwrap();
+        let err = validate_socket_parent(&file, owner).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -8,6 +8,9 @@
 use std::fmt::{self, Display};
 use std::str::FromStr;
 
+use csi_common::parameters::normalize_key;
+use serde::Serialize;
+
 use crate::agent;
 
 // ============================================================================
@@ -36,6 +39,43 @@ impl ExportType {
     }
 }
 
+/// Volume context key carrying the port for endpoints listed without one,
+/// set when the controller runs with a non-standard default port
+pub const DEFAULT_PORT_CONTEXT_KEY: &str = "defaultPort";
+
+/// StorageClass parameter (and volume context key) with extra mkfs
+/// arguments, applied only when a volume is formatted for the first time
+pub const MKFS_OPTIONS_PARAM: &str = "mkfsOptions";
+
+/// Ports assumed for endpoints given without one.
+///
+/// Defaults to the IANA ports; deployments listening elsewhere can change
+/// them instead of spelling the port out in every `endpoints` entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultPorts {
+    pub iscsi: u16,
+    pub nvmeof: u16,
+}
+
+impl Default for DefaultPorts {
+    fn default() -> Self {
+        Self {
+            iscsi: ExportType::Iscsi.default_port(),
+            nvmeof: ExportType::Nvmeof.default_port(),
+        }
+    }
+}
+
+impl DefaultPorts {
+    /// Configured port for `export_type`
+    pub const fn port(self, export_type: ExportType) -> u16 {
+        match export_type {
+            ExportType::Iscsi => self.iscsi,
+            ExportType::Nvmeof => self.nvmeof,
+        }
+    }
+}
+
 impl Display for ExportType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -100,6 +140,30 @@ pub enum CloneMode {
     Copy,
 }
 
+impl CloneMode {
+    /// StorageClass parameter requiring volumes to never share blocks with
+    /// their clone source
+    pub const STRONG_ISOLATION_PARAM: &'static str = "strongIsolation";
+
+    /// Apply a strong-isolation requirement to the requested clone mode.
+    ///
+    /// Linked (and server-default) clones share blocks with their source, so
+    /// they are upgraded to COPY. Returns the effective mode and, when the
+    /// request was overridden, the reason to log.
+    pub fn enforce_isolation(self, strong_isolation: bool) -> (Self, Option<&'static str>) {
+        if strong_isolation && self != CloneMode::Copy {
+            (
+                CloneMode::Copy,
+                Some(
+                    "strongIsolation requires an independent copy; linked clones share blocks with their source",
+                ),
+            )
+        } else {
+            (self, None)
+        }
+    }
+}
+
 impl Display for CloneMode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -216,6 +280,40 @@ impl Display for ProvisioningModeParseError {
 
 impl std::error::Error for ProvisioningModeParseError {}
 
+// ============================================================================
+// DirectIo
+// ============================================================================
+
+/// `directIo` StorageClass parameter.
+///
+/// A mount cannot force `O_DIRECT`; the workload still opens its files or
+/// block device with it. The node only tunes filesystem mounts so direct I/O
+/// performs well (see `platform::mount_options`).
+pub struct DirectIo;
+
+impl DirectIo {
+    /// Parameter name in StorageClass parameters and the volume context
+    pub const PARAM_NAME: &'static str = "directIo";
+
+    /// Whether direct I/O tuning was requested (false when unset).
+    pub fn from_parameters(
+        parameters: &std::collections::HashMap<String, String>,
+    ) -> Result<bool, String> {
+        match parameters.get(Self::PARAM_NAME) {
+            None => Ok(false),
+            Some(value) => match value.to_lowercase().as_str() {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(format!(
+                    "invalid {} '{}': expected true or false",
+                    Self::PARAM_NAME,
+                    value
+                )),
+            },
+        }
+    }
+}
+
 // ============================================================================
 // NvmeofConnectOptions
 // ============================================================================
@@ -391,6 +489,275 @@ impl Display for NvmeofConnectOptionsParseError {
 
 impl std::error::Error for NvmeofConnectOptionsParseError {}
 
+// ============================================================================
+// NvmeofDiscovery
+// ============================================================================
+
+/// Well-known NQN of NVMe discovery controllers
+pub const NVME_DISCOVERY_NQN: &str = "nqn.2014-08.org.nvmexpress.discovery";
+
+/// NVMe discovery controllers the node queries for the target's endpoints.
+///
+/// ctld serves the discovery controller itself from any transport-group
+/// with a `discovery-tcp` listener, so nothing is rendered per volume.
+#[derive(Debug, Clone)]
+pub struct NvmeofDiscovery {
+    /// Discovery controller endpoints
+    pub endpoints: Endpoints,
+}
+
+impl NvmeofDiscovery {
+    pub const DISCOVERY_PARAM: &'static str = "nvmeof.discovery";
+    pub const ENDPOINTS_PARAM: &'static str = "nvmeof.discoveryEndpoints";
+
+    pub const PARAM_NAMES: &'static [&'static str] =
+        &[Self::DISCOVERY_PARAM, Self::ENDPOINTS_PARAM];
+
+    /// Volume context key carrying the discovery controller NQN
+    pub const NQN_CONTEXT_KEY: &'static str = "discoveryNqn";
+    /// Volume context key carrying the discovery controller endpoints
+    pub const ENDPOINTS_CONTEXT_KEY: &'static str = "discoveryEndpoints";
+
+    /// Default NVMe/TCP discovery port
+    pub const DEFAULT_PORT: u16 = 8009;
+
+    /// Discovery settings from StorageClass parameters (None = direct connect).
+    ///
+    /// Without `nvmeof.discoveryEndpoints` the hosts of `endpoints` are
+    /// queried on the default discovery port.
+    pub fn from_parameters(
+        parameters: &std::collections::HashMap<String, String>,
+    ) -> Result<Option<Self>, NvmeofConnectOptionsParseError> {
+        match parameters.get(Self::DISCOVERY_PARAM) {
+            None => return Ok(None),
+            Some(value) => match value.to_lowercase().as_str() {
+                "" | "direct" | "none" => return Ok(None),
+                "discovery" => {}
+                _ => {
+                    return Err(NvmeofConnectOptionsParseError {
+                        key: Self::DISCOVERY_PARAM,
+                        value: value.clone(),
+                        expected: "'direct' or 'discovery'",
+                    });
+                }
+            },
+        }
+
+        let (key, value) = match parameters.get(Self::ENDPOINTS_PARAM) {
+            Some(value) => (Self::ENDPOINTS_PARAM, value),
+            None => match parameters.get("endpoints") {
+                Some(value) => ("endpoints", value),
+                None => return Ok(None),
+            },
+        };
+        let invalid = || NvmeofConnectOptionsParseError {
+            key,
+            value: value.clone(),
+            expected: "a comma-separated list of host[:port]",
+        };
+        let endpoints = Endpoints::parse(value, Self::DEFAULT_PORT).map_err(|_| invalid())?;
+        let endpoints = if key == Self::ENDPOINTS_PARAM {
+            endpoints
+        } else {
+            endpoints.with_port(Self::DEFAULT_PORT)
+        };
+        Ok(Some(Self { endpoints }))
+    }
+
+    /// Query the discovery service on the first of `endpoints`, for native
+    /// multipath volumes without configured discovery controllers.
+    pub fn from_first_endpoint(endpoints: &Endpoints) -> Option<Self> {
+        let first = endpoints.first()?;
+        Some(Self {
+            endpoints: std::iter::once(Endpoint::new(first.host.clone(), Self::DEFAULT_PORT))
+                .collect(),
+        })
+    }
+
+    /// Discovery settings recorded in a volume context by [`Self::to_volume_context`].
+    pub fn from_volume_context(
+        volume_context: &std::collections::HashMap<String, String>,
+    ) -> Result<Option<Self>, EndpointParseError> {
+        volume_context
+            .get(Self::ENDPOINTS_CONTEXT_KEY)
+            .map(|value| {
+                Endpoints::parse(value, Self::DEFAULT_PORT).map(|endpoints| Self { endpoints })
+            })
+            .transpose()
+    }
+
+    /// Record the discovery controller NQN and endpoints in a volume context.
+    pub fn to_volume_context(
+        &self,
+        volume_context: &mut std::collections::HashMap<String, String>,
+    ) {
+        volume_context.insert(
+            Self::NQN_CONTEXT_KEY.to_string(),
+            NVME_DISCOVERY_NQN.to_string(),
+        );
+        volume_context.insert(
+            Self::ENDPOINTS_CONTEXT_KEY.to_string(),
+            self.endpoints.to_context_string(),
+        );
+    }
+}
+
+// ============================================================================
+// NvmeofMultipath
+// ============================================================================
+
+/// How the node assembles the paths of an NVMeoF volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NvmeofMultipath {
+    /// Connect the volume's endpoints (or discovered endpoints with
+    /// `nvmeof.discovery`); native NVMe multipath or dm-multipath combines
+    /// them, whichever the node runs (default)
+    #[default]
+    Auto,
+    /// Discover every controller of the subsystem and connect them all, so
+    /// native NVMe multipath presents one `/dev/nvmeXnY` across all paths
+    Native,
+}
+
+impl NvmeofMultipath {
+    pub const PARAM_NAME: &'static str = "nvmeof.multipath";
+
+    /// Multipath mode from StorageClass parameters or volume context.
+    pub fn parse(
+        parameters: &std::collections::HashMap<String, String>,
+    ) -> Result<Self, NvmeofConnectOptionsParseError> {
+        match parameters.get(Self::PARAM_NAME) {
+            None => Ok(Self::Auto),
+            Some(value) => match value.to_lowercase().as_str() {
+                "" | "auto" => Ok(Self::Auto),
+                "native" => Ok(Self::Native),
+                _ => Err(NvmeofConnectOptionsParseError {
+                    key: Self::PARAM_NAME,
+                    value: value.clone(),
+                    expected: "'auto' or 'native'",
+                }),
+            },
+        }
+    }
+}
+
+impl Display for NvmeofMultipath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NvmeofMultipath::Auto => write!(f, "auto"),
+            NvmeofMultipath::Native => write!(f, "native"),
+        }
+    }
+}
+
+// ============================================================================
+// IscsiDiscoveryOptions
+// ============================================================================
+
+/// How the Linux iSCSI initiator locates the target behind each endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IscsiDiscoveryMode {
+    /// Create node records directly for the expected IQN (default)
+    #[default]
+    Direct,
+    /// Run SendTargets discovery against each portal before login
+    SendTargets,
+}
+
+impl Display for IscsiDiscoveryMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IscsiDiscoveryMode::Direct => write!(f, "direct"),
+            IscsiDiscoveryMode::SendTargets => write!(f, "sendtargets"),
+        }
+    }
+}
+
+/// Optional iSCSI discovery settings for `iscsiadm`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IscsiDiscoveryOptions {
+    /// Discovery mode (`discovery`).
+    pub mode: IscsiDiscoveryMode,
+    /// Discovery attempts per portal before falling back to direct login
+    /// (`discoveryRetries`).
+    pub retries: u32,
+}
+
+impl Default for IscsiDiscoveryOptions {
+    fn default() -> Self {
+        Self {
+            mode: IscsiDiscoveryMode::Direct,
+            retries: Self::DEFAULT_RETRIES,
+        }
+    }
+}
+
+impl IscsiDiscoveryOptions {
+    pub const DISCOVERY_PARAM: &'static str = "discovery";
+    pub const RETRIES_PARAM: &'static str = "discoveryRetries";
+
+    pub const PARAM_NAMES: &'static [&'static str] = &[Self::DISCOVERY_PARAM, Self::RETRIES_PARAM];
+
+    /// Default number of SendTargets attempts per portal.
+    pub const DEFAULT_RETRIES: u32 = 3;
+
+    /// Parse iSCSI discovery options from StorageClass parameters or volume context.
+    pub fn parse(
+        parameters: &std::collections::HashMap<String, String>,
+    ) -> Result<Self, IscsiDiscoveryOptionsParseError> {
+        let mode = match parameters.get(Self::DISCOVERY_PARAM) {
+            None => IscsiDiscoveryMode::Direct,
+            Some(value) => match value.to_lowercase().as_str() {
+                "" | "direct" | "none" => IscsiDiscoveryMode::Direct,
+                "sendtargets" | "st" => IscsiDiscoveryMode::SendTargets,
+                _ => {
+                    return Err(IscsiDiscoveryOptionsParseError {
+                        key: Self::DISCOVERY_PARAM,
+                        value: value.clone(),
+                        expected: "'direct' or 'sendtargets'",
+                    });
+                }
+            },
+        };
+
+        let retries = match parameters.get(Self::RETRIES_PARAM) {
+            None => Self::DEFAULT_RETRIES,
+            Some(value) => match value.parse::<u32>() {
+                Ok(parsed) if parsed > 0 => parsed,
+                _ => {
+                    return Err(IscsiDiscoveryOptionsParseError {
+                        key: Self::RETRIES_PARAM,
+                        value: value.clone(),
+                        expected: "a positive integer",
+                    });
+                }
+            },
+        };
+
+        Ok(Self { mode, retries })
+    }
+}
+
+/// Error returned when parsing invalid iSCSI discovery options.
+#[derive(Debug, Clone)]
+pub struct IscsiDiscoveryOptionsParseError {
+    key: &'static str,
+    value: String,
+    expected: &'static str,
+}
+
+impl Display for IscsiDiscoveryOptionsParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid {} value '{}': expected {}",
+            self.key, self.value, self.expected
+        )
+    }
+}
+
+impl std::error::Error for IscsiDiscoveryOptionsParseError {}
+
 // ============================================================================
 // Endpoint
 // ============================================================================
@@ -399,7 +766,7 @@ impl std::error::Error for NvmeofConnectOptionsParseError {}
 ///
 /// Represents a single endpoint for iSCSI or NVMeoF connections.
 /// The host can be an IP address (v4 or v6) or a hostname - no resolution is attempted.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Endpoint {
     /// Host address (IP or hostname, not resolved)
     pub host: String,
@@ -420,6 +787,34 @@ impl Endpoint {
     pub fn to_portal_string(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
+
+    /// Format as "host:port" with IPv6 hosts bracketed, so the string parses
+    /// back to the same endpoint.
+    pub fn to_context_string(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            self.to_portal_string()
+        }
+    }
+
+    /// Whether the host is an IP address or a valid DNS name and the port
+    /// is non-zero.
+    pub fn is_well_formed(&self) -> bool {
+        if self.port == 0 {
+            return false;
+        }
+        if self.host.parse::<std::net::IpAddr>().is_ok() {
+            return true;
+        }
+        self.host.len() <= 253
+            && self.host.split('.').all(|label| {
+                (1..=63).contains(&label.len())
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            })
+    }
 }
 
 impl Display for Endpoint {
@@ -524,6 +919,28 @@ impl Endpoints {
         Ok(Endpoint::new(s, default_port))
     }
 
+    /// Parse endpoints like [`Self::parse`], additionally rejecting hosts
+    /// that are neither IP addresses nor DNS names, port 0, IPv6 addresses
+    /// without brackets (where a trailing group would be mistaken for a
+    /// port) and brackets around anything but an IPv6 address.
+    pub fn parse_strict(s: &str, default_port: u16) -> Result<Self, EndpointParseError> {
+        let misbracketed = |part: &&str| match part.strip_prefix('[') {
+            Some(rest) => rest
+                .split(']')
+                .next()
+                .is_none_or(|host| host.parse::<std::net::Ipv6Addr>().is_err()),
+            None => part.matches(':').count() > 1,
+        };
+        if let Some(part) = s.split(',').map(str::trim).find(misbracketed) {
+            return Err(EndpointParseError(part.to_string()));
+        }
+        let endpoints = Self::parse(s, default_port)?;
+        if let Some(bad) = endpoints.endpoints.iter().find(|e| !e.is_well_formed()) {
+            return Err(EndpointParseError(bad.to_context_string()));
+        }
+        Ok(endpoints)
+    }
+
     /// Get the list of endpoints.
     pub fn as_slice(&self) -> &[Endpoint] {
         &self.endpoints
@@ -554,10 +971,39 @@ impl Endpoints {
             .join(",")
     }
 
+    /// Format all endpoints as comma-separated "host:port" strings that
+    /// parse back unchanged (IPv6 hosts bracketed).
+    pub fn to_context_string(&self) -> String {
+        self.endpoints
+            .iter()
+            .map(|e| e.to_context_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
     /// Get the first endpoint (for single-path fallback or display).
     pub fn first(&self) -> Option<&Endpoint> {
         self.endpoints.first()
     }
+
+    /// The same hosts on `port`.
+    pub fn with_port(&self, port: u16) -> Self {
+        Self {
+            endpoints: self
+                .endpoints
+                .iter()
+                .map(|e| Endpoint::new(e.host.clone(), port))
+                .collect(),
+        }
+    }
+}
+
+impl FromIterator<Endpoint> for Endpoints {
+    fn from_iter<I: IntoIterator<Item = Endpoint>>(iter: I) -> Self {
+        Self {
+            endpoints: iter.into_iter().collect(),
+        }
+    }
 }
 
 impl IntoIterator for Endpoints {
@@ -578,6 +1024,301 @@ impl<'a> IntoIterator for &'a Endpoints {
     }
 }
 
+// ============================================================================
+// Node initiator identities
+// ============================================================================
+
+/// Separates the node name from initiator entries in a reported node ID
+const NODE_ID_SEPARATOR: char = ';';
+
+/// Longest node ID Kubernetes accepts in a CSINode object
+pub const MAX_NODE_ID_LEN: usize = 192;
+
+/// iSCSI initiator name and NVMe host NQN of a node.
+///
+/// CSI gives the controller nothing but the node ID to identify a node, so
+/// the node appends these to it (`<node>;iqn=<iqn>;nqn=<nqn>`) for the
+/// controller to put into a volume's auth-group.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeInitiators {
+    /// iSCSI initiator IQN
+    pub iqn: Option<String>,
+    /// NVMe host NQN
+    pub host_nqn: Option<String>,
+}
+
+impl NodeInitiators {
+    /// Node ID carrying `node` and these initiators
+    pub fn encode_node_id(&self, node: &str) -> String {
+        let mut node_id = node.to_string();
+        for (key, value) in [("iqn", &self.iqn), ("nqn", &self.host_nqn)] {
+            if let Some(value) = value {
+                node_id.push(NODE_ID_SEPARATOR);
+                node_id.push_str(&format!("{}={}", key, value));
+            }
+        }
+        node_id
+    }
+
+    /// Split a node ID into the node name and its initiators.
+    ///
+    /// Plain node IDs (nodes not reporting initiators) have none.
+    pub fn decode_node_id(node_id: &str) -> (&str, Self) {
+        let mut parts = node_id.split(NODE_ID_SEPARATOR);
+        let node = parts.next().unwrap_or_default();
+        let mut initiators = Self::default();
+        for part in parts {
+            match part.split_once('=') {
+                Some(("iqn", iqn)) if !iqn.is_empty() => initiators.iqn = Some(iqn.to_string()),
+                Some(("nqn", nqn)) if !nqn.is_empty() => {
+                    initiators.host_nqn = Some(nqn.to_string())
+                }
+                _ => {}
+            }
+        }
+        (node, initiators)
+    }
+}
+
+// ============================================================================
+// StorageClass parameter registry
+// ============================================================================
+
+/// Key prefix reserved for metadata injected by the external-provisioner
+/// (e.g. `csi.storage.k8s.io/pvc/name` with `--extra-create-metadata`).
+pub const RESERVED_PARAM_PREFIX: &str = "csi.storage.k8s.io/";
+
+/// Component that reads a StorageClass parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParameterConsumer {
+    /// CSI controller (CreateVolume)
+    Controller,
+    /// CSI node plugin (NodeStageVolume, via the volume context)
+    Node,
+    /// ctld-agent on the storage host
+    Agent,
+}
+
+/// Description of one StorageClass parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ParameterSpec {
+    /// Parameter key
+    pub name: &'static str,
+    /// Accepted values or range, in human-readable form
+    pub accepted: &'static str,
+    /// Value used when the parameter is absent
+    pub default: &'static str,
+    /// Component that interprets the parameter
+    pub consumer: ParameterConsumer,
+}
+
+const fn param(
+    name: &'static str,
+    accepted: &'static str,
+    default: &'static str,
+    consumer: ParameterConsumer,
+) -> ParameterSpec {
+    ParameterSpec {
+        name,
+        accepted,
+        default,
+        consumer,
+    }
+}
+
+/// Every StorageClass parameter understood by the controller, node, or agent.
+///
+/// Single source of truth for `--strict-parameters` and `--list-parameters`.
+/// Keep in sync with the parameter tables in docs/configuration.md.
+pub const PARAMETERS: &[ParameterSpec] = {
+    use ParameterConsumer::{Agent, Controller, Node};
+    &[
+        param("exportType", "iscsi, nvmeof", "iscsi", Controller),
+        param("fsType", "ext4, xfs", "ext4", Node),
+        param(DirectIo::PARAM_NAME, "true, false", "false", Node),
+        param(
+            MKFS_OPTIONS_PARAM,
+            "allowlisted mkfs flags, e.g. -E stride=16",
+            "none",
+            Node,
+        ),
+        param("endpoints", "<host>[:<port>][,...]", "(required)", Node),
+        param("cloneMode", "linked, copy", "linked", Controller),
+        param(
+            CloneMode::STRONG_ISOLATION_PARAM,
+            "true, false",
+            "false",
+            Controller,
+        ),
+        param(
+            ProvisioningMode::PARAM_NAME,
+            "thin, thick",
+            "thin",
+            Controller,
+        ),
+        param(
+            IscsiDiscoveryOptions::DISCOVERY_PARAM,
+            "direct, sendtargets",
+            "direct",
+            Node,
+        ),
+        param(
+            IscsiDiscoveryOptions::RETRIES_PARAM,
+            "positive integer",
+            "3",
+            Node,
+        ),
+        param(
+            NvmeofDiscovery::DISCOVERY_PARAM,
+            "direct, discovery",
+            "direct",
+            Controller,
+        ),
+        param(
+            NvmeofDiscovery::ENDPOINTS_PARAM,
+            "<host>[:<port>][,...]",
+            "endpoints hosts on port 8009",
+            Controller,
+        ),
+        param(NvmeofMultipath::PARAM_NAME, "auto, native", "auto", Node),
+        param(
+            NvmeofConnectOptions::NR_IO_QUEUES_PARAM,
+            "positive integer",
+            "nvme-cli default",
+            Node,
+        ),
+        param(
+            NvmeofConnectOptions::QUEUE_SIZE_PARAM,
+            "positive integer",
+            "nvme-cli default",
+            Node,
+        ),
+        param(
+            NvmeofConnectOptions::DISABLE_SQFLOW_PARAM,
+            "true, false",
+            "false",
+            Node,
+        ),
+        param(
+            NvmeofConnectOptions::KEEP_ALIVE_TMO_PARAM,
+            "integer >= 0 (seconds)",
+            "nvme-cli default",
+            Node,
+        ),
+        param(
+            NvmeofConnectOptions::RECONNECT_DELAY_PARAM,
+            "positive integer (seconds)",
+            "nvme-cli default",
+            Node,
+        ),
+        param(
+            NvmeofConnectOptions::CTRL_LOSS_TMO_PARAM,
+            "-1, 0 or positive integer (seconds)",
+            "nvme-cli default",
+            Node,
+        ),
+        param("blockSize", "512, 4096", "CTL default", Agent),
+        param("physicalBlockSize", "power of two (bytes)", "none", Agent),
+        param("enableUnmap", "true, false", "false", Agent),
+        param("removable", "true, false", "CTL default", Agent),
+        param("controllerGroup", "group name (NVMeoF only)", "none", Agent),
+        param("targetAlias", "free-form text (iSCSI only)", "none", Agent),
+        param(
+            "portalGroup",
+            "portal-group name (iSCSI only)",
+            "agent's --portal-group",
+            Agent,
+        ),
+        param(
+            "transportGroup",
+            "transport-group name (NVMeoF only)",
+            "agent's --transport-group",
+            Agent,
+        ),
+        param("backend", "zvol, file", "zvol", Agent),
+        param(
+            "compression",
+            "off, lz4, zstd[-1..19], zstd-fast[-N], gzip[-1..9], zle",
+            "inherited from parent",
+            Agent,
+        ),
+        param(
+            "recordSize",
+            "power of two, 512 to 16M (backend=file only)",
+            "ZFS default",
+            Agent,
+        ),
+        param(
+            "volblocksize",
+            "power of two, 512 to 1M (zvol only)",
+            "ZFS default",
+            Agent,
+        ),
+        param("encryption", "on, off", "inherited from parent", Agent),
+        param("keyFormat", "raw, hex, passphrase", "-", Agent),
+        param("keyLocation", "file:///path/to/key", "-", Agent),
+        param("maxOverprovision", "true, false", "true", Agent),
+        param(
+            "quota",
+            "size such as 500M or 10G (backend=file only)",
+            "none",
+            Agent,
+        ),
+    ]
+};
+
+/// Look up a parameter in the registry, in any spelling of its name
+pub fn parameter_spec(name: &str) -> Option<&'static ParameterSpec> {
+    let wanted = normalize_key(name);
+    PARAMETERS
+        .iter()
+        .find(|spec| spec.name == name || normalize_key(spec.name) == wanted)
+}
+
+/// Rewrite registered parameter keys to their registry spelling and trim
+/// their values, so `enable_unmap: " on "` is read as `enableUnmap: on`.
+///
+/// An exact spelling wins if several variants are present; otherwise the
+/// lexically smallest one does, matching the agent. Unknown keys are kept
+/// unchanged.
+pub fn canonical_parameters(
+    parameters: std::collections::HashMap<String, String>,
+) -> std::collections::HashMap<String, String> {
+    let mut entries: Vec<(String, String)> = parameters.into_iter().collect();
+    entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut canonical = std::collections::HashMap::with_capacity(entries.len());
+    for (key, value) in entries {
+        match parameter_spec(&key) {
+            Some(spec) if spec.name == key => {
+                canonical.insert(key, value.trim().to_string());
+            }
+            Some(spec) => {
+                canonical
+                    .entry(spec.name.to_string())
+                    .or_insert_with(|| value.trim().to_string());
+            }
+            None => {
+                canonical.insert(key, value);
+            }
+        }
+    }
+    canonical
+}
+
+/// Return the parameter keys that are neither known nor reserved, sorted so
+/// error messages are stable.
+pub fn unknown_parameters(parameters: &std::collections::HashMap<String, String>) -> Vec<&str> {
+    let mut unknown: Vec<&str> = parameters
+        .keys()
+        .map(String::as_str)
+        .filter(|key| parameter_spec(key).is_none() && !key.starts_with(RESERVED_PARAM_PREFIX))
+        .collect();
+    unknown.sort_unstable();
+    unknown
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -648,6 +1389,28 @@ mod tests {
         assert_eq!(proto, agent::CloneMode::Copy);
     }
 
+    #[test]
+    fn test_clone_mode_enforce_isolation() {
+        // Linked and server-default requests are overridden, with a reason
+        let (mode, reason) = CloneMode::Linked.enforce_isolation(true);
+        assert_eq!(mode, CloneMode::Copy);
+        assert!(reason.unwrap().contains("share blocks"));
+
+        let (mode, reason) = CloneMode::Unspecified.enforce_isolation(true);
+        assert_eq!(mode, CloneMode::Copy);
+        assert!(reason.is_some());
+
+        // COPY already satisfies isolation; without it nothing changes
+        assert_eq!(
+            CloneMode::Copy.enforce_isolation(true),
+            (CloneMode::Copy, None)
+        );
+        assert_eq!(
+            CloneMode::Linked.enforce_isolation(false),
+            (CloneMode::Linked, None)
+        );
+    }
+
     #[test]
     fn test_provisioning_mode_from_str() {
         assert_eq!(
@@ -763,6 +1526,133 @@ mod tests {
         assert!(err.to_string().contains("nvmeof.disableSqflow"));
     }
 
+    #[test]
+    fn test_nvmeof_discovery_from_parameters() {
+        let mut params = std::collections::HashMap::new();
+        params.insert(
+            "endpoints".to_string(),
+            "10.0.0.1:4420,10.0.0.2".to_string(),
+        );
+        assert!(NvmeofDiscovery::from_parameters(&params).unwrap().is_none());
+
+        // Defaults to the I/O endpoint hosts on the discovery port
+        params.insert(
+            NvmeofDiscovery::DISCOVERY_PARAM.to_string(),
+            "discovery".to_string(),
+        );
+        let discovery = NvmeofDiscovery::from_parameters(&params).unwrap().unwrap();
+        assert_eq!(
+            discovery.endpoints.to_portal_string(),
+            "10.0.0.1:8009,10.0.0.2:8009"
+        );
+
+        params.insert(
+            NvmeofDiscovery::ENDPOINTS_PARAM.to_string(),
+            "disc.example:8010,10.0.0.9".to_string(),
+        );
+        let discovery = NvmeofDiscovery::from_parameters(&params).unwrap().unwrap();
+        assert_eq!(
+            discovery.endpoints.to_portal_string(),
+            "disc.example:8010,10.0.0.9:8009"
+        );
+
+        params.insert(
+            NvmeofDiscovery::DISCOVERY_PARAM.to_string(),
+            "mdns".to_string(),
+        );
+        assert!(NvmeofDiscovery::from_parameters(&params).is_err());
+    }
+
+    #[test]
+    fn test_nvmeof_discovery_volume_context_round_trip() {
+        let mut params = std::collections::HashMap::new();
+        params.insert(
+            NvmeofDiscovery::DISCOVERY_PARAM.to_string(),
+            "discovery".to_string(),
+        );
+        params.insert(
+            NvmeofDiscovery::ENDPOINTS_PARAM.to_string(),
+            "10.0.0.1:8009".to_string(),
+        );
+        let discovery = NvmeofDiscovery::from_parameters(&params).unwrap().unwrap();
+
+        let mut ctx = std::collections::HashMap::new();
+        assert!(
+            NvmeofDiscovery::from_volume_context(&ctx)
+                .unwrap()
+                .is_none()
+        );
+        discovery.to_volume_context(&mut ctx);
+        assert_eq!(
+            ctx.get(NvmeofDiscovery::NQN_CONTEXT_KEY)
+                .map(String::as_str),
+            Some(NVME_DISCOVERY_NQN)
+        );
+        let parsed = NvmeofDiscovery::from_volume_context(&ctx).unwrap().unwrap();
+        assert_eq!(parsed.endpoints.as_slice(), discovery.endpoints.as_slice());
+    }
+
+    #[test]
+    fn test_nvmeof_multipath_parse() {
+        let mut params = std::collections::HashMap::new();
+        assert_eq!(
+            NvmeofMultipath::parse(&params).unwrap(),
+            NvmeofMultipath::Auto
+        );
+        params.insert(
+            NvmeofMultipath::PARAM_NAME.to_string(),
+            "Native".to_string(),
+        );
+        assert_eq!(
+            NvmeofMultipath::parse(&params).unwrap(),
+            NvmeofMultipath::Native
+        );
+        params.insert(NvmeofMultipath::PARAM_NAME.to_string(), "dm".to_string());
+        assert!(NvmeofMultipath::parse(&params).is_err());
+    }
+
+    #[test]
+    fn test_nvmeof_discovery_from_first_endpoint() {
+        let endpoints = Endpoints::parse("10.0.0.1:4420,10.0.0.2:4420", 4420).unwrap();
+        let discovery = NvmeofDiscovery::from_first_endpoint(&endpoints).unwrap();
+        assert_eq!(discovery.endpoints.to_context_string(), "10.0.0.1:8009");
+        assert!(NvmeofDiscovery::from_first_endpoint(&std::iter::empty().collect()).is_none());
+    }
+
+    #[test]
+    fn test_iscsi_discovery_options_default_is_direct() {
+        let params = std::collections::HashMap::new();
+        let options = IscsiDiscoveryOptions::parse(&params).unwrap();
+
+        assert_eq!(options.mode, IscsiDiscoveryMode::Direct);
+        assert_eq!(options.retries, IscsiDiscoveryOptions::DEFAULT_RETRIES);
+    }
+
+    #[test]
+    fn test_iscsi_discovery_options_parse_sendtargets() {
+        let mut params = std::collections::HashMap::new();
+        params.insert("discovery".to_string(), "SendTargets".to_string());
+        params.insert("discoveryRetries".to_string(), "5".to_string());
+
+        let options = IscsiDiscoveryOptions::parse(&params).unwrap();
+
+        assert_eq!(options.mode, IscsiDiscoveryMode::SendTargets);
+        assert_eq!(options.retries, 5);
+    }
+
+    #[test]
+    fn test_iscsi_discovery_options_rejects_invalid_values() {
+        let mut params = std::collections::HashMap::new();
+        params.insert("discovery".to_string(), "isns".to_string());
+        let err = IscsiDiscoveryOptions::parse(&params).unwrap_err();
+        assert!(err.to_string().contains("discovery"));
+
+        let mut params = std::collections::HashMap::new();
+        params.insert("discoveryRetries".to_string(), "0".to_string());
+        let err = IscsiDiscoveryOptions::parse(&params).unwrap_err();
+        assert!(err.to_string().contains("discoveryRetries"));
+    }
+
     // Endpoint tests
 
     #[test]
@@ -851,6 +1741,37 @@ mod tests {
         assert_eq!(eps.first().unwrap().port, 3260);
     }
 
+    #[test]
+    fn test_endpoints_parse_strict() {
+        let eps = Endpoints::parse_strict(" 10.0.0.1, [2001:db8::1]:3261,san-a.example.com ", 3260)
+            .unwrap();
+        assert_eq!(
+            eps.to_context_string(),
+            "10.0.0.1:3260,[2001:db8::1]:3261,san-a.example.com:3260"
+        );
+        // The normalized form parses back to the same endpoints
+        let reparsed = Endpoints::parse(&eps.to_context_string(), 9999).unwrap();
+        assert_eq!(reparsed.as_slice(), eps.as_slice());
+
+        for value in [
+            "10.0.0.1:0",
+            "10.0.0.1:99999",
+            "2001:db8::1:3260",
+            "2001:db8::1",
+            "[2001:db8::1",
+            "[san.example.com]:3260",
+            "san_a.example.com",
+            "-san.example.com",
+            "host name:3260",
+            "10.0.0.1:3260;reboot",
+        ] {
+            assert!(
+                Endpoints::parse_strict(value, 3260).is_err(),
+                "{value:?} accepted"
+            );
+        }
+    }
+
     #[test]
     fn test_endpoints_parse_empty_fails() {
         assert!(Endpoints::parse("", 3260).is_err());
@@ -863,4 +1784,119 @@ mod tests {
         let hosts: Vec<_> = eps.into_iter().map(|e| e.host).collect();
         assert_eq!(hosts, vec!["10.0.0.1", "10.0.0.2"]);
     }
+
+    #[test]
+    fn test_node_initiators_node_id_round_trip() {
+        let initiators = NodeInitiators {
+            iqn: Some("iqn.2016-04.com.open-iscsi:4b2f9c1d7e0a".to_string()),
+            host_nqn: Some(
+                "nqn.2014-08.org.nvmexpress:uuid:9b1f2c3d-4e5f-4a6b-8c7d-0e1f2a3b4c5d".to_string(),
+            ),
+        };
+        let node_id = initiators.encode_node_id("worker-1");
+        assert_eq!(
+            node_id,
+            "worker-1;iqn=iqn.2016-04.com.open-iscsi:4b2f9c1d7e0a;\
+             nqn=nqn.2014-08.org.nvmexpress:uuid:9b1f2c3d-4e5f-4a6b-8c7d-0e1f2a3b4c5d"
+        );
+        assert_eq!(
+            NodeInitiators::decode_node_id(&node_id),
+            ("worker-1", initiators)
+        );
+
+        // Plain node IDs and partial reports
+        assert_eq!(
+            NodeInitiators::decode_node_id("worker-2"),
+            ("worker-2", NodeInitiators::default())
+        );
+        let iscsi_only = NodeInitiators {
+            iqn: Some("iqn.2016-04.com.open-iscsi:1".to_string()),
+            host_nqn: None,
+        };
+        assert_eq!(
+            NodeInitiators::decode_node_id(&iscsi_only.encode_node_id("worker-3")),
+            ("worker-3", iscsi_only)
+        );
+    }
+
+    #[test]
+    fn test_unknown_parameters_flags_typos() {
+        let mut params = std::collections::HashMap::new();
+        params.insert("exportType".to_string(), "nvmeof".to_string());
+        params.insert("provisioninMode".to_string(), "thick".to_string());
+        params.insert("compresion".to_string(), "lz4".to_string());
+        params.insert(
+            "csi.storage.k8s.io/pvc/name".to_string(),
+            "data".to_string(),
+        );
+
+        params.insert("enable_unmap".to_string(), "on".to_string());
+
+        assert_eq!(
+            unknown_parameters(&params),
+            vec!["compresion", "provisioninMode"]
+        );
+    }
+
+    #[test]
+    fn test_canonical_parameters_normalizes_registered_keys() {
+        let params: std::collections::HashMap<String, String> = [
+            ("FSTYPE", " xfs "),
+            ("provisioning-mode", "thick"),
+            ("nvmeof.nr_io_queues", "4"),
+            ("Provisioning_Mode", "thin"),
+            ("exportType", "nvmeof"),
+            ("export_type", "iscsi"),
+            ("csi.storage.k8s.io/pvc/name", " data "),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let canonical = canonical_parameters(params);
+        assert_eq!(canonical.len(), 5);
+        assert_eq!(canonical["fsType"], "xfs");
+        // Lexically smallest variant wins without an exact spelling
+        assert_eq!(canonical[ProvisioningMode::PARAM_NAME], "thin");
+        assert_eq!(canonical["nvmeof.nrIoQueues"], "4");
+        // The exact spelling wins over variants
+        assert_eq!(canonical["exportType"], "nvmeof");
+        assert_eq!(canonical["csi.storage.k8s.io/pvc/name"], " data ");
+    }
+
+    #[test]
+    fn test_parameter_registry_metadata() {
+        let spec = parameter_spec("provisioningMode").unwrap();
+        assert_eq!(spec.accepted, "thin, thick");
+        assert_eq!(spec.default, "thin");
+        assert_eq!(spec.consumer, ParameterConsumer::Controller);
+
+        assert_eq!(
+            parameter_spec("blockSize").unwrap().consumer,
+            ParameterConsumer::Agent
+        );
+        assert_eq!(parameter_spec("fsType").unwrap().default, "ext4");
+        assert_eq!(
+            parameter_spec("cloneMode").unwrap().accepted,
+            "linked, copy"
+        );
+
+        // Every parameter parsed through a typed option set is registered
+        for name in NvmeofConnectOptions::PARAM_NAMES
+            .iter()
+            .chain(IscsiDiscoveryOptions::PARAM_NAMES)
+        {
+            assert!(parameter_spec(name).is_some(), "{} missing", name);
+        }
+
+        // Names are unique
+        let mut names: Vec<_> = PARAMETERS.iter().map(|p| p.name).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), PARAMETERS.len());
+
+        let json = serde_json::to_value(parameter_spec("enableUnmap").unwrap()).unwrap();
+        assert_eq!(json["consumer"], "agent");
+        assert_eq!(json["default"], "false");
+    }
 }
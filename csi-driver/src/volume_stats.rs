@@ -0,0 +1,292 @@
+//! Volume statistics for NodeGetVolumeStats.
+//!
+//! Capacity comes from `df` for filesystem volumes and `blockdev` for raw
+//! block volumes. Optionally the I/O counters of the volume's backing device
+//! are read from `/sys/block/<dev>/stat` (falling back to `/proc/diskstats`)
+//! and exported as per-volume Prometheus metrics, since the CSI response has
+//! no place for them. Both are single file reads or one short command, so
+//! kubelet's periodic polling stays cheap.
+
+use std::path::Path;
+
+use tokio::process::Command;
+use tonic::Status;
+use tracing::debug;
+
+/// `/proc/diskstats` and `/sys/block/<dev>/stat` count 512-byte sectors
+/// regardless of the device's logical block size
+const SECTOR_SIZE: u64 = 512;
+
+/// Path of the kernel's system-wide block device statistics
+const PROC_DISKSTATS: &str = "/proc/diskstats";
+
+/// Space and inode usage of a mounted filesystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilesystemUsage {
+    pub total_bytes: u64,
+    pub used_bytes: u64,
+    pub available_bytes: u64,
+    pub total_inodes: u64,
+    pub used_inodes: u64,
+    pub available_inodes: u64,
+}
+
+/// Cumulative I/O counters of a block device since boot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskStats {
+    pub read_ios: u64,
+    pub read_bytes: u64,
+    /// Milliseconds spent on reads
+    pub read_time_ms: u64,
+    pub write_ios: u64,
+    pub write_bytes: u64,
+    /// Milliseconds spent on writes
+    pub write_time_ms: u64,
+}
+
+impl DiskStats {
+    /// Growth of each counter since `previous`. A counter below its previous
+    /// value restarted (the device was reconnected) and counts from zero.
+    pub fn increase_since(&self, previous: &DiskStats) -> DiskStats {
+        let increase =
+            |current: u64, previous: u64| current.checked_sub(previous).unwrap_or(current);
+        DiskStats {
+            read_ios: increase(self.read_ios, previous.read_ios),
+            read_bytes: increase(self.read_bytes, previous.read_bytes),
+            read_time_ms: increase(self.read_time_ms, previous.read_time_ms),
+            write_ios: increase(self.write_ios, previous.write_ios),
+            write_bytes: increase(self.write_bytes, previous.write_bytes),
+            write_time_ms: increase(self.write_time_ms, previous.write_time_ms),
+        }
+    }
+}
+
+/// Parse the counters of a `/sys/block/<dev>/stat` line.
+///
+/// Fields: read I/Os, read merges, read sectors, read ticks, write I/Os,
+/// write merges, write sectors, write ticks, then in-flight and queue
+/// counters (and discard/flush fields on newer kernels) that are ignored.
+pub fn parse_block_stat(line: &str) -> Option<DiskStats> {
+    let fields: Vec<u64> = line
+        .split_whitespace()
+        .take(8)
+        .map(|f| f.parse().ok())
+        .collect::<Option<_>>()?;
+    let [
+        read_ios,
+        _,
+        read_sectors,
+        read_time_ms,
+        write_ios,
+        _,
+        write_sectors,
+        write_time_ms,
+    ] = fields[..]
+    else {
+        return None;
+    };
+    Some(DiskStats {
+        read_ios,
+        read_bytes: read_sectors.saturating_mul(SECTOR_SIZE),
+        read_time_ms,
+        write_ios,
+        write_bytes: write_sectors.saturating_mul(SECTOR_SIZE),
+        write_time_ms,
+    })
+}
+
+/// Find `device` (a kernel name such as `sdc`, `dm-0` or `nvme1n1`) in
+/// `/proc/diskstats` content. Each line is `major minor name` followed by
+/// the same counters as `/sys/block/<dev>/stat`.
+pub fn parse_proc_diskstats(content: &str, device: &str) -> Option<DiskStats> {
+    content.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let name = fields.nth(2)?;
+        if name != device {
+            return None;
+        }
+        parse_block_stat(&fields.collect::<Vec<_>>().join(" "))
+    })
+}
+
+/// Parse `df -B1 --output=size,used,avail,itotal,iused,iavail` output
+pub fn parse_df_usage(output: &str) -> Option<FilesystemUsage> {
+    let values: Vec<u64> = output
+        .lines()
+        .nth(1)?
+        .split_whitespace()
+        .map(|f| f.parse().ok())
+        .collect::<Option<_>>()?;
+    let [
+        total_bytes,
+        used_bytes,
+        available_bytes,
+        total_inodes,
+        used_inodes,
+        available_inodes,
+    ] = values[..]
+    else {
+        return None;
+    };
+    Some(FilesystemUsage {
+        total_bytes,
+        used_bytes,
+        available_bytes,
+        total_inodes,
+        used_inodes,
+        available_inodes,
+    })
+}
+
+/// Kernel name of a device node (`/dev/disk/by-id/...` -> `sdc`)
+pub async fn kernel_device_name(device: &str) -> Option<String> {
+    let resolved = tokio::fs::canonicalize(device).await.ok()?;
+    Some(resolved.file_name()?.to_str()?.to_string())
+}
+
+/// Read the I/O counters of `device`, or `None` if the kernel does not
+/// expose them.
+pub async fn read_disk_stats(device: &str) -> Option<DiskStats> {
+    let name = kernel_device_name(device).await?;
+    let sys_stat = Path::new("/sys/block").join(&name).join("stat");
+    if let Ok(line) = tokio::fs::read_to_string(&sys_stat).await {
+        return parse_block_stat(&line);
+    }
+    debug!(device = %device, "No sysfs block stats, falling back to /proc/diskstats");
+    let content = tokio::fs::read_to_string(PROC_DISKSTATS).await.ok()?;
+    parse_proc_diskstats(&content, &name)
+}
+
+/// Space and inode usage of the filesystem mounted at `path`
+pub async fn filesystem_usage(path: &str) -> Result<FilesystemUsage, Status> {
+    let stdout = command_stdout(
+        "df",
+        &["-B1", "--output=size,used,avail,itotal,iused,iavail", path],
+    )
+    .await?;
+    parse_df_usage(&stdout)
+        .ok_or_else(|| Status::internal(format!("Failed to parse df output for {}", path)))
+}
+
+/// Size of the block device at `path` in bytes
+pub async fn block_device_size(path: &str) -> Result<u64, Status> {
+    let stdout = command_stdout("blockdev", &["--getsize64", path]).await?;
+    stdout
+        .trim()
+        .parse()
+        .map_err(|_| Status::internal(format!("Invalid blockdev size for {}", path)))
+}
+
+/// Run a command and return its stdout
+async fn command_stdout(program: &str, args: &[&str]) -> Result<String, Status> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| Status::internal(format!("Failed to run {}: {}", program, e)))?;
+    if !output.status.success() {
+        return Err(Status::internal(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_increase_since() {
+        let previous = DiskStats {
+            read_ios: 10,
+            read_bytes: 4096,
+            write_ios: 5,
+            ..Default::default()
+        };
+        let current = DiskStats {
+            read_ios: 15,
+            read_bytes: 8192,
+            write_ios: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            current.increase_since(&previous),
+            DiskStats {
+                read_ios: 5,
+                read_bytes: 4096,
+                // Restarted with the device
+                write_ios: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(current.increase_since(&DiskStats::default()), current);
+    }
+
+    #[test]
+    fn test_parse_block_stat() {
+        // Linux 5.x /sys/block/sdc/stat with discard and flush fields
+        let line = "    4126      317   284330     1932    51017    38563  2867088    99270        0    62452   103208        0        0        0        0     1525     2005\n";
+        assert_eq!(
+            parse_block_stat(line),
+            Some(DiskStats {
+                read_ios: 4126,
+                read_bytes: 284330 * 512,
+                read_time_ms: 1932,
+                write_ios: 51017,
+                write_bytes: 2867088 * 512,
+                write_time_ms: 99270,
+            })
+        );
+        // Pre-4.18 kernels only have the first 11 fields
+        assert!(parse_block_stat("1 0 8 4 2 0 16 8 0 12 12").is_some());
+
+        assert_eq!(parse_block_stat(""), None);
+        assert_eq!(parse_block_stat("1 0 8 4 2"), None);
+        assert_eq!(parse_block_stat("1 0 8 4 2 0 x 8 0 12 12"), None);
+    }
+
+    #[test]
+    fn test_parse_proc_diskstats() {
+        let content = "\
+   8       0 sda 1000 10 80000 500 2000 20 160000 900 0 1200 1400 0 0 0 0
+   8      32 sdc 4126 317 284330 1932 51017 38563 2867088 99270 0 62452 103208 0 0 0 0
+   8      33 sdc1 100 0 800 10 0 0 0 0 0 10 10 0 0 0 0
+ 253       0 dm-0 7 0 56 1 9 0 72 3 0 4 4 0 0 0 0
+";
+        let sdc = parse_proc_diskstats(content, "sdc").unwrap();
+        assert_eq!(sdc.read_ios, 4126);
+        assert_eq!(sdc.write_bytes, 2867088 * 512);
+        // Names match exactly, not by prefix
+        assert_eq!(parse_proc_diskstats(content, "sdc1").unwrap().read_ios, 100);
+        assert_eq!(parse_proc_diskstats(content, "dm-0").unwrap().write_ios, 9);
+        assert_eq!(parse_proc_diskstats(content, "sd"), None);
+        assert_eq!(parse_proc_diskstats(content, "nvme0n1"), None);
+    }
+
+    #[test]
+    fn test_parse_df_usage() {
+        let output = "\
+    1B-blocks      Used     Avail  Inodes IUsed   IFree
+  10464022528 213790720 9697488896 655360    14  655346
+";
+        assert_eq!(
+            parse_df_usage(output),
+            Some(FilesystemUsage {
+                total_bytes: 10464022528,
+                used_bytes: 213790720,
+                available_bytes: 9697488896,
+                total_inodes: 655360,
+                used_inodes: 14,
+                available_inodes: 655346,
+            })
+        );
+        assert_eq!(parse_df_usage(""), None);
+        assert_eq!(
+            parse_df_usage("1B-blocks Used Avail Inodes IUsed IFree\n1 2 3 - - -\n"),
+            None
+        );
+    }
+}
@@ -1,12 +1,21 @@
-//! Configuration validation for portal and transport groups.
+//! Configuration validation for portal, transport and auth groups.
 //!
-//! Validates that portal-group (iSCSI) and transport-group (NVMeoF)
-//! references in agent arguments actually exist in /etc/ctl.conf.
-
-use std::path::Path;
+//! Validates that portal-group (iSCSI), transport-group (NVMeoF) and
+//! default auth-group references in agent arguments actually exist in /etc/ctl.conf, both at
+//! startup and (through [`ExportGroupValidator`]) before each new export.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use uclicious::{DEFAULT_DUPLICATE_STRATEGY, Priority, raw::object::ObjectRef};
 
+use super::types::ExportType;
+
+/// How long a successful group check is trusted before re-reading the config
+pub const DEFAULT_GROUP_CHECK_TTL: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Error)]
 pub enum ValidationError {
     #[error("Config file not found: {0}")]
@@ -19,6 +28,8 @@ pub enum ValidationError {
     PortalGroupNotFound(String, String),
     #[error("transport-group '{0}' not found in {1}")]
     TransportGroupNotFound(String, String),
+    #[error("auth-group '{0}' not found in {1}")]
+    AuthGroupNotFound(String, String),
 }
 
 /// Validate that a portal-group with the given name exists in the config file.
@@ -27,29 +38,8 @@ pub async fn validate_portal_group_exists(
     group_name: &str,
 ) -> Result<(), ValidationError> {
     let path = config_path.as_ref();
-
-    if !tokio::fs::try_exists(path).await.unwrap_or(false) {
-        return Err(ValidationError::FileNotFound(path.display().to_string()));
-    }
-
-    let content = tokio::fs::read_to_string(path).await?;
-
-    // Parse the UCL config
-    let mut parser = uclicious::raw::Parser::default();
-    parser
-        .add_chunk_full(&content, Priority::default(), DEFAULT_DUPLICATE_STRATEGY)
-        .map_err(|e| ValidationError::ParseError(e.to_string()))?;
-
-    let obj = parser
-        .get_object()
-        .map_err(|e| ValidationError::ParseError(e.to_string()))?;
-
-    // Look for portal-group section
-    if let Some(portal_groups) = obj.lookup("portal-group") {
-        // Check if our group name exists as a key in the portal-group object
-        if find_group_in_object(&portal_groups, group_name) {
-            return Ok(());
-        }
+    if config_has_group(path, "portal-group", group_name).await? {
+        return Ok(());
     }
 
     Err(ValidationError::PortalGroupNotFound(
@@ -64,7 +54,59 @@ pub async fn validate_transport_group_exists(
     group_name: &str,
 ) -> Result<(), ValidationError> {
     let path = config_path.as_ref();
+    if config_has_group(path, "transport-group", group_name).await? {
+        return Ok(());
+    }
+
+    Err(ValidationError::TransportGroupNotFound(
+        group_name.to_string(),
+        path.display().to_string(),
+    ))
+}
 
+/// Validate that an auth-group with the given name exists in the config file.
+///
+/// The built-in `no-authentication` and `default` groups always exist.
+pub async fn validate_auth_group_exists(
+    config_path: impl AsRef<Path>,
+    group_name: &str,
+) -> Result<(), ValidationError> {
+    if matches!(group_name, "no-authentication" | "default") {
+        return Ok(());
+    }
+
+    let path = config_path.as_ref();
+    if config_has_group(path, "auth-group", group_name).await? {
+        return Ok(());
+    }
+
+    Err(ValidationError::AuthGroupNotFound(
+        group_name.to_string(),
+        path.display().to_string(),
+    ))
+}
+
+/// Whether exports can reference the `no-authentication` auth-group.
+///
+/// It is usable when ctld predefines it (`builtin`) or the config file
+/// defines it explicitly. When neither holds the agent has to define it in
+/// the CSI config.
+pub async fn no_authentication_usable(
+    config_path: impl AsRef<Path>,
+    builtin: bool,
+) -> Result<bool, ValidationError> {
+    if builtin {
+        return Ok(true);
+    }
+    config_has_group(config_path.as_ref(), "auth-group", "no-authentication").await
+}
+
+/// Parse the config file and look for `group_name` in a `section` block
+async fn config_has_group(
+    path: &Path,
+    section: &str,
+    group_name: &str,
+) -> Result<bool, ValidationError> {
     if !tokio::fs::try_exists(path).await.unwrap_or(false) {
         return Err(ValidationError::FileNotFound(path.display().to_string()));
     }
@@ -81,18 +123,86 @@ pub async fn validate_transport_group_exists(
         .get_object()
         .map_err(|e| ValidationError::ParseError(e.to_string()))?;
 
-    // Look for transport-group section
-    if let Some(transport_groups) = obj.lookup("transport-group") {
-        // Check if our group name exists as a key in the transport-group object
-        if find_group_in_object(&transport_groups, group_name) {
-            return Ok(());
+    // Check if our group name exists as a key in the section object
+    Ok(obj
+        .lookup(section)
+        .is_some_and(|groups| find_group_in_object(&groups, group_name)))
+}
+
+/// Re-checks, before exporting a volume, that the configured portal group
+/// (iSCSI) or transport group (NVMeoF), or the volume's own override of it,
+/// still exists in ctl.conf.
+///
+/// The config may be edited while the agent runs; without this an export
+/// would reference a missing group and only fail when ctld reloads. Only
+/// successful checks are cached (for `ttl`), so a removed group is reported
+/// on the next export after the cache expires and a restored one is picked
+/// up immediately.
+#[derive(Debug)]
+pub struct ExportGroupValidator {
+    config_path: PathBuf,
+    portal_group: String,
+    transport_group: String,
+    ttl: Duration,
+    /// Time of the last successful check, per export type and group
+    verified: Mutex<HashMap<(usize, String), Instant>>,
+}
+
+impl ExportGroupValidator {
+    /// Create a validator for the groups the agent was started with.
+    ///
+    /// An empty group name disables the check for that export type.
+    pub fn new(
+        config_path: impl Into<PathBuf>,
+        portal_group: impl Into<String>,
+        transport_group: impl Into<String>,
+        ttl: Duration,
+    ) -> Self {
+        Self {
+            config_path: config_path.into(),
+            portal_group: portal_group.into(),
+            transport_group: transport_group.into(),
+            ttl,
+            verified: Mutex::new(HashMap::new()),
         }
     }
 
-    Err(ValidationError::TransportGroupNotFound(
-        group_name.to_string(),
-        path.display().to_string(),
-    ))
+    /// Check the group used by `export_type` exists
+    pub async fn check(&self, export_type: ExportType) -> Result<(), ValidationError> {
+        self.check_group(export_type, None).await
+    }
+
+    /// Check that `group`, or the agent's group for `export_type` when None,
+    /// exists
+    pub async fn check_group(
+        &self,
+        export_type: ExportType,
+        group: Option<&str>,
+    ) -> Result<(), ValidationError> {
+        let (slot, default_group) = match export_type {
+            ExportType::Iscsi => (0, &self.portal_group),
+            ExportType::Nvmeof => (1, &self.transport_group),
+        };
+        let group = group.unwrap_or(default_group);
+        if group.is_empty() {
+            return Ok(());
+        }
+
+        let key = (slot, group.to_string());
+        if let Some(at) = self.verified.lock().unwrap().get(&key)
+            && at.elapsed() < self.ttl
+        {
+            return Ok(());
+        }
+
+        match export_type {
+            ExportType::Iscsi => validate_portal_group_exists(&self.config_path, group).await?,
+            ExportType::Nvmeof => validate_transport_group_exists(&self.config_path, group).await?,
+        }
+
+        self.verified.lock().unwrap().insert(key, Instant::now());
+        Ok(())
+    }
 }
 
 /// Check if a group name exists in a UCL object.
@@ -103,6 +213,15 @@ fn find_group_in_object(obj: &ObjectRef, group_name: &str) -> bool {
         return true;
     }
 
+    // Repeated sections (one block per group) form an implicit array whose
+    // lookup only sees the first block; walk all of them
+    if obj
+        .iter()
+        .any(|group| group.key().as_deref() == Some(group_name))
+    {
+        return true;
+    }
+
     // Also check if this object itself has the group name as its key
     // (this handles the inline format where the key is the group name)
     if let Some(key) = obj.key()
@@ -233,6 +352,136 @@ transport-group tg0 {{
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_export_validator_rejects_missing_group() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "portal-group pg0 {{ listen = \"0.0.0.0:3260\" }}").unwrap();
+
+        let validator = ExportGroupValidator::new(file.path(), "pg0", "tg0", Duration::ZERO);
+        assert!(validator.check(ExportType::Iscsi).await.is_ok());
+
+        let err = validator.check(ExportType::Nvmeof).await.unwrap_err();
+        assert!(matches!(err, ValidationError::TransportGroupNotFound(..)));
+    }
+
+    #[tokio::test]
+    async fn test_export_validator_checks_group_override() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            r#"
+portal-group pg0 {{
+    listen = "0.0.0.0:3260"
+}}
+portal-group pg-storage {{
+    listen = "10.0.1.1:3260"
+}}
+        "#
+        )
+        .unwrap();
+
+        // An override is checked even when the agent has no default group
+        let validator = ExportGroupValidator::new(file.path(), "", "", Duration::ZERO);
+        assert!(
+            validator
+                .check_group(ExportType::Iscsi, Some("pg-storage"))
+                .await
+                .is_ok()
+        );
+        let err = validator
+            .check_group(ExportType::Iscsi, Some("pg-missing"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ValidationError::PortalGroupNotFound(..)));
+        let err = validator
+            .check_group(ExportType::Nvmeof, Some("pg-storage"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ValidationError::TransportGroupNotFound(..)));
+    }
+
+    #[tokio::test]
+    async fn test_export_validator_detects_group_removed_at_runtime() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            "transport-group tg0 {{ listen {{ tcp = \"0.0.0.0:4420\" }} }}"
+        )
+        .unwrap();
+
+        let cached = ExportGroupValidator::new(file.path(), "", "tg0", Duration::from_secs(60));
+        let uncached = ExportGroupValidator::new(file.path(), "", "tg0", Duration::ZERO);
+        assert!(cached.check(ExportType::Nvmeof).await.is_ok());
+        assert!(uncached.check(ExportType::Nvmeof).await.is_ok());
+
+        // Edit the config behind the agent's back
+        std::fs::write(file.path(), "transport-group tg1 { }\n").unwrap();
+
+        assert!(cached.check(ExportType::Nvmeof).await.is_ok());
+        let err = uncached.check(ExportType::Nvmeof).await.unwrap_err();
+        assert!(err.to_string().contains("tg0"));
+
+        // An empty portal group skips the iSCSI check entirely
+        assert!(uncached.check(ExportType::Iscsi).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_find_auth_group() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            r#"
+auth-group ag-subnet {{
+    initiator-portal = "10.0.0.0/24"
+}}
+        "#
+        )
+        .unwrap();
+
+        assert!(
+            validate_auth_group_exists(file.path(), "ag-subnet")
+                .await
+                .is_ok()
+        );
+        // Built-in groups need no definition
+        assert!(
+            validate_auth_group_exists(file.path(), "no-authentication")
+                .await
+                .is_ok()
+        );
+        let err = validate_auth_group_exists(file.path(), "ag-missing")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ValidationError::AuthGroupNotFound(..)));
+    }
+
+    #[tokio::test]
+    async fn test_no_authentication_usable() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            r#"
+auth-group ag-subnet {{
+    initiator-portal = "10.0.0.0/24"
+}}
+        "#
+        )
+        .unwrap();
+        assert!(no_authentication_usable(file.path(), true).await.unwrap());
+        assert!(!no_authentication_usable(file.path(), false).await.unwrap());
+
+        writeln!(
+            file,
+            r#"
+auth-group no-authentication {{
+    auth-type = "none"
+}}
+        "#
+        )
+        .unwrap();
+        assert!(no_authentication_usable(file.path(), false).await.unwrap());
+    }
+
     #[tokio::test]
     async fn test_missing_config_file() {
         let result = validate_portal_group_exists("/nonexistent/path", "pg0").await;
@@ -3,9 +3,9 @@
 //! This module provides a single manager for both iSCSI targets and NVMeoF controllers,
 //! simplifying the architecture and reducing code duplication.
 
-use std::collections::HashMap;
 use std::collections::hash_map::Entry;
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 use tokio::process::Command;
 
@@ -19,7 +19,13 @@ use tempfile::NamedTempFile;
 
 use super::error::{CtlError, Result};
 use super::types::{AuthConfig, DevicePath, ExportType, Iqn, Nqn, TargetName};
-use super::ucl_config::{AuthGroup, Controller, CtlOptions, Target, ToUcl};
+use super::ucl_config::{
+    AuthGroup, Controller, CtlOptions, IdentifierScheme, Target, ToUcl, ucl_quote,
+};
+
+/// Prefix of controller group names within the base NQN
+/// (`<base>:group:<group>`)
+pub const CONTROLLER_GROUP_NQN_PREFIX: &str = "group:";
 
 /// Default path for CSI-managed targets config
 const CSI_CONFIG_PATH: &str = "/var/db/ctld-agent/csi-targets.conf";
@@ -41,6 +47,46 @@ pub struct Export {
     pub auth: AuthConfig,
     /// CTL options (blocksize, pblocksize, unmap)
     pub ctl_options: CtlOptions,
+    /// Initiator names (iSCSI) or host NQNs (NVMeoF) of the nodes the volume
+    /// is published to; when set, only they may connect (see
+    /// [`CtlManager::set_allowed_initiators`])
+    pub allowed_initiators: Vec<String>,
+}
+
+/// Namespace IDs promised to volumes that are not exported yet, by
+/// controller group and then volume name
+type NamespaceReservations = Arc<Mutex<HashMap<String, HashMap<String, u32>>>>;
+
+/// A namespace ID held for a volume until it is exported.
+///
+/// Creating a grouped volume picks its namespace ID long before the export
+/// is registered (the zvol is created in between). The reservation keeps
+/// concurrent creates in the same group from picking the same ID; it is
+/// released on drop, by which time a successful export holds the ID.
+#[derive(Debug)]
+pub struct NamespaceReservation {
+    reservations: NamespaceReservations,
+    group: String,
+    volume_name: String,
+    /// The reserved namespace ID
+    pub id: u32,
+}
+
+impl Drop for NamespaceReservation {
+    fn drop(&mut self) {
+        let mut reservations = self
+            .reservations
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(group) = reservations.get_mut(&self.group) {
+            if group.get(&self.volume_name) == Some(&self.id) {
+                group.remove(&self.volume_name);
+            }
+            if group.is_empty() {
+                reservations.remove(&self.group);
+            }
+        }
+    }
 }
 
 /// Unified manager for CTL exports (iSCSI and NVMeoF)
@@ -57,8 +103,18 @@ pub struct CtlManager {
     parent_dataset: String,
     /// In-memory cache of all exports, keyed by volume name
     exports: RwLock<HashMap<String, Export>>,
+    /// Namespace IDs reserved by creates that have not exported yet
+    reservations: NamespaceReservations,
     /// Path to write CSI-managed targets config
     csi_config_path: String,
+    /// Scheme for LUN/namespace world-wide identifiers
+    identifier_scheme: IdentifierScheme,
+    /// Existing auth-group used by exports without per-volume auth
+    /// (instead of `no-authentication`)
+    default_auth_group: Option<String>,
+    /// Define `no-authentication` in the CSI config because ctld does not
+    /// provide it
+    define_no_authentication: bool,
 }
 
 impl CtlManager {
@@ -102,10 +158,64 @@ impl CtlManager {
             transport_group,
             parent_dataset,
             exports: RwLock::new(HashMap::new()),
+            reservations: NamespaceReservations::default(),
             csi_config_path: CSI_CONFIG_PATH.to_string(),
+            identifier_scheme: IdentifierScheme::default(),
+            default_auth_group: None,
+            define_no_authentication: false,
         })
     }
 
+    /// Reference `group` from exports that have no per-volume auth.
+    ///
+    /// The group must already be defined in ctl.conf; the agent never
+    /// generates it.
+    pub fn with_default_auth_group(mut self, group: Option<String>) -> Self {
+        self.default_auth_group = group.filter(|g| !g.is_empty());
+        self
+    }
+
+    /// Write an explicit `auth-group "no-authentication"` into the CSI
+    /// config whenever an export references it.
+    ///
+    /// Only for ctld builds that do not predefine the group and configs that
+    /// do not define it themselves; otherwise ctld rejects the duplicate.
+    pub fn with_explicit_no_authentication(mut self, define: bool) -> Self {
+        self.define_no_authentication = define;
+        self
+    }
+
+    /// Auth group referenced by an export's target or controller
+    fn auth_group_for(&self, export: &Export) -> String {
+        match (&export.auth, &self.default_auth_group) {
+            _ if self.restricts_initiators(export) => {
+                format!("ag-{}", export.volume_name)
+            }
+            (AuthConfig::None, Some(group)) => group.clone(),
+            (auth, _) => auth.auth_group_name(&export.volume_name),
+        }
+    }
+
+    /// Whether an export gets its own auth-group admitting only its allowed
+    /// initiators.
+    ///
+    /// Only exports without authentication are restricted: per-volume and
+    /// referenced groups are not regenerated, a default auth-group carries
+    /// the operator's own restrictions, and a controller group's namespaces
+    /// share one auth-group.
+    fn restricts_initiators(&self, export: &Export) -> bool {
+        !export.allowed_initiators.is_empty()
+            && export.auth == AuthConfig::None
+            && self.default_auth_group.is_none()
+            && export.ctl_options.controller_group.is_none()
+    }
+
+    /// Set the scheme used for LUN/namespace identifiers of new exports
+    pub fn with_identifier_scheme(mut self, scheme: IdentifierScheme) -> Self {
+        self.identifier_scheme = scheme;
+        self
+    }
+
     /// Generate an IQN for a volume
     pub fn generate_iqn(&self, volume_name: &str) -> Result<Iqn> {
         Iqn::new(&self.base_iqn, volume_name)
@@ -116,6 +226,32 @@ impl CtlManager {
         Nqn::new(&self.base_nqn, volume_name)
     }
 
+    /// Generate the NQN of a controller group's shared controller.
+    ///
+    /// Group NQNs live under `<base>:group:` so a group can never take the
+    /// NQN of a volume's own controller (volume names cannot contain `:`).
+    pub fn generate_group_nqn(&self, group: &str) -> Result<Nqn> {
+        if group.contains(':') {
+            return Err(CtlError::InvalidName(format!(
+                "controller group '{}' cannot contain ':'",
+                group
+            )));
+        }
+        Nqn::new(
+            &self.base_nqn,
+            &format!("{}{}", CONTROLLER_GROUP_NQN_PREFIX, group),
+        )
+    }
+
+    /// Transport group an NVMeoF export's controller listens on
+    fn transport_group_for(&self, export: &Export) -> String {
+        export
+            .ctl_options
+            .transport_group
+            .clone()
+            .unwrap_or_else(|| self.transport_group.clone())
+    }
+
     /// Export a volume via iSCSI or NVMeoF
     ///
     /// Updates in-memory cache only. Call `write_config()` to persist.
@@ -135,19 +271,40 @@ impl CtlManager {
         export_type: ExportType,
         lun_id: u32,
         auth: AuthConfig,
-        ctl_options: CtlOptions,
+        mut ctl_options: CtlOptions,
     ) -> Result<Export> {
         // Validate and parse inputs using newtypes
-        let device_path = DevicePath::parse(device_path)?;
+        let device_path = if ctl_options.file_backed {
+            DevicePath::from_backing_file(device_path, volume_name)?
+        } else {
+            let device_path = DevicePath::parse(device_path)?;
+
+            // SECURITY: Validate device path is under the configured parent dataset.
+            // This prevents privilege escalation by ensuring we can only export
+            // volumes within our managed ZFS dataset hierarchy.
+            device_path.validate_parent_dataset(&self.parent_dataset)?;
+            device_path
+        };
 
-        // SECURITY: Validate device path is under the configured parent dataset.
-        // This prevents privilege escalation by ensuring we can only export
-        // volumes within our managed ZFS dataset hierarchy.
-        device_path.validate_parent_dataset(&self.parent_dataset)?;
+        ctl_options.identifier_scheme = self.identifier_scheme;
 
-        let target_name: TargetName = match export_type {
-            ExportType::Iscsi => self.generate_iqn(volume_name)?.into(),
-            ExportType::Nvmeof => self.generate_nqn(volume_name)?.into(),
+        let controller_group = match export_type {
+            ExportType::Nvmeof => ctl_options.controller_group.clone(),
+            ExportType::Iscsi => None,
+        };
+
+        // Grouped namespaces share one controller and therefore one auth-group
+        if controller_group.is_some() && auth.is_some() {
+            return Err(CtlError::ConfigError(format!(
+                "volume {} in a controller group cannot use per-volume authentication",
+                volume_name
+            )));
+        }
+
+        let target_name: TargetName = match (export_type, controller_group.as_deref()) {
+            (ExportType::Iscsi, _) => self.generate_iqn(volume_name)?.into(),
+            (ExportType::Nvmeof, Some(group)) => self.generate_group_nqn(group)?.into(),
+            (ExportType::Nvmeof, None) => self.generate_nqn(volume_name)?.into(),
         };
 
         debug!(
@@ -166,6 +323,7 @@ impl CtlManager {
             lun_id,
             auth,
             ctl_options,
+            allowed_initiators: Vec::new(),
         };
 
         // Use Entry API for atomic check-and-insert
@@ -173,6 +331,54 @@ impl CtlManager {
             .exports
             .write()
             .map_err(|e| CtlError::ConfigError(format!("Lock poisoned: {}", e)))?;
+        if let Some(ref group) = controller_group {
+            let members = || {
+                exports.values().filter(|e| {
+                    e.ctl_options.controller_group.as_deref() == Some(group.as_str())
+                        && e.volume_name != volume_name
+                })
+            };
+            if let Some(other) = members().find(|e| e.lun_id == lun_id) {
+                return Err(CtlError::ConfigError(format!(
+                    "namespace {} in controller group {} is already used by volume {}",
+                    lun_id, group, other.volume_name
+                )));
+            }
+            // The members share one controller, which has a single auth-group
+            // and transport group
+            if let Some(other) = members().next() {
+                let (auth_group, other_auth_group) =
+                    (self.auth_group_for(&export), self.auth_group_for(other));
+                if auth_group != other_auth_group {
+                    return Err(CtlError::ConfigError(format!(
+                        "volume {} uses auth-group {} but controller group {} uses {}",
+                        volume_name, auth_group, group, other_auth_group
+                    )));
+                }
+                let (transport_group, other_transport_group) = (
+                    self.transport_group_for(&export),
+                    self.transport_group_for(other),
+                );
+                if transport_group != other_transport_group {
+                    return Err(CtlError::ConfigError(format!(
+                        "volume {} uses transport-group {} but controller group {} uses {}",
+                        volume_name, transport_group, group, other_transport_group
+                    )));
+                }
+            }
+            let reservations = self.lock_reservations();
+            if let Some((other, _)) = reservations
+                .get(group)
+                .into_iter()
+                .flatten()
+                .find(|(name, id)| **id == lun_id && name.as_str() != volume_name)
+            {
+                return Err(CtlError::ConfigError(format!(
+                    "namespace {} in controller group {} is reserved by volume {}",
+                    lun_id, group, other
+                )));
+            }
+        }
         match exports.entry(volume_name.to_string()) {
             Entry::Occupied(_) => {
                 return Err(CtlError::TargetExists(volume_name.to_string()));
@@ -205,6 +411,185 @@ impl CtlManager {
         Ok(())
     }
 
+    /// Replace the authentication of an exported volume, returning the
+    /// previous configuration.
+    ///
+    /// Updates in-memory cache only. Call `write_config()` to persist.
+    #[instrument(skip(self, auth))]
+    pub fn update_auth(&self, volume_name: &str, auth: AuthConfig) -> Result<AuthConfig> {
+        // Rejects credentials that cannot be rendered before the cache changes
+        AuthGroup::from_auth_config(&auth, volume_name)?;
+
+        let mut exports = self
+            .exports
+            .write()
+            .map_err(|e| CtlError::ConfigError(format!("Lock poisoned: {}", e)))?;
+        let export = exports
+            .get_mut(volume_name)
+            .ok_or_else(|| CtlError::TargetNotFound(volume_name.to_string()))?;
+        if export.ctl_options.controller_group.is_some() && auth.is_some() {
+            return Err(CtlError::ConfigError(format!(
+                "volume {} in a controller group cannot use per-volume authentication",
+                volume_name
+            )));
+        }
+
+        let previous = std::mem::replace(&mut export.auth, auth);
+        info!("Updated authentication of {} (cache only)", volume_name);
+        Ok(previous)
+    }
+
+    /// Admit only `initiators` (iSCSI initiator names or NVMeoF host NQNs)
+    /// to an exported volume, or anyone when empty, returning the previous
+    /// list.
+    ///
+    /// Ignored for exports that cannot be restricted (see
+    /// [`Self::restricts_initiators`]). Updates in-memory cache only. Call
+    /// `write_config()` to persist.
+    #[instrument(skip(self))]
+    pub fn set_allowed_initiators(
+        &self,
+        volume_name: &str,
+        initiators: Vec<String>,
+    ) -> Result<Vec<String>> {
+        let mut exports = self
+            .exports
+            .write()
+            .map_err(|e| CtlError::ConfigError(format!("Lock poisoned: {}", e)))?;
+        let export = exports
+            .get_mut(volume_name)
+            .ok_or_else(|| CtlError::TargetNotFound(volume_name.to_string()))?;
+        // Rejects names that cannot be rendered before the cache changes
+        AuthGroup::restricted(export.export_type, &initiators)?;
+
+        let previous = std::mem::replace(&mut export.allowed_initiators, initiators);
+        if previous != export.allowed_initiators {
+            info!(
+                "Allowed initiators of {} set to {:?} (cache only)",
+                volume_name, export.allowed_initiators
+            );
+        }
+        Ok(previous)
+    }
+
+    /// Check that a volume joining controller group `group` uses the
+    /// transport group its members already share.
+    ///
+    /// `transport_group` is the volume's own `transportGroup`, `None` for the
+    /// agent's default. The group controller has a single transport group, so
+    /// a volume asking for another one could not be served through it.
+    pub fn check_group_transport_group(
+        &self,
+        group: &str,
+        volume_name: &str,
+        transport_group: Option<&str>,
+    ) -> Result<()> {
+        let exports = self
+            .exports
+            .read()
+            .map_err(|e| CtlError::ConfigError(format!("Lock poisoned: {}", e)))?;
+        let Some(member) = exports.values().find(|e| {
+            e.ctl_options.controller_group.as_deref() == Some(group) && e.volume_name != volume_name
+        }) else {
+            return Ok(());
+        };
+        let transport_group = transport_group.unwrap_or(&self.transport_group);
+        let member_transport_group = self.transport_group_for(member);
+        if transport_group != member_transport_group {
+            return Err(CtlError::ConfigError(format!(
+                "volume {} uses transport-group {} but controller group {} uses {}",
+                volume_name, transport_group, group, member_transport_group
+            )));
+        }
+        Ok(())
+    }
+
+    /// Pick the namespace ID for a volume in a controller group.
+    ///
+    /// Returns the volume's current namespace ID if it is already exported in
+    /// the group (or reserved for it), otherwise the lowest ID neither used
+    /// nor reserved by another member. NVMe namespace IDs start at 1 (NSID 0
+    /// is reserved).
+    pub fn allocate_namespace_id(&self, group: &str, volume_name: &str) -> Result<u32> {
+        let exports = self
+            .exports
+            .read()
+            .map_err(|e| CtlError::ConfigError(format!("Lock poisoned: {}", e)))?;
+        let reservations = self.lock_reservations();
+        Self::next_namespace_id(&exports, reservations.get(group), group, volume_name)
+    }
+
+    /// Allocate a namespace ID like [`Self::allocate_namespace_id`] and hold
+    /// it for `volume_name` until the returned reservation is dropped.
+    ///
+    /// Allocation and reservation happen under one lock, so concurrent
+    /// creates in the same group always get distinct IDs.
+    pub fn reserve_namespace_id(
+        &self,
+        group: &str,
+        volume_name: &str,
+    ) -> Result<NamespaceReservation> {
+        let exports = self
+            .exports
+            .read()
+            .map_err(|e| CtlError::ConfigError(format!("Lock poisoned: {}", e)))?;
+        let mut reservations = self.lock_reservations();
+        let id = Self::next_namespace_id(&exports, reservations.get(group), group, volume_name)?;
+        reservations
+            .entry(group.to_string())
+            .or_default()
+            .insert(volume_name.to_string(), id);
+        debug!(group = %group, volume = %volume_name, namespace_id = id, "Reserved namespace ID");
+
+        Ok(NamespaceReservation {
+            reservations: self.reservations.clone(),
+            group: group.to_string(),
+            volume_name: volume_name.to_string(),
+            id,
+        })
+    }
+
+    fn lock_reservations(
+        &self,
+    ) -> std::sync::MutexGuard<'_, HashMap<String, HashMap<String, u32>>> {
+        self.reservations
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn next_namespace_id(
+        exports: &HashMap<String, Export>,
+        reserved: Option<&HashMap<String, u32>>,
+        group: &str,
+        volume_name: &str,
+    ) -> Result<u32> {
+        let members: Vec<&Export> = exports
+            .values()
+            .filter(|e| {
+                e.export_type == ExportType::Nvmeof
+                    && e.ctl_options.controller_group.as_deref() == Some(group)
+            })
+            .collect();
+
+        if let Some(existing) = members.iter().find(|e| e.volume_name == volume_name) {
+            return Ok(existing.lun_id);
+        }
+        if let Some(id) = reserved.and_then(|r| r.get(volume_name)) {
+            return Ok(*id);
+        }
+
+        let taken = |id: u32| {
+            members.iter().any(|e| e.lun_id == id)
+                || reserved.is_some_and(|r| r.values().any(|reserved| *reserved == id))
+        };
+        (1..=u32::MAX).find(|id| !taken(*id)).ok_or_else(|| {
+            CtlError::ConfigError(format!(
+                "no free namespace ID left in controller group {}",
+                group
+            ))
+        })
+    }
+
     /// Get an export by volume name
     pub fn get_export(&self, volume_name: &str) -> Option<Export> {
         let exports = self.exports.read().unwrap();
@@ -220,32 +605,85 @@ impl CtlManager {
     /// Generates per-volume auth-groups for targets that require authentication.
     #[instrument(skip(self))]
     pub async fn write_config(&self) -> Result<()> {
+        let config = self.render_config()?;
+
+        // Write atomically using temp file + rename
+        let config_path = Path::new(&self.csi_config_path);
+        let config_dir = config_path
+            .parent()
+            .unwrap_or(Path::new("/var/db/ctld-agent"));
+
+        // Ensure config directory exists
+        if !config_dir.exists() {
+            std::fs::create_dir_all(config_dir).map_err(CtlError::Io)?;
+        }
+
+        let mut temp_file = NamedTempFile::new_in(config_dir).map_err(CtlError::Io)?;
+        temp_file
+            .write_all(config.as_bytes())
+            .map_err(CtlError::Io)?;
+        temp_file
+            .persist(&self.csi_config_path)
+            .map_err(|e| CtlError::Io(e.error))?;
+
+        info!("CSI config written to {}", self.csi_config_path);
+
+        self.reload_ctld().await?;
+
+        Ok(())
+    }
+
+    /// Render the CSI-managed targets config for the current exports
+    fn render_config(&self) -> Result<String> {
         use std::fmt::Write;
 
         // Collect targets and auth groups while holding the lock
         // Use a block to ensure the lock guard is dropped before any await points
         let (iscsi_targets, nvme_controllers, auth_groups) = {
-            let exports = self.exports.read().unwrap();
+            let exports = self
+                .exports
+                .read()
+                .map_err(|e| CtlError::ConfigError(format!("Lock poisoned: {}", e)))?;
 
             let mut iscsi_targets: Vec<(String, Target)> = Vec::new();
             let mut nvme_controllers: Vec<(String, Controller)> = Vec::new();
+            // Controllers shared by a controller group, keyed by NQN
+            let mut group_controllers: BTreeMap<String, Controller> = BTreeMap::new();
             let mut auth_groups: Vec<(String, AuthGroup)> = Vec::new();
 
             for export in exports.values() {
-                // Get auth group name (either "no-authentication" or per-volume "ag-<name>")
-                let auth_group_name = export.auth.auth_group_name(&export.volume_name);
+                // Get auth group name ("no-authentication" or the default group,
+                // a referenced group, or per-volume "ag-<name>")
+                let auth_group_name = self.auth_group_for(export);
+
+                if self.define_no_authentication
+                    && auth_group_name == "no-authentication"
+                    && !auth_groups.iter().any(|(name, _)| name == &auth_group_name)
+                {
+                    auth_groups.push((auth_group_name.clone(), AuthGroup::no_authentication()));
+                }
 
                 // If this export has authentication, create an auth group entry
                 // This validates CHAP credentials don't contain characters that would corrupt UCL
-                if let Some(ag) = AuthGroup::from_auth_config(&export.auth, &export.volume_name)? {
+                if self.restricts_initiators(export) {
+                    let ag = AuthGroup::restricted(export.export_type, &export.allowed_initiators)?;
+                    auth_groups.push((auth_group_name.clone(), ag));
+                } else if let Some(ag) =
+                    AuthGroup::from_auth_config(&export.auth, &export.volume_name)?
+                {
                     auth_groups.push((auth_group_name.clone(), ag));
                 }
 
                 match export.export_type {
                     ExportType::Iscsi => {
+                        let portal_group = export
+                            .ctl_options
+                            .portal_group
+                            .clone()
+                            .unwrap_or_else(|| self.portal_group_name.clone());
                         let target = Target::with_options(
                             auth_group_name,
-                            self.portal_group_name.clone(),
+                            portal_group,
                             export.lun_id,
                             export.device_path.as_str().to_string(),
                             &export.volume_name,
@@ -254,9 +692,26 @@ impl CtlManager {
                         iscsi_targets.push((export.target_name.to_string(), target));
                     }
                     ExportType::Nvmeof => {
+                        let transport_group = self.transport_group_for(export);
+                        // Members of a group agree on auth and transport
+                        // group (checked at export time)
+                        if let Some(ref group) = export.ctl_options.controller_group {
+                            group_controllers
+                                .entry(export.target_name.to_string())
+                                .or_insert_with(|| {
+                                    Controller::for_group(auth_group_name, transport_group, group)
+                                })
+                                .add_namespace(
+                                    export.lun_id,
+                                    export.device_path.as_str().to_string(),
+                                    &export.volume_name,
+                                    &export.ctl_options,
+                                );
+                            continue;
+                        }
                         let controller = Controller::with_options(
                             auth_group_name,
-                            self.transport_group.clone(),
+                            transport_group,
                             export.lun_id,
                             export.device_path.as_str().to_string(),
                             &export.volume_name,
@@ -267,6 +722,8 @@ impl CtlManager {
                 }
             }
 
+            nvme_controllers.extend(group_controllers);
+
             (iscsi_targets, nvme_controllers, auth_groups)
         };
 
@@ -291,7 +748,7 @@ impl CtlManager {
 
         // Write auth groups
         for (name, auth_group) in &auth_groups {
-            writeln!(config, "auth-group \"{}\" {{", name).unwrap();
+            writeln!(config, "auth-group {} {{", ucl_quote(name)).unwrap();
             write!(config, "{}", auth_group.to_ucl(1)).unwrap();
             writeln!(config, "}}").unwrap();
             writeln!(config).unwrap();
@@ -299,7 +756,7 @@ impl CtlManager {
 
         // Write iSCSI targets
         for (iqn, target) in &iscsi_targets {
-            writeln!(config, "target \"{}\" {{", iqn).unwrap();
+            writeln!(config, "target {} {{", ucl_quote(iqn)).unwrap();
             write!(config, "{}", target.to_ucl(1)).unwrap();
             writeln!(config, "}}").unwrap();
             writeln!(config).unwrap();
@@ -307,58 +764,249 @@ impl CtlManager {
 
         // Write NVMeoF controllers
         for (nqn, controller) in &nvme_controllers {
-            writeln!(config, "controller \"{}\" {{", nqn).unwrap();
+            writeln!(config, "controller {} {{", ucl_quote(nqn)).unwrap();
             write!(config, "{}", controller.to_ucl(1)).unwrap();
             writeln!(config, "}}").unwrap();
             writeln!(config).unwrap();
         }
 
-        // Write atomically using temp file + rename
-        let config_path = Path::new(&self.csi_config_path);
-        let config_dir = config_path
-            .parent()
-            .unwrap_or(Path::new("/var/db/ctld-agent"));
+        Ok(config)
+    }
 
-        // Ensure config directory exists
-        if !config_dir.exists() {
-            std::fs::create_dir_all(config_dir).map_err(CtlError::Io)?;
-        }
+    /// Reload ctld configuration
+    ///
+    /// Transient failures (busy, lock contention) are retried once after a
+    /// short delay; configuration errors are surfaced immediately.
+    async fn reload_ctld(&self) -> Result<()> {
+        retry_transient_reload(
+            run_ctld_reload,
+            Duration::from_millis(RELOAD_RETRY_DELAY_MS),
+        )
+        .await?;
 
-        let mut temp_file = NamedTempFile::new_in(config_dir).map_err(CtlError::Io)?;
-        temp_file
-            .write_all(config.as_bytes())
-            .map_err(CtlError::Io)?;
-        temp_file
-            .persist(&self.csi_config_path)
-            .map_err(|e| CtlError::Io(e.error))?;
+        info!("Successfully reloaded ctld configuration");
+        Ok(())
+    }
+}
 
-        info!("CSI config written to {}", self.csi_config_path);
+/// An export found in the persisted CSI targets config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PersistedExport {
+    /// Volume the LUN/namespace path points at
+    pub volume_name: String,
+    /// iSCSI target or NVMeoF controller
+    pub export_type: ExportType,
+    /// IQN or NQN of the target
+    pub target_name: String,
+    /// LUN ID or namespace ID
+    pub lun_id: u32,
+    /// auth-group referenced by the target
+    pub auth_group: String,
+}
 
-        self.reload_ctld().await?;
+impl CtlManager {
+    /// Read the exports from the last CSI config written by `write_config`.
+    ///
+    /// The config is not loaded at startup (ZFS metadata is the source of
+    /// truth); this is only used to repair volumes whose metadata is corrupt.
+    /// A missing file yields no exports.
+    pub async fn read_persisted_exports(&self) -> Result<Vec<PersistedExport>> {
+        let content = match tokio::fs::read_to_string(&self.csi_config_path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(CtlError::Io(e)),
+        };
+        parse_persisted_exports(&content, &self.parent_dataset)
+    }
 
-        Ok(())
+    /// Base IQN or NQN this manager names targets under
+    pub fn base_name(&self, export_type: ExportType) -> &str {
+        match export_type {
+            ExportType::Iscsi => &self.base_iqn,
+            ExportType::Nvmeof => &self.base_nqn,
+        }
     }
+}
 
-    /// Reload ctld configuration
-    async fn reload_ctld(&self) -> Result<()> {
-        debug!("Reloading ctld configuration");
-
-        let output = Command::new("service")
-            .args(["ctld", "reload"])
-            .output()
-            .await?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            warn!("ctld reload failed: {}", stderr);
-            return Err(CtlError::CommandFailed(format!(
-                "service ctld reload failed: {}",
-                stderr
-            )));
+/// Parse the exports of a CSI targets config.
+///
+/// Each LUN/namespace is attributed to a volume by its zvol path under
+/// `parent_dataset`; entries with any other path (including file-backed
+/// volumes) are skipped.
+pub fn parse_persisted_exports(
+    content: &str,
+    parent_dataset: &str,
+) -> Result<Vec<PersistedExport>> {
+    use uclicious::{DEFAULT_DUPLICATE_STRATEGY, Priority};
+
+    let mut parser = uclicious::raw::Parser::default();
+    parser
+        .add_chunk_full(content, Priority::default(), DEFAULT_DUPLICATE_STRATEGY)
+        .map_err(|e| CtlError::ConfigError(format!("failed to parse CSI config: {}", e)))?;
+    let root = parser
+        .get_object()
+        .map_err(|e| CtlError::ConfigError(format!("failed to parse CSI config: {}", e)))?;
+
+    let mut exports = Vec::new();
+    for (export_type, section, units) in [
+        (ExportType::Iscsi, "target", "lun"),
+        (ExportType::Nvmeof, "controller", "namespace"),
+    ] {
+        let Some(blocks) = root.lookup(section) else {
+            continue;
+        };
+        for block in blocks.iter() {
+            let Some(target_name) = block.key() else {
+                continue;
+            };
+            let auth_group = block
+                .lookup("auth-group")
+                .and_then(|v| v.as_string())
+                .unwrap_or_default();
+            let Some(unit_blocks) = block.lookup(units) else {
+                continue;
+            };
+            for unit in unit_blocks.iter() {
+                let lun_id = unit.key().and_then(|k| k.parse::<u32>().ok());
+                let path = unit.lookup("path").and_then(|v| v.as_string());
+                let (Some(lun_id), Some(path)) = (lun_id, path) else {
+                    continue;
+                };
+                let Some(volume_name) = zvol_volume_name(&path, parent_dataset) else {
+                    debug!(path = %path, "Skipping persisted export outside the parent dataset");
+                    continue;
+                };
+                exports.push(PersistedExport {
+                    volume_name,
+                    export_type,
+                    target_name: target_name.clone(),
+                    lun_id,
+                    auth_group: auth_group.clone(),
+                });
+            }
         }
+    }
+    Ok(exports)
+}
 
-        info!("Successfully reloaded ctld configuration");
-        Ok(())
+/// Volume name of a zvol device path directly under `parent_dataset`
+fn zvol_volume_name(path: &str, parent_dataset: &str) -> Option<String> {
+    let device_path = DevicePath::parse(path).ok()?;
+    device_path.validate_parent_dataset(parent_dataset).ok()?;
+    let name = device_path
+        .dataset_name()
+        .strip_prefix(parent_dataset)?
+        .strip_prefix('/')?;
+    (!name.contains('/')).then(|| name.to_string())
+}
+
+/// Delay before retrying a transiently failed ctld reload
+const RELOAD_RETRY_DELAY_MS: u64 = 250;
+
+/// Run `service ctld reload` once
+async fn run_ctld_reload() -> Result<()> {
+    debug!("Reloading ctld configuration");
+
+    let output = Command::new("service")
+        .args(["ctld", "reload"])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        warn!("ctld reload failed: {}", stderr);
+        return Err(CtlError::CommandFailed(format!(
+            "service ctld reload failed: {}",
+            stderr
+        )));
+    }
+
+    Ok(())
+}
+
+/// Whether ctld has an online CTL port for `target_name`.
+///
+/// Uses `ctladm portlist`, which only lists a target once ctld has applied
+/// the config that defines it.
+pub async fn is_target_live(export_type: ExportType, target_name: &str) -> Result<bool> {
+    let frontend = match export_type {
+        ExportType::Iscsi => "iscsi",
+        ExportType::Nvmeof => "nvmf",
+    };
+    let output = Command::new("ctladm")
+        .args(["portlist", "-q", "-f", frontend])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(CtlError::CommandFailed(format!(
+            "ctladm portlist failed: {}",
+            stderr
+        )));
+    }
+
+    Ok(target_port_online(
+        &String::from_utf8_lossy(&output.stdout),
+        target_name,
+    ))
+}
+
+/// Whether `ctladm portlist -q` output has an online port for `target_name`.
+///
+/// Lines look like `3  YES  iscsi  iscsi  257  1  iqn...:pvc-1,t,0x0101`;
+/// the target name is the last column up to the first comma.
+fn target_port_online(portlist: &str, target_name: &str) -> bool {
+    portlist.lines().any(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        fields.len() >= 3
+            && fields[1].eq_ignore_ascii_case("yes")
+            && fields
+                .last()
+                .and_then(|port| port.split(',').next())
+                .is_some_and(|name| name == target_name)
+    })
+}
+
+/// Whether a failed reload is worth retrying.
+///
+/// Configuration errors will fail again on retry, so they are never
+/// transient even if the message also mentions a busy resource.
+fn is_transient_reload_failure(message: &str) -> bool {
+    const FATAL: &[&str] = &["parse", "syntax", "invalid", "unknown", "error in"];
+    const TRANSIENT: &[&str] = &[
+        "resource temporarily unavailable",
+        "device busy",
+        "resource busy",
+        "try again",
+        "lock",
+        "interrupted",
+        "timed out",
+    ];
+
+    let message = message.to_lowercase();
+    if FATAL.iter().any(|p| message.contains(p)) {
+        return false;
+    }
+    TRANSIENT.iter().any(|p| message.contains(p))
+}
+
+/// Run a reload attempt, retrying once after `delay` if it fails transiently.
+async fn retry_transient_reload<F, Fut>(mut attempt: F, delay: Duration) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    match attempt().await {
+        Err(CtlError::CommandFailed(msg)) if is_transient_reload_failure(&msg) => {
+            warn!(
+                "ctld reload failed transiently, retrying once in {:?}: {}",
+                delay, msg
+            );
+            tokio::time::sleep(delay).await;
+            attempt().await
+        }
+        other => other,
     }
 }
 
@@ -368,13 +1016,17 @@ impl CtlManager {
 
 /// Default debounce duration for config writes.
 /// Multiple write requests within this window are batched into one write.
-const CONFIG_WRITE_DEBOUNCE_MS: u64 = 50;
-
-/// A write request with an optional response channel.
-struct WriteRequest {
-    /// Channel to send the result back to the caller.
-    /// If None, this is a fire-and-forget request.
-    response_tx: Option<oneshot::Sender<Result<()>>>,
+pub const DEFAULT_CONFIG_WRITE_DEBOUNCE_MS: u64 = 50;
+
+/// Message to the config writer task.
+enum WriterMessage {
+    /// Write the config. The response channel is None for fire-and-forget
+    /// requests.
+    Write(Option<oneshot::Sender<Result<()>>>),
+    /// Hold back writes until the matching Resume
+    Pause,
+    /// Undo one Pause, flushing held-back writes once none remain
+    Resume(oneshot::Sender<Result<()>>),
 }
 
 /// Handle for requesting config writes.
@@ -383,7 +1035,7 @@ struct WriteRequest {
 /// Write requests are debounced and serialized by the background writer task.
 #[derive(Clone)]
 pub struct ConfigWriterHandle {
-    tx: mpsc::Sender<WriteRequest>,
+    tx: mpsc::Sender<WriterMessage>,
 }
 
 impl ConfigWriterHandle {
@@ -394,16 +1046,14 @@ impl ConfigWriterHandle {
     /// accessible before returning success.
     ///
     /// Multiple concurrent requests are batched - all waiters receive
-    /// the result of the same write operation.
+    /// the result of the same write operation. The write always starts after
+    /// the request was received, so it includes any change the caller made
+    /// before calling; a request arriving while a write is already running
+    /// gets a follow-up write. While the writer is paused this waits for
+    /// the pause to end.
     pub async fn write_config(&self) -> Result<()> {
         let (response_tx, response_rx) = oneshot::channel();
-
-        self.tx
-            .send(WriteRequest {
-                response_tx: Some(response_tx),
-            })
-            .await
-            .map_err(|_| CtlError::ConfigError("config writer task shut down".into()))?;
+        self.send(WriterMessage::Write(Some(response_tx))).await?;
 
         response_rx
             .await
@@ -415,7 +1065,84 @@ impl ConfigWriterHandle {
     /// Use this only for non-critical operations where you don't need
     /// to guarantee the write completed before continuing.
     pub fn request_write_async(&self) {
-        let _ = self.tx.try_send(WriteRequest { response_tx: None });
+        let _ = self.tx.try_send(WriterMessage::Write(None));
+    }
+
+    /// Hold back config writes for a batch of export changes.
+    ///
+    /// Write requests made while paused are accumulated and flushed as one
+    /// write/reload once the returned [`WriterPause`] is resumed or dropped.
+    /// Pauses nest; writes go through again when the last one ends. A task
+    /// holding a pause must not await [`Self::write_config`] itself, since
+    /// that only completes on resume.
+    ///
+    /// Exports changed while paused exist only in memory. If the agent dies
+    /// before resuming, startup reconciliation re-exports them from the ZFS
+    /// metadata.
+    pub async fn pause(&self) -> Result<WriterPause> {
+        self.send(WriterMessage::Pause).await?;
+        Ok(WriterPause {
+            tx: Some(self.tx.clone()),
+        })
+    }
+
+    async fn send(&self, message: WriterMessage) -> Result<()> {
+        self.tx
+            .send(message)
+            .await
+            .map_err(|_| CtlError::ConfigError("config writer task shut down".into()))
+    }
+}
+
+/// An outstanding [`ConfigWriterHandle::pause`].
+///
+/// Call [`Self::resume`] to end it and wait for the held-back writes.
+/// Dropping it (e.g. on an early return) ends it too, without waiting.
+#[must_use = "writes stay held back until the pause is resumed or dropped"]
+pub struct WriterPause {
+    tx: Option<mpsc::Sender<WriterMessage>>,
+}
+
+impl WriterPause {
+    /// End the pause.
+    ///
+    /// When this was the last pause and writes were requested meanwhile, the
+    /// config is written and ctld reloaded before this returns, so a
+    /// successful resume means every change made while paused is persisted.
+    pub async fn resume(mut self) -> Result<()> {
+        let tx = self.tx.take().expect("pause already resumed");
+        let (response_tx, response_rx) = oneshot::channel();
+        tx.send(WriterMessage::Resume(response_tx))
+            .await
+            .map_err(|_| CtlError::ConfigError("config writer task shut down".into()))?;
+
+        response_rx
+            .await
+            .map_err(|_| CtlError::ConfigError("config writer task dropped response".into()))?
+    }
+}
+
+impl Drop for WriterPause {
+    fn drop(&mut self) {
+        let Some(tx) = self.tx.take() else {
+            return;
+        };
+        // Nobody waits for the flush; its result is only logged by the writer
+        let (response_tx, _) = oneshot::channel();
+        if let Err(mpsc::error::TrySendError::Full(message)) =
+            tx.try_send(WriterMessage::Resume(response_tx))
+        {
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    runtime.spawn(async move {
+                        let _ = tx.send(message).await;
+                    });
+                }
+                Err(_) => {
+                    warn!("Config writer pause dropped outside the runtime; writes stay held back")
+                }
+            }
+        }
     }
 }
 
@@ -431,38 +1158,92 @@ pub fn spawn_config_writer(
     ctl_manager: Arc<TokioRwLock<CtlManager>>,
     debounce_ms: Option<u64>,
 ) -> ConfigWriterHandle {
-    let (tx, rx) = mpsc::channel::<WriteRequest>(32);
-    let debounce = Duration::from_millis(debounce_ms.unwrap_or(CONFIG_WRITE_DEBOUNCE_MS));
+    let debounce = Duration::from_millis(debounce_ms.unwrap_or(DEFAULT_CONFIG_WRITE_DEBOUNCE_MS));
+
+    spawn_writer(debounce, move || {
+        let ctl_manager = ctl_manager.clone();
+        async move { ctl_manager.read().await.write_config().await }
+    })
+}
+
+/// Spawn the writer task around `write`, which persists the config
+fn spawn_writer<F, Fut>(debounce: Duration, write: F) -> ConfigWriterHandle
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel::<WriterMessage>(32);
 
-    tokio::spawn(config_writer_task(ctl_manager, rx, debounce));
+    tokio::spawn(config_writer_task(rx, debounce, write));
 
     ConfigWriterHandle { tx }
 }
 
 /// Background task that handles serialized config writes with debouncing.
-async fn config_writer_task(
-    ctl_manager: Arc<TokioRwLock<CtlManager>>,
-    mut rx: mpsc::Receiver<WriteRequest>,
+async fn config_writer_task<F, Fut>(
+    mut rx: mpsc::Receiver<WriterMessage>,
     debounce: Duration,
-) {
+    mut write: F,
+) where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
     info!("Config writer task started (debounce: {:?})", debounce);
 
-    while let Some(first_request) = rx.recv().await {
-        // Collect response channels from this batch
-        let mut response_channels: Vec<oneshot::Sender<Result<()>>> = Vec::new();
-        if let Some(tx) = first_request.response_tx {
-            response_channels.push(tx);
+    // Outstanding pauses
+    let mut paused = 0usize;
+    // A write was requested and has not been performed yet
+    let mut dirty = false;
+    // Callers waiting for the next write
+    let mut response_channels: Vec<oneshot::Sender<Result<()>>> = Vec::new();
+
+    while let Some(message) = rx.recv().await {
+        match message {
+            WriterMessage::Write(tx) => {
+                dirty = true;
+                response_channels.extend(tx);
+                if paused > 0 {
+                    continue;
+                }
+            }
+            WriterMessage::Pause => {
+                paused += 1;
+                debug!("Config writer paused ({} outstanding)", paused);
+                continue;
+            }
+            WriterMessage::Resume(tx) => {
+                paused = paused.saturating_sub(1);
+                if paused > 0 || !dirty {
+                    let _ = tx.send(Ok(()));
+                    continue;
+                }
+                debug!("Config writer resumed, flushing held-back writes");
+                response_channels.push(tx);
+            }
         }
 
-        // Debounce: wait for more requests to batch
+        // Debounce: wait for more requests to batch. The window starts at the
+        // first request and is not extended, so a steady stream of requests
+        // still gets written at least once per window. Requests arriving after
+        // the drain below (even during the write) stay queued and trigger the
+        // next write.
         if !debounce.is_zero() {
             tokio::time::sleep(debounce).await;
         }
 
-        // Drain any pending requests (they'll be handled by this write)
-        while let Ok(req) = rx.try_recv() {
-            if let Some(tx) = req.response_tx {
-                response_channels.push(tx);
+        // Drain any pending requests (they'll be handled by this write).
+        // A pause stops the drain so requests made after it are held back.
+        while let Ok(message) = rx.try_recv() {
+            match message {
+                WriterMessage::Write(tx) => response_channels.extend(tx),
+                WriterMessage::Pause => {
+                    paused += 1;
+                    break;
+                }
+                WriterMessage::Resume(tx) => {
+                    paused = paused.saturating_sub(1);
+                    response_channels.push(tx);
+                }
             }
         }
 
@@ -474,10 +1255,8 @@ async fn config_writer_task(
         }
 
         // Perform the actual write
-        let result = {
-            let ctl = ctl_manager.read().await;
-            ctl.write_config().await
-        };
+        let result = write().await;
+        dirty = false;
 
         // Log the result
         match &result {
@@ -492,7 +1271,7 @@ async fn config_writer_task(
             Err(e) => Err(CtlError::ConfigError(e.to_string())),
         };
 
-        for tx in response_channels {
+        for tx in response_channels.drain(..) {
             // Clone the wrapped result for each waiter
             let _ = tx.send(match &send_result {
                 Ok(()) => Ok(()),
@@ -521,6 +1300,7 @@ mod tests {
             lun_id: 0,
             auth: AuthConfig::None,
             ctl_options: CtlOptions::default(),
+            allowed_initiators: Vec::new(),
         };
 
         assert_eq!(export.volume_name, "vol1");
@@ -544,9 +1324,866 @@ mod tests {
             lun_id: 0,
             auth: AuthConfig::IscsiChap(chap),
             ctl_options: CtlOptions::default(),
+            allowed_initiators: Vec::new(),
         };
 
         assert!(export.auth.is_some());
         assert_eq!(export.auth.auth_group_name("vol2"), "ag-vol2");
     }
+
+    fn test_manager() -> CtlManager {
+        CtlManager::new(
+            "iqn.2024-01.org.freebsd.csi".to_string(),
+            "nqn.2024-01.org.freebsd.csi".to_string(),
+            "pg0".to_string(),
+            "tg0".to_string(),
+            "tank/csi".to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_persisted_exports_round_trip() {
+        let manager = test_manager();
+        manager
+            .export_volume(
+                "pvc-a",
+                "/dev/zvol/tank/csi/pvc-a",
+                ExportType::Iscsi,
+                0,
+                AuthConfig::IscsiChap(super::super::types::IscsiChapAuth::new("user", "secret")),
+                CtlOptions::default(),
+            )
+            .unwrap();
+        manager
+            .export_volume(
+                "pvc-b",
+                "/dev/zvol/tank/csi/pvc-b",
+                ExportType::Nvmeof,
+                1,
+                AuthConfig::None,
+                CtlOptions::default(),
+            )
+            .unwrap();
+        export_grouped(&manager, "pvc-c", "db").unwrap();
+        export_grouped(&manager, "pvc-d", "db").unwrap();
+
+        let config = manager.render_config().unwrap();
+        assert!(config.contains("auth-group \"ag-pvc-a\" {"), "{}", config);
+        assert!(
+            config.contains("target \"iqn.2024-01.org.freebsd.csi:pvc-a\" {"),
+            "{}",
+            config
+        );
+        assert!(
+            config.contains("controller \"nqn.2024-01.org.freebsd.csi:group:db\" {"),
+            "{}",
+            config
+        );
+        let mut exports = parse_persisted_exports(&config, "tank/csi").unwrap();
+        exports.sort_by(|a, b| a.volume_name.cmp(&b.volume_name));
+
+        assert_eq!(exports.len(), 4, "{:?}", exports);
+        assert_eq!(
+            exports[0],
+            PersistedExport {
+                volume_name: "pvc-a".to_string(),
+                export_type: ExportType::Iscsi,
+                target_name: "iqn.2024-01.org.freebsd.csi:pvc-a".to_string(),
+                lun_id: 0,
+                auth_group: "ag-pvc-a".to_string(),
+            }
+        );
+        assert_eq!(exports[1].export_type, ExportType::Nvmeof);
+        assert_eq!(exports[1].target_name, "nqn.2024-01.org.freebsd.csi:pvc-b");
+        assert_eq!(exports[1].auth_group, "no-authentication");
+        // Grouped namespaces share the group controller
+        assert_eq!(
+            exports[2].target_name,
+            "nqn.2024-01.org.freebsd.csi:group:db"
+        );
+        assert_eq!(
+            exports[3].target_name,
+            "nqn.2024-01.org.freebsd.csi:group:db"
+        );
+        assert_eq!((exports[2].lun_id, exports[3].lun_id), (1, 2));
+    }
+
+    #[test]
+    fn test_persisted_exports_skip_foreign_paths() {
+        let config = r#"
+target "iqn.2024-01.org.freebsd.csi:other" {
+    auth-group = "no-authentication";
+    portal-group = "pg0";
+    lun {
+        0 {
+            path = "/dev/zvol/other/pool/other";
+        }
+    }
+}
+"#;
+        assert!(
+            parse_persisted_exports(config, "tank/csi")
+                .unwrap()
+                .is_empty()
+        );
+        assert!(parse_persisted_exports("", "tank/csi").unwrap().is_empty());
+    }
+
+    fn group_options(group: &str) -> CtlOptions {
+        CtlOptions {
+            controller_group: Some(group.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn export_grouped(manager: &CtlManager, volume: &str, group: &str) -> Result<Export> {
+        let ns_id = manager.allocate_namespace_id(group, volume)?;
+        manager.export_volume(
+            volume,
+            &format!("/dev/zvol/tank/csi/{}", volume),
+            ExportType::Nvmeof,
+            ns_id,
+            AuthConfig::None,
+            group_options(group),
+        )
+    }
+
+    #[test]
+    fn test_grouped_exports_share_controller() {
+        let manager = test_manager();
+
+        let a = export_grouped(&manager, "pvc-a", "db").unwrap();
+        let b = export_grouped(&manager, "pvc-b", "db").unwrap();
+        let other = export_grouped(&manager, "pvc-c", "web").unwrap();
+
+        assert_eq!(
+            a.target_name.to_string(),
+            "nqn.2024-01.org.freebsd.csi:group:db"
+        );
+        assert_eq!(a.target_name, b.target_name);
+        assert_ne!(a.target_name, other.target_name);
+        assert_eq!((a.lun_id, b.lun_id, other.lun_id), (1, 2, 1));
+    }
+
+    #[test]
+    fn test_allocate_namespace_id_reuses_and_fills_gaps() {
+        let manager = test_manager();
+        export_grouped(&manager, "pvc-a", "db").unwrap();
+        export_grouped(&manager, "pvc-b", "db").unwrap();
+        export_grouped(&manager, "pvc-c", "db").unwrap();
+
+        // Existing member keeps its namespace ID
+        assert_eq!(manager.allocate_namespace_id("db", "pvc-b").unwrap(), 2);
+
+        // Freed IDs are reused for new members
+        manager.unexport_volume("pvc-b").unwrap();
+        assert_eq!(manager.allocate_namespace_id("db", "pvc-d").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_namespace_reservations_are_distinct_until_dropped() {
+        let manager = test_manager();
+        export_grouped(&manager, "pvc-a", "db").unwrap();
+
+        let b = manager.reserve_namespace_id("db", "pvc-b").unwrap();
+        let c = manager.reserve_namespace_id("db", "pvc-c").unwrap();
+        assert_eq!((b.id, c.id), (2, 3));
+        // A retry of the same create gets its reservation back
+        assert_eq!(manager.allocate_namespace_id("db", "pvc-b").unwrap(), 2);
+
+        // Another volume cannot be exported into a reserved slot
+        let err = manager
+            .export_volume(
+                "pvc-d",
+                "/dev/zvol/tank/csi/pvc-d",
+                ExportType::Nvmeof,
+                3,
+                AuthConfig::None,
+                group_options("db"),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("reserved by volume pvc-c"));
+
+        drop(c);
+        assert_eq!(manager.allocate_namespace_id("db", "pvc-d").unwrap(), 3);
+        drop(b);
+        assert_eq!(manager.allocate_namespace_id("db", "pvc-d").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_concurrent_grouped_exports_get_distinct_namespaces() {
+        let manager = test_manager();
+
+        let exports: Vec<Export> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|i| {
+                    let manager = &manager;
+                    scope.spawn(move || {
+                        let volume = format!("pvc-{}", i);
+                        let reservation = manager.reserve_namespace_id("db", &volume).unwrap();
+                        // The zvol is created while the ID is held
+                        std::thread::sleep(Duration::from_millis(5));
+                        let export = manager.export_volume(
+                            &volume,
+                            &format!("/dev/zvol/tank/csi/{}", volume),
+                            ExportType::Nvmeof,
+                            reservation.id,
+                            AuthConfig::None,
+                            group_options("db"),
+                        );
+                        drop(reservation);
+                        export.unwrap()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut ids: Vec<u32> = exports.iter().map(|e| e.lun_id).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, (1..=8).collect::<Vec<_>>());
+        assert!(manager.lock_reservations().is_empty());
+    }
+
+    #[test]
+    fn test_grouped_namespace_collision_rejected() {
+        let manager = test_manager();
+        export_grouped(&manager, "pvc-a", "db").unwrap();
+
+        let err = manager
+            .export_volume(
+                "pvc-b",
+                "/dev/zvol/tank/csi/pvc-b",
+                ExportType::Nvmeof,
+                1,
+                AuthConfig::None,
+                group_options("db"),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("already used by volume pvc-a"));
+    }
+
+    #[test]
+    fn test_grouped_export_rejects_per_volume_auth() {
+        let manager = test_manager();
+        let err = manager
+            .export_volume(
+                "pvc-a",
+                "/dev/zvol/tank/csi/pvc-a",
+                ExportType::Nvmeof,
+                1,
+                AuthConfig::GroupRef("ag-pvc-a".to_string()),
+                group_options("db"),
+            )
+            .unwrap_err();
+        assert!(matches!(err, CtlError::ConfigError(_)));
+    }
+
+    #[test]
+    fn test_grouped_export_rejects_mismatched_transport_group() {
+        let manager = test_manager();
+        export_grouped(&manager, "pvc-a", "db").unwrap();
+
+        let err = manager
+            .export_volume(
+                "pvc-b",
+                "/dev/zvol/tank/csi/pvc-b",
+                ExportType::Nvmeof,
+                2,
+                AuthConfig::None,
+                CtlOptions {
+                    transport_group: Some("tg-other".to_string()),
+                    ..group_options("db")
+                },
+            )
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("transport-group tg-other"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_check_group_transport_group() {
+        let manager = test_manager();
+        // An empty group takes any transport group
+        assert!(
+            manager
+                .check_group_transport_group("db", "pvc-a", Some("tg-other"))
+                .is_ok()
+        );
+
+        export_grouped(&manager, "pvc-a", "db").unwrap();
+        assert!(
+            manager
+                .check_group_transport_group("db", "pvc-b", None)
+                .is_ok()
+        );
+        let default = manager.transport_group.clone();
+        assert!(
+            manager
+                .check_group_transport_group("db", "pvc-b", Some(&default))
+                .is_ok()
+        );
+        let err = manager
+            .check_group_transport_group("db", "pvc-b", Some("tg-other"))
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("transport-group tg-other"),
+            "{}",
+            err
+        );
+        // The volume itself is not a member to agree with
+        assert!(
+            manager
+                .check_group_transport_group("db", "pvc-a", Some("tg-other"))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_group_nqn_cannot_collide_with_volume_nqn() {
+        let manager = test_manager();
+        let volume = manager.generate_nqn("db").unwrap();
+        let group = manager.generate_group_nqn("db").unwrap();
+        assert_ne!(volume, group);
+        assert!(manager.generate_group_nqn("a:b").is_err());
+    }
+
+    #[test]
+    fn test_reexport_missing_namespace_rejoins_group_controller() {
+        let manager = test_manager();
+        let a = export_grouped(&manager, "pvc-a", "db").unwrap();
+        let b = export_grouped(&manager, "pvc-b", "db").unwrap();
+
+        // Simulate a namespace lost from the controller, then reconciled from
+        // the namespace ID persisted in ZFS metadata
+        manager.unexport_volume("pvc-a").unwrap();
+        let restored = manager
+            .export_volume(
+                "pvc-a",
+                "/dev/zvol/tank/csi/pvc-a",
+                ExportType::Nvmeof,
+                a.lun_id,
+                AuthConfig::None,
+                group_options("db"),
+            )
+            .unwrap();
+
+        assert_eq!(restored.target_name, b.target_name);
+        assert_eq!(restored.lun_id, 1);
+        assert_eq!(manager.get_export("pvc-b").unwrap().lun_id, 2);
+    }
+
+    #[test]
+    fn test_export_uses_manager_identifier_scheme() {
+        let manager = test_manager().with_identifier_scheme(IdentifierScheme::Eui64);
+        let export = manager
+            .export_volume(
+                "pvc-a",
+                "/dev/zvol/tank/csi/pvc-a",
+                ExportType::Iscsi,
+                0,
+                AuthConfig::None,
+                CtlOptions::default(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            export.ctl_options.identifier_scheme,
+            IdentifierScheme::Eui64
+        );
+    }
+
+    #[test]
+    fn test_default_auth_group_used_without_per_volume_auth() {
+        let manager = test_manager().with_default_auth_group(Some("ag-subnet".to_string()));
+        use super::super::types::IscsiChapAuth;
+
+        let chap = IscsiChapAuth::new("user", "secret123456");
+
+        let open = manager
+            .export_volume(
+                "pvc-open",
+                "/dev/zvol/tank/csi/pvc-open",
+                ExportType::Iscsi,
+                0,
+                AuthConfig::None,
+                CtlOptions::default(),
+            )
+            .unwrap();
+        let chapped = manager
+            .export_volume(
+                "pvc-chap",
+                "/dev/zvol/tank/csi/pvc-chap",
+                ExportType::Iscsi,
+                0,
+                AuthConfig::IscsiChap(chap),
+                CtlOptions::default(),
+            )
+            .unwrap();
+
+        assert_eq!(manager.auth_group_for(&open), "ag-subnet");
+        assert_eq!(manager.auth_group_for(&chapped), "ag-pvc-chap");
+
+        let target = Target::new(
+            manager.auth_group_for(&open),
+            "pg0".to_string(),
+            0,
+            "/dev/zvol/tank/csi/pvc-open".to_string(),
+            "pvc-open",
+        );
+        let ucl = target.to_ucl(0);
+        assert!(ucl.contains("auth-group = \"ag-subnet\";"), "UCL: {}", ucl);
+        assert!(!ucl.contains("no-authentication"));
+
+        // Without a default, unauthenticated exports stay open
+        assert_eq!(test_manager().auth_group_for(&open), "no-authentication");
+    }
+
+    #[test]
+    fn test_explicit_no_authentication_rendered_when_needed() {
+        let export_open = |manager: &CtlManager, name: &str| {
+            manager
+                .export_volume(
+                    name,
+                    &format!("/dev/zvol/tank/csi/{}", name),
+                    ExportType::Iscsi,
+                    0,
+                    AuthConfig::None,
+                    CtlOptions::default(),
+                )
+                .unwrap();
+        };
+        let definition = "auth-group \"no-authentication\" {\n    auth-type = \"none\";\n}\n";
+
+        // ctld predefines the group: never written
+        let manager = test_manager();
+        export_open(&manager, "pvc-a");
+        assert!(
+            !manager
+                .render_config()
+                .unwrap()
+                .contains("auth-group \"no-")
+        );
+
+        // Explicit definition, written once however many exports use it
+        let manager = test_manager().with_explicit_no_authentication(true);
+        let config = manager.render_config().unwrap();
+        assert!(
+            !config.contains(definition),
+            "unused group written: {}",
+            config
+        );
+        export_open(&manager, "pvc-a");
+        export_open(&manager, "pvc-b");
+        let config = manager.render_config().unwrap();
+        assert_eq!(config.matches(definition).count(), 1, "{}", config);
+        assert!(config.contains("auth-group = \"no-authentication\";"));
+
+        // Exports using a default auth group don't reference it
+        let manager = test_manager()
+            .with_explicit_no_authentication(true)
+            .with_default_auth_group(Some("ag-subnet".to_string()));
+        export_open(&manager, "pvc-a");
+        assert!(!manager.render_config().unwrap().contains(definition));
+    }
+
+    #[test]
+    fn test_per_volume_groups_rendered() {
+        let manager = test_manager();
+        let options = |portal: Option<&str>, transport: Option<&str>| CtlOptions {
+            portal_group: portal.map(String::from),
+            transport_group: transport.map(String::from),
+            ..Default::default()
+        };
+        for (name, export_type, opts) in [
+            ("pvc-a", ExportType::Iscsi, options(Some("pg-fast"), None)),
+            ("pvc-b", ExportType::Iscsi, options(None, None)),
+            ("pvc-c", ExportType::Nvmeof, options(None, Some("tg-fast"))),
+        ] {
+            manager
+                .export_volume(
+                    name,
+                    &format!("/dev/zvol/tank/csi/{}", name),
+                    export_type,
+                    0,
+                    AuthConfig::None,
+                    opts,
+                )
+                .unwrap();
+        }
+
+        let config = manager.render_config().unwrap();
+        let block = |name: &str| {
+            let start = config.find(&format!(":{}\" {{", name)).unwrap();
+            let end = start + config[start..].find("\n}\n").unwrap();
+            config[start..end].to_string()
+        };
+        assert!(block("pvc-a").contains("portal-group = \"pg-fast\";"));
+        assert!(block("pvc-b").contains("portal-group = \"pg0\";"));
+        assert!(block("pvc-c").contains("transport-group = \"tg-fast\";"));
+    }
+
+    #[test]
+    fn test_update_auth_rotates_rendered_secret() {
+        use super::super::types::IscsiChapAuth;
+
+        let manager = test_manager();
+        let old = AuthConfig::IscsiChap(IscsiChapAuth::new("user1", "old-secret"));
+        manager
+            .export_volume(
+                "pvc-a",
+                "/dev/zvol/tank/csi/pvc-a",
+                ExportType::Iscsi,
+                0,
+                old.clone(),
+                CtlOptions::default(),
+            )
+            .unwrap();
+
+        let new = AuthConfig::IscsiChap(IscsiChapAuth::new("user1", "new-secret"));
+        assert_eq!(manager.update_auth("pvc-a", new.clone()).unwrap(), old);
+        let config = manager.render_config().unwrap();
+        assert!(config.contains("auth-group \"ag-pvc-a\""));
+        assert!(config.contains("secret = \"new-secret\";"));
+        assert!(!config.contains("old-secret"));
+
+        // Unrenderable credentials leave the export untouched
+        let bad = AuthConfig::IscsiChap(IscsiChapAuth::new("user1", "bad\nsecret"));
+        assert!(matches!(
+            manager.update_auth("pvc-a", bad),
+            Err(CtlError::ConfigError(_))
+        ));
+        assert_eq!(manager.get_export("pvc-a").unwrap().auth, new);
+
+        assert!(matches!(
+            manager.update_auth("pvc-missing", new),
+            Err(CtlError::TargetNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_allowed_initiators_rendered() {
+        let manager = test_manager();
+        manager
+            .export_volume(
+                "pvc-a",
+                "/dev/zvol/tank/csi/pvc-a",
+                ExportType::Iscsi,
+                0,
+                AuthConfig::None,
+                CtlOptions::default(),
+            )
+            .unwrap();
+        let node = "iqn.1993-08.org.debian:01:worker1".to_string();
+
+        assert!(
+            manager
+                .set_allowed_initiators("pvc-a", vec![node.clone()])
+                .unwrap()
+                .is_empty()
+        );
+        let config = manager.render_config().unwrap();
+        assert!(config.contains("auth-group = \"ag-pvc-a\";"), "{}", config);
+        assert!(config.contains(&format!("initiator-name = \"{}\";", node)));
+
+        // Unpublished everywhere: open again
+        assert_eq!(
+            manager.set_allowed_initiators("pvc-a", Vec::new()).unwrap(),
+            std::slice::from_ref(&node)
+        );
+        let config = manager.render_config().unwrap();
+        assert!(config.contains("auth-group = \"no-authentication\";"));
+        assert!(!config.contains("initiator-name"));
+
+        // A default auth-group is kept as the operator configured it
+        let manager = test_manager().with_default_auth_group(Some("ag-subnet".to_string()));
+        manager
+            .export_volume(
+                "pvc-b",
+                "/dev/zvol/tank/csi/pvc-b",
+                ExportType::Iscsi,
+                0,
+                AuthConfig::None,
+                CtlOptions::default(),
+            )
+            .unwrap();
+        manager
+            .set_allowed_initiators("pvc-b", vec![node.clone()])
+            .unwrap();
+        let config = manager.render_config().unwrap();
+        assert!(config.contains("auth-group = \"ag-subnet\";"));
+        assert!(!config.contains("initiator-name"));
+
+        assert!(matches!(
+            manager.set_allowed_initiators("pvc-missing", vec![node]),
+            Err(CtlError::TargetNotFound(_))
+        ));
+    }
+
+    /// Writer whose writes only count themselves
+    fn counting_writer() -> (ConfigWriterHandle, Arc<std::sync::atomic::AtomicUsize>) {
+        let writes = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = writes.clone();
+        let handle = spawn_writer(Duration::from_millis(1), move || {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async { Ok(()) }
+        });
+        (handle, writes)
+    }
+
+    #[tokio::test]
+    async fn test_paused_writes_coalesce_on_resume() {
+        use std::sync::atomic::Ordering;
+        let (writer, writes) = counting_writer();
+
+        let pause = writer.pause().await.unwrap();
+        for _ in 0..5 {
+            writer.request_write_async();
+        }
+        // A waiter made while paused completes with the flush
+        let waiter = tokio::spawn({
+            let writer = writer.clone();
+            async move { writer.write_config().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(writes.load(Ordering::SeqCst), 0);
+        assert!(!waiter.is_finished());
+
+        // Awaiting resume means the batch has been written
+        pause.resume().await.unwrap();
+        assert_eq!(writes.load(Ordering::SeqCst), 1);
+        waiter.await.unwrap().unwrap();
+
+        // Nothing held back: resume writes nothing
+        writer.pause().await.unwrap().resume().await.unwrap();
+        assert_eq!(writes.load(Ordering::SeqCst), 1);
+
+        // Unpaused writes go through again
+        writer.write_config().await.unwrap();
+        assert_eq!(writes.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_nested_pause_flushes_on_last_resume() {
+        use std::sync::atomic::Ordering;
+        let (writer, writes) = counting_writer();
+
+        let outer = writer.pause().await.unwrap();
+        let inner = writer.pause().await.unwrap();
+        writer.request_write_async();
+        inner.resume().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(writes.load(Ordering::SeqCst), 0);
+
+        outer.resume().await.unwrap();
+        assert_eq!(writes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_pause_resumes_writer() {
+        use std::sync::atomic::Ordering;
+        let (writer, writes) = counting_writer();
+
+        let pause = writer.pause().await.unwrap();
+        writer.request_write_async();
+        drop(pause);
+        // The held-back write is flushed and later writes are not held back
+        writer.write_config().await.unwrap();
+        assert!(writes.load(Ordering::SeqCst) >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_writes_coalesce() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Each caller makes a change before asking for a write; every write
+        // records how many changes it saw
+        let changes = Arc::new(AtomicUsize::new(0));
+        let observed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let writer = spawn_writer(Duration::from_millis(20), {
+            let changes = changes.clone();
+            let observed = observed.clone();
+            move || {
+                observed
+                    .lock()
+                    .unwrap()
+                    .push(changes.load(Ordering::SeqCst));
+                async { Ok(()) }
+            }
+        });
+
+        let callers: Vec<_> = (0..20)
+            .map(|_| {
+                let writer = writer.clone();
+                let changes = changes.clone();
+                let observed = observed.clone();
+                tokio::spawn(async move {
+                    changes.fetch_add(1, Ordering::SeqCst);
+                    writer.write_config().await.unwrap();
+                    // The write this caller waited for included its change
+                    assert!(!observed.lock().unwrap().is_empty());
+                })
+            })
+            .collect();
+        for caller in callers {
+            caller.await.unwrap();
+        }
+
+        let observed = observed.lock().unwrap();
+        assert!(observed.len() < 20, "{} writes", observed.len());
+        // The last write happened after the last change
+        assert_eq!(observed.last(), Some(&20));
+    }
+
+    #[tokio::test]
+    async fn test_write_requested_during_write_is_not_dropped() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let started = Arc::new(tokio::sync::Notify::new());
+        let writes = Arc::new(AtomicUsize::new(0));
+        let writer = spawn_writer(Duration::from_millis(1), {
+            let started = started.clone();
+            let writes = writes.clone();
+            move || {
+                let started = started.clone();
+                let writes = writes.clone();
+                async move {
+                    started.notify_one();
+                    tokio::time::sleep(Duration::from_millis(30)).await;
+                    writes.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                }
+            }
+        });
+
+        let first = tokio::spawn({
+            let writer = writer.clone();
+            async move { writer.write_config().await }
+        });
+        // Past the debounce window, in the middle of the first write
+        started.notified().await;
+        writer.write_config().await.unwrap();
+        // Only a follow-up write could have completed this request
+        assert_eq!(writes.load(Ordering::SeqCst), 2);
+        first.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_reports_write_failure() {
+        let writer = spawn_writer(Duration::ZERO, || async {
+            Err(CtlError::ConfigError("ctld reload failed".into()))
+        });
+        let pause = writer.pause().await.unwrap();
+        writer.request_write_async();
+        let err = pause.resume().await.unwrap_err();
+        assert!(err.to_string().contains("ctld reload failed"));
+    }
+
+    fn reload_attempts(
+        results: Vec<Result<()>>,
+    ) -> (
+        Arc<std::sync::Mutex<usize>>,
+        impl FnMut() -> std::future::Ready<Result<()>>,
+    ) {
+        let calls = Arc::new(std::sync::Mutex::new(0usize));
+        let counter = calls.clone();
+        let mut results = results.into_iter();
+        let attempt = move || {
+            *counter.lock().unwrap() += 1;
+            std::future::ready(results.next().expect("unexpected reload attempt"))
+        };
+        (calls, attempt)
+    }
+
+    #[test]
+    fn test_target_port_online() {
+        let portlist = "\
+1     YES    ioctl    ioctl    0    0
+3     YES    iscsi    iscsi    257  1   iqn.2024-01.org.freebsd.csi:pvc-1,t,0x0101
+4     NO     iscsi    iscsi    257  2   iqn.2024-01.org.freebsd.csi:pvc-2,t,0x0101
+5     YES    nvmf     nvmf     0    3   nqn.2024-01.org.freebsd.csi:pvc-3
+";
+        assert!(target_port_online(
+            portlist,
+            "iqn.2024-01.org.freebsd.csi:pvc-1"
+        ));
+        assert!(target_port_online(
+            portlist,
+            "nqn.2024-01.org.freebsd.csi:pvc-3"
+        ));
+        // Port offline
+        assert!(!target_port_online(
+            portlist,
+            "iqn.2024-01.org.freebsd.csi:pvc-2"
+        ));
+        // Prefix of a live target is not a match
+        assert!(!target_port_online(
+            portlist,
+            "iqn.2024-01.org.freebsd.csi:pvc"
+        ));
+        assert!(!target_port_online("", "iqn.2024-01.org.freebsd.csi:pvc-1"));
+    }
+
+    #[test]
+    fn test_transient_reload_failure_classification() {
+        assert!(is_transient_reload_failure(
+            "service ctld reload failed: ctld: Resource temporarily unavailable"
+        ));
+        assert!(is_transient_reload_failure(
+            "service ctld reload failed: device busy"
+        ));
+        assert!(!is_transient_reload_failure(
+            "service ctld reload failed: error parsing /etc/ctl.conf at line 12"
+        ));
+        assert!(!is_transient_reload_failure(
+            "service ctld reload failed: invalid auth-group; resource busy"
+        ));
+        assert!(!is_transient_reload_failure(
+            "service ctld reload failed: ctld not running"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_transient_reload_failure_is_retried() {
+        let (calls, attempt) = reload_attempts(vec![
+            Err(CtlError::CommandFailed(
+                "service ctld reload failed: device busy".into(),
+            )),
+            Ok(()),
+        ]);
+
+        retry_transient_reload(attempt, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(*calls.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_transient_reload_retried_only_once() {
+        let busy = || {
+            Err(CtlError::CommandFailed(
+                "service ctld reload failed: device busy".into(),
+            ))
+        };
+        let (calls, attempt) = reload_attempts(vec![busy(), busy()]);
+
+        let result = retry_transient_reload(attempt, Duration::ZERO).await;
+        assert!(matches!(result, Err(CtlError::CommandFailed(_))));
+        assert_eq!(*calls.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_config_error_reload_is_not_retried() {
+        let (calls, attempt) = reload_attempts(vec![Err(CtlError::CommandFailed(
+            "service ctld reload failed: syntax error in /etc/ctl.conf".into(),
+        ))]);
+
+        let result = retry_transient_reload(attempt, Duration::ZERO).await;
+        assert!(matches!(result, Err(CtlError::CommandFailed(_))));
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
 }
@@ -5,15 +5,21 @@ pub mod types;
 pub mod ucl_config;
 
 pub use config_validator::{
-    ValidationError, validate_portal_group_exists, validate_transport_group_exists,
+    DEFAULT_GROUP_CHECK_TTL, ExportGroupValidator, ValidationError, no_authentication_usable,
+    validate_auth_group_exists, validate_portal_group_exists, validate_transport_group_exists,
 };
 
 // Re-exports for module API
-pub use ctl_manager::{ConfigWriterHandle, CtlManager, spawn_config_writer};
+pub use ctl_manager::{
+    CONTROLLER_GROUP_NQN_PREFIX, ConfigWriterHandle, CtlManager, DEFAULT_CONFIG_WRITE_DEBOUNCE_MS,
+    PersistedExport, WriterPause, is_target_live, spawn_config_writer,
+};
 pub use error::CtlError;
 pub use types::ExportType;
 
 // Re-export types that may be used externally
 #[allow(unused_imports)]
 pub use types::{AuthConfig, DevicePath, Iqn, IscsiChapAuth, Nqn, NvmeAuth, TargetName};
-pub use ucl_config::{CtlOptions, validate_chap_credentials};
+pub use ucl_config::{
+    CtlOptions, IdentifierScheme, validate_chap_credentials, validate_ucl_string,
+};
@@ -235,7 +235,9 @@ impl From<Nqn> for TargetName {
 
 /// A validated ZFS device path.
 ///
-/// Device paths must be under `/dev/zvol/` and contain only safe characters.
+/// Device paths must be under `/dev/zvol/` and contain only safe characters,
+/// except for the backing files of file-backed volumes (see
+/// [`DevicePath::from_backing_file`]).
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct DevicePath(String);
@@ -293,6 +295,46 @@ impl DevicePath {
         Ok(Self(format!("{}{}", Self::PREFIX, dataset_name)))
     }
 
+    /// Validate the backing file of a file-backed volume.
+    ///
+    /// The file must be an absolute path directly inside a directory named
+    /// after the volume (the mountpoint of the volume's dataset).
+    pub fn from_backing_file(path: &str, volume_name: &str) -> Result<Self> {
+        if !path.starts_with('/') {
+            return Err(CtlError::InvalidName(format!(
+                "backing file '{}' must be an absolute path",
+                path
+            )));
+        }
+        if path.contains("..") {
+            return Err(CtlError::InvalidName(format!(
+                "backing file '{}' contains path traversal",
+                path
+            )));
+        }
+        if !path
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.' || c == '/')
+        {
+            return Err(CtlError::InvalidName(format!(
+                "backing file '{}' contains invalid characters",
+                path
+            )));
+        }
+        let in_volume_dir = path
+            .rsplit_once('/')
+            .and_then(|(dir, file)| (!file.is_empty()).then_some(dir))
+            .and_then(|dir| dir.rsplit_once('/'))
+            .is_some_and(|(_, dir_name)| dir_name == volume_name);
+        if !in_volume_dir {
+            return Err(CtlError::InvalidName(format!(
+                "backing file '{}' must be inside the directory of volume {}",
+                path, volume_name
+            )));
+        }
+        Ok(Self(path.to_string()))
+    }
+
     /// Parse an existing device path string.
     pub fn parse(s: &str) -> Result<Self> {
         if s.is_empty() {
@@ -614,6 +656,19 @@ mod tests {
         assert!(DevicePath::parse("/dev/zvol/../etc/passwd").is_err());
     }
 
+    #[test]
+    fn test_device_path_from_backing_file() {
+        let path = DevicePath::from_backing_file("/tank/csi/pvc-1/volume.img", "pvc-1").unwrap();
+        assert_eq!(path.as_str(), "/tank/csi/pvc-1/volume.img");
+
+        // Must live in the volume's own directory
+        assert!(DevicePath::from_backing_file("/tank/csi/pvc-2/volume.img", "pvc-1").is_err());
+        assert!(DevicePath::from_backing_file("/tank/csi/pvc-1/", "pvc-1").is_err());
+        assert!(DevicePath::from_backing_file("tank/csi/pvc-1/volume.img", "pvc-1").is_err());
+        assert!(DevicePath::from_backing_file("/tank/pvc-1/../etc/volume.img", "pvc-1").is_err());
+        assert!(DevicePath::from_backing_file("/tank/csi/pvc-1/vol;img", "pvc-1").is_err());
+    }
+
     #[test]
     fn test_device_path_parent_dataset_validation() {
         // Valid: path is under parent dataset
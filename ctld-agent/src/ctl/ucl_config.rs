@@ -4,7 +4,8 @@
 //! using uclicious for parsing and a ToUcl trait for serialization.
 
 use std::collections::HashMap;
-use std::fmt::Write;
+use std::fmt::{self, Write};
+use std::str::FromStr;
 
 use uclicious::Uclicious;
 
@@ -25,22 +26,45 @@ fn indent(level: usize) -> String {
     "    ".repeat(level)
 }
 
-/// Escape a string value for UCL (currently just wraps in quotes)
-fn ucl_quote(s: &str) -> String {
-    // UCL strings are quoted with double quotes
-    // We validate that strings don't contain problematic characters elsewhere
-    format!("\"{}\"", s)
+/// Quote a string value for UCL.
+///
+/// Inside a double-quoted UCL string only `\` and `"` need escaping; braces
+/// and other punctuation are literal. Control characters are escaped too,
+/// although validated inputs never contain them.
+pub(super) fn ucl_quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
 }
 
 // ============================================================================
 // LUN / Namespace types
 // ============================================================================
 
+/// CTL backend serving file-backed volumes (the block backend opens regular
+/// files as well as devices)
+const FILE_BACKEND: &str = "block";
+
 /// A LUN (Logical Unit Number) in an iSCSI target
 #[derive(Debug, Clone, Uclicious)]
 pub struct Lun {
     /// Path to the backing device
     pub path: String,
+    /// CTL backend ("block" for zvols and files; omitted uses ctld's default)
+    #[ucl(default)]
+    pub backend: Option<String>,
     /// Logical block size (optional, 512 or 4096, defaults to 512)
     #[ucl(default)]
     pub blocksize: Option<u32>,
@@ -50,12 +74,88 @@ pub struct Lun {
     /// Enable UNMAP/TRIM/discard passthrough (optional, "on" or "off")
     #[ucl(default)]
     pub unmap: Option<String>,
+    /// Report removable media to initiators (optional, "on" or "off")
+    #[ucl(default)]
+    pub removable: Option<String>,
     /// Serial number for unique device identification
     #[ucl(default)]
     pub serial: Option<String>,
     /// Device ID for unique device identification (T10 vendor format)
     #[ucl(path = "device-id", default)]
     pub device_id: Option<String>,
+    /// NAA Type 6 identifier (written to the options block, `naa` scheme only)
+    #[ucl(default)]
+    pub naa: Option<String>,
+    /// EUI-64 identifier (written to the options block, `eui64` scheme only)
+    #[ucl(default)]
+    pub eui: Option<String>,
+}
+
+/// Scheme used for the world-wide identifiers of LUNs and namespaces.
+///
+/// Every scheme derives its identifiers deterministically from the volume
+/// name, so a volume keeps the same identity across config rewrites and agent
+/// restarts. Serial numbers and T10 vendor device IDs are the same in every
+/// scheme; only the NAA/EUI descriptors differ. Changing the scheme changes
+/// the identity of already exported volumes on the next config write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdentifierScheme {
+    /// T10 vendor device ID; NVMe namespaces additionally get an NAA Type 6
+    /// identifier, which CTL needs to fill in the namespace NGUID
+    #[default]
+    Vendor,
+    /// NAA Type 6 (128-bit) identifier on iSCSI LUNs and NVMe namespaces
+    Naa,
+    /// Locally administered EUI-64 identifier on iSCSI LUNs and NVMe
+    /// namespaces, instead of NAA
+    Eui64,
+}
+
+impl fmt::Display for IdentifierScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdentifierScheme::Vendor => write!(f, "vendor"),
+            IdentifierScheme::Naa => write!(f, "naa"),
+            IdentifierScheme::Eui64 => write!(f, "eui64"),
+        }
+    }
+}
+
+impl FromStr for IdentifierScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vendor" => Ok(IdentifierScheme::Vendor),
+            "naa" => Ok(IdentifierScheme::Naa),
+            "eui64" | "eui-64" => Ok(IdentifierScheme::Eui64),
+            _ => Err(format!(
+                "unknown identifier scheme '{}': expected 'vendor', 'naa' or 'eui64'",
+                s
+            )),
+        }
+    }
+}
+
+/// Generate a locally administered EUI-64 identifier from the volume name.
+///
+/// Format: 16 hex chars (8 bytes). The first octet has the U/L bit set and
+/// the I/G bit cleared, so the value never collides with an IEEE-assigned
+/// vendor OUI.
+fn generate_eui64(volume_name: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    // Use "eui64:" prefix to get different hash than serial and NAA
+    hasher.update(b"eui64:");
+    hasher.update(volume_name.as_bytes());
+    let hash = hasher.finalize();
+
+    let mut eui_bytes = [0u8; 8];
+    eui_bytes.copy_from_slice(&hash[..8]);
+    eui_bytes[0] = (eui_bytes[0] | 0x02) & !0x01;
+
+    hex::encode(eui_bytes)
 }
 
 /// CTL LUN/Namespace options parsed from StorageClass parameters
@@ -67,6 +167,21 @@ pub struct CtlOptions {
     pub pblocksize: Option<u32>,
     /// Enable UNMAP/TRIM/discard passthrough
     pub unmap: Option<bool>,
+    /// Present the LUN/namespace as removable media
+    pub removable: Option<bool>,
+    /// Shared NVMeoF controller to place the namespace in (NVMeoF only).
+    /// Volumes with the same group are exported as namespaces of one controller.
+    pub controller_group: Option<String>,
+    /// Identifier scheme, set agent-wide by the CtlManager at export time
+    pub identifier_scheme: IdentifierScheme,
+    /// Human-friendly target alias shown by initiators (iSCSI only)
+    pub target_alias: Option<String>,
+    /// Portal group overriding the agent's default (iSCSI only)
+    pub portal_group: Option<String>,
+    /// Transport group overriding the agent's default (NVMeoF only)
+    pub transport_group: Option<String>,
+    /// Volume is a file in a ZFS filesystem rather than a zvol
+    pub file_backed: bool,
 }
 
 impl Lun {
@@ -80,21 +195,31 @@ impl Lun {
 
         Self {
             path,
+            backend: None,
             blocksize: None,
             pblocksize: None,
             unmap: None,
+            removable: None,
             serial: Some(serial),
             device_id: Some(device_id),
+            naa: None,
+            eui: None,
         }
     }
 
-    /// Create a new LUN with CTL options (blocksize, pblocksize, unmap)
+    /// Create a new LUN with CTL options (blocksize, pblocksize, unmap,
+    /// identifier scheme)
     pub fn with_options(path: String, volume_name: &str, options: &CtlOptions) -> Self {
         let serial = Self::generate_serial(volume_name);
         let device_id = Self::generate_device_id(volume_name);
+        let scheme = options.identifier_scheme;
+        // LUNs use the same NAA derivation as NVMe namespaces
+        let naa = (scheme == IdentifierScheme::Naa).then(|| Namespace::generate_naa(volume_name));
+        let eui = (scheme == IdentifierScheme::Eui64).then(|| generate_eui64(volume_name));
 
         Self {
             path,
+            backend: options.file_backed.then(|| FILE_BACKEND.to_string()),
             blocksize: options.blocksize,
             pblocksize: options.pblocksize,
             unmap: options.unmap.map(|b| {
@@ -104,8 +229,17 @@ impl Lun {
                     "off".to_string()
                 }
             }),
+            removable: options.removable.map(|b| {
+                if b {
+                    "on".to_string()
+                } else {
+                    "off".to_string()
+                }
+            }),
             serial: Some(serial),
             device_id: Some(device_id),
+            naa,
+            eui,
         }
     }
 
@@ -117,11 +251,15 @@ impl Lun {
 
         Self {
             path,
+            backend: None,
             blocksize: Some(blocksize),
             pblocksize: None,
             unmap: None,
+            removable: None,
             serial: Some(serial),
             device_id: Some(device_id),
+            naa: None,
+            eui: None,
         }
     }
 
@@ -150,6 +288,9 @@ impl ToUcl for Lun {
         let mut s = String::new();
         let ind = indent(level);
         writeln!(s, "{}path = {};", ind, ucl_quote(&self.path)).unwrap();
+        if let Some(ref backend) = self.backend {
+            writeln!(s, "{}backend = {};", ind, ucl_quote(backend)).unwrap();
+        }
         if let Some(bs) = self.blocksize {
             writeln!(s, "{}blocksize = {};", ind, bs).unwrap();
         }
@@ -160,7 +301,12 @@ impl ToUcl for Lun {
             writeln!(s, "{}device-id = {};", ind, ucl_quote(device_id)).unwrap();
         }
         // CTL backend options go in an options { } block
-        if self.pblocksize.is_some() || self.unmap.is_some() {
+        let has_options = self.pblocksize.is_some()
+            || self.unmap.is_some()
+            || self.removable.is_some()
+            || self.naa.is_some()
+            || self.eui.is_some();
+        if has_options {
             writeln!(s, "{}options {{", ind).unwrap();
             let opts_ind = indent(level + 1);
             if let Some(pbs) = self.pblocksize {
@@ -169,6 +315,15 @@ impl ToUcl for Lun {
             if let Some(ref unmap) = self.unmap {
                 writeln!(s, "{}unmap = {};", opts_ind, ucl_quote(unmap)).unwrap();
             }
+            if let Some(ref removable) = self.removable {
+                writeln!(s, "{}removable = {};", opts_ind, ucl_quote(removable)).unwrap();
+            }
+            if let Some(ref naa) = self.naa {
+                writeln!(s, "{}naa = {};", opts_ind, ucl_quote(naa)).unwrap();
+            }
+            if let Some(ref eui) = self.eui {
+                writeln!(s, "{}eui = {};", opts_ind, ucl_quote(eui)).unwrap();
+            }
             writeln!(s, "{}}}", ind).unwrap();
         }
         s
@@ -180,6 +335,9 @@ impl ToUcl for Lun {
 pub struct Namespace {
     /// Path to the backing device
     pub path: String,
+    /// CTL backend ("block" for zvols and files; omitted uses ctld's default)
+    #[ucl(default)]
+    pub backend: Option<String>,
     /// Logical block size (optional, 512 or 4096, defaults to 512)
     #[ucl(default)]
     pub blocksize: Option<u32>,
@@ -189,6 +347,9 @@ pub struct Namespace {
     /// Enable UNMAP/TRIM/discard passthrough (optional, "on" or "off")
     #[ucl(default)]
     pub unmap: Option<String>,
+    /// Report removable media to initiators (optional, "on" or "off")
+    #[ucl(default)]
+    pub removable: Option<String>,
     /// Serial number for unique namespace identification (used by multipath)
     #[ucl(default)]
     pub serial: Option<String>,
@@ -203,6 +364,9 @@ pub struct Namespace {
     /// Format: NAA Type 6 (128-bit), 32 hex chars, first nibble = '6'.
     #[ucl(default)]
     pub naa: Option<String>,
+    /// EUI-64 identifier, used instead of NAA by the `eui64` scheme
+    #[ucl(default)]
+    pub eui: Option<String>,
 }
 
 impl Namespace {
@@ -214,22 +378,34 @@ impl Namespace {
         let naa = Self::generate_naa(volume_name);
         Self {
             path,
+            backend: None,
             blocksize: None,
             pblocksize: None,
             unmap: None,
+            removable: None,
             serial: Some(serial),
             device_id: Some(device_id),
             naa: Some(naa),
+            eui: None,
         }
     }
 
-    /// Create a new namespace with CTL options (blocksize, pblocksize, unmap)
+    /// Create a new namespace with CTL options (blocksize, pblocksize, unmap,
+    /// identifier scheme)
     pub fn with_options(path: String, volume_name: &str, options: &CtlOptions) -> Self {
         let serial = Self::generate_serial(volume_name);
         let device_id = Self::generate_device_id(volume_name);
-        let naa = Self::generate_naa(volume_name);
+        // Every scheme must give the namespace an NAA or EUI-64 descriptor,
+        // otherwise CTL leaves the NGUID zeroed and multipath merges volumes
+        let (naa, eui) = match options.identifier_scheme {
+            IdentifierScheme::Vendor | IdentifierScheme::Naa => {
+                (Some(Self::generate_naa(volume_name)), None)
+            }
+            IdentifierScheme::Eui64 => (None, Some(generate_eui64(volume_name))),
+        };
         Self {
             path,
+            backend: options.file_backed.then(|| FILE_BACKEND.to_string()),
             blocksize: options.blocksize,
             pblocksize: options.pblocksize,
             unmap: options.unmap.map(|b| {
@@ -239,9 +415,17 @@ impl Namespace {
                     "off".to_string()
                 }
             }),
+            removable: options.removable.map(|b| {
+                if b {
+                    "on".to_string()
+                } else {
+                    "off".to_string()
+                }
+            }),
             serial: Some(serial),
             device_id: Some(device_id),
-            naa: Some(naa),
+            naa,
+            eui,
         }
     }
 
@@ -304,6 +488,9 @@ impl ToUcl for Namespace {
         let mut s = String::new();
         let ind = indent(level);
         writeln!(s, "{}path = {};", ind, ucl_quote(&self.path)).unwrap();
+        if let Some(ref backend) = self.backend {
+            writeln!(s, "{}backend = {};", ind, ucl_quote(backend)).unwrap();
+        }
         if let Some(bs) = self.blocksize {
             writeln!(s, "{}blocksize = {};", ind, bs).unwrap();
         }
@@ -316,7 +503,11 @@ impl ToUcl for Namespace {
         // CTL backend options go in an options { } block.
         // CRITICAL: The naa option is required for NVMe multipath support.
         // FreeBSD's CTL kernel populates nsdata->nguid ONLY from NAA/EUI64.
-        let has_options = self.pblocksize.is_some() || self.unmap.is_some() || self.naa.is_some();
+        let has_options = self.pblocksize.is_some()
+            || self.unmap.is_some()
+            || self.removable.is_some()
+            || self.naa.is_some()
+            || self.eui.is_some();
         if has_options {
             writeln!(s, "{}options {{", ind).unwrap();
             let opts_ind = indent(level + 1);
@@ -326,10 +517,16 @@ impl ToUcl for Namespace {
             if let Some(ref unmap) = self.unmap {
                 writeln!(s, "{}unmap = {};", opts_ind, ucl_quote(unmap)).unwrap();
             }
+            if let Some(ref removable) = self.removable {
+                writeln!(s, "{}removable = {};", opts_ind, ucl_quote(removable)).unwrap();
+            }
             // NAA is CRITICAL for NVMe multipath - populates nsdata->nguid
             if let Some(ref naa) = self.naa {
                 writeln!(s, "{}naa = {};", opts_ind, ucl_quote(naa)).unwrap();
             }
+            if let Some(ref eui) = self.eui {
+                writeln!(s, "{}eui = {};", opts_ind, ucl_quote(eui)).unwrap();
+            }
             writeln!(s, "{}}}", ind).unwrap();
         }
         s
@@ -349,6 +546,9 @@ pub struct Target {
     /// Portal group name
     #[ucl(path = "portal-group")]
     pub portal_group: String,
+    /// Human-friendly alias reported to initiators
+    #[ucl(default)]
+    pub alias: Option<String>,
     /// LUNs indexed by ID
     #[ucl(default)]
     pub lun: HashMap<String, Lun>,
@@ -368,6 +568,7 @@ impl Target {
         Self {
             auth_group,
             portal_group,
+            alias: None,
             lun,
         }
     }
@@ -389,6 +590,7 @@ impl Target {
         Self {
             auth_group,
             portal_group,
+            alias: options.target_alias.clone(),
             lun,
         }
     }
@@ -407,6 +609,9 @@ impl ToUcl for Target {
             ucl_quote(&self.portal_group)
         )
         .unwrap();
+        if let Some(ref alias) = self.alias {
+            writeln!(s, "{}alias = {};", ind, ucl_quote(alias)).unwrap();
+        }
 
         // Sort LUN IDs for consistent output
         let mut lun_ids: Vec<_> = self.lun.keys().collect();
@@ -484,6 +689,37 @@ impl Controller {
         }
     }
 
+    /// Create an empty controller shared by a controller group.
+    ///
+    /// Namespaces are added with [`Controller::add_namespace`]. The controller
+    /// serial is derived from the group name so it stays stable as volumes
+    /// join and leave the group.
+    pub fn for_group(auth_group: String, transport_group: String, group_name: &str) -> Self {
+        Self {
+            auth_group,
+            transport_group,
+            serial: Some(Self::generate_serial(group_name)),
+            namespace: HashMap::new(),
+        }
+    }
+
+    /// Add a namespace to the controller.
+    ///
+    /// Namespace identifiers (serial, device-id, NAA) are derived from the
+    /// volume name, so every namespace stays unique within the controller.
+    pub fn add_namespace(
+        &mut self,
+        ns_id: u32,
+        device_path: String,
+        volume_name: &str,
+        options: &CtlOptions,
+    ) {
+        self.namespace.insert(
+            ns_id.to_string(),
+            Namespace::with_options(device_path, volume_name, options),
+        );
+    }
+
     /// Generate a unique serial number for the controller from volume name.
     /// Uses SHA-256 hash with a different prefix to ensure uniqueness from namespace serial.
     /// This serial identifies the controller for multipath purposes.
@@ -538,7 +774,7 @@ impl ToUcl for Controller {
 // Auth Group types
 // ============================================================================
 
-use super::types::{AuthConfig, IscsiChapAuth, NvmeAuth};
+use super::types::{AuthConfig, ExportType, IscsiChapAuth, NvmeAuth};
 
 /// Authentication group for ctld.
 ///
@@ -549,12 +785,16 @@ use super::types::{AuthConfig, IscsiChapAuth, NvmeAuth};
 /// NVMeoF auth-groups only support host-nqn and host-address restrictions.
 #[derive(Debug, Clone)]
 pub struct AuthGroup {
+    /// Explicit `auth-type` (e.g. "none")
+    pub auth_type: Option<String>,
     /// CHAP credentials (optional, iSCSI only)
     pub chap: Option<ChapCredential>,
     /// Mutual CHAP credentials (optional, iSCSI only)
     pub chap_mutual: Option<ChapCredential>,
-    /// NVMeoF host NQN restriction (optional)
-    pub host_nqn: Option<String>,
+    /// iSCSI initiator names allowed to log in (empty: any)
+    pub initiator_names: Vec<String>,
+    /// NVMeoF host NQNs allowed to connect (empty: any)
+    pub host_nqns: Vec<String>,
 }
 
 /// CHAP credential for UCL output
@@ -583,6 +823,34 @@ impl AuthGroup {
         }
     }
 
+    /// Explicit definition of the `no-authentication` group, for ctld
+    /// builds that do not predefine it
+    pub fn no_authentication() -> Self {
+        Self {
+            auth_type: Some("none".to_string()),
+            chap: None,
+            chap_mutual: None,
+            initiator_names: Vec::new(),
+            host_nqns: Vec::new(),
+        }
+    }
+
+    /// Group without authentication that only admits the given initiators:
+    /// iSCSI initiator names or NVMeoF host NQNs, by `export_type`.
+    ///
+    /// Fails if a name would corrupt UCL syntax.
+    pub fn restricted(export_type: ExportType, initiators: &[String]) -> Result<Self> {
+        for initiator in initiators {
+            validate_ucl_string(initiator, "initiator name")?;
+        }
+        let mut group = Self::no_authentication();
+        match export_type {
+            ExportType::Iscsi => group.initiator_names = initiators.to_vec(),
+            ExportType::Nvmeof => group.host_nqns = initiators.to_vec(),
+        }
+        Ok(group)
+    }
+
     /// Create from iSCSI CHAP credentials.
     ///
     /// Validates that all credential strings are safe for UCL output.
@@ -611,9 +879,11 @@ impl AuthGroup {
         };
 
         Ok(Self {
+            auth_type: None,
             chap: Some(chap_cred),
             chap_mutual,
-            host_nqn: None,
+            initiator_names: Vec::new(),
+            host_nqns: Vec::new(),
         })
     }
 
@@ -624,9 +894,11 @@ impl AuthGroup {
     /// which NVMe hosts can connect to the controller.
     fn from_nvme_auth(nvme: &NvmeAuth) -> Self {
         Self {
+            auth_type: None,
             chap: None,
             chap_mutual: None,
-            host_nqn: Some(nvme.host_nqn.clone()),
+            initiator_names: Vec::new(),
+            host_nqns: vec![nvme.host_nqn.clone()],
         }
     }
 }
@@ -638,6 +910,10 @@ impl ToUcl for AuthGroup {
         let inner_ind = indent(level + 1);
         let entry_ind = indent(level + 2);
 
+        if let Some(ref auth_type) = self.auth_type {
+            writeln!(s, "{}auth-type = {};", ind, ucl_quote(auth_type)).unwrap();
+        }
+
         // For iSCSI CHAP: use chap-mutual if mutual auth is present, otherwise just chap
         // ctld doesn't allow mixing chap and chap-mutual in the same auth-group
         if let Some(ref chap) = self.chap {
@@ -674,8 +950,11 @@ impl ToUcl for AuthGroup {
             }
         }
 
-        // Write host-nqn restriction (NVMeoF)
-        if let Some(ref nqn) = self.host_nqn {
+        // Initiator restrictions: initiator-name (iSCSI), host-nqn (NVMeoF)
+        for name in &self.initiator_names {
+            writeln!(s, "{}initiator-name = {};", ind, ucl_quote(name)).unwrap();
+        }
+        for nqn in &self.host_nqns {
             writeln!(s, "{}host-nqn = {};", ind, ucl_quote(nqn)).unwrap();
         }
 
@@ -703,15 +982,14 @@ pub fn validate_ucl_string(value: &str, field_name: &str) -> Result<()> {
         )));
     }
 
-    // Reject characters that could corrupt UCL syntax
-    const FORBIDDEN_CHARS: &[char] = &['"', '{', '}', '\\'];
-    for c in FORBIDDEN_CHARS {
-        if value.contains(*c) {
-            return Err(CtlError::ConfigError(format!(
-                "{} contains forbidden character '{}': {}",
-                field_name, c, value
-            )));
-        }
+    // Quotes, backslashes and braces are escaped by ucl_quote. Control
+    // characters (newlines, NUL, ...) have no place in names or secrets and
+    // would break ctld's line-oriented error reporting, so reject them.
+    if let Some(c) = value.chars().find(|c| c.is_control()) {
+        return Err(CtlError::ConfigError(format!(
+            "{} contains control character {:?}",
+            field_name, c
+        )));
     }
 
     Ok(())
@@ -813,6 +1091,69 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_file_backed_lun_to_ucl() {
+        let options = CtlOptions {
+            file_backed: true,
+            ..Default::default()
+        };
+        let target = Target::with_options(
+            "ag0".to_string(),
+            "pg0".to_string(),
+            0,
+            "/tank/csi/vol1/volume.img".to_string(),
+            "vol1",
+            &options,
+        );
+        let ucl = target.to_ucl(0);
+        assert!(ucl.contains("path = \"/tank/csi/vol1/volume.img\";"));
+        assert!(
+            ucl.contains("backend = \"block\";"),
+            "Missing backend in:\n{}",
+            ucl
+        );
+
+        // zvol-backed LUNs keep ctld's default backend
+        let zvol = Lun::with_options(
+            "/dev/zvol/tank/csi/vol1".to_string(),
+            "vol1",
+            &CtlOptions::default(),
+        );
+        assert!(!zvol.to_ucl(0).contains("backend"));
+    }
+
+    #[test]
+    fn test_target_alias_to_ucl() {
+        let options = CtlOptions {
+            target_alias: Some("db-primary \"data\"".to_string()),
+            ..Default::default()
+        };
+        let target = Target::with_options(
+            "ag0".to_string(),
+            "pg0".to_string(),
+            0,
+            "/dev/zvol/tank/csi/vol1".to_string(),
+            "pvc-test-volume",
+            &options,
+        );
+        let ucl = target.to_ucl(0);
+        assert!(
+            ucl.contains("alias = \"db-primary \\\"data\\\"\";"),
+            "Missing alias in:\n{}",
+            ucl
+        );
+
+        let target = Target::with_options(
+            "ag0".to_string(),
+            "pg0".to_string(),
+            0,
+            "/dev/zvol/tank/csi/vol1".to_string(),
+            "pvc-test-volume",
+            &CtlOptions::default(),
+        );
+        assert!(!target.to_ucl(0).contains("alias"));
+    }
+
     #[test]
     fn test_controller_to_ucl() {
         let controller = Controller::new(
@@ -875,19 +1216,102 @@ mod tests {
         assert!(validate_ucl_string("ag0", "test").is_ok());
         assert!(validate_ucl_string("iqn.2024-01.org.freebsd.csi:vol1", "test").is_ok());
         assert!(validate_ucl_string("", "test").is_err());
-        assert!(validate_ucl_string("test\"value", "test").is_err());
-        assert!(validate_ucl_string("test{value", "test").is_err());
+        assert!(validate_ucl_string("test\"value", "test").is_ok());
+        assert!(validate_ucl_string("test{value", "test").is_ok());
+        assert!(validate_ucl_string("test\nvalue", "test").is_err());
+        assert!(validate_ucl_string("test\0value", "test").is_err());
+    }
+
+    /// Parse `key = <quoted>;` with libucl and return the string value
+    fn ucl_round_trip(value: &str) -> String {
+        use uclicious::{DEFAULT_DUPLICATE_STRATEGY, Parser, Priority};
+
+        let input = format!("secret = {};\n", ucl_quote(value));
+        let mut parser = Parser::default();
+        parser
+            .add_chunk_full(
+                input.as_str(),
+                Priority::default(),
+                DEFAULT_DUPLICATE_STRATEGY,
+            )
+            .unwrap_or_else(|e| panic!("failed to parse {}: {}", input, e));
+        let obj = parser.get_object().unwrap();
+        obj.lookup("secret").unwrap().as_string().unwrap()
+    }
+
+    #[test]
+    fn test_ucl_quote_round_trips_special_chars() {
+        for value in [
+            "plain",
+            "pass\"word",
+            "pass\\word",
+            "trailing\\",
+            "\"\\\"",
+            "{braces}inside",
+            "mixed\\\"{};#=/*",
+            "p@ss!w0rd#%^&*()",
+            "dollar$FOO${BAR}$$",
+        ] {
+            assert_eq!(ucl_round_trip(value), value, "round trip of {:?}", value);
+        }
+    }
+
+    /// Parse `target <quoted> { }` with libucl and return the section name
+    fn ucl_section_round_trip(name: &str) -> String {
+        use uclicious::{DEFAULT_DUPLICATE_STRATEGY, Parser, Priority};
+
+        let input = format!("target {} {{\n}}\n", ucl_quote(name));
+        let mut parser = Parser::default();
+        parser
+            .add_chunk_full(
+                input.as_str(),
+                Priority::default(),
+                DEFAULT_DUPLICATE_STRATEGY,
+            )
+            .unwrap_or_else(|e| panic!("failed to parse {}: {}", input, e));
+        let obj = parser.get_object().unwrap();
+        let sections = obj.lookup("target").unwrap();
+        let section = sections.iter().next().unwrap();
+        section.key().unwrap()
+    }
+
+    #[test]
+    fn test_ucl_quote_round_trips_section_names() {
+        for name in [
+            "iqn.2024-01.org.freebsd.csi:pvc-a",
+            "nqn.2024-01.org.freebsd.csi:group:db",
+            "ag-pvc-a",
+            "name with \"quotes\"",
+            "back\\slash",
+            "{braces};#",
+        ] {
+            assert_eq!(
+                ucl_section_round_trip(name),
+                name,
+                "round trip of {:?}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_ucl_quote_escapes() {
+        assert_eq!(ucl_quote("a\"b"), r#""a\"b""#);
+        assert_eq!(ucl_quote("a\\b"), r#""a\\b""#);
+        assert_eq!(ucl_quote("a\nb"), r#""a\nb""#);
     }
 
     #[test]
     fn test_auth_group_chap_only() {
         let auth_group = AuthGroup {
+            auth_type: None,
             chap: Some(ChapCredential {
                 username: "testuser".to_string(),
                 secret: "testsecret".to_string(),
             }),
             chap_mutual: None,
-            host_nqn: None,
+            initiator_names: Vec::new(),
+            host_nqns: Vec::new(),
         };
         let ucl = auth_group.to_ucl(0);
 
@@ -914,6 +1338,7 @@ mod tests {
     #[test]
     fn test_auth_group_chap_with_mutual() {
         let auth_group = AuthGroup {
+            auth_type: None,
             chap: Some(ChapCredential {
                 username: "initiator".to_string(),
                 secret: "initsecret".to_string(),
@@ -922,7 +1347,8 @@ mod tests {
                 username: "target".to_string(),
                 secret: "targetsecret".to_string(),
             }),
-            host_nqn: None,
+            initiator_names: Vec::new(),
+            host_nqns: Vec::new(),
         };
         let ucl = auth_group.to_ucl(0);
 
@@ -964,9 +1390,11 @@ mod tests {
     #[test]
     fn test_auth_group_nvme_host_nqn() {
         let auth_group = AuthGroup {
+            auth_type: None,
             chap: None,
             chap_mutual: None,
-            host_nqn: Some("nqn.2024-01.org.freebsd:initiator".to_string()),
+            initiator_names: Vec::new(),
+            host_nqns: vec!["nqn.2024-01.org.freebsd:initiator".to_string()],
         };
         let ucl = auth_group.to_ucl(0);
 
@@ -975,15 +1403,49 @@ mod tests {
         assert!(!ucl.contains("chap-mutual"));
     }
 
+    #[test]
+    fn test_auth_group_restricted() {
+        let names = [
+            "iqn.1993-08.org.debian:01:worker1".to_string(),
+            "iqn.1993-08.org.debian:01:worker2".to_string(),
+        ];
+        let ucl = AuthGroup::restricted(ExportType::Iscsi, &names)
+            .unwrap()
+            .to_ucl(0);
+        assert_eq!(
+            ucl,
+            "auth-type = \"none\";\n\
+             initiator-name = \"iqn.1993-08.org.debian:01:worker1\";\n\
+             initiator-name = \"iqn.1993-08.org.debian:01:worker2\";\n"
+        );
+
+        let nqn = ["nqn.2014-08.org.nvmexpress:uuid:1234".to_string()];
+        let ucl = AuthGroup::restricted(ExportType::Nvmeof, &nqn)
+            .unwrap()
+            .to_ucl(0);
+        assert!(ucl.contains("host-nqn = \"nqn.2014-08.org.nvmexpress:uuid:1234\";"));
+        assert!(!ucl.contains("initiator-name"));
+
+        assert!(AuthGroup::restricted(ExportType::Iscsi, &["iqn.x\ny".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_auth_group_no_authentication() {
+        let ucl = AuthGroup::no_authentication().to_ucl(1);
+        assert_eq!(ucl, "    auth-type = \"none\";\n");
+    }
+
     #[test]
     fn test_auth_group_indentation() {
         let auth_group = AuthGroup {
+            auth_type: None,
             chap: Some(ChapCredential {
                 username: "user".to_string(),
                 secret: "pass".to_string(),
             }),
             chap_mutual: None,
-            host_nqn: None,
+            initiator_names: Vec::new(),
+            host_nqns: Vec::new(),
         };
 
         // Test with indentation level 1 (inside auth-group block)
@@ -1045,10 +1507,7 @@ mod tests {
         assert!(ag.chap.is_none());
         assert!(ag.chap_mutual.is_none());
         // Note: only host_nqn is used from NvmeAuth (FreeBSD 15 doesn't support DH-HMAC-CHAP yet)
-        assert_eq!(
-            ag.host_nqn.as_ref().unwrap(),
-            "nqn.2024-01.org.example:host1"
-        );
+        assert_eq!(ag.host_nqns, ["nqn.2024-01.org.example:host1"]);
     }
 
     #[test]
@@ -1073,54 +1532,38 @@ mod tests {
     // ============================================================================
 
     #[test]
-    fn test_validate_ucl_string_rejects_double_quote() {
+    fn test_chap_secret_with_quotes_and_backslashes_round_trips() {
         use super::super::types::IscsiChapAuth;
 
-        let chap = IscsiChapAuth::new("user", "pass\"word");
+        let secret = "pa\"ss\\w{or}d";
+        let chap = IscsiChapAuth::new("user{name}", secret);
         let auth_config = AuthConfig::IscsiChap(chap);
-        let result = AuthGroup::from_auth_config(&auth_config, "test-volume");
-
-        assert!(result.is_err());
-        let err_msg = result.unwrap_err().to_string();
-        assert!(
-            err_msg.contains("forbidden character"),
-            "Error should mention forbidden character: {}",
-            err_msg
-        );
-    }
-
-    #[test]
-    fn test_validate_ucl_string_rejects_curly_braces() {
-        use super::super::types::IscsiChapAuth;
-
-        let chap = IscsiChapAuth::new("user{name}", "secret");
-        let auth_config = AuthConfig::IscsiChap(chap);
-        let result = AuthGroup::from_auth_config(&auth_config, "test-volume");
+        let auth_group = AuthGroup::from_auth_config(&auth_config, "test-volume")
+            .expect("quotes, braces and backslashes should be accepted")
+            .expect("auth group should be created");
 
-        assert!(result.is_err());
-        let err_msg = result.unwrap_err().to_string();
-        assert!(
-            err_msg.contains("forbidden character"),
-            "Error should mention forbidden character: {}",
-            err_msg
-        );
+        let ucl = auth_group.to_ucl(0);
+        assert!(ucl.contains(r#"secret = "pa\"ss\\w{or}d";"#), "{}", ucl);
+        assert_eq!(ucl_round_trip(secret), secret);
     }
 
     #[test]
-    fn test_validate_ucl_string_rejects_backslash() {
+    fn test_validate_ucl_string_rejects_control_chars() {
         use super::super::types::IscsiChapAuth;
 
-        let chap = IscsiChapAuth::new("user", "pass\\word");
+        let chap = IscsiChapAuth::new("user", "pass\nword");
         let auth_config = AuthConfig::IscsiChap(chap);
         let result = AuthGroup::from_auth_config(&auth_config, "test-volume");
 
         assert!(result.is_err());
         let err_msg = result.unwrap_err().to_string();
         assert!(
-            err_msg.contains("forbidden character"),
-            "Error should mention forbidden character: {}",
+            err_msg.contains("control character"),
+            "Error should mention control character: {}",
             err_msg
         );
+        // The secret itself must not leak into the error
+        assert!(!err_msg.contains("pass"), "{}", err_msg);
     }
 
     #[test]
@@ -1158,7 +1601,7 @@ mod tests {
         use super::super::types::IscsiChapAuth;
 
         // Test that mutual CHAP credentials are also validated
-        let chap = IscsiChapAuth::with_mutual("user1", "secret1", "target\"name", "tsecret");
+        let chap = IscsiChapAuth::with_mutual("user1", "secret1", "target\tname", "tsecret");
         let auth_config = AuthConfig::IscsiChap(chap);
         let result = AuthGroup::from_auth_config(&auth_config, "test-volume");
 
@@ -1190,12 +1633,13 @@ mod tests {
 
     #[test]
     fn test_validate_chap_credentials_forbidden_chars() {
-        // Double quote
-        assert!(validate_chap_credentials("user\"name", "secret").is_err());
-        // Curly braces
-        assert!(validate_chap_credentials("user", "sec{ret}").is_err());
-        // Backslash
-        assert!(validate_chap_credentials("user", "sec\\ret").is_err());
+        // Newline / carriage return
+        assert!(validate_chap_credentials("user\nname", "secret").is_err());
+        assert!(validate_chap_credentials("user", "sec\rret").is_err());
+        // NUL
+        assert!(validate_chap_credentials("user", "sec\0ret").is_err());
+        // Quotes, braces and backslashes are escaped, not rejected
+        assert!(validate_chap_credentials("user\"name", "sec{ret}\\").is_ok());
     }
 
     #[test]
@@ -1223,6 +1667,7 @@ mod tests {
             blocksize: Some(4096),
             pblocksize: Some(4096),
             unmap: Some(true),
+            ..Default::default()
         };
         let lun = Lun::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-test", &opts);
         let ucl = lun.to_ucl(0);
@@ -1239,12 +1684,40 @@ mod tests {
         assert!(ucl.contains("device-id ="), "UCL: {}", ucl);
     }
 
+    #[test]
+    fn test_removable_to_ucl() {
+        let opts = CtlOptions {
+            removable: Some(true),
+            ..Default::default()
+        };
+        let lun = Lun::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-test", &opts);
+        let ucl = lun.to_ucl(0);
+        assert!(ucl.contains("removable = \"on\";"), "UCL: {}", ucl);
+
+        let opts = CtlOptions {
+            removable: Some(false),
+            ..Default::default()
+        };
+        let ns = Namespace::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-test", &opts);
+        let ucl = ns.to_ucl(0);
+        assert!(ucl.contains("removable = \"off\";"), "UCL: {}", ucl);
+
+        // Omitted unless set
+        let defaults = CtlOptions::default();
+        let lun = Lun::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-test", &defaults);
+        assert!(!lun.to_ucl(0).contains("removable"));
+        let ns =
+            Namespace::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-test", &defaults);
+        assert!(!ns.to_ucl(0).contains("removable"));
+    }
+
     #[test]
     fn test_lun_with_options_unmap_off() {
         let opts = CtlOptions {
             blocksize: None,
             pblocksize: None,
             unmap: Some(false),
+            ..Default::default()
         };
         let lun = Lun::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-test", &opts);
         let ucl = lun.to_ucl(0);
@@ -1273,6 +1746,7 @@ mod tests {
             blocksize: Some(4096),
             pblocksize: Some(4096),
             unmap: Some(true),
+            ..Default::default()
         };
         let ns = Namespace::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-test", &opts);
         let ucl = ns.to_ucl(0);
@@ -1301,6 +1775,7 @@ mod tests {
             blocksize: Some(4096),
             pblocksize: Some(4096),
             unmap: Some(true),
+            ..Default::default()
         };
         let target = Target::with_options(
             "no-authentication".to_string(),
@@ -1335,6 +1810,7 @@ mod tests {
             blocksize: Some(4096),
             pblocksize: Some(4096),
             unmap: Some(true),
+            ..Default::default()
         };
         let controller = Controller::with_options(
             "no-authentication".to_string(),
@@ -1369,6 +1845,7 @@ mod tests {
             blocksize: Some(4096),
             pblocksize: None,
             unmap: None,
+            ..Default::default()
         };
         let lun = Lun::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-test", &opts);
         let ucl = lun.to_ucl(0);
@@ -1380,4 +1857,130 @@ mod tests {
             ucl
         );
     }
+
+    #[test]
+    fn test_group_controller_with_multiple_namespaces() {
+        let mut controller =
+            Controller::for_group("no-authentication".to_string(), "tg0".to_string(), "db");
+        controller.add_namespace(
+            1,
+            "/dev/zvol/tank/csi/pvc-a".to_string(),
+            "pvc-a",
+            &CtlOptions::default(),
+        );
+        controller.add_namespace(
+            2,
+            "/dev/zvol/tank/csi/pvc-b".to_string(),
+            "pvc-b",
+            &CtlOptions::default(),
+        );
+        let ucl = controller.to_ucl(0);
+
+        let ns1 = ucl.find("namespace 1 {").expect("namespace 1 rendered");
+        let ns2 = ucl.find("namespace 2 {").expect("namespace 2 rendered");
+        assert!(ns1 < ns2, "namespaces should be sorted: {}", ucl);
+        assert!(ucl.contains("path = \"/dev/zvol/tank/csi/pvc-a\";"));
+        assert!(ucl.contains("path = \"/dev/zvol/tank/csi/pvc-b\";"));
+
+        // Controller serial comes from the group, not a member volume
+        assert_eq!(
+            controller.serial,
+            Some(Controller::generate_serial("db")),
+            "UCL: {}",
+            ucl
+        );
+
+        // Each namespace keeps its own unique identifiers
+        let a = &controller.namespace["1"];
+        let b = &controller.namespace["2"];
+        assert_ne!(a.serial, b.serial);
+        assert_ne!(a.device_id, b.device_id);
+        assert_ne!(a.naa, b.naa);
+        assert_eq!(a.naa, Some(Namespace::generate_naa("pvc-a")));
+    }
+
+    fn scheme_options(scheme: IdentifierScheme) -> CtlOptions {
+        CtlOptions {
+            identifier_scheme: scheme,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_identifier_scheme_parse() {
+        assert_eq!(
+            "vendor".parse::<IdentifierScheme>().unwrap(),
+            IdentifierScheme::Vendor
+        );
+        assert_eq!(
+            "NAA".parse::<IdentifierScheme>().unwrap(),
+            IdentifierScheme::Naa
+        );
+        assert_eq!(
+            "eui-64".parse::<IdentifierScheme>().unwrap(),
+            IdentifierScheme::Eui64
+        );
+        assert!("wwn".parse::<IdentifierScheme>().is_err());
+        assert_eq!(IdentifierScheme::default(), IdentifierScheme::Vendor);
+        assert_eq!(IdentifierScheme::Eui64.to_string(), "eui64");
+    }
+
+    #[test]
+    fn test_vendor_scheme_matches_previous_identifiers() {
+        let opts = scheme_options(IdentifierScheme::Vendor);
+        let lun = Lun::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-a", &opts);
+        assert_eq!(lun.serial, Some(Lun::generate_serial("pvc-a")));
+        assert_eq!(lun.device_id.as_deref(), Some("FreeBSD pvc-a"));
+        assert!(lun.naa.is_none() && lun.eui.is_none());
+        assert!(!lun.to_ucl(0).contains("options {"));
+
+        let ns = Namespace::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-a", &opts);
+        assert_eq!(ns.naa, Some(Namespace::generate_naa("pvc-a")));
+        assert!(ns.eui.is_none());
+    }
+
+    #[test]
+    fn test_naa_scheme_format_and_determinism() {
+        let opts = scheme_options(IdentifierScheme::Naa);
+        let lun = Lun::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-a", &opts);
+        let naa = lun.naa.clone().unwrap();
+        assert_eq!(naa.len(), 32);
+        assert!(naa.starts_with('6'), "NAA Type 6 expected: {}", naa);
+        assert!(lun.eui.is_none());
+        assert!(lun.to_ucl(0).contains(&format!("naa = \"{}\";", naa)));
+
+        let again = Lun::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-a", &opts);
+        assert_eq!(again.naa, lun.naa);
+        let other = Lun::with_options("/dev/zvol/tank/csi/vol2".to_string(), "pvc-b", &opts);
+        assert_ne!(other.naa, lun.naa);
+
+        let ns = Namespace::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-a", &opts);
+        assert_eq!(ns.naa, lun.naa);
+        assert!(ns.eui.is_none());
+    }
+
+    #[test]
+    fn test_eui64_scheme_format_and_determinism() {
+        let eui = generate_eui64("pvc-a");
+        assert_eq!(eui.len(), 16, "EUI-64 must be 16 hex chars");
+        assert!(eui.chars().all(|c| c.is_ascii_hexdigit()));
+        let first = u8::from_str_radix(&eui[..2], 16).unwrap();
+        assert_eq!(first & 0x03, 0x02, "EUI-64 must be local unicast: {}", eui);
+        assert_eq!(eui, generate_eui64("pvc-a"));
+        assert_ne!(eui, generate_eui64("pvc-b"));
+        assert_ne!(eui, Lun::generate_serial("pvc-a"));
+
+        let opts = scheme_options(IdentifierScheme::Eui64);
+        let lun = Lun::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-a", &opts);
+        assert_eq!(lun.eui.as_deref(), Some(eui.as_str()));
+        assert!(lun.naa.is_none());
+
+        // Namespaces swap NAA for EUI-64 so the NGUID stays unique
+        let ns = Namespace::with_options("/dev/zvol/tank/csi/vol1".to_string(), "pvc-a", &opts);
+        assert_eq!(ns.eui.as_deref(), Some(eui.as_str()));
+        assert!(ns.naa.is_none());
+        let ucl = ns.to_ucl(0);
+        assert!(ucl.contains(&format!("eui = \"{}\";", eui)), "UCL: {}", ucl);
+        assert!(!ucl.contains("naa ="), "UCL: {}", ucl);
+    }
 }
@@ -0,0 +1,281 @@
+//! Plain HTTP endpoints for metrics and health probes
+//!
+//! Serves `/metrics` (Prometheus text format), `/healthz` (process is up) and
+//! `/readyz` (startup reconciliation finished, the gRPC server is serving and
+//! the ZFS pool accepts writes).
+//! One listener can carry all three paths, so resource-constrained storage
+//! nodes don't need a second port just for probes.
+//!
+//! Requests are handled by hyper, the same HTTP stack behind the exporter's
+//! own listener, with an axum router picking the endpoint by path.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use axum::Router;
+use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::routing::get;
+use hyper::server::conn::http1;
+use hyper_util::rt::{TokioIo, TokioTimer};
+use hyper_util::service::TowerToHyperService;
+use metrics_exporter_prometheus::PrometheusHandle;
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+/// How long a client may take to send its request head
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Shared readiness flag for `/readyz`
+#[derive(Debug, Default)]
+pub struct HealthState {
+    ready: AtomicBool,
+    pool_unavailable: AtomicBool,
+}
+
+impl HealthState {
+    /// Create a new health state (not ready)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark the agent as ready or not ready
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::SeqCst);
+    }
+
+    /// Whether the agent is ready to serve requests
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    /// Record whether the ZFS pool was last seen read-only or suspended
+    pub fn set_pool_unavailable(&self, unavailable: bool) {
+        self.pool_unavailable.store(unavailable, Ordering::SeqCst);
+    }
+
+    /// Whether the agent is degraded by a read-only or suspended pool
+    pub fn is_pool_unavailable(&self) -> bool {
+        self.pool_unavailable.load(Ordering::SeqCst)
+    }
+}
+
+/// Paths served by a listener. A `None` member answers 404.
+#[derive(Clone, Default)]
+pub struct HttpRoutes {
+    /// Prometheus handle backing `/metrics`
+    pub metrics: Option<PrometheusHandle>,
+    /// Health state backing `/healthz` and `/readyz`
+    pub health: Option<Arc<HealthState>>,
+}
+
+impl HttpRoutes {
+    /// Build the router for the configured paths; GET routes answer HEAD too
+    fn router(self) -> Router {
+        let mut router = Router::new();
+        if let Some(handle) = self.metrics {
+            router = router.route(
+                "/metrics",
+                get(move || {
+                    let body = handle.render();
+                    async move { ([(CONTENT_TYPE, "text/plain; version=0.0.4")], body) }
+                }),
+            );
+        }
+        if let Some(health) = self.health {
+            router = router.route("/healthz", get(|| async { "ok\n" })).route(
+                "/readyz",
+                get(move || {
+                    let response = readiness(&health);
+                    async move { response }
+                }),
+            );
+        }
+        router
+    }
+}
+
+/// `/readyz` status and body for the current health state
+fn readiness(health: &HealthState) -> (StatusCode, &'static str) {
+    if !health.is_ready() {
+        (StatusCode::SERVICE_UNAVAILABLE, "not ready\n")
+    } else if health.is_pool_unavailable() {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "degraded: pool unavailable\n",
+        )
+    } else {
+        (StatusCode::OK, "ready\n")
+    }
+}
+
+/// Bind `addr` and serve `routes` in a background task.
+///
+/// Returns the bound address (useful when binding port 0).
+pub async fn spawn_http_server(
+    addr: SocketAddr,
+    routes: HttpRoutes,
+) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let local = listener.local_addr()?;
+
+    let paths: Vec<&str> = [
+        routes.metrics.as_ref().map(|_| "/metrics"),
+        routes.health.as_ref().map(|_| "/healthz"),
+        routes.health.as_ref().map(|_| "/readyz"),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    info!(
+        "HTTP server listening on http://{} ({})",
+        local,
+        paths.join(", ")
+    );
+
+    tokio::spawn(serve(listener, routes.router()));
+    Ok(local)
+}
+
+/// Accept loop; each connection is handled in its own task
+async fn serve(listener: TcpListener, router: Router) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let service = TowerToHyperService::new(router.clone());
+                tokio::spawn(async move {
+                    // A client that connects and never finishes its request
+                    // would otherwise hold the connection and its task forever
+                    let mut builder = http1::Builder::new();
+                    builder
+                        .timer(TokioTimer::new())
+                        .header_read_timeout(REQUEST_READ_TIMEOUT)
+                        .keep_alive(false);
+                    if let Err(e) = builder
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await
+                    {
+                        debug!(peer = %peer, error = %e, "HTTP connection error");
+                    }
+                });
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to accept HTTP connection");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics_exporter_prometheus::PrometheusBuilder;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    async fn request(addr: SocketAddr, method: &str, path: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            method, path
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let status = response
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse().ok())
+            .unwrap();
+        let body = response
+            .split_once("\r\n\r\n")
+            .map(|(_, b)| b.to_string())
+            .unwrap_or_default();
+        (status, body)
+    }
+
+    async fn get(addr: SocketAddr, path: &str) -> (u16, String) {
+        request(addr, "GET", path).await
+    }
+
+    async fn spawn_shared() -> (SocketAddr, Arc<HealthState>) {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let health = Arc::new(HealthState::new());
+        let routes = HttpRoutes {
+            metrics: Some(recorder.handle()),
+            health: Some(health.clone()),
+        };
+        let addr = spawn_http_server("127.0.0.1:0".parse().unwrap(), routes)
+            .await
+            .unwrap();
+        (addr, health)
+    }
+
+    #[tokio::test]
+    async fn test_shared_listener_serves_all_paths() {
+        let (addr, health) = spawn_shared().await;
+
+        let (status, _) = get(addr, "/metrics").await;
+        assert_eq!(status, 200);
+
+        let (status, body) = get(addr, "/healthz").await;
+        assert_eq!(status, 200);
+        assert_eq!(body, "ok\n");
+
+        let (status, _) = get(addr, "/readyz").await;
+        assert_eq!(status, 503);
+
+        health.set_ready(true);
+        let (status, body) = get(addr, "/readyz").await;
+        assert_eq!(status, 200);
+        assert_eq!(body, "ready\n");
+
+        let (status, _) = get(addr, "/nope").await;
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn test_readyz_reports_unavailable_pool() {
+        let health = HealthState::new();
+        assert_eq!(readiness(&health).0, StatusCode::SERVICE_UNAVAILABLE);
+
+        health.set_ready(true);
+        health.set_pool_unavailable(true);
+        assert_eq!(
+            readiness(&health),
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "degraded: pool unavailable\n"
+            )
+        );
+
+        health.set_pool_unavailable(false);
+        assert_eq!(readiness(&health), (StatusCode::OK, "ready\n"));
+    }
+
+    #[tokio::test]
+    async fn test_health_only_listener_has_no_metrics() {
+        let routes = HttpRoutes {
+            metrics: None,
+            health: Some(Arc::new(HealthState::new())),
+        };
+        let addr = spawn_http_server("127.0.0.1:0".parse().unwrap(), routes)
+            .await
+            .unwrap();
+
+        assert_eq!(get(addr, "/metrics").await.0, 404);
+        assert_eq!(get(addr, "/healthz").await.0, 200);
+    }
+
+    #[tokio::test]
+    async fn test_route_rejects_non_get() {
+        let (addr, _) = spawn_shared().await;
+        assert_eq!(request(addr, "POST", "/healthz").await.0, 405);
+        assert_eq!(request(addr, "HEAD", "/healthz").await.0, 200);
+        assert_eq!(get(addr, "/metrics?foo=bar").await.0, 200);
+    }
+}
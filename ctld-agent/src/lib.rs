@@ -8,10 +8,14 @@
 //! - `zfs`: ZFS volume and snapshot management
 //! - `service`: gRPC service implementation
 //! - `metrics`: Prometheus metrics collection
+//! - `parameters`: Normalized access to StorageClass parameters
+//! - `http`: HTTP listener for metrics and health probes
 
 pub mod auth;
 pub mod ctl;
+pub mod http;
 pub mod metrics;
+pub mod parameters;
 pub mod service;
 pub mod zfs;
 
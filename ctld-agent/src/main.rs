@@ -1,18 +1,28 @@
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
+use csi_common::logging::{LogFormat, init_tracing};
 use tokio::signal;
 use tokio::sync::RwLock;
 use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
-use tracing::{Level, info};
-use tracing_subscriber::FmtSubscriber;
+use tracing::{Level, info, warn};
 
-use ctld_agent::ctl::CtlManager;
+use ctld_agent::ctl::{
+    CtlManager, DEFAULT_CONFIG_WRITE_DEBOUNCE_MS, DEFAULT_GROUP_CHECK_TTL, ExportGroupValidator,
+    IdentifierScheme,
+};
+use ctld_agent::http::{HealthState, HttpRoutes, spawn_http_server};
 use ctld_agent::metrics;
-use ctld_agent::service::StorageService;
 use ctld_agent::service::proto::storage_agent_server::StorageAgentServer;
-use ctld_agent::zfs::ZfsManager;
+use ctld_agent::service::{
+    ForeignOriginPolicy, OrphanedMetadataPolicy, POOL_PROBE_INTERVAL, StorageService,
+    parse_volume_size_limit,
+};
+use ctld_agent::zfs::{
+    DEFAULT_IMAGE_FETCH_TIMEOUT_SECS, DEFAULT_MAX_CONCURRENT_COPIES, ZfsManager,
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "ctld-agent")]
@@ -62,14 +72,111 @@ struct Args {
     #[arg(long, env = "LOG_LEVEL", default_value = "info")]
     log_level: String,
 
+    /// Log line format: "text" or "json"
+    #[arg(long, env = "LOG_FORMAT", default_value = "text")]
+    log_format: LogFormat,
+
     /// Maximum concurrent storage operations (rate limiting)
     #[arg(long, env = "MAX_CONCURRENT_OPS", default_value = "10")]
     max_concurrent_ops: usize,
 
+    /// Maximum concurrent zfs send/recv copies (COPY clones, image
+    /// provisioning), on top of --max-concurrent-ops; excess copies queue
+    #[arg(long, env = "MAX_CONCURRENT_COPIES", default_value_t = DEFAULT_MAX_CONCURRENT_COPIES)]
+    max_concurrent_copies: usize,
+
+    /// Milliseconds ctl.conf write requests are collected before one write
+    /// and ctld reload covers them all (0 disables batching)
+    #[arg(long, env = "CONFIG_WRITE_DEBOUNCE_MS", default_value_t = DEFAULT_CONFIG_WRITE_DEBOUNCE_MS)]
+    config_write_debounce_ms: u64,
+
+    /// Seconds a shutdown waits for running send/recv copies before aborting
+    /// them and destroying their partially received targets
+    #[arg(long, env = "COPY_DRAIN_TIMEOUT", default_value = "30")]
+    copy_drain_timeout: u64,
+
+    /// Largest volume CreateVolume/ExpandVolume accept, in bytes or with a
+    /// K/M/G/T suffix (e.g. 2T); unset means no limit
+    #[arg(long, env = "MAX_VOLUME_SIZE", value_parser = parse_volume_size_limit)]
+    max_volume_size: Option<u64>,
+
+    /// URL schemes allowed for provisioning volumes from `zfs send` images
+    /// (comma-separated, e.g. https); unset disables image provisioning
+    #[arg(long, env = "IMAGE_URL_SCHEMES", value_delimiter = ',')]
+    image_url_schemes: Vec<String>,
+
+    /// Seconds an image download and receive may run before it is killed
+    /// and the partially received volume destroyed
+    #[arg(long, env = "IMAGE_FETCH_TIMEOUT", default_value_t = DEFAULT_IMAGE_FETCH_TIMEOUT_SECS)]
+    image_fetch_timeout: u64,
+
+    /// Reject NVMeoF volumes that request DH-HMAC-CHAP authentication, which
+    /// ctld cannot enforce, instead of downgrading them to host-nqn access control
+    #[arg(long, env = "STRICT_AUTH", default_value = "false")]
+    strict_auth: bool,
+
+    /// On startup, rebuild the metadata of volumes whose CSI metadata is
+    /// corrupt from their export in the persisted CTL config
+    #[arg(long, env = "REPAIR_CORRUPT_METADATA", default_value = "false")]
+    repair_corrupt_metadata: bool,
+
+    /// Reject CreateSnapshot when another volume already has a snapshot with
+    /// the same name (snapshot names are otherwise unique per volume only)
+    #[arg(long, env = "GLOBALLY_UNIQUE_SNAPSHOT_NAMES", default_value = "false")]
+    globally_unique_snapshot_names: bool,
+
+    /// DeleteVolume handling of clones whose origin snapshot was not created
+    /// by the driver (PVC cloning or CreateSnapshot): "leave" the origin in
+    /// place, or "refuse" the delete
+    #[arg(long, env = "FOREIGN_ORIGIN_POLICY", default_value = "leave")]
+    foreign_origin_policy: ForeignOriginPolicy,
+
+    /// DeleteVolume handling of a tracked volume whose dataset and export are
+    /// already gone: "drop" its metadata and leftover clone snapshots, or run
+    /// the "full" delete
+    #[arg(long, env = "ORPHANED_METADATA_POLICY", default_value = "drop")]
+    orphaned_metadata_policy: OrphanedMetadataPolicy,
+
+    /// Promote LINKED PVC-to-PVC clones right after creation, moving the
+    /// temporary clone snapshot to the clone so the source has no dependents
+    #[arg(long, env = "PROMOTE_LINKED_CLONES", default_value = "false")]
+    promote_linked_clones: bool,
+
+    /// Existing ctl.conf auth-group referenced by volumes created without
+    /// per-volume authentication (default: no-authentication)
+    #[arg(long, env = "DEFAULT_AUTH_GROUP")]
+    default_auth_group: Option<String>,
+
+    /// ctld does not predefine the `no-authentication` auth-group: define it
+    /// in the CSI config unless /etc/ctl.conf already does
+    #[arg(long, env = "DEFINE_NO_AUTHENTICATION", default_value = "false")]
+    define_no_authentication: bool,
+
+    /// World-wide identifier scheme for LUNs and namespaces: "vendor" (T10
+    /// vendor ID, plus NAA on NVMe namespaces), "naa" or "eui64"
+    #[arg(long, env = "IDENTIFIER_SCHEME", default_value = "vendor")]
+    identifier_scheme: IdentifierScheme,
+
+    /// Seconds between export reconciliation passes that retry exports,
+    /// unexports and config writes left unfinished by failed operations
+    /// (0 reconciles on startup only)
+    #[arg(long, env = "RECONCILE_INTERVAL", default_value = "300")]
+    reconcile_interval: u64,
+
     /// Prometheus metrics HTTP address (e.g., 0.0.0.0:9091)
     /// If not set, metrics endpoint is disabled
     #[arg(long, env = "METRICS_ADDR")]
     metrics_addr: Option<String>,
+
+    /// Health probe HTTP address serving /healthz and /readyz (e.g., 0.0.0.0:9092)
+    /// If not set, health endpoints are disabled unless --http-addr is used
+    #[arg(long, env = "HEALTH_ADDR")]
+    health_addr: Option<String>,
+
+    /// Shared HTTP address serving /metrics, /healthz and /readyz on one
+    /// listener (replaces --metrics-addr and --health-addr)
+    #[arg(long, env = "HTTP_ADDR", conflicts_with_all = ["metrics_addr", "health_addr"])]
+    http_addr: Option<String>,
 }
 
 #[tokio::main]
@@ -86,16 +193,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         _ => Level::INFO,
     };
 
-    let subscriber = FmtSubscriber::builder().with_max_level(level).finish();
-    tracing::subscriber::set_global_default(subscriber)?;
+    init_tracing(level, args.log_format)?;
 
-    // Initialize Prometheus metrics endpoint if configured
-    if let Some(ref addr_str) = args.metrics_addr {
+    let health = Arc::new(HealthState::new());
+
+    if let Some(ref addr_str) = args.http_addr {
+        // Shared listener: metrics and health on one address, routed by path
         let addr = addr_str
             .parse()
-            .map_err(|e| format!("Invalid metrics address '{}': {}", addr_str, e))?;
-        if let Err(e) = metrics::init_metrics(addr) {
-            return Err(format!("Failed to initialize metrics: {}", e).into());
+            .map_err(|e| format!("Invalid HTTP address '{}': {}", addr_str, e))?;
+        let handle = metrics::install_recorder()
+            .map_err(|e| format!("Failed to initialize metrics: {}", e))?;
+        let routes = HttpRoutes {
+            metrics: Some(handle),
+            health: Some(health.clone()),
+        };
+        spawn_http_server(addr, routes).await?;
+    } else {
+        // Initialize Prometheus metrics endpoint if configured
+        if let Some(ref addr_str) = args.metrics_addr {
+            let addr = addr_str
+                .parse()
+                .map_err(|e| format!("Invalid metrics address '{}': {}", addr_str, e))?;
+            if let Err(e) = metrics::init_metrics(addr) {
+                return Err(format!("Failed to initialize metrics: {}", e).into());
+            }
+        }
+
+        if let Some(ref addr_str) = args.health_addr {
+            let addr = addr_str
+                .parse()
+                .map_err(|e| format!("Invalid health address '{}': {}", addr_str, e))?;
+            let routes = HttpRoutes {
+                metrics: None,
+                health: Some(health.clone()),
+            };
+            spawn_http_server(addr, routes).await?;
         }
     }
 
@@ -108,6 +241,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("Portal group: {}", args.portal_group);
     info!("Transport group name: {}", args.transport_group);
     info!("Max concurrent operations: {}", args.max_concurrent_ops);
+    info!("Image URL schemes: {:?}", args.image_url_schemes);
 
     // Validate portal group exists if specified
     if !args.portal_group.is_empty() {
@@ -131,8 +265,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         );
     }
 
+    // Validate the default auth group exists if specified
+    if let Some(ref group) = args.default_auth_group {
+        ctld_agent::ctl::validate_auth_group_exists(&args.ctl_config, group)
+            .await
+            .map_err(|e| format!("Startup validation failed: {}", e))?;
+        info!("Validated auth-group '{}' exists in config", group);
+    }
+
+    // Make sure exports without authentication reference a defined group
+    let define_no_authentication = !ctld_agent::ctl::no_authentication_usable(
+        &args.ctl_config,
+        !args.define_no_authentication,
+    )
+    .await
+    .map_err(|e| format!("Startup validation failed: {}", e))?;
+    if define_no_authentication {
+        warn!(
+            "auth-group 'no-authentication' is not defined in {}, defining it in the CSI config",
+            args.ctl_config.display()
+        );
+    }
+
     // Initialize ZFS manager
-    let zfs_manager = ZfsManager::new(args.zfs_parent.clone()).await?;
+    let zfs_manager = ZfsManager::new(args.zfs_parent.clone())
+        .await?
+        .with_max_concurrent_copies(args.max_concurrent_copies)
+        .with_image_fetch_timeout(Duration::from_secs(args.image_fetch_timeout));
+    let copy_limiter = zfs_manager.copy_limiter();
     let zfs = Arc::new(RwLock::new(zfs_manager));
 
     // Initialize unified CTL manager for iSCSI and NVMeoF exports
@@ -143,7 +303,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         args.portal_group.clone(),
         args.transport_group.clone(),
         args.zfs_parent.clone(),
-    )?;
+    )?
+    .with_identifier_scheme(args.identifier_scheme)
+    .with_default_auth_group(args.default_auth_group.clone())
+    .with_explicit_no_authentication(define_no_authentication);
 
     // Note: We intentionally do NOT load from UCL config here.
     // ZFS user properties are the source of truth for CSI-managed volumes.
@@ -153,7 +316,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let ctl = Arc::new(RwLock::new(ctl_manager));
 
     // Create the storage service with rate limiting
-    let storage_service = StorageService::with_concurrency_limit(zfs, ctl, args.max_concurrent_ops);
+    let storage_service = StorageService::with_limits(
+        zfs,
+        ctl,
+        args.max_concurrent_ops,
+        args.config_write_debounce_ms,
+    )
+    .with_image_url_schemes(args.image_url_schemes.clone())
+    .with_strict_auth(args.strict_auth)
+    .with_repair_corrupt_metadata(args.repair_corrupt_metadata)
+    .with_globally_unique_snapshot_names(args.globally_unique_snapshot_names)
+    .with_foreign_origin_policy(args.foreign_origin_policy)
+    .with_orphaned_metadata_policy(args.orphaned_metadata_policy)
+    .with_promote_linked_clones(args.promote_linked_clones)
+    .with_export_group_validator(ExportGroupValidator::new(
+        args.ctl_config.clone(),
+        args.portal_group.clone(),
+        args.transport_group.clone(),
+        DEFAULT_GROUP_CHECK_TTL,
+    ))
+    .with_health_state(health.clone())
+    .with_max_volume_size(args.max_volume_size);
 
     // Restore volume metadata from ZFS user properties
     match storage_service.restore_from_zfs().await {
@@ -176,11 +359,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Reconcile exports: ensure all volumes in ZFS metadata are exported
     match storage_service.reconcile_exports().await {
-        Ok(count) => {
-            if count > 0 {
-                info!(
-                    "Reconciled {} export(s) that were missing from CTL config",
-                    count
+        Ok(summary) => {
+            if !summary.failed.is_empty() {
+                tracing::warn!(
+                    "{} volume(s) could not be reconciled and need attention",
+                    summary.failed.len()
                 );
             }
         }
@@ -193,6 +376,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    let storage_service = Arc::new(storage_service);
+    if args.reconcile_interval > 0 {
+        storage_service.spawn_reconcile_loop(Duration::from_secs(args.reconcile_interval));
+    }
+    storage_service.spawn_pool_probe(POOL_PROBE_INTERVAL);
+
     // Parse the listen address
     let addr = args.listen.parse()?;
 
@@ -223,15 +412,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("TLS disabled - running in plaintext mode");
     }
 
+    // Startup reconciliation is done; report ready while serving
+    health.set_ready(true);
+
     // Start the gRPC server with graceful shutdown
+    // Copies can outlive the RPC drain, so they get their own bounded wait
+    // before being aborted (which also ends the RPCs waiting on them)
+    let health_clone = health.clone();
+    let copy_drain_timeout = Duration::from_secs(args.copy_drain_timeout);
+    let (copies_drained_tx, copies_drained_rx) = tokio::sync::oneshot::channel();
     builder
-        .add_service(StorageAgentServer::new(storage_service))
-        .serve_with_shutdown(addr, async {
+        .add_service(StorageAgentServer::from_arc(storage_service))
+        .serve_with_shutdown(addr, async move {
             shutdown_signal().await;
             info!("Shutdown signal received, draining connections...");
+            health_clone.set_ready(false);
+            tokio::spawn(async move {
+                let finished = copy_limiter.drain(copy_drain_timeout).await;
+                let _ = copies_drained_tx.send(finished);
+            });
         })
         .await?;
 
+    // Don't exit while an aborted copy is still destroying its partial target
+    if let Ok(false) = copies_drained_rx.await {
+        warn!("Aborted send/recv copies that did not finish within the drain timeout");
+    }
+
     info!("ctld-agent shutdown complete");
     Ok(())
 }
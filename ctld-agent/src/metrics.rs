@@ -4,11 +4,12 @@
 //! and agent performance.
 
 use std::net::SocketAddr;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use metrics::{counter, gauge, histogram};
-use metrics_exporter_prometheus::PrometheusBuilder;
-use tracing::info;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info};
 
 /// Metric names
 pub mod names {
@@ -16,6 +17,8 @@ pub mod names {
     pub const STORAGE_OPERATIONS_TOTAL: &str = "ctld_storage_operations_total";
     /// Histogram: Duration of storage operations in seconds
     pub const STORAGE_OPERATION_DURATION_SECONDS: &str = "ctld_storage_operation_duration_seconds";
+    /// Histogram: Duration of volume create/delete/expand by export type
+    pub const VOLUME_OPERATION_DURATION_SECONDS: &str = "ctld_volume_operation_duration_seconds";
     /// Gauge: Number of active volumes
     pub const VOLUMES_TOTAL: &str = "ctld_volumes_total";
     /// Gauge: Number of active exports by type (iscsi/nvmeof)
@@ -24,8 +27,38 @@ pub mod names {
     pub const RATE_LIMITED_TOTAL: &str = "ctld_rate_limited_total";
     /// Gauge: Current concurrent operations in progress
     pub const CONCURRENT_OPS: &str = "ctld_concurrent_ops";
+    /// Gauge: Current send/recv copies in progress
+    pub const CONCURRENT_COPIES: &str = "ctld_concurrent_copies";
+    /// Gauge: Volumes per outcome of the last startup reconciliation
+    pub const RECONCILE_VOLUMES: &str = "ctld_reconcile_volumes";
+    /// Counter: Volumes reported with zero capacity because volsize was missing
+    pub const VOLSIZE_MISSING_TOTAL: &str = "ctld_volsize_missing_total";
+    /// Counter: Snapshot deletes refused because several snapshots share the ID
+    pub const AMBIGUOUS_SNAPSHOTS_TOTAL: &str = "ctld_ambiguous_snapshots_total";
+    /// Gauge: Volumes at or below each size bucket (cumulative, by `le`)
+    pub const VOLUME_SIZE_BYTES: &str = "ctld_volume_size_bytes";
+    /// Gauge: Sum of the provisioned sizes of all volumes
+    pub const PROVISIONED_BYTES: &str = "ctld_provisioned_bytes";
+    /// Gauge: Total capacity (used + available) of the parent dataset
+    pub const POOL_CAPACITY_BYTES: &str = "ctld_pool_capacity_bytes";
+    /// Gauge: 1 for the pool's current health state, 0 for the others
+    pub const POOL_HEALTH: &str = "ctld_pool_health";
+    /// Gauge: Free-space fragmentation of the pool in percent
+    pub const POOL_FRAGMENTATION_PERCENT: &str = "ctld_pool_fragmentation_percent";
 }
 
+/// Upper bounds of the volume size buckets: 1 GiB to 16 TiB in steps of 4x
+const VOLUME_SIZE_BUCKETS: &[u64] = &[
+    1 << 30,
+    4 << 30,
+    16 << 30,
+    64 << 30,
+    256 << 30,
+    1 << 40,
+    4 << 40,
+    16 << 40,
+];
+
 /// Initialize the Prometheus metrics exporter
 ///
 /// Starts an HTTP server on the specified address that serves metrics
@@ -39,6 +72,33 @@ pub fn init_metrics(addr: SocketAddr) -> Result<(), Box<dyn std::error::Error +
     Ok(())
 }
 
+/// How often histogram buckets are drained when no HTTP listener of the
+/// exporter does it (matches the exporter's own default)
+const RECORDER_UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Install the Prometheus recorder without an HTTP listener
+///
+/// The returned handle renders the metrics; it is served by the shared
+/// listener in [`crate::http`] when metrics and health share one address.
+/// Without the exporter's listener nothing drains histogram samples, so a
+/// background task runs the upkeep periodically. Must be called from within
+/// a tokio runtime.
+pub fn install_recorder() -> Result<PrometheusHandle, Box<dyn std::error::Error + Send + Sync>> {
+    let handle = PrometheusBuilder::new().install_recorder()?;
+
+    let upkeep = handle.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(RECORDER_UPKEEP_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            upkeep.run_upkeep();
+        }
+    });
+
+    Ok(handle)
+}
+
 /// Record a storage operation with its result
 pub fn record_operation(operation: &str, status: &str, duration_secs: f64) {
     counter!(names::STORAGE_OPERATIONS_TOTAL, "operation" => operation.to_string(), "status" => status.to_string())
@@ -47,6 +107,43 @@ pub fn record_operation(operation: &str, status: &str, duration_secs: f64) {
         .record(duration_secs);
 }
 
+/// `export_type` label values.
+///
+/// A closed set, so the label cannot grow past three series per operation
+/// whatever a request carries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportLabel {
+    Iscsi,
+    Nvmeof,
+    /// The export type is not known yet (e.g. a request rejected before it
+    /// was parsed, or deleting a volume without tracked metadata)
+    #[default]
+    Unspecified,
+}
+
+impl ExportLabel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportLabel::Iscsi => "iscsi",
+            ExportLabel::Nvmeof => "nvmeof",
+            ExportLabel::Unspecified => "unspecified",
+        }
+    }
+}
+
+/// Record the duration of a volume operation for one export type.
+///
+/// Recorded in addition to [`record_operation`], whose series keep their
+/// existing labels.
+pub fn record_volume_operation(operation: &str, export_type: ExportLabel, duration_secs: f64) {
+    histogram!(
+        names::VOLUME_OPERATION_DURATION_SECONDS,
+        "operation" => operation.to_string(),
+        "export_type" => export_type.as_str()
+    )
+    .record(duration_secs);
+}
+
 /// Set the number of active volumes
 pub fn set_volumes_count(count: usize) {
     gauge!(names::VOLUMES_TOTAL).set(count as f64);
@@ -67,10 +164,104 @@ pub fn set_concurrent_ops(count: usize) {
     gauge!(names::CONCURRENT_OPS).set(count as f64);
 }
 
+/// Set the current number of send/recv copies in progress
+pub fn set_concurrent_copies(count: usize) {
+    gauge!(names::CONCURRENT_COPIES).set(count as f64);
+}
+
+/// Record the per-outcome volume counts of a reconciliation pass
+pub fn set_reconcile_results(reconciled: usize, unchanged: usize, skipped: usize, failed: usize) {
+    for (outcome, count) in [
+        ("reconciled", reconciled),
+        ("unchanged", unchanged),
+        ("skipped", skipped),
+        ("failed", failed),
+    ] {
+        gauge!(names::RECONCILE_VOLUMES, "outcome" => outcome).set(count as f64);
+    }
+}
+
+/// Record a volume whose volsize could not be determined
+pub fn record_volsize_missing() {
+    counter!(names::VOLSIZE_MISSING_TOTAL).increment(1);
+}
+
+/// Record a snapshot delete refused because its CSI ID is on several snapshots
+pub fn record_ambiguous_snapshot() {
+    counter!(names::AMBIGUOUS_SNAPSHOTS_TOTAL).increment(1);
+}
+
+/// Set the volume size distribution and total provisioned bytes.
+///
+/// The distribution is recomputed from the full volume list on every call,
+/// so it is exported as cumulative `le` gauges rather than a histogram,
+/// which would keep accumulating across refreshes.
+pub fn set_volume_sizes(sizes: &[u64]) {
+    for (le, count) in volume_size_buckets(sizes) {
+        gauge!(names::VOLUME_SIZE_BYTES, "le" => le).set(count as f64);
+    }
+    gauge!(names::PROVISIONED_BYTES).set(sizes.iter().sum::<u64>() as f64);
+}
+
+/// Set the total capacity of the parent dataset
+pub fn set_pool_capacity(bytes: u64) {
+    gauge!(names::POOL_CAPACITY_BYTES).set(bytes as f64);
+}
+
+/// Pool health states reported by `zpool get health`, plus the state any
+/// other value is reported as
+const POOL_HEALTH_STATES: &[&str] = &[
+    "ONLINE",
+    "DEGRADED",
+    "FAULTED",
+    "OFFLINE",
+    "UNAVAIL",
+    "REMOVED",
+    "SUSPENDED",
+    OTHER_POOL_HEALTH,
+];
+
+/// `state` label of a health value missing from [`POOL_HEALTH_STATES`]
+const OTHER_POOL_HEALTH: &str = "OTHER";
+
+/// Set the pool's health and fragmentation. Every known state gets a
+/// series, so alerts can match `state!="ONLINE"` without gaps.
+pub fn set_pool_status(pool: &str, health: &str, fragmentation_percent: Option<u32>) {
+    // Unknown states share one series, so it is zeroed once the pool
+    // reports a known state again
+    let current = if POOL_HEALTH_STATES.contains(&health) {
+        health
+    } else {
+        debug!(pool = %pool, health = %health, "Reporting unknown pool health as OTHER");
+        OTHER_POOL_HEALTH
+    };
+    for &state in POOL_HEALTH_STATES {
+        gauge!(names::POOL_HEALTH, "pool" => pool.to_string(), "state" => state)
+            .set(if state == current { 1.0 } else { 0.0 });
+    }
+    if let Some(percent) = fragmentation_percent {
+        gauge!(names::POOL_FRAGMENTATION_PERCENT, "pool" => pool.to_string()).set(percent as f64);
+    }
+}
+
+/// Cumulative count of volumes per size bucket, ending with `+Inf`
+fn volume_size_buckets(sizes: &[u64]) -> Vec<(String, usize)> {
+    VOLUME_SIZE_BUCKETS
+        .iter()
+        .map(|&bound| {
+            let count = sizes.iter().filter(|&&size| size <= bound).count();
+            (bound.to_string(), count)
+        })
+        .chain(std::iter::once(("+Inf".to_string(), sizes.len())))
+        .collect()
+}
+
 /// Helper for timing operations
 pub struct OperationTimer {
     operation: String,
     start: Instant,
+    /// Also record the per-export-type volume histogram
+    export_type: Option<ExportLabel>,
 }
 
 impl OperationTimer {
@@ -79,19 +270,42 @@ impl OperationTimer {
         Self {
             operation: operation.to_string(),
             start: Instant::now(),
+            export_type: None,
+        }
+    }
+
+    /// Start timing a volume operation that is also recorded by export type.
+    ///
+    /// Pass [`ExportLabel::Unspecified`] when the type is only known later
+    /// and update it with [`set_export_type`](Self::set_export_type).
+    pub fn with_export_type(operation: &str, export_type: ExportLabel) -> Self {
+        Self {
+            export_type: Some(export_type),
+            ..Self::new(operation)
         }
     }
 
+    /// Set the export type once it is known
+    pub fn set_export_type(&mut self, export_type: ExportLabel) {
+        self.export_type = Some(export_type);
+    }
+
     /// Complete the operation with success
     pub fn success(self) {
-        let duration = self.start.elapsed().as_secs_f64();
-        record_operation(&self.operation, "success", duration);
+        self.finish("success");
     }
 
     /// Complete the operation with failure
     pub fn failure(self, error_code: &str) {
+        self.finish(error_code);
+    }
+
+    fn finish(self, status: &str) {
         let duration = self.start.elapsed().as_secs_f64();
-        record_operation(&self.operation, error_code, duration);
+        record_operation(&self.operation, status, duration);
+        if let Some(export_type) = self.export_type {
+            record_volume_operation(&self.operation, export_type, duration);
+        }
     }
 }
 
@@ -99,6 +313,120 @@ impl OperationTimer {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_volume_sizes_populate_distribution() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let gib = 1u64 << 30;
+        let sizes = [gib, 2 * gib, 10 * gib, 20 * gib, 32 << 40];
+
+        metrics::with_local_recorder(&recorder, || set_volume_sizes(&sizes));
+        let rendered = handle.render();
+
+        for (le, count) in [
+            ("1073741824", 1),
+            ("4294967296", 2),
+            ("17179869184", 3),
+            ("68719476736", 4),
+            ("17592186044416", 4),
+            ("+Inf", 5),
+        ] {
+            let line = format!("ctld_volume_size_bytes{{le=\"{}\"}} {}", le, count);
+            assert!(rendered.contains(&line), "missing {}:\n{}", line, rendered);
+        }
+        let total = sizes.iter().sum::<u64>();
+        assert!(rendered.contains(&format!("ctld_provisioned_bytes {}", total)));
+    }
+
+    #[test]
+    fn test_pool_status_gauges() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+
+        metrics::with_local_recorder(&recorder, || {
+            set_pool_status("tank", "ONLINE", Some(12));
+            set_pool_status("tank", "DEGRADED", None);
+        });
+        let rendered = handle.render();
+
+        for line in [
+            "ctld_pool_health{pool=\"tank\",state=\"DEGRADED\"} 1",
+            "ctld_pool_health{pool=\"tank\",state=\"ONLINE\"} 0",
+            "ctld_pool_health{pool=\"tank\",state=\"FAULTED\"} 0",
+            "ctld_pool_health{pool=\"tank\",state=\"OTHER\"} 0",
+            // Unknown fragmentation keeps the last reported value
+            "ctld_pool_fragmentation_percent{pool=\"tank\"} 12",
+        ] {
+            assert!(rendered.contains(line), "missing {}:\n{}", line, rendered);
+        }
+
+        // A suspended pool (I/O failures) has its own series
+        metrics::with_local_recorder(&recorder, || {
+            set_pool_status("tank", "SUSPENDED", None);
+        });
+        let rendered = handle.render();
+        assert!(rendered.contains("ctld_pool_health{pool=\"tank\",state=\"SUSPENDED\"} 1"));
+        assert!(rendered.contains("ctld_pool_health{pool=\"tank\",state=\"OTHER\"} 0"));
+
+        // An unknown state is reported as OTHER and cleared again
+        metrics::with_local_recorder(&recorder, || {
+            set_pool_status("tank", "NOT_A_STATE", None);
+        });
+        let rendered = handle.render();
+        assert!(rendered.contains("ctld_pool_health{pool=\"tank\",state=\"OTHER\"} 1"));
+        assert!(rendered.contains("ctld_pool_health{pool=\"tank\",state=\"SUSPENDED\"} 0"));
+        assert!(rendered.contains("ctld_pool_health{pool=\"tank\",state=\"DEGRADED\"} 0"));
+        assert!(!rendered.contains("NOT_A_STATE"));
+
+        metrics::with_local_recorder(&recorder, || {
+            set_pool_status("tank", "ONLINE", None);
+        });
+        let rendered = handle.render();
+        assert!(rendered.contains("ctld_pool_health{pool=\"tank\",state=\"OTHER\"} 0"));
+    }
+
+    #[test]
+    fn test_volume_operations_labeled_by_export_type() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+
+        metrics::with_local_recorder(&recorder, || {
+            let mut timer =
+                OperationTimer::with_export_type("create_volume", ExportLabel::Unspecified);
+            timer.set_export_type(ExportLabel::Nvmeof);
+            timer.success();
+            OperationTimer::with_export_type("delete_volume", ExportLabel::Unspecified)
+                .failure("invalid_argument");
+            OperationTimer::new("create_snapshot").success();
+        });
+        let rendered = handle.render();
+
+        let labeled: Vec<&str> = rendered
+            .lines()
+            .filter(|l| l.starts_with("ctld_volume_operation_duration_seconds_count"))
+            .collect();
+        assert_eq!(labeled.len(), 2, "{}", rendered);
+        assert!(
+            labeled
+                .iter()
+                .any(|l| l.contains("operation=\"create_volume\"")
+                    && l.contains("export_type=\"nvmeof\""))
+        );
+        assert!(
+            labeled
+                .iter()
+                .any(|l| l.contains("operation=\"delete_volume\"")
+                    && l.contains("export_type=\"unspecified\""))
+        );
+        // The existing histogram keeps its labels and still sees every operation
+        let unlabeled: Vec<&str> = rendered
+            .lines()
+            .filter(|l| l.starts_with("ctld_storage_operation_duration_seconds_count"))
+            .collect();
+        assert_eq!(unlabeled.len(), 3, "{}", rendered);
+        assert!(unlabeled.iter().all(|l| !l.contains("export_type")));
+    }
+
     #[test]
     fn test_operation_timer() {
         let timer = OperationTimer::new("test_operation");
@@ -0,0 +1,242 @@
+//! StorageClass parameter access.
+//!
+//! Parameters reach the agent as a plain string map. [`Parameters`] wraps it
+//! so every parser treats keys and values the same way:
+//!
+//! - Keys match regardless of case and `_`/`-` separators, so
+//!   `provisioning_mode` finds `provisioningMode`. An exact spelling wins if
+//!   several variants are present.
+//! - Values are trimmed of surrounding whitespace.
+//! - Typed getters accept values case-insensitively and report errors as
+//!   `invalid <key> '<value>': expected <values>`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use csi_common::parameters::normalize_key;
+
+/// A parameter value that does not parse as its expected type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterError {
+    pub key: String,
+    pub value: String,
+    pub expected: String,
+}
+
+impl fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid {} '{}': expected {}",
+            self.key, self.value, self.expected
+        )
+    }
+}
+
+impl std::error::Error for ParameterError {}
+
+/// Normalized read-only view of StorageClass parameters
+#[derive(Debug, Clone, Copy)]
+pub struct Parameters<'a> {
+    raw: &'a HashMap<String, String>,
+}
+
+impl<'a> Parameters<'a> {
+    pub fn new(raw: &'a HashMap<String, String>) -> Self {
+        Self { raw }
+    }
+
+    /// Trimmed value of `key`, matched by its normalized spelling
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        if let Some(value) = self.raw.get(key) {
+            return Some(value.trim());
+        }
+        let wanted = normalize_key(key);
+        self.raw
+            .iter()
+            .filter(|(k, _)| normalize_key(k) == wanted)
+            // Deterministic pick when several spellings are present
+            .min_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, v)| v.trim())
+    }
+
+    /// Whether `key` is set, in any spelling
+    pub fn contains(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Trimmed value of `key`, treating an empty value as unset
+    pub fn get_non_empty(&self, key: &str) -> Option<&'a str> {
+        self.get(key).filter(|v| !v.is_empty())
+    }
+
+    /// Boolean value: `true`/`false`, `on`/`off`, `yes`/`no` or `1`/`0`
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, ParameterError> {
+        self.get_enum(
+            key,
+            &[
+                ("true", true),
+                ("false", false),
+                ("on", true),
+                ("off", false),
+                ("yes", true),
+                ("no", false),
+                ("1", true),
+                ("0", false),
+            ],
+        )
+        .map_err(|e| ParameterError {
+            expected: "true or false".to_string(),
+            ..e
+        })
+    }
+
+    /// Unsigned integer value
+    pub fn get_u32(&self, key: &str) -> Result<Option<u32>, ParameterError> {
+        self.get(key)
+            .map(|value| {
+                value.parse().map_err(|_| ParameterError {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                    expected: "a non-negative integer".to_string(),
+                })
+            })
+            .transpose()
+    }
+
+    /// One of `variants`, matched case-insensitively by name
+    pub fn get_enum<T: Copy>(
+        &self,
+        key: &str,
+        variants: &[(&str, T)],
+    ) -> Result<Option<T>, ParameterError> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+        variants
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(value))
+            .map(|&(_, variant)| Some(variant))
+            .ok_or_else(|| ParameterError {
+                key: key.to_string(),
+                value: value.to_string(),
+                expected: expected_list(variants.iter().map(|(name, _)| *name)),
+            })
+    }
+}
+
+/// "a, b or c"
+fn expected_list<'n>(names: impl Iterator<Item = &'n str>) -> String {
+    let names: Vec<&str> = names.collect();
+    match names.split_last() {
+        None => String::new(),
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_normalizes_keys_and_trims_values() {
+        let raw = map(&[("provisioning_mode", " thick "), ("Enable-Unmap", "on")]);
+        let params = Parameters::new(&raw);
+        assert_eq!(params.get("provisioningMode"), Some("thick"));
+        assert_eq!(params.get("enableUnmap"), Some("on"));
+        assert!(params.contains("ENABLE_UNMAP"));
+        assert_eq!(params.get("blockSize"), None);
+
+        // The exact spelling wins over a variant
+        let raw = map(&[("blockSize", "512"), ("block_size", "4096")]);
+        assert_eq!(Parameters::new(&raw).get("blockSize"), Some("512"));
+        assert_eq!(Parameters::new(&raw).get("block_size"), Some("4096"));
+
+        let raw = map(&[("targetAlias", "  ")]);
+        let params = Parameters::new(&raw);
+        assert_eq!(params.get("targetAlias"), Some(""));
+        assert_eq!(params.get_non_empty("targetAlias"), None);
+    }
+
+    #[test]
+    fn test_get_bool() {
+        for (value, expected) in [
+            ("true", true),
+            ("TRUE", true),
+            (" on", true),
+            ("Yes ", true),
+            ("1", true),
+            ("false", false),
+            ("Off", false),
+            ("no", false),
+            ("0", false),
+        ] {
+            let raw = map(&[("removable", value)]);
+            assert_eq!(
+                Parameters::new(&raw).get_bool("removable"),
+                Ok(Some(expected)),
+                "{value:?}"
+            );
+        }
+        assert_eq!(Parameters::new(&map(&[])).get_bool("removable"), Ok(None));
+
+        let raw = map(&[("removable", "maybe")]);
+        let err = Parameters::new(&raw).get_bool("removable").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid removable 'maybe': expected true or false"
+        );
+    }
+
+    #[test]
+    fn test_get_u32() {
+        let raw = map(&[("blockSize", " 4096 "), ("bad", "4k"), ("neg", "-1")]);
+        let params = Parameters::new(&raw);
+        assert_eq!(params.get_u32("block_size"), Ok(Some(4096)));
+        assert_eq!(params.get_u32("missing"), Ok(None));
+        assert_eq!(
+            params.get_u32("bad").unwrap_err().to_string(),
+            "invalid bad '4k': expected a non-negative integer"
+        );
+        assert!(params.get_u32("neg").is_err());
+    }
+
+    #[test]
+    fn test_get_enum() {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        enum Mode {
+            Thin,
+            Thick,
+        }
+        let variants = [("thin", Mode::Thin), ("thick", Mode::Thick)];
+
+        let raw = map(&[("provisioningMode", " THICK")]);
+        assert_eq!(
+            Parameters::new(&raw).get_enum("provisioningMode", &variants),
+            Ok(Some(Mode::Thick))
+        );
+
+        let raw = map(&[("provisioningMode", "thinnish")]);
+        assert_eq!(
+            Parameters::new(&raw)
+                .get_enum("provisioningMode", &variants)
+                .unwrap_err()
+                .to_string(),
+            "invalid provisioningMode 'thinnish': expected thin or thick"
+        );
+    }
+
+    #[test]
+    fn test_expected_list() {
+        assert_eq!(expected_list(["a"].into_iter()), "a");
+        assert_eq!(expected_list(["a", "b", "c"].into_iter()), "a, b or c");
+    }
+}
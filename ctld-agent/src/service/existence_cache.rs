@@ -0,0 +1,149 @@
+//! Short-lived volume existence cache for idempotent create/delete retries.
+//!
+//! CSI sidecars retry CreateVolume/DeleteVolume aggressively. Without a cache,
+//! every retry of a name we just created or deleted re-queries ZFS. This cache
+//! remembers the outcome for a short window so those retries can skip the
+//! scan. ZFS stays authoritative: a miss or expired entry always falls back to
+//! querying ZFS, and every create/delete/resize updates or invalidates the entry.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::zfs::Dataset;
+
+/// Default time an existence entry stays valid
+pub const DEFAULT_EXISTENCE_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Cached existence of a volume
+#[derive(Debug, Clone)]
+pub enum CachedExistence {
+    /// Volume exists with the given dataset info
+    Exists(Dataset),
+    /// Volume was recently deleted or confirmed absent
+    Absent,
+}
+
+/// Time-bounded existence cache keyed by volume name
+#[derive(Debug)]
+pub struct ExistenceCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, CachedExistence)>>,
+}
+
+impl ExistenceCache {
+    /// Create a cache whose entries expire after `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Look up a volume. Returns `None` on a miss or expired entry, in which
+    /// case the caller must consult ZFS.
+    pub fn lookup(&self, name: &str) -> Option<CachedExistence> {
+        self.lookup_at(name, Instant::now())
+    }
+
+    fn lookup_at(&self, name: &str, now: Instant) -> Option<CachedExistence> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(name) {
+            Some((recorded, existence)) if now.duration_since(*recorded) < self.ttl => {
+                Some(existence.clone())
+            }
+            Some(_) => {
+                entries.remove(name);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Record that a volume exists (after create or an idempotent lookup)
+    pub fn record_exists(&self, name: &str, dataset: Dataset) {
+        self.record(name, CachedExistence::Exists(dataset));
+    }
+
+    /// Record that a volume is gone (after delete)
+    pub fn record_absent(&self, name: &str) {
+        self.record(name, CachedExistence::Absent);
+    }
+
+    /// Drop any cached entry for a volume so the next lookup goes to ZFS
+    pub fn invalidate(&self, name: &str) {
+        self.entries.lock().unwrap().remove(name);
+    }
+
+    fn record(&self, name: &str, existence: CachedExistence) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        // Prune expired entries so the cache stays bounded by the retry window
+        entries.retain(|_, (recorded, _)| now.duration_since(*recorded) < self.ttl);
+        entries.insert(name.to_string(), (now, existence));
+    }
+}
+
+impl Default for ExistenceCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_EXISTENCE_CACHE_TTL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dataset(name: &str, size: u64) -> Dataset {
+        Dataset {
+            name: format!("tank/csi/{}", name),
+            referenced: 0,
+            volsize: Some(size),
+            refreservation: 0,
+        }
+    }
+
+    #[test]
+    fn test_create_populates_cache() {
+        let cache = ExistenceCache::default();
+        cache.record_exists("pvc-1", dataset("pvc-1", 1024));
+
+        match cache.lookup("pvc-1") {
+            Some(CachedExistence::Exists(d)) => assert_eq!(d.volsize, Some(1024)),
+            other => panic!("expected cached existence, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_delete_replaces_positive_entry() {
+        let cache = ExistenceCache::default();
+        cache.record_exists("pvc-1", dataset("pvc-1", 1024));
+        cache.record_absent("pvc-1");
+
+        assert!(matches!(
+            cache.lookup("pvc-1"),
+            Some(CachedExistence::Absent)
+        ));
+    }
+
+    #[test]
+    fn test_invalidate_forces_zfs_lookup() {
+        let cache = ExistenceCache::default();
+        cache.record_exists("pvc-1", dataset("pvc-1", 1024));
+        cache.invalidate("pvc-1");
+
+        assert!(cache.lookup("pvc-1").is_none());
+    }
+
+    #[test]
+    fn test_miss_and_expiry_fall_back_to_zfs() {
+        let cache = ExistenceCache::new(Duration::from_secs(5));
+        assert!(cache.lookup("unknown").is_none());
+
+        cache.record_exists("pvc-1", dataset("pvc-1", 1024));
+        let later = Instant::now() + Duration::from_secs(6);
+        assert!(cache.lookup_at("pvc-1", later).is_none());
+        // Expired entry was dropped
+        assert!(cache.entries.lock().unwrap().is_empty());
+    }
+}
@@ -1,3 +1,10 @@
+mod existence_cache;
+mod recent_errors;
+mod snapshot_progress;
 pub mod storage;
+mod volume_locks;
 
-pub use storage::{StorageService, proto};
+pub use storage::{
+    ForeignOriginPolicy, OrphanedMetadataPolicy, POOL_PROBE_INTERVAL, StorageService,
+    parse_volume_size_limit, proto,
+};
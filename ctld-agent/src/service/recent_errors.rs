@@ -0,0 +1,202 @@
+//! Bounded in-memory record of recent operation errors.
+//!
+//! Keeps the last few failed mutating operations (create/delete/expand
+//! volume, create/delete snapshot) so operators can see what has been failing
+//! through `GetRecentErrors` without access to the agent's logs. Memory is
+//! bounded by both the entry count and the stored message length. Secrets
+//! carried by the failing request are redacted before an entry is stored.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tonic::Status;
+
+/// Default number of errors kept
+pub const DEFAULT_RECENT_ERRORS_CAPACITY: usize = 64;
+
+/// Longest error message kept per entry, in bytes
+const MAX_MESSAGE_LEN: usize = 1024;
+
+/// Replacement for redacted secret values
+const REDACTED: &str = "[REDACTED]";
+
+/// A single recorded operation error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentError {
+    /// RPC name (e.g. "CreateVolume")
+    pub operation: String,
+    /// Volume or snapshot ID the operation targeted
+    pub resource_id: String,
+    /// gRPC status code name (e.g. "Internal")
+    pub code: String,
+    /// Error message with secrets redacted
+    pub message: String,
+    /// Unix timestamp (seconds) of the failure
+    pub timestamp: i64,
+}
+
+/// Ring buffer of the most recent operation errors
+#[derive(Debug, Clone)]
+pub struct RecentErrors {
+    entries: Arc<Mutex<VecDeque<RecentError>>>,
+    capacity: usize,
+}
+
+impl Default for RecentErrors {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_RECENT_ERRORS_CAPACITY)
+    }
+}
+
+impl RecentErrors {
+    /// Create a buffer holding at most `capacity` errors
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Record a failed operation, evicting the oldest entry when full.
+    ///
+    /// Every non-empty string in `secrets` is replaced in the message.
+    pub fn record(&self, operation: &str, resource_id: &str, status: &Status, secrets: &[&str]) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        let entry = RecentError {
+            operation: operation.to_string(),
+            resource_id: resource_id.to_string(),
+            code: format!("{:?}", status.code()),
+            message: redact(status.message(), secrets),
+            timestamp,
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Record the error of `result`, if any
+    pub fn record_result<T>(
+        &self,
+        operation: &str,
+        resource_id: &str,
+        result: &Result<T, Status>,
+        secrets: &[&str],
+    ) {
+        if let Err(status) = result {
+            self.record(operation, resource_id, status, secrets);
+        }
+    }
+
+    /// Recorded errors, newest first, limited to `max_entries` (0 = all)
+    pub fn list(&self, max_entries: usize) -> Vec<RecentError> {
+        let entries = self.entries.lock().unwrap();
+        let limit = if max_entries == 0 {
+            entries.len()
+        } else {
+            max_entries
+        };
+        entries.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+/// Redact secret values from a message and cap its length
+fn redact(message: &str, secrets: &[&str]) -> String {
+    let mut message = message.to_string();
+    for secret in secrets.iter().filter(|s| !s.is_empty()) {
+        message = message.replace(secret, REDACTED);
+    }
+
+    if message.len() > MAX_MESSAGE_LEN {
+        let mut end = MAX_MESSAGE_LEN;
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message.truncate(end);
+        message.push_str("...");
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_are_recorded_newest_first() {
+        let errors = RecentErrors::with_capacity(4);
+        errors.record(
+            "CreateVolume",
+            "pvc-1",
+            &Status::internal("zfs failed"),
+            &[],
+        );
+        errors.record(
+            "DeleteSnapshot",
+            "pvc-1@snap",
+            &Status::failed_precondition("has clones"),
+            &[],
+        );
+
+        let listed = errors.list(0);
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].operation, "DeleteSnapshot");
+        assert_eq!(listed[0].resource_id, "pvc-1@snap");
+        assert_eq!(listed[0].code, "FailedPrecondition");
+        assert_eq!(listed[1].message, "zfs failed");
+        assert!(listed[1].timestamp > 0);
+
+        assert_eq!(errors.list(1).len(), 1);
+    }
+
+    #[test]
+    fn test_buffer_evicts_oldest_beyond_capacity() {
+        let errors = RecentErrors::with_capacity(3);
+        for i in 0..5 {
+            errors.record(
+                "ExpandVolume",
+                &format!("pvc-{}", i),
+                &Status::internal("boom"),
+                &[],
+            );
+        }
+
+        let ids: Vec<_> = errors.list(0).into_iter().map(|e| e.resource_id).collect();
+        assert_eq!(ids, vec!["pvc-4", "pvc-3", "pvc-2"]);
+    }
+
+    #[test]
+    fn test_secrets_redacted_and_message_bounded() {
+        let errors = RecentErrors::default();
+        let result: Result<(), Status> = Err(Status::internal(
+            "auth-group rejected secret s3cr3t-value for user admin",
+        ));
+        errors.record_result("CreateVolume", "pvc-1", &result, &["s3cr3t-value", ""]);
+
+        let entry = &errors.list(0)[0];
+        assert!(!entry.message.contains("s3cr3t-value"));
+        assert!(entry.message.contains(REDACTED));
+
+        let long = "x".repeat(MAX_MESSAGE_LEN * 2);
+        errors.record("CreateVolume", "pvc-2", &Status::internal(long), &[]);
+        assert_eq!(errors.list(1)[0].message.len(), MAX_MESSAGE_LEN + 3);
+    }
+
+    #[test]
+    fn test_successful_results_are_not_recorded() {
+        let errors = RecentErrors::default();
+        errors.record_result::<()>("CreateVolume", "pvc-1", &Ok(()), &[]);
+        assert!(errors.list(0).is_empty());
+    }
+}
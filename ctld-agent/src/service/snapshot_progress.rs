@@ -0,0 +1,83 @@
+//! Tracking of snapshots whose backing operation has not finished yet.
+//!
+//! `zfs snapshot` is atomic, so a snapshot taken on its own is ready as soon
+//! as it exists. Snapshots taken as the first step of a COPY clone are a
+//! different story: they show up in ZFS (and in ListSnapshots) while the
+//! send/recv stream is still running. Those are registered here for the
+//! duration of the operation so they are reported with `ready_to_use = false`.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+/// Set of snapshot IDs with an in-progress operation
+#[derive(Debug, Clone, Default)]
+pub struct InProgressSnapshots {
+    ids: Arc<Mutex<HashSet<String>>>,
+}
+
+impl InProgressSnapshots {
+    /// Mark a snapshot as in progress until the returned guard is dropped
+    pub fn begin(&self, snapshot_id: &str) -> InProgressGuard {
+        self.ids.lock().unwrap().insert(snapshot_id.to_string());
+        InProgressGuard {
+            ids: self.ids.clone(),
+            snapshot_id: snapshot_id.to_string(),
+        }
+    }
+
+    /// Whether a snapshot can be reported as ready to use
+    pub fn is_ready(&self, snapshot_id: &str) -> bool {
+        !self.ids.lock().unwrap().contains(snapshot_id)
+    }
+}
+
+/// Clears the in-progress mark for a snapshot when dropped, so every exit
+/// path of the owning operation (success, error, early return) settles it.
+#[derive(Debug)]
+pub struct InProgressGuard {
+    ids: Arc<Mutex<HashSet<String>>>,
+    snapshot_id: String,
+}
+
+impl Drop for InProgressGuard {
+    fn drop(&mut self) {
+        self.ids.lock().unwrap().remove(&self.snapshot_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_untracked_snapshot_is_ready() {
+        let tracker = InProgressSnapshots::default();
+        assert!(tracker.is_ready("pvc-1@snap"));
+    }
+
+    #[test]
+    fn test_snapshot_not_ready_while_operation_runs() {
+        let tracker = InProgressSnapshots::default();
+        let guard = tracker.begin("pvc-1@pvc-clone-pvc-2-1");
+
+        assert!(!tracker.is_ready("pvc-1@pvc-clone-pvc-2-1"));
+        // Other snapshots are unaffected
+        assert!(tracker.is_ready("pvc-1@snap"));
+
+        drop(guard);
+        assert!(tracker.is_ready("pvc-1@pvc-clone-pvc-2-1"));
+    }
+
+    #[test]
+    fn test_guard_settles_on_early_return() {
+        let tracker = InProgressSnapshots::default();
+
+        fn failing_copy(tracker: &InProgressSnapshots) -> Result<(), String> {
+            let _guard = tracker.begin("pvc-1@tmp");
+            Err("send/recv failed".to_string())
+        }
+
+        assert!(failing_copy(&tracker).is_err());
+        assert!(tracker.is_ready("pvc-1@tmp"));
+    }
+}
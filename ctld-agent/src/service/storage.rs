@@ -7,23 +7,91 @@
 //! storage operations.
 
 use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use tokio::sync::{RwLock, Semaphore};
+use tokio::task::JoinHandle;
 use tonic::{Request, Response, Status};
 use tracing::{debug, error, info, instrument, warn};
 
+/// Revision of the agent API reported by GetSystemInfo.
+///
+/// Bump it whenever an RPC or request field is added, so controllers can
+/// tell whether this agent understands it.
+pub const API_VERSION: u32 = 6;
+
 /// Default maximum number of concurrent storage operations
 const DEFAULT_MAX_CONCURRENT_OPS: usize = 10;
 
+/// How often a pool marked read-only or suspended is checked again
+pub const POOL_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Minimum interval between recomputations of the volume size metrics
+const VOLUME_STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// StorageClass parameter grouping NVMeoF namespaces under a shared controller
+const CONTROLLER_GROUP_PARAM: &str = "controllerGroup";
+
+/// StorageClass parameter setting the iSCSI target alias
+const TARGET_ALIAS_PARAM: &str = "targetAlias";
+
+/// StorageClass parameter overriding the agent's iSCSI portal group
+const PORTAL_GROUP_PARAM: &str = "portalGroup";
+
+/// StorageClass parameter overriding the agent's NVMeoF transport group
+const TRANSPORT_GROUP_PARAM: &str = "transportGroup";
+
+/// Prefix of the temporary snapshots taken for PVC-to-PVC clones
+const CLONE_SNAPSHOT_PREFIX: &str = "pvc-clone-";
+
+/// Name prefixes reserved for datasets and snapshots the agent creates itself.
+///
+/// Requested volume and snapshot names may not start with any of these:
+/// - `pvc-clone-`: temporary clone snapshots. DeleteVolume destroys a clone's
+///   origin when it carries this prefix, and the foreign-origin policy trusts
+///   it, so a user snapshot with this name could be destroyed or exempted by
+///   mistake.
+/// - `csi-auto-`: system snapshots, which ListSnapshots hides by default.
+const RESERVED_NAME_PREFIXES: &[&str] = &[CLONE_SNAPSHOT_PREFIX, SYSTEM_SNAPSHOT_PREFIX];
+
+/// Reject a requested volume or snapshot name that uses a reserved prefix
+fn check_reserved_name(kind: &str, name: &str) -> Result<(), Status> {
+    match RESERVED_NAME_PREFIXES
+        .iter()
+        .find(|prefix| name.starts_with(*prefix))
+    {
+        Some(prefix) => Err(Status::invalid_argument(format!(
+            "{} name '{}' uses the prefix '{}', which is reserved for names generated by the driver",
+            kind, name, prefix
+        ))),
+        None => Ok(()),
+    }
+}
+
 use crate::ctl::{
-    AuthConfig, ConfigWriterHandle, CtlError, CtlManager, CtlOptions, ExportType as CtlExportType,
-    IscsiChapAuth, NvmeAuth, spawn_config_writer,
+    AuthConfig, CONTROLLER_GROUP_NQN_PREFIX, ConfigWriterHandle, CtlError, CtlManager, CtlOptions,
+    DEFAULT_CONFIG_WRITE_DEBOUNCE_MS, ExportGroupValidator, ExportType as CtlExportType,
+    IscsiChapAuth, NvmeAuth, PersistedExport, is_target_live, spawn_config_writer,
+    validate_ucl_string,
 };
-use crate::metrics::{self, OperationTimer};
+use crate::http::HealthState;
+use crate::metrics::{self, ExportLabel, OperationTimer};
+use crate::parameters::{ParameterError, Parameters};
+use crate::service::existence_cache::{CachedExistence, ExistenceCache};
+use crate::service::recent_errors::RecentErrors;
+use crate::service::snapshot_progress::InProgressSnapshots;
+use crate::service::volume_locks::VolumeLocks;
 use crate::zfs::{
-    VolumeMetadata as ZfsVolumeMetadata, VolumeMetadataLookup as MissingMetadataLookup, ZfsManager,
+    BACKEND_PARAM, CsiSnapshotInfo, Dataset, ENCRYPTION_PARAM, Encryption, KEY_FORMAT_PARAM,
+    KEY_LOCATION_PARAM, PROVISIONING_MODE_PARAM, QUOTA_PARAM, RECORD_SIZE_PARAM,
+    SYSTEM_SNAPSHOT_PREFIX, VOLBLOCKSIZE_PARAM, VolumeAttachment as ZfsVolumeAttachment,
+    VolumeBackend, VolumeMetadata as ZfsVolumeMetadata,
+    VolumeMetadataLookup as MissingMetadataLookup, ZfsManager, check_quota,
+    compression_from_parameters, encryption_from_parameters, parse_byte_size, parse_record_size,
+    parse_volblocksize, quota_from_parameters, reserves_full_size,
 };
 
 /// Generated protobuf types and service trait
@@ -36,11 +104,46 @@ use proto::{
     AuthCredentials, CloneMode, CreateSnapshotRequest, CreateSnapshotResponse, CreateVolumeRequest,
     CreateVolumeResponse, DeleteSnapshotRequest, DeleteSnapshotResponse, DeleteVolumeRequest,
     DeleteVolumeResponse, ExpandVolumeRequest, ExpandVolumeResponse, ExportType,
-    GetCapacityRequest, GetCapacityResponse, GetSnapshotRequest, GetSnapshotResponse,
-    GetVolumeRequest, GetVolumeResponse, ListSnapshotsRequest, ListSnapshotsResponse,
-    ListVolumesRequest, ListVolumesResponse, Snapshot, Volume,
+    GetCapacityRequest, GetCapacityResponse, GetPoolStatusRequest, GetPoolStatusResponse,
+    GetRecentErrorsRequest, GetRecentErrorsResponse, GetSnapshotRequest, GetSnapshotResponse,
+    GetSystemInfoRequest, GetSystemInfoResponse, GetVolumeRequest, GetVolumeResponse,
+    IsVolumeExportReadyRequest, IsVolumeExportReadyResponse, ListAmbiguousSnapshotsRequest,
+    ListAmbiguousSnapshotsResponse, ListSnapshotsRequest, ListSnapshotsResponse,
+    ListVolumeAttachmentsRequest, ListVolumeAttachmentsResponse, ListVolumesRequest,
+    ListVolumesResponse, ProvisioningMode, SetVolumeAttachmentRequest, SetVolumeAttachmentResponse,
+    Snapshot, UpdateVolumeAuthRequest, UpdateVolumeAuthResponse, Volume, VolumeExistsRequest,
+    VolumeExistsResponse,
 };
 
+/// Whether a dataset with this `refreservation` is thick provisioned
+fn is_thick(refreservation: u64) -> bool {
+    refreservation > 0
+}
+
+/// Report the provisioning mode a volume actually has.
+///
+/// The dataset's refreservation is authoritative: a volume is thick when
+/// space is reserved for it, whatever the request asked for (clones and
+/// copies are created without a reservation). The mode is also echoed in
+/// the parameters for clients that only read those.
+fn report_provisioning(volume: &mut Volume, refreservation: u64) {
+    let mode = if is_thick(refreservation) {
+        ProvisioningMode::Thick
+    } else {
+        ProvisioningMode::Thin
+    };
+    volume.set_provisioning_mode(mode);
+    volume.refreservation_bytes = refreservation as i64;
+    volume.parameters.insert(
+        PROVISIONING_MODE_PARAM.to_string(),
+        match mode {
+            ProvisioningMode::Thick => "thick",
+            _ => "thin",
+        }
+        .to_string(),
+    );
+}
+
 /// Convert proto ExportType to CTL ExportType
 fn to_ctl_export_type(export_type: ExportType) -> Option<CtlExportType> {
     match export_type {
@@ -50,6 +153,16 @@ fn to_ctl_export_type(export_type: ExportType) -> Option<CtlExportType> {
     }
 }
 
+impl From<ExportType> for ExportLabel {
+    fn from(export_type: ExportType) -> Self {
+        match export_type {
+            ExportType::Iscsi => ExportLabel::Iscsi,
+            ExportType::Nvmeof => ExportLabel::Nvmeof,
+            ExportType::Unspecified => ExportLabel::Unspecified,
+        }
+    }
+}
+
 /// Convert CTL ExportType to proto ExportType
 fn ctl_to_proto_export_type(export_type: CtlExportType) -> ExportType {
     match export_type {
@@ -87,37 +200,452 @@ fn proto_to_ctl_auth(auth: Option<&AuthCredentials>) -> AuthConfig {
     }
 }
 
+/// Check NVMeoF credentials against what ctld can enforce.
+///
+/// FreeBSD's ctld has no DH-HMAC-CHAP support for NVMeoF, so requested
+/// secrets are reduced to host-nqn access control. With `strict` the request
+/// is rejected instead of silently getting weaker authentication.
+fn check_nvme_auth_support(auth: &AuthConfig, strict: bool) -> Result<(), Status> {
+    let AuthConfig::NvmeAuth(nvme) = auth else {
+        return Ok(());
+    };
+    if nvme.secret.is_empty() && nvme.dh_group.is_none() {
+        return Ok(());
+    }
+
+    if strict {
+        return Err(Status::invalid_argument(format!(
+            "NVMeoF DH-HMAC-CHAP authentication was requested but ctld does not support it; \
+             only host-nqn access control ({}) can be enforced. Remove the NVMe secret or \
+             disable --strict-auth to accept host-nqn-only access control",
+            nvme.host_nqn
+        )));
+    }
+
+    warn!(
+        host_nqn = %nvme.host_nqn,
+        "NVMeoF DH-HMAC-CHAP requested but unsupported by ctld; \
+         downgrading to host-nqn access control"
+    );
+    Ok(())
+}
+
+/// Check replacement credentials for UpdateVolumeAuth before anything
+/// changes, with the same UCL rules the rendered auth-group must satisfy.
+fn validate_auth_credentials(auth: Option<&AuthCredentials>) -> Result<(), Status> {
+    use proto::auth_credentials::Credentials;
+
+    match auth.and_then(|a| a.credentials.as_ref()) {
+        Some(Credentials::IscsiChap(chap)) => check_chap_credentials(chap),
+        Some(Credentials::NvmeAuth(nvme)) => validate_ucl_string(&nvme.host_nqn, "host NQN")
+            .map_err(|e| Status::invalid_argument(format!("Invalid credentials: {}", e))),
+        // Removing authentication is not a credential update
+        None => Err(Status::invalid_argument("auth credentials are required")),
+    }
+}
+
+/// Validate iSCSI CHAP credentials for safe UCL output.
+///
+/// Mutual CHAP needs both its username and its secret; one without the
+/// other is rejected rather than silently dropping to one-way CHAP.
+fn check_chap_credentials(chap: &proto::IscsiChapCredentials) -> Result<(), Status> {
+    crate::ctl::validate_chap_credentials(&chap.username, &chap.secret)
+        .map_err(|e| Status::invalid_argument(format!("Invalid CHAP credentials: {}", e)))?;
+    match (
+        chap.mutual_username.is_empty(),
+        chap.mutual_secret.is_empty(),
+    ) {
+        (true, true) => Ok(()),
+        (false, false) => {
+            crate::ctl::validate_chap_credentials(&chap.mutual_username, &chap.mutual_secret)
+                .map_err(|e| {
+                    Status::invalid_argument(format!("Invalid mutual CHAP credentials: {}", e))
+                })
+        }
+        _ => Err(Status::invalid_argument(
+            "mutual CHAP requires both a username and a secret",
+        )),
+    }
+}
+
+/// Check that credentials fit the volume's export type. Only iSCSI CHAP can
+/// be updated: ctld has no DH-HMAC-CHAP, so NVMeoF volumes never carry
+/// per-volume credentials.
+fn check_auth_export_type(export_type: ExportType, auth: &AuthConfig) -> Result<(), Status> {
+    match (export_type, auth) {
+        (ExportType::Iscsi, AuthConfig::IscsiChap(_)) => Ok(()),
+        (ExportType::Iscsi, _) => Err(Status::invalid_argument(
+            "iSCSI volumes take iSCSI CHAP credentials",
+        )),
+        _ => Err(Status::invalid_argument(
+            "NVMeoF DH-HMAC-CHAP authentication is not yet supported on FreeBSD; \
+             only iSCSI volumes have credentials to update",
+        )),
+    }
+}
+
+/// What DeleteVolume does with a clone whose origin snapshot was not created
+/// by the driver, i.e. neither a temporary PVC-clone snapshot (`pvc-clone-`
+/// prefix) nor a CSI snapshot (tagged with `user:csi:snapshot_id`), such as a
+/// snapshot taken by hand.
+///
+/// There is no "promote" option: promoting the clone would move the origin
+/// snapshot into the volume being deleted and turn the source into its
+/// dependent, so the delete could never complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ForeignOriginPolicy {
+    /// Delete the clone and leave the origin snapshot untouched
+    #[default]
+    Leave,
+    /// Refuse the delete with FAILED_PRECONDITION
+    Refuse,
+}
+
+impl fmt::Display for ForeignOriginPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForeignOriginPolicy::Leave => write!(f, "leave"),
+            ForeignOriginPolicy::Refuse => write!(f, "refuse"),
+        }
+    }
+}
+
+impl FromStr for ForeignOriginPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "leave" => Ok(ForeignOriginPolicy::Leave),
+            "refuse" => Ok(ForeignOriginPolicy::Refuse),
+            _ => Err(format!(
+                "unknown foreign origin policy '{}': expected 'leave' or 'refuse'",
+                s
+            )),
+        }
+    }
+}
+
+/// How DeleteVolume handles a tracked volume whose dataset and export are
+/// already gone (e.g. a retry after the destroy succeeded).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrphanedMetadataPolicy {
+    /// Drop the metadata and remove temporary clone snapshots left for the
+    /// volume, skipping the steps that need the dataset
+    #[default]
+    Drop,
+    /// Run the full delete, each step of which tolerates the missing dataset
+    Full,
+}
+
+impl fmt::Display for OrphanedMetadataPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrphanedMetadataPolicy::Drop => write!(f, "drop"),
+            OrphanedMetadataPolicy::Full => write!(f, "full"),
+        }
+    }
+}
+
+impl FromStr for OrphanedMetadataPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "drop" => Ok(OrphanedMetadataPolicy::Drop),
+            "full" => Ok(OrphanedMetadataPolicy::Full),
+            _ => Err(format!(
+                "unknown orphaned metadata policy '{}': expected 'drop' or 'full'",
+                s
+            )),
+        }
+    }
+}
+
+/// Apply the foreign-origin policy to a volume about to be deleted.
+///
+/// `origin` is the volume's ZFS origin (`pool/path/source@snap`), if any, and
+/// `origin_tagged` whether it carries a CSI snapshot ID, i.e. the volume was
+/// restored from a VolumeSnapshot.
+/// Origins created by PVC cloning are always fine; they are cleaned up after
+/// the delete.
+fn check_foreign_origin(
+    volume_name: &str,
+    origin: Option<&str>,
+    origin_tagged: bool,
+    policy: ForeignOriginPolicy,
+) -> Result<(), Status> {
+    let Some(origin) = origin else {
+        return Ok(());
+    };
+    let snap_name = origin.rsplit('@').next().unwrap_or(origin);
+    if snap_name.starts_with(CLONE_SNAPSHOT_PREFIX) || origin_tagged {
+        return Ok(());
+    }
+
+    match policy {
+        ForeignOriginPolicy::Leave => {
+            info!(
+                volume = %volume_name,
+                origin = %origin,
+                "Volume is a clone of a snapshot not created by the driver; \
+                 the origin snapshot will be left in place"
+            );
+            Ok(())
+        }
+        ForeignOriginPolicy::Refuse => {
+            warn!(
+                volume = %volume_name,
+                origin = %origin,
+                "Refusing to delete clone of a snapshot not created by the driver"
+            );
+            Err(Status::failed_precondition(format!(
+                "Cannot delete volume '{}': it is a clone of snapshot '{}', which was not \
+                 created by this driver. Promote or destroy it manually (zfs promote / \
+                 zfs destroy), or run ctld-agent with --foreign-origin-policy=leave",
+                volume_name, origin
+            )))
+        }
+    }
+}
+
+/// Secret values carried by a request, redacted from recorded errors
+fn request_secrets(auth: Option<&AuthCredentials>) -> Vec<String> {
+    use proto::auth_credentials::Credentials;
+
+    match auth.and_then(|a| a.credentials.as_ref()) {
+        None => Vec::new(),
+        Some(Credentials::IscsiChap(chap)) => vec![chap.secret.clone(), chap.mutual_secret.clone()],
+        Some(Credentials::NvmeAuth(nvme)) => vec![nvme.secret.clone()],
+    }
+}
+
 /// Parse CTL options from request parameters.
 ///
 /// Supports the following StorageClass parameters:
 /// - `blockSize`: Logical block size (512 or 4096)
 /// - `physicalBlockSize`: Physical block hint
 /// - `enableUnmap`: Enable TRIM/discard ("true" or "false")
-fn parse_ctl_options(params: &HashMap<String, String>) -> CtlOptions {
+/// - `controllerGroup`: Shared NVMeoF controller for the namespace
+/// - `targetAlias`: Alias of the iSCSI target
+/// - `portalGroup`/`transportGroup`: Group the target is reachable through
+/// - `removable`: Present the LUN/namespace as removable media
+///
+/// Options are also re-read from stored metadata when exports are restored,
+/// so invalid values are ignored here rather than failing the export; new
+/// volumes are validated by [`check_ctl_options`].
+fn parse_ctl_options(raw: &HashMap<String, String>) -> CtlOptions {
+    let params = Parameters::new(raw);
+
     let blocksize = params
-        .get("blockSize")
-        .and_then(|v| v.parse::<u32>().ok())
+        .get_u32("blockSize")
+        .ok()
+        .flatten()
         .filter(|&bs| bs == 512 || bs == 4096);
 
-    let pblocksize = params
-        .get("physicalBlockSize")
-        .and_then(|v| v.parse::<u32>().ok());
+    let pblocksize = params.get_u32("physicalBlockSize").ok().flatten();
 
-    let unmap = params
-        .get("enableUnmap")
-        .and_then(|v| match v.to_lowercase().as_str() {
-            "true" | "1" | "on" | "yes" => Some(true),
-            "false" | "0" | "off" | "no" => Some(false),
-            _ => None,
-        });
+    let unmap = params.get_bool("enableUnmap").ok().flatten();
+
+    let removable = params.get_bool("removable").ok().flatten();
+
+    let owned = |key| params.get_non_empty(key).map(str::to_string);
+    let controller_group = owned(CONTROLLER_GROUP_PARAM);
+    let target_alias = owned(TARGET_ALIAS_PARAM);
+    let portal_group = owned(PORTAL_GROUP_PARAM);
+    let transport_group = owned(TRANSPORT_GROUP_PARAM);
+
+    let file_backed = VolumeBackend::from_parameters(raw) == Ok(VolumeBackend::File);
 
     CtlOptions {
         blocksize,
         pblocksize,
         unmap,
+        removable,
+        controller_group,
+        target_alias,
+        portal_group,
+        transport_group,
+        file_backed,
+        ..Default::default()
+    }
+}
+
+/// Validate the CTL options [`parse_ctl_options`] reads.
+///
+/// Run at CreateVolume so a value that does not parse is rejected instead of
+/// silently falling back to ctld's default.
+fn check_ctl_options(raw: &HashMap<String, String>) -> Result<(), ParameterError> {
+    let params = Parameters::new(raw);
+    if let Some(blocksize) = params.get_u32("blockSize")?
+        && blocksize != 512
+        && blocksize != 4096
+    {
+        return Err(ParameterError {
+            key: "blockSize".to_string(),
+            value: blocksize.to_string(),
+            expected: "512 or 4096".to_string(),
+        });
+    }
+    params.get_u32("physicalBlockSize")?;
+    params.get_bool("enableUnmap")?;
+    params.get_bool("removable")?;
+    Ok(())
+}
+
+/// Validate the `portalGroup`/`transportGroup` parameters for `export_type`.
+///
+/// Returns the group overriding the agent's default, if any. Whether the
+/// group exists in ctl.conf is checked separately.
+fn check_group_override(
+    params: &HashMap<String, String>,
+    export_type: ExportType,
+) -> Result<Option<String>, Status> {
+    let (param, other, other_type) = match export_type {
+        ExportType::Nvmeof => (TRANSPORT_GROUP_PARAM, PORTAL_GROUP_PARAM, "iSCSI"),
+        _ => (PORTAL_GROUP_PARAM, TRANSPORT_GROUP_PARAM, "NVMeoF"),
+    };
+    let params = Parameters::new(params);
+    if params.get_non_empty(other).is_some() {
+        return Err(Status::invalid_argument(format!(
+            "{} is only supported for {} exports",
+            other, other_type
+        )));
+    }
+
+    match params.get_non_empty(param) {
+        Some(group) => {
+            validate_ucl_string(group, param)
+                .map_err(|e| Status::invalid_argument(e.to_string()))?;
+            Ok(Some(group.to_string()))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Rebuild minimal metadata for a volume whose metadata JSON is corrupt.
+///
+/// Only succeeds when the volume has a regular (non-reserved) name and
+/// exactly one persisted export whose target sits under the configured
+/// base IQN/NQN, so the export type, target and LUN can be taken from it.
+/// Anything ambiguous is refused and the volume stays unmanaged.
+fn reconstruct_metadata(
+    volume_name: &str,
+    exports: &[PersistedExport],
+    base_iqn: &str,
+    base_nqn: &str,
+) -> Result<ZfsVolumeMetadata, String> {
+    if check_reserved_name("volume", volume_name).is_err() {
+        return Err("name uses a reserved prefix".to_string());
+    }
+
+    let mut matching = exports.iter().filter(|e| e.volume_name == volume_name);
+    let export = match (matching.next(), matching.next()) {
+        (Some(export), None) => export,
+        (None, _) => return Err("no persisted export".to_string()),
+        (Some(_), Some(_)) => return Err("multiple persisted exports".to_string()),
+    };
+
+    let base = match export.export_type {
+        CtlExportType::Iscsi => base_iqn,
+        CtlExportType::Nvmeof => base_nqn,
+    };
+    let suffix = export
+        .target_name
+        .strip_prefix(base)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .ok_or_else(|| {
+            format!(
+                "target '{}' is not under base name '{}'",
+                export.target_name, base
+            )
+        })?;
+
+    let mut parameters = HashMap::new();
+    if suffix != volume_name {
+        // NVMeoF namespaces may share a grouped controller
+        match (
+            export.export_type,
+            suffix.strip_prefix(CONTROLLER_GROUP_NQN_PREFIX),
+        ) {
+            (CtlExportType::Nvmeof, Some(group)) => {
+                parameters.insert(CONTROLLER_GROUP_PARAM.to_string(), group.to_string());
+            }
+            _ => {
+                return Err(format!(
+                    "target '{}' does not match the volume name",
+                    export.target_name
+                ));
+            }
+        }
+    }
+
+    let auth_group = match export.auth_group.as_str() {
+        "" | "no-authentication" => None,
+        group => Some(group.to_string()),
+    };
+
+    Ok(ZfsVolumeMetadata::new(
+        export.export_type,
+        export.target_name.clone(),
+        Some(export.lun_id),
+        None,
+        parameters,
+        unix_timestamp_now(),
+        auth_group,
+    ))
+}
+
+/// Whether the volume size metrics should be recomputed at `now`.
+///
+/// Claims the refresh by recording `now`, so concurrent callers within
+/// [`VOLUME_STATS_INTERVAL`] don't list the volumes again.
+fn volume_stats_due(last_refresh: &Mutex<Option<Instant>>, now: Instant) -> bool {
+    let mut last_refresh = last_refresh.lock().unwrap_or_else(|e| e.into_inner());
+    match *last_refresh {
+        Some(last) if now.saturating_duration_since(last) < VOLUME_STATS_INTERVAL => false,
+        _ => {
+            *last_refresh = Some(now);
+            true
+        }
     }
 }
 
+/// Source volume of the snapshot that keeps `name` when several volumes
+/// have a snapshot called `name`, if it is not `source_volume_id`.
+///
+/// Used when snapshot names must be unique across all volumes. The snapshot
+/// is taken before this check, so concurrent requests for the same name all
+/// see each other; the oldest snapshot (ties broken by volume ID) keeps the
+/// name and the others are rolled back.
+fn conflicting_snapshot_source<'a>(
+    snapshots: &'a [CsiSnapshotInfo],
+    source_volume_id: &str,
+    name: &str,
+) -> Option<&'a str> {
+    snapshots
+        .iter()
+        .filter(|s| s.name == name)
+        .min_by(|a, b| {
+            (a.creation_time, &a.source_volume_id).cmp(&(b.creation_time, &b.source_volume_id))
+        })
+        .map(|s| s.source_volume_id.as_str())
+        .filter(|&owner| owner != source_volume_id)
+}
+
+/// Snapshots returned by ListSnapshots: those of `source_volume_id` (all
+/// volumes when empty), leaving out system snapshots unless requested
+fn listed_snapshots(
+    snapshots: Vec<CsiSnapshotInfo>,
+    source_volume_id: &str,
+    include_system: bool,
+) -> Vec<CsiSnapshotInfo> {
+    snapshots
+        .into_iter()
+        .filter(|s| source_volume_id.is_empty() || s.source_volume_id == source_volume_id)
+        .filter(|s| include_system || !s.system)
+        .collect()
+}
+
 /// Get current Unix timestamp in seconds
 fn unix_timestamp_now() -> i64 {
     SystemTime::now()
@@ -126,28 +654,48 @@ fn unix_timestamp_now() -> i64 {
         .unwrap_or(0)
 }
 
-/// Apply pagination to a list of items
+/// Prefix of pagination tokens carrying the id of the last returned item
+const CURSOR_TOKEN_PREFIX: &str = "after:";
+
+/// Apply pagination to a list of items.
+///
+/// Items are sorted by `id` and `next_token` names the last returned id, so
+/// the next page resumes after it even if items were created or deleted in
+/// between. Plain numeric tokens (an offset, as issued by older agents) are
+/// still accepted.
 fn paginate<T>(
-    items: Vec<T>,
+    mut items: Vec<T>,
     max_entries: i32,
     starting_token: &str,
+    id: impl Fn(&T) -> &str,
 ) -> Result<(Vec<T>, String), Status> {
+    items.sort_by(|a, b| id(a).cmp(id(b)));
+
     let max_entries = if max_entries > 0 {
         max_entries as usize
     } else {
         items.len()
     };
 
-    let start_idx = if !starting_token.is_empty() {
+    let start_idx = if starting_token.is_empty() {
+        0
+    } else if let Some(last_id) = starting_token.strip_prefix(CURSOR_TOKEN_PREFIX) {
+        items.partition_point(|item| id(item) <= last_id)
+    } else {
         starting_token
             .parse::<usize>()
             .map_err(|_| Status::invalid_argument("Invalid starting_token"))?
-    } else {
-        0
     };
 
     let total_len = items.len();
-    let end_idx = std::cmp::min(start_idx + max_entries, total_len);
+    let start_idx = std::cmp::min(start_idx, total_len);
+    let end_idx = std::cmp::min(start_idx.saturating_add(max_entries), total_len);
+
+    let next_token = if end_idx < total_len {
+        format!("{}{}", CURSOR_TOKEN_PREFIX, id(&items[end_idx - 1]))
+    } else {
+        String::new()
+    };
 
     let paginated: Vec<T> = items
         .into_iter()
@@ -155,13 +703,250 @@ fn paginate<T>(
         .take(end_idx - start_idx)
         .collect();
 
-    let next_token = if end_idx < total_len {
-        end_idx.to_string()
-    } else {
-        String::new()
+    Ok((paginated, next_token))
+}
+
+/// Check that an existing volume satisfies a CreateVolume retry.
+///
+/// An existing volume at least as large as requested is accepted (it may have
+/// been expanded since); a smaller one is ALREADY_EXISTS per CSI spec.
+fn check_existing_volume_size(
+    name: &str,
+    existing: Dataset,
+    requested_size: u64,
+) -> Result<Dataset, Status> {
+    let existing_size = existing.volsize.unwrap_or(0);
+
+    if existing_size < requested_size {
+        return Err(Status::already_exists(format!(
+            "Volume '{}' exists with size {} bytes but {} bytes was requested. \
+             Existing volume is smaller than requested.",
+            name, existing_size, requested_size
+        )));
+    }
+
+    info!(
+        volume = %name,
+        existing_size = existing_size,
+        requested_size = requested_size,
+        "Existing volume matches requested parameters (idempotent success)"
+    );
+    Ok(existing)
+}
+
+/// Status for a failed ZFS operation.
+///
+/// A read-only or suspended pool is reported as Unavailable so the CO backs
+/// off and retries, as is a copy aborted by agent shutdown; anything else is
+/// an internal error.
+fn zfs_error_status(context: &str, e: &crate::zfs::ZfsError) -> Status {
+    match e {
+        crate::zfs::ZfsError::PoolUnavailable(_) => Status::unavailable(format!(
+            "{}: {}; retry once the pool is writable",
+            context, e
+        )),
+        crate::zfs::ZfsError::Aborted(_) => Status::unavailable(format!("{}: {}", context, e)),
+        _ => Status::internal(format!("{}: {}", context, e)),
+    }
+}
+
+/// Parse a `--max-volume-size` value: bytes, optionally with a binary
+/// suffix (`K`, `M`, `G`, `T`, also spelled `Ki`, `Gi`, ...).
+pub fn parse_volume_size_limit(value: &str) -> Result<u64, String> {
+    parse_byte_size(value)
+        .filter(|&bytes| bytes > 0)
+        .ok_or_else(|| {
+            format!(
+                "invalid volume size limit '{}': expected a positive size such as 500G or 2Ti",
+                value
+            )
+        })
+}
+
+/// Reject a create or expand whose size exceeds the configured cap.
+///
+/// This is a policy limit, independent of the pool's free space.
+fn check_max_volume_size(field: &str, size_bytes: u64, max: Option<u64>) -> Result<(), Status> {
+    match max {
+        Some(max) if size_bytes > max => Err(Status::out_of_range(format!(
+            "{} {} exceeds the maximum volume size of {} bytes",
+            field, size_bytes, max
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Check that the pool can back the reservation of a thick volume expanded
+/// to `new_size` bytes. Only the growth beyond the `reserved` bytes it
+/// already holds is new; `available` is net of existing reservations.
+fn check_expand_reservation(
+    volume_id: &str,
+    reserved: u64,
+    new_size: u64,
+    available: u64,
+) -> Result<(), Status> {
+    let additional = new_size.saturating_sub(reserved);
+    if additional > available {
+        return Err(Status::resource_exhausted(format!(
+            "expanding thick volume '{}' to {} bytes needs {} more bytes of reserved space, \
+             but only {} bytes are available",
+            volume_id, new_size, additional, available
+        )));
+    }
+    Ok(())
+}
+
+/// Validate a requested `volblocksize`.
+///
+/// It only applies to new, empty zvols: file-backed volumes use `recordSize`
+/// and volumes populated from a content source keep their origin's block
+/// size. A StorageClass may still repeat that size; `origin` holds the
+/// parameters of the tracked origin, if known.
+fn check_volblocksize(
+    params: &HashMap<String, String>,
+    backend: VolumeBackend,
+    has_content_source: bool,
+    origin: Option<&HashMap<String, String>>,
+) -> Result<(), Status> {
+    let Some(value) = Parameters::new(params).get(VOLBLOCKSIZE_PARAM) else {
+        return Ok(());
     };
+    if backend == VolumeBackend::File {
+        return Err(Status::invalid_argument(format!(
+            "{} requires {}=zvol; use {} for file-backed volumes",
+            VOLBLOCKSIZE_PARAM, BACKEND_PARAM, RECORD_SIZE_PARAM
+        )));
+    }
+    let requested = parse_volblocksize(value).map_err(Status::invalid_argument)?;
+    if has_content_source {
+        let inherited = origin
+            .and_then(|origin| origin.get(VOLBLOCKSIZE_PARAM))
+            .and_then(|value| parse_volblocksize(value).ok());
+        if inherited != Some(requested) {
+            return Err(Status::invalid_argument(format!(
+                "{} cannot be set for a volume created from a snapshot, volume or image \
+                 unless it matches the origin's: the block size is inherited from the origin",
+                VOLBLOCKSIZE_PARAM
+            )));
+        }
+    }
+    Ok(())
+}
 
-    Ok((paginated, next_token))
+/// Check the requested encryption of a volume created from a tracked
+/// snapshot or volume against its origin's.
+///
+/// Clones of an encrypted origin must repeat the origin's key parameters so
+/// a copy is never silently decrypted. A linked clone shares its origin's
+/// encryption root and cannot be encrypted on its own.
+fn check_origin_encryption(
+    requested: Option<&Encryption>,
+    origin: Option<&Encryption>,
+    linked: bool,
+) -> Result<(), Status> {
+    match origin {
+        Some(origin) if requested != Some(origin) => Err(Status::invalid_argument(format!(
+            "source volume is encrypted: set {}=on with its {} and {} to clone it",
+            ENCRYPTION_PARAM, KEY_FORMAT_PARAM, KEY_LOCATION_PARAM
+        ))),
+        None if linked && requested.is_some() => Err(Status::invalid_argument(format!(
+            "source volume is not encrypted and a linked clone inherits that; \
+             use COPY clone mode to create an encrypted copy ({}=on)",
+            ENCRYPTION_PARAM
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Parameters to persist for a new volume: the request's, plus the origin's
+/// `volblocksize` for clones so the inherited block size stays on record.
+fn with_inherited_volblocksize(
+    mut params: HashMap<String, String>,
+    origin: Option<&HashMap<String, String>>,
+) -> HashMap<String, String> {
+    if let Some(value) = origin.and_then(|origin| origin.get(VOLBLOCKSIZE_PARAM)) {
+        params.insert(VOLBLOCKSIZE_PARAM.to_string(), value.clone());
+    }
+    params
+}
+
+/// NotFound for a CreateVolume content source that does not exist.
+///
+/// `snapshot` is the named snapshot and whether it exists; volume clones
+/// pass `None`, as their snapshot is taken during the clone. Checked before
+/// cloning so a missing source is reported as such instead of as the
+/// internal error the failed `zfs clone`/`zfs send` would produce.
+fn check_content_source(
+    source_volume: &str,
+    volume_exists: bool,
+    snapshot: Option<(&str, bool)>,
+) -> Result<(), Status> {
+    if !volume_exists {
+        return Err(Status::not_found(format!(
+            "content source volume '{}' not found",
+            source_volume
+        )));
+    }
+    match snapshot {
+        Some((snap_name, false)) => Err(Status::not_found(format!(
+            "content source snapshot '{}@{}' not found",
+            source_volume, snap_name
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// The attachment to store in a volume's metadata; none once no node holds
+/// the volume.
+fn zfs_attachment(attachment: proto::VolumeAttachment) -> Option<ZfsVolumeAttachment> {
+    let sorted = |mut names: Vec<String>| {
+        names.sort();
+        names.dedup();
+        names
+    };
+    let nodes = sorted(attachment.node_ids);
+    (!nodes.is_empty()).then(|| ZfsVolumeAttachment {
+        nodes,
+        multi_node: attachment.multi_node,
+        initiator_names: sorted(attachment.initiator_names),
+        host_nqns: sorted(attachment.host_nqns),
+    })
+}
+
+/// Initiators allowed to connect to a volume's export: those of the nodes it
+/// is published to, or anyone (empty) when it is not published or some
+/// node's initiator is unknown
+fn allowed_initiators(
+    attachment: Option<&ZfsVolumeAttachment>,
+    export_type: CtlExportType,
+) -> Vec<String> {
+    attachment
+        .map(|a| a.initiators(export_type).to_vec())
+        .unwrap_or_default()
+}
+
+/// Whether a tracked volume being deleted is only left in memory: its
+/// dataset is gone and it has no export, so dropping the metadata completes
+/// the delete. A failed existence check and the `full` policy take the full
+/// cleanup path.
+fn is_orphaned_metadata(
+    dataset_exists: &std::result::Result<bool, crate::zfs::ZfsError>,
+    has_export: bool,
+    policy: OrphanedMetadataPolicy,
+) -> bool {
+    policy == OrphanedMetadataPolicy::Drop && matches!(dataset_exists, Ok(false)) && !has_export
+}
+
+/// Whether `snapshot_path` (`pool/csi/source@snap`) is a temporary snapshot
+/// taken by PVC cloning for `volume_name`, named
+/// `pvc-clone-<volume_name>-<millis>`
+fn is_clone_snapshot_for(snapshot_path: &str, volume_name: &str) -> bool {
+    snapshot_path
+        .rsplit_once('@')
+        .and_then(|(_, snap)| snap.strip_prefix(CLONE_SNAPSHOT_PREFIX))
+        .and_then(|rest| rest.strip_prefix(volume_name))
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|stamp| !stamp.is_empty() && stamp.bytes().all(|b| b.is_ascii_digit()))
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -231,73 +1016,535 @@ fn volume_metadata_from_zfs(
         })?,
         parameters: zfs_meta.parameters.clone(),
         auth,
+        state: VolumeState::Exported,
+        allowed_initiators: allowed_initiators(zfs_meta.attachment.as_ref(), zfs_meta.export_type),
     })
 }
 
-/// Internal tracking of volume metadata
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct VolumeMetadata {
-    /// Volume ID (same as name for now)
-    id: String,
-    /// Volume name
-    name: String,
-    /// Export type (iSCSI or NVMeoF)
-    export_type: ExportType,
-    /// Target name (IQN or NQN)
-    target_name: String,
-    /// LUN ID
-    lun_id: i32,
-    /// Additional parameters
-    parameters: HashMap<String, String>,
-    /// Authentication configuration
-    auth: AuthConfig,
-}
-
-/// gRPC Storage Agent service
+/// Lifecycle state of a tracked volume
 ///
-/// Uses a semaphore to limit concurrent operations and prevent overload.
-/// When the semaphore is exhausted, new requests will receive ResourceExhausted.
-pub struct StorageService {
-    /// ZFS volume manager
-    zfs: Arc<RwLock<ZfsManager>>,
-    /// Unified CTL manager (handles both iSCSI and NVMeoF)
-    ctl: Arc<RwLock<CtlManager>>,
-    /// Handle to the serialized config writer task
-    config_writer: ConfigWriterHandle,
-    /// Volume metadata tracking
-    volumes: Arc<RwLock<HashMap<String, VolumeMetadata>>>,
-    // Note: Snapshot metadata is stored in ZFS properties and queried directly.
-    // No in-memory cache needed - ZFS is the single source of truth.
-    /// Semaphore for rate limiting concurrent operations
-    ops_semaphore: Arc<Semaphore>,
-    /// Maximum concurrent operations (for error messages)
-    max_concurrent_ops: usize,
+/// Every mutation (create, export, config write, delete) moves the volume
+/// through these states explicitly, so a partially failed operation leaves
+/// behind a state that reconciliation knows how to recover from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VolumeState {
+    /// ZFS volume exists; export and config write are in progress
+    Creating,
+    /// Exported and persisted in the CTL config
+    Exported,
+    /// Delete in progress; export removal and config write are pending
+    Unexporting,
+    /// Export removed and persisted; ZFS volume destruction is pending
+    Deleting,
+    /// Export or config write failed during create
+    Failed,
 }
 
-impl StorageService {
-    /// Create a new StorageService with default rate limiting (10 concurrent ops)
-    pub fn new(zfs: Arc<RwLock<ZfsManager>>, ctl: Arc<RwLock<CtlManager>>) -> Self {
-        Self::with_concurrency_limit(zfs, ctl, DEFAULT_MAX_CONCURRENT_OPS)
+impl VolumeState {
+    /// Whether the lifecycle allows moving from `self` to `next`.
+    ///
+    /// Staying in the same state is always allowed so retried operations are
+    /// idempotent.
+    fn can_transition_to(self, next: VolumeState) -> bool {
+        use VolumeState::*;
+
+        self == next
+            || matches!(
+                (self, next),
+                (Creating, Exported)
+                    | (Creating, Failed)
+                    | (Failed, Creating)
+                    | (Failed, Exported)
+                    | (Failed, Unexporting)
+                    | (Exported, Unexporting)
+                    | (Unexporting, Deleting)
+            )
     }
 
-    /// Create a new StorageService with configurable concurrency limit
-    pub fn with_concurrency_limit(
-        zfs: Arc<RwLock<ZfsManager>>,
+    /// State a volume settles in once its reconcile action has been persisted.
+    fn reconciled(self) -> VolumeState {
+        match self {
+            VolumeState::Creating | VolumeState::Exported | VolumeState::Failed => {
+                VolumeState::Exported
+            }
+            VolumeState::Unexporting | VolumeState::Deleting => VolumeState::Deleting,
+        }
+    }
+}
+
+impl std::fmt::Display for VolumeState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VolumeState::Creating => write!(f, "creating"),
+            VolumeState::Exported => write!(f, "exported"),
+            VolumeState::Unexporting => write!(f, "unexporting"),
+            VolumeState::Deleting => write!(f, "deleting"),
+            VolumeState::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Refuse to use a snapshot whose CSI ID is on several snapshots.
+///
+/// Deleting or restoring any of them could pick the wrong data, so the
+/// condition is logged with every path and counted for alerting; an operator
+/// has to resolve it by hand (see ListAmbiguousSnapshots). `action` names
+/// what was refused, e.g. "delete".
+fn ambiguous_snapshot_error(snapshot_id: &str, paths: &[String], action: &str) -> Status {
+    error!(
+        snapshot_id = %snapshot_id,
+        paths = ?paths,
+        "Several snapshots carry the same CSI snapshot ID; refusing to {}",
+        action
+    );
+    metrics::record_ambiguous_snapshot();
+    Status::failed_precondition(format!(
+        "Found {} snapshots with ID '{}' ({}) - refusing to {}. \
+         This indicates a bug or data corruption; remove or retag the wrong one by hand.",
+        paths.len(),
+        snapshot_id,
+        paths.join(", "),
+        action
+    ))
+}
+
+/// Where a content source snapshot lives according to a search by its CSI
+/// ID, used once it is no longer under its source volume (e.g. after a
+/// clone promotion). `locate` splits a snapshot path into volume and
+/// snapshot name, or returns `None` outside the managed volumes.
+fn content_source_location(
+    snapshot_id: &str,
+    found: crate::zfs::FindSnapshotResult,
+    locate: impl FnOnce(&str) -> Option<(String, String)>,
+) -> Result<(String, String), Status> {
+    use crate::zfs::FindSnapshotResult;
+
+    match found {
+        FindSnapshotResult::Found(path) => {
+            let location = locate(&path).ok_or_else(|| {
+                Status::failed_precondition(format!(
+                    "content source snapshot '{}' is at '{}', outside the managed volumes",
+                    snapshot_id, path
+                ))
+            })?;
+            info!(
+                snapshot_id = %snapshot_id,
+                path = %path,
+                "Content source snapshot moved by clone promotion"
+            );
+            Ok(location)
+        }
+        FindSnapshotResult::Ambiguous(paths) => Err(ambiguous_snapshot_error(
+            snapshot_id,
+            &paths,
+            "restore from it",
+        )),
+        FindSnapshotResult::NotFound => Err(Status::not_found(format!(
+            "content source snapshot '{}' not found",
+            snapshot_id
+        ))),
+    }
+}
+
+/// What happens to a PVC clone's temporary snapshot once the clone attempt ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TempSnapshotAction {
+    /// Destroy it: the clone failed, or COPY mode no longer needs it
+    Delete,
+    /// Keep it on the source: the LINKED clone depends on it
+    Keep,
+    /// Promote the LINKED clone, moving the snapshot (and the dependency)
+    /// from the source to the clone
+    PromoteClone,
+}
+
+/// Clone mode of a content source that leaves it unspecified, as documented
+/// on `CLONE_MODE_UNSPECIFIED`
+const DEFAULT_CLONE_MODE: CloneMode = CloneMode::Linked;
+
+/// Clone mode a request asks for, resolving an unspecified or unknown mode
+/// to [`DEFAULT_CLONE_MODE`]
+fn requested_clone_mode(clone_mode: i32) -> CloneMode {
+    match CloneMode::try_from(clone_mode) {
+        Ok(CloneMode::Unspecified) | Err(_) => DEFAULT_CLONE_MODE,
+        Ok(mode) => mode,
+    }
+}
+
+/// Decide what to do with a PVC clone's temporary snapshot
+fn temp_snapshot_action(
+    clone_succeeded: bool,
+    clone_mode: CloneMode,
+    promote_linked_clones: bool,
+) -> TempSnapshotAction {
+    match (clone_succeeded, clone_mode) {
+        (false, _) | (true, CloneMode::Copy) => TempSnapshotAction::Delete,
+        (true, _) if promote_linked_clones => TempSnapshotAction::PromoteClone,
+        (true, _) => TempSnapshotAction::Keep,
+    }
+}
+
+/// Recovery action taken for a volume during export reconciliation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReconcileAction {
+    /// Nothing to do
+    Skip,
+    /// Export is missing and must be (re-)created
+    Export,
+    /// Export is still present for a volume being deleted and must be removed
+    Unexport,
+    /// CTL state is already correct but may not have been persisted
+    WriteConfig,
+}
+
+/// Decide how to reconcile a volume given its lifecycle state and whether
+/// the CTL manager currently holds an export for it.
+fn reconcile_action(state: VolumeState, has_export: bool) -> ReconcileAction {
+    match (state, has_export) {
+        // An in-flight CreateVolume owns the export; the CO retries it on failure
+        (VolumeState::Creating, _) => ReconcileAction::Skip,
+        (VolumeState::Exported, true) => ReconcileAction::Skip,
+        (VolumeState::Exported, false) | (VolumeState::Failed, false) => ReconcileAction::Export,
+        // Export exists but the config write that followed it failed
+        (VolumeState::Failed, true) => ReconcileAction::WriteConfig,
+        (VolumeState::Unexporting, true) | (VolumeState::Deleting, true) => {
+            ReconcileAction::Unexport
+        }
+        // Export removed but the config write that followed it failed
+        (VolumeState::Unexporting, false) => ReconcileAction::WriteConfig,
+        (VolumeState::Deleting, false) => ReconcileAction::Skip,
+    }
+}
+
+/// What happened to a single volume during reconciliation
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ReconcileOutcome {
+    /// Export state was repaired
+    Reconciled,
+    /// Left alone; carries the reason
+    Skipped(String),
+    /// Could not be repaired; carries the error
+    Failed(String),
+}
+
+/// Per-volume accounting for one `reconcile_exports` pass
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReconcileSummary {
+    /// Volumes whose export state was repaired
+    pub reconciled: Vec<String>,
+    /// Volumes already consistent with their lifecycle state
+    pub unchanged: usize,
+    /// Volumes left alone, with the reason
+    pub skipped: Vec<(String, String)>,
+    /// Volumes that need attention, with the error
+    pub failed: Vec<(String, String)>,
+}
+
+impl ReconcileSummary {
+    fn record(&mut self, vol_name: &str, outcome: ReconcileOutcome) {
+        match outcome {
+            ReconcileOutcome::Reconciled => self.reconciled.push(vol_name.to_string()),
+            ReconcileOutcome::Skipped(reason) => self.skipped.push((vol_name.to_string(), reason)),
+            ReconcileOutcome::Failed(error) => self.failed.push((vol_name.to_string(), error)),
+        }
+    }
+
+    /// The repaired exports were never persisted; count them as failed
+    fn fail_reconciled(&mut self, error: &str) {
+        for vol_name in self.reconciled.drain(..) {
+            self.failed.push((vol_name, error.to_string()));
+        }
+    }
+
+    /// Log the totals, then one line per volume that needs attention.
+    /// A pass that found nothing to do is only logged at debug level.
+    fn log(&self) {
+        if self.reconciled.is_empty() && self.skipped.is_empty() && self.failed.is_empty() {
+            debug!(
+                unchanged = self.unchanged,
+                "Export reconciliation found nothing to do"
+            );
+            return;
+        }
+        info!(
+            reconciled = self.reconciled.len(),
+            unchanged = self.unchanged,
+            skipped = self.skipped.len(),
+            failed = self.failed.len(),
+            "Export reconciliation complete"
+        );
+        for (vol_name, reason) in &self.skipped {
+            info!("Reconciliation skipped '{}': {}", vol_name, reason);
+        }
+        for (vol_name, error) in &self.failed {
+            warn!("Reconciliation failed for '{}': {}", vol_name, error);
+        }
+    }
+
+    fn report(&self) {
+        metrics::set_reconcile_results(
+            self.reconciled.len(),
+            self.unchanged,
+            self.skipped.len(),
+            self.failed.len(),
+        );
+    }
+}
+
+/// Capacity of a zvol: the listed volsize, or an explicit re-query when the
+/// listing came back without one.
+///
+/// Only reports 0 (logged as an error and counted) when the re-query also
+/// fails to produce a size, so a transient parse problem doesn't tell
+/// Kubernetes the volume has no capacity.
+async fn resolve_volsize<F, Fut>(dataset: &str, listed: Option<u64>, requery: F) -> u64
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = crate::zfs::Result<Option<u64>>>,
+{
+    if let Some(volsize) = listed {
+        return volsize;
+    }
+
+    warn!(dataset = %dataset, "zvol listing missing volsize - re-querying");
+    let reason = match requery().await {
+        Ok(Some(volsize)) => return volsize,
+        Ok(None) => "volsize property absent".to_string(),
+        Err(e) => e.to_string(),
+    };
+
+    error!(
+        dataset = %dataset,
+        reason = %reason,
+        "zvol volsize unavailable - reporting 0 capacity"
+    );
+    metrics::record_volsize_missing();
+    0
+}
+
+/// Internal tracking of volume metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct VolumeMetadata {
+    /// Volume ID (same as name for now)
+    id: String,
+    /// Volume name
+    name: String,
+    /// Export type (iSCSI or NVMeoF)
+    export_type: ExportType,
+    /// Target name (IQN or NQN)
+    target_name: String,
+    /// LUN ID
+    lun_id: i32,
+    /// Additional parameters
+    parameters: HashMap<String, String>,
+    /// Authentication configuration
+    auth: AuthConfig,
+    /// Lifecycle state
+    state: VolumeState,
+    /// Initiators admitted to the export (empty: any), from the attachment
+    allowed_initiators: Vec<String>,
+}
+
+/// gRPC Storage Agent service
+///
+/// Uses a semaphore to limit concurrent operations and prevent overload.
+/// When the semaphore is exhausted, new requests will receive ResourceExhausted.
+pub struct StorageService {
+    /// ZFS volume manager
+    zfs: Arc<RwLock<ZfsManager>>,
+    /// Unified CTL manager (handles both iSCSI and NVMeoF)
+    ctl: Arc<RwLock<CtlManager>>,
+    /// Handle to the serialized config writer task
+    config_writer: ConfigWriterHandle,
+    /// Volume metadata tracking
+    volumes: Arc<RwLock<HashMap<String, VolumeMetadata>>>,
+    /// Short-lived existence cache for create/delete retries
+    existence_cache: ExistenceCache,
+    /// URL schemes accepted for `image_url` content sources
+    image_url_schemes: Vec<String>,
+    /// Reject NVMeoF auth that would be downgraded to host-nqn only
+    strict_auth: bool,
+    /// Rebuild corrupt volume metadata from persisted exports on restore
+    repair_corrupt_metadata: bool,
+    /// Reject snapshot names already used on any other volume
+    globally_unique_snapshot_names: bool,
+    /// DeleteVolume handling of clones with a non-driver origin snapshot
+    foreign_origin_policy: ForeignOriginPolicy,
+    /// DeleteVolume handling of metadata whose dataset is already gone
+    orphaned_metadata_policy: OrphanedMetadataPolicy,
+    /// Promote LINKED PVC clones right after they are created
+    promote_linked_clones: bool,
+    /// Last few failed mutating operations, for GetRecentErrors
+    recent_errors: RecentErrors,
+    /// Re-checks the portal/transport group in ctl.conf before exporting
+    group_validator: Option<Arc<ExportGroupValidator>>,
+    /// Snapshots whose producing operation is still running
+    in_progress_snapshots: InProgressSnapshots,
+    /// Readiness state degraded while the pool is read-only or suspended
+    health: Option<Arc<HealthState>>,
+    /// Largest size a volume may be created with or expanded to
+    max_volume_size: Option<u64>,
+    /// When the volume size metrics were last recomputed
+    volume_stats_refreshed: Mutex<Option<Instant>>,
+    // Note: Snapshot metadata is stored in ZFS properties and queried directly.
+    // No in-memory cache needed - ZFS is the single source of truth.
+    /// Semaphore for rate limiting concurrent operations
+    ops_semaphore: Arc<Semaphore>,
+    /// Maximum concurrent operations (for error messages)
+    max_concurrent_ops: usize,
+    /// Serializes create, delete and expand of the same volume
+    volume_locks: VolumeLocks,
+}
+
+impl StorageService {
+    /// Create a new StorageService with default rate limiting (10 concurrent ops)
+    pub fn new(zfs: Arc<RwLock<ZfsManager>>, ctl: Arc<RwLock<CtlManager>>) -> Self {
+        Self::with_concurrency_limit(zfs, ctl, DEFAULT_MAX_CONCURRENT_OPS)
+    }
+
+    /// Create a new StorageService with configurable concurrency limit
+    pub fn with_concurrency_limit(
+        zfs: Arc<RwLock<ZfsManager>>,
+        ctl: Arc<RwLock<CtlManager>>,
+        max_concurrent_ops: usize,
+    ) -> Self {
+        Self::with_limits(
+            zfs,
+            ctl,
+            max_concurrent_ops,
+            DEFAULT_CONFIG_WRITE_DEBOUNCE_MS,
+        )
+    }
+
+    /// Create a new StorageService with configurable concurrency limit and
+    /// config write debounce.
+    ///
+    /// Config writes requested within `config_write_debounce_ms` of each
+    /// other are coalesced into one write and ctld reload (0 writes every
+    /// request separately).
+    pub fn with_limits(
+        zfs: Arc<RwLock<ZfsManager>>,
         ctl: Arc<RwLock<CtlManager>>,
         max_concurrent_ops: usize,
+        config_write_debounce_ms: u64,
     ) -> Self {
         // Spawn the serialized config writer task.
         // This ensures all config writes are serialized with debouncing,
         // preventing race conditions during parallel volume operations.
-        let config_writer = spawn_config_writer(ctl.clone(), None);
+        let config_writer = spawn_config_writer(ctl.clone(), Some(config_write_debounce_ms));
 
         Self {
             zfs,
             ctl,
             config_writer,
             volumes: Arc::new(RwLock::new(HashMap::new())),
+            existence_cache: ExistenceCache::default(),
+            image_url_schemes: Vec::new(),
+            strict_auth: false,
+            repair_corrupt_metadata: false,
+            globally_unique_snapshot_names: false,
+            foreign_origin_policy: ForeignOriginPolicy::default(),
+            orphaned_metadata_policy: OrphanedMetadataPolicy::default(),
+            promote_linked_clones: false,
+            recent_errors: RecentErrors::default(),
+            group_validator: None,
+            in_progress_snapshots: InProgressSnapshots::default(),
+            health: None,
+            max_volume_size: None,
+            volume_stats_refreshed: Mutex::new(None),
             ops_semaphore: Arc::new(Semaphore::new(max_concurrent_ops)),
             max_concurrent_ops,
+            volume_locks: VolumeLocks::default(),
+        }
+    }
+
+    /// Set the URL schemes accepted for `image_url` content sources.
+    ///
+    /// Provisioning from images is disabled until schemes are set.
+    pub fn with_image_url_schemes(mut self, mut schemes: Vec<String>) -> Self {
+        schemes.retain(|scheme| !scheme.is_empty());
+        self.image_url_schemes = schemes;
+        self
+    }
+
+    /// Reject CreateVolume when NVMeoF authentication beyond host-nqn is
+    /// requested, instead of downgrading it with a warning.
+    pub fn with_strict_auth(mut self, strict_auth: bool) -> Self {
+        self.strict_auth = strict_auth;
+        self
+    }
+
+    /// On restore, rebuild the metadata of volumes whose metadata JSON is
+    /// corrupt from their persisted CTL export.
+    pub fn with_repair_corrupt_metadata(mut self, repair: bool) -> Self {
+        self.repair_corrupt_metadata = repair;
+        self
+    }
+
+    /// Reject CreateSnapshot with `AlreadyExists` when another volume already
+    /// has a snapshot of the same name (by default names are per volume).
+    pub fn with_globally_unique_snapshot_names(mut self, unique: bool) -> Self {
+        self.globally_unique_snapshot_names = unique;
+        self
+    }
+
+    /// Promote LINKED PVC-to-PVC clones once created, so the temporary
+    /// snapshot belongs to the clone and the source has no dependents.
+    pub fn with_promote_linked_clones(mut self, promote: bool) -> Self {
+        self.promote_linked_clones = promote;
+        self
+    }
+
+    /// Set how DeleteVolume treats clones of snapshots the driver didn't create.
+    pub fn with_foreign_origin_policy(mut self, policy: ForeignOriginPolicy) -> Self {
+        self.foreign_origin_policy = policy;
+        self
+    }
+
+    /// Set how DeleteVolume treats tracked volumes whose dataset is gone.
+    pub fn with_orphaned_metadata_policy(mut self, policy: OrphanedMetadataPolicy) -> Self {
+        self.orphaned_metadata_policy = policy;
+        self
+    }
+
+    /// Verify the configured portal/transport group still exists before
+    /// each new export.
+    pub fn with_export_group_validator(mut self, validator: ExportGroupValidator) -> Self {
+        self.group_validator = Some(Arc::new(validator));
+        self
+    }
+
+    /// Report a read-only or suspended pool through `/readyz`.
+    pub fn with_health_state(mut self, health: Arc<HealthState>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Reject volumes created with or expanded to more than `max` bytes.
+    pub fn with_max_volume_size(mut self, max: Option<u64>) -> Self {
+        self.max_volume_size = max;
+        self
+    }
+
+    /// Map a failed ZFS operation to a Status, marking readiness degraded
+    /// when the pool cannot accept writes.
+    fn zfs_failure(&self, context: &str, e: &crate::zfs::ZfsError) -> Status {
+        if matches!(e, crate::zfs::ZfsError::PoolUnavailable(_)) {
+            warn!(error = %e, "ZFS pool is read-only or suspended");
+            if let Some(health) = &self.health {
+                health.set_pool_unavailable(true);
+            }
+        }
+        zfs_error_status(context, e)
+    }
+
+    /// Clear a degraded readiness after a ZFS write went through.
+    fn pool_writable(&self) {
+        if let Some(health) = &self.health
+            && health.is_pool_unavailable()
+        {
+            info!("ZFS pool accepts writes again");
+            health.set_pool_unavailable(false);
         }
     }
 
@@ -327,16 +1574,172 @@ impl StorageService {
         }
     }
 
+    /// Move a tracked volume to `next`, refusing transitions the lifecycle does
+    /// not allow. Returns whether the state was applied.
+    async fn transition_volume(&self, volume_id: &str, next: VolumeState) -> bool {
+        let mut volumes = self.volumes.write().await;
+        let Some(metadata) = volumes.get_mut(volume_id) else {
+            return false;
+        };
+
+        if !metadata.state.can_transition_to(next) {
+            warn!(
+                volume = %volume_id,
+                from = %metadata.state,
+                to = %next,
+                "Refusing invalid volume state transition"
+            );
+            return false;
+        }
+
+        if metadata.state != next {
+            debug!(volume = %volume_id, from = %metadata.state, to = %next, "Volume state transition");
+            metadata.state = next;
+        }
+        true
+    }
+
+    /// Stop tracking a deleted volume
+    async fn forget_volume(&self, volume_id: &str) {
+        self.existence_cache.record_absent(volume_id);
+        let mut volumes = self.volumes.write().await;
+        volumes.remove(volume_id);
+        metrics::set_volumes_count(volumes.len());
+    }
+
+    /// Delete the temporary snapshot a deleted PVC-to-PVC clone was created
+    /// from, once no other clone depends on it. `origin` is the clone's ZFS
+    /// origin (`pool/csi/source@snap`); only snapshots with our `pvc-clone-`
+    /// prefix are cleaned up, to avoid deleting user-created snapshots.
+    async fn cleanup_clone_snapshot(&self, volume_name: &str, origin: &str) {
+        let Some((source_path, snap_name)) = origin.rsplit_once('@') else {
+            return;
+        };
+        if !snap_name.starts_with(CLONE_SNAPSHOT_PREFIX) {
+            return;
+        }
+        // Extract just the volume name from the full path
+        let source_volume = source_path.rsplit('/').next().unwrap_or(source_path);
+        if source_volume.is_empty() || !source_path.contains('/') {
+            warn!(
+                origin = %origin,
+                source_path = %source_path,
+                "Unexpected origin path format, skipping snapshot cleanup"
+            );
+            return;
+        }
+
+        info!(
+            deleted_clone = %volume_name,
+            origin_snapshot = %origin,
+            source_volume = %source_volume,
+            "Attempting to clean up temp snapshot from PVC cloning"
+        );
+
+        let zfs = self.zfs.read().await;
+        // Check if snapshot still has other clones
+        match zfs.list_clones_for_volume(source_volume).await {
+            Ok(clones) => {
+                // Filter to clones of this specific snapshot
+                let snap_clones: Vec<_> = clones.iter().filter(|(sn, _)| sn == snap_name).collect();
+
+                if snap_clones.is_empty() {
+                    // No more clones, safe to delete the temp snapshot
+                    if let Err(e) = zfs.delete_snapshot(source_volume, snap_name).await {
+                        warn!(
+                            snapshot = %snap_name,
+                            source_volume = %source_volume,
+                            error = %e,
+                            "Failed to clean up temp snapshot (may already be deleted)"
+                        );
+                    } else {
+                        info!(
+                            snapshot = %snap_name,
+                            source_volume = %source_volume,
+                            "Cleaned up temp snapshot from PVC cloning"
+                        );
+                    }
+                } else {
+                    debug!(
+                        snapshot = %snap_name,
+                        remaining_clones = snap_clones.len(),
+                        "Temp snapshot still has clones, not deleting"
+                    );
+                }
+            }
+            Err(e) => {
+                debug!(
+                    source_volume = %source_volume,
+                    error = %e,
+                    "Could not check clones for cleanup"
+                );
+            }
+        }
+    }
+
+    /// Delete temporary clone snapshots taken for `volume_name` that no clone
+    /// depends on any more. Used once the volume's dataset is gone and its
+    /// origin can no longer be read; best effort.
+    async fn cleanup_orphaned_clone_snapshots(&self, volume_name: &str) {
+        let zfs = self.zfs.read().await;
+        let snapshots = match zfs.list_unreferenced_snapshots().await {
+            Ok(snapshots) => snapshots,
+            Err(e) => {
+                debug!(
+                    volume = %volume_name,
+                    error = %e,
+                    "Could not list snapshots for clone snapshot cleanup"
+                );
+                return;
+            }
+        };
+        for snapshot in snapshots
+            .iter()
+            .filter(|path| is_clone_snapshot_for(path, volume_name))
+        {
+            match zfs.delete_snapshot_by_path(snapshot).await {
+                Ok(()) => info!(
+                    volume = %volume_name,
+                    snapshot = %snapshot,
+                    "Cleaned up temp snapshot from PVC cloning"
+                ),
+                Err(e) => warn!(
+                    volume = %volume_name,
+                    snapshot = %snapshot,
+                    error = %e,
+                    "Failed to clean up temp snapshot (may already be deleted)"
+                ),
+            }
+        }
+    }
+
     /// Restore volume metadata from ZFS user properties on startup
     pub async fn restore_from_zfs(&self) -> Result<usize, String> {
         info!("Restoring volume metadata from ZFS user properties");
 
-        let volumes_with_metadata = {
+        let scan = {
             let zfs = self.zfs.read().await;
             zfs.list_volumes_with_metadata()
                 .await
                 .map_err(|e| format!("failed to list volumes with metadata: {}", e))?
         };
+        let mut volumes_with_metadata = scan.volumes;
+        if !scan.corrupt.is_empty() {
+            if self.repair_corrupt_metadata {
+                volumes_with_metadata.extend(self.repair_corrupt_metadata(&scan.corrupt).await);
+            } else {
+                warn!(
+                    count = scan.corrupt.len(),
+                    "Volumes with corrupt metadata left unmanaged (see --repair-corrupt-metadata)"
+                );
+            }
+        }
+        if !scan.locked.is_empty() {
+            warn!(
+                count = scan.locked.len(),
+                "Encrypted volumes with unloaded keys left unmanaged (load keys and restart)"
+            );
+        }
 
         let mut restored_count = 0;
         let mut volumes = self.volumes.write().await;
@@ -368,6 +1771,14 @@ impl StorageService {
                 })?,
                 parameters: zfs_meta.parameters.clone(),
                 auth,
+                // Metadata is only written once the volume is created, so a
+                // restored volume is expected to be exported; reconciliation
+                // re-exports it if it is not.
+                state: VolumeState::Exported,
+                allowed_initiators: allowed_initiators(
+                    zfs_meta.attachment.as_ref(),
+                    zfs_meta.export_type,
+                ),
             };
 
             volumes.insert(vol_name.clone(), metadata);
@@ -385,118 +1796,292 @@ impl StorageService {
         Ok(restored_count)
     }
 
-    /// Reconcile exports: ensure all volumes in ZFS metadata are exported
+    /// Rebuild and persist metadata for volumes whose metadata JSON is corrupt.
     ///
-    /// This should be called after restore_from_zfs and load_config to ensure
-    /// that CTL exports match the ZFS metadata (source of truth for what volumes exist).
-    /// After reconciliation, writes the unified UCL config.
-    pub async fn reconcile_exports(&self) -> Result<usize, String> {
-        info!("Reconciling CTL exports with ZFS metadata");
-
-        let volumes = self.volumes.read().await;
-        let mut reconciled_count = 0;
-
-        for (vol_name, metadata) in volumes.iter() {
-            // Get device path for this volume
-            let device_path = {
-                let zfs = self.zfs.read().await;
-                zfs.get_device_path(vol_name)
+    /// Returns the repaired volumes; volumes that cannot be reconstructed
+    /// unambiguously are logged and left unmanaged.
+    async fn repair_corrupt_metadata(
+        &self,
+        corrupt: &[String],
+    ) -> Vec<(String, ZfsVolumeMetadata)> {
+        let (exports, base_iqn, base_nqn) = {
+            let ctl = self.ctl.read().await;
+            let exports = match ctl.read_persisted_exports().await {
+                Ok(exports) => exports,
+                Err(e) => {
+                    warn!(error = %e, "Cannot read persisted exports, skipping metadata repair");
+                    return Vec::new();
+                }
             };
+            (
+                exports,
+                ctl.base_name(CtlExportType::Iscsi).to_string(),
+                ctl.base_name(CtlExportType::Nvmeof).to_string(),
+            )
+        };
 
-            // Check if export exists in CtlManager
-            let needs_export = {
-                let ctl = self.ctl.read().await;
-                ctl.get_export(vol_name).is_none()
+        let mut repaired = Vec::new();
+        let zfs = self.zfs.read().await;
+        for vol_name in corrupt {
+            let metadata = match reconstruct_metadata(vol_name, &exports, &base_iqn, &base_nqn) {
+                Ok(metadata) => metadata,
+                Err(reason) => {
+                    warn!(volume = %vol_name, reason = %reason, "Not repairing corrupt metadata");
+                    continue;
+                }
             };
-
-            if !needs_export {
+            if let Err(e) = zfs.set_volume_metadata(vol_name, &metadata).await {
+                warn!(volume = %vol_name, error = %e, "Failed to persist repaired metadata");
                 continue;
             }
+            warn!(
+                volume = %vol_name,
+                export_type = %metadata.export_type,
+                target = %metadata.target_name,
+                lun_id = ?metadata.lun_id,
+                "Repaired corrupt volume metadata from persisted export"
+            );
+            repaired.push((vol_name.clone(), metadata));
+        }
+        repaired
+    }
 
-            let Some(ctl_export_type) = to_ctl_export_type(metadata.export_type) else {
-                debug!(
-                    "Volume '{}' has no export type, skipping reconciliation",
-                    vol_name
-                );
+    /// Reconcile exports: bring CTL exports in line with each volume's lifecycle state
+    ///
+    /// This runs after restore_from_zfs on startup to ensure that CTL exports
+    /// match the ZFS metadata (source of truth for what volumes exist), and
+    /// then periodically (see [`spawn_reconcile_loop`](Self::spawn_reconcile_loop))
+    /// to recover volumes a failed export, unexport or config write left in
+    /// `Failed`, `Unexporting` or `Deleting`; those states only exist in
+    /// memory, so a restart alone would forget them.
+    /// The recovery action per volume is chosen by `reconcile_action`: volumes that
+    /// should be exported are re-exported, volumes stuck mid-delete get their
+    /// unexport retried. After reconciliation, writes the unified UCL config.
+    ///
+    /// Each volume is reconciled under its volume lock, so a pass never races
+    /// a CreateVolume or DeleteVolume of the same volume.
+    ///
+    /// Per-volume failures do not abort the pass; they are collected in the
+    /// returned summary alongside the volumes that were repaired or skipped.
+    pub async fn reconcile_exports(&self) -> Result<ReconcileSummary, String> {
+        debug!("Reconciling CTL exports with ZFS metadata");
+
+        let vol_names: Vec<String> = self.volumes.read().await.keys().cloned().collect();
+
+        let mut summary = ReconcileSummary::default();
+        // States to apply once the reconciled exports have been persisted
+        let mut settled: Vec<(String, VolumeState)> = Vec::new();
+
+        for vol_name in vol_names {
+            let _volume_lock = self.volume_locks.lock(&vol_name).await;
+            // Re-read under the lock: the volume may have changed or gone
+            let Some(metadata) = self.volumes.read().await.get(&vol_name).cloned() else {
                 continue;
             };
-
-            // Validate lun_id can be safely converted to u32
-            let lun_id: u32 = match metadata.lun_id.try_into() {
-                Ok(id) => id,
-                Err(_) => {
-                    warn!(
-                        "Volume '{}' has invalid LUN ID {}, skipping reconciliation",
-                        vol_name, metadata.lun_id
-                    );
+            let has_export = self.ctl.read().await.get_export(&vol_name).is_some();
+            let outcome = match reconcile_action(metadata.state, has_export) {
+                ReconcileAction::Skip => {
+                    summary.unchanged += 1;
                     continue;
                 }
+                ReconcileAction::Export => self.reconcile_export(&vol_name, &metadata).await,
+                ReconcileAction::Unexport => {
+                    let ctl = self.ctl.read().await;
+                    match ctl.unexport_volume(&vol_name) {
+                        Ok(()) | Err(CtlError::TargetNotFound(_)) => {
+                            info!(
+                                "Reconciled: retried unexport for '{}' (state={})",
+                                vol_name, metadata.state
+                            );
+                            ReconcileOutcome::Reconciled
+                        }
+                        Err(e) => ReconcileOutcome::Failed(format!("unexport failed: {}", e)),
+                    }
+                }
+                ReconcileAction::WriteConfig => {
+                    info!(
+                        "Reconciled: persisting CTL config for '{}' (state={})",
+                        vol_name, metadata.state
+                    );
+                    ReconcileOutcome::Reconciled
+                }
             };
+            if outcome == ReconcileOutcome::Reconciled {
+                settled.push((vol_name.clone(), metadata.state.reconciled()));
+            }
+            summary.record(&vol_name, outcome);
+        }
 
-            let ctl = self.ctl.read().await;
-            // Auth-group NAME is stored in ZFS metadata; credentials are in ctl.conf.
-            // GroupRef tells write_config() to reference the existing auth-group
-            // without creating a new one (credentials already persisted in ctl.conf).
-            // CTL options (blockSize, physicalBlockSize, enableUnmap) are stored in
-            // metadata.parameters - parse them to restore the original configuration.
-            let ctl_options = parse_ctl_options(&metadata.parameters);
-            match ctl.export_volume(
-                vol_name,
-                &device_path,
-                ctl_export_type,
-                lun_id,
-                metadata.auth.clone(),
-                ctl_options,
-            ) {
-                Ok(_) => {
-                    info!(
-                        "Reconciled: re-exported {:?} target for '{}'",
-                        ctl_export_type, vol_name
-                    );
-                    reconciled_count += 1;
+        // Write unified UCL config after reconciliation
+        if !summary.reconciled.is_empty() {
+            match self.config_writer.write_config().await {
+                Ok(()) => {
+                    for (vol_name, state) in settled {
+                        self.transition_volume(&vol_name, state).await;
+                    }
                 }
                 Err(e) => {
-                    // Ignore "already exists" errors (race with load_config)
-                    if !e.to_string().contains("exists") {
-                        warn!("Failed to reconcile export for '{}': {}", vol_name, e);
-                    }
+                    warn!("Failed to write CTL config after reconciliation: {}", e);
+                    summary.fail_reconciled(&format!("config write failed: {}", e));
                 }
             }
         }
-        drop(volumes);
 
-        // Write unified UCL config after reconciliation
-        if reconciled_count > 0
-            && let Err(e) = self.config_writer.write_config().await
-        {
-            warn!("Failed to write CTL config after reconciliation: {}", e);
-        }
+        summary.log();
+        summary.report();
+        Ok(summary)
+    }
+
+    /// Spawn the periodic export reconciliation task.
+    ///
+    /// The first pass runs one `interval` after the call; the startup pass is
+    /// expected to have run already.
+    pub fn spawn_reconcile_loop(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        info!(
+            interval_secs = interval.as_secs(),
+            "Periodic export reconciliation enabled"
+        );
+
+        let service = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = service.reconcile_exports().await {
+                    warn!(error = %e, "Periodic export reconciliation failed");
+                }
+            }
+        })
+    }
+
+    /// Spawn the task that re-probes a pool marked read-only or suspended.
+    ///
+    /// Readiness otherwise only recovers with the next successful write, and
+    /// the CO may send none while the agent reports itself unready.
+    pub fn spawn_pool_probe(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let service = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                if !service
+                    .health
+                    .as_ref()
+                    .is_some_and(|health| health.is_pool_unavailable())
+                {
+                    continue;
+                }
+                match service.zfs.read().await.pool_accepts_writes().await {
+                    Ok(true) => service.pool_writable(),
+                    Ok(false) => debug!("ZFS pool is still read-only or suspended"),
+                    Err(e) => warn!(error = %e, "Failed to probe ZFS pool state"),
+                }
+            }
+        })
+    }
+
+    /// Parameters of the tracked volume a content source reads from, if known
+    async fn content_source_parameters(
+        &self,
+        source: &proto::volume_content_source::Source,
+    ) -> Option<HashMap<String, String>> {
+        use proto::volume_content_source::Source;
+
+        let volume = match source {
+            Source::SnapshotId(snapshot_id) => snapshot_id.split('@').next()?,
+            Source::SourceVolumeId(volume_id) => volume_id.as_str(),
+            Source::ImageUrl(_) => return None,
+        };
+        let volumes = self.volumes.read().await;
+        volumes.get(volume).map(|m| m.parameters.clone())
+    }
+
+    /// Re-create the CTL export for a tracked volume.
+    async fn reconcile_export(
+        &self,
+        vol_name: &str,
+        metadata: &VolumeMetadata,
+    ) -> ReconcileOutcome {
+        let Some(ctl_export_type) = to_ctl_export_type(metadata.export_type) else {
+            return ReconcileOutcome::Skipped("no export type".to_string());
+        };
+
+        // Validate lun_id can be safely converted to u32
+        let lun_id: u32 = match metadata.lun_id.try_into() {
+            Ok(id) => id,
+            Err(_) => {
+                return ReconcileOutcome::Failed(format!("invalid LUN ID {}", metadata.lun_id));
+            }
+        };
+
+        // Get device path for this volume
+        let backend = VolumeBackend::from_parameters(&metadata.parameters).unwrap_or_default();
+        let device_path = {
+            let zfs = self.zfs.read().await;
+            match zfs.volume_device_path(vol_name, backend).await {
+                Ok(path) => path,
+                Err(e) => {
+                    return ReconcileOutcome::Failed(format!(
+                        "failed to resolve device path: {}",
+                        e
+                    ));
+                }
+            }
+        };
 
-        info!("Reconciled {} export(s)", reconciled_count);
-        Ok(reconciled_count)
+        let ctl = self.ctl.read().await;
+        // Auth-group NAME is stored in ZFS metadata; credentials are in ctl.conf.
+        // GroupRef tells write_config() to reference the existing auth-group
+        // without creating a new one (credentials already persisted in ctl.conf).
+        // CTL options (blockSize, physicalBlockSize, enableUnmap) are stored in
+        // metadata.parameters - parse them to restore the original configuration.
+        let ctl_options = parse_ctl_options(&metadata.parameters);
+        match ctl.export_volume(
+            vol_name,
+            &device_path,
+            ctl_export_type,
+            lun_id,
+            metadata.auth.clone(),
+            ctl_options,
+        ) {
+            Ok(_) => {
+                if let Err(e) =
+                    ctl.set_allowed_initiators(vol_name, metadata.allowed_initiators.clone())
+                {
+                    warn!(volume = %vol_name, error = %e, "Failed to restrict export to attached nodes");
+                }
+                info!(
+                    "Reconciled: re-exported {:?} target for '{}' (state={})",
+                    ctl_export_type, vol_name, metadata.state
+                );
+                ReconcileOutcome::Reconciled
+            }
+            // "already exists" is a race with load_config, not a failure
+            Err(e) if e.to_string().contains("exists") => {
+                ReconcileOutcome::Skipped("already exported".to_string())
+            }
+            Err(e) => ReconcileOutcome::Failed(format!("export failed: {}", e)),
+        }
     }
 
     /// Convert ZFS dataset info to proto Volume
-    fn dataset_to_volume(
+    async fn dataset_to_volume(
         &self,
         dataset: &crate::zfs::Dataset,
         metadata: &VolumeMetadata,
     ) -> Volume {
-        // Use volsize for zvols (the actual volume capacity)
-        // For zvols, volsize should always be present - if not, log warning and use 0
-        // Do NOT fall back to 'referenced' as it's semantically different (allocated space, not capacity)
-        let size_bytes = match dataset.volsize {
-            Some(volsize) => volsize as i64,
-            None => {
-                warn!(
-                    dataset = %dataset.name,
-                    referenced = dataset.referenced,
-                    "zvol missing volsize property - reporting 0 capacity"
-                );
-                0
-            }
-        };
-        Volume {
+        // Use volsize for zvols (the actual volume capacity). A listing without
+        // it is re-queried before reporting 0; do NOT fall back to 'referenced'
+        // as it's semantically different (allocated space, not capacity)
+        let size_bytes = resolve_volsize(&dataset.name, dataset.volsize, || async {
+            let zfs = self.zfs.read().await;
+            zfs.get_volsize(&metadata.name).await
+        })
+        .await as i64;
+        let mut volume = Volume {
             id: metadata.id.clone(),
             name: metadata.name.clone(),
             size_bytes,
@@ -505,7 +2090,83 @@ impl StorageService {
             target_name: metadata.target_name.clone(),
             lun_id: metadata.lun_id,
             parameters: metadata.parameters.clone(),
+            ..Default::default()
+        };
+        report_provisioning(&mut volume, dataset.refreservation);
+        volume
+    }
+
+    /// The volume a CreateVolume with a content source would create, if it
+    /// already exists with at least the requested size.
+    ///
+    /// A retry then succeeds without looking at the source again, which may
+    /// have been deleted since the first attempt.
+    async fn existing_content_target(
+        &self,
+        name: &str,
+        size_bytes: u64,
+    ) -> Result<Option<Dataset>, Status> {
+        let existing = match self.existence_cache.lookup(name) {
+            Some(CachedExistence::Exists(existing)) => existing,
+            _ => match self.zfs.read().await.get_dataset(name).await {
+                Ok(existing) => existing,
+                Err(crate::zfs::ZfsError::DatasetNotFound(_)) => return Ok(None),
+                Err(e) => {
+                    return Err(self.zfs_failure("failed to check for an existing volume", &e));
+                }
+            },
+        };
+        info!(
+            volume = %name,
+            "Volume already exists, checking parameters for idempotency"
+        );
+        check_existing_volume_size(name, existing, size_bytes).map(Some)
+    }
+
+    /// Check that a content source volume exists.
+    async fn ensure_content_source_exists(&self, source_volume: &str) -> Result<(), Status> {
+        let volume_exists = self
+            .zfs
+            .read()
+            .await
+            .volume_exists(source_volume)
+            .await
+            .map_err(|e| self.zfs_failure("failed to check content source volume", &e))?;
+        check_content_source(source_volume, volume_exists, None)
+    }
+
+    /// Find the volume and snapshot name currently holding a content source
+    /// snapshot.
+    ///
+    /// That is `source_volume@snap_name` unless a clone of the source was
+    /// promoted (e.g. by `--promote-linked-clones`), which moves the source's
+    /// older snapshots under the clone; those are found by their snapshot ID
+    /// tag, also after the source volume was deleted.
+    async fn locate_content_source_snapshot(
+        &self,
+        snapshot_id: &str,
+        source_volume: &str,
+        snap_name: &str,
+    ) -> Result<(String, String), Status> {
+        let zfs = self.zfs.read().await;
+        let volume_exists = zfs
+            .volume_exists(source_volume)
+            .await
+            .map_err(|e| self.zfs_failure("failed to check content source volume", &e))?;
+        if volume_exists
+            && zfs
+                .snapshot_exists(source_volume, snap_name)
+                .await
+                .map_err(|e| self.zfs_failure("failed to check content source snapshot", &e))?
+        {
+            return Ok((source_volume.to_string(), snap_name.to_string()));
         }
+
+        let found = zfs
+            .find_snapshot_by_id(snapshot_id)
+            .await
+            .map_err(|e| self.zfs_failure("failed to search content source snapshot", &e))?;
+        content_source_location(snapshot_id, found, |path| zfs.snapshot_location(path))
     }
 
     /// Helper to create a volume from a snapshot (used by both snapshot restore and volume clone).
@@ -534,10 +2195,9 @@ impl StorageService {
                 );
                 zfs.copy_from_snapshot(source_volume, snap_name, target_name, metadata)
                     .await
-                    .map_err(|e| {
-                        Status::internal(format!("failed to copy volume from snapshot: {}", e))
-                    })
+                    .map_err(|e| self.zfs_failure("failed to copy volume from snapshot", &e))
             }
+            // Unspecified is resolved by requested_clone_mode before this
             CloneMode::Linked | CloneMode::Unspecified => {
                 // Fast clone (instant but creates dependency on snapshot)
                 info!(
@@ -548,26 +2208,21 @@ impl StorageService {
                 );
                 zfs.clone_from_snapshot(source_volume, snap_name, target_name, metadata)
                     .await
-                    .map_err(|e| {
-                        Status::internal(format!("failed to clone volume from snapshot: {}", e))
-                    })
+                    .map_err(|e| self.zfs_failure("failed to clone volume from snapshot", &e))
             }
         }
     }
 }
 
-#[tonic::async_trait]
-impl StorageAgent for StorageService {
+/// RPC handlers; the `StorageAgent` impl below delegates to these
+impl StorageService {
     /// Create a new volume, export via iSCSI or NVMeoF
     #[instrument(skip(self, request))]
-    async fn create_volume(
+    async fn handle_create_volume(
         &self,
         request: Request<CreateVolumeRequest>,
     ) -> Result<Response<CreateVolumeResponse>, Status> {
-        let timer = OperationTimer::new("create_volume");
-
-        // Rate limiting: acquire permit before proceeding
-        let _permit = self.acquire_permit("create_volume").await?;
+        let mut timer = OperationTimer::with_export_type("create_volume", ExportLabel::Unspecified);
 
         let req = request.into_inner();
         info!(
@@ -579,12 +2234,29 @@ impl StorageAgent for StorageService {
             timer.failure("invalid_argument");
             return Err(Status::invalid_argument("volume name cannot be empty"));
         }
+        let _volume_lock = self.volume_locks.lock(&req.name).await;
+        let _permit = self.acquire_permit("create_volume").await?;
+        if let Err(e) = check_reserved_name("volume", &req.name) {
+            timer.failure("invalid_argument");
+            return Err(e);
+        }
+        if let Err(e) = self.zfs.read().await.check_name_length(&req.name, None) {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument(e.to_string()));
+        }
         if req.size_bytes <= 0 {
             timer.failure("invalid_argument");
             return Err(Status::invalid_argument("size_bytes must be positive"));
         }
+        if let Err(e) =
+            check_max_volume_size("size_bytes", req.size_bytes as u64, self.max_volume_size)
+        {
+            timer.failure("out_of_range");
+            return Err(e);
+        }
 
         let export_type = ExportType::try_from(req.export_type).unwrap_or(ExportType::Unspecified);
+        timer.set_export_type(export_type.into());
 
         if export_type == ExportType::Unspecified {
             timer.failure("invalid_argument");
@@ -593,13 +2265,177 @@ impl StorageAgent for StorageService {
             ));
         }
 
+        // Extract auth config for CTL export (credentials used in ctl.conf)
+        let auth_config = proto_to_ctl_auth(req.auth.as_ref());
+        if let Err(status) = check_nvme_auth_support(&auth_config, self.strict_auth) {
+            timer.failure("invalid_argument");
+            return Err(status);
+        }
+
+        if let Err(e) = check_ctl_options(&req.parameters) {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument(e.to_string()));
+        }
+
+        // Volumes sharing a controller group become namespaces of one NVMeoF controller
+        let controller_group = parse_ctl_options(&req.parameters).controller_group;
+        if let Some(ref group) = controller_group {
+            if export_type != ExportType::Nvmeof {
+                timer.failure("invalid_argument");
+                return Err(Status::invalid_argument(format!(
+                    "{} is only supported for NVMeoF exports",
+                    CONTROLLER_GROUP_PARAM
+                )));
+            }
+            if auth_config.is_some() {
+                timer.failure("invalid_argument");
+                return Err(Status::invalid_argument(format!(
+                    "{} '{}' cannot be combined with per-volume authentication",
+                    CONTROLLER_GROUP_PARAM, group
+                )));
+            }
+        }
+
+        // The alias is stored with the other parameters, so reconciliation
+        // re-renders it; validate it once here
+        let params = Parameters::new(&req.parameters);
+        if let Some(alias) = params.get(TARGET_ALIAS_PARAM) {
+            if export_type != ExportType::Iscsi {
+                timer.failure("invalid_argument");
+                return Err(Status::invalid_argument(format!(
+                    "{} is only supported for iSCSI exports",
+                    TARGET_ALIAS_PARAM
+                )));
+            }
+            if let Err(e) = validate_ucl_string(alias, TARGET_ALIAS_PARAM) {
+                timer.failure("invalid_argument");
+                return Err(Status::invalid_argument(e.to_string()));
+            }
+        }
+
+        // Per-volume groups are stored with the other parameters, so
+        // reconciliation re-exports through the same group
+        let group_override = match check_group_override(&req.parameters, export_type) {
+            Ok(group) => group,
+            Err(e) => {
+                timer.failure("invalid_argument");
+                return Err(e);
+            }
+        };
+
+        // The backend is stored with the other parameters and decides how the
+        // volume is created, exported and expanded
+        let backend = match VolumeBackend::from_parameters(&req.parameters) {
+            Ok(backend) => backend,
+            Err(e) => {
+                timer.failure("invalid_argument");
+                return Err(Status::invalid_argument(e));
+            }
+        };
+        if let Some(record_size) = params.get(RECORD_SIZE_PARAM) {
+            if backend != VolumeBackend::File {
+                timer.failure("invalid_argument");
+                return Err(Status::invalid_argument(format!(
+                    "{} requires {}=file",
+                    RECORD_SIZE_PARAM, BACKEND_PARAM
+                )));
+            }
+            if let Err(e) = parse_record_size(record_size) {
+                timer.failure("invalid_argument");
+                return Err(Status::invalid_argument(e));
+            }
+        }
+        if let Err(e) = compression_from_parameters(&req.parameters) {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument(e.to_string()));
+        }
+        if let Err(e) = reserves_full_size(&req.parameters)
+            .and_then(|_| quota_from_parameters(&req.parameters, backend, req.size_bytes as u64))
+        {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument(e.to_string()));
+        }
+        let encryption = match encryption_from_parameters(&req.parameters) {
+            Ok(encryption) => encryption,
+            Err(e) => {
+                timer.failure("invalid_argument");
+                return Err(Status::invalid_argument(e.to_string()));
+            }
+        };
+        let content_source = req.content_source.as_ref().and_then(|c| c.source.as_ref());
+        let origin_parameters = match content_source {
+            Some(source) => self.content_source_parameters(source).await,
+            None => None,
+        };
+        if let Err(e) = check_volblocksize(
+            &req.parameters,
+            backend,
+            content_source.is_some(),
+            origin_parameters.as_ref(),
+        ) {
+            timer.failure("invalid_argument");
+            return Err(e);
+        }
+        if content_source.is_some() {
+            let source_backend = origin_parameters
+                .as_ref()
+                .map(|p| VolumeBackend::from_parameters(p).unwrap_or_default());
+            if backend == VolumeBackend::File || source_backend == Some(VolumeBackend::File) {
+                timer.failure("invalid_argument");
+                return Err(Status::invalid_argument(format!(
+                    "content sources are not supported for {}=file volumes",
+                    BACKEND_PARAM
+                )));
+            }
+            if let Some(origin) = origin_parameters.as_ref() {
+                // Origin parameters were validated when it was created
+                let origin_encryption = encryption_from_parameters(origin).ok().flatten();
+                let linked = req
+                    .content_source
+                    .as_ref()
+                    .is_some_and(|c| requested_clone_mode(c.clone_mode) == CloneMode::Linked);
+                if let Err(e) =
+                    check_origin_encryption(encryption.as_ref(), origin_encryption.as_ref(), linked)
+                {
+                    timer.failure("invalid_argument");
+                    return Err(e);
+                }
+            }
+        }
+
         // Compute export parameters before volume creation so we can set metadata atomically
         // Default LUN/Namespace ID
         // Note: iSCSI LUN IDs can start at 0, but NVMeoF namespace IDs must start at 1
-        // (NSID 0 is reserved per NVMe spec)
-        let lun_id: u32 = match export_type {
-            ExportType::Iscsi => 0,
-            ExportType::Nvmeof => 1,
+        // (NSID 0 is reserved per NVMe spec). Grouped namespaces take the next free ID
+        // in their controller.
+        // The namespace ID stays reserved until the export below registers it.
+        let namespace_reservation = match (export_type, controller_group.as_deref()) {
+            (ExportType::Nvmeof, Some(group)) => {
+                let ctl = self.ctl.read().await;
+                // The group controller serves all members through one
+                // transport group
+                if let Err(e) =
+                    ctl.check_group_transport_group(group, &req.name, group_override.as_deref())
+                {
+                    timer.failure("invalid_argument");
+                    return Err(Status::invalid_argument(format!(
+                        "invalid {}: {}",
+                        TRANSPORT_GROUP_PARAM, e
+                    )));
+                }
+                match ctl.reserve_namespace_id(group, &req.name) {
+                    Ok(reservation) => Some(reservation),
+                    Err(e) => {
+                        timer.failure("resource_exhausted");
+                        return Err(Status::resource_exhausted(e.to_string()));
+                    }
+                }
+            }
+            _ => None,
+        };
+        let lun_id: u32 = match (export_type, &namespace_reservation) {
+            (_, Some(reservation)) => reservation.id,
+            (ExportType::Nvmeof, None) => 1,
             _ => 0,
         };
 
@@ -607,21 +2443,30 @@ impl StorageAgent for StorageService {
         let ctl_export_type = to_ctl_export_type(export_type).expect("already validated");
         let target_name = {
             let ctl = self.ctl.read().await;
-            match ctl_export_type {
-                crate::ctl::ExportType::Iscsi => ctl
+            match (ctl_export_type, controller_group.as_deref()) {
+                (crate::ctl::ExportType::Iscsi, _) => ctl
                     .generate_iqn(&req.name)
                     .map(|iqn| iqn.to_string())
                     .map_err(|e| Status::internal(format!("failed to generate IQN: {}", e)))?,
-                crate::ctl::ExportType::Nvmeof => ctl
+                (crate::ctl::ExportType::Nvmeof, Some(group)) => {
+                    match ctl.generate_group_nqn(group) {
+                        Ok(nqn) => nqn.to_string(),
+                        Err(e) => {
+                            timer.failure("invalid_argument");
+                            return Err(Status::invalid_argument(format!(
+                                "invalid {} '{}': {}",
+                                CONTROLLER_GROUP_PARAM, group, e
+                            )));
+                        }
+                    }
+                }
+                (crate::ctl::ExportType::Nvmeof, None) => ctl
                     .generate_nqn(&req.name)
                     .map(|nqn| nqn.to_string())
                     .map_err(|e| Status::internal(format!("failed to generate NQN: {}", e)))?,
             }
         };
 
-        // Extract auth config for CTL export (credentials used in ctl.conf)
-        let auth_config = proto_to_ctl_auth(req.auth.as_ref());
-
         // Reject NVMeoF authentication until FreeBSD supports DH-HMAC-CHAP
         if export_type == ExportType::Nvmeof && matches!(auth_config, AuthConfig::NvmeAuth(_)) {
             timer.failure("invalid_argument");
@@ -635,31 +2480,36 @@ impl StorageAgent for StorageService {
         // Validate CHAP credentials if present (prevents invalid creds from being stored)
         if let Some(auth) = &req.auth {
             use proto::auth_credentials::Credentials;
-            if let Some(Credentials::IscsiChap(chap)) = &auth.credentials {
-                if let Err(e) = crate::ctl::validate_chap_credentials(&chap.username, &chap.secret)
-                {
-                    timer.failure("validation_error");
-                    return Err(Status::invalid_argument(format!(
-                        "Invalid CHAP credentials: {}",
-                        e
-                    )));
-                }
+            if let Some(Credentials::IscsiChap(chap)) = &auth.credentials
+                && let Err(e) = check_chap_credentials(chap)
+            {
+                timer.failure("validation_error");
+                return Err(e);
+            }
+        }
 
-                // Validate mutual CHAP credentials if both are provided
-                if !chap.mutual_username.is_empty()
-                    && !chap.mutual_secret.is_empty()
-                    && let Err(e) = crate::ctl::validate_chap_credentials(
-                        &chap.mutual_username,
-                        &chap.mutual_secret,
-                    )
-                {
-                    timer.failure("validation_error");
-                    return Err(Status::invalid_argument(format!(
-                        "Invalid mutual CHAP credentials: {}",
-                        e
-                    )));
-                }
+        // The portal/transport group may have been removed from ctl.conf since
+        // startup; check before creating anything so the failure is clear
+        if let Some(validator) = &self.group_validator
+            && let Err(e) = validator
+                .check_group(ctl_export_type, group_override.as_deref())
+                .await
+        {
+            warn!(volume = %req.name, error = %e, "Export group check failed");
+            // An unknown group requested by the StorageClass is a caller
+            // error; the agent's own group going missing is not
+            if group_override.is_some() {
+                timer.failure("invalid_argument");
+                return Err(Status::invalid_argument(format!(
+                    "cannot export volume '{}': {}",
+                    req.name, e
+                )));
             }
+            timer.failure("failed_precondition");
+            return Err(Status::failed_precondition(format!(
+                "cannot export volume '{}': {}",
+                req.name, e
+            )));
         }
 
         // Compute auth-group name for ZFS metadata (credentials NOT stored in ZFS)
@@ -669,6 +2519,10 @@ impl StorageAgent for StorageService {
             None
         };
 
+        // Clones keep their origin's block size; record it with the clone
+        let parameters =
+            with_inherited_volblocksize(req.parameters.clone(), origin_parameters.as_ref());
+
         // Build ZFS metadata to set atomically during volume creation
         // SECURITY: Only the auth-group NAME is stored, not credentials.
         // Credentials are persisted in /etc/ctl.conf (root-only).
@@ -677,18 +2531,37 @@ impl StorageAgent for StorageService {
             target_name.clone(),
             Some(lun_id),
             None, // namespace_id
-            req.parameters.clone(),
+            parameters.clone(),
             unix_timestamp_now(),
             auth_group_name,
         );
 
+        // A retry of a populated volume finds it before touching the source
+        let existing_target = match &req.content_source {
+            Some(_) => match self
+                .existing_content_target(&req.name, req.size_bytes as u64)
+                .await
+            {
+                Ok(existing) => existing,
+                Err(e) => {
+                    timer.failure(if e.code() == tonic::Code::AlreadyExists {
+                        "size_mismatch"
+                    } else {
+                        "zfs_error"
+                    });
+                    return Err(e);
+                }
+            },
+            None => None,
+        };
+
         // Create ZFS volume - either fresh or from content source (snapshot/volume)
-        let dataset = if let Some(ref content_source) = req.content_source {
+        let dataset = if let Some(existing) = existing_target {
+            existing
+        } else if let Some(ref content_source) = req.content_source {
             use proto::volume_content_source::Source;
 
-            // Determine clone mode
-            let clone_mode =
-                CloneMode::try_from(content_source.clone_mode).unwrap_or(CloneMode::Unspecified);
+            let clone_mode = requested_clone_mode(content_source.clone_mode);
 
             match &content_source.source {
                 Some(Source::SnapshotId(snapshot_id)) => {
@@ -710,14 +2583,26 @@ impl StorageAgent for StorageService {
                         )));
                     }
 
-                    let source_volume = parts[0];
-                    let snap_name = parts[1];
+                    let (source_volume, snap_name) = match self
+                        .locate_content_source_snapshot(snapshot_id, parts[0], parts[1])
+                        .await
+                    {
+                        Ok(location) => location,
+                        Err(e) => {
+                            timer.failure(match e.code() {
+                                tonic::Code::NotFound => "not_found",
+                                tonic::Code::FailedPrecondition => "failed_precondition",
+                                _ => "zfs_error",
+                            });
+                            return Err(e);
+                        }
+                    };
 
                     match self
                         .create_volume_from_snapshot(
                             &req.name,
-                            source_volume,
-                            snap_name,
+                            &source_volume,
+                            &snap_name,
                             clone_mode,
                             &zfs_metadata,
                         )
@@ -739,6 +2624,8 @@ impl StorageAgent for StorageService {
                     // - LINKED mode: temp snapshot preserved (clone depends on it)
                     //   When the clone is later deleted, our auto-promote logic
                     //   transfers snapshot ownership if needed.
+                    // - LINKED mode with --promote-linked-clones: the clone is
+                    //   promoted at once and owns the temp snapshot
                     if source_volume_id.is_empty() {
                         timer.failure("invalid_argument");
                         return Err(Status::invalid_argument(
@@ -746,13 +2633,39 @@ impl StorageAgent for StorageService {
                         ));
                     }
 
+                    if let Err(e) = self.ensure_content_source_exists(source_volume_id).await {
+                        timer.failure(if e.code() == tonic::Code::NotFound {
+                            "not_found"
+                        } else {
+                            "zfs_error"
+                        });
+                        return Err(e);
+                    }
+
                     // Generate unique snapshot name using target volume name + timestamp
                     // Using timestamp avoids collision if same target name is retried
                     let timestamp = std::time::SystemTime::now()
                         .duration_since(std::time::UNIX_EPOCH)
                         .map(|d| d.as_millis())
                         .unwrap_or(0);
-                    let temp_snap_name = format!("pvc-clone-{}-{}", &req.name, timestamp);
+                    let temp_snap_name =
+                        format!("{}{}-{}", CLONE_SNAPSHOT_PREFIX, &req.name, timestamp);
+
+                    // The temp snapshot embeds the target name under the
+                    // source volume, so it can overflow even when both
+                    // volume names fit on their own.
+                    if let Err(e) = self
+                        .zfs
+                        .read()
+                        .await
+                        .check_name_length(source_volume_id, Some(&temp_snap_name))
+                    {
+                        timer.failure("invalid_argument");
+                        return Err(Status::invalid_argument(format!(
+                            "volume name too long to clone from '{}': {}",
+                            source_volume_id, e
+                        )));
+                    }
 
                     info!(
                         source_volume = %source_volume_id,
@@ -762,16 +2675,23 @@ impl StorageAgent for StorageService {
                         "Cloning volume from existing PVC"
                     );
 
+                    // COPY clones keep the temp snapshot visible for the whole
+                    // send/recv; report it as not ready until that settles.
+                    let _copy_in_progress = (clone_mode == CloneMode::Copy).then(|| {
+                        self.in_progress_snapshots
+                            .begin(&format!("{}@{}", source_volume_id, temp_snap_name))
+                    });
+
                     // Create temporary snapshot of source volume
                     {
                         let zfs = self.zfs.read().await;
                         if let Err(e) = zfs.create_snapshot(source_volume_id, &temp_snap_name).await
                         {
                             timer.failure("zfs_error");
-                            return Err(Status::internal(format!(
-                                "failed to create temporary snapshot for volume clone: {}",
-                                e
-                            )));
+                            return Err(self.zfs_failure(
+                                "failed to create temporary snapshot for volume clone",
+                                &e,
+                            ));
                         }
                     }
 
@@ -787,9 +2707,13 @@ impl StorageAgent for StorageService {
                         .await;
 
                     // Handle cleanup based on result and clone mode
-                    match (&result, clone_mode) {
-                        (Ok(_), CloneMode::Copy) => {
-                            // Success with COPY mode - clean up temp snapshot
+                    match temp_snapshot_action(
+                        result.is_ok(),
+                        clone_mode,
+                        self.promote_linked_clones,
+                    ) {
+                        TempSnapshotAction::Delete => {
+                            // COPY mode no longer needs it; a failed clone never did
                             let zfs = self.zfs.read().await;
                             if let Err(e) =
                                 zfs.delete_snapshot(source_volume_id, &temp_snap_name).await
@@ -798,30 +2722,36 @@ impl StorageAgent for StorageService {
                                     source_volume = %source_volume_id,
                                     snapshot = %temp_snap_name,
                                     error = %e,
-                                    "Failed to clean up temporary snapshot after copy"
+                                    clone_succeeded = result.is_ok(),
+                                    "Failed to clean up temporary snapshot"
                                 );
                             }
                         }
-                        (Ok(_), _) => {
-                            // Success with LINKED mode - keep temp snapshot
+                        TempSnapshotAction::Keep => {
                             info!(
                                 source_volume = %source_volume_id,
                                 snapshot = %temp_snap_name,
                                 "Temporary snapshot preserved (LINKED mode clone depends on it)"
                             );
                         }
-                        (Err(_), _) => {
-                            // Failed - always clean up temp snapshot
+                        TempSnapshotAction::PromoteClone => {
+                            // The snapshot keeps its CSI ID tag when it moves,
+                            // so find_snapshot_by_id still resolves it. A
+                            // failed promote leaves a valid, unpromoted clone.
                             let zfs = self.zfs.read().await;
-                            if let Err(e) =
-                                zfs.delete_snapshot(source_volume_id, &temp_snap_name).await
-                            {
-                                warn!(
+                            match zfs.promote_clone(&req.name).await {
+                                Ok(()) => info!(
                                     source_volume = %source_volume_id,
+                                    clone = %req.name,
                                     snapshot = %temp_snap_name,
+                                    "Promoted LINKED clone; temporary snapshot moved to the clone"
+                                ),
+                                Err(e) => warn!(
+                                    source_volume = %source_volume_id,
+                                    clone = %req.name,
                                     error = %e,
-                                    "Failed to clean up temporary snapshot after failed clone"
-                                );
+                                    "Failed to promote LINKED clone; source keeps the temporary snapshot"
+                                ),
                             }
                         }
                     }
@@ -834,112 +2764,229 @@ impl StorageAgent for StorageService {
                         }
                     }
                 }
+                Some(Source::ImageUrl(image_url)) => {
+                    // Volume creation from a remote `zfs send` stream
+                    if image_url.is_empty() {
+                        timer.failure("invalid_argument");
+                        return Err(Status::invalid_argument(
+                            "content_source.image_url cannot be empty",
+                        ));
+                    }
+
+                    let zfs = self.zfs.read().await;
+                    match zfs
+                        .receive_from_url(
+                            image_url,
+                            &req.name,
+                            req.size_bytes as u64,
+                            &zfs_metadata,
+                            &self.image_url_schemes,
+                        )
+                        .await
+                    {
+                        Ok(d) => d,
+                        Err(crate::zfs::ZfsError::InvalidName(msg)) => {
+                            timer.failure("invalid_argument");
+                            return Err(Status::invalid_argument(format!(
+                                "invalid image_url: {}",
+                                msg
+                            )));
+                        }
+                        Err(crate::zfs::ZfsError::DatasetExists(_)) => {
+                            // Idempotent retry: the image was already received
+                            let existing = match zfs.get_dataset(&req.name).await {
+                                Ok(d) => d,
+                                Err(e) => {
+                                    timer.failure("zfs_error");
+                                    return Err(Status::internal(format!(
+                                        "Failed to get existing volume info: {}",
+                                        e
+                                    )));
+                                }
+                            };
+                            match check_existing_volume_size(
+                                &req.name,
+                                existing,
+                                req.size_bytes as u64,
+                            ) {
+                                Ok(d) => d,
+                                Err(status) => {
+                                    timer.failure("size_mismatch");
+                                    return Err(status);
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            timer.failure("zfs_error");
+                            return Err(self.zfs_failure("failed to receive volume from image", &e));
+                        }
+                    }
+                }
                 None => {
                     timer.failure("invalid_argument");
                     return Err(Status::invalid_argument(
-                        "content_source must specify either snapshot_id or source_volume_id",
+                        "content_source must specify one of snapshot_id, source_volume_id or image_url",
                     ));
                 }
             }
         } else {
-            // Fresh volume creation with metadata set atomically
-            let zfs = self.zfs.read().await;
-            match zfs
-                .create_volume(&req.name, req.size_bytes as u64, &zfs_metadata)
-                .await
+            // Fresh volume creation with metadata set atomically.
+            // Retries of a name created within the cache window skip the ZFS
+            // create attempt and existence scan; a miss goes to ZFS.
+            if let Some(CachedExistence::Exists(existing)) = self.existence_cache.lookup(&req.name)
             {
-                Ok(d) => d,
-                Err(crate::zfs::ZfsError::DatasetExists(_)) => {
-                    // Recovery: Volume already exists - check if it matches requested parameters
-                    // This handles idempotent retries per CSI spec
-                    info!(
-                        volume = %req.name,
-                        "Volume already exists, checking parameters for idempotency"
-                    );
-
-                    // Get existing volume info to compare parameters
-                    let existing = match zfs.get_dataset(&req.name).await {
-                        Ok(d) => d,
-                        Err(e) => {
-                            timer.failure("zfs_error");
-                            return Err(Status::internal(format!(
-                                "Failed to get existing volume info: {}",
-                                e
-                            )));
-                        }
-                    };
-
-                    // Check size: existing >= requested is OK (volume may have been expanded)
-                    let existing_size = existing.volsize.unwrap_or(0);
-                    let requested_size = req.size_bytes as u64;
-
-                    if existing_size < requested_size {
+                debug!(
+                    volume = %req.name,
+                    "Volume existence cached, skipping ZFS create"
+                );
+                match check_existing_volume_size(&req.name, existing, req.size_bytes as u64) {
+                    Ok(d) => d,
+                    Err(status) => {
                         timer.failure("size_mismatch");
-                        return Err(Status::already_exists(format!(
-                            "Volume '{}' exists with size {} bytes but {} bytes was requested. \
-                             Existing volume is smaller than requested.",
-                            req.name, existing_size, requested_size
-                        )));
+                        return Err(status);
                     }
+                }
+            } else {
+                let zfs = self.zfs.read().await;
+                match zfs
+                    .create_volume(&req.name, req.size_bytes as u64, &zfs_metadata)
+                    .await
+                {
+                    Ok(d) => d,
+                    Err(crate::zfs::ZfsError::DatasetExists(_)) => {
+                        // Recovery: Volume already exists - check if it matches requested parameters
+                        // This handles idempotent retries per CSI spec
+                        info!(
+                            volume = %req.name,
+                            "Volume already exists, checking parameters for idempotency"
+                        );
 
-                    info!(
-                        volume = %req.name,
-                        existing_size = existing_size,
-                        requested_size = requested_size,
-                        "Existing volume matches requested parameters (idempotent success)"
-                    );
+                        // Get existing volume info to compare parameters
+                        let existing = match zfs.get_dataset(&req.name).await {
+                            Ok(d) => d,
+                            Err(e) => {
+                                timer.failure("zfs_error");
+                                return Err(Status::internal(format!(
+                                    "Failed to get existing volume info: {}",
+                                    e
+                                )));
+                            }
+                        };
 
-                    // Return existing dataset info - continue with target export setup
-                    existing
+                        // Return existing dataset info - continue with target export setup
+                        match check_existing_volume_size(&req.name, existing, req.size_bytes as u64)
+                        {
+                            Ok(d) => d,
+                            Err(status) => {
+                                timer.failure("size_mismatch");
+                                return Err(status);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        timer.failure("zfs_error");
+                        return Err(self.zfs_failure("failed to create ZFS volume", &e));
+                    }
                 }
+            }
+        };
+        self.existence_cache
+            .record_exists(&req.name, dataset.clone());
+
+        // Get device path
+        let device_path = {
+            let zfs = self.zfs.read().await;
+            match zfs.volume_device_path(&req.name, backend).await {
+                Ok(path) => path,
                 Err(e) => {
                     timer.failure("zfs_error");
                     return Err(Status::internal(format!(
-                        "failed to create ZFS volume: {}",
+                        "failed to resolve device path: {}",
                         e
                     )));
                 }
             }
         };
 
-        // Get device path
-        let device_path = {
-            let zfs = self.zfs.read().await;
-            zfs.get_device_path(&req.name)
-        };
-
         // auth_config was extracted earlier for ZFS metadata persistence
         let has_auth = auth_config.is_some();
 
         // Parse CTL options from request parameters
         let ctl_options = parse_ctl_options(&req.parameters);
 
-        // Export the volume via unified CTL manager
+        // Track the volume as Creating before touching CTL so a failed export
+        // or config write leaves a state reconciliation can act on.
+        // ZFS metadata was set atomically during creation.
+        let mut metadata = VolumeMetadata {
+            id: req.name.clone(),
+            name: req.name.clone(),
+            export_type,
+            target_name: target_name.clone(),
+            lun_id: lun_id
+                .try_into()
+                .map_err(|_| Status::internal(format!("LUN ID {} exceeds i32::MAX", lun_id)))?,
+            parameters,
+            auth: auth_config.clone(),
+            state: VolumeState::Creating,
+            allowed_initiators: Vec::new(),
+        };
         {
+            let mut volumes = self.volumes.write().await;
+            match volumes.get_mut(&req.name) {
+                Some(existing) if existing.state.can_transition_to(VolumeState::Creating) => {
+                    *existing = metadata.clone();
+                }
+                Some(_) => {}
+                None => {
+                    volumes.insert(req.name.clone(), metadata.clone());
+                }
+            }
+        }
+
+        // Export the volume via unified CTL manager
+        let export_result = {
             let ctl = self.ctl.read().await;
-            if let Err(e) = ctl.export_volume(
+            ctl.export_volume(
                 &req.name,
                 &device_path,
                 ctl_export_type,
                 lun_id,
-                auth_config.clone(),
+                auth_config,
                 ctl_options,
-            ) {
-                warn!("Failed to export volume: {}", e);
-                timer.failure("export_error");
-                return Err(Status::internal(format!("failed to export volume: {}", e)));
-            }
+            )
+        };
+        drop(namespace_reservation);
+        if let Err(e) = export_result {
+            warn!("Failed to export volume: {}", e);
+            self.transition_volume(&req.name, VolumeState::Failed).await;
+            timer.failure("export_error");
+            return Err(Status::internal(format!("failed to export volume: {}", e)));
         }
 
         if has_auth {
             info!("Exported volume {} with authentication enabled", req.name);
         }
 
+        // A retried create may reuse a dataset whose metadata recorded a different
+        // namespace ID; persist the one actually exported so reconciliation
+        // restores the namespace in the same slot of the group controller.
+        if controller_group.is_some() {
+            let zfs = self.zfs.read().await;
+            if let Err(e) = zfs.set_volume_metadata(&req.name, &zfs_metadata).await {
+                warn!(
+                    volume = %req.name,
+                    error = %e,
+                    "Failed to persist controller group namespace ID"
+                );
+            }
+        }
+
         // Write UCL config and reload ctld
         // CRITICAL: If this fails, ctld won't know about the export and
         // initiators won't be able to connect. We must return error.
         if let Err(e) = self.config_writer.write_config().await {
             error!("Failed to write CTL config: {}", e);
+            self.transition_volume(&req.name, VolumeState::Failed).await;
             timer.failure("config_write_error");
             return Err(Status::internal(format!(
                 "Volume created but CTL config write failed: {}. Target may be inaccessible.",
@@ -947,25 +2994,12 @@ impl StorageAgent for StorageService {
             )));
         }
 
-        // Store in-memory metadata (ZFS metadata was set atomically during creation)
-        let metadata = VolumeMetadata {
-            id: req.name.clone(),
-            name: req.name.clone(),
-            export_type,
-            target_name: target_name.clone(),
-            lun_id: lun_id
-                .try_into()
-                .map_err(|_| Status::internal(format!("LUN ID {} exceeds i32::MAX", lun_id)))?,
-            parameters: req.parameters.clone(),
-            auth: auth_config,
-        };
-
-        {
-            let mut volumes = self.volumes.write().await;
-            volumes.insert(req.name.clone(), metadata.clone());
-        }
+        self.transition_volume(&req.name, VolumeState::Exported)
+            .await;
+        metadata.state = VolumeState::Exported;
 
-        let volume = self.dataset_to_volume(&dataset, &metadata);
+        let mut volume = self.dataset_to_volume(&dataset, &metadata).await;
+        volume.content_source = req.content_source.clone();
         info!("Created volume: {}", req.name);
 
         // Update volume count metric
@@ -974,6 +3008,7 @@ impl StorageAgent for StorageService {
             metrics::set_volumes_count(volumes.len());
         }
 
+        self.pool_writable();
         timer.success();
         Ok(Response::new(CreateVolumeResponse {
             volume: Some(volume),
@@ -987,14 +3022,11 @@ impl StorageAgent for StorageService {
     /// - If unexport fails with "not found", treat as already unexported
     /// - If ZFS volume doesn't exist, treat as already deleted
     #[instrument(skip(self, request))]
-    async fn delete_volume(
+    async fn handle_delete_volume(
         &self,
         request: Request<DeleteVolumeRequest>,
     ) -> Result<Response<DeleteVolumeResponse>, Status> {
-        let timer = OperationTimer::new("delete_volume");
-
-        // Rate limiting: acquire permit before proceeding
-        let _permit = self.acquire_permit("delete_volume").await?;
+        let mut timer = OperationTimer::with_export_type("delete_volume", ExportLabel::Unspecified);
 
         let req = request.into_inner();
         info!("DeleteVolume request: volume_id={}", req.volume_id);
@@ -1003,6 +3035,8 @@ impl StorageAgent for StorageService {
             timer.failure("invalid_argument");
             return Err(Status::invalid_argument("volume_id cannot be empty"));
         }
+        let _volume_lock = self.volume_locks.lock(&req.volume_id).await;
+        let _permit = self.acquire_permit("delete_volume").await?;
 
         // Get volume metadata from in-memory cache.
         // We only perform destructive actions for CSI-managed volumes.
@@ -1010,18 +3044,62 @@ impl StorageAgent for StorageService {
             let volumes = self.volumes.read().await;
             volumes.get(&req.volume_id).cloned()
         };
+        if let Some(ref tracked) = metadata {
+            timer.set_export_type(tracked.export_type.into());
+        }
+
+        // Tracked volume whose dataset and export are already gone (e.g. a
+        // retry after the destroy succeeded): drop the metadata and skip the
+        // clone, snapshot and unexport steps. A temporary clone snapshot the
+        // volume was cloned from can no longer be found through its origin,
+        // so look for it by name.
+        if let Some(ref tracked) = metadata {
+            let dataset_exists = {
+                let zfs = self.zfs.read().await;
+                zfs.volume_exists(&tracked.name).await
+            };
+            let has_export = {
+                let ctl = self.ctl.read().await;
+                ctl.get_export(&req.volume_id).is_some()
+            };
+            if is_orphaned_metadata(&dataset_exists, has_export, self.orphaned_metadata_policy) {
+                info!(
+                    volume = %req.volume_id,
+                    "Volume dataset and export already gone, dropping orphaned metadata"
+                );
+                self.forget_volume(&req.volume_id).await;
+                self.cleanup_orphaned_clone_snapshots(&tracked.name).await;
+                timer.success();
+                return Ok(Response::new(DeleteVolumeResponse {}));
+            }
+        }
 
         // If the cache is missing metadata, read the ZFS metadata property
         // directly. Versioned metadata is the ownership marker; existing
         // datasets without valid metadata are not CSI-managed.
         if metadata.is_none() {
-            let zfs = self.zfs.read().await;
-            let action = missing_metadata_delete_action(
-                &req.volume_id,
-                zfs.get_volume_metadata(&req.volume_id).await,
-            );
+            // A volume deleted within the cache window is known absent;
+            // skip the ZFS lookup and only check for a stale export.
+            let action = if matches!(
+                self.existence_cache.lookup(&req.volume_id),
+                Some(CachedExistence::Absent)
+            ) {
+                Ok(MissingMetadataDeleteAction::CleanupStaleExport)
+            } else {
+                let zfs = self.zfs.read().await;
+                missing_metadata_delete_action(
+                    &req.volume_id,
+                    zfs.get_volume_metadata(&req.volume_id).await,
+                )
+            };
             match action {
                 Ok(MissingMetadataDeleteAction::UseZfsMetadata(zfs_metadata)) => {
+                    // Track the volume so the delete steps below are reflected
+                    // in its lifecycle state
+                    let mut volumes = self.volumes.write().await;
+                    volumes
+                        .entry(req.volume_id.clone())
+                        .or_insert_with(|| (*zfs_metadata).clone());
                     metadata = Some(*zfs_metadata);
                 }
                 Ok(MissingMetadataDeleteAction::CleanupStaleExport) => {
@@ -1077,6 +3155,53 @@ impl StorageAgent for StorageService {
             .name
             .clone();
 
+        // Check if this volume is a clone (has an origin snapshot)
+        // We need this info BEFORE deletion to clean up temp snapshots afterward,
+        // and before promoting any clones so a refused delete changes nothing
+        let mut origin_info: Option<String> = {
+            let zfs = self.zfs.read().await;
+            match zfs.get_origin(&volume_name).await {
+                Ok(origin) => origin,
+                Err(e) => {
+                    debug!(
+                        volume = %volume_name,
+                        error = %e,
+                        "Could not get origin (volume may not exist)"
+                    );
+                    None
+                }
+            }
+        };
+
+        // Only `refuse` cares whether the origin is a CSI snapshot
+        let origin_tagged = match (&origin_info, self.foreign_origin_policy) {
+            (Some(origin), ForeignOriginPolicy::Refuse) => {
+                let zfs = self.zfs.read().await;
+                match zfs.get_snapshot_id_at(origin).await {
+                    Ok(snapshot_id) => snapshot_id.is_some(),
+                    Err(e) => {
+                        warn!(
+                            volume = %volume_name,
+                            origin = %origin,
+                            error = %e,
+                            "Could not read the snapshot ID of the origin"
+                        );
+                        false
+                    }
+                }
+            }
+            _ => false,
+        };
+        if let Err(status) = check_foreign_origin(
+            &volume_name,
+            origin_info.as_deref(),
+            origin_tagged,
+            self.foreign_origin_policy,
+        ) {
+            timer.failure("foreign_origin");
+            return Err(status);
+        }
+
         // Handle clone dependencies: auto-promote clones to allow source deletion.
         // When volume A has snapshot A@snap with clone B, we must promote B first
         // so that A can be deleted. After promotion, A@snap becomes B@snap and
@@ -1115,6 +3240,14 @@ impl StorageAgent for StorageService {
                             // Continue anyway - the clone might be outside our managed dataset
                         }
                     }
+
+                    // The volume is now a clone of a promoted clone's snapshot
+                    // (e.g. when deleting a LINKED clone promoted at creation);
+                    // use that origin so a temp clone snapshot is cleaned up below.
+                    // Any earlier origin moved to the promoted clone with it.
+                    if let Ok(Some(origin)) = zfs.get_origin(&volume_name).await {
+                        origin_info = Some(origin);
+                    }
                 }
                 Ok(_) => {
                     debug!(volume = %volume_name, "No clones to promote");
@@ -1174,22 +3307,16 @@ impl StorageAgent for StorageService {
             }
         }
 
-        // Check if this volume is a clone (has an origin snapshot)
-        // We need this info BEFORE deletion to clean up temp snapshots afterward
-        let origin_info: Option<String> = {
-            let zfs = self.zfs.read().await;
-            match zfs.get_origin(&volume_name).await {
-                Ok(origin) => origin,
-                Err(e) => {
-                    debug!(
-                        volume = %volume_name,
-                        error = %e,
-                        "Could not get origin (volume may not exist)"
-                    );
-                    None
-                }
+        // A retried delete that already removed the export stays in Deleting;
+        // everything else starts unexporting.
+        let delete_state = {
+            let volumes = self.volumes.read().await;
+            match volumes.get(&req.volume_id).map(|m| m.state) {
+                Some(VolumeState::Deleting) => VolumeState::Deleting,
+                _ => VolumeState::Unexporting,
             }
         };
+        self.transition_volume(&req.volume_id, delete_state).await;
 
         // Try to unexport the volume via unified CTL manager
         // This is idempotent - if already unexported, we continue
@@ -1229,6 +3356,9 @@ impl StorageAgent for StorageService {
             )));
         }
 
+        self.transition_volume(&req.volume_id, VolumeState::Deleting)
+            .await;
+
         // Clear ZFS metadata before deleting (for consistency)
         {
             let zfs = self.zfs.read().await;
@@ -1246,115 +3376,32 @@ impl StorageAgent for StorageService {
             let zfs = self.zfs.read().await;
             if let Err(e) = zfs.delete_volume(&volume_name).await {
                 timer.failure("zfs_error");
-                return Err(Status::internal(format!(
-                    "failed to delete ZFS volume: {}",
-                    e
-                )));
+                return Err(self.zfs_failure("failed to delete ZFS volume", &e));
             }
         }
 
-        // Clean up origin snapshot if this was a clone from PVC-to-PVC cloning
-        // We only clean up snapshots with our "pvc-clone-" prefix to avoid
-        // accidentally deleting user-created snapshots
-        if let Some(origin) = origin_info
-            && let Some(snap_name) = origin.rsplit('@').next()
-            && snap_name.starts_with("pvc-clone-")
-            && let Some(source_path) = origin.rsplit_once('@').map(|(p, _)| p)
-        {
-            // Origin format: "pool/dataset/volume@snapshot_name"
-            // Extract just the volume name from the full path
-            // SAFETY: Validate the path contains '/' before extracting - if not, skip cleanup
-            let Some(source_volume) = source_path.rsplit('/').next() else {
-                warn!(
-                    origin = %origin,
-                    source_path = %source_path,
-                    "Unexpected origin path format (no '/'), skipping snapshot cleanup"
-                );
-                // Don't continue with cleanup - path parsing failed
-                return Ok(Response::new(DeleteVolumeResponse {}));
-            };
-
-            // Additional validation: volume name should not be empty or contain unexpected chars
-            if source_volume.is_empty() || source_volume.contains('@') {
-                warn!(
-                    origin = %origin,
-                    source_volume = %source_volume,
-                    "Invalid source volume name extracted, skipping snapshot cleanup"
-                );
-                return Ok(Response::new(DeleteVolumeResponse {}));
-            }
-
-            info!(
-                deleted_clone = %volume_name,
-                origin_snapshot = %origin,
-                source_volume = %source_volume,
-                "Attempting to clean up temp snapshot from PVC cloning"
-            );
-
-            let zfs = self.zfs.read().await;
-            // Check if snapshot still has other clones
-            match zfs.list_clones_for_volume(source_volume).await {
-                Ok(clones) => {
-                    // Filter to clones of this specific snapshot
-                    let snap_clones: Vec<_> =
-                        clones.iter().filter(|(sn, _)| sn == snap_name).collect();
-
-                    if snap_clones.is_empty() {
-                        // No more clones, safe to delete the temp snapshot
-                        if let Err(e) = zfs.delete_snapshot(source_volume, snap_name).await {
-                            warn!(
-                                snapshot = %snap_name,
-                                source_volume = %source_volume,
-                                error = %e,
-                                "Failed to clean up temp snapshot (may already be deleted)"
-                            );
-                        } else {
-                            info!(
-                                snapshot = %snap_name,
-                                source_volume = %source_volume,
-                                "Cleaned up temp snapshot from PVC cloning"
-                            );
-                        }
-                    } else {
-                        debug!(
-                            snapshot = %snap_name,
-                            remaining_clones = snap_clones.len(),
-                            "Temp snapshot still has clones, not deleting"
-                        );
-                    }
-                }
-                Err(e) => {
-                    debug!(
-                        source_volume = %source_volume,
-                        error = %e,
-                        "Could not check clones for cleanup"
-                    );
-                }
-            }
-        }
+        // Volume is gone; stop tracking it before the best-effort snapshot
+        // cleanup below
+        self.forget_volume(&req.volume_id).await;
 
-        // Remove metadata from cache
-        {
-            let mut volumes = self.volumes.write().await;
-            volumes.remove(&req.volume_id);
-            metrics::set_volumes_count(volumes.len());
+        // Clean up origin snapshot if this was a clone from PVC-to-PVC cloning
+        if let Some(origin) = origin_info {
+            self.cleanup_clone_snapshot(&volume_name, &origin).await;
         }
 
         info!("Deleted volume: {}", req.volume_id);
+        self.pool_writable();
         timer.success();
         Ok(Response::new(DeleteVolumeResponse {}))
     }
 
     /// Expand (resize) a volume
     #[instrument(skip(self, request))]
-    async fn expand_volume(
+    async fn handle_expand_volume(
         &self,
         request: Request<ExpandVolumeRequest>,
     ) -> Result<Response<ExpandVolumeResponse>, Status> {
-        let timer = OperationTimer::new("expand_volume");
-
-        // Rate limiting: acquire permit before proceeding
-        let _permit = self.acquire_permit("expand_volume").await?;
+        let mut timer = OperationTimer::with_export_type("expand_volume", ExportLabel::Unspecified);
 
         let req = request.into_inner();
         info!(
@@ -1366,10 +3413,20 @@ impl StorageAgent for StorageService {
             timer.failure("invalid_argument");
             return Err(Status::invalid_argument("volume_id cannot be empty"));
         }
+        let _volume_lock = self.volume_locks.lock(&req.volume_id).await;
+        let _permit = self.acquire_permit("expand_volume").await?;
         if req.new_size_bytes <= 0 {
             timer.failure("invalid_argument");
             return Err(Status::invalid_argument("new_size_bytes must be positive"));
         }
+        if let Err(e) = check_max_volume_size(
+            "new_size_bytes",
+            req.new_size_bytes as u64,
+            self.max_volume_size,
+        ) {
+            timer.failure("out_of_range");
+            return Err(e);
+        }
 
         // Verify volume exists
         let metadata = {
@@ -1385,16 +3442,71 @@ impl StorageAgent for StorageService {
                 }
             }
         };
+        timer.set_export_type(metadata.export_type.into());
+
+        // The quota was checked against the original size; it is not raised
+        if let Some(quota) = metadata
+            .parameters
+            .get(QUOTA_PARAM)
+            .and_then(|v| parse_byte_size(v))
+            && let Err(e) = check_quota(quota, req.new_size_bytes as u64)
+        {
+            timer.failure("out_of_range");
+            return Err(Status::out_of_range(e.to_string()));
+        }
+
+        // A thick volume's reservation grows with it. Check the pool can back
+        // it up front rather than surface a generic ZFS failure; thin volumes
+        // reserve nothing. As when reporting the mode, the applied reservation
+        // decides: a clone or copy of a thick request has none.
+        let thick = {
+            let zfs = self.zfs.read().await;
+            let reserved = match zfs.get_dataset(&metadata.name).await {
+                Ok(dataset) => dataset.refreservation,
+                Err(e) => {
+                    timer.failure("zfs_error");
+                    return Err(self.zfs_failure("failed to read volume reservation", &e));
+                }
+            };
+            if is_thick(reserved) {
+                let available = match zfs.get_capacity().await {
+                    Ok(capacity) => capacity.available,
+                    Err(e) => {
+                        timer.failure("zfs_error");
+                        return Err(self.zfs_failure("failed to get capacity", &e));
+                    }
+                };
+                if let Err(e) = check_expand_reservation(
+                    &req.volume_id,
+                    reserved,
+                    req.new_size_bytes as u64,
+                    available,
+                ) {
+                    timer.failure("resource_exhausted");
+                    return Err(e);
+                }
+            }
+            is_thick(reserved)
+        };
 
-        // Resize ZFS volume
+        // Resize ZFS volume; the cached size is stale either way
+        self.existence_cache.invalidate(&req.volume_id);
         {
             let zfs = self.zfs.read().await;
-            if let Err(e) = zfs
-                .resize_volume(&metadata.name, req.new_size_bytes as u64)
-                .await
-            {
+            let new_size_bytes = req.new_size_bytes as u64;
+            let resized = match VolumeBackend::from_parameters(&metadata.parameters) {
+                Ok(VolumeBackend::File) => {
+                    zfs.resize_backing_file(&metadata.name, new_size_bytes, thick)
+                        .await
+                }
+                _ => {
+                    zfs.resize_volume(&metadata.name, new_size_bytes, thick)
+                        .await
+                }
+            };
+            if let Err(e) = resized {
                 timer.failure("zfs_error");
-                return Err(Status::internal(format!("failed to resize volume: {}", e)));
+                return Err(self.zfs_failure("failed to resize volume", &e));
             }
         }
 
@@ -1403,50 +3515,206 @@ impl StorageAgent for StorageService {
             req.volume_id, req.new_size_bytes
         );
 
+        self.pool_writable();
         timer.success();
         Ok(Response::new(ExpandVolumeResponse {
             size_bytes: req.new_size_bytes,
         }))
     }
 
-    /// List all volumes
+    /// Replace a volume's credentials, e.g. to rotate its CHAP secret
+    ///
+    /// The volume's auth-group is regenerated and ctld reloaded, and the
+    /// auth-group name recorded in its ZFS metadata. Credentials equal to the
+    /// current ones change nothing.
     #[instrument(skip(self, request))]
-    async fn list_volumes(
+    async fn handle_update_volume_auth(
         &self,
-        request: Request<ListVolumesRequest>,
-    ) -> Result<Response<ListVolumesResponse>, Status> {
-        let req = request.into_inner();
-        debug!(
-            "ListVolumes request: max_entries={}, starting_token={}",
-            req.max_entries, req.starting_token
-        );
+        request: Request<UpdateVolumeAuthRequest>,
+    ) -> Result<Response<UpdateVolumeAuthResponse>, Status> {
+        let mut timer =
+            OperationTimer::with_export_type("update_volume_auth", ExportLabel::Unspecified);
 
-        // Get ZFS datasets
-        let datasets = {
-            let zfs = self.zfs.read().await;
-            zfs.list_volumes()
-                .await
-                .map_err(|e| Status::internal(format!("failed to list volumes: {}", e)))?
-        };
+        let req = request.into_inner();
+        info!("UpdateVolumeAuth request: volume_id={}", req.volume_id);
 
-        // Build response with metadata
-        let volumes_meta = self.volumes.read().await;
-        let mut volumes = Vec::new();
+        if req.volume_id.is_empty() {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument("volume_id cannot be empty"));
+        }
+        let _volume_lock = self.volume_locks.lock(&req.volume_id).await;
+        let _permit = self.acquire_permit("update_volume_auth").await?;
 
-        for dataset in &datasets {
-            // Extract volume name from full dataset path
-            let name = dataset.name.rsplit('/').next().unwrap_or(&dataset.name);
+        if let Err(e) = validate_auth_credentials(req.auth.as_ref()) {
+            timer.failure("validation_error");
+            return Err(e);
+        }
+        let auth = proto_to_ctl_auth(req.auth.as_ref());
 
-            if let Some(metadata) = volumes_meta.get(name) {
-                volumes.push(self.dataset_to_volume(dataset, metadata));
-            } else {
-                // Volume exists in ZFS but not in our metadata (orphaned or created externally)
-                debug!("Found ZFS volume without metadata: {}", name);
+        let metadata = {
+            let volumes = self.volumes.read().await;
+            match volumes.get(&req.volume_id).cloned() {
+                Some(m) => m,
+                None => {
+                    timer.failure("not_found");
+                    return Err(Status::not_found(format!(
+                        "volume '{}' not found",
+                        req.volume_id
+                    )));
+                }
             }
+        };
+        timer.set_export_type(metadata.export_type.into());
+        if let Err(e) = check_auth_export_type(metadata.export_type, &auth) {
+            timer.failure("invalid_argument");
+            return Err(e);
         }
 
-        let (paginated_volumes, next_token) =
-            paginate(volumes, req.max_entries, &req.starting_token)?;
+        // After a restart the cache only knows the auth-group name, so the
+        // first update always rewrites the config
+        if metadata.auth == auth {
+            debug!(volume = %req.volume_id, "Credentials unchanged");
+            timer.success();
+            return Ok(Response::new(UpdateVolumeAuthResponse { changed: false }));
+        }
+
+        let previous = {
+            let ctl = self.ctl.read().await;
+            match ctl.update_auth(&req.volume_id, auth.clone()) {
+                Ok(previous) => previous,
+                Err(CtlError::TargetNotFound(_)) => {
+                    timer.failure("failed_precondition");
+                    return Err(Status::failed_precondition(format!(
+                        "volume '{}' is not exported",
+                        req.volume_id
+                    )));
+                }
+                Err(e) => {
+                    timer.failure("export_error");
+                    return Err(Status::internal(format!(
+                        "failed to update authentication: {}",
+                        e
+                    )));
+                }
+            }
+        };
+
+        if let Err(e) = self.config_writer.write_config().await {
+            error!("Failed to write CTL config: {}", e);
+            // Keep the export in line with the credentials ctld still uses
+            if let Err(e) = self.ctl.read().await.update_auth(&req.volume_id, previous) {
+                warn!(volume = %req.volume_id, error = %e, "Failed to restore previous authentication");
+            }
+            timer.failure("config_write_error");
+            return Err(Status::internal(format!("CTL config write failed: {}", e)));
+        }
+
+        // Credentials stay in the CTL config; ZFS only records the group name
+        let auth_group_name = auth.auth_group_name(&req.volume_id);
+        {
+            let zfs = self.zfs.read().await;
+            match zfs.get_volume_metadata(&req.volume_id).await {
+                Ok(MissingMetadataLookup::Found(mut zfs_metadata)) => {
+                    if zfs_metadata.auth_group.as_deref() != Some(auth_group_name.as_str()) {
+                        zfs_metadata.auth_group = Some(auth_group_name);
+                        if let Err(e) = zfs.set_volume_metadata(&req.volume_id, &zfs_metadata).await
+                        {
+                            timer.failure("zfs_error");
+                            return Err(self.zfs_failure("failed to update volume metadata", &e));
+                        }
+                    }
+                }
+                Ok(_) => {
+                    warn!(volume = %req.volume_id, "No CSI metadata to record the auth-group in");
+                }
+                Err(e) => {
+                    timer.failure("zfs_error");
+                    return Err(self.zfs_failure("failed to read volume metadata", &e));
+                }
+            }
+        }
+
+        if let Some(cached) = self.volumes.write().await.get_mut(&req.volume_id) {
+            cached.auth = auth;
+        }
+
+        info!("Updated authentication of volume {}", req.volume_id);
+        timer.success();
+        Ok(Response::new(UpdateVolumeAuthResponse { changed: true }))
+    }
+
+    /// List all volumes
+    #[instrument(skip(self, request))]
+    async fn handle_list_volumes(
+        &self,
+        request: Request<ListVolumesRequest>,
+    ) -> Result<Response<ListVolumesResponse>, Status> {
+        let req = request.into_inner();
+        debug!(
+            "ListVolumes request: max_entries={}, starting_token={}",
+            req.max_entries, req.starting_token
+        );
+
+        // Get ZFS datasets
+        let datasets = {
+            let zfs = self.zfs.read().await;
+            zfs.list_volumes()
+                .await
+                .map_err(|e| Status::internal(format!("failed to list volumes: {}", e)))?
+        };
+
+        // Build response with metadata, reading it from ZFS for volumes the
+        // cache is missing (e.g. after a partial restore)
+        let mut metadata: HashMap<String, VolumeMetadata> = HashMap::new();
+        let mut uncached = Vec::new();
+        {
+            let volumes_meta = self.volumes.read().await;
+            for dataset in &datasets {
+                // Extract volume name from full dataset path
+                let name = dataset.name.rsplit('/').next().unwrap_or(&dataset.name);
+                match volumes_meta.get(name) {
+                    Some(meta) => {
+                        metadata.insert(name.to_string(), meta.clone());
+                    }
+                    None => uncached.push(name.to_string()),
+                }
+            }
+        }
+        if !uncached.is_empty() {
+            let scan = {
+                let zfs = self.zfs.read().await;
+                zfs.read_volumes_metadata(&uncached).await.map_err(|e| {
+                    Status::internal(format!("failed to read volume metadata: {}", e))
+                })?
+            };
+            for (name, zfs_meta) in &scan.volumes {
+                match volume_metadata_from_zfs(name, zfs_meta) {
+                    Ok(meta) => {
+                        // Debug only: every ListVolumes would repeat it until
+                        // the cache is repopulated
+                        debug!(volume = %name, "Volume missing from metadata cache, listing it from ZFS");
+                        metadata.insert(name.clone(), meta);
+                    }
+                    Err(e) => {
+                        warn!(volume = %name, error = %e.message(), "Invalid CSI metadata, skipping")
+                    }
+                }
+            }
+        }
+
+        let mut volumes = Vec::new();
+        for dataset in &datasets {
+            let name = dataset.name.rsplit('/').next().unwrap_or(&dataset.name);
+            if let Some(meta) = metadata.get(name) {
+                volumes.push(self.dataset_to_volume(dataset, meta).await);
+            } else {
+                // Volume exists in ZFS without CSI metadata (orphaned or created externally)
+                debug!("Found ZFS volume without metadata: {}", name);
+            }
+        }
+
+        let (paginated_volumes, next_token) =
+            paginate(volumes, req.max_entries, &req.starting_token, |v| &v.id)?;
 
         Ok(Response::new(ListVolumesResponse {
             volumes: paginated_volumes,
@@ -1456,7 +3724,7 @@ impl StorageAgent for StorageService {
 
     /// Get a single volume by ID
     #[instrument(skip(self, request))]
-    async fn get_volume(
+    async fn handle_get_volume(
         &self,
         request: Request<GetVolumeRequest>,
     ) -> Result<Response<GetVolumeResponse>, Status> {
@@ -1484,16 +3752,214 @@ impl StorageAgent for StorageService {
                 .map_err(|e| Status::internal(format!("failed to get volume info: {}", e)))?
         };
 
-        let volume = self.dataset_to_volume(&dataset, &metadata);
+        let volume = self.dataset_to_volume(&dataset, &metadata).await;
 
         Ok(Response::new(GetVolumeResponse {
             volume: Some(volume),
         }))
     }
 
+    /// Check whether a volume's target is live in CTL
+    #[instrument(skip(self, request))]
+    async fn handle_is_volume_export_ready(
+        &self,
+        request: Request<IsVolumeExportReadyRequest>,
+    ) -> Result<Response<IsVolumeExportReadyResponse>, Status> {
+        let req = request.into_inner();
+        debug!("IsVolumeExportReady request: volume_id={}", req.volume_id);
+
+        if req.volume_id.is_empty() {
+            return Err(Status::invalid_argument("volume_id cannot be empty"));
+        }
+
+        let metadata = {
+            let volumes = self.volumes.read().await;
+            volumes
+                .get(&req.volume_id)
+                .cloned()
+                .ok_or_else(|| Status::not_found(format!("volume '{}' not found", req.volume_id)))?
+        };
+
+        let export_type = to_ctl_export_type(metadata.export_type).ok_or_else(|| {
+            Status::failed_precondition(format!("volume '{}' has no export type", req.volume_id))
+        })?;
+
+        let exported = self.ctl.read().await.get_export(&req.volume_id).is_some();
+        let ready = exported
+            && is_target_live(export_type, &metadata.target_name)
+                .await
+                .map_err(|e| Status::internal(format!("failed to query CTL ports: {}", e)))?;
+
+        Ok(Response::new(IsVolumeExportReadyResponse { ready }))
+    }
+
+    /// Check whether a volume exists
+    ///
+    /// Asks ZFS directly rather than the metadata cache, but only lists the
+    /// dataset name, which is much cheaper than GetVolume's property fetch.
+    #[instrument(skip(self, request))]
+    async fn handle_volume_exists(
+        &self,
+        request: Request<VolumeExistsRequest>,
+    ) -> Result<Response<VolumeExistsResponse>, Status> {
+        let req = request.into_inner();
+        debug!("VolumeExists request: volume_id={}", req.volume_id);
+
+        if req.volume_id.is_empty() {
+            return Err(Status::invalid_argument("volume_id cannot be empty"));
+        }
+
+        let exists = match self.zfs.read().await.volume_exists(&req.volume_id).await {
+            Ok(exists) => exists,
+            // No volume can have a name that fails validation
+            Err(crate::zfs::ZfsError::InvalidName(_)) => false,
+            Err(e) => {
+                return Err(Status::internal(format!(
+                    "failed to check volume existence: {}",
+                    e
+                )));
+            }
+        };
+
+        Ok(Response::new(VolumeExistsResponse { exists }))
+    }
+
+    /// Record the nodes a volume is published to in its ZFS metadata
+    #[instrument(skip(self, request))]
+    async fn handle_set_volume_attachment(
+        &self,
+        request: Request<SetVolumeAttachmentRequest>,
+    ) -> Result<Response<SetVolumeAttachmentResponse>, Status> {
+        let attachment = request.into_inner().attachment.unwrap_or_default();
+        debug!(
+            "SetVolumeAttachment request: volume_id={}, nodes={:?}",
+            attachment.volume_id, attachment.node_ids
+        );
+
+        if attachment.volume_id.is_empty() {
+            return Err(Status::invalid_argument("volume_id cannot be empty"));
+        }
+        let volume_id = attachment.volume_id.clone();
+        let _volume_lock = self.volume_locks.lock(&volume_id).await;
+
+        let zfs = self.zfs.read().await;
+        let mut zfs_metadata = match zfs.get_volume_metadata(&volume_id).await {
+            Ok(MissingMetadataLookup::Found(metadata)) => metadata,
+            Ok(MissingMetadataLookup::DatasetNotFound) => {
+                return Err(Status::not_found(format!(
+                    "volume '{}' not found",
+                    volume_id
+                )));
+            }
+            Ok(MissingMetadataLookup::MissingMetadata) => {
+                return Err(Status::failed_precondition(format!(
+                    "volume '{}' has no CSI metadata to record the attachment in",
+                    volume_id
+                )));
+            }
+            Err(e) => return Err(self.zfs_failure("failed to read volume metadata", &e)),
+        };
+
+        let attachment = zfs_attachment(attachment);
+        if zfs_metadata.attachment != attachment {
+            zfs_metadata.attachment = attachment;
+            zfs.set_volume_metadata(&volume_id, &zfs_metadata)
+                .await
+                .map_err(|e| self.zfs_failure("failed to update volume metadata", &e))?;
+        }
+        drop(zfs);
+
+        let initiators =
+            allowed_initiators(zfs_metadata.attachment.as_ref(), zfs_metadata.export_type);
+        if let Some(metadata) = self.volumes.write().await.get_mut(&volume_id) {
+            metadata.allowed_initiators = initiators.clone();
+        }
+        self.apply_allowed_initiators(&volume_id, initiators)
+            .await?;
+
+        Ok(Response::new(SetVolumeAttachmentResponse {}))
+    }
+
+    /// Restrict a volume's export to `initiators` and write the config if
+    /// that changed it.
+    ///
+    /// A volume that is not exported (yet) gets the restriction when
+    /// reconciliation exports it. A failed write restores the previous
+    /// restriction, so the cache matches what ctld enforces.
+    async fn apply_allowed_initiators(
+        &self,
+        volume_id: &str,
+        initiators: Vec<String>,
+    ) -> Result<(), Status> {
+        let previous = match self
+            .ctl
+            .read()
+            .await
+            .set_allowed_initiators(volume_id, initiators.clone())
+        {
+            Ok(previous) => previous,
+            Err(CtlError::TargetNotFound(_)) => return Ok(()),
+            Err(e) => {
+                return Err(Status::invalid_argument(format!(
+                    "cannot restrict volume '{}' to its nodes' initiators: {}",
+                    volume_id, e
+                )));
+            }
+        };
+        if previous == initiators {
+            return Ok(());
+        }
+
+        if let Err(e) = self.config_writer.write_config().await {
+            error!("Failed to write CTL config: {}", e);
+            if let Err(e) = self
+                .ctl
+                .read()
+                .await
+                .set_allowed_initiators(volume_id, previous)
+            {
+                warn!(volume = %volume_id, error = %e, "Failed to restore allowed initiators");
+            }
+            return Err(Status::internal(format!("CTL config write failed: {}", e)));
+        }
+        Ok(())
+    }
+
+    /// Attachments recorded by SetVolumeAttachment, read back from ZFS
+    #[instrument(skip(self, _request))]
+    async fn handle_list_volume_attachments(
+        &self,
+        _request: Request<ListVolumeAttachmentsRequest>,
+    ) -> Result<Response<ListVolumeAttachmentsResponse>, Status> {
+        let scan = self
+            .zfs
+            .read()
+            .await
+            .list_volumes_with_metadata()
+            .await
+            .map_err(|e| self.zfs_failure("failed to list volumes with metadata", &e))?;
+
+        let attachments = scan
+            .volumes
+            .into_iter()
+            .filter_map(|(volume_id, metadata)| {
+                let attachment = metadata.attachment?;
+                Some(proto::VolumeAttachment {
+                    volume_id,
+                    node_ids: attachment.nodes,
+                    multi_node: attachment.multi_node,
+                    initiator_names: attachment.initiator_names,
+                    host_nqns: attachment.host_nqns,
+                })
+            })
+            .collect();
+
+        Ok(Response::new(ListVolumeAttachmentsResponse { attachments }))
+    }
+
     /// Create a snapshot of a volume
     #[instrument(skip(self, request))]
-    async fn create_snapshot(
+    async fn handle_create_snapshot(
         &self,
         request: Request<CreateSnapshotRequest>,
     ) -> Result<Response<CreateSnapshotResponse>, Status> {
@@ -1516,6 +3982,19 @@ impl StorageAgent for StorageService {
             timer.failure("invalid_argument");
             return Err(Status::invalid_argument("snapshot name cannot be empty"));
         }
+        if let Err(e) = check_reserved_name("snapshot", &req.name) {
+            timer.failure("invalid_argument");
+            return Err(e);
+        }
+        if let Err(e) = self
+            .zfs
+            .read()
+            .await
+            .check_name_length(&req.source_volume_id, Some(&req.name))
+        {
+            timer.failure("invalid_argument");
+            return Err(Status::invalid_argument(e.to_string()));
+        }
 
         // Verify source volume exists
         let _metadata = {
@@ -1535,22 +4014,74 @@ impl StorageAgent for StorageService {
         // Create ZFS snapshot
         let snapshot_name = {
             let zfs = self.zfs.read().await;
-            match zfs.create_snapshot(&req.source_volume_id, &req.name).await {
-                Ok(n) => n,
-                Err(e) => {
-                    timer.failure("zfs_error");
-                    return Err(Status::internal(format!(
-                        "failed to create snapshot: {}",
-                        e
+
+            // Whether this call took the snapshot, and so may roll it back
+            let (snapshot_name, created) =
+                match zfs.create_snapshot(&req.source_volume_id, &req.name).await {
+                    Ok(n) => (n, true),
+                    // Taken by an earlier attempt of this request
+                    Err(crate::zfs::ZfsError::DatasetExists(n)) => (n, false),
+                    Err(crate::zfs::ZfsError::DatasetNotFound(_)) => {
+                        timer.failure("not_found");
+                        return Err(Status::not_found(format!(
+                            "source volume '{}' not found",
+                            req.source_volume_id
+                        )));
+                    }
+                    Err(e) => {
+                        timer.failure("zfs_error");
+                        return Err(self.zfs_failure("failed to create snapshot", &e));
+                    }
+                };
+            let roll_back = || async {
+                if !created {
+                    return;
+                }
+                if let Err(e) = zfs.delete_snapshot(&req.source_volume_id, &req.name).await {
+                    warn!(snapshot = %snapshot_name, error = %e, "Failed to roll back snapshot");
+                }
+            };
+
+            if self.globally_unique_snapshot_names {
+                let snapshots = match zfs.list_csi_snapshots().await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        // Unchecked, the snapshot may hold a name in use elsewhere
+                        roll_back().await;
+                        timer.failure("zfs_error");
+                        return Err(Status::internal(format!("failed to list snapshots: {}", e)));
+                    }
+                };
+                if let Some(other) =
+                    conflicting_snapshot_source(&snapshots, &req.source_volume_id, &req.name)
+                {
+                    roll_back().await;
+                    timer.failure("already_exists");
+                    return Err(Status::already_exists(format!(
+                        "snapshot name '{}' is already used by volume '{}'",
+                        req.name, other
                     )));
                 }
             }
+
+            snapshot_name
         };
 
         // Create snapshot ID and timestamp
         let snapshot_id = format!("{}@{}", req.source_volume_id, req.name);
         let creation_time = unix_timestamp_now();
 
+        // Only informational, so a failed lookup does not fail the snapshot
+        let space = {
+            let zfs = self.zfs.read().await;
+            zfs.get_snapshot_space(&snapshot_name)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!(snapshot = %snapshot_name, error = %e, "Failed to read snapshot space usage");
+                    Default::default()
+                })
+        };
+
         // Note: Snapshot metadata is stored in ZFS properties by create_snapshot().
         // ListSnapshots and GetSnapshot query ZFS directly, so no in-memory cache needed.
 
@@ -1559,11 +4090,17 @@ impl StorageAgent for StorageService {
             source_volume_id: req.source_volume_id,
             name: snapshot_name,
             creation_time,
-            size_bytes: 0, // ZFS snapshots don't consume space until divergence
+            size_bytes: space.size_bytes as i64,
+            ready_to_use: true, // zfs snapshot is atomic
+            written_bytes: space.written_bytes as i64,
+            used_bytes: space.used_bytes as i64,
+            referenced_bytes: space.referenced_bytes as i64,
+            system: false,
         };
 
         info!("Created snapshot: {}", snapshot.id);
 
+        self.pool_writable();
         timer.success();
         Ok(Response::new(CreateSnapshotResponse {
             snapshot: Some(snapshot),
@@ -1572,7 +4109,7 @@ impl StorageAgent for StorageService {
 
     /// Delete a snapshot
     #[instrument(skip(self, request))]
-    async fn delete_snapshot(
+    async fn handle_delete_snapshot(
         &self,
         request: Request<DeleteSnapshotRequest>,
     ) -> Result<Response<DeleteSnapshotResponse>, Status> {
@@ -1607,9 +4144,43 @@ impl StorageAgent for StorageService {
         {
             let zfs = self.zfs.read().await;
 
+            // The direct path only holds this snapshot if its CSI tag agrees.
+            // After a clone is promoted it may instead hold a snapshot moved in
+            // from the former origin volume, which must not be touched here.
+            let direct_is_ours = match zfs.get_snapshot_id(volume_name, snap_name).await {
+                Ok(tag) => {
+                    let ours = crate::zfs::snapshot_tag_matches(tag.as_deref(), &req.snapshot_id);
+                    if !ours {
+                        info!(
+                            snapshot_id = %req.snapshot_id,
+                            tag = ?tag,
+                            "Snapshot at expected path belongs to another CSI snapshot"
+                        );
+                    }
+                    ours
+                }
+                Err(crate::zfs::ZfsError::DatasetNotFound(_)) => true,
+                Err(crate::zfs::ZfsError::InvalidName(msg)) => {
+                    timer.failure("invalid_argument");
+                    return Err(Status::invalid_argument(msg));
+                }
+                Err(e) => {
+                    timer.failure("zfs_error");
+                    return Err(Status::internal(format!(
+                        "failed to read snapshot ID: {}",
+                        e
+                    )));
+                }
+            };
+
             // Check for clones BEFORE attempting delete
             // If the snapshot has clones, return FAILED_PRECONDITION with clear message
-            match zfs.snapshot_has_clones(volume_name, snap_name).await {
+            let clones = if direct_is_ours {
+                zfs.snapshot_has_clones(volume_name, snap_name).await
+            } else {
+                Ok(Vec::new())
+            };
+            match clones {
                 Ok(clones) if !clones.is_empty() => {
                     // Extract volume names from full paths for user-friendly message
                     let pvc_names: Vec<&str> =
@@ -1640,7 +4211,14 @@ impl StorageAgent for StorageService {
             }
 
             // Try direct deletion first
-            match zfs.delete_snapshot(volume_name, snap_name).await {
+            let direct = if direct_is_ours {
+                zfs.delete_snapshot(volume_name, snap_name).await
+            } else {
+                Err(crate::zfs::ZfsError::DatasetNotFound(
+                    req.snapshot_id.clone(),
+                ))
+            };
+            match direct {
                 Ok(()) => {
                     info!(
                         snapshot_id = %req.snapshot_id,
@@ -1712,15 +4290,15 @@ impl StorageAgent for StorageService {
                                 )));
                             }
                         }
-                        Ok(FindSnapshotResult::Ambiguous(count)) => {
+                        Ok(FindSnapshotResult::Ambiguous(paths)) => {
                             // Multiple snapshots with same ID - this should never happen
                             // Refuse to delete to avoid data loss
                             timer.failure("ambiguous_snapshot");
-                            return Err(Status::failed_precondition(format!(
-                                "Found {} snapshots with ID '{}' - refusing to delete. \
-                                 This indicates a bug or data corruption.",
-                                count, req.snapshot_id
-                            )));
+                            return Err(ambiguous_snapshot_error(
+                                &req.snapshot_id,
+                                &paths,
+                                "delete",
+                            ));
                         }
                         Err(e) => {
                             timer.failure("zfs_error");
@@ -1737,10 +4315,7 @@ impl StorageAgent for StorageService {
                 }
                 Err(e) => {
                     timer.failure("zfs_error");
-                    return Err(Status::internal(format!(
-                        "failed to delete snapshot: {}",
-                        e
-                    )));
+                    return Err(self.zfs_failure("failed to delete snapshot", &e));
                 }
             }
         }
@@ -1751,6 +4326,7 @@ impl StorageAgent for StorageService {
             "Deleted snapshot: {} (volume={}, snap={})",
             req.snapshot_id, volume_name, snap_name
         );
+        self.pool_writable();
         timer.success();
         Ok(Response::new(DeleteSnapshotResponse {}))
     }
@@ -1760,7 +4336,7 @@ impl StorageAgent for StorageService {
     /// This queries ZFS directly for snapshots with the CSI metadata property,
     /// ensuring the list survives restarts and always reflects the actual ZFS state.
     #[instrument(skip(self, request))]
-    async fn list_snapshots(
+    async fn handle_list_snapshots(
         &self,
         request: Request<ListSnapshotsRequest>,
     ) -> Result<Response<ListSnapshotsResponse>, Status> {
@@ -1778,15 +4354,7 @@ impl StorageAgent for StorageService {
             })?
         };
 
-        // Filter by source volume if specified
-        let filtered: Vec<_> = if req.source_volume_id.is_empty() {
-            csi_snapshots
-        } else {
-            csi_snapshots
-                .into_iter()
-                .filter(|s| s.source_volume_id == req.source_volume_id)
-                .collect()
-        };
+        let filtered = listed_snapshots(csi_snapshots, &req.source_volume_id, req.include_system);
 
         // Convert to proto snapshots
         let snapshots: Vec<Snapshot> = filtered
@@ -1796,116 +4364,1030 @@ impl StorageAgent for StorageService {
                 source_volume_id: s.source_volume_id.clone(),
                 name: s.name.clone(),
                 creation_time: s.creation_time,
-                size_bytes: 0, // ZFS snapshots don't consume space until divergence
+                size_bytes: s.space.size_bytes as i64,
+                ready_to_use: self.in_progress_snapshots.is_ready(&s.snapshot_id),
+                written_bytes: s.space.written_bytes as i64,
+                used_bytes: s.space.used_bytes as i64,
+                referenced_bytes: s.space.referenced_bytes as i64,
+                system: s.system,
             })
             .collect();
 
-        let (paginated, next_token) = paginate(snapshots, req.max_entries, &req.starting_token)?;
+        let (paginated, next_token) =
+            paginate(snapshots, req.max_entries, &req.starting_token, |s| &s.id)?;
+
+        Ok(Response::new(ListSnapshotsResponse {
+            snapshots: paginated,
+            next_token,
+        }))
+    }
+
+    /// Get a single snapshot by ID
+    ///
+    /// This queries ZFS directly for the snapshot, ensuring accurate results
+    /// that survive restarts.
+    #[instrument(skip(self, request))]
+    async fn handle_get_snapshot(
+        &self,
+        request: Request<GetSnapshotRequest>,
+    ) -> Result<Response<GetSnapshotResponse>, Status> {
+        let req = request.into_inner();
+        debug!("GetSnapshot request: snapshot_id={}", req.snapshot_id);
+
+        if req.snapshot_id.is_empty() {
+            return Err(Status::invalid_argument("snapshot_id cannot be empty"));
+        }
+
+        // Query ZFS directly for all CSI snapshots and find the matching one
+        let csi_snapshots = {
+            let zfs = self.zfs.read().await;
+            zfs.list_csi_snapshots().await.map_err(|e| {
+                Status::internal(format!("failed to query snapshots from ZFS: {}", e))
+            })?
+        };
+
+        let snapshot_info = csi_snapshots
+            .into_iter()
+            .find(|s| s.snapshot_id == req.snapshot_id)
+            .ok_or_else(|| {
+                Status::not_found(format!("snapshot '{}' not found", req.snapshot_id))
+            })?;
+
+        let ready_to_use = self
+            .in_progress_snapshots
+            .is_ready(&snapshot_info.snapshot_id);
+        let snapshot = Snapshot {
+            id: snapshot_info.snapshot_id,
+            source_volume_id: snapshot_info.source_volume_id,
+            name: snapshot_info.name,
+            creation_time: snapshot_info.creation_time,
+            size_bytes: snapshot_info.space.size_bytes as i64,
+            ready_to_use,
+            written_bytes: snapshot_info.space.written_bytes as i64,
+            used_bytes: snapshot_info.space.used_bytes as i64,
+            referenced_bytes: snapshot_info.space.referenced_bytes as i64,
+            system: snapshot_info.system,
+        };
+
+        Ok(Response::new(GetSnapshotResponse {
+            snapshot: Some(snapshot),
+        }))
+    }
+
+    /// Get storage capacity information for the ZFS pool
+    #[instrument(skip(self, _request))]
+    async fn handle_get_capacity(
+        &self,
+        _request: Request<GetCapacityRequest>,
+    ) -> Result<Response<GetCapacityResponse>, Status> {
+        debug!("GetCapacity request");
+
+        // Get capacity from ZFS parent dataset
+        let zfs = self.zfs.read().await;
+        let capacity = zfs
+            .get_capacity()
+            .await
+            .map_err(|e| Status::internal(format!("failed to get capacity: {}", e)))?;
+
+        info!(
+            available = capacity.available,
+            used = capacity.used,
+            total = capacity.available + capacity.used,
+            "Retrieved storage capacity"
+        );
+
+        // GetCapacity is polled by the provisioner, which makes it the
+        // periodic refresh point for the capacity planning metrics.
+        metrics::set_pool_capacity(capacity.available + capacity.used);
+        if volume_stats_due(&self.volume_stats_refreshed, Instant::now()) {
+            match zfs.list_volumes().await {
+                Ok(datasets) => {
+                    let sizes: Vec<u64> = datasets.iter().filter_map(|d| d.volsize).collect();
+                    metrics::set_volume_sizes(&sizes);
+                }
+                Err(e) => warn!(error = %e, "Failed to list volumes for size metrics"),
+            }
+            match zfs.get_pool_status().await {
+                Ok(status) => metrics::set_pool_status(
+                    &status.pool,
+                    &status.health,
+                    status.fragmentation_percent,
+                ),
+                Err(e) => warn!(error = %e, "Failed to get pool status for metrics"),
+            }
+        }
+
+        Ok(Response::new(GetCapacityResponse {
+            available_capacity: capacity.available as i64,
+            total_capacity: (capacity.available + capacity.used) as i64,
+            used_capacity: capacity.used as i64,
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl StorageAgent for StorageService {
+    async fn create_volume(
+        &self,
+        request: Request<CreateVolumeRequest>,
+    ) -> Result<Response<CreateVolumeResponse>, Status> {
+        let name = request.get_ref().name.clone();
+        let secrets = request_secrets(request.get_ref().auth.as_ref());
+        let result = self.handle_create_volume(request).await;
+        let secrets: Vec<&str> = secrets.iter().map(String::as_str).collect();
+        self.recent_errors
+            .record_result("CreateVolume", &name, &result, &secrets);
+        result
+    }
+
+    async fn delete_volume(
+        &self,
+        request: Request<DeleteVolumeRequest>,
+    ) -> Result<Response<DeleteVolumeResponse>, Status> {
+        let volume_id = request.get_ref().volume_id.clone();
+        let result = self.handle_delete_volume(request).await;
+        self.recent_errors
+            .record_result("DeleteVolume", &volume_id, &result, &[]);
+        result
+    }
+
+    async fn expand_volume(
+        &self,
+        request: Request<ExpandVolumeRequest>,
+    ) -> Result<Response<ExpandVolumeResponse>, Status> {
+        let volume_id = request.get_ref().volume_id.clone();
+        let result = self.handle_expand_volume(request).await;
+        self.recent_errors
+            .record_result("ExpandVolume", &volume_id, &result, &[]);
+        result
+    }
+
+    async fn update_volume_auth(
+        &self,
+        request: Request<UpdateVolumeAuthRequest>,
+    ) -> Result<Response<UpdateVolumeAuthResponse>, Status> {
+        let volume_id = request.get_ref().volume_id.clone();
+        let secrets = request_secrets(request.get_ref().auth.as_ref());
+        let result = self.handle_update_volume_auth(request).await;
+        let secrets: Vec<&str> = secrets.iter().map(String::as_str).collect();
+        self.recent_errors
+            .record_result("UpdateVolumeAuth", &volume_id, &result, &secrets);
+        result
+    }
+
+    async fn list_volumes(
+        &self,
+        request: Request<ListVolumesRequest>,
+    ) -> Result<Response<ListVolumesResponse>, Status> {
+        self.handle_list_volumes(request).await
+    }
+
+    async fn get_volume(
+        &self,
+        request: Request<GetVolumeRequest>,
+    ) -> Result<Response<GetVolumeResponse>, Status> {
+        self.handle_get_volume(request).await
+    }
+
+    async fn is_volume_export_ready(
+        &self,
+        request: Request<IsVolumeExportReadyRequest>,
+    ) -> Result<Response<IsVolumeExportReadyResponse>, Status> {
+        self.handle_is_volume_export_ready(request).await
+    }
+
+    async fn volume_exists(
+        &self,
+        request: Request<VolumeExistsRequest>,
+    ) -> Result<Response<VolumeExistsResponse>, Status> {
+        self.handle_volume_exists(request).await
+    }
+
+    async fn set_volume_attachment(
+        &self,
+        request: Request<SetVolumeAttachmentRequest>,
+    ) -> Result<Response<SetVolumeAttachmentResponse>, Status> {
+        self.handle_set_volume_attachment(request).await
+    }
+
+    async fn list_volume_attachments(
+        &self,
+        request: Request<ListVolumeAttachmentsRequest>,
+    ) -> Result<Response<ListVolumeAttachmentsResponse>, Status> {
+        self.handle_list_volume_attachments(request).await
+    }
+
+    async fn create_snapshot(
+        &self,
+        request: Request<CreateSnapshotRequest>,
+    ) -> Result<Response<CreateSnapshotResponse>, Status> {
+        let snapshot_id = format!(
+            "{}@{}",
+            request.get_ref().source_volume_id,
+            request.get_ref().name
+        );
+        let result = self.handle_create_snapshot(request).await;
+        self.recent_errors
+            .record_result("CreateSnapshot", &snapshot_id, &result, &[]);
+        result
+    }
+
+    async fn delete_snapshot(
+        &self,
+        request: Request<DeleteSnapshotRequest>,
+    ) -> Result<Response<DeleteSnapshotResponse>, Status> {
+        let snapshot_id = request.get_ref().snapshot_id.clone();
+        let result = self.handle_delete_snapshot(request).await;
+        self.recent_errors
+            .record_result("DeleteSnapshot", &snapshot_id, &result, &[]);
+        result
+    }
+
+    async fn list_snapshots(
+        &self,
+        request: Request<ListSnapshotsRequest>,
+    ) -> Result<Response<ListSnapshotsResponse>, Status> {
+        self.handle_list_snapshots(request).await
+    }
+
+    async fn get_snapshot(
+        &self,
+        request: Request<GetSnapshotRequest>,
+    ) -> Result<Response<GetSnapshotResponse>, Status> {
+        self.handle_get_snapshot(request).await
+    }
+
+    async fn get_capacity(
+        &self,
+        request: Request<GetCapacityRequest>,
+    ) -> Result<Response<GetCapacityResponse>, Status> {
+        self.handle_get_capacity(request).await
+    }
+
+    /// Health and fragmentation of the pool holding the parent dataset
+    async fn get_pool_status(
+        &self,
+        _request: Request<GetPoolStatusRequest>,
+    ) -> Result<Response<GetPoolStatusResponse>, Status> {
+        let status = {
+            let zfs = self.zfs.read().await;
+            zfs.get_pool_status()
+                .await
+                .map_err(|e| Status::internal(format!("failed to get pool status: {}", e)))?
+        };
+        metrics::set_pool_status(&status.pool, &status.health, status.fragmentation_percent);
+
+        Ok(Response::new(GetPoolStatusResponse {
+            pool: status.pool,
+            health: status.health,
+            fragmentation_percent: status.fragmentation_percent,
+            capacity_percent: status.capacity_percent,
+        }))
+    }
+
+    /// Recently failed mutating operations, newest first
+    async fn get_recent_errors(
+        &self,
+        request: Request<GetRecentErrorsRequest>,
+    ) -> Result<Response<GetRecentErrorsResponse>, Status> {
+        let max_entries = request.into_inner().max_entries as usize;
+        let errors = self
+            .recent_errors
+            .list(max_entries)
+            .into_iter()
+            .map(|e| proto::RecentError {
+                operation: e.operation,
+                resource_id: e.resource_id,
+                code: e.code,
+                message: e.message,
+                timestamp: e.timestamp,
+            })
+            .collect();
+
+        Ok(Response::new(GetRecentErrorsResponse { errors }))
+    }
+
+    /// Snapshot IDs that DeleteSnapshot would refuse as ambiguous
+    async fn list_ambiguous_snapshots(
+        &self,
+        _request: Request<ListAmbiguousSnapshotsRequest>,
+    ) -> Result<Response<ListAmbiguousSnapshotsResponse>, Status> {
+        let ambiguous = {
+            let zfs = self.zfs.read().await;
+            zfs.list_ambiguous_snapshots()
+                .await
+                .map_err(|e| Status::internal(format!("failed to list snapshots: {}", e)))?
+        };
+        let snapshots = ambiguous
+            .into_iter()
+            .map(|(snapshot_id, paths)| proto::AmbiguousSnapshot { snapshot_id, paths })
+            .collect();
+
+        Ok(Response::new(ListAmbiguousSnapshotsResponse { snapshots }))
+    }
+
+    /// Agent release and API revision
+    async fn get_system_info(
+        &self,
+        _request: Request<GetSystemInfoRequest>,
+    ) -> Result<Response<GetSystemInfoResponse>, Status> {
+        Ok(Response::new(GetSystemInfoResponse {
+            agent_version: env!("CARGO_PKG_VERSION").to_string(),
+            api_version: API_VERSION,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_provisioning_thin_and_thick() {
+        let requested = |mode: &str| Volume {
+            parameters: HashMap::from([(PROVISIONING_MODE_PARAM.to_string(), mode.to_string())]),
+            size_bytes: 1 << 30,
+            ..Default::default()
+        };
+
+        let mut thin = requested("thin");
+        report_provisioning(&mut thin, 0);
+        assert_eq!(thin.provisioning_mode(), ProvisioningMode::Thin);
+        assert_eq!(thin.refreservation_bytes, 0);
+        assert_eq!(thin.parameters[PROVISIONING_MODE_PARAM], "thin");
+
+        let mut thick = requested("thick");
+        report_provisioning(&mut thick, 1 << 30);
+        assert_eq!(thick.provisioning_mode(), ProvisioningMode::Thick);
+        assert_eq!(thick.refreservation_bytes, 1 << 30);
+        assert_eq!(thick.parameters[PROVISIONING_MODE_PARAM], "thick");
+
+        // The applied reservation wins over the request: a thick clone has
+        // no reservation, and the mode is echoed even when never requested
+        let mut clone = requested("thick");
+        report_provisioning(&mut clone, 0);
+        assert_eq!(clone.provisioning_mode(), ProvisioningMode::Thin);
+        assert_eq!(clone.parameters[PROVISIONING_MODE_PARAM], "thin");
+
+        let mut unset = Volume::default();
+        report_provisioning(&mut unset, 0);
+        assert_eq!(unset.parameters[PROVISIONING_MODE_PARAM], "thin");
+    }
+
+    #[test]
+    fn test_clone_without_reservation_expands_thin() {
+        // A clone of a thick StorageClass carries the thick parameters in its
+        // metadata but has no reservation; expanding it must not add one
+        let parameters =
+            HashMap::from([(PROVISIONING_MODE_PARAM.to_string(), "thick".to_string())]);
+        assert!(reserves_full_size(&parameters).unwrap());
+        assert!(!is_thick(0));
+        assert!(is_thick(1 << 30));
+
+        let mut clone = Volume {
+            parameters,
+            ..Default::default()
+        };
+        report_provisioning(&mut clone, 0);
+        assert_eq!(clone.provisioning_mode(), ProvisioningMode::Thin);
+    }
+
+    #[test]
+    fn test_max_volume_size_rejects_create_above_limit() {
+        let max = Some(100 << 30);
+        assert!(check_max_volume_size("size_bytes", 100 << 30, max).is_ok());
+        let err = check_max_volume_size("size_bytes", (100 << 30) + 1, max).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::OutOfRange);
+        assert!(err.message().contains("size_bytes"));
+
+        // No limit configured
+        assert!(check_max_volume_size("size_bytes", u64::MAX, None).is_ok());
+    }
+
+    #[test]
+    fn test_max_volume_size_rejects_expand_above_limit() {
+        let max = Some(1 << 40);
+        assert!(check_max_volume_size("new_size_bytes", 1 << 40, max).is_ok());
+        let err = check_max_volume_size("new_size_bytes", (1 << 40) + 4096, max).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::OutOfRange);
+        assert!(err.message().contains("new_size_bytes"));
+    }
+
+    #[test]
+    fn test_parse_volume_size_limit() {
+        assert_eq!(parse_volume_size_limit("1073741824").unwrap(), 1 << 30);
+        assert_eq!(parse_volume_size_limit("500G").unwrap(), 500 << 30);
+        assert_eq!(parse_volume_size_limit("2Ti").unwrap(), 2 << 40);
+        assert_eq!(parse_volume_size_limit("64m").unwrap(), 64 << 20);
+        for value in ["", "0", "G", "1.5T", "10i", "-1G", "1P", "99999999999T"] {
+            assert!(
+                parse_volume_size_limit(value).is_err(),
+                "{value:?} accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn test_clone_of_encrypted_origin_requires_matching_keys() {
+        let origin = Encryption {
+            key_format: "raw".to_string(),
+            key_location: "file:///etc/csi/keys/a.key".to_string(),
+        };
+        let other = Encryption {
+            key_location: "file:///etc/csi/keys/b.key".to_string(),
+            ..origin.clone()
+        };
+
+        for linked in [true, false] {
+            assert!(check_origin_encryption(Some(&origin), Some(&origin), linked).is_ok());
+            let err = check_origin_encryption(None, Some(&origin), linked).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument);
+            assert!(check_origin_encryption(Some(&other), Some(&origin), linked).is_err());
+            assert!(check_origin_encryption(None, None, linked).is_ok());
+        }
+
+        // Only a copy can encrypt an unencrypted origin
+        assert!(check_origin_encryption(Some(&origin), None, true).is_err());
+        assert!(check_origin_encryption(Some(&origin), None, false).is_ok());
+    }
+
+    #[test]
+    fn test_zfs_attachment() {
+        let strings = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let attachment = |node_ids: &[&str]| proto::VolumeAttachment {
+            volume_id: "pvc-1".to_string(),
+            node_ids: strings(node_ids),
+            multi_node: true,
+            initiator_names: strings(&["iqn.x:b", "iqn.x:a"]),
+            host_nqns: Vec::new(),
+        };
+        assert_eq!(zfs_attachment(attachment(&[])), None);
+        let saved = zfs_attachment(attachment(&["node-b", "node-a", "node-b"]));
+        assert_eq!(
+            saved,
+            Some(ZfsVolumeAttachment {
+                nodes: strings(&["node-a", "node-b"]),
+                multi_node: true,
+                initiator_names: strings(&["iqn.x:a", "iqn.x:b"]),
+                host_nqns: Vec::new(),
+            })
+        );
+
+        assert_eq!(
+            allowed_initiators(saved.as_ref(), CtlExportType::Iscsi),
+            ["iqn.x:a", "iqn.x:b"]
+        );
+        assert!(allowed_initiators(saved.as_ref(), CtlExportType::Nvmeof).is_empty());
+        assert!(allowed_initiators(None, CtlExportType::Iscsi).is_empty());
+    }
+
+    #[test]
+    fn test_check_content_source_missing_volume() {
+        for snapshot in [None, Some(("snap1", true)), Some(("snap1", false))] {
+            let err = check_content_source("pvc-src", false, snapshot).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::NotFound);
+            assert!(err.message().contains("volume 'pvc-src'"));
+        }
+    }
+
+    #[test]
+    fn test_check_content_source_missing_snapshot() {
+        let err = check_content_source("pvc-src", true, Some(("snap1", false))).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+        assert!(err.message().contains("'pvc-src@snap1'"));
+
+        assert!(check_content_source("pvc-src", true, Some(("snap1", true))).is_ok());
+        assert!(check_content_source("pvc-src", true, None).is_ok());
+    }
+
+    #[test]
+    fn test_check_volblocksize() {
+        let params = HashMap::from([(VOLBLOCKSIZE_PARAM.to_string(), "16K".to_string())]);
+        assert!(check_volblocksize(&params, VolumeBackend::Zvol, false, None).is_ok());
+        assert!(check_volblocksize(&HashMap::new(), VolumeBackend::File, true, None).is_ok());
+
+        let err = check_volblocksize(&params, VolumeBackend::File, false, None).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert!(err.message().contains(RECORD_SIZE_PARAM));
+
+        let err = check_volblocksize(&params, VolumeBackend::Zvol, true, None).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert!(err.message().contains("inherited from the origin"));
+
+        // Repeating the origin's block size is fine, in any spelling
+        let origin = HashMap::from([(VOLBLOCKSIZE_PARAM.to_string(), "16384".to_string())]);
+        assert!(check_volblocksize(&params, VolumeBackend::Zvol, true, Some(&origin)).is_ok());
+        let other = HashMap::from([(VOLBLOCKSIZE_PARAM.to_string(), "64K".to_string())]);
+        assert!(check_volblocksize(&params, VolumeBackend::Zvol, true, Some(&other)).is_err());
+
+        let bad = HashMap::from([(VOLBLOCKSIZE_PARAM.to_string(), "3K".to_string())]);
+        let err = check_volblocksize(&bad, VolumeBackend::Zvol, false, None).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_clone_inherits_volblocksize() {
+        let origin = HashMap::from([
+            (VOLBLOCKSIZE_PARAM.to_string(), "64K".to_string()),
+            ("exportType".to_string(), "iscsi".to_string()),
+        ]);
+        let request = HashMap::from([("exportType".to_string(), "nvmeof".to_string())]);
+
+        let params = with_inherited_volblocksize(request.clone(), Some(&origin));
+        assert_eq!(params[VOLBLOCKSIZE_PARAM], "64K");
+        assert_eq!(params["exportType"], "nvmeof");
+
+        assert_eq!(with_inherited_volblocksize(request.clone(), None), request);
+    }
+
+    #[test]
+    fn test_zfs_error_status_pool_unavailable() {
+        let status = zfs_error_status(
+            "failed to create ZFS volume",
+            &crate::zfs::ZfsError::PoolUnavailable(
+                "tank/csi/pvc-1: cannot create 'tank/csi/pvc-1': pool is read-only".to_string(),
+            ),
+        );
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        assert!(status.message().contains("pool is read-only"));
+
+        let status = zfs_error_status(
+            "failed to create ZFS volume",
+            &crate::zfs::ZfsError::CommandFailed("out of space".to_string()),
+        );
+        assert_eq!(status.code(), tonic::Code::Internal);
+
+        // A copy cut short by agent shutdown is retried after the restart
+        let status = zfs_error_status(
+            "failed to copy volume from snapshot",
+            &crate::zfs::ZfsError::Aborted("copy interrupted by agent shutdown".to_string()),
+        );
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+    }
+
+    #[test]
+    fn test_reserved_name_prefixes_rejected() {
+        let err = check_reserved_name("snapshot", "pvc-clone-pvc-b-1700000000").unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert!(err.message().contains("pvc-clone-"));
+        assert!(check_reserved_name("volume", "pvc-clone-data").is_err());
+        assert!(check_reserved_name("snapshot", "csi-auto-1700000000").is_err());
+    }
+
+    #[test]
+    fn test_check_expand_reservation() {
+        let gib = 1u64 << 30;
+        // Growing a 10G thick volume to 15G needs 5G more
+        assert!(check_expand_reservation("pvc-1", 10 * gib, 15 * gib, 5 * gib).is_ok());
+        assert!(check_expand_reservation("pvc-1", 10 * gib, 15 * gib, 20 * gib).is_ok());
+
+        let err = check_expand_reservation("pvc-1", 10 * gib, 15 * gib, 4 * gib).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(
+            err.message(),
+            format!(
+                "expanding thick volume 'pvc-1' to {} bytes needs {} more bytes of reserved \
+                 space, but only {} bytes are available",
+                15 * gib,
+                5 * gib,
+                4 * gib
+            )
+        );
+
+        // A reservation already at the new size needs nothing more
+        assert!(check_expand_reservation("pvc-1", 15 * gib, 15 * gib, 0).is_ok());
+    }
+
+    #[test]
+    fn test_check_ctl_options() {
+        let params = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert!(check_ctl_options(&params(&[])).is_ok());
+        assert!(
+            check_ctl_options(&params(&[
+                ("blockSize", "4096"),
+                ("physicalBlockSize", "8192"),
+                ("enable_unmap", "on"),
+                ("removable", "false"),
+            ]))
+            .is_ok()
+        );
+
+        for (key, value) in [
+            ("blockSize", "1024"),
+            ("blockSize", "4k"),
+            ("physicalBlockSize", "-1"),
+            ("enableUnmap", "maybe"),
+            ("removable", "sometimes"),
+        ] {
+            let err = check_ctl_options(&params(&[(key, value)])).unwrap_err();
+            assert_eq!(err.key, key);
+            assert_eq!(err.value, value);
+        }
+    }
+
+    #[test]
+    fn test_invalid_removable_is_rejected() {
+        for key in ["removable", "Removable", "REMOVABLE"] {
+            let raw = HashMap::from([(key.to_string(), "sometimes".to_string())]);
+            let err = check_ctl_options(&raw).unwrap_err();
+            assert_eq!(
+                Status::invalid_argument(err.to_string()).message(),
+                "invalid removable 'sometimes': expected true or false"
+            );
+            // Restoring an existing export still tolerates the bad value
+            assert_eq!(parse_ctl_options(&raw).removable, None);
+        }
+
+        let raw = HashMap::from([("removable".to_string(), "yes".to_string())]);
+        assert!(check_ctl_options(&raw).is_ok());
+        assert_eq!(parse_ctl_options(&raw).removable, Some(true));
+    }
+
+    #[test]
+    fn test_check_group_override() {
+        let mut params = HashMap::new();
+        assert_eq!(
+            check_group_override(&params, ExportType::Iscsi).unwrap(),
+            None
+        );
+
+        params.insert(PORTAL_GROUP_PARAM.to_string(), "pg-storage".to_string());
+        assert_eq!(
+            check_group_override(&params, ExportType::Iscsi).unwrap(),
+            Some("pg-storage".to_string())
+        );
+        // A portal group means nothing to an NVMeoF export
+        let err = check_group_override(&params, ExportType::Nvmeof).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert!(err.message().contains(PORTAL_GROUP_PARAM));
+
+        params.insert(PORTAL_GROUP_PARAM.to_string(), "pg\n0".to_string());
+        assert!(check_group_override(&params, ExportType::Iscsi).is_err());
+    }
+
+    #[test]
+    fn test_group_override_survives_reconciliation() {
+        let mut params = HashMap::new();
+        params.insert(TRANSPORT_GROUP_PARAM.to_string(), "tg-fast".to_string());
+        let metadata = ZfsVolumeMetadata::new(
+            CtlExportType::Nvmeof,
+            "nqn.2024-01.org.freebsd.csi:pvc-a".to_string(),
+            None,
+            Some(1),
+            params,
+            0,
+            None,
+        );
+
+        // Reconciliation re-exports from the parameters persisted in ZFS
+        let json = serde_json::to_string(&metadata).unwrap();
+        let restored: ZfsVolumeMetadata = serde_json::from_str(&json).unwrap();
+        let options = parse_ctl_options(&restored.parameters);
+        assert_eq!(options.transport_group.as_deref(), Some("tg-fast"));
+        assert_eq!(options.portal_group, None);
+    }
+
+    #[test]
+    fn test_volume_stats_refresh_is_throttled() {
+        let last_refresh = Mutex::new(None);
+        let start = Instant::now();
+
+        assert!(volume_stats_due(&last_refresh, start));
+        assert!(!volume_stats_due(&last_refresh, start));
+        assert!(!volume_stats_due(
+            &last_refresh,
+            start + VOLUME_STATS_INTERVAL - Duration::from_secs(1)
+        ));
+        assert!(volume_stats_due(
+            &last_refresh,
+            start + VOLUME_STATS_INTERVAL
+        ));
+    }
+
+    fn snapshot_info(source: &str, name: &str) -> CsiSnapshotInfo {
+        snapshot_taken(source, name, 1_700_000_000)
+    }
+
+    fn snapshot_taken(source: &str, name: &str, creation_time: i64) -> CsiSnapshotInfo {
+        CsiSnapshotInfo {
+            snapshot_id: format!("{}@{}", source, name),
+            source_volume_id: source.to_string(),
+            name: name.to_string(),
+            creation_time,
+            space: Default::default(),
+            system: false,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_name_conflicts_across_volumes() {
+        let snapshots = vec![
+            snapshot_taken("pvc-a", "daily", 100),
+            snapshot_taken("pvc-c", "daily", 200),
+            snapshot_taken("pvc-b", "weekly", 100),
+            snapshot_taken("pvc-a", "weekly", 200),
+        ];
+
+        // The newer snapshot gives way to the existing one
+        assert_eq!(
+            conflicting_snapshot_source(&snapshots, "pvc-c", "daily"),
+            Some("pvc-a")
+        );
+        assert_eq!(
+            conflicting_snapshot_source(&snapshots, "pvc-a", "weekly"),
+            Some("pvc-b")
+        );
+
+        // Snapshots taken in the same second: the lowest volume ID wins
+        let racing = vec![
+            snapshot_taken("pvc-y", "hourly", 100),
+            snapshot_taken("pvc-x", "hourly", 100),
+        ];
+        assert_eq!(
+            conflicting_snapshot_source(&racing, "pvc-y", "hourly"),
+            Some("pvc-x")
+        );
+        assert_eq!(
+            conflicting_snapshot_source(&racing, "pvc-x", "hourly"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_snapshot_name_retry_and_new_names_do_not_conflict() {
+        let snapshots = vec![
+            snapshot_taken("pvc-a", "daily", 100),
+            snapshot_taken("pvc-b", "hourly", 200),
+        ];
+
+        // CreateSnapshot retry on the same volume
+        assert_eq!(
+            conflicting_snapshot_source(&snapshots, "pvc-a", "daily"),
+            None
+        );
+        assert_eq!(
+            conflicting_snapshot_source(&snapshots, "pvc-b", "hourly"),
+            None
+        );
+        assert_eq!(conflicting_snapshot_source(&[], "pvc-b", "daily"), None);
+    }
+
+    #[test]
+    fn test_listed_snapshots_hide_system_snapshots() {
+        let snapshots = vec![
+            snapshot_info("pvc-a", "daily"),
+            CsiSnapshotInfo {
+                system: true,
+                ..snapshot_info("pvc-a", "csi-auto-1700000000")
+            },
+            snapshot_info("pvc-b", "weekly"),
+        ];
+        let names = |listed: Vec<CsiSnapshotInfo>| -> Vec<String> {
+            listed.into_iter().map(|s| s.name).collect()
+        };
+
+        assert_eq!(
+            names(listed_snapshots(snapshots.clone(), "", false)),
+            ["daily", "weekly"]
+        );
+        assert_eq!(
+            names(listed_snapshots(snapshots.clone(), "pvc-a", false)),
+            ["daily"]
+        );
+        assert_eq!(
+            names(listed_snapshots(snapshots.clone(), "pvc-a", true)),
+            ["daily", "csi-auto-1700000000"]
+        );
+        assert_eq!(
+            names(listed_snapshots(snapshots, "", true)),
+            ["daily", "csi-auto-1700000000", "weekly"]
+        );
+    }
 
-        Ok(Response::new(ListSnapshotsResponse {
-            snapshots: paginated,
-            next_token,
-        }))
+    #[test]
+    fn test_normal_names_accepted() {
+        for name in [
+            "pvc-5c1830ef-0beb-412d-8015-5a6a941b7390",
+            "snapshot-1f2e",
+            "pvc-cloned-data",
+            "my-pvc-clone-",
+        ] {
+            assert!(check_reserved_name("volume", name).is_ok(), "{}", name);
+        }
     }
 
-    /// Get a single snapshot by ID
-    ///
-    /// This queries ZFS directly for the snapshot, ensuring accurate results
-    /// that survive restarts.
-    #[instrument(skip(self, request))]
-    async fn get_snapshot(
-        &self,
-        request: Request<GetSnapshotRequest>,
-    ) -> Result<Response<GetSnapshotResponse>, Status> {
-        let req = request.into_inner();
-        debug!("GetSnapshot request: snapshot_id={}", req.snapshot_id);
+    #[test]
+    fn test_foreign_origin_leave_allows_delete() {
+        let origin = Some("tank/csi/pvc-src@nightly-2024-01-01");
+        assert!(
+            check_foreign_origin("pvc-clone", origin, false, ForeignOriginPolicy::Leave).is_ok()
+        );
+    }
 
-        if req.snapshot_id.is_empty() {
-            return Err(Status::invalid_argument("snapshot_id cannot be empty"));
+    #[test]
+    fn test_foreign_origin_refuse_rejects_clone_of_user_snapshot() {
+        let origin = Some("tank/csi/pvc-src@nightly-2024-01-01");
+        let err = check_foreign_origin("pvc-clone", origin, false, ForeignOriginPolicy::Refuse)
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(
+            err.message()
+                .contains("tank/csi/pvc-src@nightly-2024-01-01")
+        );
+    }
+
+    #[test]
+    fn test_foreign_origin_policy_ignores_driver_origins() {
+        // Temporary PVC-clone snapshots and non-clones are never refused
+        let driver_origin = Some("tank/csi/pvc-src@pvc-clone-pvc-dst-1700000000");
+        for policy in [ForeignOriginPolicy::Leave, ForeignOriginPolicy::Refuse] {
+            assert!(check_foreign_origin("pvc-dst", driver_origin, false, policy).is_ok());
+            assert!(check_foreign_origin("pvc-dst", None, false, policy).is_ok());
         }
+        // Volumes restored from a CSI VolumeSnapshot are not foreign either
+        let snapshot_origin = Some("tank/csi/pvc-src@snapshot-1234");
+        assert!(
+            check_foreign_origin(
+                "pvc-dst",
+                snapshot_origin,
+                true,
+                ForeignOriginPolicy::Refuse
+            )
+            .is_ok()
+        );
+    }
 
-        // Query ZFS directly for all CSI snapshots and find the matching one
-        let csi_snapshots = {
-            let zfs = self.zfs.read().await;
-            zfs.list_csi_snapshots().await.map_err(|e| {
-                Status::internal(format!("failed to query snapshots from ZFS: {}", e))
-            })?
-        };
+    #[test]
+    fn test_foreign_origin_policy_parse() {
+        assert_eq!(
+            "leave".parse::<ForeignOriginPolicy>().unwrap(),
+            ForeignOriginPolicy::Leave
+        );
+        assert_eq!(
+            "Refuse".parse::<ForeignOriginPolicy>().unwrap(),
+            ForeignOriginPolicy::Refuse
+        );
+        assert!("promote".parse::<ForeignOriginPolicy>().is_err());
+    }
 
-        let snapshot_info = csi_snapshots
-            .into_iter()
-            .find(|s| s.snapshot_id == req.snapshot_id)
-            .ok_or_else(|| {
-                Status::not_found(format!("snapshot '{}' not found", req.snapshot_id))
-            })?;
+    #[test]
+    fn test_nvme_auth_strict_rejects_dhchap() {
+        let auth = AuthConfig::NvmeAuth(NvmeAuth::new(
+            "nqn.2014-08.org.nvmexpress:uuid:host",
+            "DHHC-1:00:secret:",
+            "SHA-256",
+        ));
+
+        let err = check_nvme_auth_support(&auth, true).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert!(err.message().contains("DH-HMAC-CHAP"));
+        assert!(err.message().contains("host-nqn"));
+    }
 
-        let snapshot = Snapshot {
-            id: snapshot_info.snapshot_id,
-            source_volume_id: snapshot_info.source_volume_id,
-            name: snapshot_info.name,
-            creation_time: snapshot_info.creation_time,
-            size_bytes: 0, // ZFS snapshots don't consume space until divergence
-        };
+    #[test]
+    fn test_nvme_auth_lenient_downgrades() {
+        let auth = AuthConfig::NvmeAuth(
+            NvmeAuth::new(
+                "nqn.2014-08.org.nvmexpress:uuid:host",
+                "DHHC-1:00:secret:",
+                "SHA-256",
+            )
+            .with_dh_group("ffdhe2048"),
+        );
 
-        Ok(Response::new(GetSnapshotResponse {
-            snapshot: Some(snapshot),
-        }))
+        assert!(check_nvme_auth_support(&auth, false).is_ok());
     }
 
-    /// Get storage capacity information for the ZFS pool
-    #[instrument(skip(self, _request))]
-    async fn get_capacity(
-        &self,
-        _request: Request<GetCapacityRequest>,
-    ) -> Result<Response<GetCapacityResponse>, Status> {
-        debug!("GetCapacity request");
+    #[test]
+    fn test_strict_auth_ignores_other_auth() {
+        let host_nqn_only = AuthConfig::NvmeAuth(NvmeAuth::new(
+            "nqn.2014-08.org.nvmexpress:uuid:host",
+            "",
+            "",
+        ));
+        let chap = AuthConfig::IscsiChap(IscsiChapAuth::new("user", "secret123456"));
+
+        assert!(check_nvme_auth_support(&host_nqn_only, true).is_ok());
+        assert!(check_nvme_auth_support(&chap, true).is_ok());
+        assert!(check_nvme_auth_support(&AuthConfig::None, true).is_ok());
+    }
 
-        // Get capacity from ZFS parent dataset
-        let zfs = self.zfs.read().await;
-        let capacity = zfs
-            .get_capacity()
-            .await
-            .map_err(|e| Status::internal(format!("failed to get capacity: {}", e)))?;
+    #[test]
+    fn test_validate_auth_credentials() {
+        use proto::auth_credentials::Credentials;
+
+        let chap =
+            |user: &str, secret: &str, mutual_user: &str, mutual_secret: &str| AuthCredentials {
+                credentials: Some(Credentials::IscsiChap(proto::IscsiChapCredentials {
+                    username: user.to_string(),
+                    secret: secret.to_string(),
+                    mutual_username: mutual_user.to_string(),
+                    mutual_secret: mutual_secret.to_string(),
+                })),
+            };
+        for auth in [
+            chap("user", "secret123456", "", ""),
+            chap("user", "secret123456", "target", "tsecret123456"),
+        ] {
+            assert!(validate_auth_credentials(Some(&auth)).is_ok(), "{auth:?}");
+        }
 
-        info!(
-            available = capacity.available,
-            used = capacity.used,
-            total = capacity.available + capacity.used,
-            "Retrieved storage capacity"
+        for auth in [
+            chap("user", "", "", ""),
+            chap("user", "line\nbreak", "", ""),
+            chap("user", "secret123456", "target", "tab\tsecret"),
+            // Half of a mutual pair
+            chap("user", "secret123456", "target", ""),
+            chap("user", "secret123456", "", "tsecret123456"),
+            AuthCredentials { credentials: None },
+        ] {
+            let err = validate_auth_credentials(Some(&auth)).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument, "{auth:?}");
+        }
+        assert!(validate_auth_credentials(None).is_err());
+
+        let err = check_chap_credentials(&proto::IscsiChapCredentials {
+            username: "user".to_string(),
+            secret: "secret123456".to_string(),
+            mutual_username: "target".to_string(),
+            mutual_secret: String::new(),
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.message(),
+            "mutual CHAP requires both a username and a secret"
         );
+    }
 
-        Ok(Response::new(GetCapacityResponse {
-            available_capacity: capacity.available as i64,
-            total_capacity: (capacity.available + capacity.used) as i64,
-            used_capacity: capacity.used as i64,
-        }))
+    #[test]
+    fn test_check_auth_export_type() {
+        let chap = AuthConfig::IscsiChap(IscsiChapAuth::new("user", "secret123456"));
+        let nvme = AuthConfig::NvmeAuth(NvmeAuth::new(
+            "nqn.2014-08.org.nvmexpress:uuid:host",
+            "",
+            "",
+        ));
+        assert!(check_auth_export_type(ExportType::Iscsi, &chap).is_ok());
+        assert!(check_auth_export_type(ExportType::Iscsi, &nvme).is_err());
+        assert!(check_auth_export_type(ExportType::Nvmeof, &nvme).is_err());
+        assert!(check_auth_export_type(ExportType::Nvmeof, &chap).is_err());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn page(items: &[&'static str], max: i32, token: &str) -> (Vec<&'static str>, String) {
+        paginate(items.to_vec(), max, token, |s| s).unwrap()
+    }
 
     #[test]
     fn test_paginate_empty_token() {
-        let items = vec![1, 2, 3, 4, 5];
-        let (result, next_token) = paginate(items, 2, "").unwrap();
-        assert_eq!(result, vec![1, 2]);
-        assert_eq!(next_token, "2");
+        let (result, next_token) = page(&["pvc-c", "pvc-a", "pvc-b", "pvc-e", "pvc-d"], 2, "");
+        assert_eq!(result, vec!["pvc-a", "pvc-b"]);
+        assert_eq!(next_token, "after:pvc-b");
     }
 
     #[test]
     fn test_paginate_valid_token() {
-        let items = vec![1, 2, 3, 4, 5];
-        let (result, next_token) = paginate(items, 2, "2").unwrap();
-        assert_eq!(result, vec![3, 4]);
-        assert_eq!(next_token, "4");
+        let items = ["pvc-a", "pvc-b", "pvc-c", "pvc-d", "pvc-e"];
+        let (result, next_token) = page(&items, 2, "after:pvc-b");
+        assert_eq!(result, vec!["pvc-c", "pvc-d"]);
+        assert_eq!(next_token, "after:pvc-d");
+    }
+
+    #[test]
+    fn test_paginate_numeric_token_is_an_offset() {
+        let items = ["pvc-a", "pvc-b", "pvc-c", "pvc-d", "pvc-e"];
+        let (result, next_token) = page(&items, 2, "2");
+        assert_eq!(result, vec!["pvc-c", "pvc-d"]);
+        assert_eq!(next_token, "after:pvc-d");
+        // An offset past the end yields an empty last page
+        let (result, next_token) = page(&items, 2, "9");
+        assert!(result.is_empty());
+        assert!(next_token.is_empty());
+    }
+
+    #[test]
+    fn test_paginate_stable_across_insert_and_delete() {
+        let (first, token) = page(&["pvc-b", "pvc-d", "pvc-f", "pvc-h"], 2, "");
+        assert_eq!(first, vec!["pvc-b", "pvc-d"]);
+
+        // pvc-b deleted, pvc-a (before the cursor) and pvc-e (after) created
+        let (second, token) = page(&["pvc-a", "pvc-d", "pvc-e", "pvc-f", "pvc-h"], 2, &token);
+        assert_eq!(second, vec!["pvc-e", "pvc-f"]);
+
+        // The cursor item itself deleted
+        let (third, token) = page(&["pvc-a", "pvc-d", "pvc-e", "pvc-h"], 2, &token);
+        assert_eq!(third, vec!["pvc-h"]);
+        assert!(token.is_empty());
     }
 
     #[test]
     fn test_paginate_invalid_token_returns_error() {
-        let items = vec![1, 2, 3, 4, 5];
-        let result = paginate(items, 2, "invalid");
+        let items = vec!["pvc-a", "pvc-b"];
+        let result = paginate(items, 2, "invalid", |s| s);
         assert!(result.is_err());
         let err = result.unwrap_err();
         assert_eq!(err.code(), tonic::Code::InvalidArgument);
@@ -1914,20 +5396,85 @@ mod tests {
 
     #[test]
     fn test_paginate_last_page() {
-        let items = vec![1, 2, 3, 4, 5];
-        let (result, next_token) = paginate(items, 2, "4").unwrap();
-        assert_eq!(result, vec![5]);
+        let items = ["pvc-a", "pvc-b", "pvc-c", "pvc-d", "pvc-e"];
+        let (result, next_token) = page(&items, 2, "after:pvc-d");
+        assert_eq!(result, vec!["pvc-e"]);
         assert!(next_token.is_empty()); // No more pages
     }
 
     #[test]
     fn test_paginate_zero_max_entries_returns_all() {
-        let items = vec![1, 2, 3];
-        let (result, next_token) = paginate(items, 0, "").unwrap();
-        assert_eq!(result, vec![1, 2, 3]);
+        let (result, next_token) = page(&["pvc-a", "pvc-b", "pvc-c"], 0, "");
+        assert_eq!(result, vec!["pvc-a", "pvc-b", "pvc-c"]);
         assert!(next_token.is_empty());
     }
 
+    #[test]
+    fn test_orphaned_metadata_fast_path_when_dataset_and_export_gone() {
+        assert!(is_orphaned_metadata(
+            &Ok(false),
+            false,
+            OrphanedMetadataPolicy::Drop
+        ));
+        // Configured to always run the full delete
+        assert!(!is_orphaned_metadata(
+            &Ok(false),
+            false,
+            OrphanedMetadataPolicy::Full
+        ));
+    }
+
+    #[test]
+    fn test_orphaned_metadata_requires_full_cleanup_otherwise() {
+        let policy = OrphanedMetadataPolicy::Drop;
+        // Dataset still present
+        assert!(!is_orphaned_metadata(&Ok(true), false, policy));
+        // Export must still be removed and the config rewritten
+        assert!(!is_orphaned_metadata(&Ok(false), true, policy));
+        // Unknown existence never skips cleanup
+        assert!(!is_orphaned_metadata(
+            &Err(crate::zfs::ZfsError::CommandFailed("timeout".to_string())),
+            false,
+            policy
+        ));
+    }
+
+    #[test]
+    fn test_orphaned_metadata_policy_parse() {
+        assert_eq!(
+            "drop".parse::<OrphanedMetadataPolicy>(),
+            Ok(OrphanedMetadataPolicy::Drop)
+        );
+        assert_eq!(
+            "FULL".parse::<OrphanedMetadataPolicy>(),
+            Ok(OrphanedMetadataPolicy::Full)
+        );
+        assert!("fast".parse::<OrphanedMetadataPolicy>().is_err());
+        assert_eq!(OrphanedMetadataPolicy::default().to_string(), "drop");
+    }
+
+    #[test]
+    fn test_is_clone_snapshot_for() {
+        let snapshot = "tank/csi/pvc-src@pvc-clone-pvc-b-1700000000000";
+        assert!(is_clone_snapshot_for(snapshot, "pvc-b"));
+        // Another volume whose name extends this one's
+        assert!(!is_clone_snapshot_for(
+            "tank/csi/pvc-src@pvc-clone-pvc-b-2-1700000000000",
+            "pvc-b"
+        ));
+        assert!(!is_clone_snapshot_for(snapshot, "pvc"));
+        assert!(!is_clone_snapshot_for(
+            "tank/csi/pvc-src@pvc-clone-pvc-b-",
+            "pvc-b"
+        ));
+        // User and CSI snapshots are never touched
+        assert!(!is_clone_snapshot_for(
+            "tank/csi/pvc-src@pvc-b-1700000000000",
+            "pvc-b"
+        ));
+        assert!(!is_clone_snapshot_for("tank/csi/pvc-b", "pvc-b"));
+    }
+
     #[test]
     fn test_missing_metadata_delete_with_zfs_metadata_uses_metadata() {
         let mut parameters = HashMap::new();
@@ -1945,7 +5492,7 @@ mod tests {
 
         let action = missing_metadata_delete_action(
             "pvc-123",
-            Ok(MissingMetadataLookup::Found(zfs_metadata)),
+            Ok(MissingMetadataLookup::Found(Box::new(zfs_metadata))),
         )
         .unwrap();
 
@@ -1963,6 +5510,7 @@ mod tests {
             metadata.auth,
             AuthConfig::GroupRef("ag-pvc-123".to_string())
         );
+        assert_eq!(metadata.state, VolumeState::Exported);
     }
 
     #[test]
@@ -2019,4 +5567,437 @@ mod tests {
         assert!(err.message().contains("invalid CSI metadata"));
         assert!(err.message().contains("refusing deletion"));
     }
+
+    #[tokio::test]
+    async fn test_resolve_volsize_uses_listed_value() {
+        let size = resolve_volsize("tank/csi/pvc-1", Some(1 << 30), || async {
+            panic!("listed volsize must not be re-queried")
+        })
+        .await;
+        assert_eq!(size, 1 << 30);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_volsize_requery_returns_real_size() {
+        let size = resolve_volsize("tank/csi/pvc-1", None, || async { Ok(Some(10 << 30)) }).await;
+        assert_eq!(size, 10 << 30);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_volsize_reports_zero_when_genuinely_absent() {
+        let size = resolve_volsize("tank/csi/pvc-1", None, || async { Ok(None) }).await;
+        assert_eq!(size, 0);
+
+        let size = resolve_volsize("tank/csi/pvc-1", None, || async {
+            Err(crate::zfs::ZfsError::CommandFailed("timeout".to_string()))
+        })
+        .await;
+        assert_eq!(size, 0);
+    }
+
+    #[test]
+    fn test_reconcile_summary_accounts_each_outcome() {
+        let mut summary = ReconcileSummary::default();
+        summary.unchanged += 1;
+        summary.record("pvc-ok", ReconcileOutcome::Reconciled);
+        summary.record(
+            "pvc-raced",
+            ReconcileOutcome::Skipped("already exported".to_string()),
+        );
+        summary.record(
+            "pvc-bad",
+            ReconcileOutcome::Failed("invalid LUN ID -1".to_string()),
+        );
+
+        assert_eq!(summary.reconciled, vec!["pvc-ok".to_string()]);
+        assert_eq!(summary.unchanged, 1);
+        assert_eq!(
+            summary.skipped,
+            vec![("pvc-raced".to_string(), "already exported".to_string())]
+        );
+        assert_eq!(
+            summary.failed,
+            vec![("pvc-bad".to_string(), "invalid LUN ID -1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_reconcile_summary_config_write_failure_fails_reconciled() {
+        let mut summary = ReconcileSummary::default();
+        summary.record("pvc-a", ReconcileOutcome::Reconciled);
+        summary.record(
+            "pvc-b",
+            ReconcileOutcome::Skipped("no export type".to_string()),
+        );
+
+        summary.fail_reconciled("config write failed: disk full");
+
+        assert!(summary.reconciled.is_empty());
+        assert_eq!(
+            summary.failed,
+            vec![(
+                "pvc-a".to_string(),
+                "config write failed: disk full".to_string()
+            )]
+        );
+        // Skipped volumes never depended on the config write
+        assert_eq!(summary.skipped.len(), 1);
+    }
+
+    #[test]
+    fn test_volume_state_allowed_transitions() {
+        use VolumeState::*;
+
+        // Create path
+        assert!(Creating.can_transition_to(Exported));
+        assert!(Creating.can_transition_to(Failed));
+        assert!(Failed.can_transition_to(Creating));
+        assert!(Failed.can_transition_to(Exported));
+
+        // Delete path
+        assert!(Exported.can_transition_to(Unexporting));
+        assert!(Failed.can_transition_to(Unexporting));
+        assert!(Unexporting.can_transition_to(Deleting));
+
+        // Retries stay in place
+        for state in [Creating, Exported, Unexporting, Deleting, Failed] {
+            assert!(state.can_transition_to(state));
+        }
+    }
+
+    #[test]
+    fn test_volume_state_rejected_transitions() {
+        use VolumeState::*;
+
+        // A healthy export must not be downgraded by a failed create retry
+        assert!(!Exported.can_transition_to(Failed));
+        assert!(!Exported.can_transition_to(Creating));
+        // Deletion cannot skip unexporting or be reversed
+        assert!(!Exported.can_transition_to(Deleting));
+        assert!(!Unexporting.can_transition_to(Exported));
+        assert!(!Deleting.can_transition_to(Exported));
+        assert!(!Deleting.can_transition_to(Unexporting));
+        assert!(!Deleting.can_transition_to(Creating));
+    }
+
+    #[test]
+    fn test_ambiguous_snapshot_error_counts_and_reports_paths() {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let paths = vec![
+            "tank/csi/pvc-a@s1".to_string(),
+            "tank/csi/pvc-b@s1".to_string(),
+        ];
+
+        let err = ::metrics::with_local_recorder(&recorder, || {
+            ambiguous_snapshot_error("pvc-a@s1", &paths, "delete");
+            ambiguous_snapshot_error("pvc-a@s1", &paths, "delete")
+        });
+
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(
+            err.message().contains(
+                "Found 2 snapshots with ID 'pvc-a@s1' (tank/csi/pvc-a@s1, tank/csi/pvc-b@s1)"
+            ),
+            "{}",
+            err.message()
+        );
+        let rendered = handle.render();
+        assert!(
+            rendered.contains("ctld_ambiguous_snapshots_total 2"),
+            "{}",
+            rendered
+        );
+    }
+
+    #[tokio::test]
+    async fn test_same_volume_handler_calls_serialize() {
+        let zfs = Arc::new(RwLock::new(ZfsManager::unchecked("tank/csi")));
+        let ctl = Arc::new(RwLock::new(
+            CtlManager::new(
+                "iqn.2024-01.org.freebsd.csi".to_string(),
+                "nqn.2024-01.org.freebsd.csi".to_string(),
+                "pg0".to_string(),
+                "tg0".to_string(),
+                "tank/csi".to_string(),
+            )
+            .unwrap(),
+        ));
+        let service = Arc::new(StorageService::with_concurrency_limit(zfs, ctl, 1));
+        let update = |volume: &str| {
+            let service = service.clone();
+            let request = Request::new(UpdateVolumeAuthRequest {
+                volume_id: volume.to_string(),
+                auth: Some(AuthCredentials {
+                    credentials: Some(proto::auth_credentials::Credentials::IscsiChap(
+                        proto::IscsiChapCredentials {
+                            username: "user".to_string(),
+                            secret: "secretsecret".to_string(),
+                            ..Default::default()
+                        },
+                    )),
+                }),
+            });
+            tokio::spawn(async move { service.update_volume_auth(request).await })
+        };
+
+        // Stall the first call inside the handler, holding the volume and
+        // the only permit
+        let volumes = service.volumes.write().await;
+        let first = update("pvc-1");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(service.ops_semaphore.available_permits(), 0);
+
+        // A second call on the same volume waits for the first instead of
+        // being turned away for want of a permit
+        let second = update("pvc-1");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!second.is_finished());
+        // Another volume is rate limited as usual
+        let other = update("pvc-2").await.unwrap().unwrap_err();
+        assert_eq!(other.code(), tonic::Code::ResourceExhausted);
+
+        drop(volumes);
+        for call in [first, second] {
+            let err = call.await.unwrap().unwrap_err();
+            assert_eq!(err.code(), tonic::Code::NotFound);
+        }
+    }
+
+    #[test]
+    fn test_content_source_location_after_promotion() {
+        use crate::zfs::FindSnapshotResult;
+
+        let locate = |path: &str| {
+            path.strip_prefix("tank/csi/")
+                .and_then(|p| p.split_once('@'))
+                .map(|(v, s)| (v.to_string(), s.to_string()))
+        };
+
+        // Promotion moved the snapshot to the clone
+        assert_eq!(
+            content_source_location(
+                "pvc-a@s1",
+                FindSnapshotResult::Found("tank/csi/pvc-b@s1".to_string()),
+                locate
+            )
+            .unwrap(),
+            ("pvc-b".to_string(), "s1".to_string())
+        );
+
+        let err = content_source_location(
+            "pvc-a@s1",
+            FindSnapshotResult::Found("other/pool/pvc-b@s1".to_string()),
+            locate,
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+
+        let err =
+            content_source_location("pvc-a@s1", FindSnapshotResult::NotFound, locate).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+
+        // Ambiguous IDs are refused and counted like on delete
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let err = ::metrics::with_local_recorder(&recorder, || {
+            content_source_location(
+                "pvc-a@s1",
+                FindSnapshotResult::Ambiguous(vec![
+                    "tank/csi/pvc-a@s1".to_string(),
+                    "tank/csi/pvc-b@s1".to_string(),
+                ]),
+                locate,
+            )
+            .unwrap_err()
+        });
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(
+            err.message().contains("refusing to restore from it"),
+            "{}",
+            err.message()
+        );
+        let rendered = handle.render();
+        assert!(
+            rendered.contains("ctld_ambiguous_snapshots_total 1"),
+            "{}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn test_requested_clone_mode_defaults_to_linked() {
+        assert_eq!(
+            requested_clone_mode(CloneMode::Unspecified as i32),
+            CloneMode::Linked
+        );
+        assert_eq!(requested_clone_mode(99), CloneMode::Linked);
+        assert_eq!(
+            requested_clone_mode(CloneMode::Linked as i32),
+            CloneMode::Linked
+        );
+        assert_eq!(
+            requested_clone_mode(CloneMode::Copy as i32),
+            CloneMode::Copy
+        );
+    }
+
+    #[test]
+    fn test_temp_snapshot_action() {
+        use TempSnapshotAction::*;
+
+        for promote in [false, true] {
+            // Failed clones never leave the snapshot behind
+            for mode in [CloneMode::Linked, CloneMode::Copy, CloneMode::Unspecified] {
+                assert_eq!(temp_snapshot_action(false, mode, promote), Delete);
+            }
+            // COPY clones are independent of it
+            assert_eq!(temp_snapshot_action(true, CloneMode::Copy, promote), Delete);
+        }
+
+        // LINKED clones depend on it; unspecified means LINKED
+        for mode in [CloneMode::Linked, CloneMode::Unspecified] {
+            assert_eq!(temp_snapshot_action(true, mode, false), Keep);
+            assert_eq!(temp_snapshot_action(true, mode, true), PromoteClone);
+        }
+    }
+
+    #[test]
+    fn test_reconcile_action_per_state() {
+        use VolumeState::*;
+
+        assert_eq!(reconcile_action(Creating, false), ReconcileAction::Skip);
+        assert_eq!(reconcile_action(Creating, true), ReconcileAction::Skip);
+        assert_eq!(reconcile_action(Exported, false), ReconcileAction::Export);
+        assert_eq!(reconcile_action(Exported, true), ReconcileAction::Skip);
+        assert_eq!(reconcile_action(Failed, false), ReconcileAction::Export);
+        assert_eq!(reconcile_action(Failed, true), ReconcileAction::WriteConfig);
+        assert_eq!(
+            reconcile_action(Unexporting, true),
+            ReconcileAction::Unexport
+        );
+        assert_eq!(
+            reconcile_action(Unexporting, false),
+            ReconcileAction::WriteConfig
+        );
+        assert_eq!(reconcile_action(Deleting, true), ReconcileAction::Unexport);
+        assert_eq!(reconcile_action(Deleting, false), ReconcileAction::Skip);
+    }
+
+    #[test]
+    fn test_reconciled_state_is_reachable() {
+        use VolumeState::*;
+
+        for state in [Creating, Exported, Unexporting, Deleting, Failed] {
+            let next = state.reconciled();
+            assert!(
+                state.can_transition_to(next),
+                "{} cannot settle in {}",
+                state,
+                next
+            );
+        }
+        assert_eq!(Failed.reconciled(), Exported);
+        assert_eq!(Unexporting.reconciled(), Deleting);
+    }
+
+    fn persisted(volume: &str, export_type: CtlExportType, target: &str) -> PersistedExport {
+        PersistedExport {
+            volume_name: volume.to_string(),
+            export_type,
+            target_name: target.to_string(),
+            lun_id: 3,
+            auth_group: "no-authentication".to_string(),
+        }
+    }
+
+    const BASE_IQN: &str = "iqn.2024-01.org.freebsd.csi";
+    const BASE_NQN: &str = "nqn.2024-01.org.freebsd.csi";
+
+    #[test]
+    fn test_reconstruct_metadata_from_iscsi_export() {
+        let mut export = persisted(
+            "pvc-1",
+            CtlExportType::Iscsi,
+            "iqn.2024-01.org.freebsd.csi:pvc-1",
+        );
+        export.auth_group = "ag-pvc-1".to_string();
+        let exports = vec![
+            export,
+            persisted(
+                "pvc-2",
+                CtlExportType::Iscsi,
+                "iqn.2024-01.org.freebsd.csi:pvc-2",
+            ),
+        ];
+
+        let meta = reconstruct_metadata("pvc-1", &exports, BASE_IQN, BASE_NQN).unwrap();
+        assert_eq!(meta.export_type, CtlExportType::Iscsi);
+        assert_eq!(meta.target_name, "iqn.2024-01.org.freebsd.csi:pvc-1");
+        assert_eq!(meta.lun_id, Some(3));
+        assert_eq!(meta.auth_group.as_deref(), Some("ag-pvc-1"));
+        assert!(meta.parameters.is_empty());
+        assert!(!meta.needs_migration());
+    }
+
+    #[test]
+    fn test_reconstruct_metadata_grouped_nvme_controller() {
+        let exports = vec![persisted(
+            "pvc-1",
+            CtlExportType::Nvmeof,
+            "nqn.2024-01.org.freebsd.csi:group:shared",
+        )];
+
+        let meta = reconstruct_metadata("pvc-1", &exports, BASE_IQN, BASE_NQN).unwrap();
+        assert_eq!(meta.export_type, CtlExportType::Nvmeof);
+        assert_eq!(meta.auth_group, None);
+        assert_eq!(
+            meta.parameters
+                .get(CONTROLLER_GROUP_PARAM)
+                .map(String::as_str),
+            Some("shared")
+        );
+    }
+
+    #[test]
+    fn test_reconstruct_metadata_refuses_ambiguous_volumes() {
+        let exports = vec![
+            persisted(
+                "pvc-1",
+                CtlExportType::Iscsi,
+                "iqn.2000-01.com.example:pvc-1",
+            ),
+            persisted(
+                "pvc-2",
+                CtlExportType::Iscsi,
+                "iqn.2024-01.org.freebsd.csi:other",
+            ),
+            persisted(
+                "pvc-3",
+                CtlExportType::Iscsi,
+                "iqn.2024-01.org.freebsd.csi:pvc-3",
+            ),
+            persisted(
+                "pvc-3",
+                CtlExportType::Nvmeof,
+                "nqn.2024-01.org.freebsd.csi:pvc-3",
+            ),
+        ];
+
+        // Foreign base IQN
+        assert!(reconstruct_metadata("pvc-1", &exports, BASE_IQN, BASE_NQN).is_err());
+        // iSCSI target not named after the volume
+        assert!(reconstruct_metadata("pvc-2", &exports, BASE_IQN, BASE_NQN).is_err());
+        // Exported twice
+        assert!(reconstruct_metadata("pvc-3", &exports, BASE_IQN, BASE_NQN).is_err());
+        // No export at all
+        assert!(reconstruct_metadata("pvc-4", &exports, BASE_IQN, BASE_NQN).is_err());
+        // Reserved name
+        let clone = vec![persisted(
+            "pvc-clone-x",
+            CtlExportType::Iscsi,
+            "iqn.2024-01.org.freebsd.csi:pvc-clone-x",
+        )];
+        assert!(reconstruct_metadata("pvc-clone-x", &clone, BASE_IQN, BASE_NQN).is_err());
+    }
 }
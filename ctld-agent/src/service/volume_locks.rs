@@ -0,0 +1,159 @@
+//! Per-volume serialization of create, delete and expand.
+//!
+//! The global semaphore bounds how many operations run at once but lets two
+//! operations on the same volume interleave. When a PVC is deleted and
+//! recreated with the same name, a DeleteVolume retry can then destroy the
+//! volume a concurrent CreateVolume just made. Operations take the volume's
+//! lock for their whole duration, so same-name operations run one after the
+//! other while different volumes still proceed in parallel.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use tracing::debug;
+
+type LockMap = Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>;
+
+/// Async locks keyed by volume name, created on demand.
+///
+/// Handlers take the volume's lock before their rate-limiting permit, so
+/// operations queued behind one on the same volume hold no permit.
+#[derive(Debug, Clone, Default)]
+pub struct VolumeLocks {
+    locks: LockMap,
+}
+
+impl VolumeLocks {
+    /// Wait for exclusive access to `name` until the returned guard is dropped
+    pub async fn lock(&self, name: &str) -> VolumeLockGuard {
+        let lock = self
+            .locks
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .clone();
+        let guard = match lock.clone().try_lock_owned() {
+            Ok(guard) => guard,
+            Err(_) => {
+                debug!(volume = %name, "Waiting for another operation on the volume");
+                lock.lock_owned().await
+            }
+        };
+        VolumeLockGuard {
+            locks: self.locks.clone(),
+            name: name.to_string(),
+            guard: Some(guard),
+        }
+    }
+
+    /// Number of volumes with a lock entry (held or awaited)
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.locks.lock().unwrap().len()
+    }
+}
+
+/// Releases the volume's lock when dropped, removing its entry once no
+/// other operation holds or awaits it.
+#[derive(Debug)]
+pub struct VolumeLockGuard {
+    locks: LockMap,
+    name: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for VolumeLockGuard {
+    fn drop(&mut self) {
+        self.guard.take();
+        let mut locks = self.locks.lock().unwrap();
+        // Waiters clone the entry under the map lock, so a count of one
+        // means nobody else can reach it
+        if locks
+            .get(&self.name)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&self.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_same_volume_operations_serialize() {
+        let locks = VolumeLocks::default();
+        // Stands in for the volume's existence in ZFS
+        let exists = Arc::new(Mutex::new(false));
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let create = {
+            let (locks, exists, log) = (locks.clone(), exists.clone(), log.clone());
+            async move {
+                let _guard = locks.lock("pvc-1").await;
+                log.lock().unwrap().push("create start");
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                *exists.lock().unwrap() = true;
+                log.lock().unwrap().push("create end");
+            }
+        };
+        let delete = {
+            let (locks, exists, log) = (locks.clone(), exists.clone(), log.clone());
+            async move {
+                // Let the create take the lock first
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                let _guard = locks.lock("pvc-1").await;
+                log.lock().unwrap().push("delete start");
+                // A delete that got in mid-create would see no volume
+                assert!(*exists.lock().unwrap(), "delete ran during create");
+                *exists.lock().unwrap() = false;
+                log.lock().unwrap().push("delete end");
+            }
+        };
+        tokio::join!(create, delete);
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["create start", "create end", "delete start", "delete end"]
+        );
+        assert!(!*exists.lock().unwrap());
+        assert_eq!(locks.len(), 0, "lock entries are removed once released");
+    }
+
+    #[tokio::test]
+    async fn test_different_volumes_proceed_in_parallel() {
+        let locks = VolumeLocks::default();
+        let _first = locks.lock("pvc-1").await;
+        // Would hang if pvc-2 waited on pvc-1
+        let second = tokio::time::timeout(Duration::from_secs(1), locks.lock("pvc-2")).await;
+        assert!(second.is_ok());
+        assert_eq!(locks.len(), 2);
+
+        drop(second);
+        assert_eq!(locks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_waiter_keeps_entry_alive() {
+        let locks = VolumeLocks::default();
+        let first = locks.lock("pvc-1").await;
+        let waiter = {
+            let locks = locks.clone();
+            tokio::spawn(async move {
+                let _guard = locks.lock("pvc-1").await;
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+
+        // Releasing with a waiter queued must not drop the shared entry
+        drop(first);
+        assert_eq!(locks.len(), 1);
+        waiter.await.unwrap();
+        assert_eq!(locks.len(), 0);
+    }
+}
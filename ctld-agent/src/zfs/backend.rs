@@ -0,0 +1,293 @@
+//! Volume backing store selection.
+//!
+//! Volumes are zvols by default. The `file` backend instead creates a ZFS
+//! filesystem dataset (so `recordsize` can be tuned for the workload) holding
+//! a single sparse backing file, which ctld serves through its block backend.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use super::encryption::Encryption;
+use super::error::{Result, ZfsError};
+use super::quota::parse_byte_size;
+use crate::parameters::Parameters;
+
+/// StorageClass parameter selecting the backing store
+pub const BACKEND_PARAM: &str = "backend";
+
+/// StorageClass parameter setting `recordsize` for file-backed volumes
+pub const RECORD_SIZE_PARAM: &str = "recordSize";
+
+/// StorageClass parameter setting `volblocksize` for zvols
+pub const VOLBLOCKSIZE_PARAM: &str = "volblocksize";
+
+/// Name of the backing file inside a file-backed volume's dataset
+pub const BACKING_FILE_NAME: &str = "volume.img";
+
+/// Smallest and largest `recordsize` ZFS accepts
+const MIN_RECORD_SIZE: u64 = 512;
+const MAX_RECORD_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Largest `volblocksize` ZFS accepts
+const MAX_VOLBLOCKSIZE: u64 = 1024 * 1024;
+
+/// Backing store of a volume
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VolumeBackend {
+    /// ZFS volume exposed as /dev/zvol/...
+    #[default]
+    Zvol,
+    /// File in a ZFS filesystem dataset
+    File,
+}
+
+impl VolumeBackend {
+    /// Backend selected by StorageClass parameters (zvol when unset)
+    pub fn from_parameters(params: &HashMap<String, String>) -> std::result::Result<Self, String> {
+        Parameters::new(params)
+            .get_enum(
+                BACKEND_PARAM,
+                &[("zvol", VolumeBackend::Zvol), ("file", VolumeBackend::File)],
+            )
+            .map(Option::unwrap_or_default)
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl fmt::Display for VolumeBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VolumeBackend::Zvol => write!(f, "zvol"),
+            VolumeBackend::File => write!(f, "file"),
+        }
+    }
+}
+
+impl FromStr for VolumeBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "zvol" => Ok(VolumeBackend::Zvol),
+            "file" => Ok(VolumeBackend::File),
+            _ => Err(format!(
+                "invalid {} '{}': expected zvol or file",
+                BACKEND_PARAM, s
+            )),
+        }
+    }
+}
+
+/// Parse a `recordSize` value ("131072", "128K", "1M") into bytes.
+///
+/// ZFS requires a power of two between 512 bytes and 16 MiB.
+pub fn parse_record_size(value: &str) -> std::result::Result<u64, String> {
+    parse_block_size(value, RECORD_SIZE_PARAM, MAX_RECORD_SIZE, "16M")
+}
+
+/// Parse a `volblocksize` value ("16384", "16K", "1M") into bytes.
+///
+/// ZFS requires a power of two between 512 bytes and 1 MiB. The block size
+/// is fixed when the zvol is created; clones inherit their origin's.
+pub fn parse_volblocksize(value: &str) -> std::result::Result<u64, String> {
+    parse_block_size(value, VOLBLOCKSIZE_PARAM, MAX_VOLBLOCKSIZE, "1M")
+}
+
+/// Parse a power-of-two size with an optional size suffix, between 512
+/// bytes and `max` (spelled `max_label` in errors).
+fn parse_block_size(
+    value: &str,
+    param: &str,
+    max: u64,
+    max_label: &str,
+) -> std::result::Result<u64, String> {
+    let invalid = || {
+        format!(
+            "invalid {} '{}': expected a power of two between 512 and {}",
+            param,
+            value.trim(),
+            max_label
+        )
+    };
+
+    let bytes = parse_byte_size(value).ok_or_else(invalid)?;
+    if !bytes.is_power_of_two() || !(MIN_RECORD_SIZE..=max).contains(&bytes) {
+        return Err(invalid());
+    }
+    Ok(bytes)
+}
+
+/// `zfs` arguments creating the filesystem dataset of a file-backed volume.
+///
+/// For thick provisioning `refreservation` guarantees the space of the
+/// (sparse) backing file up front; `quota` caps the dataset including its
+/// snapshots.
+pub(super) fn build_file_dataset_args(
+    full_name: &str,
+    record_size: Option<u64>,
+    compression: Option<&str>,
+    encryption: Option<&Encryption>,
+    metadata_property: &str,
+    thick_size: Option<u64>,
+    quota: Option<u64>,
+) -> Vec<String> {
+    let mut args = vec!["create".to_string()];
+    if let Some(record_size) = record_size {
+        args.push("-o".to_string());
+        args.push(format!("recordsize={}", record_size));
+    }
+    if let Some(compression) = compression {
+        args.push("-o".to_string());
+        args.push(format!("compression={}", compression));
+    }
+    if let Some(encryption) = encryption {
+        args.extend(encryption.create_options());
+    }
+    if let Some(size) = thick_size {
+        args.push("-o".to_string());
+        args.push(format!("refreservation={}", size));
+    }
+    if let Some(quota) = quota {
+        args.push("-o".to_string());
+        args.push(format!("quota={}", quota));
+    }
+    args.push("-o".to_string());
+    args.push(metadata_property.to_string());
+    args.push(full_name.to_string());
+    args
+}
+
+/// `truncate` arguments creating or resizing a sparse backing file
+pub(super) fn build_backing_file_args(path: &str, size_bytes: u64) -> Vec<String> {
+    vec!["-s".to_string(), size_bytes.to_string(), path.to_string()]
+}
+
+/// Path of the backing file given its dataset's `mountpoint` property
+pub(super) fn backing_file_path(full_name: &str, mountpoint: &str) -> Result<String> {
+    match mountpoint {
+        "" | "-" | "none" | "legacy" => Err(ZfsError::CommandFailed(format!(
+            "file-backed volume {} has no usable mountpoint ({})",
+            full_name, mountpoint
+        ))),
+        mountpoint => Ok(format!(
+            "{}/{}",
+            mountpoint.trim_end_matches('/'),
+            BACKING_FILE_NAME
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_from_parameters() {
+        let mut params = HashMap::new();
+        assert_eq!(
+            VolumeBackend::from_parameters(&params),
+            Ok(VolumeBackend::Zvol)
+        );
+        params.insert(BACKEND_PARAM.to_string(), "File".to_string());
+        assert_eq!(
+            VolumeBackend::from_parameters(&params),
+            Ok(VolumeBackend::File)
+        );
+        params.insert(BACKEND_PARAM.to_string(), "nfs".to_string());
+        assert!(VolumeBackend::from_parameters(&params).is_err());
+    }
+
+    #[test]
+    fn test_parse_record_size() {
+        assert_eq!(parse_record_size("131072").unwrap(), 131072);
+        assert_eq!(parse_record_size("128K").unwrap(), 131072);
+        assert_eq!(parse_record_size("1M").unwrap(), 1024 * 1024);
+        assert_eq!(parse_record_size("512").unwrap(), 512);
+        assert!(parse_record_size("100K").is_err());
+        assert!(parse_record_size("256").is_err());
+        assert!(parse_record_size("32M").is_err());
+        assert!(parse_record_size("K").is_err());
+        assert!(parse_record_size("").is_err());
+    }
+
+    #[test]
+    fn test_parse_volblocksize() {
+        assert_eq!(parse_volblocksize("4096").unwrap(), 4096);
+        assert_eq!(parse_volblocksize("16K").unwrap(), 16384);
+        assert_eq!(parse_volblocksize("64k").unwrap(), 65536);
+        assert_eq!(parse_volblocksize("1M").unwrap(), 1024 * 1024);
+        assert!(parse_volblocksize("2M").is_err());
+        assert!(parse_volblocksize("256").is_err());
+        assert!(parse_volblocksize("12K").is_err());
+        let err = parse_volblocksize("8 K").unwrap_err();
+        assert!(
+            err.contains(VOLBLOCKSIZE_PARAM) && err.contains("1M"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_build_file_dataset_args() {
+        let args = build_file_dataset_args(
+            "tank/csi/pvc-1",
+            Some(16384),
+            Some("zstd"),
+            None,
+            "user:csi:metadata={}",
+            None,
+            None,
+        );
+        assert_eq!(
+            args,
+            [
+                "create",
+                "-o",
+                "recordsize=16384",
+                "-o",
+                "compression=zstd",
+                "-o",
+                "user:csi:metadata={}",
+                "tank/csi/pvc-1"
+            ]
+        );
+
+        let thick = build_file_dataset_args(
+            "tank/csi/pvc-1",
+            None,
+            None,
+            None,
+            "user:csi:metadata={}",
+            Some(1 << 30),
+            Some(2 << 30),
+        );
+        assert!(thick.contains(&format!("refreservation={}", 1u64 << 30)));
+        assert!(thick.contains(&format!("quota={}", 2u64 << 30)));
+        assert!(!thick.iter().any(|a| a.starts_with("recordsize=")));
+        assert!(!thick.iter().any(|a| a.starts_with("compression=")));
+        // Dataset name stays last
+        assert_eq!(thick.last().unwrap(), "tank/csi/pvc-1");
+    }
+
+    #[test]
+    fn test_build_backing_file_args() {
+        assert_eq!(
+            build_backing_file_args("/tank/csi/pvc-1/volume.img", 1073741824),
+            ["-s", "1073741824", "/tank/csi/pvc-1/volume.img"]
+        );
+    }
+
+    #[test]
+    fn test_backing_file_path() {
+        assert_eq!(
+            backing_file_path("tank/csi/pvc-1", "/tank/csi/pvc-1").unwrap(),
+            "/tank/csi/pvc-1/volume.img"
+        );
+        assert_eq!(
+            backing_file_path("tank/csi/pvc-1", "/mnt/csi/pvc-1/").unwrap(),
+            "/mnt/csi/pvc-1/volume.img"
+        );
+        assert!(backing_file_path("tank/csi/pvc-1", "legacy").is_err());
+        assert!(backing_file_path("tank/csi/pvc-1", "none").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! Per-volume ZFS compression.
+//!
+//! The `compression` StorageClass parameter is passed to `zfs create` as
+//! `-o compression=<value>`. Values are checked against the algorithms ZFS
+//! knows so nothing else ends up on the command line.
+
+use std::collections::HashMap;
+
+use super::error::{Result, ZfsError};
+use crate::parameters::Parameters;
+
+/// StorageClass parameter selecting the compression algorithm
+pub const COMPRESSION_PARAM: &str = "compression";
+
+/// Algorithms accepted without a level
+const PLAIN_ALGORITHMS: &[&str] = &[
+    "on",
+    "off",
+    "lz4",
+    "lzjb",
+    "zle",
+    "gzip",
+    "zstd",
+    "zstd-fast",
+];
+
+/// Whether `value` (lowercase) names a compression setting ZFS accepts
+fn is_known_compression(value: &str) -> bool {
+    if PLAIN_ALGORITHMS.contains(&value) {
+        return true;
+    }
+    let level = |prefix: &str| {
+        value
+            .strip_prefix(prefix)
+            .filter(|level| !level.starts_with('0'))
+            .and_then(|level| level.parse::<u32>().ok())
+    };
+    // zstd-fast-N must be checked before zstd-N
+    if let Some(level) = level("zstd-fast-") {
+        return matches!(level, 1..=10 | 500 | 1000) || (level <= 100 && level % 10 == 0);
+    }
+    if let Some(level) = level("zstd-") {
+        return (1..=19).contains(&level);
+    }
+    if let Some(level) = level("gzip-") {
+        return (1..=9).contains(&level);
+    }
+    false
+}
+
+/// Compression requested by StorageClass parameters, normalized to
+/// lowercase (`None` when unset, inheriting the parent's setting).
+pub fn compression_from_parameters(params: &HashMap<String, String>) -> Result<Option<String>> {
+    let Some(value) = Parameters::new(params).get(COMPRESSION_PARAM) else {
+        return Ok(None);
+    };
+    let normalized = value.to_lowercase();
+    if !is_known_compression(&normalized) {
+        return Err(ZfsError::InvalidName(format!(
+            "invalid {} '{}': expected off, on, lz4, lzjb, zle, gzip[-1..9], zstd[-1..19] or zstd-fast[-N]",
+            COMPRESSION_PARAM, value
+        )));
+    }
+    Ok(Some(normalized))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compression(value: &str) -> Result<Option<String>> {
+        compression_from_parameters(&HashMap::from([(
+            COMPRESSION_PARAM.to_string(),
+            value.to_string(),
+        )]))
+    }
+
+    #[test]
+    fn test_compression_accepts_known_algorithms() {
+        assert_eq!(compression_from_parameters(&HashMap::new()).unwrap(), None);
+        for value in [
+            "off",
+            "lz4",
+            "zstd",
+            "zstd-3",
+            "zstd-19",
+            "zstd-fast",
+            "zstd-fast-1",
+            "zstd-fast-50",
+            "zstd-fast-1000",
+            "gzip",
+            "gzip-9",
+            "zle",
+        ] {
+            assert_eq!(compression(value).unwrap().as_deref(), Some(value));
+        }
+        assert_eq!(compression("ZSTD").unwrap().as_deref(), Some("zstd"));
+    }
+
+    #[test]
+    fn test_compression_rejects_unknown_values() {
+        for value in [
+            "",
+            "brotli",
+            "zstd-0",
+            "zstd-20",
+            "zstd-03",
+            "zstd-fast-11",
+            "zstd-fast-200",
+            "gzip-10",
+            "lz4 -o readonly=on",
+            "lz4,readonly=on",
+        ] {
+            assert!(
+                matches!(compression(value), Err(ZfsError::InvalidName(_))),
+                "{value:?} accepted"
+            );
+        }
+    }
+}
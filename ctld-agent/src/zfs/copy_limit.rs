@@ -0,0 +1,42 @@
+//! Concurrency limit for bulk data movement.
+//!
+//! `zfs send | zfs recv` copies and image receives stream whole volumes and
+//! can saturate pool bandwidth long before the per-RPC operation limit is
+//! reached. They take a slot from a dedicated [`Limiter`] in addition to the
+//! RPC permit, so heavy copies queue up behind each other while metadata
+//! operations keep flowing.
+//!
+//! On shutdown the limiter is drained: running copies get until the drain
+//! timeout to finish, after which they are aborted. An aborted copy kills its
+//! `zfs send`/`zfs recv` processes and destroys the partially received target
+//! before releasing its slot, so no orphan dataset is left behind.
+
+use std::future::Future;
+
+use csi_common::limit::{Limiter, Permit};
+
+use super::error::{Result, ZfsError};
+use crate::metrics;
+
+/// Default number of concurrent send/recv copies
+pub const DEFAULT_MAX_CONCURRENT_COPIES: usize = 2;
+
+/// Limiter for send/recv copies allowing `limit` at once (at least one),
+/// reporting the running count as a metric
+pub fn copy_limiter(limit: usize) -> Limiter {
+    Limiter::new("copy", limit).with_observer(metrics::set_concurrent_copies)
+}
+
+/// Run `copy` under `permit`, failing with `ZfsError::Aborted` if the
+/// limiter is drained first. Processes spawned by the copy must use
+/// `kill_on_drop` so dropping it stops them.
+pub async fn unless_aborted<T>(
+    permit: &Permit,
+    copy: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    permit.unless_aborted(copy).await.unwrap_or_else(|| {
+        Err(ZfsError::Aborted(
+            "copy interrupted by agent shutdown".to_string(),
+        ))
+    })
+}
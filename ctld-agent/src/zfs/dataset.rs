@@ -1,11 +1,29 @@
-use std::process::Output;
+use std::collections::HashSet;
+use std::future::Future;
+use std::process::{Output, Stdio};
+use std::time::Duration;
 use tokio::process::Command;
 use tracing::{debug, info, instrument, warn};
 
+use super::backend::{self, VolumeBackend};
+use super::compression::compression_from_parameters;
+use super::copy_limit::{DEFAULT_MAX_CONCURRENT_COPIES, copy_limiter, unless_aborted};
+use super::encryption::{Encryption, encryption_from_parameters};
 use super::error::{Result, ZfsError};
 use super::properties::{
-    CURRENT_SCHEMA_VERSION, METADATA_PROPERTY, SNAPSHOT_ID_PROPERTY, VolumeMetadata,
+    CURRENT_SCHEMA_VERSION, METADATA_PROPERTY, SNAPSHOT_ID_PROPERTY, SNAPSHOT_KIND_PROPERTY,
+    SYSTEM_SNAPSHOT_KIND, VolumeMetadata,
 };
+use super::quota::{parse_byte_size, quota_from_parameters, reserves_full_size};
+use crate::parameters::Parameters;
+use csi_common::limit::{Limiter, Permit};
+
+/// Longest dataset or snapshot name ZFS accepts (ZFS_MAX_DATASET_NAME_LEN
+/// minus the terminating NUL)
+pub const MAX_DATASET_NAME_LEN: usize = 255;
+
+/// Name prefix of system snapshots, see [`system_snapshot_name`]
+pub const SYSTEM_SNAPSHOT_PREFIX: &str = "csi-auto-";
 
 /// Result of searching for a snapshot by its CSI snapshot ID
 #[derive(Debug)]
@@ -14,8 +32,9 @@ pub enum FindSnapshotResult {
     NotFound,
     /// Exactly one snapshot found at the given path
     Found(String),
-    /// Multiple snapshots found with the same ID (should not happen)
-    Ambiguous(usize),
+    /// Multiple snapshots found with the same ID (should not happen); holds
+    /// their full paths
+    Ambiguous(Vec<String>),
 }
 
 /// Information about a CSI snapshot retrieved from ZFS
@@ -29,19 +48,107 @@ pub struct CsiSnapshotInfo {
     pub name: String,
     /// Creation timestamp (Unix seconds)
     pub creation_time: i64,
+    /// Space accounting of the snapshot
+    pub space: SnapshotSpace,
+    /// Taken by the agent's own tooling rather than requested through CSI
+    pub system: bool,
+}
+
+/// Space accounting of a snapshot, from its `written`, `used`, `referenced`
+/// and `volsize` properties
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SnapshotSpace {
+    /// Space written since the previous snapshot of the volume
+    pub written_bytes: u64,
+    /// Space held only by this snapshot, freed when it is deleted
+    pub used_bytes: u64,
+    /// Data the snapshot references (allocated blocks including metadata)
+    pub referenced_bytes: u64,
+    /// Minimum size of a volume restored from the snapshot: the volume size
+    /// of a zvol snapshot, whatever it references, and `referenced` for
+    /// file-backed volumes, which have no volsize
+    pub size_bytes: u64,
+}
+
+impl SnapshotSpace {
+    /// Build from raw `-p` property values; unparsable values count as 0
+    fn from_values(written: &str, used: &str, referenced: &str, volsize: &str) -> Self {
+        let referenced_bytes = parse_written_bytes(referenced);
+        Self {
+            written_bytes: parse_written_bytes(written),
+            used_bytes: parse_written_bytes(used),
+            referenced_bytes,
+            size_bytes: volsize.trim().parse().unwrap_or(referenced_bytes),
+        }
+    }
+}
+
+/// Snapshot properties queried for [`SnapshotSpace`], in column order
+const SNAPSHOT_SPACE_PROPERTIES: &str = "written,used,referenced,volsize";
+
+/// Parse `zfs get -Hp -o property,value written,used,referenced,volsize`
+/// output for one snapshot. Missing properties count as 0.
+fn parse_snapshot_space(stdout: &str) -> SnapshotSpace {
+    let value = |property: &str| {
+        stdout
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .find(|(name, _)| *name == property)
+            .map_or("-", |(_, value)| value)
+    };
+    SnapshotSpace::from_values(
+        value("written"),
+        value("used"),
+        value("referenced"),
+        value("volsize"),
+    )
 }
 
 /// Result of looking up CSI volume metadata for one dataset.
 #[derive(Debug, Clone)]
 pub enum VolumeMetadataLookup {
     /// Dataset exists and has valid versioned CSI metadata.
-    Found(VolumeMetadata),
+    Found(Box<VolumeMetadata>),
     /// Dataset exists but has no CSI metadata property.
     MissingMetadata,
     /// Dataset does not exist.
     DatasetNotFound,
 }
 
+/// Result of scanning the parent dataset for CSI metadata
+#[derive(Debug, Default)]
+pub struct MetadataScan {
+    /// Volumes with valid CSI metadata
+    pub volumes: Vec<(String, VolumeMetadata)>,
+    /// Volumes whose CSI metadata property is not valid JSON
+    pub corrupt: Vec<String>,
+    /// Encrypted volumes whose key is not loaded
+    pub locked: Vec<String>,
+    /// Volumes whose metadata was migrated from an older schema in memory
+    pub migrated: Vec<String>,
+}
+
+/// `zfs` stderr fragments reported while the pool cannot accept writes.
+///
+/// Only libzfs' pool-level messages: a plain "Read-only file system" also
+/// comes from a dataset with `readonly=on` and says nothing about the pool.
+const POOL_UNAVAILABLE_PATTERNS: &[&str] = &[
+    "pool is read-only",
+    "i/o is currently suspended",
+    "pool is suspended",
+];
+
+/// Whether `zfs` failed because the pool is read-only or suspended.
+///
+/// These failures are transient from the CO's point of view: the request
+/// may succeed once the pool is imported read-write or resumed.
+fn is_pool_unavailable(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    POOL_UNAVAILABLE_PATTERNS
+        .iter()
+        .any(|pattern| stderr.contains(pattern))
+}
+
 /// Check command output for success or return appropriate error.
 ///
 /// This helper reduces boilerplate for checking command results.
@@ -53,6 +160,16 @@ fn check_command_result(output: &Output, context: &str) -> Result<()> {
 
     let stderr = String::from_utf8_lossy(&output.stderr);
 
+    // A read-only or suspended pool fails every operation; report that
+    // rather than whatever the individual command tripped over
+    if is_pool_unavailable(&stderr) {
+        return Err(ZfsError::PoolUnavailable(format!(
+            "{}: {}",
+            context,
+            stderr.trim()
+        )));
+    }
+
     // Map common error patterns to specific error types
     if stderr.contains("does not exist") || stderr.contains("not found") {
         return Err(ZfsError::DatasetNotFound(context.to_string()));
@@ -67,6 +184,150 @@ fn check_command_result(output: &Output, context: &str) -> Result<()> {
     Err(ZfsError::CommandFailed(format!("{}: {}", context, stderr)))
 }
 
+/// Check the `zfs get -H -o value type` output for the parent dataset.
+///
+/// Volumes are created as children of the parent, which only a filesystem
+/// dataset can have; a zvol or snapshot given as `--zfs-parent` would make
+/// every CreateVolume fail.
+fn check_parent_type(parent: &str, type_output: &str) -> Result<()> {
+    match type_output.trim() {
+        "filesystem" => Ok(()),
+        other => Err(ZfsError::InvalidName(format!(
+            "parent dataset '{}' is a {}, not a filesystem; --zfs-parent must name a \
+             filesystem dataset that volumes can be created under",
+            parent,
+            if other.is_empty() {
+                "dataset of unknown type"
+            } else {
+                other
+            }
+        ))),
+    }
+}
+
+/// `zfs create` arguments for a zvol of `size_bytes`.
+///
+/// For thick provisioning `refreservation` guarantees the space up front.
+fn build_zvol_args(
+    full_name: &str,
+    size_bytes: u64,
+    metadata_property: &str,
+    compression: Option<&str>,
+    volblocksize: Option<u64>,
+    encryption: Option<&Encryption>,
+    thick: bool,
+) -> Vec<String> {
+    let mut args = vec![
+        "create".to_string(),
+        "-V".to_string(),
+        size_bytes.to_string(),
+        "-o".to_string(),
+        "volmode=dev".to_string(),
+        "-o".to_string(),
+        metadata_property.to_string(),
+    ];
+
+    if let Some(compression) = compression {
+        args.push("-o".to_string());
+        args.push(format!("compression={}", compression));
+    }
+
+    if let Some(volblocksize) = volblocksize {
+        args.push("-o".to_string());
+        args.push(format!("volblocksize={}", volblocksize));
+    }
+
+    if let Some(encryption) = encryption {
+        args.extend(encryption.create_options());
+    }
+
+    if thick {
+        args.push("-o".to_string());
+        args.push(format!("refreservation={}", size_bytes));
+    }
+
+    args.push(full_name.to_string());
+    args
+}
+
+/// Full paths of the snapshots tagged with `snapshot_id` in a
+/// `zfs list -H -o name,user:csi:snapshot_id` listing.
+///
+/// The tag, not the path, identifies a CSI snapshot: promotion moves origin
+/// snapshots to the promoted clone, and snapshots taken on a clone live
+/// under the clone's dataset from the start.
+fn snapshot_paths_with_id<'a>(listing: &'a str, snapshot_id: &str) -> Vec<&'a str> {
+    listing
+        .lines()
+        .filter_map(|line| {
+            let (path, tag) = line.split_once('\t')?;
+            (tag.split('\t').next() == Some(snapshot_id)).then_some(path)
+        })
+        .collect()
+}
+
+/// Split `<parent>/<volume>@<snap>` into volume and snapshot name.
+fn snapshot_location(parent_dataset: &str, snapshot_path: &str) -> Option<(String, String)> {
+    let (volume, snap) = snapshot_path
+        .strip_prefix(parent_dataset)?
+        .strip_prefix('/')?
+        .split_once('@')?;
+    if volume.is_empty() || volume.contains('/') || snap.is_empty() {
+        return None;
+    }
+    Some((volume.to_string(), snap.to_string()))
+}
+
+/// CSI snapshot IDs tagged on more than one snapshot in a
+/// `zfs list -H -o name,user:csi:snapshot_id` listing, with their paths,
+/// sorted by ID.
+fn ambiguous_snapshot_ids(listing: &str) -> Vec<(String, Vec<String>)> {
+    let mut by_id: std::collections::BTreeMap<&str, Vec<String>> = Default::default();
+    for line in listing.lines() {
+        let Some((path, tag)) = line.split_once('\t') else {
+            continue;
+        };
+        let tag = tag.split('\t').next().unwrap_or_default();
+        if tag.is_empty() || tag == "-" {
+            continue;
+        }
+        by_id.entry(tag).or_default().push(path.to_string());
+    }
+    by_id
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(id, paths)| (id.to_string(), paths))
+        .collect()
+}
+
+/// Whether the snapshot at the path derived from `snapshot_id` is the CSI
+/// snapshot it names.
+///
+/// A snapshot moved there by promotion keeps the tag of the volume it was
+/// taken on, so it must not be mistaken for a (deleted) snapshot of the
+/// promoted clone with the same name. Untagged snapshots predate tagging
+/// and are accepted.
+pub fn snapshot_tag_matches(tag: Option<&str>, snapshot_id: &str) -> bool {
+    tag.is_none_or(|tag| tag == snapshot_id)
+}
+
+/// Name of a system snapshot taken `since_epoch` after the Unix epoch, e.g.
+/// `csi-auto-1700000000-000250` for 250µs past the second, so snapshots
+/// taken within the same second get distinct names. Names sort by age
+/// within a volume.
+///
+/// System snapshots are taken by tooling outside the CSI flow and tagged
+/// `user:csi:snapshot_kind=system`; ListSnapshots leaves them out unless
+/// they are requested.
+pub fn system_snapshot_name(since_epoch: Duration) -> String {
+    format!(
+        "{}{}-{:06}",
+        SYSTEM_SNAPSHOT_PREFIX,
+        since_epoch.as_secs(),
+        since_epoch.subsec_micros()
+    )
+}
+
 fn classify_dataset_exists(success: bool, stderr: &str, context: &str) -> Result<bool> {
     if success {
         return Ok(true);
@@ -79,15 +340,252 @@ fn classify_dataset_exists(success: bool, stderr: &str, context: &str) -> Result
     Err(ZfsError::CommandFailed(format!("{}: {}", context, stderr)))
 }
 
-/// Escape a string for safe use in shell commands.
-/// Wraps the string in single quotes and escapes any embedded single quotes.
-fn shell_escape(s: &str) -> String {
-    format!("'{}'", s.replace('\'', "'\\''"))
+/// Pick the output line describing exactly `full_name` from `zfs list`/`zfs get`
+/// output whose first tab-separated column is the dataset name.
+///
+/// Single-dataset queries normally print one line, but child datasets or a
+/// stray recursive flag can add more, and the target is not guaranteed to be
+/// first.
+fn select_dataset_line<'a>(stdout: &'a str, full_name: &str) -> Result<&'a str> {
+    stdout
+        .lines()
+        .find(|line| line.split('\t').next() == Some(full_name))
+        .ok_or_else(|| {
+            ZfsError::ParseError(format!("dataset {} not found in zfs output", full_name))
+        })
+}
+
+/// Value column for `full_name` from `zfs get -H -o name,value` output
+fn select_property_value<'a>(stdout: &'a str, full_name: &str) -> Result<&'a str> {
+    let line = select_dataset_line(stdout, full_name)?;
+    Ok(line.split_once('\t').map_or("", |(_, value)| value).trim())
+}
+
+/// Parse the volsize of `full_name` from `zfs get -Hp -o name,value volsize`
+/// output. Filesystems report "-", which maps to None.
+fn parse_volsize_value(stdout: &str, full_name: &str) -> Result<Option<u64>> {
+    match select_property_value(stdout, full_name)? {
+        "-" | "none" | "" => Ok(None),
+        value => value
+            .parse::<u64>()
+            .map(Some)
+            .map_err(|_| ZfsError::ParseError(format!("invalid volsize value: {}", value))),
+    }
+}
+
+/// Parse a space value such as `written` from `-p` output. A "-" (or anything
+/// else that is not a byte count) is reported as 0 rather than failing the
+/// listing.
+fn parse_written_bytes(value: &str) -> u64 {
+    value.trim().parse().unwrap_or(0)
+}
+
+/// Seconds an image download and receive may take when not configured
+pub const DEFAULT_IMAGE_FETCH_TIMEOUT_SECS: u64 = 3600;
+
+/// Validate a volume image URL against an allowlist of schemes.
+///
+/// The URL is handed to fetch(1) as a single argument (no shell), but we still
+/// reject whitespace and control characters so it cannot smuggle options or
+/// confuse log output.
+pub fn validate_image_url(url: &str, allowed_schemes: &[String]) -> Result<()> {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return Err(ZfsError::InvalidName(format!(
+            "image URL '{}' has no scheme",
+            url
+        )));
+    };
+
+    if allowed_schemes.is_empty() {
+        return Err(ZfsError::InvalidName(
+            "provisioning from image URLs is disabled".into(),
+        ));
+    }
+
+    let scheme = scheme.to_ascii_lowercase();
+    if scheme.is_empty()
+        || !allowed_schemes
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(&scheme))
+    {
+        return Err(ZfsError::InvalidName(format!(
+            "image URL scheme '{}' is not allowed (allowed: {})",
+            scheme,
+            allowed_schemes.join(", ")
+        )));
+    }
+
+    if rest.is_empty() || (rest.starts_with('/') && scheme != "file") {
+        return Err(ZfsError::InvalidName(format!(
+            "image URL '{}' has no host",
+            url
+        )));
+    }
+
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(ZfsError::InvalidName(
+            "image URL contains whitespace or control characters".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Build the two halves of the image receive pipeline:
+/// `fetch -q -o - <url> | zfs recv -o <metadata> <target>`.
+///
+/// The commands are spawned separately and connected with a pipe so each
+/// side's exit status is checked (sh has no portable pipefail).
+fn build_image_recv_commands(
+    url: &str,
+    metadata_property: &str,
+    encryption: Option<&Encryption>,
+    target_full: &str,
+) -> (Vec<String>, Vec<String>) {
+    let fetch = vec![
+        "-q".to_string(),
+        "-o".to_string(),
+        "-".to_string(),
+        url.to_string(),
+    ];
+    let mut recv = vec![
+        "recv".to_string(),
+        "-o".to_string(),
+        metadata_property.to_string(),
+    ];
+    if let Some(encryption) = encryption {
+        recv.extend(encryption.create_options());
+    }
+    recv.push(target_full.to_string());
+    (fetch, recv)
+}
+
+/// Build the two halves of the copy pipeline:
+/// `zfs send <snapshot> | zfs recv -o <metadata> <target>`.
+///
+/// Like the image receive, the commands are spawned separately and connected
+/// with a pipe, so both processes can be killed if the copy is aborted.
+fn build_copy_commands(
+    snapshot_full: &str,
+    metadata_property: &str,
+    encryption: Option<&Encryption>,
+    target_full: &str,
+) -> (Vec<String>, Vec<String>) {
+    let send = vec!["send".to_string(), snapshot_full.to_string()];
+    let mut recv = vec![
+        "recv".to_string(),
+        "-o".to_string(),
+        metadata_property.to_string(),
+    ];
+    // A non-raw send is decrypted, so the copy must be re-encrypted on
+    // receive or it would land in plaintext
+    if let Some(encryption) = encryption {
+        recv.extend(encryption.create_options());
+    }
+    recv.push(target_full.to_string());
+    (send, recv)
+}
+
+/// Run a copy under `permit`; if agent shutdown aborts it, wait for
+/// `destroy_partial` to remove the partially received target before
+/// returning `ZfsError::Aborted`.
+async fn abortable_copy<T>(
+    permit: &Permit,
+    copy: impl Future<Output = Result<T>>,
+    destroy_partial: impl Future<Output = ()>,
+) -> Result<T> {
+    let result = unless_aborted(permit, copy).await;
+    if matches!(result, Err(ZfsError::Aborted(_))) {
+        destroy_partial.await;
+    }
+    result
+}
+
+/// Split a `zfs list -H -o name,keystatus,<metadata>` line into its columns
+/// (`keystatus` is `-` for unencrypted datasets).
+fn split_metadata_line(line: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = line.split('\t');
+    Some((parts.next()?, parts.next()?, parts.next()?))
+}
+
+/// Snapshots in `zfs list -H -t snapshot -o name,clones` output that have
+/// no clones, as full paths
+fn unreferenced_snapshots(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(_, clones)| matches!(clones.trim(), "" | "-"))
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// Parse `zfs list -H -o name,keystatus,<metadata>` output for volumes
+/// under `parent`. Datasets without metadata are left out, locked and
+/// unparseable volumes are reported in [`MetadataScan::locked`] and
+/// [`MetadataScan::corrupt`], and metadata from a newer schema is skipped.
+/// Old schemas are migrated in memory only and listed in
+/// [`MetadataScan::migrated`].
+fn parse_metadata_listing(stdout: &str, parent: &str) -> MetadataScan {
+    let prefix = format!("{}/", parent);
+    let mut scan = MetadataScan::default();
+    for line in stdout.lines() {
+        let Some((name, keystatus, metadata_json)) = split_metadata_line(line) else {
+            continue;
+        };
+        // Skips the parent dataset itself
+        let Some(vol_name) = name.strip_prefix(&prefix) else {
+            continue;
+        };
+        let metadata_json = metadata_json.trim();
+        if metadata_json.is_empty() || metadata_json == "-" {
+            debug!(volume = %name, "Volume has no CSI metadata, skipping");
+            continue;
+        }
+
+        // A locked zvol has no device node to export; leave it alone
+        // until its key is loaded
+        if keystatus == "unavailable" {
+            warn!(volume = %vol_name, "Encryption key not loaded, skipping locked volume");
+            scan.locked.push(vol_name.to_string());
+            continue;
+        }
+
+        match serde_json::from_str::<VolumeMetadata>(metadata_json) {
+            Ok(metadata) if metadata.schema_version > CURRENT_SCHEMA_VERSION => {
+                warn!(
+                    volume = %vol_name,
+                    metadata_version = metadata.schema_version,
+                    supported_version = CURRENT_SCHEMA_VERSION,
+                    "Metadata version too new, skipping (upgrade ctld-agent to manage this volume)"
+                );
+            }
+            Ok(mut metadata) => {
+                if metadata.needs_migration() {
+                    let from_version = metadata.schema_version;
+                    metadata.migrate();
+                    info!(
+                        volume = %vol_name,
+                        from_version = from_version,
+                        to_version = CURRENT_SCHEMA_VERSION,
+                        "Migrated metadata schema"
+                    );
+                    scan.migrated.push(vol_name.to_string());
+                }
+                debug!(volume = %vol_name, "Found volume with valid CSI metadata");
+                scan.volumes.push((vol_name.to_string(), metadata));
+            }
+            Err(e) => {
+                warn!(volume = %name, error = %e, "Corrupt CSI metadata, skipping");
+                scan.corrupt.push(vol_name.to_string());
+            }
+        }
+    }
+    scan
 }
 
 /// Validate that a name is safe for use in ZFS commands.
 /// Only allows alphanumeric characters, underscores, hyphens, and periods.
-fn validate_name(name: &str) -> Result<()> {
+pub(super) fn validate_name(name: &str) -> Result<()> {
     if name.is_empty() {
         return Err(ZfsError::InvalidName("name cannot be empty".into()));
     }
@@ -106,6 +604,32 @@ fn validate_name(name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Check that `parent/name` (or `parent/name@snap_name`) fits within ZFS's
+/// name length limit, so an oversized name is rejected before any `zfs`
+/// command runs instead of failing halfway through provisioning.
+fn check_name_length(parent: &str, name: &str, snap_name: Option<&str>) -> Result<()> {
+    let full_len = parent.len() + 1 + name.len() + snap_name.map_or(0, |s| s.len() + 1);
+    if full_len > MAX_DATASET_NAME_LEN {
+        let full_name = match snap_name {
+            Some(snap) => format!("{}/{}@{}", parent, name, snap),
+            None => format!("{}/{}", parent, name),
+        };
+        return Err(ZfsError::InvalidName(format!(
+            "'{}' is {} characters long, exceeding the ZFS limit of {}",
+            full_name, full_len, MAX_DATASET_NAME_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Size of a file-backed volume's backing file, given its dataset's mountpoint.
+///
+/// Returns None for filesystems that are not file-backed volumes.
+async fn backing_file_size(full_name: &str, mountpoint: &str) -> Option<u64> {
+    let path = backend::backing_file_path(full_name, mountpoint).ok()?;
+    tokio::fs::metadata(&path).await.ok().map(|m| m.len())
+}
+
 /// Serialize metadata into a ZFS property string (key=value format).
 fn format_metadata_property(metadata: &VolumeMetadata) -> Result<String> {
     let json = serde_json::to_string(metadata)
@@ -120,8 +644,11 @@ pub struct Dataset {
     pub name: String,
     /// Referenced space in bytes
     pub referenced: u64,
-    /// Volume size in bytes (only for zvols)
+    /// Volume size in bytes (zvol volsize, or backing file size for
+    /// file-backed volumes)
     pub volsize: Option<u64>,
+    /// Space reserved for the dataset (`refreservation`), 0 when none
+    pub refreservation: u64,
 }
 
 /// Capacity information for the ZFS storage pool/dataset
@@ -133,10 +660,83 @@ pub struct Capacity {
     pub used: u64,
 }
 
+/// Health and fragmentation of the pool holding the parent dataset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolStatus {
+    /// Pool name
+    pub pool: String,
+    /// `ONLINE`, `DEGRADED`, `FAULTED`, `OFFLINE`, `UNAVAIL` or `REMOVED`
+    pub health: String,
+    /// Free-space fragmentation, `None` for pools that do not track it
+    pub fragmentation_percent: Option<u32>,
+    /// Share of the pool's space in use
+    pub capacity_percent: u32,
+}
+
+/// Pool holding `dataset`: its first path component
+fn pool_name(dataset: &str) -> &str {
+    dataset.split('/').next().unwrap_or(dataset)
+}
+
+/// Parse `zpool get -H -p -o property,value fragmentation,health,capacity`
+/// output. Fragmentation is `-` when the pool does not track it.
+fn parse_pool_status(stdout: &str, pool: &str) -> Result<PoolStatus> {
+    let get = |property: &str| {
+        stdout
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .find(|(name, _)| name.trim() == property)
+            .map(|(_, value)| value.trim())
+            .ok_or_else(|| {
+                ZfsError::ParseError(format!("zpool get output for {} lacks {}", pool, property))
+            })
+    };
+    let percent = |property: &str, value: &str| {
+        value.trim_end_matches('%').parse::<u32>().map_err(|_| {
+            ZfsError::ParseError(format!(
+                "invalid {} '{}' for pool {}",
+                property, value, pool
+            ))
+        })
+    };
+
+    let fragmentation = get("fragmentation")?;
+    Ok(PoolStatus {
+        pool: pool.to_string(),
+        health: get("health")?.to_string(),
+        fragmentation_percent: match fragmentation {
+            "-" => None,
+            value => Some(percent("fragmentation", value)?),
+        },
+        capacity_percent: percent("capacity", get("capacity")?)?,
+    })
+}
+
+/// Parse `zpool get -H -o property,value readonly,health` output: whether
+/// the pool is imported read-write and neither suspended nor faulted
+fn parse_pool_writable(stdout: &str, pool: &str) -> Result<bool> {
+    let get = |property: &str| {
+        stdout
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .find(|(name, _)| name.trim() == property)
+            .map(|(_, value)| value.trim())
+            .ok_or_else(|| {
+                ZfsError::ParseError(format!("zpool get output for {} lacks {}", pool, property))
+            })
+    };
+
+    Ok(get("readonly")? == "off" && matches!(get("health")?, "ONLINE" | "DEGRADED"))
+}
+
 /// Manager for ZFS operations under a parent dataset
 pub struct ZfsManager {
     /// Parent dataset under which all volumes are created
     parent_dataset: String,
+    /// Limits concurrent send/recv copies
+    copy_limiter: Limiter,
+    /// Longest an image download and receive may run
+    image_fetch_timeout: Duration,
 }
 
 impl ZfsManager {
@@ -151,9 +751,9 @@ impl ZfsManager {
             ));
         }
 
-        // Verify parent dataset exists
+        // Verify parent dataset exists and can hold volumes
         let output = Command::new("zfs")
-            .args(["list", "-H", "-o", "name", &parent_dataset])
+            .args(["get", "-H", "-o", "value", "type", &parent_dataset])
             .output()
             .await?;
 
@@ -164,9 +764,42 @@ impl ZfsManager {
             }
             return Err(ZfsError::CommandFailed(stderr.to_string()));
         }
+        check_parent_type(&parent_dataset, &String::from_utf8_lossy(&output.stdout))?;
 
         info!(dataset = %parent_dataset, "ZFS manager initialized successfully");
-        Ok(Self { parent_dataset })
+        Ok(Self {
+            parent_dataset,
+            copy_limiter: copy_limiter(DEFAULT_MAX_CONCURRENT_COPIES),
+            image_fetch_timeout: Duration::from_secs(DEFAULT_IMAGE_FETCH_TIMEOUT_SECS),
+        })
+    }
+
+    /// Manager for `parent_dataset` without checking that it exists, for
+    /// tests that never reach ZFS
+    #[cfg(test)]
+    pub(crate) fn unchecked(parent_dataset: &str) -> Self {
+        Self {
+            parent_dataset: parent_dataset.to_string(),
+            copy_limiter: copy_limiter(DEFAULT_MAX_CONCURRENT_COPIES),
+            image_fetch_timeout: Duration::from_secs(DEFAULT_IMAGE_FETCH_TIMEOUT_SECS),
+        }
+    }
+
+    /// Limit the number of concurrent send/recv copies
+    pub fn with_max_concurrent_copies(mut self, limit: usize) -> Self {
+        self.copy_limiter = copy_limiter(limit);
+        self
+    }
+
+    /// Limit how long an image download and receive may run
+    pub fn with_image_fetch_timeout(mut self, timeout: Duration) -> Self {
+        self.image_fetch_timeout = timeout;
+        self
+    }
+
+    /// Limiter shared by this manager's copies, for draining on shutdown
+    pub fn copy_limiter(&self) -> Limiter {
+        self.copy_limiter.clone()
     }
 
     /// Get the full dataset path for a volume name
@@ -174,6 +807,12 @@ impl ZfsManager {
         format!("{}/{}", self.parent_dataset, name)
     }
 
+    /// Check that a volume (or one of its snapshots) would have a full name
+    /// within ZFS's length limit under this manager's parent dataset
+    pub fn check_name_length(&self, name: &str, snap_name: Option<&str>) -> Result<()> {
+        check_name_length(&self.parent_dataset, name, snap_name)
+    }
+
     /// Create a new ZFS volume (zvol) with metadata set atomically
     ///
     /// The metadata is set as a ZFS user property during creation, ensuring
@@ -182,6 +821,18 @@ impl ZfsManager {
     /// Supports thin/thick provisioning via `provisioningMode` parameter:
     /// - "thin" (default): No reservation, space allocated on write
     /// - "thick": Sets refreservation=volsize to guarantee space upfront
+    ///
+    /// `maxOverprovision=false` reserves the full size like "thick". A
+    /// `quota` caps a file-backed volume's dataset (see [`super::quota`]).
+    ///
+    /// A `compression` parameter sets the dataset's compression algorithm;
+    /// without it the setting is inherited from the parent. A `volblocksize`
+    /// parameter fixes the zvol's block size (it cannot change afterwards).
+    /// With `encryption=on` the volume is its own encryption root, keyed by
+    /// the `keyLocation` file.
+    ///
+    /// With `backend=file` a filesystem dataset holding a sparse backing file
+    /// is created instead of a zvol (see [`ZfsManager::create_file_volume`]).
     #[instrument(skip(self, metadata))]
     pub async fn create_volume(
         &self,
@@ -191,17 +842,45 @@ impl ZfsManager {
     ) -> Result<Dataset> {
         // Validate name for command injection prevention
         validate_name(name)?;
+        self.check_name_length(name, None)?;
 
         let full_name = self.full_path(name);
 
         let metadata_property = format_metadata_property(metadata)?;
 
+        // Reject unknown algorithms before they reach the command line
+        let compression = compression_from_parameters(&metadata.parameters)?;
+        let encryption = encryption_from_parameters(&metadata.parameters)?;
+
         // Check provisioning mode from StorageClass parameters
-        let is_thick = metadata
-            .parameters
-            .get("provisioningMode")
-            .map(|v| v.eq_ignore_ascii_case("thick"))
-            .unwrap_or(false);
+        let is_thick = reserves_full_size(&metadata.parameters)?;
+
+        let volume_backend =
+            VolumeBackend::from_parameters(&metadata.parameters).map_err(ZfsError::ParseError)?;
+        let quota = quota_from_parameters(&metadata.parameters, volume_backend, size_bytes)?;
+        if volume_backend == VolumeBackend::File {
+            let record_size = Parameters::new(&metadata.parameters)
+                .get(backend::RECORD_SIZE_PARAM)
+                .map(backend::parse_record_size)
+                .transpose()
+                .map_err(ZfsError::ParseError)?;
+            let thick_size = is_thick.then_some(size_bytes);
+            return self
+                .create_file_volume(
+                    name,
+                    size_bytes,
+                    &backend::build_file_dataset_args(
+                        &full_name,
+                        record_size,
+                        compression.as_deref(),
+                        encryption.as_ref(),
+                        &metadata_property,
+                        thick_size,
+                        quota,
+                    ),
+                )
+                .await;
+        }
 
         info!(
             volume = %full_name,
@@ -210,24 +889,21 @@ impl ZfsManager {
             "Creating ZFS volume with metadata"
         );
 
-        // Build command arguments
-        let mut args = vec![
-            "create".to_string(),
-            "-V".to_string(),
-            size_bytes.to_string(),
-            "-o".to_string(),
-            "volmode=dev".to_string(),
-            "-o".to_string(),
-            metadata_property,
-        ];
-
-        // For thick provisioning, set refreservation to guarantee space
-        if is_thick {
-            args.push("-o".to_string());
-            args.push(format!("refreservation={}", size_bytes));
-        }
+        let volblocksize = Parameters::new(&metadata.parameters)
+            .get(backend::VOLBLOCKSIZE_PARAM)
+            .map(backend::parse_volblocksize)
+            .transpose()
+            .map_err(ZfsError::InvalidName)?;
 
-        args.push(full_name.clone());
+        let args = build_zvol_args(
+            &full_name,
+            size_bytes,
+            &metadata_property,
+            compression.as_deref(),
+            volblocksize,
+            encryption.as_ref(),
+            is_thick,
+        );
 
         // Create the volume with volmode=dev and metadata set atomically
         // Let zfs create fail if already exists (avoids TOCTOU race)
@@ -248,6 +924,132 @@ impl ZfsManager {
         self.get_dataset(name).await
     }
 
+    /// Create a file-backed volume: a filesystem dataset plus its backing file.
+    ///
+    /// If the backing file cannot be created the dataset is destroyed again so
+    /// a retry starts from scratch.
+    async fn create_file_volume(
+        &self,
+        name: &str,
+        size_bytes: u64,
+        create_args: &[String],
+    ) -> Result<Dataset> {
+        let full_name = self.full_path(name);
+        info!(volume = %full_name, size_bytes, "Creating file-backed ZFS volume");
+
+        // Let zfs create fail if already exists (avoids TOCTOU race)
+        let output = Command::new("zfs").args(create_args).output().await?;
+        if let Err(e) = check_command_result(&output, &full_name) {
+            warn!(volume = %full_name, error = %e, "Failed to create volume dataset");
+            return Err(e);
+        }
+
+        let created = match self.backing_file(name).await {
+            Ok(path) => {
+                self.truncate_backing_file(&full_name, &path, size_bytes)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = created {
+            warn!(volume = %full_name, error = %e, "Failed to create backing file, removing dataset");
+            if let Err(cleanup) = self.delete_volume(name).await {
+                warn!(volume = %full_name, error = %cleanup, "Failed to remove dataset");
+            }
+            return Err(e);
+        }
+
+        info!(volume = %full_name, size_bytes, "File-backed ZFS volume created successfully");
+        self.get_dataset(name).await
+    }
+
+    /// Path of a file-backed volume's backing file
+    #[instrument(skip(self))]
+    pub async fn backing_file(&self, name: &str) -> Result<String> {
+        validate_name(name)?;
+
+        let full_name = self.full_path(name);
+        let output = Command::new("zfs")
+            .args(["get", "-H", "-o", "name,value", "mountpoint", &full_name])
+            .output()
+            .await?;
+        check_command_result(&output, &full_name)?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mountpoint = select_property_value(&stdout, &full_name)?;
+        backend::backing_file_path(&full_name, mountpoint)
+    }
+
+    /// Device path to export for a volume with the given backend
+    pub async fn volume_device_path(&self, name: &str, backend: VolumeBackend) -> Result<String> {
+        match backend {
+            VolumeBackend::Zvol => Ok(self.get_device_path(name)),
+            VolumeBackend::File => self.backing_file(name).await,
+        }
+    }
+
+    /// Resize a file-backed volume's backing file
+    ///
+    /// With `thick`, the dataset's `refreservation` is raised to the new size
+    /// first, so the file is only grown once its space is guaranteed.
+    #[instrument(skip(self))]
+    pub async fn resize_backing_file(
+        &self,
+        name: &str,
+        new_size_bytes: u64,
+        thick: bool,
+    ) -> Result<()> {
+        let full_name = self.full_path(name);
+        info!(volume = %full_name, new_size_bytes, "Resizing backing file");
+
+        let path = self.backing_file(name).await?;
+        if thick {
+            let output = Command::new("zfs")
+                .args([
+                    "set",
+                    &format!("refreservation={}", new_size_bytes),
+                    &full_name,
+                ])
+                .output()
+                .await?;
+            if let Err(e) = check_command_result(&output, &full_name) {
+                warn!(volume = %full_name, error = %e, "Failed to raise reservation");
+                return Err(e);
+            }
+        }
+        if let Err(e) = self
+            .truncate_backing_file(&full_name, &path, new_size_bytes)
+            .await
+        {
+            warn!(volume = %full_name, error = %e, "Failed to resize backing file");
+            return Err(e);
+        }
+
+        info!(volume = %full_name, new_size_bytes, "Backing file resized successfully");
+        Ok(())
+    }
+
+    /// Create or resize a sparse backing file with `truncate`
+    async fn truncate_backing_file(
+        &self,
+        full_name: &str,
+        path: &str,
+        size_bytes: u64,
+    ) -> Result<()> {
+        let output = Command::new("truncate")
+            .args(backend::build_backing_file_args(path, size_bytes))
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(ZfsError::CommandFailed(format!(
+                "truncate {} for {} failed: {}",
+                path, full_name, stderr
+            )));
+        }
+        Ok(())
+    }
+
     /// Delete a ZFS volume
     ///
     /// This operation is idempotent: if the volume doesn't exist, returns Ok.
@@ -305,8 +1107,12 @@ impl ZfsManager {
     }
 
     /// Resize a ZFS volume
+    ///
+    /// Only `volsize` changes; `volblocksize` is fixed at creation. With
+    /// `thick`, `refreservation` is raised to the new size in the same
+    /// command, so the volume keeps its full reservation.
     #[instrument(skip(self))]
-    pub async fn resize_volume(&self, name: &str, new_size_bytes: u64) -> Result<()> {
+    pub async fn resize_volume(&self, name: &str, new_size_bytes: u64, thick: bool) -> Result<()> {
         // Validate name for command injection prevention
         validate_name(name)?;
 
@@ -319,10 +1125,12 @@ impl ZfsManager {
             return Err(ZfsError::DatasetNotFound(full_name));
         }
 
-        let output = Command::new("zfs")
-            .args(["set", &format!("volsize={}", new_size_bytes), &full_name])
-            .output()
-            .await?;
+        let mut args = vec!["set".to_string(), format!("volsize={}", new_size_bytes)];
+        if thick {
+            args.push(format!("refreservation={}", new_size_bytes));
+        }
+        args.push(full_name.clone());
+        let output = Command::new("zfs").args(&args).output().await?;
 
         if let Err(e) = check_command_result(&output, &full_name) {
             warn!(volume = %full_name, error = %e, "Failed to resize volume");
@@ -344,6 +1152,7 @@ impl ZfsManager {
         // Validate names for command injection prevention
         validate_name(volume_name)?;
         validate_name(snap_name)?;
+        self.check_name_length(volume_name, Some(snap_name))?;
 
         let full_volume = self.full_path(volume_name);
         let snapshot_path = format!("{}@{}", full_volume, snap_name);
@@ -351,12 +1160,6 @@ impl ZfsManager {
         let snapshot_id = format!("{}@{}", volume_name, snap_name);
         info!(volume = %full_volume, snapshot = %snap_name, snapshot_id = %snapshot_id, "Creating ZFS snapshot");
 
-        // Check if volume exists
-        if !self.dataset_exists(&full_volume).await? {
-            warn!(volume = %full_volume, "Volume not found for snapshot");
-            return Err(ZfsError::DatasetNotFound(full_volume));
-        }
-
         // Create snapshot with CSI snapshot ID property set atomically
         let property_arg = format!(
             "{}={}",
@@ -368,9 +1171,17 @@ impl ZfsManager {
             .output()
             .await?;
 
-        if let Err(e) = check_command_result(&output, &snapshot_path) {
-            warn!(snapshot = %snapshot_path, error = %e, "Failed to create snapshot");
-            return Err(e);
+        match check_command_result(&output, &snapshot_path) {
+            Ok(()) => {}
+            // A missing volume is reported rather than checked up front
+            Err(ZfsError::DatasetNotFound(_)) => {
+                warn!(volume = %full_volume, "Volume not found for snapshot");
+                return Err(ZfsError::DatasetNotFound(full_volume));
+            }
+            Err(e) => {
+                warn!(snapshot = %snapshot_path, error = %e, "Failed to create snapshot");
+                return Err(e);
+            }
         }
 
         info!(snapshot = %snapshot_path, snapshot_id = %snapshot_id, "ZFS snapshot created successfully");
@@ -465,49 +1276,15 @@ impl ZfsManager {
     /// Returns:
     /// - `NotFound` if no snapshot has the given ID
     /// - `Found(path)` if exactly one snapshot matches
-    /// - `Ambiguous(count)` if multiple snapshots match (should not happen with UUIDs)
+    /// - `Ambiguous(paths)` if multiple snapshots match (should not happen with UUIDs)
     #[instrument(skip(self))]
     pub async fn find_snapshot_by_id(&self, snapshot_id: &str) -> Result<FindSnapshotResult> {
         debug!(snapshot_id = %snapshot_id, "Searching for snapshot by CSI ID");
 
-        // List all snapshots with their CSI snapshot ID property
-        let output = Command::new("zfs")
-            .args([
-                "list",
-                "-H",
-                "-t",
-                "snapshot",
-                "-o",
-                &format!("name,{}", SNAPSHOT_ID_PROPERTY),
-                "-r",
-                &self.parent_dataset,
-            ])
-            .output()
-            .await?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            if stderr.contains("no datasets available") {
-                return Ok(FindSnapshotResult::NotFound);
-            }
-            return Err(ZfsError::CommandFailed(format!(
-                "failed to search snapshots: {}",
-                stderr
-            )));
-        }
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let matches: Vec<&str> = stdout
-            .lines()
-            .filter_map(|line| {
-                let parts: Vec<&str> = line.split('\t').collect();
-                if parts.len() >= 2 && parts[1] == snapshot_id {
-                    Some(parts[0])
-                } else {
-                    None
-                }
-            })
-            .collect();
+        let Some(stdout) = self.list_snapshot_tags().await? else {
+            return Ok(FindSnapshotResult::NotFound);
+        };
+        let matches = snapshot_paths_with_id(&stdout, snapshot_id);
 
         match matches.len() {
             0 => {
@@ -523,18 +1300,113 @@ impl ZfsManager {
                 warn!(
                     snapshot_id = %snapshot_id,
                     count = n,
-                    "Multiple snapshots found with same CSI ID - refusing to delete"
+                    paths = ?matches,
+                    "Multiple snapshots found with same CSI ID"
                 );
-                Ok(FindSnapshotResult::Ambiguous(n))
+                Ok(FindSnapshotResult::Ambiguous(
+                    matches.into_iter().map(str::to_string).collect(),
+                ))
+            }
+        }
+    }
+
+    /// Volume and snapshot name of a snapshot path found by
+    /// [`find_snapshot_by_id`](Self::find_snapshot_by_id), or `None` if it is
+    /// not a snapshot of a volume directly under the parent dataset.
+    pub fn snapshot_location(&self, snapshot_path: &str) -> Option<(String, String)> {
+        snapshot_location(&self.parent_dataset, snapshot_path)
+    }
+
+    /// Every CSI snapshot ID tagged on more than one snapshot, with the
+    /// paths carrying it. Such IDs cannot be deleted until resolved by hand.
+    #[instrument(skip(self))]
+    pub async fn list_ambiguous_snapshots(&self) -> Result<Vec<(String, Vec<String>)>> {
+        let Some(stdout) = self.list_snapshot_tags().await? else {
+            return Ok(Vec::new());
+        };
+        Ok(ambiguous_snapshot_ids(&stdout))
+    }
+
+    /// `zfs list -H -o name,<snapshot ID tag>` of every snapshot under the
+    /// parent dataset, or `None` if there are none.
+    async fn list_snapshot_tags(&self) -> Result<Option<String>> {
+        let output = Command::new("zfs")
+            .args([
+                "list",
+                "-H",
+                "-t",
+                "snapshot",
+                "-o",
+                &format!("name,{}", SNAPSHOT_ID_PROPERTY),
+                "-r",
+                &self.parent_dataset,
+            ])
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.contains("no datasets available") {
+                return Ok(None);
             }
+            return Err(ZfsError::CommandFailed(format!(
+                "failed to search snapshots: {}",
+                stderr
+            )));
+        }
+
+        Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+
+    /// Read the CSI snapshot ID tag of `volume_name@snap_name`.
+    ///
+    /// Returns `None` for snapshots without the tag, and `DatasetNotFound`
+    /// if the snapshot does not exist.
+    #[instrument(skip(self))]
+    pub async fn get_snapshot_id(
+        &self,
+        volume_name: &str,
+        snap_name: &str,
+    ) -> Result<Option<String>> {
+        validate_name(volume_name)?;
+        validate_name(snap_name)?;
+
+        let snapshot_path = format!("{}@{}", self.full_path(volume_name), snap_name);
+        self.get_snapshot_id_at(&snapshot_path).await
+    }
+
+    /// Read the CSI snapshot ID tag of a snapshot given by its full path,
+    /// e.g. a clone's origin, which may lie outside the parent dataset.
+    #[instrument(skip(self))]
+    pub async fn get_snapshot_id_at(&self, snapshot_path: &str) -> Result<Option<String>> {
+        if !snapshot_path.contains('@') {
+            return Err(ZfsError::InvalidName(format!(
+                "'{}' is not a snapshot",
+                snapshot_path
+            )));
         }
+        let output = Command::new("zfs")
+            .args([
+                "get",
+                "-H",
+                "-o",
+                "value",
+                SNAPSHOT_ID_PROPERTY,
+                snapshot_path,
+            ])
+            .output()
+            .await?;
+        check_command_result(&output, snapshot_path)?;
+
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(Some(value).filter(|v| !v.is_empty() && v != "-"))
     }
 
     /// List all ZFS snapshots with CSI metadata
     ///
     /// This queries ZFS for all snapshots under the parent dataset that have the
     /// `user:csi:snapshot_id` property set. Returns snapshot information including
-    /// the snapshot ID, source volume ID, name, and creation time.
+    /// the snapshot ID, source volume ID, name, creation time and space usage.
     ///
     /// This is used by ListSnapshots to query ZFS directly instead of relying on
     /// an in-memory cache, ensuring the list survives restarts and always reflects
@@ -543,16 +1415,21 @@ impl ZfsManager {
     pub async fn list_csi_snapshots(&self) -> Result<Vec<CsiSnapshotInfo>> {
         debug!("Listing all CSI snapshots");
 
-        // List all snapshots with their CSI snapshot ID property and creation time
-        // Format: name<TAB>user:csi:snapshot_id<TAB>creation
+        // List all snapshots with their CSI snapshot ID and kind properties,
+        // creation time and space usage, as exact numbers
+        // Format: name<TAB>user:csi:snapshot_id<TAB>user:csi:snapshot_kind
+        //         <TAB>creation<TAB>written<TAB>used<TAB>referenced<TAB>volsize
         let output = Command::new("zfs")
             .args([
                 "list",
-                "-H",
+                "-Hp",
                 "-t",
                 "snapshot",
                 "-o",
-                &format!("name,{},creation", SNAPSHOT_ID_PROPERTY),
+                &format!(
+                    "name,{},{},creation,{}",
+                    SNAPSHOT_ID_PROPERTY, SNAPSHOT_KIND_PROPERTY, SNAPSHOT_SPACE_PROPERTIES
+                ),
                 "-r",
                 &self.parent_dataset,
             ])
@@ -575,13 +1452,15 @@ impl ZfsManager {
 
         for line in stdout.lines() {
             let parts: Vec<&str> = line.split('\t').collect();
-            if parts.len() < 3 {
+            if parts.len() < 8 {
                 continue;
             }
 
             let _zfs_name = parts[0];
             let snapshot_id = parts[1];
-            let creation_str = parts[2];
+            let system = parts[2] == SYSTEM_SNAPSHOT_KIND;
+            let creation_str = parts[3];
+            let space = SnapshotSpace::from_values(parts[4], parts[5], parts[6], parts[7]);
 
             // Skip snapshots without a CSI snapshot ID (indicated by "-" in ZFS output)
             if snapshot_id == "-" || snapshot_id.is_empty() {
@@ -598,15 +1477,20 @@ impl ZfsManager {
                 }
             };
 
-            // Parse creation time - ZFS returns it in a human-readable format
-            // We need to convert it to Unix timestamp
-            let creation_time = Self::parse_zfs_creation_time(creation_str).await;
+            // -p reports the creation time as Unix seconds; parse the
+            // human-readable format in case a ZFS version does not
+            let creation_time = match creation_str.parse() {
+                Ok(seconds) => seconds,
+                Err(_) => Self::parse_zfs_creation_time(creation_str).await,
+            };
 
             snapshots.push(CsiSnapshotInfo {
                 snapshot_id: snapshot_id.to_string(),
                 source_volume_id,
                 name,
                 creation_time,
+                space,
+                system,
             });
         }
 
@@ -680,10 +1564,10 @@ impl ZfsManager {
                 "-H",
                 "-p", // Machine-parseable output (bytes)
                 "-t",
-                "volume",
+                "volume,filesystem",
                 "-r",
                 "-o",
-                "name,refer,volsize",
+                "name,refer,volsize,mountpoint,refreservation",
                 &self.parent_dataset,
             ])
             .output()
@@ -703,12 +1587,22 @@ impl ZfsManager {
                 continue;
             }
 
-            let dataset = self.parse_dataset_line(line)?;
+            let mut dataset = self.parse_dataset_line(line)?;
             // Only include direct children (volumes under our parent)
-            if dataset.name.starts_with(&self.parent_dataset) && dataset.name != self.parent_dataset
+            if !dataset.name.starts_with(&self.parent_dataset)
+                || dataset.name == self.parent_dataset
             {
-                datasets.push(dataset);
+                continue;
+            }
+            // Filesystems are volumes only if they hold a backing file
+            if dataset.volsize.is_none() {
+                let mountpoint = line.split('\t').nth(3).unwrap_or("-");
+                match backing_file_size(&dataset.name, mountpoint).await {
+                    Some(size) => dataset.volsize = Some(size),
+                    None => continue,
+                }
             }
+            datasets.push(dataset);
         }
 
         debug!(count = datasets.len(), "Found volumes");
@@ -765,7 +1659,14 @@ impl ZfsManager {
         let full_name = self.full_path(name);
 
         let output = Command::new("zfs")
-            .args(["get", "-H", "-o", "value", METADATA_PROPERTY, &full_name])
+            .args([
+                "get",
+                "-H",
+                "-o",
+                "name,value",
+                METADATA_PROPERTY,
+                &full_name,
+            ])
             .output()
             .await?;
 
@@ -780,8 +1681,8 @@ impl ZfsManager {
             )));
         }
 
-        let metadata_json = String::from_utf8_lossy(&output.stdout);
-        let metadata_json = metadata_json.trim();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let metadata_json = select_property_value(&stdout, &full_name)?;
         if metadata_json.is_empty() || metadata_json == "-" {
             return Ok(VolumeMetadataLookup::MissingMetadata);
         }
@@ -814,7 +1715,7 @@ impl ZfsManager {
             }
         }
 
-        Ok(VolumeMetadataLookup::Found(metadata))
+        Ok(VolumeMetadataLookup::Found(Box::new(metadata)))
     }
 
     /// Clear volume metadata (on deletion)
@@ -834,20 +1735,73 @@ impl ZfsManager {
         Ok(())
     }
 
-    /// List all volumes with CSI metadata (for startup recovery)
+    /// List all volumes with CSI metadata (for startup recovery).
+    ///
+    /// Volumes whose metadata cannot be parsed are skipped and reported in
+    /// [`MetadataScan::corrupt`]. Metadata migrated from an older schema is
+    /// persisted back to ZFS.
     #[instrument(skip(self))]
-    pub async fn list_volumes_with_metadata(&self) -> Result<Vec<(String, VolumeMetadata)>> {
+    pub async fn list_volumes_with_metadata(&self) -> Result<MetadataScan> {
         info!(parent = %self.parent_dataset, "Scanning for volumes with CSI metadata");
 
+        let scan = self.scan_metadata().await?;
+        for (name, metadata) in &scan.volumes {
+            if !scan.migrated.contains(name) {
+                continue;
+            }
+            if let Err(e) = self.set_volume_metadata(name, metadata).await {
+                warn!(
+                    volume = %name,
+                    error = %e,
+                    "Failed to persist migrated metadata (will retry on next scan)"
+                );
+            }
+        }
+
+        info!(
+            count = scan.volumes.len(),
+            corrupt = scan.corrupt.len(),
+            locked = scan.locked.len(),
+            "Volume scan complete"
+        );
+        Ok(scan)
+    }
+
+    /// Read CSI metadata of the named volumes, for callers whose cached
+    /// metadata is missing.
+    ///
+    /// Unlike [`Self::list_volumes_with_metadata`] this never writes: migrated
+    /// metadata is not persisted. Volumes without metadata, or destroyed
+    /// since the caller listed them, are left out.
+    #[instrument(skip(self, names))]
+    pub async fn read_volumes_metadata(&self, names: &[String]) -> Result<MetadataScan> {
+        if names.is_empty() {
+            return Ok(MetadataScan::default());
+        }
+
+        // One recursive listing of the parent rather than a name per
+        // argument, which could outgrow the argument list
+        let mut scan = self.scan_metadata().await?;
+        let names: HashSet<&str> = names.iter().map(String::as_str).collect();
+        let wanted = |name: &String| names.contains(name.as_str());
+        scan.volumes.retain(|(name, _)| wanted(name));
+        scan.corrupt.retain(wanted);
+        scan.locked.retain(wanted);
+        scan.migrated.retain(wanted);
+        Ok(scan)
+    }
+
+    /// Read the CSI metadata of every dataset under the parent
+    async fn scan_metadata(&self) -> Result<MetadataScan> {
         let output = Command::new("zfs")
             .args([
                 "list",
                 "-H",
                 "-r",
                 "-t",
-                "volume",
+                "volume,filesystem",
                 "-o",
-                &format!("name,{}", METADATA_PROPERTY),
+                &format!("name,keystatus,{}", METADATA_PROPERTY),
                 &self.parent_dataset,
             ])
             .output()
@@ -860,82 +1814,7 @@ impl ZfsManager {
         }
 
         let stdout = String::from_utf8_lossy(&output.stdout);
-        let mut results = Vec::new();
-
-        for line in stdout.lines() {
-            if line.trim().is_empty() {
-                continue;
-            }
-
-            let parts: Vec<&str> = line.split('\t').collect();
-            if parts.len() < 2 {
-                continue;
-            }
-
-            let name = parts[0];
-            let metadata_json = parts[1];
-
-            // Skip parent dataset itself
-            if name == self.parent_dataset {
-                continue;
-            }
-
-            // Skip volumes without metadata
-            if metadata_json.is_empty() || metadata_json == "-" {
-                debug!(volume = %name, "Volume has no CSI metadata, skipping");
-                continue;
-            }
-
-            // Parse metadata
-            // Extract volume name (remove parent prefix)
-            let vol_name = name
-                .strip_prefix(&format!("{}/", self.parent_dataset))
-                .unwrap_or(name)
-                .to_string();
-
-            match serde_json::from_str::<VolumeMetadata>(metadata_json) {
-                Ok(mut metadata) => {
-                    // Reject metadata from future versions we don't understand
-                    if metadata.schema_version > CURRENT_SCHEMA_VERSION {
-                        warn!(
-                            volume = %vol_name,
-                            metadata_version = metadata.schema_version,
-                            supported_version = CURRENT_SCHEMA_VERSION,
-                            "Metadata version too new, skipping (upgrade ctld-agent to manage this volume)"
-                        );
-                        continue;
-                    }
-
-                    // Migrate old metadata formats to current version and persist
-                    if metadata.needs_migration() {
-                        let from_version = metadata.schema_version;
-                        metadata.migrate();
-                        info!(
-                            volume = %vol_name,
-                            from_version = from_version,
-                            to_version = CURRENT_SCHEMA_VERSION,
-                            "Migrated metadata schema"
-                        );
-                        // Persist the migrated metadata back to ZFS
-                        if let Err(e) = self.set_volume_metadata(&vol_name, &metadata).await {
-                            warn!(
-                                volume = %vol_name,
-                                error = %e,
-                                "Failed to persist migrated metadata (will retry on next scan)"
-                            );
-                        }
-                    }
-                    debug!(volume = %vol_name, "Found volume with valid CSI metadata");
-                    results.push((vol_name, metadata));
-                }
-                Err(e) => {
-                    warn!(volume = %name, error = %e, "Corrupt CSI metadata, skipping");
-                }
-            }
-        }
-
-        info!(count = results.len(), "Volume scan complete");
-        Ok(results)
+        Ok(parse_metadata_listing(&stdout, &self.parent_dataset))
     }
 
     /// Clone a volume from an existing snapshot (instant, creates dependency).
@@ -956,6 +1835,7 @@ impl ZfsManager {
         validate_name(source_volume)?;
         validate_name(snap_name)?;
         validate_name(target_volume)?;
+        self.check_name_length(target_volume, None)?;
 
         let snapshot_full = format!("{}@{}", self.full_path(source_volume), snap_name);
         let target_full = self.full_path(target_volume);
@@ -1015,6 +1895,8 @@ impl ZfsManager {
     /// The data is physically copied, so this takes time proportional to volume size.
     ///
     /// Metadata is set atomically during receive to ensure crash safety.
+    /// If agent shutdown aborts the copy, the send/recv processes are killed
+    /// and the partially received target is destroyed.
     #[instrument(skip(self, metadata))]
     pub async fn copy_from_snapshot(
         &self,
@@ -1026,11 +1908,19 @@ impl ZfsManager {
         validate_name(source_volume)?;
         validate_name(snap_name)?;
         validate_name(target_volume)?;
+        self.check_name_length(target_volume, None)?;
 
         let snapshot_full = format!("{}@{}", self.full_path(source_volume), snap_name);
         let target_full = self.full_path(target_volume);
         let metadata_property = format_metadata_property(metadata)?;
+        let encryption = encryption_from_parameters(&metadata.parameters)?;
+
+        // Anything at the target after an aborted copy must be ours to destroy
+        if self.dataset_exists(&target_full).await? {
+            return Err(ZfsError::DatasetExists(target_full));
+        }
 
+        let copy_permit = self.copy_limiter.acquire().await;
         info!(
             snapshot = %snapshot_full,
             target = %target_full,
@@ -1048,35 +1938,19 @@ impl ZfsManager {
             return Err(ZfsError::DatasetNotFound(snapshot_full));
         }
 
-        // Use zfs send | zfs recv pipeline with metadata property
-        // We use sh -c to pipe the commands together
-        // Note: zfs recv -o sets properties on the received dataset
-        let pipeline = format!(
-            "zfs send {} | zfs recv -o {} {}",
-            shell_escape(&snapshot_full),
-            shell_escape(&metadata_property),
-            shell_escape(&target_full)
+        // zfs recv -o sets properties on the received dataset
+        let (send_args, recv_args) = build_copy_commands(
+            &snapshot_full,
+            &metadata_property,
+            encryption.as_ref(),
+            &target_full,
         );
-
-        let output = Command::new("sh").args(["-c", &pipeline]).output().await?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            warn!(
-                snapshot = %snapshot_full,
-                target = %target_full,
-                error = %stderr,
-                "Failed to copy volume via send/recv"
-            );
-
-            if stderr.contains("already exists") {
-                return Err(ZfsError::DatasetExists(target_full));
-            }
-            return Err(ZfsError::CommandFailed(format!(
-                "send/recv failed: {}",
-                stderr
-            )));
-        }
+        abortable_copy(
+            &copy_permit,
+            self.send_recv(&send_args, &recv_args, &snapshot_full, &target_full),
+            self.destroy_partial_target(&target_full),
+        )
+        .await?;
 
         info!(
             snapshot = %snapshot_full,
@@ -1105,6 +1979,257 @@ impl ZfsManager {
         self.get_dataset(target_volume).await
     }
 
+    /// Create a volume by receiving a `zfs send` stream from a URL.
+    ///
+    /// The stream is downloaded with fetch(1) and piped into `zfs recv` with
+    /// CSI metadata set atomically via `-o`. The received dataset must be a
+    /// volume no larger than `size_bytes`; smaller images are grown to the
+    /// requested size. Snapshots carried by the stream are destroyed. On any
+    /// failure the partially received dataset is destroyed.
+    #[instrument(skip(self, metadata, allowed_schemes))]
+    pub async fn receive_from_url(
+        &self,
+        url: &str,
+        target_volume: &str,
+        size_bytes: u64,
+        metadata: &VolumeMetadata,
+        allowed_schemes: &[String],
+    ) -> Result<Dataset> {
+        validate_name(target_volume)?;
+        self.check_name_length(target_volume, None)?;
+        validate_image_url(url, allowed_schemes)?;
+
+        let target_full = self.full_path(target_volume);
+        let metadata_property = format_metadata_property(metadata)?;
+        let encryption = encryption_from_parameters(&metadata.parameters)?;
+
+        if self.dataset_exists(&target_full).await? {
+            return Err(ZfsError::DatasetExists(target_full));
+        }
+
+        let copy_permit = self.copy_limiter.acquire().await;
+        info!(url = %url, target = %target_full, "Receiving volume from image stream");
+
+        let result = unless_aborted(
+            &copy_permit,
+            self.receive_image_stream(
+                url,
+                &metadata_property,
+                encryption.as_ref(),
+                &target_full,
+                size_bytes,
+            ),
+        )
+        .await;
+
+        if let Err(ref e) = result {
+            warn!(
+                target = %target_full,
+                error = %e,
+                "Image receive failed, cleaning up partial dataset"
+            );
+            // The target did not exist before, so anything there is ours
+            self.destroy_partial_target(&target_full).await;
+        }
+        result?;
+
+        info!(url = %url, target = %target_full, "Volume received from image stream");
+        self.get_dataset(target_volume).await
+    }
+
+    /// Run `zfs send | zfs recv` for a copy.
+    ///
+    /// Both processes are killed if the returned future is dropped, which is
+    /// how an aborted copy stops.
+    async fn send_recv(
+        &self,
+        send_args: &[String],
+        recv_args: &[String],
+        snapshot_full: &str,
+        target_full: &str,
+    ) -> Result<()> {
+        let mut send = Command::new("zfs")
+            .args(send_args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let send_stdout: Stdio = send
+            .stdout
+            .take()
+            .ok_or_else(|| ZfsError::CommandFailed("zfs send stdout unavailable".into()))?
+            .try_into()?;
+
+        let recv_output = Command::new("zfs")
+            .args(recv_args)
+            .stdin(send_stdout)
+            .kill_on_drop(true)
+            .output()
+            .await?;
+        let send_output = send.wait_with_output().await?;
+
+        if recv_output.status.success() && send_output.status.success() {
+            return Ok(());
+        }
+        let stderr = if recv_output.status.success() {
+            String::from_utf8_lossy(&send_output.stderr)
+        } else {
+            String::from_utf8_lossy(&recv_output.stderr)
+        };
+        warn!(
+            snapshot = %snapshot_full,
+            target = %target_full,
+            error = %stderr,
+            "Failed to copy volume via send/recv"
+        );
+        if stderr.contains("already exists") {
+            return Err(ZfsError::DatasetExists(target_full.to_string()));
+        }
+        Err(ZfsError::CommandFailed(format!(
+            "send/recv failed: {}",
+            stderr
+        )))
+    }
+
+    /// Destroy a partially received copy target, if anything was received.
+    ///
+    /// Callers make sure the target did not exist before the copy started.
+    async fn destroy_partial_target(&self, target_full: &str) {
+        if !matches!(self.dataset_exists(target_full).await, Ok(true)) {
+            return;
+        }
+        info!(target = %target_full, "Destroying partially received dataset");
+        let cleanup = Command::new("zfs")
+            .args(["destroy", "-r", target_full])
+            .output()
+            .await;
+        if let Ok(output) = cleanup
+            && !output.status.success()
+        {
+            warn!(
+                target = %target_full,
+                error = %String::from_utf8_lossy(&output.stderr),
+                "Failed to clean up partially received dataset"
+            );
+        }
+    }
+
+    /// Run the fetch | zfs recv pipeline and validate the result.
+    async fn receive_image_stream(
+        &self,
+        url: &str,
+        metadata_property: &str,
+        encryption: Option<&Encryption>,
+        target_full: &str,
+        size_bytes: u64,
+    ) -> Result<()> {
+        let (fetch_args, recv_args) =
+            build_image_recv_commands(url, metadata_property, encryption, target_full);
+
+        let mut fetch = Command::new("fetch")
+            .args(&fetch_args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let fetch_stdout: Stdio = fetch
+            .stdout
+            .take()
+            .ok_or_else(|| ZfsError::CommandFailed("fetch stdout unavailable".into()))?
+            .try_into()?;
+
+        let recv = Command::new("zfs")
+            .args(&recv_args)
+            .stdin(fetch_stdout)
+            .kill_on_drop(true)
+            .output();
+
+        // Wait for both sides together so fetch's stderr is drained while the
+        // stream runs; a stalled download is killed when the timeout drops
+        // both processes.
+        let (recv_output, fetch_output) = tokio::time::timeout(self.image_fetch_timeout, async {
+            tokio::join!(recv, fetch.wait_with_output())
+        })
+        .await
+        .map_err(|_| {
+            ZfsError::CommandFailed(format!(
+                "fetch {} did not finish within {}s",
+                url,
+                self.image_fetch_timeout.as_secs()
+            ))
+        })?;
+        let (recv_output, fetch_output) = (recv_output?, fetch_output?);
+
+        if !fetch_output.status.success() {
+            return Err(ZfsError::CommandFailed(format!(
+                "fetch {} failed: {}",
+                url,
+                String::from_utf8_lossy(&fetch_output.stderr)
+            )));
+        }
+        check_command_result(&recv_output, target_full)?;
+
+        // Verify the stream produced a volume that fits the request
+        let output = Command::new("zfs")
+            .args([
+                "get",
+                "-H",
+                "-p",
+                "-o",
+                "value",
+                "type,volsize",
+                target_full,
+            ])
+            .output()
+            .await?;
+        check_command_result(&output, target_full)?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut values = stdout.lines().map(str::trim);
+        let kind = values.next().unwrap_or("");
+        if kind != "volume" {
+            return Err(ZfsError::CommandFailed(format!(
+                "image stream produced a {} instead of a volume",
+                if kind.is_empty() {
+                    "unknown dataset"
+                } else {
+                    kind
+                }
+            )));
+        }
+        let volsize: u64 = values
+            .next()
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| ZfsError::ParseError("invalid volsize of received volume".into()))?;
+        if volsize > size_bytes {
+            return Err(ZfsError::CommandFailed(format!(
+                "image volume is {} bytes, larger than the requested {} bytes",
+                volsize, size_bytes
+            )));
+        }
+        if volsize < size_bytes {
+            let output = Command::new("zfs")
+                .args(["set", &format!("volsize={}", size_bytes), target_full])
+                .output()
+                .await?;
+            check_command_result(&output, target_full)?;
+        }
+
+        // Drop snapshots carried in the stream so the volume has no dependents
+        let output = Command::new("zfs")
+            .args(["destroy", &format!("{}@%", target_full)])
+            .output()
+            .await?;
+        if !output.status.success() {
+            warn!(
+                target = %target_full,
+                error = %String::from_utf8_lossy(&output.stderr),
+                "Failed to clean up received snapshots"
+            );
+        }
+
+        Ok(())
+    }
+
     /// List clones that depend on snapshots of a volume.
     ///
     /// Returns a list of (snapshot_name, clone_name) tuples for all clones
@@ -1185,19 +2310,52 @@ impl ZfsManager {
         Ok(results)
     }
 
-    /// Promote a clone to become the origin (reverses dependency).
+    /// List snapshots of all volumes under the parent dataset that have no
+    /// clones, as full paths (`pool/csi/vol@snap`).
     ///
-    /// After promotion, the original parent becomes dependent on this clone.
-    /// This allows deleting the original parent volume.
+    /// Finds temporary clone snapshots left behind by a clone whose dataset
+    /// is already gone, when its origin can no longer be read from it.
     #[instrument(skip(self))]
-    pub async fn promote_clone(&self, clone_name: &str) -> Result<()> {
-        validate_name(clone_name)?;
-
-        let full_name = self.full_path(clone_name);
-        info!(clone = %full_name, "Promoting clone");
-
+    pub async fn list_unreferenced_snapshots(&self) -> Result<Vec<String>> {
         let output = Command::new("zfs")
-            .args(["promote", &full_name])
+            .args([
+                "list",
+                "-H",
+                "-t",
+                "snapshot",
+                "-o",
+                "name,clones",
+                "-r",
+                &self.parent_dataset,
+            ])
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(ZfsError::CommandFailed(format!(
+                "failed to list snapshots: {}",
+                stderr
+            )));
+        }
+        Ok(unreferenced_snapshots(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    }
+
+    /// Promote a clone to become the origin (reverses dependency).
+    ///
+    /// After promotion, the original parent becomes dependent on this clone.
+    /// This allows deleting the original parent volume.
+    #[instrument(skip(self))]
+    pub async fn promote_clone(&self, clone_name: &str) -> Result<()> {
+        validate_name(clone_name)?;
+
+        let full_name = self.full_path(clone_name);
+        info!(clone = %full_name, "Promoting clone");
+
+        let output = Command::new("zfs")
+            .args(["promote", &full_name])
             .output()
             .await?;
 
@@ -1220,7 +2378,7 @@ impl ZfsManager {
         let full_name = self.full_path(name);
 
         let output = Command::new("zfs")
-            .args(["get", "-H", "-o", "value", "origin", &full_name])
+            .args(["get", "-H", "-o", "name,value", "origin", &full_name])
             .output()
             .await?;
 
@@ -1235,7 +2393,8 @@ impl ZfsManager {
             )));
         }
 
-        let origin = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let origin = select_property_value(&stdout, &full_name)?.to_string();
 
         // "-" means no origin (not a clone)
         if origin == "-" || origin.is_empty() {
@@ -1245,6 +2404,57 @@ impl ZfsManager {
         }
     }
 
+    /// Query a zvol's `volsize` on its own.
+    ///
+    /// Used when a combined listing came back without it. Returns None if the
+    /// dataset genuinely has no volsize.
+    #[instrument(skip(self))]
+    pub async fn get_volsize(&self, name: &str) -> Result<Option<u64>> {
+        validate_name(name)?;
+
+        let full_name = self.full_path(name);
+
+        let output = Command::new("zfs")
+            .args(["get", "-Hp", "-o", "name,value", "volsize", &full_name])
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.contains("does not exist") {
+                return Err(ZfsError::DatasetNotFound(full_name));
+            }
+            return Err(ZfsError::CommandFailed(format!(
+                "failed to get volsize: {}",
+                stderr
+            )));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        parse_volsize_value(&stdout, &full_name)
+    }
+
+    /// Space accounting of the snapshot at `snapshot_path`, queried the same
+    /// way as [`list_csi_snapshots`](Self::list_csi_snapshots) does.
+    #[instrument(skip(self))]
+    pub async fn get_snapshot_space(&self, snapshot_path: &str) -> Result<SnapshotSpace> {
+        let output = Command::new("zfs")
+            .args([
+                "get",
+                "-Hp",
+                "-o",
+                "property,value",
+                SNAPSHOT_SPACE_PROPERTIES,
+                snapshot_path,
+            ])
+            .output()
+            .await?;
+        check_command_result(&output, snapshot_path)?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(parse_snapshot_space(&stdout))
+    }
+
     /// Check if a snapshot has any clones.
     ///
     /// Returns a list of clone dataset paths that depend on this snapshot.
@@ -1271,7 +2481,7 @@ impl ZfsManager {
         debug!(snapshot = %snapshot_path, "Checking for clones");
 
         let output = Command::new("zfs")
-            .args(["get", "-H", "-o", "value", "clones", snapshot_path])
+            .args(["get", "-H", "-o", "name,value", "clones", snapshot_path])
             .output()
             .await?;
 
@@ -1286,7 +2496,8 @@ impl ZfsManager {
             )));
         }
 
-        let clones_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let clones_str = select_property_value(&stdout, snapshot_path)?.to_string();
 
         // "-" means no clones
         if clones_str == "-" || clones_str.is_empty() {
@@ -1308,6 +2519,12 @@ impl ZfsManager {
     /// Get capacity information for the parent dataset.
     ///
     /// Returns available and used space for the dataset that holds CSI volumes.
+    ///
+    /// `available` is already net of outstanding reservations: the unused part
+    /// of every volume's `refreservation` (thick or `maxOverprovision=false`
+    /// volumes) is charged to the parent's `used` the moment it is set, so
+    /// subtracting it again would count it twice. Space thin volumes have not
+    /// written yet is not reserved and is not deducted.
     #[instrument(skip(self))]
     pub async fn get_capacity(&self) -> Result<Capacity> {
         debug!(dataset = %self.parent_dataset, "Getting capacity");
@@ -1318,7 +2535,7 @@ impl ZfsManager {
                 "-H",
                 "-p", // Machine-parseable output (bytes)
                 "-o",
-                "available,used",
+                "name,available,used",
                 &self.parent_dataset,
             ])
             .output()
@@ -1336,22 +2553,19 @@ impl ZfsManager {
         }
 
         let stdout = String::from_utf8_lossy(&output.stdout);
-        let line = stdout
-            .lines()
-            .next()
-            .ok_or_else(|| ZfsError::ParseError("empty output from zfs list".to_string()))?;
+        let line = select_dataset_line(&stdout, &self.parent_dataset)?;
 
         let fields: Vec<&str> = line.split('\t').collect();
-        if fields.len() < 2 {
+        if fields.len() < 3 {
             return Err(ZfsError::ParseError(format!(
-                "expected 2 fields for capacity, got {}: {}",
+                "expected 3 fields for capacity, got {}: {}",
                 fields.len(),
                 line
             )));
         }
 
-        let available = Self::parse_size(fields[0])?;
-        let used = Self::parse_size(fields[1])?;
+        let available = Self::parse_size(fields[1])?;
+        let used = Self::parse_size(fields[2])?;
 
         debug!(
             dataset = %self.parent_dataset,
@@ -1363,6 +2577,56 @@ impl ZfsManager {
         Ok(Capacity { available, used })
     }
 
+    /// Health, fragmentation and fill level of the pool holding the parent
+    /// dataset
+    #[instrument(skip(self))]
+    pub async fn get_pool_status(&self) -> Result<PoolStatus> {
+        let pool = pool_name(&self.parent_dataset);
+        let output = Command::new("zpool")
+            .args([
+                "get",
+                "-H",
+                "-p",
+                "-o",
+                "property,value",
+                "fragmentation,health,capacity",
+                pool,
+            ])
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(ZfsError::CommandFailed(format!(
+                "failed to get status of pool {}: {}",
+                pool,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        parse_pool_status(&String::from_utf8_lossy(&output.stdout), pool)
+    }
+
+    /// Whether the pool holding the parent dataset can accept writes again
+    /// after it was seen read-only or suspended
+    #[instrument(skip(self))]
+    pub async fn pool_accepts_writes(&self) -> Result<bool> {
+        let pool = pool_name(&self.parent_dataset);
+        let output = Command::new("zpool")
+            .args(["get", "-H", "-o", "property,value", "readonly,health", pool])
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(ZfsError::CommandFailed(format!(
+                "failed to get state of pool {}: {}",
+                pool,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        parse_pool_writable(&String::from_utf8_lossy(&output.stdout), pool)
+    }
+
     /// Check whether a managed child volume exists under the parent dataset
     #[instrument(skip(self))]
     pub async fn volume_exists(&self, name: &str) -> Result<bool> {
@@ -1371,6 +2635,15 @@ impl ZfsManager {
         self.dataset_exists(&full_name).await
     }
 
+    /// Check whether a snapshot of a managed volume exists
+    #[instrument(skip(self))]
+    pub async fn snapshot_exists(&self, volume_name: &str, snap_name: &str) -> Result<bool> {
+        validate_name(volume_name)?;
+        validate_name(snap_name)?;
+        let full_name = format!("{}@{}", self.full_path(volume_name), snap_name);
+        self.dataset_exists(&full_name).await
+    }
+
     /// Check if a dataset exists
     async fn dataset_exists(&self, full_name: &str) -> Result<bool> {
         let output = Command::new("zfs")
@@ -1390,7 +2663,7 @@ impl ZfsManager {
                 "-H",
                 "-p", // Machine-parseable output (bytes)
                 "-o",
-                "name,refer,volsize",
+                "name,refer,volsize,mountpoint,refreservation",
                 full_name,
             ])
             .output()
@@ -1405,15 +2678,18 @@ impl ZfsManager {
         }
 
         let stdout = String::from_utf8_lossy(&output.stdout);
-        let line = stdout
-            .lines()
-            .next()
-            .ok_or_else(|| ZfsError::ParseError("empty output from zfs list".to_string()))?;
-
-        self.parse_dataset_line(line)
+        let line = select_dataset_line(&stdout, full_name)?;
+        let mut dataset = self.parse_dataset_line(line)?;
+        if dataset.volsize.is_none()
+            && let Some(mountpoint) = line.split('\t').nth(3)
+        {
+            dataset.volsize = backing_file_size(full_name, mountpoint).await;
+        }
+        Ok(dataset)
     }
 
-    /// Parse a line of ZFS output into a Dataset (expects: name, refer, volsize)
+    /// Parse a line of ZFS output into a Dataset (expects: name, refer, volsize,
+    /// optionally followed by mountpoint and refreservation)
     fn parse_dataset_line(&self, line: &str) -> Result<Dataset> {
         let fields: Vec<&str> = line.split('\t').collect();
         if fields.len() < 3 {
@@ -1433,10 +2709,16 @@ impl ZfsManager {
             Some(Self::parse_size(fields[2])?)
         };
 
+        let refreservation = match fields.get(4).map(|f| f.trim()) {
+            None | Some("none") => 0,
+            Some(value) => Self::parse_size(value)?,
+        };
+
         Ok(Dataset {
             name,
             referenced,
             volsize,
+            refreservation,
         })
     }
 
@@ -1448,15 +2730,187 @@ impl ZfsManager {
             return Ok(0);
         }
 
-        size_str
-            .parse::<u64>()
-            .map_err(|_| ZfsError::ParseError(format!("invalid size value: {}", size_str)))
+        parse_byte_size(size_str)
+            .ok_or_else(|| ZfsError::ParseError(format!("invalid size value: {}", size_str)))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    fn failed_output(stderr: &str) -> Output {
+        Output {
+            status: ExitStatus::from_raw(1 << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_unreferenced_snapshots() {
+        let stdout = "tank/csi/pvc-a@pvc-clone-pvc-b-1700000000000\t-\n\
+                      tank/csi/pvc-a@pvc-clone-pvc-c-1700000000001\ttank/csi/pvc-c\n\
+                      tank/csi/pvc-a@snap-1\ttank/csi/pvc-d,tank/csi/pvc-e\n\
+                      tank/csi/pvc-f@snap-2\t-\n";
+        assert_eq!(
+            unreferenced_snapshots(stdout),
+            vec![
+                "tank/csi/pvc-a@pvc-clone-pvc-b-1700000000000".to_string(),
+                "tank/csi/pvc-f@snap-2".to_string(),
+            ]
+        );
+        assert!(unreferenced_snapshots("").is_empty());
+    }
+
+    #[test]
+    fn test_check_command_result_pool_unavailable() {
+        for stderr in [
+            "cannot create 'tank/csi/pvc-1': pool is read-only\n",
+            "cannot open 'tank/csi/pvc-1': pool I/O is currently suspended\n",
+        ] {
+            let err = check_command_result(&failed_output(stderr), "tank/csi/pvc-1").unwrap_err();
+            assert!(
+                matches!(err, ZfsError::PoolUnavailable(ref msg) if msg.starts_with("tank/csi/pvc-1: ")),
+                "{stderr:?} mapped to {err:?}"
+            );
+        }
+
+        // Ordinary failures keep their specific mapping
+        assert!(matches!(
+            check_command_result(
+                &failed_output("cannot open 'tank/csi/pvc-1': dataset does not exist\n"),
+                "tank/csi/pvc-1"
+            ),
+            Err(ZfsError::DatasetNotFound(_))
+        ));
+        assert!(matches!(
+            check_command_result(&failed_output("out of space\n"), "tank/csi/pvc-1"),
+            Err(ZfsError::CommandFailed(_))
+        ));
+        // A dataset with readonly=on is not a pool problem
+        assert!(matches!(
+            check_command_result(
+                &failed_output("cannot mount 'tank/csi/fs-1': Read-only file system\n"),
+                "tank/csi/fs-1"
+            ),
+            Err(ZfsError::CommandFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_pool_writable() {
+        let state =
+            |readonly: &str, health: &str| format!("readonly\t{}\nhealth\t{}\n", readonly, health);
+        assert!(parse_pool_writable(&state("off", "ONLINE"), "tank").unwrap());
+        assert!(parse_pool_writable(&state("off", "DEGRADED"), "tank").unwrap());
+        assert!(!parse_pool_writable(&state("on", "ONLINE"), "tank").unwrap());
+        assert!(!parse_pool_writable(&state("off", "SUSPENDED"), "tank").unwrap());
+        assert!(parse_pool_writable("readonly\toff\n", "tank").is_err());
+    }
+
+    #[test]
+    fn test_snapshot_of_clone_found_after_promotion() {
+        // pvc-b was cloned from pvc-a@s1, then snapshotted as pvc-b@s2
+        let before = "tank/csi/pvc-a@s1\tpvc-a@s1\n\
+                      tank/csi/pvc-b@s2\tpvc-b@s2\n";
+        assert_eq!(
+            snapshot_paths_with_id(before, "pvc-b@s2"),
+            vec!["tank/csi/pvc-b@s2"]
+        );
+
+        // Promoting pvc-b moves pvc-a@s1 under pvc-b; the clone's own
+        // snapshot stays put and both remain findable by their tag
+        let after = "tank/csi/pvc-b@s1\tpvc-a@s1\n\
+                     tank/csi/pvc-b@s2\tpvc-b@s2\n\
+                     tank/csi/pvc-b@manual\t-\n";
+        assert_eq!(
+            snapshot_paths_with_id(after, "pvc-b@s2"),
+            vec!["tank/csi/pvc-b@s2"]
+        );
+        assert_eq!(
+            snapshot_paths_with_id(after, "pvc-a@s1"),
+            vec!["tank/csi/pvc-b@s1"]
+        );
+        assert!(snapshot_paths_with_id(after, "pvc-b@s1").is_empty());
+        assert_eq!(
+            snapshot_location("tank/csi", "tank/csi/pvc-b@s1"),
+            Some(("pvc-b".to_string(), "s1".to_string()))
+        );
+        assert_eq!(snapshot_location("tank/csi", "tank/csi/a/pvc-b@s1"), None);
+        assert_eq!(snapshot_location("tank/csi", "tank/csi2/pvc-b@s1"), None);
+        // Extra columns (e.g. creation) don't affect matching
+        assert_eq!(
+            snapshot_paths_with_id(
+                "tank/csi/pvc-b@s2\tpvc-b@s2\tSat Jan 25 12:34 2025",
+                "pvc-b@s2"
+            ),
+            vec!["tank/csi/pvc-b@s2"]
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_snapshot_ids() {
+        let listing = "tank/csi/pvc-a@s1\tpvc-a@s1\n\
+                       tank/csi/pvc-b@s1\tpvc-a@s1\n\
+                       tank/csi/pvc-b@s2\tpvc-b@s2\n\
+                       tank/csi/pvc-b@manual\t-\n\
+                       tank/csi/pvc-c@manual\t-\n\
+                       tank/csi/pvc-c@s3\tpvc-c@s3\tSat Jan 25 12:34 2025\n\
+                       tank/csi/pvc-d@s3\tpvc-c@s3\n";
+        assert_eq!(
+            ambiguous_snapshot_ids(listing),
+            vec![
+                (
+                    "pvc-a@s1".to_string(),
+                    vec![
+                        "tank/csi/pvc-a@s1".to_string(),
+                        "tank/csi/pvc-b@s1".to_string()
+                    ]
+                ),
+                (
+                    "pvc-c@s3".to_string(),
+                    vec![
+                        "tank/csi/pvc-c@s3".to_string(),
+                        "tank/csi/pvc-d@s3".to_string()
+                    ]
+                ),
+            ]
+        );
+        assert!(ambiguous_snapshot_ids("tank/csi/pvc-a@s1\tpvc-a@s1\n").is_empty());
+    }
+
+    #[test]
+    fn test_system_snapshot_name() {
+        let at = |secs, micros| Duration::from_secs(secs) + Duration::from_micros(micros);
+
+        let name = system_snapshot_name(at(1_700_000_000, 250));
+        assert_eq!(name, "csi-auto-1700000000-000250");
+        assert!(validate_name(&name).is_ok());
+        // Snapshots within one second do not collide
+        assert_ne!(
+            system_snapshot_name(at(1_700_000_000, 1)),
+            system_snapshot_name(at(1_700_000_000, 2))
+        );
+        // Lexical order follows age for timestamps of the same width
+        assert!(
+            system_snapshot_name(at(1_700_000_000, 999_999))
+                < system_snapshot_name(at(1_700_000_001, 0))
+        );
+        assert!(
+            system_snapshot_name(at(1_700_000_000, 0)) < system_snapshot_name(at(1_700_003_600, 0))
+        );
+    }
+
+    #[test]
+    fn test_snapshot_tag_matches_rejects_moved_snapshot() {
+        assert!(snapshot_tag_matches(Some("pvc-b@s2"), "pvc-b@s2"));
+        assert!(snapshot_tag_matches(None, "pvc-b@s2"));
+        // pvc-b@s1 is pvc-a@s1 moved in by promotion, not a snapshot of pvc-b
+        assert!(!snapshot_tag_matches(Some("pvc-a@s1"), "pvc-b@s1"));
+    }
 
     #[test]
     fn test_parse_size() {
@@ -1467,8 +2921,9 @@ mod tests {
             1024 * 1024 * 1024
         );
         assert_eq!(ZfsManager::parse_size("-").unwrap(), 0);
+        assert_eq!(ZfsManager::parse_size("0").unwrap(), 0);
         // Invalid input should error
-        assert!(ZfsManager::parse_size("1K").is_err());
+        assert!(ZfsManager::parse_size("1.5K").is_err());
         assert!(ZfsManager::parse_size("invalid").is_err());
     }
 
@@ -1494,10 +2949,29 @@ mod tests {
         assert!(validate_name("../../../etc/passwd").is_err());
     }
 
+    #[test]
+    fn test_check_name_length() {
+        let parent = format!("tank/{}", "p".repeat(200));
+        // parent + "/" + name leaves this much room for the name
+        let room = MAX_DATASET_NAME_LEN - parent.len() - 1;
+
+        assert!(check_name_length(&parent, &"v".repeat(room), None).is_ok());
+        let err = check_name_length(&parent, &"v".repeat(room + 1), None).unwrap_err();
+        assert!(matches!(err, ZfsError::InvalidName(_)));
+        assert!(err.to_string().contains("exceeding the ZFS limit"));
+
+        // Snapshot suffix counts against the same limit
+        let name = "v".repeat(room - 10);
+        assert!(check_name_length(&parent, &name, Some(&"s".repeat(9))).is_ok());
+        assert!(check_name_length(&parent, &name, Some(&"s".repeat(10))).is_err());
+    }
+
     #[test]
     fn test_full_path() {
         let manager = ZfsManager {
             parent_dataset: "tank/csi".to_string(),
+            copy_limiter: copy_limiter(DEFAULT_MAX_CONCURRENT_COPIES),
+            image_fetch_timeout: Duration::from_secs(DEFAULT_IMAGE_FETCH_TIMEOUT_SECS),
         };
         assert_eq!(manager.full_path("vol1"), "tank/csi/vol1");
     }
@@ -1506,6 +2980,8 @@ mod tests {
     fn test_get_device_path() {
         let manager = ZfsManager {
             parent_dataset: "tank/csi".to_string(),
+            copy_limiter: copy_limiter(DEFAULT_MAX_CONCURRENT_COPIES),
+            image_fetch_timeout: Duration::from_secs(DEFAULT_IMAGE_FETCH_TIMEOUT_SECS),
         };
         assert_eq!(manager.get_device_path("vol1"), "/dev/zvol/tank/csi/vol1");
     }
@@ -1532,4 +3008,443 @@ mod tests {
 
         assert!(matches!(result, Err(ZfsError::CommandFailed(_))));
     }
+
+    #[test]
+    fn test_validate_image_url_scheme_allowlist() {
+        let allowed = vec!["https".to_string()];
+        assert!(validate_image_url("https://images.example.com/golden.zstream", &allowed).is_ok());
+        assert!(validate_image_url("HTTPS://images.example.com/golden.zstream", &allowed).is_ok());
+
+        let err = validate_image_url("http://images.example.com/golden.zstream", &allowed)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("not allowed"), "{}", err);
+        assert!(validate_image_url("file:///tmp/golden.zstream", &allowed).is_err());
+        assert!(validate_image_url("images.example.com/golden", &allowed).is_err());
+
+        // No configured schemes disables image provisioning
+        let err = validate_image_url("https://images.example.com/golden.zstream", &[])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("disabled"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_image_url_rejects_malformed() {
+        let allowed = vec!["https".to_string(), "file".to_string()];
+        assert!(validate_image_url("https://", &allowed).is_err());
+        assert!(validate_image_url("https:///no-host", &allowed).is_err());
+        assert!(validate_image_url("https://host/a b", &allowed).is_err());
+        assert!(validate_image_url("https://host/a\nb", &allowed).is_err());
+        assert!(validate_image_url("file:///srv/images/golden.zstream", &allowed).is_ok());
+    }
+
+    #[test]
+    fn test_select_dataset_line_skips_children() {
+        let stdout = "tank/csi/pvc-1/child\t4096\t1024\n\
+                      tank/csi/pvc-10\t8192\t2048\n\
+                      tank/csi/pvc-1\t16384\t1073741824\n";
+        let line = select_dataset_line(stdout, "tank/csi/pvc-1").unwrap();
+        assert_eq!(line, "tank/csi/pvc-1\t16384\t1073741824");
+
+        let mgr = ZfsManager {
+            parent_dataset: "tank/csi".to_string(),
+            copy_limiter: copy_limiter(DEFAULT_MAX_CONCURRENT_COPIES),
+            image_fetch_timeout: Duration::from_secs(DEFAULT_IMAGE_FETCH_TIMEOUT_SECS),
+        };
+        let dataset = mgr.parse_dataset_line(line).unwrap();
+        assert_eq!(dataset.volsize, Some(1073741824));
+        assert_eq!(dataset.refreservation, 0);
+    }
+
+    #[test]
+    fn test_check_parent_type() {
+        assert!(check_parent_type("tank/csi", "filesystem\n").is_ok());
+
+        for (parent, kind) in [("tank/vol", "volume"), ("tank/csi@snap", "snapshot")] {
+            let err = check_parent_type(parent, &format!("{}\n", kind)).unwrap_err();
+            assert!(
+                matches!(err, ZfsError::InvalidName(ref msg) if msg.contains(parent) && msg.contains(kind)),
+                "{err:?}"
+            );
+        }
+        assert!(check_parent_type("tank/csi", "").is_err());
+    }
+
+    #[test]
+    fn test_build_zvol_args() {
+        let args = build_zvol_args(
+            "tank/csi/pvc-1",
+            1073741824,
+            "user:csi:metadata={}",
+            Some("zstd-3"),
+            Some(16384),
+            None,
+            false,
+        );
+        assert_eq!(
+            args,
+            [
+                "create",
+                "-V",
+                "1073741824",
+                "-o",
+                "volmode=dev",
+                "-o",
+                "user:csi:metadata={}",
+                "-o",
+                "compression=zstd-3",
+                "-o",
+                "volblocksize=16384",
+                "tank/csi/pvc-1"
+            ]
+        );
+
+        let thick = build_zvol_args(
+            "tank/csi/pvc-1",
+            1073741824,
+            "user:csi:metadata={}",
+            None,
+            None,
+            None,
+            true,
+        );
+        assert!(thick.contains(&"refreservation=1073741824".to_string()));
+        assert!(!thick.iter().any(|a| a.starts_with("compression=")));
+        assert!(!thick.iter().any(|a| a.starts_with("volblocksize=")));
+        assert_eq!(thick.last().unwrap(), "tank/csi/pvc-1");
+    }
+
+    #[test]
+    fn test_parse_dataset_line_refreservation() {
+        let mgr = ZfsManager {
+            parent_dataset: "tank/csi".to_string(),
+            copy_limiter: copy_limiter(DEFAULT_MAX_CONCURRENT_COPIES),
+            image_fetch_timeout: Duration::from_secs(DEFAULT_IMAGE_FETCH_TIMEOUT_SECS),
+        };
+        let thick = mgr
+            .parse_dataset_line("tank/csi/pvc-1	16384	1073741824	-	1090519040")
+            .unwrap();
+        assert_eq!(thick.refreservation, 1090519040);
+        let thin = mgr
+            .parse_dataset_line("tank/csi/pvc-2	16384	1073741824	-	none")
+            .unwrap();
+        assert_eq!(thin.refreservation, 0);
+        assert!(
+            mgr.parse_dataset_line("tank/csi/pvc-3	16384	1073741824	-	lots")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_select_dataset_line_missing_target() {
+        let stdout = "tank/csi/pvc-10\t8192\t2048\n";
+        let err = select_dataset_line(stdout, "tank/csi/pvc-1").unwrap_err();
+        assert!(matches!(err, ZfsError::ParseError(ref m) if m.contains("tank/csi/pvc-1")));
+        assert!(select_dataset_line("", "tank/csi/pvc-1").is_err());
+    }
+
+    #[test]
+    fn test_select_property_value_multi_line() {
+        let stdout = "tank/csi/pvc-1/child\t-\n\
+                      tank/csi/pvc-1\ttank/csi/src@snap\n";
+        assert_eq!(
+            select_property_value(stdout, "tank/csi/pvc-1").unwrap(),
+            "tank/csi/src@snap"
+        );
+        assert_eq!(
+            select_property_value(stdout, "tank/csi/pvc-1/child").unwrap(),
+            "-"
+        );
+    }
+
+    #[test]
+    fn test_parse_volsize_value() {
+        let stdout = "tank/csi/pvc-1\t10737418240\n";
+        assert_eq!(
+            parse_volsize_value(stdout, "tank/csi/pvc-1").unwrap(),
+            Some(10737418240)
+        );
+        assert_eq!(
+            parse_volsize_value("tank/csi\t-\n", "tank/csi").unwrap(),
+            None
+        );
+        assert!(parse_volsize_value("tank/csi/pvc-1\t10G\n", "tank/csi/pvc-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_written_bytes() {
+        assert_eq!(parse_written_bytes("0"), 0);
+        assert_eq!(parse_written_bytes("1048576"), 1048576);
+        assert_eq!(parse_written_bytes(" 4096\n"), 4096);
+        // Unparsable values do not fail the listing
+        assert_eq!(parse_written_bytes("-"), 0);
+        assert_eq!(parse_written_bytes("1.5M"), 0);
+
+        let stdout = "tank/csi/pvc-1@snap-1\t8192\n";
+        assert_eq!(
+            parse_written_bytes(select_property_value(stdout, "tank/csi/pvc-1@snap-1").unwrap()),
+            8192
+        );
+    }
+
+    #[test]
+    fn test_parse_snapshot_space() {
+        // zvol snapshot on raidz, where referenced exceeds volsize
+        let stdout = "written\t8192\nused\t4096\nreferenced\t1074003968\nvolsize\t1073741824\n";
+        assert_eq!(
+            parse_snapshot_space(stdout),
+            SnapshotSpace {
+                written_bytes: 8192,
+                used_bytes: 4096,
+                referenced_bytes: 1074003968,
+                size_bytes: 1073741824,
+            }
+        );
+
+        // File-backed volume snapshots have no volsize
+        let stdout = "written\t0\nused\t0\nreferenced\t52428800\nvolsize\t-\n";
+        assert_eq!(parse_snapshot_space(stdout).size_bytes, 52428800);
+
+        // Columns of a list_csi_snapshots line
+        let line = "tank/csi/pvc-1@snap-1\tpvc-1@snap-1\t-\t1700000000\t8192\t0\t65536\t1073741824";
+        let parts: Vec<&str> = line.split('\t').collect();
+        assert_eq!(
+            SnapshotSpace::from_values(parts[4], parts[5], parts[6], parts[7]),
+            SnapshotSpace {
+                written_bytes: 8192,
+                used_bytes: 0,
+                referenced_bytes: 65536,
+                // A restored volume needs the full volume size, not just
+                // the blocks the sparse snapshot references
+                size_bytes: 1073741824,
+            }
+        );
+
+        assert_eq!(parse_snapshot_space(""), SnapshotSpace::default());
+    }
+
+    #[test]
+    fn test_build_image_recv_commands() {
+        let (fetch, recv) = build_image_recv_commands(
+            "https://images.example.com/golden.zstream",
+            "user:csi:metadata={\"schema_version\":1}",
+            None,
+            "tank/csi/pvc-1",
+        );
+        assert_eq!(
+            fetch,
+            vec!["-q", "-o", "-", "https://images.example.com/golden.zstream"]
+        );
+        assert_eq!(
+            recv,
+            vec![
+                "recv",
+                "-o",
+                "user:csi:metadata={\"schema_version\":1}",
+                "tank/csi/pvc-1"
+            ]
+        );
+
+        let encryption = Encryption {
+            key_format: "raw".to_string(),
+            key_location: "file:///etc/csi/keys/a.key".to_string(),
+        };
+        let (_, recv) = build_image_recv_commands(
+            "https://images.example.com/golden.zstream",
+            "user:csi:metadata={}",
+            Some(&encryption),
+            "tank/csi/pvc-1",
+        );
+        assert!(recv.contains(&"encryption=aes-256-gcm".to_string()));
+        assert!(recv.contains(&"keylocation=file:///etc/csi/keys/a.key".to_string()));
+        assert_eq!(recv.last().unwrap(), "tank/csi/pvc-1");
+    }
+
+    #[test]
+    fn test_build_copy_commands() {
+        let (send, recv) = build_copy_commands(
+            "tank/csi/pvc-src@pvc-clone-pvc-1-1700000000",
+            "user:csi:metadata={}",
+            None,
+            "tank/csi/pvc-1",
+        );
+        assert_eq!(
+            send,
+            ["send", "tank/csi/pvc-src@pvc-clone-pvc-1-1700000000"]
+        );
+        assert_eq!(
+            recv,
+            ["recv", "-o", "user:csi:metadata={}", "tank/csi/pvc-1"]
+        );
+
+        let encryption = Encryption {
+            key_format: "raw".to_string(),
+            key_location: "file:///etc/csi/keys/a.key".to_string(),
+        };
+        let (_, recv) = build_copy_commands(
+            "tank/csi/pvc-src@snap",
+            "user:csi:metadata={}",
+            Some(&encryption),
+            "tank/csi/pvc-1",
+        );
+        assert!(recv.contains(&"encryption=aes-256-gcm".to_string()));
+        assert_eq!(recv.last().unwrap(), "tank/csi/pvc-1");
+    }
+
+    #[tokio::test]
+    async fn test_aborted_copy_destroys_partial_target() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::time::Duration;
+
+        let limiter = copy_limiter(1);
+        let destroyed = Arc::new(AtomicBool::new(false));
+        let copy = {
+            let permit = limiter.acquire().await;
+            let destroyed = destroyed.clone();
+            // A copy still streaming when shutdown drains the limiter
+            tokio::spawn(async move {
+                abortable_copy(&permit, std::future::pending::<Result<()>>(), async {
+                    destroyed.store(true, Ordering::SeqCst);
+                })
+                .await
+            })
+        };
+
+        assert!(!limiter.drain(Duration::from_millis(20)).await);
+        assert!(matches!(copy.await.unwrap(), Err(ZfsError::Aborted(_))));
+        assert!(destroyed.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_finished_copy_keeps_target() {
+        let limiter = copy_limiter(1);
+        let permit = limiter.acquire().await;
+        let mut destroyed = false;
+        abortable_copy(&permit, async { Ok(()) }, async { destroyed = true })
+            .await
+            .unwrap();
+        assert!(!destroyed);
+
+        // A failed copy is reported as is; only aborts destroy the target
+        let result: Result<()> = abortable_copy(
+            &permit,
+            async { Err(ZfsError::DatasetExists("tank/csi/pvc-1".to_string())) },
+            async { destroyed = true },
+        )
+        .await;
+        assert!(matches!(result, Err(ZfsError::DatasetExists(_))));
+        assert!(!destroyed);
+    }
+
+    #[test]
+    fn test_build_zvol_args_encrypted() {
+        let encryption = Encryption {
+            key_format: "hex".to_string(),
+            key_location: "file:///etc/csi/keys/a.key".to_string(),
+        };
+        let args = build_zvol_args(
+            "tank/csi/pvc-1",
+            1073741824,
+            "user:csi:metadata={}",
+            None,
+            None,
+            Some(&encryption),
+            false,
+        );
+        assert!(args.contains(&"encryption=aes-256-gcm".to_string()));
+        assert!(args.contains(&"keyformat=hex".to_string()));
+        assert_eq!(args.last().unwrap(), "tank/csi/pvc-1");
+    }
+
+    #[test]
+    fn test_parse_pool_status() {
+        assert_eq!(pool_name("tank/k8s/csi"), "tank");
+        assert_eq!(pool_name("tank"), "tank");
+
+        let status = parse_pool_status(
+            "fragmentation\t23\nhealth\tDEGRADED\ncapacity\t71\n",
+            "tank",
+        )
+        .unwrap();
+        assert_eq!(
+            status,
+            PoolStatus {
+                pool: "tank".to_string(),
+                health: "DEGRADED".to_string(),
+                fragmentation_percent: Some(23),
+                capacity_percent: 71,
+            }
+        );
+
+        // Pools without spacemap histograms report no fragmentation
+        let status =
+            parse_pool_status("fragmentation\t-\nhealth\tONLINE\ncapacity\t5\n", "tank").unwrap();
+        assert_eq!(status.fragmentation_percent, None);
+
+        assert!(parse_pool_status("health\tONLINE\ncapacity\t5\n", "tank").is_err());
+        assert!(
+            parse_pool_status("fragmentation\t1\nhealth\tONLINE\ncapacity\tlots\n", "tank")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata_listing() {
+        let current = serde_json::to_string(&VolumeMetadata::new(
+            crate::ctl::ExportType::Iscsi,
+            "iqn.2024-01.org.freebsd.csi:pvc-1".to_string(),
+            Some(0),
+            None,
+            Default::default(),
+            1700000000,
+            None,
+        ))
+        .unwrap();
+        let future = current.replace(
+            &format!("\"schema_version\":{}", CURRENT_SCHEMA_VERSION),
+            &format!("\"schema_version\":{}", CURRENT_SCHEMA_VERSION + 1),
+        );
+        let old = current.replace(
+            &format!("\"schema_version\":{}", CURRENT_SCHEMA_VERSION),
+            "\"schema_version\":1",
+        );
+        let stdout = format!(
+            "tank/csi\t-\t-\n\
+             tank/csi/pvc-1\t-\t{current}\n\
+             tank/csi/pvc-2\t-\t-\n\
+             tank/csi/pvc-3\t-\t{{not json\n\
+             tank/csi/pvc-4\t-\t{future}\n\
+             tank/other/pvc-5\t-\t{current}\n\
+             tank/csi/pvc-6\tunavailable\t{current}\n\
+             tank/csi/pvc-7\tavailable\t{old}\n"
+        );
+
+        let scan = parse_metadata_listing(&stdout, "tank/csi");
+        assert_eq!(scan.volumes.len(), 2);
+        assert_eq!(scan.volumes[0].0, "pvc-1");
+        assert_eq!(
+            scan.volumes[0].1.target_name,
+            "iqn.2024-01.org.freebsd.csi:pvc-1"
+        );
+        assert_eq!(scan.volumes[1].0, "pvc-7");
+        assert_eq!(scan.volumes[1].1.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(scan.corrupt, ["pvc-3"]);
+        assert_eq!(scan.locked, ["pvc-6"]);
+        assert_eq!(scan.migrated, ["pvc-7"]);
+    }
+
+    #[test]
+    fn test_split_metadata_line() {
+        assert_eq!(
+            split_metadata_line("tank/csi/pvc-1\tunavailable\t{}"),
+            Some(("tank/csi/pvc-1", "unavailable", "{}"))
+        );
+        assert_eq!(
+            split_metadata_line("tank/csi/pvc-1\t-\t-"),
+            Some(("tank/csi/pvc-1", "-", "-"))
+        );
+        assert_eq!(split_metadata_line("tank/csi/pvc-1\t-"), None);
+    }
 }
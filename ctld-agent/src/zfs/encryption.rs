@@ -0,0 +1,205 @@
+//! Per-volume ZFS native encryption.
+//!
+//! With `encryption=on` a volume becomes its own encryption root:
+//! `zfs create -o encryption=aes-256-gcm -o keyformat=<fmt> -o keylocation=<loc>`.
+//! Only `file://` key locations are accepted since the agent has no way to
+//! answer a passphrase prompt; the key file must be readable by the agent
+//! process whenever the key is loaded. The key itself never appears in CSI
+//! metadata, only its location.
+
+use std::collections::HashMap;
+
+use super::dataset::validate_name;
+use super::error::{Result, ZfsError};
+use crate::parameters::Parameters;
+
+/// StorageClass parameter enabling encryption (`on` or `off`)
+pub const ENCRYPTION_PARAM: &str = "encryption";
+
+/// StorageClass parameter naming the key file (`file:///path/to/key`)
+pub const KEY_LOCATION_PARAM: &str = "keyLocation";
+
+/// StorageClass parameter selecting the key format
+pub const KEY_FORMAT_PARAM: &str = "keyFormat";
+
+/// Cipher used for `encryption=on`
+const ENCRYPTION_ALGORITHM: &str = "aes-256-gcm";
+
+/// Key formats ZFS accepts
+const KEY_FORMATS: &[&str] = &["raw", "hex", "passphrase"];
+
+/// Encryption settings of a volume
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encryption {
+    /// `keyformat` property (raw, hex or passphrase)
+    pub key_format: String,
+    /// `keylocation` property (a `file://` URI)
+    pub key_location: String,
+}
+
+impl Encryption {
+    /// `-o` options making a new dataset an encryption root with this key
+    pub fn create_options(&self) -> Vec<String> {
+        vec![
+            "-o".to_string(),
+            format!("encryption={}", ENCRYPTION_ALGORITHM),
+            "-o".to_string(),
+            format!("keyformat={}", self.key_format),
+            "-o".to_string(),
+            format!("keylocation={}", self.key_location),
+        ]
+    }
+}
+
+/// Check a `keyLocation` value.
+///
+/// The location ends up on `zfs` command lines, so every path component
+/// must pass the same character rules as dataset names.
+fn validate_key_location(location: &str) -> Result<()> {
+    let invalid = |reason: &str| {
+        ZfsError::InvalidName(format!(
+            "invalid {} '{}': {}",
+            KEY_LOCATION_PARAM, location, reason
+        ))
+    };
+
+    let Some(path) = location.strip_prefix("file://") else {
+        return Err(invalid("expected a file:// URI"));
+    };
+    let Some(relative) = path.strip_prefix('/') else {
+        return Err(invalid("the key file path must be absolute"));
+    };
+    for component in relative.split('/') {
+        validate_name(component).map_err(|e| invalid(&e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Encryption requested by StorageClass parameters (`None` when unset or
+/// `encryption=off`, inheriting the parent's setting).
+pub fn encryption_from_parameters(params: &HashMap<String, String>) -> Result<Option<Encryption>> {
+    let params = Parameters::new(params);
+    let enabled = params
+        .get_enum(ENCRYPTION_PARAM, &[("on", true), ("off", false)])?
+        .unwrap_or(false);
+    let key_format = params.get(KEY_FORMAT_PARAM);
+    let key_location = params.get(KEY_LOCATION_PARAM);
+
+    if !enabled {
+        if key_format.is_some() || key_location.is_some() {
+            return Err(ZfsError::InvalidName(format!(
+                "{} and {} require {}=on",
+                KEY_FORMAT_PARAM, KEY_LOCATION_PARAM, ENCRYPTION_PARAM
+            )));
+        }
+        return Ok(None);
+    }
+
+    let (Some(key_format), Some(key_location)) = (key_format, key_location) else {
+        return Err(ZfsError::InvalidName(format!(
+            "{}=on requires both {} and {}",
+            ENCRYPTION_PARAM, KEY_FORMAT_PARAM, KEY_LOCATION_PARAM
+        )));
+    };
+    let key_format = key_format.to_lowercase();
+    if !KEY_FORMATS.contains(&key_format.as_str()) {
+        return Err(ZfsError::InvalidName(format!(
+            "invalid {} '{}': expected raw, hex or passphrase",
+            KEY_FORMAT_PARAM, key_format
+        )));
+    }
+    validate_key_location(key_location)?;
+
+    Ok(Some(Encryption {
+        key_format,
+        key_location: key_location.to_string(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_encryption_from_parameters() {
+        assert_eq!(encryption_from_parameters(&HashMap::new()).unwrap(), None);
+        assert_eq!(
+            encryption_from_parameters(&params(&[(ENCRYPTION_PARAM, "off")])).unwrap(),
+            None
+        );
+
+        let encryption = encryption_from_parameters(&params(&[
+            (ENCRYPTION_PARAM, "on"),
+            (KEY_FORMAT_PARAM, "Raw"),
+            (KEY_LOCATION_PARAM, "file:///etc/csi/keys/tenant-a.key"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            encryption.create_options(),
+            [
+                "-o",
+                "encryption=aes-256-gcm",
+                "-o",
+                "keyformat=raw",
+                "-o",
+                "keylocation=file:///etc/csi/keys/tenant-a.key"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encryption_requires_key_parameters() {
+        for pairs in [
+            &[(ENCRYPTION_PARAM, "on")][..],
+            &[(ENCRYPTION_PARAM, "on"), (KEY_FORMAT_PARAM, "hex")],
+            &[
+                (ENCRYPTION_PARAM, "on"),
+                (KEY_LOCATION_PARAM, "file:///etc/csi/keys/a.key"),
+            ],
+            &[(KEY_LOCATION_PARAM, "file:///etc/csi/keys/a.key")],
+            &[(ENCRYPTION_PARAM, "aes-128-ccm")],
+            &[
+                (ENCRYPTION_PARAM, "on"),
+                (KEY_FORMAT_PARAM, "pkcs11"),
+                (KEY_LOCATION_PARAM, "file:///etc/csi/keys/a.key"),
+            ],
+        ] {
+            assert!(
+                matches!(
+                    encryption_from_parameters(&params(pairs)),
+                    Err(ZfsError::InvalidName(_))
+                ),
+                "{pairs:?} accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_location_rejects_unsafe_values() {
+        assert!(validate_key_location("file:///etc/csi/keys/tenant_a-1.key").is_ok());
+        for location in [
+            "prompt",
+            "https://keys.example.com/a.key",
+            "file://etc/csi/a.key",
+            "file:///etc/csi/../shadow",
+            "file:///etc/csi//a.key",
+            "file:///etc/csi/a.key; rm -rf /",
+            "file:///etc/csi/$(id).key",
+            "file:///etc/csi/a key",
+            "file:///etc/csi/a.key\n",
+        ] {
+            assert!(
+                validate_key_location(location).is_err(),
+                "{location:?} accepted"
+            );
+        }
+    }
+}
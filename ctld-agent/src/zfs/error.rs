@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::parameters::ParameterError;
+
 #[derive(Error, Debug)]
 pub enum ZfsError {
     #[error("dataset '{0}' not found")]
@@ -11,12 +13,18 @@ pub enum ZfsError {
     #[error("dataset '{0}' is busy")]
     DatasetBusy(String),
 
+    #[error("pool unavailable: {0}")]
+    PoolUnavailable(String),
+
     #[error("invalid dataset name: {0}")]
     InvalidName(String),
 
     #[error("zfs command failed: {0}")]
     CommandFailed(String),
 
+    #[error("operation aborted: {0}")]
+    Aborted(String),
+
     #[error("failed to parse zfs output: {0}")]
     ParseError(String),
 
@@ -24,4 +32,10 @@ pub enum ZfsError {
     Io(#[from] std::io::Error),
 }
 
+impl From<ParameterError> for ZfsError {
+    fn from(e: ParameterError) -> Self {
+        ZfsError::InvalidName(e.to_string())
+    }
+}
+
 pub type Result<T> = std::result::Result<T, ZfsError>;
@@ -1,11 +1,31 @@
+pub mod backend;
+pub mod compression;
+pub mod copy_limit;
 pub mod dataset;
+pub mod encryption;
 pub mod error;
 pub mod properties;
+pub mod quota;
 
+pub use backend::{
+    BACKEND_PARAM, RECORD_SIZE_PARAM, VOLBLOCKSIZE_PARAM, VolumeBackend, parse_record_size,
+    parse_volblocksize,
+};
+pub use compression::{COMPRESSION_PARAM, compression_from_parameters};
+pub use copy_limit::DEFAULT_MAX_CONCURRENT_COPIES;
 pub use dataset::{
-    Capacity, CsiSnapshotInfo, Dataset, FindSnapshotResult, VolumeMetadataLookup, ZfsManager,
+    Capacity, CsiSnapshotInfo, DEFAULT_IMAGE_FETCH_TIMEOUT_SECS, Dataset, FindSnapshotResult,
+    MetadataScan, PoolStatus, SYSTEM_SNAPSHOT_PREFIX, VolumeMetadataLookup, ZfsManager,
+    snapshot_tag_matches,
+};
+pub use encryption::{
+    ENCRYPTION_PARAM, Encryption, KEY_FORMAT_PARAM, KEY_LOCATION_PARAM, encryption_from_parameters,
 };
 // Re-export for module API
 #[allow(unused_imports)]
 pub use error::{Result, ZfsError};
-pub use properties::VolumeMetadata;
+pub use properties::{VolumeAttachment, VolumeMetadata};
+pub use quota::{
+    MAX_OVERPROVISION_PARAM, PROVISIONING_MODE_PARAM, QUOTA_PARAM, check_quota, parse_byte_size,
+    quota_from_parameters, reserves_full_size,
+};
@@ -47,6 +47,35 @@ pub struct VolumeMetadata {
     /// None means "no-authentication".
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub auth_group: Option<String>,
+    /// Nodes the volume is published to, recorded by ControllerPublishVolume.
+    /// None when it is not published.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<VolumeAttachment>,
+}
+
+/// Nodes a volume is published to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeAttachment {
+    pub nodes: Vec<String>,
+    /// Published with a multi-node access mode
+    pub multi_node: bool,
+    /// iSCSI initiator names of the nodes, when every node reported one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub initiator_names: Vec<String>,
+    /// NVMe host NQNs of the nodes, when every node reported one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub host_nqns: Vec<String>,
+}
+
+impl VolumeAttachment {
+    /// Names of the attached nodes' initiators for an `export_type` export;
+    /// empty when they are not all known
+    pub fn initiators(&self, export_type: ExportType) -> &[String] {
+        match export_type {
+            ExportType::Iscsi => &self.initiator_names,
+            ExportType::Nvmeof => &self.host_nqns,
+        }
+    }
 }
 
 impl VolumeMetadata {
@@ -69,6 +98,7 @@ impl VolumeMetadata {
             parameters,
             created_at,
             auth_group,
+            attachment: None,
         }
     }
 
@@ -117,6 +147,13 @@ pub const METADATA_PROPERTY: &str = "user:csi:metadata";
 /// This property is set on snapshots to track them even after promotion moves them
 pub const SNAPSHOT_ID_PROPERTY: &str = "user:csi:snapshot_id";
 
+/// ZFS user property set to [`SYSTEM_SNAPSHOT_KIND`] on snapshots taken by
+/// operator tooling, as opposed to snapshots requested through CSI
+pub const SNAPSHOT_KIND_PROPERTY: &str = "user:csi:snapshot_kind";
+
+/// [`SNAPSHOT_KIND_PROPERTY`] value of system snapshots
+pub const SYSTEM_SNAPSHOT_KIND: &str = "system";
+
 #[cfg(test)]
 mod tests {
     use super::*;
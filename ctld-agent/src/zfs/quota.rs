@@ -0,0 +1,173 @@
+//! Space limits for volumes.
+//!
+//! Thin volumes let a pool be overcommitted. `maxOverprovision=false` opts a
+//! volume out of that by reserving its full size (`refreservation=volsize`,
+//! the same guarantee as `provisioningMode=thick`). A `quota` caps the total
+//! space of a file-backed volume's dataset, snapshots included, with
+//! `-o quota=<bytes>`. Zvols have no use for it: `volsize` already bounds
+//! what the volume can write, and ZFS rejects `quota` on volumes.
+
+use std::collections::HashMap;
+
+use super::backend::{BACKEND_PARAM, VolumeBackend};
+use super::error::{Result, ZfsError};
+use crate::parameters::Parameters;
+
+/// StorageClass parameter capping a file-backed volume's dataset
+pub const QUOTA_PARAM: &str = "quota";
+
+/// StorageClass parameter that, when `false`, reserves the full volume size
+pub const MAX_OVERPROVISION_PARAM: &str = "maxOverprovision";
+
+/// StorageClass parameter selecting thin or thick provisioning
+pub const PROVISIONING_MODE_PARAM: &str = "provisioningMode";
+
+/// Parse a byte size with an optional binary suffix (`K`, `M`, `G`, `T`,
+/// also spelled `Ki`, `Gi`, ...). Overflowing sizes are rejected.
+///
+/// This is the one size parser for StorageClass parameters, agent flags and
+/// `zfs -p` output; callers needing a positive size check that themselves.
+pub fn parse_byte_size(value: &str) -> Option<u64> {
+    let trimmed = value.trim();
+    let number = trimmed.trim_end_matches(['i', 'I']);
+    let (digits, shift) = match number.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&number[..i], 10),
+        Some((i, 'M' | 'm')) => (&number[..i], 20),
+        Some((i, 'G' | 'g')) => (&number[..i], 30),
+        Some((i, 'T' | 't')) => (&number[..i], 40),
+        // A trailing "i" without a unit is not a size
+        _ if number.len() != trimmed.len() => ("", 0),
+        _ => (number, 0),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+}
+
+/// Whether the volume must reserve its full size up front, either through
+/// `provisioningMode=thick` or `maxOverprovision=false`.
+pub fn reserves_full_size(params: &HashMap<String, String>) -> Result<bool> {
+    let params = Parameters::new(params);
+    let thick = params
+        .get(PROVISIONING_MODE_PARAM)
+        .is_some_and(|v| v.eq_ignore_ascii_case("thick"));
+    let no_overprovision = params.get_bool(MAX_OVERPROVISION_PARAM)? == Some(false);
+    Ok(thick || no_overprovision)
+}
+
+/// Check that a quota leaves room for a volume of `size_bytes`
+pub fn check_quota(quota: u64, size_bytes: u64) -> Result<()> {
+    if quota < size_bytes {
+        return Err(ZfsError::InvalidName(format!(
+            "{} {} is smaller than the volume size of {} bytes",
+            QUOTA_PARAM, quota, size_bytes
+        )));
+    }
+    Ok(())
+}
+
+/// Quota requested by StorageClass parameters for a volume of `size_bytes`
+/// (`None` when unset).
+pub fn quota_from_parameters(
+    params: &HashMap<String, String>,
+    backend: VolumeBackend,
+    size_bytes: u64,
+) -> Result<Option<u64>> {
+    let Some(value) = Parameters::new(params).get(QUOTA_PARAM) else {
+        return Ok(None);
+    };
+    if backend != VolumeBackend::File {
+        return Err(ZfsError::InvalidName(format!(
+            "{} requires {}=file; a zvol is already capped by its size",
+            QUOTA_PARAM, BACKEND_PARAM
+        )));
+    }
+    let quota = parse_byte_size(value)
+        .filter(|&bytes| bytes > 0)
+        .ok_or_else(|| {
+            ZfsError::InvalidName(format!(
+                "invalid {} '{}': expected a positive size such as 500M or 10G",
+                QUOTA_PARAM, value
+            ))
+        })?;
+    check_quota(quota, size_bytes)?;
+    Ok(Some(quota))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("1073741824"), Some(1 << 30));
+        assert_eq!(parse_byte_size("10G"), Some(10 << 30));
+        assert_eq!(parse_byte_size("500M"), Some(500 << 20));
+        assert_eq!(parse_byte_size("512k"), Some(512 << 10));
+        assert_eq!(parse_byte_size("2Ti"), Some(2 << 40));
+        assert_eq!(parse_byte_size(" 64Mi "), Some(64 << 20));
+        assert_eq!(parse_byte_size("0"), Some(0));
+        for value in ["", "-", "G", "1.5T", "10i", "-1G", "1P", "99999999999T"] {
+            assert_eq!(parse_byte_size(value), None, "{value:?} accepted");
+        }
+    }
+
+    #[test]
+    fn test_reserves_full_size() {
+        assert!(!reserves_full_size(&HashMap::new()).unwrap());
+        assert!(!reserves_full_size(&params(&[(MAX_OVERPROVISION_PARAM, "true")])).unwrap());
+        assert!(reserves_full_size(&params(&[(MAX_OVERPROVISION_PARAM, "False")])).unwrap());
+        assert!(reserves_full_size(&params(&[(PROVISIONING_MODE_PARAM, "thick")])).unwrap());
+        assert!(
+            reserves_full_size(&params(&[
+                (PROVISIONING_MODE_PARAM, "thin"),
+                (MAX_OVERPROVISION_PARAM, "false")
+            ]))
+            .unwrap()
+        );
+        assert!(reserves_full_size(&params(&[(MAX_OVERPROVISION_PARAM, " no ")])).unwrap());
+        assert!(reserves_full_size(&params(&[("max_overprovision", "false")])).unwrap());
+        assert!(reserves_full_size(&params(&[(MAX_OVERPROVISION_PARAM, "maybe")])).is_err());
+    }
+
+    #[test]
+    fn test_quota_from_parameters() {
+        let size = 1 << 30;
+        assert_eq!(
+            quota_from_parameters(&HashMap::new(), VolumeBackend::File, size).unwrap(),
+            None
+        );
+        assert_eq!(
+            quota_from_parameters(&params(&[(QUOTA_PARAM, "2G")]), VolumeBackend::File, size)
+                .unwrap(),
+            Some(2 << 30)
+        );
+        // Exactly the volume size leaves no room for snapshots but is allowed
+        assert!(
+            quota_from_parameters(&params(&[(QUOTA_PARAM, "1G")]), VolumeBackend::File, size)
+                .is_ok()
+        );
+
+        for (value, backend) in [
+            ("2G", VolumeBackend::Zvol),
+            ("500M", VolumeBackend::File),
+            ("lots", VolumeBackend::File),
+        ] {
+            assert!(
+                matches!(
+                    quota_from_parameters(&params(&[(QUOTA_PARAM, value)]), backend, size),
+                    Err(ZfsError::InvalidName(_))
+                ),
+                "{value:?} accepted for {backend}"
+            );
+        }
+    }
+}